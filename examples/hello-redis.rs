@@ -1,4 +1,4 @@
-use mini_redis::{client, Result};
+use toyredis::{client, Result};
 
 #[tokio::main]
 async fn main() -> Result<()> {
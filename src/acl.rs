@@ -0,0 +1,513 @@
+//! 访问控制：`AUTH` 与简化的 ACL。
+//!
+//! 与 redis 一样，始终存在名为 `default` 的用户，新连接自动以它的身份登录，除非它设置了密码，
+//! 此时连接需要先执行 `AUTH`。配置项 `requirepass` 就是 default 用户的密码。
+//!
+//! 每个用户有以下属性，通过 `ACL SETUSER` 的规则修改：
+//! - 是否启用（`on`、`off`）；
+//! - 密码（`>密码`、`<密码`、`#SHA256`、`nopass`、`resetpass`），只保存密码的 SHA256；
+//! - 可以访问的 key 的 glob 模式（`~模式`、`allkeys`、`resetkeys`）；
+//! - 可以执行的命令（`+@分类`、`-@分类`、`+命令`、`-命令`、`allcommands`、`nocommands`），
+//!   按顺序生效，后面的规则覆盖前面的规则。
+//!
+//! 权限在命令执行前检查，命令中的 key 按 [`KeySpec`] 从参数中取出。
+
+use std::{collections::BTreeMap, fmt, ops::BitOr};
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+
+use crate::{cmd::Command, frame::Frame, glob};
+
+/// 默认用户的名称
+pub const DEFAULT_USER: &str = "default";
+
+/// 命令的分类，对应 ACL 规则中的 `@名称`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Categories(u32);
+
+impl Categories {
+    pub const KEYSPACE: Categories = Categories(1 << 0);
+    pub const READ: Categories = Categories(1 << 1);
+    pub const WRITE: Categories = Categories(1 << 2);
+    pub const STRING: Categories = Categories(1 << 3);
+    pub const BITMAP: Categories = Categories(1 << 4);
+    pub const LIST: Categories = Categories(1 << 5);
+    pub const HASH: Categories = Categories(1 << 6);
+    pub const SET: Categories = Categories(1 << 7);
+    pub const SORTEDSET: Categories = Categories(1 << 8);
+    pub const PUBSUB: Categories = Categories(1 << 9);
+    pub const TRANSACTION: Categories = Categories(1 << 10);
+    pub const SCRIPTING: Categories = Categories(1 << 11);
+    pub const CONNECTION: Categories = Categories(1 << 12);
+    pub const ADMIN: Categories = Categories(1 << 13);
+    pub const DANGEROUS: Categories = Categories(1 << 14);
+    pub const GEO: Categories = Categories(1 << 15);
+    pub const STREAM: Categories = Categories(1 << 16);
+
+    /// 所有分类及其名称，`all` 不是分类，单独处理
+    const NAMES: [(&'static str, Categories); 17] = [
+        ("keyspace", Categories::KEYSPACE),
+        ("read", Categories::READ),
+        ("write", Categories::WRITE),
+        ("string", Categories::STRING),
+        ("bitmap", Categories::BITMAP),
+        ("list", Categories::LIST),
+        ("hash", Categories::HASH),
+        ("set", Categories::SET),
+        ("sortedset", Categories::SORTEDSET),
+        ("pubsub", Categories::PUBSUB),
+        ("transaction", Categories::TRANSACTION),
+        ("scripting", Categories::SCRIPTING),
+        ("connection", Categories::CONNECTION),
+        ("admin", Categories::ADMIN),
+        ("dangerous", Categories::DANGEROUS),
+        ("geo", Categories::GEO),
+        ("stream", Categories::STREAM),
+    ];
+
+    pub fn contains(self, other: Categories) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    fn from_name(name: &str) -> Option<Categories> {
+        Self::NAMES.iter().find(|(found, _)| *found == name).map(|(_, category)| *category)
+    }
+
+    fn name(self) -> &'static str {
+        Self::NAMES.iter().find(|(_, category)| *category == self).map_or("", |(name, _)| name)
+    }
+}
+
+impl BitOr for Categories {
+    type Output = Categories;
+
+    fn bitor(self, rhs: Categories) -> Categories {
+        Categories(self.0 | rhs.0)
+    }
+}
+
+/// 命令的参数中哪些是 key，下标从命令名之后的第一个参数算起为 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySpec {
+    /// 没有 key
+    None,
+    /// 从 first 到 last（包括 last，负数表示从末尾倒数），每 step 个参数一个 key
+    Range { first: usize, last: isize, step: usize },
+    /// pos 处的参数为 key 的个数，之后是各个 key，如 `EVAL script numkeys key...`。
+    /// dest 表示第一个参数也是 key，如 `ZUNIONSTORE destination numkeys key...`
+    NumKeys { pos: usize, dest: bool },
+    /// `SORT key ... [STORE destination]`：第一个参数以及 STORE 之后的参数。
+    /// 跳过 BY、GET、LIMIT 的参数，模式恰好为 STORE 时不会被当作选项
+    Sort,
+    /// `XREAD ... STREAMS key [key ...] id [id ...]`：STREAMS 之后参数的前一半。
+    /// XREADGROUP 的 `GROUP group consumer` 在最前面，从它之后开始查找 STREAMS
+    Streams,
+}
+
+impl KeySpec {
+    /// 单个 key，位于第一个参数
+    pub const SINGLE: KeySpec = KeySpec::Range { first: 1, last: 1, step: 1 };
+
+    /// 所有参数都是 key
+    pub const ALL: KeySpec = KeySpec::Range { first: 1, last: -1, step: 1 };
+
+    /// 从命令的参数（包括命令名）中取出所有 key，参数不够时只取出存在的部分
+    pub fn keys<'a>(&self, args: &'a [Frame]) -> Vec<&'a [u8]> {
+        let arg = |i: usize| match args.get(i) {
+            Some(Frame::Bulk(data)) => Some(&data[..]),
+            Some(Frame::Simple(s)) => Some(s.as_bytes()),
+            _ => None,
+        };
+        let indices: Vec<usize> = match *self {
+            KeySpec::None => vec![],
+            KeySpec::Range { first, last, step } => {
+                let last = if last < 0 { args.len() as isize + last } else { last };
+                if last < first as isize {
+                    vec![]
+                } else {
+                    (first..=last as usize).step_by(step).collect()
+                }
+            },
+            KeySpec::NumKeys { pos, dest } => {
+                let numkeys = arg(pos).and_then(atoi::atoi::<usize>).unwrap_or(0);
+                let dest = if dest { Some(1) } else { None };
+                dest.into_iter().chain(pos + 1..=pos + numkeys).collect()
+            },
+            KeySpec::Sort => {
+                let mut indices = vec![1];
+                let mut i = 2;
+                while let Some(option) = arg(i) {
+                    i += match option.to_ascii_uppercase().as_slice() {
+                        b"LIMIT" => 3,
+                        b"BY" | b"GET" => 2,
+                        b"STORE" => {
+                            indices.push(i + 1);
+                            2
+                        },
+                        _ => 1,
+                    };
+                }
+                indices
+            },
+            KeySpec::Streams => {
+                let from = match arg(1) {
+                    Some(group) if group.eq_ignore_ascii_case(b"GROUP") => 4,
+                    _ => 1,
+                };
+                let streams = (from..args.len()).find(|&i| arg(i).is_some_and(|option| option.eq_ignore_ascii_case(b"STREAMS")));
+                match streams {
+                    Some(i) => (i + 1..i + 1 + (args.len() - i - 1) / 2).collect(),
+                    None => vec![],
+                }
+            },
+        };
+        indices.into_iter().filter_map(arg).collect()
+    }
+}
+
+/// 没有权限执行命令
+#[derive(Debug, PartialEq, Eq)]
+pub enum NoPermission {
+    Command { user: String, command: String },
+    Key,
+}
+
+impl fmt::Display for NoPermission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoPermission::Command { user, command } => {
+                write!(f, "NOPERM User {} has no permissions to run the '{}' command", user, command)
+            },
+            NoPermission::Key => "NOPERM No permissions to access a key".fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for NoPermission {}
+
+/// 命令权限规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Rule {
+    All,
+    Category(Categories),
+    Command(String),
+}
+
+/// 一个用户
+#[derive(Debug, Clone)]
+struct User {
+    enabled: bool,
+    /// 不需要密码
+    nopass: bool,
+    /// 密码的 SHA256，十六进制小写
+    passwords: Vec<String>,
+    /// 可以访问的 key 的 glob 模式
+    keys: Vec<Bytes>,
+    /// 命令权限规则及其是否允许，后面的规则覆盖前面的规则
+    commands: Vec<(bool, Rule)>,
+}
+
+impl User {
+    /// 新用户没有任何权限，也不能登录
+    fn new() -> User {
+        User { enabled: false, nopass: false, passwords: vec![], keys: vec![], commands: vec![] }
+    }
+
+    fn apply_rule(&mut self, rule: &str) -> Result<(), String> {
+        let lower = rule.to_lowercase();
+        match lower.as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            },
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            },
+            "allkeys" => self.keys = vec![Bytes::from("*")],
+            "resetkeys" => self.keys.clear(),
+            "allcommands" => self.commands = vec![(true, Rule::All)],
+            "nocommands" => self.commands.clear(),
+            "reset" => *self = User::new(),
+            _ => match rule.split_at(rule.chars().next().map_or(0, char::len_utf8)) {
+                (">", password) => {
+                    let hash = sha256_hex(password);
+                    if !self.passwords.contains(&hash) {
+                        self.passwords.push(hash);
+                    }
+                    self.nopass = false;
+                },
+                ("<", password) => {
+                    let hash = sha256_hex(password);
+                    let len = self.passwords.len();
+                    self.passwords.retain(|found| *found != hash);
+                    if self.passwords.len() == len {
+                        return Err("no such password".to_string());
+                    }
+                },
+                ("#", hash) => {
+                    if hash.len() != 64 || !hash.bytes().all(|c| c.is_ascii_hexdigit()) {
+                        return Err("the password hash must be exactly 64 characters and contain only lowercase hexadecimal characters".to_string());
+                    }
+                    let hash = hash.to_ascii_lowercase();
+                    if !self.passwords.contains(&hash) {
+                        self.passwords.push(hash);
+                    }
+                    self.nopass = false;
+                },
+                ("~", pattern) => {
+                    let pattern = Bytes::from(pattern.to_string());
+                    if !self.keys.contains(&pattern) {
+                        self.keys.push(pattern);
+                    }
+                },
+                (sign @ ("+" | "-"), name) => {
+                    let allow = sign == "+";
+                    let name = name.to_lowercase();
+                    let rule = match name.strip_prefix('@') {
+                        Some("all") => {
+                            // 覆盖之前所有的规则
+                            self.commands.clear();
+                            if allow {
+                                self.commands.push((true, Rule::All));
+                            }
+                            return Ok(());
+                        },
+                        Some(category) => Rule::Category(Categories::from_name(category).ok_or("unknown command category")?),
+                        None if name.is_empty() => return Err("Syntax error".to_string()),
+                        None => Rule::Command(name),
+                    };
+                    self.commands.push((allow, rule));
+                },
+                _ => return Err("Syntax error".to_string()),
+            },
+        }
+        Ok(())
+    }
+
+    fn check_password(&self, password: &[u8]) -> bool {
+        self.nopass || self.passwords.contains(&sha256_hex(password))
+    }
+
+    fn can_run(&self, name: &str, categories: Categories) -> bool {
+        self.commands.iter().fold(false, |allowed, (allow, rule)| {
+            let matched = match rule {
+                Rule::All => true,
+                Rule::Category(category) => categories.contains(*category),
+                Rule::Command(command) => command == name,
+            };
+            if matched { *allow } else { allowed }
+        })
+    }
+
+    fn can_access(&self, key: &[u8]) -> bool {
+        self.keys.iter().any(|pattern| glob::matches(pattern, key))
+    }
+
+    /// `ACL LIST` 中的一行，除了名称以外的部分
+    fn describe(&self) -> String {
+        let mut rules = vec![if self.enabled { "on" } else { "off" }.to_string()];
+        if self.nopass {
+            rules.push("nopass".to_string());
+        }
+        rules.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        rules.extend(self.keys.iter().map(|pattern| format!("~{}", String::from_utf8_lossy(pattern))));
+        if !matches!(self.commands.first(), Some((true, Rule::All))) {
+            rules.push("-@all".to_string());
+        }
+        rules.extend(self.commands.iter().map(|(allow, rule)| {
+            let sign = if *allow { '+' } else { '-' };
+            match rule {
+                Rule::All => format!("{}@all", sign),
+                Rule::Category(category) => format!("{}@{}", sign, category.name()),
+                Rule::Command(command) => format!("{}{}", sign, command),
+            }
+        }));
+        rules.join(" ")
+    }
+}
+
+/// 所有用户
+#[derive(Debug, Clone)]
+pub struct Acl {
+    users: BTreeMap<String, User>,
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Acl::new("")
+    }
+}
+
+impl Acl {
+    /// 只有 default 用户，它可以执行所有命令、访问所有 key，password 为空时不需要密码
+    pub fn new(password: &str) -> Acl {
+        let mut user = User::new();
+        for rule in ["on", "allkeys", "allcommands"] {
+            user.apply_rule(rule).unwrap();
+        }
+        let mut acl = Acl { users: BTreeMap::from([(DEFAULT_USER.to_string(), user)]) };
+        acl.set_default_password(password);
+        acl
+    }
+
+    /// 修改 default 用户的密码，即 `requirepass`，为空时不需要密码
+    pub fn set_default_password(&mut self, password: &str) {
+        let user = self.users.get_mut(DEFAULT_USER).expect("default user always exists");
+        let rule = if password.is_empty() { "nopass".to_string() } else { format!(">{}", password) };
+        user.apply_rule("resetpass").unwrap();
+        user.apply_rule(&rule).unwrap();
+    }
+
+    /// 新连接是否不需要 `AUTH` 就能以 default 用户的身份执行命令
+    pub fn default_nopass(&self) -> bool {
+        self.users.get(DEFAULT_USER).is_some_and(|user| user.enabled && user.nopass)
+    }
+
+    /// 用户存在、已启用并且密码正确
+    pub fn authenticate(&self, user: &str, password: &[u8]) -> bool {
+        self.users.get(user).is_some_and(|found| found.enabled && found.check_password(password))
+    }
+
+    /// `ACL SETUSER`，用户不存在时创建。任一规则有误时不做任何修改
+    pub fn set_user(&mut self, name: &str, rules: &[String]) -> Result<(), String> {
+        let mut user = self.users.get(name).cloned().unwrap_or_else(User::new);
+        for rule in rules {
+            user.apply_rule(rule).map_err(|err| format!("ERR Error in ACL SETUSER modifier '{}': {}", rule, err))?;
+        }
+        self.users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    /// `ACL LIST`，每个用户一行
+    pub fn list(&self) -> Vec<String> {
+        self.users.iter().map(|(name, user)| format!("user {} {}", name, user.describe())).collect()
+    }
+
+    /// 检查 user 能否执行命令 cmd，args 为客户端发来的原始 frame，用于取出命令访问的 key
+    pub fn check(&self, user: &str, cmd: &Command, args: &Frame) -> Result<(), NoPermission> {
+        let no_command = || NoPermission::Command { user: user.to_string(), command: cmd.get_name().to_string() };
+        let found = self.users.get(user).filter(|found| found.enabled).ok_or_else(no_command)?;
+        if !found.can_run(cmd.get_name(), cmd.categories()) {
+            return Err(no_command());
+        }
+        let args = match args {
+            Frame::Array(args) => &args[..],
+            _ => &[],
+        };
+        if cmd.key_spec().keys(args).into_iter().all(|key| found.can_access(key)) {
+            Ok(())
+        } else {
+            Err(NoPermission::Key)
+        }
+    }
+}
+
+fn sha256_hex(password: impl AsRef<[u8]>) -> String {
+    Sha256::digest(password)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{cmd::Command, frame::Frame};
+
+    use super::{Acl, KeySpec, NoPermission};
+
+    fn command(args: &[&str]) -> (Command, Frame) {
+        let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::from(arg.to_string()))).collect());
+        (Command::from_frame(frame.clone()).unwrap(), frame)
+    }
+
+    fn check(acl: &Acl, user: &str, args: &[&str]) -> Result<(), NoPermission> {
+        let (cmd, frame) = command(args);
+        acl.check(user, &cmd, &frame)
+    }
+
+    fn rules(rules: &str) -> Vec<String> {
+        rules.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn key_specs() {
+        let (_, frame) = command(&["mset", "a", "1", "b", "2"]);
+        let Frame::Array(args) = frame else { unreachable!() };
+        assert_eq!(KeySpec::Range { first: 1, last: -1, step: 2 }.keys(&args), [b"a", b"b"]);
+        assert_eq!(KeySpec::SINGLE.keys(&args), [b"a"]);
+        assert!(KeySpec::Range { first: 2, last: 1, step: 1 }.keys(&args).is_empty());
+        assert!(KeySpec::None.keys(&args).is_empty());
+
+        let (_, frame) = command(&["zunionstore", "dest", "2", "a", "b", "weights", "1", "2"]);
+        let Frame::Array(args) = frame else { unreachable!() };
+        assert_eq!(KeySpec::NumKeys { pos: 2, dest: true }.keys(&args), [&b"dest"[..], b"a", b"b"]);
+
+        let (_, frame) = command(&["sort", "list", "by", "w_*", "get", "store", "limit", "0", "1", "store", "dest"]);
+        let Frame::Array(args) = frame else { unreachable!() };
+        assert_eq!(KeySpec::Sort.keys(&args), [&b"list"[..], b"dest"]);
+
+        let (_, frame) = command(&["xreadgroup", "GROUP", "streams", "c", "count", "1", "streams", "a", "b", "0", ">"]);
+        let Frame::Array(args) = frame else { unreachable!() };
+        assert_eq!(KeySpec::Streams.keys(&args), [b"a", b"b"]);
+    }
+
+    #[test]
+    fn default_user() {
+        let acl = Acl::default();
+        assert!(acl.default_nopass());
+        assert!(acl.authenticate("default", b"anything"));
+        assert_eq!(acl.list(), ["user default on nopass ~* +@all"]);
+        check(&acl, "default", &["flushall-is-unknown"]).unwrap();
+        check(&acl, "default", &["set", "k", "v"]).unwrap();
+
+        let acl = Acl::new("secret");
+        assert!(!acl.default_nopass());
+        assert!(acl.authenticate("default", b"secret"));
+        assert!(!acl.authenticate("default", b"wrong"));
+        assert!(!acl.authenticate("nosuchuser", b"secret"));
+    }
+
+    #[test]
+    fn set_user() {
+        let mut acl = Acl::default();
+        acl.set_user("alice", &rules("on >p1 >p2 <p1 ~cache:* +@read -@dangerous +set")).unwrap();
+        assert!(acl.authenticate("alice", b"p2"));
+        assert!(!acl.authenticate("alice", b"p1"));
+
+        check(&acl, "alice", &["get", "cache:1"]).unwrap();
+        check(&acl, "alice", &["set", "cache:1", "v"]).unwrap();
+        assert_eq!(check(&acl, "alice", &["get", "other"]), Err(NoPermission::Key));
+        assert_eq!(check(&acl, "alice", &["mget", "cache:1", "other"]), Err(NoPermission::Key));
+        let err = check(&acl, "alice", &["del", "cache:1"]).unwrap_err();
+        assert_eq!(err.to_string(), "NOPERM User alice has no permissions to run the 'del' command");
+        assert!(check(&acl, "alice", &["keys", "*"]).is_err());
+
+        let list = acl.list();
+        assert!(list[0].starts_with("user alice on #"));
+        assert!(list[0].ends_with(" ~cache:* -@all +@read -@dangerous +set"));
+
+        // 规则有误时不做任何修改
+        let err = acl.set_user("alice", &rules("off +@nosuchcategory")).unwrap_err();
+        assert_eq!(err, "ERR Error in ACL SETUSER modifier '+@nosuchcategory': unknown command category");
+        assert!(acl.authenticate("alice", b"p2"));
+        assert!(acl.set_user("alice", &rules("<nosuchpassword")).is_err());
+        assert!(acl.set_user("alice", &rules("what")).is_err());
+
+        acl.set_user("alice", &rules("off")).unwrap();
+        assert!(!acl.authenticate("alice", b"p2"));
+        assert!(check(&acl, "alice", &["get", "cache:1"]).is_err());
+
+        acl.set_user("bob", &rules("on nopass allkeys allcommands -set")).unwrap();
+        check(&acl, "bob", &["get", "k"]).unwrap();
+        assert!(check(&acl, "bob", &["set", "k", "v"]).is_err());
+        assert_eq!(acl.list()[1], "user bob on nopass ~* +@all -set");
+        acl.set_user("bob", &rules("reset")).unwrap();
+        assert_eq!(acl.list()[1], "user bob off -@all");
+    }
+}
@@ -1,6 +1,23 @@
-use mini_redis::client;
+use bytes::Bytes;
 use tokio::sync::{mpsc, oneshot};
-use toyredis::cmd::Command::{Get, Set};
+use toyredis::client;
+use Command::{Get, Set};
+
+/// 发送给连接管理任务的请求，通过 oneshot 回传结果
+#[derive(Debug)]
+enum Command {
+    Get {
+        key: String,
+        resp: Responder<Option<Bytes>>,
+    },
+    Set {
+        key: String,
+        value: Bytes,
+        resp: Responder<()>,
+    }
+}
+
+type Responder<T> = oneshot::Sender<toyredis::Result<T>>;
 
 
 #[tokio::main]
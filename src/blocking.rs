@@ -0,0 +1,160 @@
+//! 阻塞命令的等待与唤醒，对应 redis 的 `blocked.c`。
+//!
+//! 阻塞的连接先通过 [`Waiters::register`] 登记关心的 key，再执行一次命令；没有结果时等待 [`Waiter::wait`]，
+//! 被唤醒后重新登记、重新执行，直到有结果或者超时。写入这些 key 的命令执行后调用 [`Waiters::signal`]。
+//!
+//! 登记在执行命令之前，执行期间发生的写入同样会留下通知，不会丢失唤醒。
+//! 唤醒只表示 key 可能有了新数据，可能被其他连接抢先读走，所以总是重新执行命令来判断。
+//!
+//! 流的新条目所有读者都能读到，XADD 唤醒所有等待的连接；列表的元素只能被一个连接取走，
+//! 写入列表时用 [`Waiters::signal_first`] 只唤醒最早登记的连接，它撤销登记时再唤醒下一个，
+//! 阻塞在同一个 key 上的连接按先来后到取得元素。
+
+use std::{collections::HashMap, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}};
+
+use bytes::Bytes;
+use tokio::sync::Notify;
+
+/// 所有阻塞中的连接，按 key 索引，同一个 key 上按登记的先后排列
+#[derive(Default)]
+pub struct Waiters {
+    keys: Mutex<HashMap<Bytes, Vec<Arc<Registration>>>>,
+}
+
+#[derive(Default)]
+struct Registration {
+    notify: Notify,
+    /// 被 [`Waiters::signal_first`] 唤醒过，撤销登记时要把唤醒传给下一个连接
+    handed: AtomicBool,
+}
+
+impl Registration {
+    fn wake(&self) {
+        // 对方可能还在执行命令，notify_one 会保留通知直到它开始等待
+        self.notify.notify_one();
+    }
+
+    fn hand(&self) {
+        self.handed.store(true, Ordering::Relaxed);
+        self.wake();
+    }
+}
+
+/// 一次登记，drop 时撤销
+pub struct Waiter<'a> {
+    waiters: &'a Waiters,
+    keys: Vec<Bytes>,
+    registration: Arc<Registration>,
+}
+
+impl Waiters {
+    /// 登记等待 keys 中的任意一个。在同一个 key 上排在已经登记的连接之后
+    pub fn register(&self, keys: &[Bytes]) -> Waiter<'_> {
+        let registration = Arc::new(Registration::default());
+        let mut registry = self.keys.lock().unwrap();
+        for key in keys {
+            registry.entry(key.clone()).or_default().push(registration.clone());
+        }
+        Waiter { waiters: self, keys: keys.to_vec(), registration }
+    }
+
+    /// 唤醒等待 key 的所有连接
+    pub fn signal(&self, key: &[u8]) {
+        if let Some(waiting) = self.keys.lock().unwrap().get(key) {
+            waiting.iter().for_each(|registration| registration.wake());
+        }
+    }
+
+    /// 只唤醒最早登记等待 key 的连接。它取走元素或者放弃等待时撤销登记，同时唤醒排在它之后的连接，
+    /// 见 [`Waiter`] 的 drop
+    pub fn signal_first(&self, key: &[u8]) {
+        if let Some(first) = self.keys.lock().unwrap().get(key).and_then(|waiting| waiting.first()) {
+            first.hand();
+        }
+    }
+
+    /// 正在等待的 key 的个数
+    pub fn blocking_keys(&self) -> usize {
+        self.keys.lock().unwrap().len()
+    }
+}
+
+impl Waiter<'_> {
+    /// 等待任意一个 key 被写入
+    pub async fn wait(&self) {
+        self.registration.notify.notified().await
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let handed = self.registration.handed.load(Ordering::Relaxed);
+        let mut registry = self.waiters.keys.lock().unwrap();
+        for key in &self.keys {
+            if let Some(waiting) = registry.get_mut(key) {
+                waiting.retain(|registration| !Arc::ptr_eq(registration, &self.registration));
+                match waiting.first() {
+                    // 被唤醒的连接可能没有取走元素就离开了（超时、断开），由下一个连接重新检查
+                    Some(next) if handed => next.hand(),
+                    Some(_) => {},
+                    None => {
+                        registry.remove(key);
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::{Waiter, Waiters};
+
+    async fn woken(waiter: &Waiter<'_>) -> bool {
+        tokio::time::timeout(Duration::from_millis(10), waiter.wait()).await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn signal_before_wait() {
+        let waiters = Waiters::default();
+        let waiter = waiters.register(&[Bytes::from("a"), Bytes::from("b")]);
+        waiters.signal(b"c");
+        assert!(tokio::time::timeout(Duration::from_millis(10), waiter.wait()).await.is_err());
+        // 登记之后、等待之前的通知不会丢失
+        waiters.signal(b"b");
+        tokio::time::timeout(Duration::from_millis(10), waiter.wait()).await.unwrap();
+        assert_eq!(waiters.blocking_keys(), 2);
+        drop(waiter);
+        assert_eq!(waiters.blocking_keys(), 0);
+    }
+
+    #[tokio::test]
+    async fn signal_first_in_order() {
+        let waiters = Waiters::default();
+        let key = [Bytes::from("k")];
+        let first = waiters.register(&key);
+        let second = waiters.register(&key);
+
+        waiters.signal_first(b"k");
+        assert!(!woken(&second).await);
+        assert!(woken(&first).await);
+        // 第一个连接离开后唤醒传给下一个
+        drop(first);
+        assert!(woken(&second).await);
+
+        drop(second);
+        assert_eq!(waiters.blocking_keys(), 0);
+
+        // 没有被选中唤醒过的连接离开时不影响其他连接
+        let third = waiters.register(&key);
+        let fourth = waiters.register(&key);
+        drop(third);
+        assert!(!woken(&fourth).await);
+        waiters.signal(b"k");
+        assert!(woken(&fourth).await);
+    }
+}
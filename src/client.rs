@@ -0,0 +1,293 @@
+//! 客户端实现，基于本库自己的 `Connection` 和 `Frame`。
+//!
+//! 除了逐条发送命令的 `Client`，还提供了 `Pipeline`：一次性发送多条命令，再依次读取回复，减少网络往返。
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use crate::{cmd::{Config, Del, Exists, Expiration, Get, Info, Ping, Publish, Set, SlowLog}, connection::Connection, frame::Frame, slowlog::SlowLogEntry};
+
+/// 与 redis 服务端建立的连接
+pub struct Client {
+    connection: Connection,
+}
+
+/// 连接到指定地址的 redis 服务端
+///
+/// # Example
+/// ```no_run
+/// use toyredis::client;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut client = client::connect("127.0.0.1:6379").await.unwrap();
+///     client.set("hello", "world".into()).await.unwrap();
+/// }
+/// ```
+pub async fn connect<T: ToSocketAddrs>(addr: T) -> crate::Result<Client> {
+    let socket = TcpStream::connect(addr).await?;
+    let connection = Connection::new(socket);
+    Ok(Client { connection })
+}
+
+impl Client {
+    /// `PING [message]`
+    pub async fn ping(&mut self, msg: Option<Bytes>) -> crate::Result<Bytes> {
+        let frame = Ping::new(msg).into_frame();
+        Ok(Bytes::try_from(self.request(&frame).await?)?)
+    }
+
+    /// `GET key`，key 不存在时返回 `None`
+    pub async fn get(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        let frame = Get::new(key.to_string()).into_frame();
+        match self.request(&frame).await? {
+            Frame::Null => Ok(None),
+            frame => Ok(Some(Bytes::try_from(frame)?)),
+        }
+    }
+
+    /// `SET key value`
+    pub async fn set(&mut self, key: &str, value: Bytes) -> crate::Result<()> {
+        self.set_cmd(Set::new(key.to_string(), value, None)).await
+    }
+
+    /// `SET key value PX milliseconds`，设置值的同时指定存活时间
+    pub async fn set_expires(&mut self, key: &str, value: Bytes, expiration: Duration) -> crate::Result<()> {
+        let expire = Expiration::Milliseconds(expiration.as_millis() as u64);
+        self.set_cmd(Set::new(key.to_string(), value, Some(expire))).await
+    }
+
+    async fn set_cmd(&mut self, cmd: Set) -> crate::Result<()> {
+        match self.request(&cmd.into_frame()).await? {
+            Frame::Simple(resp) if resp == "OK" => Ok(()),
+            frame => Err(unexpected_frame(frame)),
+        }
+    }
+
+    /// `DEL key [key ...]`，返回删除的 key 数量
+    pub async fn del(&mut self, keys: &[&str]) -> crate::Result<u64> {
+        let frame = Del::new(to_bytes_vec(keys)).into_frame();
+        Ok(self.request(&frame).await?.as_int()? as u64)
+    }
+
+    /// `EXISTS key [key ...]`，返回存在的 key 数量
+    pub async fn exists(&mut self, keys: &[&str]) -> crate::Result<u64> {
+        let frame = Exists::new(to_bytes_vec(keys)).into_frame();
+        Ok(self.request(&frame).await?.as_int()? as u64)
+    }
+
+    /// `PUBLISH channel message`，返回收到消息的订阅者数量
+    pub async fn publish(&mut self, channel: &str, message: Bytes) -> crate::Result<u64> {
+        let frame = Publish::new(channel.to_string(), message).into_frame();
+        Ok(self.request(&frame).await?.as_int()? as u64)
+    }
+
+    /// `INFO [section]`，返回服务端状态的文本，section 为 `None` 时返回默认的 section
+    pub async fn info(&mut self, section: Option<&str>) -> crate::Result<String> {
+        let info = match section {
+            Some(section) => Info::section(section),
+            None => Info::new(),
+        };
+        Ok(String::try_from(self.request(&info.into_frame()).await?)?)
+    }
+
+    /// `CONFIG GET pattern`，返回名称匹配 glob 模式的配置项及其值
+    pub async fn config_get(&mut self, pattern: &str) -> crate::Result<Vec<(String, String)>> {
+        let pairs = match self.request(&Config::get(pattern.to_string()).into_frame()).await? {
+            Frame::Map(pairs) => pairs,
+            // RESP2 中 map 以数组的形式返回，名称与值交替出现
+            Frame::Array(items) if items.len() % 2 == 0 => {
+                let mut items = items.into_iter();
+                std::iter::from_fn(|| Some((items.next()?, items.next()?))).collect()
+            },
+            frame => return Err(unexpected_frame(frame)),
+        };
+        pairs
+            .into_iter()
+            .map(|(name, value)| Ok((String::try_from(name)?, String::try_from(value)?)))
+            .collect()
+    }
+
+    /// `CONFIG SET name value`
+    pub async fn config_set(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        match self.request(&Config::set(name, value).into_frame()).await? {
+            Frame::Simple(resp) if resp == "OK" => Ok(()),
+            frame => Err(unexpected_frame(frame)),
+        }
+    }
+
+    /// `SLOWLOG GET [count]`，最新的 count 条慢查询记录，count 为 `None` 时返回所有记录
+    pub async fn slowlog_get(&mut self, count: Option<usize>) -> crate::Result<Vec<SlowLogEntry>> {
+        let entries = Vec::<Frame>::try_from(self.request(&SlowLog::Get(count).into_frame()).await?)?;
+        entries
+            .into_iter()
+            .map(|entry| match <[Frame; 4]>::try_from(Vec::<Frame>::try_from(entry)?) {
+                Ok([id, timestamp, duration, args]) => {
+                    let args = Vec::<Frame>::try_from(args)?
+                        .into_iter()
+                        .map(Bytes::try_from)
+                        .collect::<Result<_, _>>()?;
+                    Ok(SlowLogEntry {
+                        id: id.as_int()? as u64,
+                        timestamp: timestamp.as_int()? as u64,
+                        duration: duration.as_int()? as u64,
+                        args,
+                    })
+                },
+                Err(fields) => Err(unexpected_frame(Frame::Array(fields))),
+            })
+            .collect()
+    }
+
+    /// `SLOWLOG LEN`
+    pub async fn slowlog_len(&mut self) -> crate::Result<usize> {
+        Ok(self.request(&SlowLog::Len.into_frame()).await?.as_int()? as usize)
+    }
+
+    /// `SLOWLOG RESET`
+    pub async fn slowlog_reset(&mut self) -> crate::Result<()> {
+        match self.request(&SlowLog::Reset.into_frame()).await? {
+            Frame::Simple(resp) if resp == "OK" => Ok(()),
+            frame => Err(unexpected_frame(frame)),
+        }
+    }
+
+    /// 创建一个 pipeline，命令会先缓存起来，直到调用 `Pipeline::execute` 才一起发送
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline { client: self, frames: vec![] }
+    }
+
+    /// 发送一个请求并读取回复。服务端返回的错误会被转换为 `Err`
+    async fn request(&mut self, frame: &Frame) -> crate::Result<Frame> {
+        self.connection.write_frame(frame).await?;
+        self.read_response().await
+    }
+
+    async fn read_response(&mut self) -> crate::Result<Frame> {
+        match self.connection.read_frame().await? {
+            Some(Frame::Error(msg)) => Err(msg.into()),
+            Some(frame) => Ok(frame),
+            // 服务端在回复前关闭了连接
+            None => Err("connection reset by server".into()),
+        }
+    }
+}
+
+/// 批量发送命令。
+///
+/// 与逐条请求相比，pipeline 中所有命令只需要一次网络往返，适合批量写入等场景。
+/// 返回的回复与命令一一对应，服务端返回的错误以 `Frame::Error` 的形式保留在结果中，不会中断其余命令。
+pub struct Pipeline<'a> {
+    client: &'a mut Client,
+    frames: Vec<Frame>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn ping(&mut self, msg: Option<Bytes>) -> &mut Self {
+        self.frames.push(Ping::new(msg).into_frame());
+        self
+    }
+
+    pub fn get(&mut self, key: &str) -> &mut Self {
+        self.frames.push(Get::new(key.to_string()).into_frame());
+        self
+    }
+
+    pub fn set(&mut self, key: &str, value: Bytes) -> &mut Self {
+        self.frames.push(Set::new(key.to_string(), value, None).into_frame());
+        self
+    }
+
+    pub fn del(&mut self, keys: &[&str]) -> &mut Self {
+        self.frames.push(Del::new(to_bytes_vec(keys)).into_frame());
+        self
+    }
+
+    pub fn exists(&mut self, keys: &[&str]) -> &mut Self {
+        self.frames.push(Exists::new(to_bytes_vec(keys)).into_frame());
+        self
+    }
+
+    /// 缓存的命令数量
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// 发送所有缓存的命令，并按顺序返回各自的回复
+    pub async fn execute(self) -> crate::Result<Vec<Frame>> {
+        let connection = &mut self.client.connection;
+        connection.write_frames(&self.frames).await?;
+        let mut responses = Vec::with_capacity(self.frames.len());
+        for _ in 0..self.frames.len() {
+            match connection.read_frame().await? {
+                Some(frame) => responses.push(frame),
+                None => return Err("connection reset by server".into()),
+            }
+        }
+        Ok(responses)
+    }
+}
+
+fn to_bytes_vec(keys: &[&str]) -> Vec<Bytes> {
+    keys.iter()
+        .map(|key| Bytes::copy_from_slice(key.as_bytes()))
+        .collect()
+}
+
+fn unexpected_frame(frame: Frame) -> crate::Error {
+    format!("protocol error; unexpected {} frame", frame.kind()).into()
+}
+
+#[cfg(all(test, not(feature = "uring")))]
+mod tests {
+    use bytes::Bytes;
+    use tokio::net::TcpListener;
+
+    use crate::{config::Config, db::Db, frame::Frame, object::RedisObject, server::Server, types::Hash};
+
+    #[tokio::test]
+    async fn commands_and_pipeline() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let db = Db::new();
+        let server = tokio::spawn(Server::new(Config::default()).db(db.clone()).serve(listener, std::future::pending::<()>()));
+        let mut client = super::connect(addr).await.unwrap();
+
+        assert_eq!(client.ping(None).await.unwrap(), Bytes::from("PONG"));
+        assert_eq!(client.ping(Some(Bytes::from("hi"))).await.unwrap(), Bytes::from("hi"));
+        assert_eq!(client.get("a").await.unwrap(), None);
+        client.set("a", Bytes::from("1")).await.unwrap();
+        assert_eq!(client.get("a").await.unwrap(), Some(Bytes::from("1")));
+        assert_eq!(client.exists(&["a", "b", "a"]).await.unwrap(), 2);
+        assert_eq!(client.del(&["a", "b"]).await.unwrap(), 1);
+
+        let mut pipeline = client.pipeline();
+        pipeline.set("b", Bytes::from("2")).get("b").exists(&["b"]).del(&["b"]).ping(None);
+        assert_eq!(pipeline.len(), 5);
+        let responses = pipeline.execute().await.unwrap();
+        assert_eq!(responses, [
+            Frame::Simple("OK".into()),
+            Frame::Bulk(Bytes::from("2")),
+            Frame::Integer(1),
+            Frame::Integer(1),
+            Frame::Simple("PONG".into()),
+        ]);
+        assert!(client.pipeline().execute().await.unwrap().is_empty());
+
+        // 服务端返回的错误：单条请求转换为 Err，pipeline 中保留在结果里，不影响之后的命令
+        db.update(&Bytes::from("hash"), |value| *value = Some(RedisObject::Hash(Hash::new())));
+        assert!(client.get("hash").await.unwrap_err().to_string().starts_with("WRONGTYPE"));
+        let mut pipeline = client.pipeline();
+        pipeline.get("hash").ping(None);
+        let responses = pipeline.execute().await.unwrap();
+        assert!(matches!(&responses[0], Frame::Error(msg) if msg.starts_with("WRONGTYPE")));
+        assert_eq!(responses[1], Frame::Simple("PONG".into()));
+        server.abort();
+    }
+}
@@ -0,0 +1,244 @@
+//! 客户端连接的注册表，供 `CLIENT` 命令使用。
+//!
+//! - [`Clients`] 保存在 `Db` 中，记录所有存活的连接；
+//! - [`ClientHandle`] 由连接的处理循环持有，drop 时从注册表中移除。
+//!
+//! `CLIENT KILL` 通过注册表通知目标连接退出，目标连接在等待下一条请求时收到通知，
+//! 正在执行的命令会执行完并回复。空闲超时同样只在等待请求时检查，见 [`Client::idle_timeout`]。
+
+use std::{collections::BTreeMap, fmt::Write, net::SocketAddr, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}, time::{Duration, Instant}};
+
+use bytes::Bytes;
+use tokio::sync::Notify;
+
+/// 等待空闲超时的过程中，至少每隔这么久重新读取一次超时时间
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 所有存活的连接，按 id 排序
+#[derive(Default)]
+pub struct Clients {
+    /// 下一个连接的 id，与 redis 一样从 1 开始
+    next_id: AtomicU64,
+    clients: Mutex<BTreeMap<u64, Arc<Client>>>,
+}
+
+/// 一个连接的信息
+pub struct Client {
+    id: u64,
+    addr: SocketAddr,
+    connected_at: Instant,
+    state: Mutex<ClientState>,
+    /// `CLIENT KILL` 通过它通知连接退出
+    kill: Notify,
+}
+
+struct ClientState {
+    /// `CLIENT SETNAME` 设置的名称
+    name: Option<Bytes>,
+    /// 最近一次收到命令或者发出回复的时间
+    last_interaction: Instant,
+    /// 最近执行的命令
+    last_command: String,
+    /// 登录的用户，未登录时为 `None`，见 [`crate::acl`]
+    user: Option<String>,
+}
+
+/// 连接存活期间持有，drop 时从注册表中移除
+pub struct ClientHandle<'a> {
+    clients: &'a Clients,
+    client: Arc<Client>,
+}
+
+impl Drop for ClientHandle<'_> {
+    fn drop(&mut self) {
+        self.clients.clients.lock().unwrap().remove(&self.client.id);
+    }
+}
+
+impl std::ops::Deref for ClientHandle<'_> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl Clients {
+    /// 注册一个新连接，addr 为客户端的地址
+    pub fn register(&self, addr: SocketAddr) -> ClientHandle<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        let client = Arc::new(Client {
+            id,
+            addr,
+            connected_at: now,
+            state: Mutex::new(ClientState { name: None, last_interaction: now, last_command: "NULL".to_string(), user: None }),
+            kill: Notify::new(),
+        });
+        self.clients.lock().unwrap().insert(id, client.clone());
+        ClientHandle { clients: self, client }
+    }
+
+    /// `CLIENT LIST` 的内容，每个连接一行
+    pub fn list(&self) -> String {
+        let mut list = String::new();
+        for client in self.clients.lock().unwrap().values() {
+            writeln!(list, "{}", client.describe()).unwrap();
+        }
+        list
+    }
+
+    /// 通知满足 filter 的连接退出，返回通知的连接数
+    pub fn kill(&self, mut filter: impl FnMut(&Client) -> bool) -> usize {
+        let clients = self.clients.lock().unwrap();
+        let mut killed = 0;
+        for client in clients.values().filter(|client| filter(client)) {
+            // 目标连接可能还没有在等待，notify_one 会保留通知直到它下次等待
+            client.kill.notify_one();
+            killed += 1;
+        }
+        killed
+    }
+}
+
+impl Client {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn name(&self) -> Option<Bytes> {
+        self.state.lock().unwrap().name.clone()
+    }
+
+    /// 名称为空时清除名称
+    pub fn set_name(&self, name: Bytes) {
+        self.state.lock().unwrap().name = if name.is_empty() { None } else { Some(name) };
+    }
+
+    /// 登录的用户
+    pub fn user(&self) -> Option<String> {
+        self.state.lock().unwrap().user.clone()
+    }
+
+    pub fn set_user(&self, user: String) {
+        self.state.lock().unwrap().user = Some(user);
+    }
+
+    /// 记录连接执行了一条命令
+    pub fn touch(&self, command: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.last_interaction = Instant::now();
+        command.clone_into(&mut state.last_command);
+    }
+
+    /// 记录连接发出了回复，空闲时间从这时开始计算
+    pub fn interacted(&self) {
+        self.state.lock().unwrap().last_interaction = Instant::now();
+    }
+
+    /// 距离最近一次收到命令或者发出回复的时间
+    pub fn idle(&self) -> Duration {
+        self.state.lock().unwrap().last_interaction.elapsed()
+    }
+
+    /// 等到连接空闲超过 timeout() 时返回，timeout() 为 0 时不超时。
+    ///
+    /// 超时时间可能被 `CONFIG SET timeout` 修改，等待期间会定期重新读取
+    pub async fn idle_timeout(&self, timeout: impl Fn() -> Duration) {
+        loop {
+            let (timeout, idle) = (timeout(), self.idle());
+            if !timeout.is_zero() && idle >= timeout {
+                return;
+            }
+            let wait = if timeout.is_zero() { IDLE_CHECK_INTERVAL } else { (timeout - idle).min(IDLE_CHECK_INTERVAL) };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// 等待 `CLIENT KILL` 的通知。通知收到后重新放回，之后再等待时立即返回，
+    /// 阻塞命令放弃等待后，处理循环仍然能看到连接已被断开
+    pub async fn killed(&self) {
+        self.kill.notified().await;
+        self.kill.notify_one();
+    }
+
+    /// `CLIENT LIST` 中的一行，字段与 redis 相同的部分使用相同的名称
+    fn describe(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let name = state.name.as_deref().map(String::from_utf8_lossy).unwrap_or_default();
+        format!(
+            "id={} addr={} name={} age={} idle={} cmd={} user={}",
+            self.id,
+            self.addr,
+            name,
+            self.connected_at.elapsed().as_secs(),
+            state.last_interaction.elapsed().as_secs(),
+            state.last_command,
+            state.user.as_deref().unwrap_or(""),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+
+    use super::Clients;
+
+    #[test]
+    fn registry() {
+        let clients = Clients::default();
+        let first = clients.register("127.0.0.1:5000".parse().unwrap());
+        let second = clients.register("127.0.0.1:5001".parse().unwrap());
+        assert_eq!((first.id(), second.id()), (1, 2));
+
+        first.set_name(Bytes::from("worker"));
+        first.set_user("default".to_string());
+        second.touch("get");
+        assert_eq!(first.name(), Some(Bytes::from("worker")));
+        let list = clients.list();
+        let lines: Vec<_> = list.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("id=1 addr=127.0.0.1:5000 name=worker age=0 idle=0 cmd=NULL"));
+        assert!(lines[0].ends_with("user=default"));
+        assert!(lines[1].ends_with("name= age=0 idle=0 cmd=get user="));
+
+        first.set_name(Bytes::new());
+        assert_eq!(first.name(), None);
+
+        assert_eq!(clients.kill(|client| client.addr().port() == 5001), 1);
+        drop(second);
+        assert_eq!(clients.list().lines().count(), 1);
+        assert_eq!(clients.kill(|client| client.id() == 2), 0);
+    }
+
+    #[tokio::test]
+    async fn kill_before_wait() {
+        let clients = Clients::default();
+        let client = clients.register("127.0.0.1:5000".parse().unwrap());
+        clients.kill(|_| true);
+        // 通知在连接开始等待之前发出也不会丢失
+        client.killed().await;
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let clients = Clients::default();
+        let client = clients.register("127.0.0.1:5000".parse().unwrap());
+        let started = Instant::now();
+        client.idle_timeout(|| Duration::from_millis(50)).await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        // 发出回复后重新计算空闲时间
+        client.interacted();
+        assert!(client.idle() < Duration::from_millis(50));
+        let never = tokio::time::timeout(Duration::from_millis(100), client.idle_timeout(|| Duration::ZERO));
+        assert!(never.await.is_err());
+    }
+}
@@ -0,0 +1,311 @@
+//! 简化的集群模式。
+//!
+//! 与 redis 集群一样，键空间被划分为 16384 个槽，key 所在的槽为 `CRC16(key) % 16384`。
+//! key 中含有 hash tag，即第一个 `{` 与它之后第一个 `}` 之间的内容不为空时，只对 hash tag 计算 CRC16，
+//! 这样相关的 key 可以被放进同一个槽。
+//!
+//! 槽到节点的映射是静态的，由配置项 `cluster-nodes` 给出，节点之间不通信，也不支持迁移槽与故障转移。
+//! 访问的 key 不由本节点负责时回复 `-MOVED 槽 地址`，客户端据此重定向到负责的节点。
+//! 一条命令（以及一个事务中的所有命令）访问的 key 必须位于同一个槽，否则回复 `-CROSSSLOT`。
+
+use std::fmt;
+
+use crate::{cmd::Command, frame::Frame};
+
+/// 槽的个数
+pub const SLOTS: u16 = 16384;
+
+/// CRC16/XMODEM（多项式 0x1021，初始值 0），与 redis 集群使用的算法相同
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &b| {
+        let mut crc = crc ^ ((b as u16) << 8);
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// key 中参与计算槽的部分：有不为空的 hash tag 时为 hash tag，否则为整个 key
+pub fn hash_tag(key: &[u8]) -> &[u8] {
+    if let Some(start) = key.iter().position(|&b| b == b'{') {
+        let rest = &key[start + 1..];
+        if let Some(end) = rest.iter().position(|&b| b == b'}') {
+            if end > 0 {
+                return &rest[..end];
+            }
+        }
+    }
+    key
+}
+
+/// key 所在的槽
+pub fn key_slot(key: &[u8]) -> u16 {
+    crc16(hash_tag(key)) & (SLOTS - 1)
+}
+
+/// 节点 ID：地址的 SHA1，40 个十六进制字符。节点由配置静态给出，ID 只在回复中使用
+pub fn node_id(addr: &str) -> String {
+    sha1_smol::Sha1::from(addr).digest().to_string()
+}
+
+/// 集群中的一个节点及其负责的槽
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    /// `ip:port`
+    pub addr: String,
+    /// 负责的槽，每一项为闭区间 `(起始, 结束)`
+    pub slots: Vec<(u16, u16)>,
+}
+
+impl Node {
+    pub fn id(&self) -> String {
+        node_id(&self.addr)
+    }
+
+    /// 拆开地址中的 ip 与端口，解析配置时已经检查过格式
+    pub fn ip_port(&self) -> (&str, u16) {
+        let (ip, port) = self.addr.rsplit_once(':').unwrap();
+        (ip, port.parse().unwrap())
+    }
+
+    pub fn owns(&self, slot: u16) -> bool {
+        self.slots.iter().any(|&(start, end)| (start..=end).contains(&slot))
+    }
+}
+
+/// 静态的槽到节点的映射，对应配置项 `cluster-nodes`。
+///
+/// 配置的值依次为节点的地址与它负责的槽，槽之间以逗号分隔，可以是单个槽或者 `起始-结束`，
+/// 如 `127.0.0.1:7000 0-5460 127.0.0.1:7001 5461-10922,16383`。同一个槽不能分配给多个节点
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SlotMap {
+    nodes: Vec<Node>,
+}
+
+impl SlotMap {
+    pub fn parse(value: &str) -> crate::Result<SlotMap> {
+        let args: Vec<_> = value.split_whitespace().collect();
+        if args.len() % 2 != 0 {
+            return Err("wrong number of arguments".into());
+        }
+        let mut assigned = vec![false; SLOTS as usize];
+        let mut nodes = Vec::with_capacity(args.len() / 2);
+        for args in args.chunks(2) {
+            let addr = args[0];
+            if !addr.rsplit_once(':').is_some_and(|(ip, port)| !ip.is_empty() && port.parse::<u16>().is_ok()) {
+                return Err(format!("invalid node address '{}'", addr).into());
+            }
+            let mut slots = vec![];
+            for range in args[1].split(',') {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                let (start, end) = match (start.parse::<u16>(), end.parse::<u16>()) {
+                    (Ok(start), Ok(end)) if start <= end && end < SLOTS => (start, end),
+                    _ => return Err(format!("invalid slot range '{}'", range).into()),
+                };
+                for slot in start..=end {
+                    if std::mem::replace(&mut assigned[slot as usize], true) {
+                        return Err(format!("slot {} is assigned more than once", slot).into());
+                    }
+                }
+                slots.push((start, end));
+            }
+            nodes.push(Node { addr: addr.to_string(), slots });
+        }
+        Ok(SlotMap { nodes })
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// 负责 slot 的节点
+    pub fn owner(&self, slot: u16) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.owns(slot))
+    }
+
+    /// 已经分配给节点的槽数
+    pub fn assigned(&self) -> usize {
+        self.nodes.iter().flat_map(|node| &node.slots).map(|&(start, end)| (end - start) as usize + 1).sum()
+    }
+}
+
+impl fmt::Display for SlotMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{} ", node.addr)?;
+            for (j, &(start, end)) in node.slots.iter().enumerate() {
+                if j > 0 {
+                    f.write_str(",")?;
+                }
+                match start == end {
+                    true => write!(f, "{}", start)?,
+                    false => write!(f, "{}-{}", start, end)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 集群模式下的状态：槽的分配，以及本节点的地址
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    myself: String,
+    slots: SlotMap,
+}
+
+impl Cluster {
+    /// myself 为本节点的地址，与 slots 中节点的地址比较
+    pub fn new(myself: impl Into<String>, slots: SlotMap) -> Cluster {
+        Cluster { myself: myself.into(), slots }
+    }
+
+    pub fn myself(&self) -> &str {
+        &self.myself
+    }
+
+    pub fn slots(&self) -> &SlotMap {
+        &self.slots
+    }
+
+    /// 检查 slot 是否由本节点负责
+    pub fn check_slot(&self, slot: u16) -> Result<(), Redirect> {
+        match self.slots.owner(slot) {
+            Some(node) if node.addr == self.myself => Ok(()),
+            Some(node) => Err(Redirect::Moved { slot, addr: node.addr.clone() }),
+            None => Err(Redirect::Down),
+        }
+    }
+
+    /// 检查命令访问的 key 是否位于同一个槽，并且这个槽由本节点负责，返回 key 所在的槽，命令没有 key 时为 `None`。
+    /// args 为客户端发来的原始 frame，按 [`Command::slot_spec`] 取出 key，分片频道也按 key 计算
+    pub fn check(&self, cmd: &Command, args: &Frame) -> Result<Option<u16>, Redirect> {
+        let args = match args {
+            Frame::Array(args) => &args[..],
+            _ => &[],
+        };
+        let slot = keys_slot(cmd.slot_spec().keys(args))?;
+        if let Some(slot) = slot {
+            self.check_slot(slot)?;
+        }
+        Ok(slot)
+    }
+}
+
+/// 一组 key 共同所在的槽，没有 key 时为 `None`，不在同一个槽时返回 [`Redirect::CrossSlot`]
+pub fn keys_slot<'a>(keys: impl IntoIterator<Item = &'a [u8]>) -> Result<Option<u16>, Redirect> {
+    let mut slots = keys.into_iter().map(key_slot);
+    let first = slots.next();
+    match first {
+        Some(first) if slots.any(|slot| slot != first) => Err(Redirect::CrossSlot),
+        _ => Ok(first),
+    }
+}
+
+/// 命令访问的 key 不由本节点负责
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redirect {
+    /// 槽由其他节点负责
+    Moved { slot: u16, addr: String },
+    /// 槽没有分配给任何节点
+    Down,
+    /// 访问的 key 不在同一个槽
+    CrossSlot,
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redirect::Moved { slot, addr } => write!(f, "MOVED {} {}", slot, addr),
+            Redirect::Down => f.write_str("CLUSTERDOWN Hash slot not served"),
+            Redirect::CrossSlot => f.write_str("CROSSSLOT Keys in request don't hash to the same slot"),
+        }
+    }
+}
+
+impl std::error::Error for Redirect {}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{cmd::Command, frame::Frame};
+
+    use super::{Cluster, Redirect, SlotMap, crc16, hash_tag, key_slot, keys_slot};
+
+    #[test]
+    fn slots() {
+        // redis 集群规范中给出的校验值
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(key_slot(b"foo"), 12182);
+        assert_eq!(key_slot(b""), 0);
+        assert_eq!(hash_tag(b"{user1000}.following"), b"user1000");
+        assert_eq!(hash_tag(b"foo{}{bar}"), b"foo{}{bar}");
+        assert_eq!(hash_tag(b"foo{{bar}}zap"), b"{bar");
+        assert_eq!(hash_tag(b"foo{bar}{zap}"), b"bar");
+        assert_eq!(hash_tag(b"foo{bar"), b"foo{bar");
+        assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"{user1000}.followers"));
+    }
+
+    #[test]
+    fn slot_map() {
+        let value = "127.0.0.1:7000 0-5460 127.0.0.1:7001 5461-10922,16383";
+        let map = SlotMap::parse(value).unwrap();
+        assert_eq!(map.to_string(), value);
+        assert_eq!(map.assigned(), 10924);
+        assert_eq!(map.owner(5461).unwrap().addr, "127.0.0.1:7001");
+        assert_eq!(map.owner(16383).unwrap().ip_port(), ("127.0.0.1", 7001));
+        assert!(map.owner(10923).is_none());
+        assert_eq!(SlotMap::parse("").unwrap(), SlotMap::default());
+
+        assert!(SlotMap::parse("127.0.0.1:7000").is_err());
+        assert!(SlotMap::parse("127.0.0.1 0-10").is_err());
+        assert!(SlotMap::parse("127.0.0.1:7000 10-0").is_err());
+        assert!(SlotMap::parse("127.0.0.1:7000 0-16384").is_err());
+        assert!(SlotMap::parse("127.0.0.1:7000 0-10 127.0.0.1:7001 10").is_err());
+    }
+
+    #[test]
+    fn redirect() {
+        let map = SlotMap::parse("127.0.0.1:7000 0-8191 127.0.0.1:7001 8192-16382").unwrap();
+        let cluster = Cluster::new("127.0.0.1:7000", map);
+        let frame = |args: &[&str]| Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+        let check = |args: &[&str]| cluster.check(&Command::from_frame(frame(args)).unwrap(), &frame(args));
+
+        // foo 在 12182 号槽
+        assert_eq!(check(&["get", "foo"]), Err(Redirect::Moved { slot: 12182, addr: "127.0.0.1:7001".into() }));
+        assert_eq!(check(&["get", "{b}foo"]), Ok(Some(3300)));
+        assert_eq!(check(&["ping"]), Ok(None));
+        assert_eq!(check(&["mget", "foo", "{foo}bar"]).unwrap_err().to_string(), "MOVED 12182 127.0.0.1:7001");
+        assert_eq!(cluster.check_slot(16383), Err(Redirect::Down));
+    }
+
+    #[test]
+    fn cross_slot() {
+        assert_eq!(keys_slot([]), Ok(None));
+        assert_eq!(keys_slot([&b"{b}1"[..], b"{b}2", b"b"]), Ok(Some(3300)));
+        assert_eq!(keys_slot([&b"{b}1"[..], b"{c}1"]), Err(Redirect::CrossSlot));
+
+        // 按命令的 key 位置取出 key，MSET 的值不参与计算
+        let cluster = Cluster::new("127.0.0.1:7000", SlotMap::parse("127.0.0.1:7000 0-16383").unwrap());
+        let frame = |args: &[&str]| Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+        let check = |args: &[&str]| cluster.check(&Command::from_frame(frame(args)).unwrap(), &frame(args));
+        assert_eq!(check(&["mset", "{b}1", "x", "{b}2", "y"]), Ok(Some(3300)));
+        assert_eq!(check(&["mset", "{b}1", "x", "{c}2", "y"]), Err(Redirect::CrossSlot));
+        assert_eq!(check(&["sinterstore", "{c}dest", "{b}1", "{b}2"]), Err(Redirect::CrossSlot));
+        assert_eq!(check(&["eval", "return 1", "2", "{b}1", "{b}2", "{c}arg"]), Ok(Some(3300)));
+
+        // 分片频道与 key 一样需要位于同一个槽，普通频道不受限制
+        assert_eq!(check(&["spublish", "{b}news", "hi"]), Ok(Some(3300)));
+        assert_eq!(check(&["ssubscribe", "{b}1", "{c}1"]), Err(Redirect::CrossSlot));
+        assert_eq!(check(&["sunsubscribe"]), Ok(None));
+        assert_eq!(check(&["subscribe", "{b}1", "{c}1"]), Ok(None));
+        let cluster = Cluster::new("127.0.0.1:7000", SlotMap::parse("127.0.0.1:7000 0-100 127.0.0.1:7001 101-16383").unwrap());
+        let args = frame(&["spublish", "{b}news", "hi"]);
+        assert_eq!(cluster.check(&Command::from_frame(args.clone()).unwrap(), &args), Err(Redirect::Moved { slot: 3300, addr: "127.0.0.1:7001".into() }));
+    }
+}
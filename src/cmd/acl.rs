@@ -0,0 +1,47 @@
+use bytes::Bytes;
+
+use crate::{clients::ClientHandle, db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `ACL <subcommand>`，查看、修改用户，规则见 [`crate::acl`]
+#[derive(Debug)]
+pub enum Acl {
+    /// `ACL WHOAMI`，当前连接的用户名
+    WhoAmI,
+    /// `ACL LIST`，所有用户及其规则，每个用户一项
+    List,
+    /// `ACL SETUSER username [rule ...]`，修改用户，不存在时创建
+    SetUser(String, Vec<String>),
+}
+
+impl Acl {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Acl, ParseError> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_lowercase().as_str() {
+            "whoami" => Ok(Acl::WhoAmI),
+            "list" => Ok(Acl::List),
+            "setuser" => {
+                let user = parse.next_string()?;
+                let mut rules = vec![];
+                while parse.has_remaining() {
+                    rules.push(parse.next_string()?);
+                }
+                Ok(Acl::SetUser(user, rules))
+            },
+            _ => Err(format!("ERR unknown subcommand '{}'. Try ACL HELP.", subcommand).into()),
+        }
+    }
+
+    /// client 为执行命令的连接
+    pub fn apply(self, db: &Db, client: &ClientHandle<'_>) -> Frame {
+        match self {
+            Acl::WhoAmI => client.user().map_or(Frame::Null, |user| Frame::Bulk(Bytes::from(user))),
+            Acl::List => Frame::Array(db.acl().list().into_iter().map(|user| Frame::Bulk(Bytes::from(user))).collect()),
+            Acl::SetUser(user, rules) => match db.update_acl(|acl| acl.set_user(&user, &rules)) {
+                Ok(()) => Frame::Simple("OK".into()),
+                Err(err) => Frame::Error(err),
+            },
+        }
+    }
+}
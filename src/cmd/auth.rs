@@ -0,0 +1,41 @@
+use bytes::Bytes;
+
+use crate::{acl::DEFAULT_USER, clients::ClientHandle, db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `AUTH [username] password`，以指定用户的身份登录，不指定用户时为 default 用户，见 [`crate::acl`]
+#[derive(Debug)]
+pub struct Auth {
+    user: Option<String>,
+    password: Bytes,
+}
+
+impl Auth {
+    pub fn new(user: Option<String>, password: impl Into<Bytes>) -> Auth {
+        Auth { user, password: password.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Auth, ParseError> {
+        let first = parse.next_bytes()?;
+        if !parse.has_remaining() {
+            return Ok(Auth { user: None, password: first });
+        }
+        let user = String::from_utf8_lossy(&first).into_owned();
+        Ok(Auth { user: Some(user), password: parse.next_bytes()? })
+    }
+
+    /// 登录成功后 client 以新用户的身份执行之后的命令，失败时保持原来的身份
+    pub fn apply(self, db: &Db, client: &ClientHandle<'_>) -> Frame {
+        let acl = db.acl();
+        if self.user.is_none() && acl.default_nopass() {
+            return Frame::Error("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".into());
+        }
+        let user = self.user.unwrap_or_else(|| DEFAULT_USER.to_string());
+        if !acl.authenticate(&user, &self.password) {
+            return Frame::Error("WRONGPASS invalid username-password pair or user is disabled.".into());
+        }
+        client.set_user(user);
+        Frame::Simple("OK".into())
+    }
+}
@@ -0,0 +1,284 @@
+//! 位图相关命令，对应 redis 的 `bitops.c`。
+//!
+//! 位图就是普通的字符串，第 0 位是第一个字节的最高位。写入超出长度的位时字符串自动增长，新增部分以 0 填充
+
+use bytes::Bytes;
+
+use crate::{db::{Db, WrongType}, ds::perfstr::{SmartString, range_of, sds::SDS}, frame::Frame, object::{RedisObject, int_to_bytes}};
+
+use super::{Parse, ParseError};
+
+/// 最大的位偏移，与字符串的最大长度 512MB 对应
+const MAX_BIT_OFFSET: u64 = 512 * 1024 * 1024 * 8 - 1;
+
+/// `SETBIT key offset value`
+///
+/// 设置第 offset 位，返回该位原来的值
+#[derive(Debug)]
+pub struct SetBit {
+    key: Bytes,
+    offset: u64,
+    bit: bool,
+}
+
+impl SetBit {
+    pub fn new(key: impl Into<Bytes>, offset: u64, bit: bool) -> SetBit {
+        SetBit { key: key.into(), offset, bit }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SetBit, ParseError> {
+        let key = parse.next_bytes()?;
+        let offset = parse_offset(parse)?;
+        let bit = match parse.next_int() {
+            Ok(0) => false,
+            Ok(1) => true,
+            Ok(_) | Err(ParseError::Other(_)) => return Err("ERR bit is not an integer or out of range".into()),
+            Err(err) => return Err(err),
+        };
+        Ok(SetBit { key, offset, bit })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update(&self.key, |value| {
+            let sds = match value.get_or_insert_with(|| RedisObject::String(SDS::empty())).as_sds_mut() {
+                Some(sds) => sds,
+                None => return Frame::Error(WrongType.to_string()),
+            };
+            let index = (self.offset / 8) as usize;
+            let mask = 0x80 >> (self.offset % 8);
+            let byte = sds.val().get(index).copied().unwrap_or(0);
+            // 超出长度时 set_range 会补齐中间的 0
+            sds.set_range(index, &[if self.bit { byte | mask } else { byte & !mask }]);
+            Frame::Integer((byte & mask != 0) as i64)
+        })
+    }
+}
+
+/// `GETBIT key offset`
+///
+/// 第 offset 位的值，超出长度或者 key 不存在时为 0
+#[derive(Debug)]
+pub struct GetBit {
+    key: Bytes,
+    offset: u64,
+}
+
+impl GetBit {
+    pub fn new(key: impl Into<Bytes>, offset: u64) -> GetBit {
+        GetBit { key: key.into(), offset }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetBit, ParseError> {
+        let key = parse.next_bytes()?;
+        let offset = parse_offset(parse)?;
+        Ok(GetBit { key, offset })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_bytes(db, &self.key, |bytes| {
+            let byte = bytes.get((self.offset / 8) as usize).copied().unwrap_or(0);
+            Frame::Integer((byte & (0x80 >> (self.offset % 8)) != 0) as i64)
+        })
+    }
+}
+
+/// `BITCOUNT key [start end [BYTE | BIT]]`
+///
+/// 统计值为 1 的位数。可以指定闭区间 [start, end]，默认以字节为单位，下标可以为负数
+#[derive(Debug)]
+pub struct BitCount {
+    key: Bytes,
+    /// (start, end, 是否以位为单位)
+    range: Option<(i64, i64, bool)>,
+}
+
+impl BitCount {
+    pub fn new(key: impl Into<Bytes>) -> BitCount {
+        BitCount { key: key.into(), range: None }
+    }
+
+    /// 只统计 [start, end] 之间的字节
+    pub fn bytes(mut self, start: i64, end: i64) -> BitCount {
+        self.range = Some((start, end, false));
+        self
+    }
+
+    /// 只统计 [start, end] 之间的位
+    pub fn bits(mut self, start: i64, end: i64) -> BitCount {
+        self.range = Some((start, end, true));
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<BitCount, ParseError> {
+        let key = parse.next_bytes()?;
+        if !parse.has_remaining() {
+            return Ok(BitCount { key, range: None });
+        }
+        let start = parse.next_int()?;
+        // 只给出 start 时 redis 回复 syntax error
+        let end = parse.next_int().map_err(|err| match err {
+            ParseError::EndOfStream => "ERR syntax error".into(),
+            err => err,
+        })?;
+        let bit = if parse.has_remaining() {
+            match &parse.next_string()?.to_uppercase()[..] {
+                "BYTE" => false,
+                "BIT" => true,
+                _ => return Err("ERR syntax error".into()),
+            }
+        } else {
+            false
+        };
+        Ok(BitCount { key, range: Some((start, end, bit)) })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_bytes(db, &self.key, |bytes| {
+            let count = match self.range {
+                None => popcount(bytes),
+                Some((start, end, false)) => range_of(bytes.len(), start, end).map_or(0, |(start, end)| popcount(&bytes[start..end])),
+                Some((start, end, true)) => range_of(bytes.len() * 8, start, end).map_or(0, |(start, end)| {
+                    let (first, last) = (start / 8, (end - 1) / 8);
+                    // 减去首尾两个字节中不在范围内的位
+                    popcount(&bytes[first..=last])
+                        - (bytes[first] & !(0xff >> (start % 8))).count_ones() as usize
+                        - (bytes[last] & 0xffu8.checked_shr(((end - 1) % 8 + 1) as u32).unwrap_or(0)).count_ones() as usize
+                }),
+            };
+            Frame::Integer(count as i64)
+        })
+    }
+}
+
+/// BITOP 支持的运算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// `BITOP AND | OR | XOR | NOT destkey key [key ...]`
+///
+/// 对各个 key 按字节运算，结果保存到 destkey，返回结果的长度。
+/// 较短的字符串以 0 补齐，不存在的 key 视为空字符串；结果为空时删除 destkey
+#[derive(Debug)]
+pub struct BitOp {
+    op: BitOperation,
+    destination: Bytes,
+    keys: Vec<Bytes>,
+}
+
+impl BitOp {
+    pub fn new(op: BitOperation, destination: impl Into<Bytes>, keys: Vec<Bytes>) -> BitOp {
+        BitOp { op, destination: destination.into(), keys }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<BitOp, ParseError> {
+        let op = match &parse.next_string()?.to_uppercase()[..] {
+            "AND" => BitOperation::And,
+            "OR" => BitOperation::Or,
+            "XOR" => BitOperation::Xor,
+            "NOT" => BitOperation::Not,
+            _ => return Err("ERR syntax error".into()),
+        };
+        let destination = parse.next_bytes()?;
+        let mut keys = vec![parse.next_bytes()?];
+        while parse.has_remaining() {
+            keys.push(parse.next_bytes()?);
+        }
+        if op == BitOperation::Not && keys.len() > 1 {
+            return Err("ERR BITOP NOT must be called with a single source key.".into());
+        }
+        Ok(BitOp { op, destination, keys })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut values = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            match db.get(key) {
+                Ok(value) => values.push(value.unwrap_or_default()),
+                Err(err) => return Frame::Error(err.to_string()),
+            }
+        }
+        let len = values.iter().map(Bytes::len).max().unwrap_or(0);
+        let mut result = vec![0; len];
+        for (i, byte) in result.iter_mut().enumerate() {
+            let mut bytes = values.iter().map(|value| value.get(i).copied().unwrap_or(0));
+            let first = bytes.next().unwrap();
+            *byte = match self.op {
+                BitOperation::And => bytes.fold(first, |acc, b| acc & b),
+                BitOperation::Or => bytes.fold(first, |acc, b| acc | b),
+                BitOperation::Xor => bytes.fold(first, |acc, b| acc ^ b),
+                BitOperation::Not => !first,
+            };
+        }
+        if result.is_empty() {
+            db.del(&self.destination);
+        } else {
+            db.insert(self.destination, RedisObject::String(SDS::new(&result)), None);
+        }
+        Frame::Integer(len as i64)
+    }
+}
+
+/// 解析位偏移，超出范围时与 redis 回复相同的错误
+fn parse_offset(parse: &mut Parse) -> Result<u64, ParseError> {
+    match parse.next_int() {
+        Ok(offset) if (0..=MAX_BIT_OFFSET as i64).contains(&offset) => Ok(offset as u64),
+        Ok(_) | Err(ParseError::Other(_)) => Err("ERR bit offset is not an integer or out of range".into()),
+        Err(err) => Err(err),
+    }
+}
+
+/// 以字节的形式读取字符串，key 不存在时为空
+fn with_bytes(db: &Db, key: &[u8], f: impl FnOnce(&[u8]) -> Frame) -> Frame {
+    db.lookup_read(key, |value| match value {
+        None => f(&[]),
+        Some(RedisObject::String(sds)) => f(sds.val()),
+        Some(RedisObject::Int(n)) => f(&int_to_bytes(*n)),
+        Some(_) => Frame::Error(WrongType.to_string()),
+    })
+}
+
+/// 值为 1 的位数，每次处理 8 个字节
+fn popcount(bytes: &[u8]) -> usize {
+    let mut words = bytes.chunks_exact(8);
+    let count: usize = (&mut words).map(|word| u64::from_ne_bytes(word.try_into().unwrap()).count_ones() as usize).sum();
+    count + words.remainder().iter().map(|byte| byte.count_ones() as usize).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{cmd::Command, db::Db, frame::{Frame, Protocol}};
+
+    use super::popcount;
+
+    #[test]
+    fn popcount_words() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for len in [0, 1, 7, 8, 9, 100, 256] {
+            let expected: u32 = bytes[..len].iter().map(|b| b.count_ones()).sum();
+            assert_eq!(popcount(&bytes[..len]), expected as usize);
+        }
+    }
+
+    #[test]
+    fn bitcount_negative() {
+        let db = Db::new();
+        db.set(Bytes::from("s"), Bytes::from("abc"), None);
+        db.set(Bytes::from("e"), Bytes::new(), None);
+        let bitcount = |args: &[&str]| {
+            let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+            Command::from_frame(frame).unwrap().apply(&db, &mut Protocol::Resp2)
+        };
+        assert_eq!(bitcount(&["bitcount", "s", "-1", "-1"]), Frame::Integer(4));
+        assert_eq!(bitcount(&["bitcount", "s", "0", "-4"]), Frame::Integer(0));
+        assert_eq!(bitcount(&["bitcount", "e", "0", "-1"]), Frame::Integer(0));
+        assert_eq!(bitcount(&["bitcount", "s", "0", "-25", "bit"]), Frame::Integer(0));
+        assert_eq!(bitcount(&["bitcount", "e", "0", "-1", "bit"]), Frame::Integer(0));
+    }
+}
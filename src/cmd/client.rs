@@ -0,0 +1,111 @@
+//! `CLIENT` 命令。连接的信息保存在 [`crate::clients`] 中，当前连接由处理循环传入
+
+use bytes::Bytes;
+
+use crate::{clients::{Client as ClientInfo, ClientHandle}, db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `CLIENT <subcommand>`，查看、管理连接
+#[derive(Debug)]
+pub enum Client {
+    /// `CLIENT LIST`，所有连接的信息，每个连接一行
+    List,
+    /// `CLIENT ID`，当前连接的 id
+    Id,
+    /// `CLIENT SETNAME name`，设置当前连接的名称，为空时清除名称
+    SetName(Bytes),
+    /// `CLIENT GETNAME`，当前连接的名称，没有时返回 Null
+    GetName,
+    /// `CLIENT KILL`，断开满足条件的连接
+    Kill(Kill),
+}
+
+/// `CLIENT KILL` 的条件
+#[derive(Debug, Default)]
+pub struct Kill {
+    /// 旧的形式 `CLIENT KILL addr`，只断开这一个连接，回复 OK 或者错误
+    legacy: bool,
+    id: Option<u64>,
+    addr: Option<String>,
+    /// 新的形式 `CLIENT KILL [ID id] [ADDR addr] [SKIPME yes/no]` 默认不断开当前连接，回复断开的连接数
+    skip_me: bool,
+}
+
+impl Client {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Client, ParseError> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_lowercase().as_str() {
+            "list" => Ok(Client::List),
+            "id" => Ok(Client::Id),
+            "setname" => {
+                let name = parse.next_bytes()?;
+                // 名称会出现在 CLIENT LIST 中，不能包含空格、换行等字符
+                if name.iter().any(|c| !(b'!'..=b'~').contains(c)) {
+                    return Err("ERR Client names cannot contain spaces, newlines or special characters.".into());
+                }
+                Ok(Client::SetName(name))
+            },
+            "getname" => Ok(Client::GetName),
+            "kill" => Ok(Client::Kill(Kill::parse_frames(parse)?)),
+            _ => Err(format!("ERR unknown subcommand '{}'. Try CLIENT HELP.", subcommand).into()),
+        }
+    }
+
+    /// client 为执行命令的连接
+    pub fn apply(self, db: &Db, client: &ClientHandle<'_>) -> Frame {
+        match self {
+            Client::List => Frame::Bulk(Bytes::from(db.clients().list())),
+            Client::Id => Frame::Integer(client.id() as i64),
+            Client::SetName(name) => {
+                client.set_name(name);
+                Frame::Simple("OK".into())
+            },
+            Client::GetName => client.name().map_or(Frame::Null, Frame::Bulk),
+            Client::Kill(kill) => {
+                let killed = db.clients().kill(|target| kill.matches(target, client.id()));
+                match (kill.legacy, killed) {
+                    (false, killed) => Frame::Integer(killed as i64),
+                    (true, 0) => Frame::Error("ERR No such client".into()),
+                    (true, _) => Frame::Simple("OK".into()),
+                }
+            },
+        }
+    }
+}
+
+impl Kill {
+    fn parse_frames(parse: &mut Parse) -> Result<Kill, ParseError> {
+        let first = parse.next_string()?;
+        if !parse.has_remaining() {
+            return Ok(Kill { legacy: true, addr: Some(first), ..Kill::default() });
+        }
+        let mut kill = Kill { skip_me: true, ..Kill::default() };
+        let mut filter = first;
+        loop {
+            match filter.to_uppercase().as_str() {
+                "ID" => match parse.next_int()? {
+                    id if id > 0 => kill.id = Some(id as u64),
+                    _ => return Err("ERR client-id should be greater than 0".into()),
+                },
+                "ADDR" => kill.addr = Some(parse.next_string()?),
+                "SKIPME" => kill.skip_me = match parse.next_string()?.to_lowercase().as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err("ERR syntax error".into()),
+                },
+                _ => return Err("ERR syntax error".into()),
+            }
+            if !parse.has_remaining() {
+                return Ok(kill);
+            }
+            filter = parse.next_string()?;
+        }
+    }
+
+    fn matches(&self, client: &ClientInfo, me: u64) -> bool {
+        !(self.skip_me && client.id() == me)
+            && self.id.is_none_or(|id| id == client.id())
+            && self.addr.as_ref().is_none_or(|addr| *addr == client.addr().to_string())
+    }
+}
@@ -0,0 +1,115 @@
+use bytes::Bytes;
+
+use crate::{cluster::{self, SLOTS}, db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `CLUSTER <subcommand>`，查看集群的槽分配，见 [`crate::cluster`]
+#[derive(Debug)]
+pub enum Cluster {
+    /// `CLUSTER INFO`
+    Info,
+    /// `CLUSTER MYID`，本节点的 ID
+    MyId,
+    /// `CLUSTER KEYSLOT key`，key 所在的槽
+    KeySlot(Bytes),
+    /// `CLUSTER SLOTS`，每个连续的槽区间及负责它的节点
+    Slots,
+    /// `CLUSTER SHARDS`，每个节点负责的槽
+    Shards,
+}
+
+impl Cluster {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Cluster, ParseError> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_lowercase().as_str() {
+            "info" => Ok(Cluster::Info),
+            "myid" => Ok(Cluster::MyId),
+            "keyslot" => Ok(Cluster::KeySlot(parse.next_bytes()?)),
+            "slots" => Ok(Cluster::Slots),
+            "shards" => Ok(Cluster::Shards),
+            _ => Err(format!("ERR unknown subcommand '{}'. Try CLUSTER HELP.", subcommand).into()),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let Some(cluster) = db.cluster() else {
+            return Frame::Error("ERR This instance has cluster support disabled".into());
+        };
+        let map = cluster.slots();
+        let bulk = |s: String| Frame::Bulk(Bytes::from(s));
+        match self {
+            Cluster::Info => {
+                let assigned = map.assigned();
+                let state = if assigned == SLOTS as usize { "ok" } else { "fail" };
+                let size = map.nodes().iter().filter(|node| !node.slots.is_empty()).count();
+                bulk(format!(
+                    "cluster_enabled:1\r\ncluster_state:{}\r\ncluster_slots_assigned:{}\r\ncluster_known_nodes:{}\r\ncluster_size:{}\r\n",
+                    state, assigned, map.nodes().len(), size,
+                ))
+            },
+            Cluster::MyId => bulk(cluster::node_id(cluster.myself())),
+            Cluster::KeySlot(key) => Frame::Integer(cluster::key_slot(&key) as i64),
+            Cluster::Slots => {
+                let mut ranges: Vec<_> = map.nodes().iter()
+                    .flat_map(|node| node.slots.iter().map(move |&range| (range, node)))
+                    .collect();
+                ranges.sort_by_key(|&((start, _), _)| start);
+                Frame::Array(ranges.into_iter().map(|((start, end), node)| {
+                    let (ip, port) = node.ip_port();
+                    Frame::Array(vec![
+                        Frame::Integer(start as i64),
+                        Frame::Integer(end as i64),
+                        Frame::Array(vec![bulk(ip.to_string()), Frame::Integer(port as i64), bulk(node.id())]),
+                    ])
+                }).collect())
+            },
+            Cluster::Shards => Frame::Array(map.nodes().iter().map(|node| {
+                let (ip, port) = node.ip_port();
+                let field = |name: &str| bulk(name.to_string());
+                let slots = node.slots.iter().flat_map(|&(start, end)| [Frame::Integer(start as i64), Frame::Integer(end as i64)]);
+                Frame::Map(vec![
+                    (field("slots"), Frame::Array(slots.collect())),
+                    (field("nodes"), Frame::Array(vec![Frame::Map(vec![
+                        (field("id"), bulk(node.id())),
+                        (field("port"), Frame::Integer(port as i64)),
+                        (field("ip"), field(ip)),
+                        (field("endpoint"), field(ip)),
+                        (field("role"), field("master")),
+                        (field("replication-offset"), Frame::Integer(0)),
+                        (field("health"), field("online")),
+                    ])])),
+                ])
+            }).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{cluster::SlotMap, config::Config, db::Db, frame::Frame};
+
+    use super::Cluster;
+
+    #[test]
+    fn subcommands() {
+        assert!(matches!(Cluster::Slots.apply(&Db::new()), Frame::Error(_)));
+
+        let cluster_nodes = SlotMap::parse("127.0.0.1:6379 0-100,200 127.0.0.1:6380 101-199").unwrap();
+        let db = Db::with_config(Config { cluster_enabled: true, cluster_nodes, ..Config::default() });
+        assert_eq!(Cluster::KeySlot(Bytes::from("foo")).apply(&db), Frame::Integer(12182));
+        let Frame::Bulk(info) = Cluster::Info.apply(&db) else { panic!() };
+        assert!(info.starts_with(b"cluster_enabled:1\r\ncluster_state:fail\r\ncluster_slots_assigned:201\r\n"));
+
+        let Frame::Array(slots) = Cluster::Slots.apply(&db) else { panic!() };
+        let starts: Vec<_> = slots.iter().map(|slot| match slot {
+            Frame::Array(fields) => fields[0].clone(),
+            _ => panic!(),
+        }).collect();
+        assert_eq!(starts, [Frame::Integer(0), Frame::Integer(101), Frame::Integer(200)]);
+        let Frame::Array(shards) = Cluster::Shards.apply(&db) else { panic!() };
+        assert_eq!(shards.len(), 2);
+    }
+}
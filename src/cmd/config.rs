@@ -0,0 +1,100 @@
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `CONFIG <subcommand>`，查看、修改服务端配置，配置项见 [`crate::config`]
+#[derive(Debug)]
+pub enum Config {
+    /// `CONFIG GET pattern [pattern ...]`，返回名称匹配任一 glob 模式的配置项及其值
+    Get(Vec<Bytes>),
+    /// `CONFIG SET name value [name value ...]`，任一配置项修改失败时不修改其他配置项
+    Set(Vec<(String, String)>),
+}
+
+impl Config {
+    pub fn get(pattern: impl Into<Bytes>) -> Config {
+        Config::Get(vec![pattern.into()])
+    }
+
+    pub fn set(name: impl Into<String>, value: impl Into<String>) -> Config {
+        Config::Set(vec![(name.into(), value.into())])
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Config, ParseError> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_lowercase().as_str() {
+            "get" => {
+                let mut patterns = vec![parse.next_bytes()?];
+                while parse.has_remaining() {
+                    patterns.push(parse.next_bytes()?);
+                }
+                Ok(Config::Get(patterns))
+            },
+            "set" => {
+                let mut params = vec![(parse.next_string()?, parse.next_string()?)];
+                while parse.has_remaining() {
+                    params.push((parse.next_string()?, parse.next_string()?));
+                }
+                Ok(Config::Set(params))
+            },
+            _ => Err(format!("ERR unknown subcommand '{}'. Try CONFIG HELP.", subcommand).into()),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self {
+            Config::Get(patterns) => {
+                let config = db.config();
+                let mut params: Vec<(&str, String)> = vec![];
+                for pattern in patterns {
+                    // 配置项的名称不区分大小写
+                    for (name, value) in config.get(&pattern.to_ascii_lowercase()) {
+                        if params.iter().all(|(found, _)| *found != name) {
+                            params.push((name, value));
+                        }
+                    }
+                }
+                Frame::Map(params
+                    .into_iter()
+                    .map(|(name, value)| (Frame::Bulk(Bytes::from(name)), Frame::Bulk(Bytes::from(value))))
+                    .collect())
+            },
+            Config::Set(params) => db.update_config(|config| {
+                // 先在副本上修改，所有配置项都合法时才替换
+                let mut updated = config.clone();
+                for (name, value) in &params {
+                    if let Err(err) = updated.set_mutable(name, value) {
+                        return Frame::Error(format!("ERR CONFIG SET failed (possibly related to argument '{}') - {}", name, err));
+                    }
+                }
+                // requirepass 就是 default 用户的密码
+                if updated.requirepass != config.requirepass {
+                    db.update_acl(|acl| acl.set_default_password(&updated.requirepass));
+                }
+                *config = updated;
+                Frame::Simple("OK".into())
+            }),
+        }
+    }
+
+    /// 转换成发送给服务端的 frame，供客户端使用
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::from("config")];
+        match self {
+            Config::Get(patterns) => {
+                frames.push("get".into());
+                frames.extend(patterns.into_iter().map(Frame::from));
+            },
+            Config::Set(params) => {
+                frames.push("set".into());
+                for (name, value) in params {
+                    frames.push(name.into());
+                    frames.push(value.into());
+                }
+            },
+        }
+        Frame::Array(frames)
+    }
+}
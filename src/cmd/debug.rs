@@ -0,0 +1,102 @@
+//! `DEBUG` 命令，供测试与排查问题使用
+
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::{db::{Db, now_ms}, frame::Frame, glob, rdb};
+
+use super::{Parse, ParseError, object::SHARED_REFCOUNT};
+
+/// redis 的 LRU 时钟只有 24 位，`DEBUG OBJECT` 中的 lru 字段按同样的位数回绕
+const LRU_CLOCK_MAX: u64 = (1 << 24) - 1;
+
+/// `DEBUG STRINGMATCH-LEN` 随机生成的模式与字符串的个数
+const STRINGMATCH_ROUNDS: usize = 10_000;
+
+/// `DEBUG STRINGMATCH-LEN` 随机生成的模式与字符串的最大长度
+const STRINGMATCH_MAX_LEN: usize = 32;
+
+/// `DEBUG <subcommand>`
+#[derive(Debug)]
+pub enum Debug {
+    /// `DEBUG OBJECT key`，对象的内部信息，包括在快照中占用的字节数
+    Object(Bytes),
+    /// `DEBUG SLEEP seconds`，等待一段时间后回复，秒数可以是小数。
+    /// 由连接的处理循环异步等待，不持有任何锁，不影响其他连接
+    Sleep(Duration),
+    /// `DEBUG SET-ACTIVE-EXPIRE 0|1`，暂停或恢复主动过期，暂停期间过期的 key 只会被惰性删除
+    SetActiveExpire(bool),
+    /// `DEBUG STRINGMATCH-LEN`，用随机的模式与字符串测试 glob 匹配，确认不会崩溃或者卡住
+    StringMatchLen,
+    /// `DEBUG RELOAD`，在内存中把整个键空间编码为快照再加载回来，检查快照编解码是否一致，见 [`Db::reload`]
+    Reload,
+}
+
+impl Debug {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Debug, ParseError> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_lowercase().as_str() {
+            "object" => Ok(Debug::Object(parse.next_bytes()?)),
+            "sleep" => match parse.next_float()? {
+                secs if secs.is_finite() && secs >= 0.0 => Ok(Debug::Sleep(Duration::from_secs_f64(secs))),
+                _ => Err("ERR value is not a valid float".into()),
+            },
+            "set-active-expire" => Ok(Debug::SetActiveExpire(parse.next_int()? != 0)),
+            "stringmatch-len" => Ok(Debug::StringMatchLen),
+            "reload" => Ok(Debug::Reload),
+            _ => Err(format!("ERR unknown subcommand '{}'. Try DEBUG HELP.", subcommand).into()),
+        }
+    }
+
+    /// `DEBUG SLEEP` 需要异步等待，由连接的处理循环执行，在这里（事务、脚本中）执行时回复错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self {
+            Debug::Object(key) => {
+                let now = now_ms();
+                db.peek(&key, |found| {
+                    let Some((value, access)) = found else {
+                        return Frame::Error("ERR no such key".into());
+                    };
+                    let idle = access.idle(now);
+                    Frame::Simple(format!(
+                        "Value at:{:p} refcount:{} encoding:{} serializedlength:{} lru:{} lru_seconds_idle:{}",
+                        value,
+                        if value.is_shared() { SHARED_REFCOUNT } else { 1 },
+                        value.encoding().as_str(),
+                        rdb::serialized_len(value),
+                        ((now - idle) / 1000) & LRU_CLOCK_MAX,
+                        idle / 1000,
+                    ))
+                })
+            },
+            Debug::Sleep(_) => Frame::Error("ERR 'debug sleep' is unsupported in this context".into()),
+            Debug::SetActiveExpire(enabled) => {
+                db.set_active_expire(enabled);
+                Frame::Simple("OK".into())
+            },
+            Debug::StringMatchLen => {
+                for _ in 0..STRINGMATCH_ROUNDS {
+                    glob::matches(&random_bytes(), &random_bytes());
+                }
+                Frame::Simple("Apparently Redis did not crash: test passed".into())
+            },
+            Debug::Reload => match db.reload() {
+                Ok(_) => Frame::Simple("OK".into()),
+                Err(err) => Frame::Error(format!("ERR Error trying to load the RDB dump: {}", err)),
+            },
+        }
+    }
+}
+
+/// 随机长度的随机字节，一半的字节取自 glob 的特殊字符，更容易构造出复杂的模式
+fn random_bytes() -> Vec<u8> {
+    const SPECIAL: &[u8] = b"*?[]^-\\a";
+    let len = rand::random::<usize>() % (STRINGMATCH_MAX_LEN + 1);
+    (0..len)
+        .map(|_| match rand::random::<bool>() {
+            true => SPECIAL[rand::random::<usize>() % SPECIAL.len()],
+            false => rand::random(),
+        })
+        .collect()
+}
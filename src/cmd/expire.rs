@@ -0,0 +1,87 @@
+use bytes::Bytes;
+
+use crate::{db::{Db, now_ms}, expires::ExpireFlags, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `EXPIRE key seconds [NX|XX|GT|LT]` / `PEXPIRE key milliseconds [NX|XX|GT|LT]` /
+/// `EXPIREAT key unix-time-seconds [NX|XX|GT|LT]` / `PEXPIREAT key unix-time-milliseconds [NX|XX|GT|LT]`
+///
+/// 设置 key 的存活时间或过期的时间点。设置了返回 1，key 不存在或者不满足条件时返回 0。
+/// 过期时间已经过去时 key 会被直接删除
+#[derive(Debug)]
+pub struct Expire {
+    key: Bytes,
+    /// 存活时间，或者 EXPIREAT/PEXPIREAT 的 unix 时间戳，单位由 `unit_ms` 决定
+    ttl: i64,
+    /// 每个时间单位对应的毫秒数，EXPIRE 为 1000，PEXPIRE 为 1
+    unit_ms: i64,
+    /// 是否为 EXPIREAT/PEXPIREAT
+    absolute: bool,
+    flags: ExpireFlags,
+}
+
+impl Expire {
+    /// `EXPIRE key seconds`
+    pub fn seconds(key: impl Into<Bytes>, seconds: i64) -> Expire {
+        Expire { key: key.into(), ttl: seconds, unit_ms: 1000, absolute: false, flags: ExpireFlags::default() }
+    }
+
+    /// `PEXPIRE key milliseconds`
+    pub fn milliseconds(key: impl Into<Bytes>, milliseconds: i64) -> Expire {
+        Expire { key: key.into(), ttl: milliseconds, unit_ms: 1, absolute: false, flags: ExpireFlags::default() }
+    }
+
+    /// `PEXPIREAT key unix-time-milliseconds`
+    pub fn at(key: impl Into<Bytes>, unix_time_ms: i64) -> Expire {
+        Expire { key: key.into(), ttl: unix_time_ms, unit_ms: 1, absolute: true, flags: ExpireFlags::default() }
+    }
+
+    /// 只在原来的过期时间满足 flags 时修改
+    pub fn flags(mut self, flags: ExpireFlags) -> Expire {
+        self.flags = flags;
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, unit_ms: i64, absolute: bool) -> Result<Expire, ParseError> {
+        let key = parse.next_bytes()?;
+        let ttl = parse.next_int()?;
+        let mut cmd = Expire { key, ttl, unit_ms, absolute, flags: ExpireFlags::default() };
+        while parse.has_remaining() {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "NX" => cmd.flags.nx = true,
+                "XX" => cmd.flags.xx = true,
+                "GT" => cmd.flags.gt = true,
+                "LT" => cmd.flags.lt = true,
+                option => return Err(format!("ERR Unsupported option {}", option).into()),
+            }
+        }
+        let ExpireFlags { nx, xx, gt, lt } = cmd.flags;
+        if nx && (xx || gt || lt) {
+            return Err("ERR NX and XX, GT or LT options at the same time are not compatible".into());
+        }
+        if gt && lt {
+            return Err("ERR GT and LT options at the same time are not compatible".into());
+        }
+        if ttl.checked_mul(unit_ms).is_none() {
+            return Err(format!("ERR invalid expire time in '{}' command", cmd.name()).into());
+        }
+        Ok(cmd)
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match (self.unit_ms == 1, self.absolute) {
+            (false, false) => "expire",
+            (true, false) => "pexpire",
+            (false, true) => "expireat",
+            (true, true) => "pexpireat",
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let base = if self.absolute { 0 } else { now_ms() as i64 };
+        // 已经过去的时间点会让 key 被直接删除
+        let when = base.saturating_add(self.ttl * self.unit_ms).max(0) as u64;
+        Frame::Integer(db.expire_at_if(&self.key, when, self.flags) as i64)
+    }
+}
@@ -0,0 +1,453 @@
+//! GEO 命令。位置按 geohash 编码为分数保存在有序集合中，见 [`crate::geohash`]，
+//! 所以 GEOADD 添加的 key 就是普通的有序集合，也可以用 ZRANGE、ZREM 等命令操作
+
+use bytes::Bytes;
+
+use crate::{db::Db, ds::skiplist::Bound, frame::Frame, geohash::{self, Shape}, types::{AddFlags, ZSet}};
+
+use super::{Parse, ParseError, ZAdd, incr::parse_float};
+
+/// 长度单位，值为对应的米数
+fn parse_unit(parse: &mut Parse) -> Result<f64, ParseError> {
+    match parse.next_string()?.to_lowercase().as_str() {
+        "m" => Ok(1.0),
+        "km" => Ok(1000.0),
+        "ft" => Ok(0.3048),
+        "mi" => Ok(1609.34),
+        _ => Err("ERR unsupported unit provided. please use M, KM, FT, MI".into()),
+    }
+}
+
+/// 读取一对经纬度，超出可以编码的范围时返回错误
+fn parse_coordinates(parse: &mut Parse) -> Result<(f64, f64), ParseError> {
+    validate(parse.next_float()?, parse.next_float()?)
+}
+
+fn validate(longitude: f64, latitude: f64) -> Result<(f64, f64), ParseError> {
+    if !geohash::is_valid(longitude, latitude) {
+        return Err(format!("ERR invalid longitude,latitude pair {:.6},{:.6}", longitude, latitude).into());
+    }
+    Ok((longitude, latitude))
+}
+
+fn format_coordinate(value: f64) -> Frame {
+    Frame::Bulk(Bytes::from(value.to_string()))
+}
+
+/// 距离保留 4 位小数
+fn format_distance(meters: f64, unit: f64) -> Frame {
+    Frame::Bulk(Bytes::from(format!("{:.4}", meters / unit)))
+}
+
+/// `GEOADD key [NX|XX] [CH] longitude latitude member [longitude latitude member ...]`
+///
+/// 添加位置，已存在的 member 更新位置。选项的含义与 ZADD 相同，返回新增（CH 时还包括位置有变化）的 member 数量
+#[derive(Debug)]
+pub struct GeoAdd {
+    key: Bytes,
+    /// (经度, 纬度, member)
+    members: Vec<(f64, f64, Bytes)>,
+    flags: AddFlags,
+    ch: bool,
+}
+
+impl GeoAdd {
+    pub fn new(key: impl Into<Bytes>, members: Vec<(f64, f64, Bytes)>) -> GeoAdd {
+        GeoAdd { key: key.into(), members, flags: AddFlags::default(), ch: false }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoAdd, ParseError> {
+        let key = parse.next_bytes()?;
+        let mut args = vec![];
+        while parse.has_remaining() {
+            args.push(parse.next_bytes()?);
+        }
+        let mut flags = AddFlags::default();
+        let mut ch = false;
+        // 选项都在第一个经度之前
+        let options = args.iter().take_while(|arg| matches!(arg.to_ascii_uppercase().as_slice(), b"NX" | b"XX" | b"CH")).count();
+        for option in &args[..options] {
+            match option.to_ascii_uppercase().as_slice() {
+                b"NX" => flags.nx = true,
+                b"XX" => flags.xx = true,
+                _ => ch = true,
+            }
+        }
+        if flags.nx && flags.xx {
+            return Err("ERR XX and NX options at the same time are not compatible".into());
+        }
+        let args = &args[options..];
+        if args.is_empty() || args.len() % 3 != 0 {
+            return Err("ERR syntax error. Try GEOADD key [x1] [y1] [name1] [x2] [y2] [name2] ... ".into());
+        }
+        let mut members = vec![];
+        for triple in args.chunks(3) {
+            let [longitude, latitude] = [&triple[0], &triple[1]].map(|value| parse_float(value));
+            let (Some(longitude), Some(latitude)) = (longitude, latitude) else {
+                return Err("ERR value is not a valid float".into());
+            };
+            let (longitude, latitude) = validate(longitude, latitude)?;
+            members.push((longitude, latitude, triple[2].clone()));
+        }
+        Ok(GeoAdd { key, members, flags, ch })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let members = self.members
+            .into_iter()
+            .map(|(longitude, latitude, member)| (geohash::encode_score(longitude, latitude), member))
+            .collect();
+        let zadd = ZAdd::new(self.key, members).flags(self.flags);
+        match self.ch {
+            true => zadd.ch().apply(db),
+            false => zadd.apply(db),
+        }
+    }
+}
+
+/// `GEOPOS key member [member ...]`，返回各 member 的 [经度, 纬度]，member 不存在时为 nil。
+///
+/// 返回的是 geohash 格子的中心，与添加时的经纬度可能有细微的差别
+#[derive(Debug)]
+pub struct GeoPos {
+    key: Bytes,
+    members: Vec<Bytes>,
+}
+
+impl GeoPos {
+    pub fn new(key: impl Into<Bytes>, members: Vec<Bytes>) -> GeoPos {
+        GeoPos { key: key.into(), members }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoPos, ParseError> {
+        let key = parse.next_bytes()?;
+        let mut members = vec![];
+        while parse.has_remaining() {
+            members.push(parse.next_bytes()?);
+        }
+        Ok(GeoPos { key, members })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.with_typed(&self.key, |zset: Option<&mut ZSet>| {
+            let mut zset = zset;
+            let positions = self.members.iter().map(|member| {
+                match zset.as_deref_mut().and_then(|zset| zset.score(member)) {
+                    Some(score) => {
+                        let (longitude, latitude) = geohash::decode_score(score);
+                        Frame::Array(vec![format_coordinate(longitude), format_coordinate(latitude)])
+                    },
+                    None => Frame::Null,
+                }
+            });
+            Frame::Array(positions.collect())
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `GEODIST key member1 member2 [M|KM|FT|MI]`，两个 member 之间的距离，默认以米为单位。
+/// 任意一个 member 不存在时返回 nil
+#[derive(Debug)]
+pub struct GeoDist {
+    key: Bytes,
+    members: (Bytes, Bytes),
+    /// 单位对应的米数
+    unit: f64,
+}
+
+impl GeoDist {
+    pub fn new(key: impl Into<Bytes>, member1: impl Into<Bytes>, member2: impl Into<Bytes>) -> GeoDist {
+        GeoDist { key: key.into(), members: (member1.into(), member2.into()), unit: 1.0 }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoDist, ParseError> {
+        let key = parse.next_bytes()?;
+        let members = (parse.next_bytes()?, parse.next_bytes()?);
+        let unit = match parse.has_remaining() {
+            true => parse_unit(parse)?,
+            false => 1.0,
+        };
+        Ok(GeoDist { key, members, unit })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.with_typed(&self.key, |zset: Option<&mut ZSet>| {
+            let Some(zset) = zset else {
+                return Frame::Null;
+            };
+            let (Some(a), Some(b)) = (zset.score(&self.members.0), zset.score(&self.members.1)) else {
+                return Frame::Null;
+            };
+            let ((long1, lat1), (long2, lat2)) = (geohash::decode_score(a), geohash::decode_score(b));
+            format_distance(geohash::distance(long1, lat1, long2, lat2), self.unit)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// 查找的中心
+#[derive(Debug)]
+enum Origin {
+    Member(Bytes),
+    Coordinates(f64, f64),
+}
+
+/// `GEOSEARCH key FROMMEMBER member|FROMLONLAT longitude latitude BYRADIUS radius unit|BYBOX width height unit
+/// [ASC|DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]`
+///
+/// 查找范围内的 member。默认不排序；指定 COUNT 而没有 ANY 时按距离从近到远排序后取前 count 个，
+/// 有 ANY 时找到 count 个就停止查找，结果不一定是最近的。
+///
+/// 没有 WITH* 选项时只返回 member，否则每一项为 [member, 距离, geohash 分数, [经度, 纬度]] 中指定的部分
+#[derive(Debug)]
+pub struct GeoSearch {
+    key: Bytes,
+    origin: Origin,
+    /// 以米为单位
+    shape: Shape,
+    /// BYRADIUS、BYBOX 指定的单位对应的米数，返回的距离使用同样的单位
+    unit: f64,
+    /// `Some(true)` 为 DESC
+    desc: Option<bool>,
+    count: Option<usize>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+/// 查找到的 member
+struct Found {
+    member: Bytes,
+    distance: f64,
+    score: f64,
+    coordinates: (f64, f64),
+}
+
+impl GeoSearch {
+    pub fn from_member(key: impl Into<Bytes>, member: impl Into<Bytes>, shape: Shape) -> GeoSearch {
+        GeoSearch::new(key.into(), Origin::Member(member.into()), shape)
+    }
+
+    pub fn from_coordinates(key: impl Into<Bytes>, longitude: f64, latitude: f64, shape: Shape) -> GeoSearch {
+        GeoSearch::new(key.into(), Origin::Coordinates(longitude, latitude), shape)
+    }
+
+    fn new(key: Bytes, origin: Origin, shape: Shape) -> GeoSearch {
+        GeoSearch { key, origin, shape, unit: 1.0, desc: None, count: None, any: false, with_coord: false, with_dist: false, with_hash: false }
+    }
+
+    /// 按距离排序，desc 为真时从远到近
+    pub fn sort(mut self, desc: bool) -> GeoSearch {
+        self.desc = Some(desc);
+        self
+    }
+
+    pub fn count(mut self, count: usize, any: bool) -> GeoSearch {
+        self.count = Some(count);
+        self.any = any;
+        self
+    }
+
+    /// 同时返回距离、geohash 分数与经纬度
+    pub fn with_all(mut self) -> GeoSearch {
+        (self.with_coord, self.with_dist, self.with_hash) = (true, true, true);
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoSearch, ParseError> {
+        let key = parse.next_bytes()?;
+        let mut search = GeoSearch::new(key, Origin::Coordinates(0.0, 0.0), Shape::Radius(0.0));
+        let (mut origin, mut shape) = (0, 0);
+        while parse.has_remaining() {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "FROMMEMBER" => {
+                    search.origin = Origin::Member(parse.next_bytes()?);
+                    origin += 1;
+                },
+                "FROMLONLAT" => {
+                    let (longitude, latitude) = parse_coordinates(parse)?;
+                    search.origin = Origin::Coordinates(longitude, latitude);
+                    origin += 1;
+                },
+                "BYRADIUS" => {
+                    let radius = parse.next_float()?;
+                    search.unit = parse_unit(parse)?;
+                    if radius < 0.0 {
+                        return Err("ERR radius cannot be negative".into());
+                    }
+                    search.shape = Shape::Radius(radius * search.unit);
+                    shape += 1;
+                },
+                "BYBOX" => {
+                    let (width, height) = (parse.next_float()?, parse.next_float()?);
+                    search.unit = parse_unit(parse)?;
+                    if width < 0.0 || height < 0.0 {
+                        return Err("ERR height or width cannot be negative".into());
+                    }
+                    search.shape = Shape::Box { width: width * search.unit, height: height * search.unit };
+                    shape += 1;
+                },
+                "ASC" => search.desc = Some(false),
+                "DESC" => search.desc = Some(true),
+                "COUNT" => {
+                    let count = parse.next_int()?;
+                    if count <= 0 {
+                        return Err("ERR COUNT must be > 0".into());
+                    }
+                    search.count = Some(count as usize);
+                },
+                "ANY" => search.any = true,
+                "WITHCOORD" => search.with_coord = true,
+                "WITHDIST" => search.with_dist = true,
+                "WITHHASH" => search.with_hash = true,
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        if origin != 1 {
+            return Err("ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH".into());
+        }
+        if shape != 1 {
+            return Err("ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH".into());
+        }
+        if search.any && search.count.is_none() {
+            return Err("ERR the ANY argument requires COUNT argument".into());
+        }
+        Ok(search)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let found = db.with_typed(&self.key, |zset: Option<&mut ZSet>| match zset {
+            Some(zset) => self.search(zset),
+            None => Ok(vec![]),
+        });
+        let mut found = match found {
+            Ok(Ok(found)) => found,
+            Ok(Err(err)) => return Frame::Error(err.into()),
+            Err(err) => return err.into(),
+        };
+        // COUNT 而没有 ANY 时需要取最近的 count 个
+        let desc = match (self.desc, self.count) {
+            (None, Some(_)) if !self.any => Some(false),
+            (desc, _) => desc,
+        };
+        if let Some(desc) = desc {
+            found.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+            if desc {
+                found.reverse();
+            }
+        }
+        if let Some(count) = self.count {
+            found.truncate(count);
+        }
+        let items = found.into_iter().map(|found| {
+            if !(self.with_dist || self.with_hash || self.with_coord) {
+                return Frame::Bulk(found.member);
+            }
+            let mut item = vec![Frame::Bulk(found.member)];
+            if self.with_dist {
+                item.push(format_distance(found.distance, self.unit));
+            }
+            if self.with_hash {
+                item.push(Frame::Integer(found.score as i64));
+            }
+            if self.with_coord {
+                let (longitude, latitude) = found.coordinates;
+                item.push(Frame::Array(vec![format_coordinate(longitude), format_coordinate(latitude)]));
+            }
+            Frame::Array(item)
+        });
+        Frame::Array(items.collect())
+    }
+
+    /// 逐个检查中心及其周围的格子，按分数范围取出 member 后按实际距离过滤
+    fn search(&self, zset: &mut ZSet) -> Result<Vec<Found>, &'static str> {
+        let center = match &self.origin {
+            Origin::Coordinates(longitude, latitude) => (*longitude, *latitude),
+            Origin::Member(member) => geohash::decode_score(zset.score(member).ok_or("ERR could not decode requested zset member")?),
+        };
+        let limit = match self.any {
+            true => self.count.unwrap_or(usize::MAX),
+            false => usize::MAX,
+        };
+        let mut found = vec![];
+        for area in geohash::search_areas(center.0, center.1, self.shape) {
+            let (min, max) = area.score_range();
+            for (member, score) in zset.range_by_score(Some(Bound::new_inclusive(min)), Some(Bound::new_exclusive(max)), 0, 0) {
+                let coordinates = geohash::decode_score(score);
+                if let Some(distance) = self.shape.contains(center, coordinates.0, coordinates.1) {
+                    found.push(Found { member, distance, score, coordinates });
+                    if found.len() >= limit {
+                        return Ok(found);
+                    }
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{cmd::{ZAdd, ZScore}, db::Db, frame::Frame, geohash::Shape};
+
+    use super::{GeoAdd, GeoDist, GeoPos, GeoSearch};
+
+    fn sicily(db: &Db) {
+        let members = vec![(13.361389, 38.115556, Bytes::from("Palermo")), (15.087269, 37.502669, Bytes::from("Catania"))];
+        assert_eq!(GeoAdd::new("sicily", members).apply(db), Frame::Integer(2));
+    }
+
+    fn bulks(values: &[&'static str]) -> Frame {
+        Frame::Array(values.iter().map(|value| Frame::Bulk(Bytes::from(*value))).collect())
+    }
+
+    #[test]
+    fn add_pos_and_dist() {
+        let db = Db::new();
+        sicily(&db);
+        // 与 redis 保存的分数一致
+        assert_eq!(ZScore::new("sicily", "Palermo").apply(&db), Frame::Bulk(Bytes::from("3479099956230698")));
+        let Frame::Array(positions) = GeoPos::new("sicily", vec![Bytes::from("Palermo"), Bytes::from("nowhere")]).apply(&db) else { panic!() };
+        let Frame::Array(palermo) = &positions[0] else { panic!() };
+        let longitude: f64 = palermo[0].as_str().unwrap().parse().unwrap();
+        assert!((longitude - 13.361389).abs() < 1e-5);
+        assert_eq!(positions[1], Frame::Null);
+
+        assert_eq!(GeoDist::new("sicily", "Palermo", "Catania").apply(&db), Frame::Bulk(Bytes::from("166274.1516")));
+        let mut km = GeoDist::new("sicily", "Palermo", "Catania");
+        km.unit = 1000.0;
+        assert_eq!(km.apply(&db), Frame::Bulk(Bytes::from("166.2742")));
+        assert_eq!(GeoDist::new("sicily", "Palermo", "nowhere").apply(&db), Frame::Null);
+        assert_eq!(GeoDist::new("missing", "Palermo", "Catania").apply(&db), Frame::Null);
+
+        ZAdd::new("zset", vec![(1.0, Bytes::from("a"))]).apply(&db);
+        assert_eq!(GeoDist::new("zset", "a", "a").apply(&db), Frame::Bulk(Bytes::from("0.0000")));
+    }
+
+    #[test]
+    fn search() {
+        let db = Db::new();
+        sicily(&db);
+        let near = |radius: f64| GeoSearch::from_coordinates("sicily", 15.0, 37.0, Shape::Radius(radius * 1000.0)).sort(false);
+        assert_eq!(near(200.0).apply(&db), bulks(&["Catania", "Palermo"]));
+        assert_eq!(near(200.0).sort(true).apply(&db), bulks(&["Palermo", "Catania"]));
+        assert_eq!(near(100.0).apply(&db), bulks(&["Catania"]));
+        assert_eq!(near(10.0).apply(&db), bulks(&[]));
+        // COUNT 没有 ANY 时取最近的
+        let nearest = GeoSearch::from_coordinates("sicily", 15.0, 37.0, Shape::Radius(200_000.0)).count(1, false);
+        assert_eq!(nearest.apply(&db), bulks(&["Catania"]));
+
+        let square = Shape::Box { width: 400_000.0, height: 400_000.0 };
+        assert_eq!(GeoSearch::from_member("sicily", "Catania", square).sort(false).apply(&db), bulks(&["Catania", "Palermo"]));
+        let Frame::Array(items) = GeoSearch::from_member("sicily", "Palermo", Shape::Radius(1.0)).with_all().apply(&db) else { panic!() };
+        let Frame::Array(item) = &items[0] else { panic!() };
+        assert_eq!(item[..3], [Frame::Bulk(Bytes::from("Palermo")), Frame::Bulk(Bytes::from("0.0000")), Frame::Integer(3479099956230698)]);
+
+        assert!(matches!(GeoSearch::from_member("sicily", "nowhere", square).apply(&db), Frame::Error(err) if err.contains("could not decode")));
+        assert_eq!(GeoSearch::from_member("missing", "nowhere", square).apply(&db), bulks(&[]));
+    }
+}
@@ -0,0 +1,138 @@
+use bytes::Bytes;
+
+use crate::{connection::Reply, db::{Db, WrongType, now_ms}, ds::perfstr::SmartString, frame::Frame, object::{RedisObject, int_to_bytes}};
+
+use super::{Parse, ParseError, set::Expiration};
+
+/// `GET key`，获取 key 对应的字符串值，key 不存在时返回 Null
+#[derive(Debug)]
+pub struct Get {
+    key: Bytes,
+}
+
+impl Get {
+    pub fn new(key: impl Into<Bytes>) -> Get {
+        Get { key: key.into() }
+    }
+
+    pub fn key(&self) -> &Bytes {
+        &self.key
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Get, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(Get { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.get(&self.key) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+
+    /// 同 [`Get::apply`]，但直接把值从键空间复制到输出缓冲区，不构造 `Bytes` 与 `Frame`
+    pub(crate) fn reply(self, db: &Db, reply: Reply) {
+        db.lookup_read(&self.key, |value| match value {
+            Some(RedisObject::String(sds)) => reply.bulk(sds.val()),
+            Some(RedisObject::Int(n)) => reply.bulk(&int_to_bytes(*n)),
+            Some(_) => reply.error(&WrongType.to_string()),
+            None => reply.null(),
+        })
+    }
+
+    /// 转换成发送给服务端的 frame，供客户端使用
+    pub(crate) fn into_frame(self) -> Frame {
+        Frame::array([Bytes::from("get"), self.key])
+    }
+}
+
+/// `GETDEL key`，获取 key 对应的字符串值并删除 key，key 不存在时返回 Null
+#[derive(Debug)]
+pub struct GetDel {
+    key: Bytes,
+}
+
+impl GetDel {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetDel, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(GetDel { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.getdel(&self.key) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+}
+
+/// `GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | PERSIST]`
+///
+/// 获取 key 对应的字符串值，同时设置或移除 key 的过期时间。不带选项时与 GET 相同
+#[derive(Debug)]
+pub struct GetEx {
+    key: Bytes,
+    /// `None` 为不修改过期时间，`Some(None)` 为 `PERSIST`
+    expire: Option<Option<Expiration>>,
+}
+
+impl GetEx {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetEx, ParseError> {
+        let key = parse.next_bytes()?;
+        let mut expire = None;
+        while parse.has_remaining() {
+            let option = parse.next_string()?.to_uppercase();
+            // 选项只能出现一次
+            if expire.is_some() {
+                return Err("ERR syntax error".into());
+            }
+            expire = match Expiration::parse_option(&option, parse, "getex")? {
+                Some(expiration) => Some(Some(expiration)),
+                None if option == "PERSIST" => Some(None),
+                None => return Err("ERR syntax error".into()),
+            };
+        }
+        Ok(GetEx { key, expire })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let now = now_ms();
+        let expire_at = self.expire.map(|expire| expire.map(|expire| expire.to_unix_ms(now)));
+        match db.getex(&self.key, expire_at) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+}
+
+/// `MGET key [key ...]`，获取多个 key 的字符串值，不存在或者不是字符串的 key 对应 Null
+#[derive(Debug)]
+pub struct MGet {
+    keys: Vec<Bytes>,
+}
+
+impl MGet {
+    pub fn new(keys: Vec<Bytes>) -> MGet {
+        MGet { keys }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<MGet, ParseError> {
+        let mut keys = vec![parse.next_bytes()?];
+        while parse.has_remaining() {
+            keys.push(parse.next_bytes()?);
+        }
+        Ok(MGet { keys })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let values = db.mget(&self.keys)
+            .into_iter()
+            .map(|value| value.map_or(Frame::Null, Frame::Bulk))
+            .collect();
+        Frame::Array(values)
+    }
+}
@@ -0,0 +1,501 @@
+//! 哈希表相关命令，数据保存在 [`Hash`] 中
+
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame, glob, object::{ZipLimits, parse_int}, types::Hash};
+
+use super::{Parse, ParseError, incr::parse_float, keyspace::{ScanOptions, scan_reply}};
+
+/// `HSET key field value [field value ...]`
+///
+/// 设置 field 的值，返回新增的 field 数量
+#[derive(Debug)]
+pub struct HSet {
+    key: Bytes,
+    fields: Vec<(Bytes, Bytes)>,
+}
+
+impl HSet {
+    pub fn new(key: impl Into<Bytes>, fields: Vec<(Bytes, Bytes)>) -> HSet {
+        HSet { key: key.into(), fields }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HSet, ParseError> {
+        let key = parse.next_bytes()?;
+        // 至少需要一对 field value，不成对时按参数个数错误处理
+        let mut fields = Vec::with_capacity(parse.remaining() / 2);
+        fields.push((parse.next_bytes()?, parse.next_bytes()?));
+        while parse.has_remaining() {
+            fields.push((parse.next_bytes()?, parse.next_bytes()?));
+        }
+        Ok(HSet { key, fields })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let limits = db.encoding_limits().hash;
+        db.update_typed(&self.key, |value| {
+            let hash = value.get_or_insert_with(Hash::new);
+            let mut added = 0;
+            for (field, v) in self.fields {
+                if hash.insert(field, v, &limits) {
+                    added += 1;
+                }
+            }
+            Frame::Integer(added)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `HSETNX key field value`，field 不存在时才设置，返回是否设置了
+#[derive(Debug)]
+pub struct HSetNx {
+    key: Bytes,
+    field: Bytes,
+    value: Bytes,
+}
+
+impl HSetNx {
+    pub fn new(key: impl Into<Bytes>, field: impl Into<Bytes>, value: impl Into<Bytes>) -> HSetNx {
+        HSetNx { key: key.into(), field: field.into(), value: value.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HSetNx, ParseError> {
+        let key = parse.next_bytes()?;
+        let field = parse.next_bytes()?;
+        let value = parse.next_bytes()?;
+        Ok(HSetNx { key, field, value })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let limits = db.encoding_limits().hash;
+        db.update_typed(&self.key, |value| {
+            let hash = value.get_or_insert_with(Hash::new);
+            if hash.get(&self.field).is_some() {
+                return Frame::Integer(0);
+            }
+            hash.insert(self.field, self.value, &limits);
+            Frame::Integer(1)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `HINCRBY key field increment`
+///
+/// 将 field 保存的整数加上 increment，返回新的值。key、field 不存在时按 0 处理
+#[derive(Debug)]
+pub struct HIncrBy {
+    key: Bytes,
+    field: Bytes,
+    delta: i64,
+}
+
+impl HIncrBy {
+    pub fn new(key: impl Into<Bytes>, field: impl Into<Bytes>, increment: i64) -> HIncrBy {
+        HIncrBy { key: key.into(), field: field.into(), delta: increment }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HIncrBy, ParseError> {
+        let key = parse.next_bytes()?;
+        let field = parse.next_bytes()?;
+        let delta = parse.next_int()?;
+        Ok(HIncrBy { key, field, delta })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let limits = db.encoding_limits().hash;
+        update_field(db, &self.key, self.field, &limits, |old| {
+            let current = match old {
+                Some(old) => parse_int(old).ok_or("ERR hash value is not an integer")?,
+                None => 0,
+            };
+            let result = current.checked_add(self.delta).ok_or("ERR increment or decrement would overflow")?;
+            Ok(Bytes::from(result.to_string()))
+        })
+        .map_or_else(Frame::Error, |result| Frame::Integer(parse_int(&result).unwrap()))
+    }
+}
+
+/// `HINCRBYFLOAT key field increment`
+///
+/// 将 field 保存的数值加上浮点数 increment，以字符串形式返回新的值。key、field 不存在时按 0 处理
+#[derive(Debug)]
+pub struct HIncrByFloat {
+    key: Bytes,
+    field: Bytes,
+    delta: f64,
+}
+
+impl HIncrByFloat {
+    pub fn new(key: impl Into<Bytes>, field: impl Into<Bytes>, increment: f64) -> HIncrByFloat {
+        HIncrByFloat { key: key.into(), field: field.into(), delta: increment }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HIncrByFloat, ParseError> {
+        let key = parse.next_bytes()?;
+        let field = parse.next_bytes()?;
+        let delta = parse.next_float()?;
+        Ok(HIncrByFloat { key, field, delta })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let limits = db.encoding_limits().hash;
+        update_field(db, &self.key, self.field, &limits, |old| {
+            let current = match old {
+                Some(old) => parse_float(old).ok_or("ERR hash value is not a float")?,
+                None => 0f64,
+            };
+            let result = current + self.delta;
+            if !result.is_finite() {
+                return Err("ERR increment would produce NaN or Infinity");
+            }
+            Ok(Bytes::from(result.to_string()))
+        })
+        .map_or_else(Frame::Error, Frame::Bulk)
+    }
+}
+
+/// `HRANDFIELD key [count [WITHVALUES]]`
+///
+/// 不带 count 时随机返回一个 field，key 不存在时返回 nil；带 count 时返回数组，
+/// count 为正数时 field 互不相同，为负数时可能重复。WITHVALUES 时 field 与 value 交替出现
+#[derive(Debug)]
+pub struct HRandField {
+    key: Bytes,
+    count: Option<i64>,
+    with_values: bool,
+}
+
+impl HRandField {
+    pub fn new(key: impl Into<Bytes>, count: Option<i64>, with_values: bool) -> HRandField {
+        HRandField { key: key.into(), count, with_values }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HRandField, ParseError> {
+        let key = parse.next_bytes()?;
+        if !parse.has_remaining() {
+            return Ok(HRandField { key, count: None, with_values: false });
+        }
+        let count = parse.next_int()?;
+        // 与 redis 一样限制负数的范围，WITHVALUES 时回复的元素个数还要再翻倍
+        if count < -(i64::MAX / 2) {
+            return Err("ERR value is out of range".into());
+        }
+        let with_values = match parse.has_remaining() {
+            false => false,
+            true if parse.next_string()?.eq_ignore_ascii_case("withvalues") => true,
+            true => return Err("ERR syntax error".into()),
+        };
+        Ok(HRandField { key, count: Some(count), with_values })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_hash(db, &self.key, |hash| {
+            let Some(count) = self.count else {
+                let entry = hash.and_then(|hash| hash.random_entries(1).pop());
+                return entry.map_or(Frame::Null, |(field, _)| Frame::Bulk(field));
+            };
+            let entries = hash.map_or_else(Vec::new, |hash| hash.random_entries(count));
+            let mut frames = Vec::with_capacity(entries.len() * if self.with_values { 2 } else { 1 });
+            for (field, v) in entries {
+                frames.push(Frame::Bulk(field));
+                if self.with_values {
+                    frames.push(Frame::Bulk(v));
+                }
+            }
+            Frame::Array(frames)
+        })
+    }
+}
+
+/// `HGET key field`，返回 field 的值，不存在时返回 nil
+#[derive(Debug)]
+pub struct HGet {
+    key: Bytes,
+    field: Bytes,
+}
+
+impl HGet {
+    pub fn new(key: impl Into<Bytes>, field: impl Into<Bytes>) -> HGet {
+        HGet { key: key.into(), field: field.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HGet, ParseError> {
+        let key = parse.next_bytes()?;
+        let field = parse.next_bytes()?;
+        Ok(HGet { key, field })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_hash(db, &self.key, |hash| {
+            match hash.and_then(|hash| hash.get(&self.field)) {
+                Some(v) => Frame::Bulk(v),
+                None => Frame::Null,
+            }
+        })
+    }
+}
+
+/// `HDEL key field [field ...]`
+///
+/// 删除 field，返回实际删除的数量。哈希表为空时 key 也会被删除
+#[derive(Debug)]
+pub struct HDel {
+    key: Bytes,
+    fields: Vec<Bytes>,
+}
+
+impl HDel {
+    pub fn new(key: impl Into<Bytes>, fields: Vec<Bytes>) -> HDel {
+        HDel { key: key.into(), fields }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HDel, ParseError> {
+        let key = parse.next_bytes()?;
+        let mut fields = vec![parse.next_bytes()?];
+        while parse.has_remaining() {
+            fields.push(parse.next_bytes()?);
+        }
+        Ok(HDel { key, fields })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update_typed(&self.key, |value: &mut Option<Hash>| {
+            let Some(hash) = value else {
+                return Frame::Integer(0);
+            };
+            let removed = self.fields
+                .iter()
+                .filter(|field| hash.remove(field))
+                .count();
+            if hash.is_empty() {
+                *value = None;
+            }
+            Frame::Integer(removed as i64)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `HGETALL key`，依次返回所有的 field 与 value，顺序不确定
+#[derive(Debug)]
+pub struct HGetAll {
+    key: Bytes,
+}
+
+impl HGetAll {
+    pub fn new(key: impl Into<Bytes>) -> HGetAll {
+        HGetAll { key: key.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HGetAll, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(HGetAll { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_hash(db, &self.key, |hash| {
+            let mut frames = vec![];
+            for (field, v) in hash.map_or_else(Vec::new, |hash| hash.entries()) {
+                frames.push(Frame::Bulk(field));
+                frames.push(Frame::Bulk(v));
+            }
+            Frame::Array(frames)
+        })
+    }
+}
+
+/// `HLEN key`，返回 field 数量，key 不存在时为 0
+#[derive(Debug)]
+pub struct HLen {
+    key: Bytes,
+}
+
+impl HLen {
+    pub fn new(key: impl Into<Bytes>) -> HLen {
+        HLen { key: key.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HLen, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(HLen { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_hash(db, &self.key, |hash| {
+            Frame::Integer(hash.map_or(0, |hash| hash.len()) as i64)
+        })
+    }
+}
+
+/// `HEXISTS key field`，field 存在时返回 1，否则返回 0
+#[derive(Debug)]
+pub struct HExists {
+    key: Bytes,
+    field: Bytes,
+}
+
+impl HExists {
+    pub fn new(key: impl Into<Bytes>, field: impl Into<Bytes>) -> HExists {
+        HExists { key: key.into(), field: field.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HExists, ParseError> {
+        let key = parse.next_bytes()?;
+        let field = parse.next_bytes()?;
+        Ok(HExists { key, field })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_hash(db, &self.key, |hash| {
+            let exists = hash.is_some_and(|hash| hash.get(&self.field).is_some());
+            Frame::Integer(exists as i64)
+        })
+    }
+}
+
+/// `HSCAN key cursor [MATCH pattern] [COUNT count]`
+///
+/// 增量遍历哈希表，回复 `[下一次的 cursor, [field value ...]]`，语义同 `SCAN`。
+/// ziplist 编码时一次返回全部
+#[derive(Debug)]
+pub struct HScan {
+    key: Bytes,
+    options: ScanOptions,
+}
+
+impl HScan {
+    pub fn new(key: impl Into<Bytes>, cursor: u64) -> HScan {
+        HScan { key: key.into(), options: ScanOptions::new(cursor) }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HScan, ParseError> {
+        let key = parse.next_bytes()?;
+        let options = ScanOptions::parse(parse, |_, _| Ok(false))?;
+        Ok(HScan { key, options })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let ScanOptions { cursor, pattern, count } = self.options;
+        with_hash(db, &self.key, |hash| {
+            let (cursor, entries) = match hash {
+                Some(hash) => hash.scan(cursor, count, |field| {
+                    pattern.as_ref().is_none_or(|pattern| glob::matches(pattern, field))
+                }),
+                None => (0, vec![]),
+            };
+            let mut frames = Vec::with_capacity(entries.len() * 2);
+            for (field, v) in entries {
+                frames.push(Frame::Bulk(field));
+                frames.push(Frame::Bulk(v));
+            }
+            scan_reply(cursor, frames)
+        })
+    }
+}
+
+/// 修改 key 对应的哈希表中 field 的值，见 [`Hash::update`]。key 不存在时新建，f 出错时不会留下空的哈希表
+fn update_field(
+    db: &Db,
+    key: &Bytes,
+    field: Bytes,
+    limits: &ZipLimits,
+    f: impl FnOnce(Option<&[u8]>) -> Result<Bytes, &'static str>,
+) -> Result<Bytes, String> {
+    db.update_typed(key, |value| {
+        let hash = value.get_or_insert_with(Hash::new);
+        let result = hash.update(field, f, limits).map_err(String::from);
+        if hash.is_empty() {
+            *value = None;
+        }
+        result
+    })
+    .unwrap_or_else(|err| Err(err.to_string()))
+}
+
+/// 访问 key 对应的哈希表，key 不存在时 f 收到 `None`，类型不符时回复 WRONGTYPE 错误。
+/// Dict 查找时会顺带做一步 rehash，所以需要可变引用
+fn with_hash(db: &Db, key: &[u8], f: impl FnOnce(Option<&mut Hash>) -> Frame) -> Frame {
+    db.with_typed(key, f).unwrap_or_else(Frame::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bytes::Bytes;
+
+    use crate::{db::Db, frame::Frame, object::ObjectEncoding};
+
+    use super::{HDel, HExists, HGet, HGetAll, HLen, HScan, HSet};
+
+    fn bulk(s: &str) -> Frame {
+        Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+    }
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(Bytes, Bytes)> {
+        pairs.iter().map(|(f, v)| (Bytes::copy_from_slice(f.as_bytes()), Bytes::copy_from_slice(v.as_bytes()))).collect()
+    }
+
+    #[test]
+    fn basic() {
+        let db = Db::new();
+        assert_eq!(HSet::new("h", fields(&[("a", "1"), ("b", "2")])).apply(&db), Frame::Integer(2));
+        // 已有的 field 只更新值，不计入新增数量
+        assert_eq!(HSet::new("h", fields(&[("a", "3"), ("c", "4")])).apply(&db), Frame::Integer(1));
+        assert_eq!(HGet::new("h", "a").apply(&db), bulk("3"));
+        assert_eq!(HGet::new("h", "x").apply(&db), Frame::Null);
+        assert_eq!(HGet::new("missing", "a").apply(&db), Frame::Null);
+        assert_eq!(HLen::new("h").apply(&db), Frame::Integer(3));
+        assert_eq!(HExists::new("h", "b").apply(&db), Frame::Integer(1));
+        assert_eq!(HExists::new("h", "x").apply(&db), Frame::Integer(0));
+        let Frame::Array(all) = HGetAll::new("h").apply(&db) else { panic!() };
+        let mut pairs: Vec<_> = all.chunks(2).map(|pair| match pair {
+            [Frame::Bulk(field), Frame::Bulk(v)] => (field.clone(), v.clone()),
+            _ => panic!("{:?}", pair),
+        }).collect();
+        pairs.sort();
+        assert_eq!(pairs, fields(&[("a", "3"), ("b", "2"), ("c", "4")]));
+
+        let del = |fields: &[&str]| HDel::new("h", fields.iter().map(|f| Bytes::copy_from_slice(f.as_bytes())).collect()).apply(&db);
+        assert_eq!(del(&["a", "x", "a"]), Frame::Integer(1));
+        // 删除最后的 field 时 key 也被删除
+        assert_eq!(del(&["b", "c"]), Frame::Integer(2));
+        assert!(!db.exists(b"h"));
+        assert_eq!(HLen::new("h").apply(&db), Frame::Integer(0));
+        assert_eq!(HGetAll::new("h").apply(&db), Frame::Array(vec![]));
+
+        db.set(Bytes::from("s"), Bytes::from("v"), None);
+        assert!(matches!(HSet::new("s", fields(&[("a", "1")])).apply(&db), Frame::Error(msg) if msg.starts_with("WRONGTYPE")));
+        assert!(matches!(HGet::new("s", "a").apply(&db), Frame::Error(msg) if msg.starts_with("WRONGTYPE")));
+        assert_eq!(db.get(b"s").unwrap(), Some(Bytes::from("v")));
+    }
+
+    #[test]
+    fn hashtable_scan() {
+        let db = Db::new();
+        let pairs: Vec<_> = (0..1000).map(|i| (Bytes::from(format!("f{}", i)), Bytes::from(i.to_string()))).collect();
+        assert_eq!(HSet::new("h", pairs).apply(&db), Frame::Integer(1000));
+        assert_eq!(db.lookup_read(b"h", |value| value.unwrap().encoding()), ObjectEncoding::HashTable);
+        assert_eq!(HGet::new("h", "f999").apply(&db), bulk("999"));
+
+        // 遍历到 cursor 为 0 时每个 field 至少出现一次
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let Frame::Array(reply) = HScan::new("h", cursor).apply(&db) else { panic!() };
+            let [Frame::Bulk(next), Frame::Array(items)] = &reply[..] else { panic!("{:?}", reply) };
+            for pair in items.chunks(2) {
+                let [Frame::Bulk(field), Frame::Bulk(v)] = pair else { panic!() };
+                assert_eq!(&field[1..], &v[..]);
+                seen.insert(field.clone());
+            }
+            cursor = std::str::from_utf8(next).unwrap().parse().unwrap();
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 1000);
+    }
+}
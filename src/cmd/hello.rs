@@ -0,0 +1,48 @@
+use bytes::Bytes;
+
+use crate::frame::{Frame, Protocol};
+
+use super::{Parse, ParseError};
+
+/// `HELLO [protover]`，切换连接使用的协议版本，并返回服务端信息。
+///
+/// 不带 protover 时保持当前版本。回复在 RESP3 下是 map，在 RESP2 下展开为数组
+#[derive(Debug, Default)]
+pub struct Hello {
+    protocol: Option<Protocol>,
+}
+
+impl Hello {
+    pub fn new(protocol: Option<Protocol>) -> Hello {
+        Hello { protocol }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Hello, ParseError> {
+        if !parse.has_remaining() {
+            return Ok(Hello::default());
+        }
+        let protocol = match parse.next_int() {
+            Ok(2) => Protocol::Resp2,
+            Ok(3) => Protocol::Resp3,
+            Ok(_) => return Err("NOPROTO unsupported protocol version".into()),
+            Err(_) => return Err("ERR Protocol version is not an integer or out of range".into()),
+        };
+        Ok(Hello::new(Some(protocol)))
+    }
+
+    /// 协议版本记录在连接上，由调用方传入，协商成功后会被修改
+    pub(crate) fn apply(self, protocol: &mut Protocol) -> Frame {
+        if let Some(new) = self.protocol {
+            *protocol = new;
+        }
+        let field = |name: &'static str| Frame::Bulk(Bytes::from(name));
+        Frame::Map(vec![
+            (field("server"), field("toyredis")),
+            (field("version"), field(env!("CARGO_PKG_VERSION"))),
+            (field("proto"), Frame::Integer(protocol.version())),
+            (field("mode"), field("standalone")),
+            (field("role"), field("master")),
+            (field("modules"), Frame::Array(vec![])),
+        ])
+    }
+}
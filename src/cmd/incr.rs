@@ -0,0 +1,115 @@
+//! 整数、浮点数自增相关命令。key 不存在时按 0 处理，过期时间保持不变
+
+use bytes::Bytes;
+
+use crate::{db::{Db, WrongType}, frame::Frame, object::RedisObject};
+
+/// 浮点数参数与值的解析规则，geo、sort 等命令共用
+pub(super) use crate::ds::perfstr::parse_float;
+
+use super::{Parse, ParseError};
+
+/// `INCR key` / `DECR key` / `INCRBY key increment` / `DECRBY key decrement`
+///
+/// 将 key 保存的整数加上 delta，返回新的值。int 编码的值会被原地修改
+#[derive(Debug)]
+pub struct IncrBy {
+    key: Bytes,
+    delta: i64,
+    /// 命令名，四个命令只有 delta 的来源不同
+    name: &'static str,
+}
+
+impl IncrBy {
+    /// `INCR key`
+    pub fn incr(key: impl Into<Bytes>) -> IncrBy {
+        IncrBy { key: key.into(), delta: 1, name: "incr" }
+    }
+
+    /// `DECR key`
+    pub fn decr(key: impl Into<Bytes>) -> IncrBy {
+        IncrBy { key: key.into(), delta: -1, name: "decr" }
+    }
+
+    /// `INCRBY key increment`
+    pub fn new(key: impl Into<Bytes>, increment: i64) -> IncrBy {
+        IncrBy { key: key.into(), delta: increment, name: "incrby" }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, name: &'static str) -> Result<IncrBy, ParseError> {
+        let key = parse.next_bytes()?;
+        let delta = match name {
+            "incr" => 1,
+            "decr" => -1,
+            "incrby" => parse.next_int()?,
+            // DECRBY -9223372036854775808 无法取反
+            _ => parse.next_int()?.checked_neg().ok_or("ERR decrement would overflow")?,
+        };
+        Ok(IncrBy { key, delta, name })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update(&self.key, |value| {
+            let value = value.get_or_insert(RedisObject::Int(0));
+            if !value.is_string() {
+                return Frame::Error(WrongType.to_string());
+            }
+            let n = match value.as_int_mut() {
+                Some(n) => n,
+                None => return Frame::Error("ERR value is not an integer or out of range".into()),
+            };
+            match n.checked_add(self.delta) {
+                Some(result) => {
+                    *n = result;
+                    Frame::Integer(result)
+                },
+                None => Frame::Error("ERR increment or decrement would overflow".into()),
+            }
+        })
+    }
+}
+
+/// `INCRBYFLOAT key increment`，将 key 保存的数值加上浮点数 increment，以字符串形式返回新的值
+#[derive(Debug)]
+pub struct IncrByFloat {
+    key: Bytes,
+    delta: f64,
+}
+
+impl IncrByFloat {
+    pub fn new(key: impl Into<Bytes>, increment: f64) -> IncrByFloat {
+        IncrByFloat { key: key.into(), delta: increment }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<IncrByFloat, ParseError> {
+        let key = parse.next_bytes()?;
+        let delta = parse.next_float()?;
+        Ok(IncrByFloat { key, delta })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update(&self.key, |value| {
+            let current = match value {
+                Some(obj) => match obj.as_bytes() {
+                    Some(data) => match parse_float(&data) {
+                        Some(current) => current,
+                        None => return Frame::Error("ERR value is not a valid float".into()),
+                    },
+                    None => return Frame::Error(WrongType.to_string()),
+                },
+                None => 0f64,
+            };
+            let result = current + self.delta;
+            if !result.is_finite() {
+                return Frame::Error("ERR increment would produce NaN or Infinity".into());
+            }
+            let result = Bytes::from(result.to_string());
+            *value = Some(RedisObject::string(&result));
+            Frame::Bulk(result)
+        })
+    }
+}
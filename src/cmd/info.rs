@@ -0,0 +1,192 @@
+//! `INFO [section]`，以文本的形式返回服务端的状态
+
+use std::fmt::Write;
+
+use bytes::Bytes;
+
+use crate::{db::Db, evict::lru_clock, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `INFO [section]`。
+///
+/// 与 redis 一样，每个 section 以 `# 名称` 开头，之后每行一个 `字段:值`，section 之间以空行分隔。
+/// 不指定 section（或者为 `default`）时返回除 commandstats 以外的 section，`all`、`everything` 返回所有 section
+#[derive(Debug, Default)]
+pub struct Info {
+    section: Option<String>,
+}
+
+/// 一个 section 的名称及其中的字段
+type Section = (&'static str, Vec<(String, String)>);
+
+impl Info {
+    pub fn new() -> Info {
+        Info::default()
+    }
+
+    /// 只返回指定的 section
+    pub fn section(section: impl Into<String>) -> Info {
+        Info { section: Some(section.into().to_lowercase()) }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Info, ParseError> {
+        let section = match parse.has_remaining() {
+            true => Some(parse.next_string()?.to_lowercase()),
+            false => None,
+        };
+        Ok(Info { section })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut info = String::new();
+        for (name, fields) in sections(db) {
+            let selected = match self.section.as_deref() {
+                None | Some("default") => name != "Commandstats",
+                Some("all") | Some("everything") => true,
+                Some(section) => section == name.to_lowercase(),
+            };
+            if !selected {
+                continue;
+            }
+            if !info.is_empty() {
+                info.push_str("\r\n");
+            }
+            write!(info, "# {}\r\n", name).unwrap();
+            for (field, value) in fields {
+                write!(info, "{}:{}\r\n", field, value).unwrap();
+            }
+        }
+        Frame::Bulk(Bytes::from(info))
+    }
+
+    /// 转换成发送给服务端的 frame，供客户端使用
+    pub(crate) fn into_frame(self) -> Frame {
+        Frame::array(["info".to_string()].into_iter().chain(self.section))
+    }
+}
+
+/// 收集所有 section 的字段
+fn sections(db: &Db) -> Vec<Section> {
+    let stats = db.stats();
+    let uptime = stats.uptime().as_secs();
+    let used_memory = db.used_memory();
+    let maxmemory = db.maxmemory();
+    let (keys, expires) = db.key_counts();
+    let fields = |fields: Vec<(&str, String)>| fields.into_iter().map(|(field, value)| (field.to_string(), value)).collect();
+    vec![
+        ("Server", fields(vec![
+            ("toyredis_version", env!("CARGO_PKG_VERSION").to_string()),
+            ("process_id", std::process::id().to_string()),
+            ("uptime_in_seconds", uptime.to_string()),
+            ("uptime_in_days", (uptime / 86400).to_string()),
+            ("lru_clock", lru_clock().to_string()),
+        ])),
+        ("Clients", fields(vec![
+            ("connected_clients", stats.connected_clients().to_string()),
+        ])),
+        ("Memory", fields(vec![
+            ("used_memory", used_memory.to_string()),
+            ("used_memory_human", human_bytes(used_memory)),
+            ("maxmemory", maxmemory.to_string()),
+            ("maxmemory_human", human_bytes(maxmemory)),
+            ("maxmemory_policy", db.eviction_policy().to_string()),
+            ("lazyfree_pending_objects", db.lazyfree_pending().to_string()),
+        ])),
+        ("Persistence", fields(vec![
+            ("rdb_bgsave_in_progress", (db.is_saving() as u8).to_string()),
+        ])),
+        ("Stats", fields(vec![
+            ("total_connections_received", stats.connections_received().to_string()),
+            ("total_commands_processed", stats.commands_processed().to_string()),
+            ("keyspace_hits", stats.keyspace_hits().to_string()),
+            ("keyspace_misses", stats.keyspace_misses().to_string()),
+            ("expired_keys", db.expired_keys().to_string()),
+            ("evicted_keys", db.evicted_keys().to_string()),
+        ])),
+        ("Replication", fields(vec![
+            ("role", "master".to_string()),
+            ("connected_slaves", db.replication().replicas().to_string()),
+            ("master_repl_offset", db.replication().offset().to_string()),
+        ])),
+        ("Commandstats", stats.command_calls()
+            .into_iter()
+            .map(|(name, calls)| (format!("cmdstat_{}", name), format!("calls={}", calls)))
+            .collect()),
+        ("Cluster", fields(vec![
+            ("cluster_enabled", (db.cluster().is_some() as u8).to_string()),
+        ])),
+        // 与 redis 一样，没有 key 时不显示
+        ("Keyspace", if keys > 0 {
+            vec![("db0".to_string(), format!("keys={},expires={}", keys, expires))]
+        } else {
+            vec![]
+        }),
+    ]
+}
+
+/// 便于阅读的字节数，如 `1.50K`、`2.00M`
+fn human_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2}{}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{db::{Db, now_ms}, frame::Frame};
+
+    use super::{Info, human_bytes};
+
+    fn render(db: &Db, info: Info) -> String {
+        match info.apply(db) {
+            Frame::Bulk(data) => String::from_utf8(data.to_vec()).unwrap(),
+            frame => panic!("unexpected reply {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn sections() {
+        let db = Db::new();
+        db.set(Bytes::from("k"), Bytes::from("v"), None);
+        db.get(b"k").unwrap();
+        db.get(b"missing").unwrap();
+        db.stats().record_command("get");
+
+        let info = render(&db, Info::new());
+        assert!(info.starts_with("# Server\r\n"));
+        assert!(info.contains("\r\n\r\n# Memory\r\n"));
+        assert!(info.contains("keyspace_hits:1\r\nkeyspace_misses:1\r\n"));
+        assert!(info.contains("# Keyspace\r\ndb0:keys=1,expires=0\r\n"));
+        assert!(!info.contains("cmdstat_get"));
+        assert!(render(&db, Info::section("all")).contains("# Commandstats\r\ncmdstat_get:calls=1\r\n"));
+
+        let info = render(&db, Info::section("CLIENTS"));
+        assert_eq!(info, "# Clients\r\nconnected_clients:0\r\n");
+        assert_eq!(render(&db, Info::section("nosuchsection")), "");
+        db.replication().ack(1, 0);
+        assert_eq!(render(&db, Info::section("replication")), "# Replication\r\nrole:master\r\nconnected_slaves:1\r\nmaster_repl_offset:0\r\n");
+
+        // 读到已过期的 key 时删除它，计入 keyspace_misses 与 expired_keys
+        db.set(Bytes::from("e"), Bytes::from("v"), Some(now_ms() - 1));
+        assert_eq!(db.get(b"e").unwrap(), None);
+        assert!(render(&db, Info::section("stats")).contains("keyspace_misses:2\r\nexpired_keys:1\r\n"));
+    }
+
+    #[test]
+    fn human_readable() {
+        assert_eq!(human_bytes(0), "0B");
+        assert_eq!(human_bytes(1536), "1.50K");
+        assert_eq!(human_bytes(3 * 1024 * 1024), "3.00M");
+    }
+}
@@ -0,0 +1,401 @@
+//! 键空间相关命令：删除、查找、重命名 key 等，不关心 key 对应值的具体类型
+
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame, glob};
+
+use super::{Parse, ParseError};
+
+/// `DEL key [key ...]`，删除一个或多个 key，返回实际删除的数量
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<Bytes>,
+}
+
+impl Del {
+    pub fn new(keys: Vec<Bytes>) -> Del {
+        Del { keys }
+    }
+
+    pub fn keys(&self) -> &[Bytes] {
+        &self.keys
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Del, ParseError> {
+        // 至少需要一个 key
+        let mut keys = vec![parse.next_bytes()?];
+        while parse.has_remaining() {
+            keys.push(parse.next_bytes()?);
+        }
+        Ok(Del { keys })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let removed = self.keys
+            .iter()
+            .filter(|key| db.del(key))
+            .count();
+        Frame::Integer(removed as i64)
+    }
+
+    /// 转换成发送给服务端的 frame，供客户端使用
+    pub(crate) fn into_frame(self) -> Frame {
+        Frame::array([Bytes::from("del")].into_iter().chain(self.keys))
+    }
+}
+
+/// `UNLINK key [key ...]`，与 DEL 相同，但大的值在后台释放，见 [`crate::lazyfree`]
+#[derive(Debug)]
+pub struct Unlink {
+    keys: Vec<Bytes>,
+}
+
+impl Unlink {
+    pub fn new(keys: Vec<Bytes>) -> Unlink {
+        Unlink { keys }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Unlink, ParseError> {
+        let mut keys = vec![parse.next_bytes()?];
+        while parse.has_remaining() {
+            keys.push(parse.next_bytes()?);
+        }
+        Ok(Unlink { keys })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let removed = self.keys
+            .iter()
+            .filter(|key| db.unlink(key))
+            .count();
+        Frame::Integer(removed as i64)
+    }
+}
+
+/// `EXISTS key [key ...]`，返回存在的 key 的数量。同一个 key 出现多次会被重复计数，与 redis 一致
+#[derive(Debug)]
+pub struct Exists {
+    keys: Vec<Bytes>,
+}
+
+impl Exists {
+    pub fn new(keys: Vec<Bytes>) -> Exists {
+        Exists { keys }
+    }
+
+    pub fn keys(&self) -> &[Bytes] {
+        &self.keys
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Exists, ParseError> {
+        let mut keys = vec![parse.next_bytes()?];
+        while parse.has_remaining() {
+            keys.push(parse.next_bytes()?);
+        }
+        Ok(Exists { keys })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let count = self.keys
+            .iter()
+            .filter(|key| db.exists(key))
+            .count();
+        Frame::Integer(count as i64)
+    }
+
+    /// 转换成发送给服务端的 frame，供客户端使用
+    pub(crate) fn into_frame(self) -> Frame {
+        Frame::array([Bytes::from("exists")].into_iter().chain(self.keys))
+    }
+}
+
+/// `KEYS pattern`，返回所有匹配 glob 模式的 key，模式语法见 [`crate::glob`]。
+///
+/// 需要扫描整个键空间，key 很多时会比较慢
+#[derive(Debug)]
+pub struct Keys {
+    pattern: Bytes,
+}
+
+impl Keys {
+    pub fn new(pattern: impl Into<Bytes>) -> Keys {
+        Keys { pattern: pattern.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Keys, ParseError> {
+        let pattern = parse.next_bytes()?;
+        Ok(Keys { pattern })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Array(db.keys(&self.pattern).into_iter().map(Frame::Bulk).collect())
+    }
+}
+
+/// `DBSIZE`，返回 key 的数量，不包括已过期但还没被删除的 key
+#[derive(Debug, Default)]
+pub struct DbSize;
+
+impl DbSize {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<DbSize, ParseError> {
+        Ok(DbSize)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.dbsize() as i64)
+    }
+}
+
+/// `RANDOMKEY`，随机返回一个 key，没有 key 时返回 nil
+#[derive(Debug, Default)]
+pub struct RandomKey;
+
+impl RandomKey {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<RandomKey, ParseError> {
+        Ok(RandomKey)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.random_key().map_or(Frame::Null, Frame::Bulk)
+    }
+}
+
+/// `TYPE key`，返回 key 对应值的类型，key 不存在时返回 none
+#[derive(Debug)]
+pub struct Type {
+    key: Bytes,
+}
+
+impl Type {
+    pub fn new(key: impl Into<Bytes>) -> Type {
+        Type { key: key.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Type, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(Type { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let name = db.lookup_read(&self.key, |value| value.map_or("none", |value| value.type_name()));
+        Frame::Simple(name.into())
+    }
+}
+
+/// `RENAME key newkey` / `RENAMENX key newkey`
+///
+/// 重命名 key，过期时间随之转移。RENAME 会覆盖已存在的 newkey，回复 OK；
+/// RENAMENX 只在 newkey 不存在时重命名，返回是否重命名了。key 不存在时回复错误
+#[derive(Debug)]
+pub struct Rename {
+    key: Bytes,
+    new_key: Bytes,
+    /// 是否为 RENAMENX
+    nx: bool,
+}
+
+impl Rename {
+    /// `RENAME key newkey`
+    pub fn new(key: impl Into<Bytes>, new_key: impl Into<Bytes>) -> Rename {
+        Rename { key: key.into(), new_key: new_key.into(), nx: false }
+    }
+
+    /// `RENAMENX key newkey`
+    pub fn nx(key: impl Into<Bytes>, new_key: impl Into<Bytes>) -> Rename {
+        Rename { key: key.into(), new_key: new_key.into(), nx: true }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, nx: bool) -> Result<Rename, ParseError> {
+        let key = parse.next_bytes()?;
+        let new_key = parse.next_bytes()?;
+        Ok(Rename { key, new_key, nx })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.nx {
+            "renamenx"
+        } else {
+            "rename"
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.rename(&self.key, self.new_key, self.nx) {
+            None => Frame::Error("ERR no such key".into()),
+            Some(renamed) if self.nx => Frame::Integer(renamed as i64),
+            Some(_) => Frame::Simple("OK".into()),
+        }
+    }
+}
+
+/// `COPY source destination [DB destination-db] [REPLACE]`
+///
+/// 把 source 的值连同过期时间复制到 destination，返回是否复制了。destination 已存在时只有指定 REPLACE 才会覆盖。
+/// 只有一个数据库，DB 只接受 0
+#[derive(Debug)]
+pub struct Copy {
+    source: Bytes,
+    destination: Bytes,
+    replace: bool,
+}
+
+impl Copy {
+    pub fn new(source: impl Into<Bytes>, destination: impl Into<Bytes>, replace: bool) -> Copy {
+        Copy { source: source.into(), destination: destination.into(), replace }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Copy, ParseError> {
+        let source = parse.next_bytes()?;
+        let destination = parse.next_bytes()?;
+        let mut replace = false;
+        while parse.has_remaining() {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "REPLACE" => replace = true,
+                "DB" => {
+                    if parse.next_int()? != 0 {
+                        return Err("ERR DB index is out of range".into());
+                    }
+                },
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        Ok(Copy { source, destination, replace })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.copy(&self.source, self.destination, self.replace) {
+            Ok(copied) => Frame::Integer(copied as i64),
+            Err(err) => err.into(),
+        }
+    }
+}
+
+/// `FLUSHALL [ASYNC|SYNC]` / `FLUSHDB [ASYNC|SYNC]`
+///
+/// 删除所有 key。只有一个数据库，两者相同。ASYNC 时旧的键空间交给 lazyfree 线程释放，
+/// 都不指定时由配置项 `lazyfree-lazy-user-flush` 决定
+#[derive(Debug)]
+pub struct FlushAll {
+    /// `None` 表示按配置
+    lazy: Option<bool>,
+    /// 是否为 FLUSHDB
+    db: bool,
+}
+
+impl FlushAll {
+    pub fn new(lazy: bool) -> FlushAll {
+        FlushAll { lazy: Some(lazy), db: false }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, db: bool) -> Result<FlushAll, ParseError> {
+        let lazy = match parse.has_remaining() {
+            true => match parse.next_string()?.to_uppercase().as_str() {
+                "ASYNC" => Some(true),
+                "SYNC" => Some(false),
+                _ => return Err("ERR syntax error".into()),
+            },
+            false => None,
+        };
+        Ok(FlushAll { lazy, db })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.db {
+            "flushdb"
+        } else {
+            "flushall"
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let lazy = self.lazy.unwrap_or_else(|| db.config().lazyfree_lazy_user_flush);
+        db.flushall(lazy);
+        Frame::Simple("OK".into())
+    }
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]`
+///
+/// 增量遍历键空间，回复 `[下一次的 cursor, [key ...]]`，cursor 为 0 表示遍历完成。
+/// 遍历开始前就存在、且一直没被删除的 key 一定会被返回，但可能返回多次
+#[derive(Debug)]
+pub struct Scan {
+    options: ScanOptions,
+    /// 只返回该类型的 key，类型名同 `TYPE` 命令
+    type_name: Option<String>,
+}
+
+impl Scan {
+    pub fn new(cursor: u64) -> Scan {
+        Scan { options: ScanOptions::new(cursor), type_name: None }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Scan, ParseError> {
+        let mut type_name = None;
+        let options = ScanOptions::parse(parse, |option, parse| match option {
+            "TYPE" => {
+                type_name = Some(parse.next_string()?.to_lowercase());
+                Ok(true)
+            },
+            _ => Ok(false),
+        })?;
+        Ok(Scan { options, type_name })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let ScanOptions { cursor, pattern, count } = self.options;
+        let (cursor, keys) = db.scan(cursor, count, |key, value| {
+            pattern.as_ref().is_none_or(|pattern| glob::matches(pattern, key))
+                && self.type_name.as_ref().is_none_or(|name| value.type_name() == name)
+        });
+        scan_reply(cursor, keys.into_iter().map(Frame::Bulk).collect())
+    }
+}
+
+/// SCAN 系列命令共用的参数：`cursor [MATCH pattern] [COUNT count]`
+#[derive(Debug)]
+pub(crate) struct ScanOptions {
+    pub(crate) cursor: u64,
+    pub(crate) pattern: Option<Bytes>,
+    pub(crate) count: usize,
+}
+
+impl ScanOptions {
+    /// 与 redis 一致，默认每次大约返回 10 个
+    const DEFAULT_COUNT: usize = 10;
+
+    pub(crate) fn new(cursor: u64) -> ScanOptions {
+        ScanOptions { cursor, pattern: None, count: Self::DEFAULT_COUNT }
+    }
+
+    /// 解析 cursor 及其后的选项。extra 用于解析各命令特有的选项，参数为大写的选项名，
+    /// 返回 `false` 表示不认识该选项
+    pub(crate) fn parse(
+        parse: &mut Parse,
+        mut extra: impl FnMut(&str, &mut Parse) -> Result<bool, ParseError>,
+    ) -> Result<ScanOptions, ParseError> {
+        let cursor = parse.next_string()?.parse().map_err(|_| "ERR invalid cursor")?;
+        let mut options = ScanOptions::new(cursor);
+        while parse.has_remaining() {
+            let option = parse.next_string()?.to_uppercase();
+            match option.as_str() {
+                "MATCH" => options.pattern = Some(parse.next_bytes()?),
+                "COUNT" => match parse.next_int()? {
+                    count if count < 1 => return Err("ERR syntax error".into()),
+                    count => options.count = count as usize,
+                },
+                _ => {
+                    if !extra(&option, parse)? {
+                        return Err("ERR syntax error".into());
+                    }
+                },
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// SCAN 系列命令的回复：cursor 以字符串形式返回
+pub(crate) fn scan_reply(cursor: u64, items: Vec<Frame>) -> Frame {
+    Frame::Array(vec![Frame::Bulk(Bytes::from(cursor.to_string())), Frame::Array(items)])
+}
@@ -0,0 +1,593 @@
+//! 列表相关命令，数据保存在 [`List`] 中
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+use crate::{db::{Db, WrongType}, frame::{Frame, Protocol}, object::RedisObject, types::List};
+
+use super::{Command, Parse, ParseError};
+
+/// `LPUSH key element [element ...]` / `RPUSH key element [element ...]`
+///
+/// 依次在表头（表尾）插入元素，返回插入后列表的长度
+#[derive(Debug)]
+pub struct Push {
+    key: Bytes,
+    values: Vec<Bytes>,
+    /// 是否在表头插入，即 LPUSH
+    front: bool,
+}
+
+impl Push {
+    /// `LPUSH key element [element ...]`
+    pub fn front(key: impl Into<Bytes>, values: Vec<Bytes>) -> Push {
+        Push { key: key.into(), values, front: true }
+    }
+
+    /// `RPUSH key element [element ...]`
+    pub fn back(key: impl Into<Bytes>, values: Vec<Bytes>) -> Push {
+        Push { key: key.into(), values, front: false }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, front: bool) -> Result<Push, ParseError> {
+        let key = parse.next_bytes()?;
+        let mut values = vec![parse.next_bytes()?];
+        while parse.has_remaining() {
+            values.push(parse.next_bytes()?);
+        }
+        Ok(Push { key, values, front })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.front {
+            "lpush"
+        } else {
+            "rpush"
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let limits = db.encoding_limits().list;
+        let reply = db.update_typed(&self.key, |value| {
+            let list = value.get_or_insert_with(List::new);
+            for v in self.values {
+                if self.front {
+                    list.push_front(v, &limits);
+                } else {
+                    list.push_back(v, &limits);
+                }
+            }
+            Frame::Integer(list.len() as i64)
+        });
+        // 唤醒最早阻塞在这个 key 上的 BLMOVE，见 [`crate::blocking`]
+        if reply.is_ok() {
+            db.waiters().signal_first(&self.key);
+        }
+        reply.unwrap_or_else(Frame::from)
+    }
+}
+
+/// `LPOP key [count]` / `RPOP key [count]`
+///
+/// 从表头（表尾）弹出元素。不带 count 时返回单个元素，否则返回数组；key 不存在时返回 nil。
+/// 列表为空后 key 会被删除
+#[derive(Debug)]
+pub struct Pop {
+    key: Bytes,
+    count: Option<usize>,
+    /// 是否从表头弹出，即 LPOP
+    front: bool,
+}
+
+impl Pop {
+    /// `LPOP key`
+    pub fn front(key: impl Into<Bytes>) -> Pop {
+        Pop { key: key.into(), count: None, front: true }
+    }
+
+    /// `RPOP key`
+    pub fn back(key: impl Into<Bytes>) -> Pop {
+        Pop { key: key.into(), count: None, front: false }
+    }
+
+    /// 最多弹出 count 个元素
+    pub fn count(mut self, count: usize) -> Pop {
+        self.count = Some(count);
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, front: bool) -> Result<Pop, ParseError> {
+        let key = parse.next_bytes()?;
+        let count = if parse.has_remaining() {
+            match parse.next_int()? {
+                count if count < 0 => return Err("ERR value is out of range, must be positive".into()),
+                count => Some(count as usize),
+            }
+        } else {
+            None
+        };
+        Ok(Pop { key, count, front })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.front {
+            "lpop"
+        } else {
+            "rpop"
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update_typed(&self.key, |value: &mut Option<List>| {
+            let Some(list) = value else {
+                return Frame::Null;
+            };
+            let mut pop = || if self.front { list.pop_front() } else { list.pop_back() };
+            let frame = match self.count {
+                None => pop().map_or(Frame::Null, Frame::Bulk),
+                Some(count) => Frame::Array((0..count).map_while(|_| pop()).map(Frame::Bulk).collect()),
+            };
+            if list.is_empty() {
+                *value = None;
+            }
+            frame
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `LMOVE source destination LEFT|RIGHT LEFT|RIGHT` / `RPOPLPUSH source destination`，
+/// 以及阻塞的 `BLMOVE ... timeout` / `BRPOPLPUSH source destination timeout`
+///
+/// 从 source 的一端弹出元素，插入 destination 的一端，返回该元素，source 不存在时返回 nil。
+/// 两个 key 一起修改，是原子的；两个 key 相同时即为旋转列表。
+///
+/// 阻塞只在连接的处理循环中生效，见 [`LMove::block_on`]；在事务、脚本中与 LMOVE 一样立即回复
+#[derive(Debug, Clone)]
+pub struct LMove {
+    source: Bytes,
+    destination: Bytes,
+    /// 是否从 source 的表头弹出
+    from_front: bool,
+    /// 是否插入 destination 的表头
+    to_front: bool,
+    /// 是否为 RPOPLPUSH，只影响命令名
+    rpoplpush: bool,
+    /// BLMOVE、BRPOPLPUSH 的超时时间，0 表示一直等待
+    block: Option<Duration>,
+}
+
+impl LMove {
+    /// `LMOVE source destination LEFT|RIGHT LEFT|RIGHT`，front 为真表示 LEFT
+    pub fn new(source: impl Into<Bytes>, destination: impl Into<Bytes>, from_front: bool, to_front: bool) -> LMove {
+        LMove { source: source.into(), destination: destination.into(), from_front, to_front, rpoplpush: false, block: None }
+    }
+
+    /// 没有元素时最多等待 timeout，即 BLMOVE
+    pub fn block(mut self, timeout: Duration) -> LMove {
+        self.block = Some(timeout);
+        self
+    }
+
+    /// blocking 为真时解析 BLMOVE、BRPOPLPUSH，最后一个参数为超时的秒数，可以是小数
+    pub(crate) fn parse_frames(parse: &mut Parse, rpoplpush: bool, blocking: bool) -> Result<LMove, ParseError> {
+        let source = parse.next_bytes()?;
+        let destination = parse.next_bytes()?;
+        let (from_front, to_front) = match rpoplpush {
+            true => (false, true),
+            false => {
+                let mut side = || match parse.next_string()?.to_uppercase().as_str() {
+                    "LEFT" => Ok(true),
+                    "RIGHT" => Ok(false),
+                    _ => Err(ParseError::from("ERR syntax error")),
+                };
+                (side()?, side()?)
+            },
+        };
+        let block = match blocking {
+            true => Some(parse_timeout(parse)?),
+            false => None,
+        };
+        Ok(LMove { source, destination, from_front, to_front, rpoplpush, block })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match (self.rpoplpush, self.block.is_some()) {
+            (true, false) => "rpoplpush",
+            (true, true) => "brpoplpush",
+            (false, false) => "lmove",
+            (false, true) => "blmove",
+        }
+    }
+
+    /// 是否为 BLMOVE、BRPOPLPUSH
+    pub fn is_blocking(&self) -> bool {
+        self.block.is_some()
+    }
+
+    /// 阻塞执行：source 为空时等待它被写入，被唤醒后重新执行，超时后回复 nil。
+    ///
+    /// 等待期间不持有任何锁。只登记一次，重新执行时保持在队列中的位置，
+    /// 阻塞在同一个 key 上的连接按先来后到取得元素，见 [`crate::blocking`]
+    pub async fn block_on(self, db: &Db, protocol: &mut Protocol) -> Frame {
+        let Some(timeout) = self.block else {
+            return Command::LMove(self).apply(db, protocol);
+        };
+        let deadline = Instant::now() + timeout;
+        let waiter = db.waiters().register(std::slice::from_ref(&self.source));
+        loop {
+            let reply = Command::LMove(self.clone()).apply(db, protocol);
+            if reply != Frame::Null {
+                return reply;
+            }
+            if timeout.is_zero() {
+                waiter.wait().await;
+            } else if tokio::time::timeout_at(deadline, waiter.wait()).await.is_err() {
+                return Frame::Null;
+            }
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let limits = db.encoding_limits().list;
+        let reply = db.update_pair(&self.source, &self.destination, |source, destination| {
+            // 两个 key 的类型都检查过之后才修改，出错时不会只弹出了元素
+            let list = match source {
+                Some(RedisObject::List(list)) => list,
+                Some(_) => return Frame::Error(WrongType.to_string()),
+                None => return Frame::Null,
+            };
+            if matches!(destination, Some(Some(value)) if !matches!(value, RedisObject::List(_))) {
+                return Frame::Error(WrongType.to_string());
+            }
+            // 空列表会被删除，存在的列表至少有一个元素
+            let value = if self.from_front { list.pop_front() } else { list.pop_back() }.unwrap();
+            let target = match destination {
+                // 同一个 key，插回原来的列表
+                None => list,
+                Some(destination) => {
+                    if list.is_empty() {
+                        *source = None;
+                    }
+                    match destination.get_or_insert_with(|| RedisObject::List(List::new())) {
+                        RedisObject::List(list) => list,
+                        _ => unreachable!(),
+                    }
+                },
+            };
+            if self.to_front {
+                target.push_front(value.clone(), &limits);
+            } else {
+                target.push_back(value.clone(), &limits);
+            }
+            Frame::Bulk(value)
+        });
+        // 元素进入了 destination，阻塞在它上面的连接可以继续
+        if matches!(reply, Frame::Bulk(_)) {
+            db.waiters().signal_first(&self.destination);
+        }
+        reply
+    }
+}
+
+/// 阻塞命令的超时秒数，可以是小数，0 表示一直等待
+fn parse_timeout(parse: &mut Parse) -> Result<Duration, ParseError> {
+    let timeout = parse.next_float().map_err(|_| ParseError::from("ERR timeout is not a float or out of range"))?;
+    if timeout < 0.0 {
+        return Err("ERR timeout is negative".into());
+    }
+    Duration::try_from_secs_f64(timeout).map_err(|_| "ERR timeout is out of range".into())
+}
+
+/// `LRANGE key start stop`，返回 [start, stop] 内的元素，负数表示从表尾倒数
+#[derive(Debug)]
+pub struct LRange {
+    key: Bytes,
+    start: i64,
+    stop: i64,
+}
+
+impl LRange {
+    pub fn new(key: impl Into<Bytes>, start: i64, stop: i64) -> LRange {
+        LRange { key: key.into(), start, stop }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LRange, ParseError> {
+        let key = parse.next_bytes()?;
+        let start = parse.next_int()?;
+        let stop = parse.next_int()?;
+        Ok(LRange { key, start, stop })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_list(db, &self.key, |list| {
+            let values = list.map_or_else(Vec::new, |list| list.range(self.start, self.stop));
+            Frame::Array(values.into_iter().map(Frame::Bulk).collect())
+        })
+    }
+}
+
+/// `LINDEX key index`，返回第 index 个元素，负数表示从表尾倒数，越界时返回 nil
+#[derive(Debug)]
+pub struct LIndex {
+    key: Bytes,
+    index: i64,
+}
+
+impl LIndex {
+    pub fn new(key: impl Into<Bytes>, index: i64) -> LIndex {
+        LIndex { key: key.into(), index }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LIndex, ParseError> {
+        let key = parse.next_bytes()?;
+        let index = parse.next_int()?;
+        Ok(LIndex { key, index })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_list(db, &self.key, |list| {
+            list.and_then(|list| list.get(self.index)).map_or(Frame::Null, Frame::Bulk)
+        })
+    }
+}
+
+/// `LLEN key`，返回列表长度，key 不存在时为 0
+#[derive(Debug)]
+pub struct LLen {
+    key: Bytes,
+}
+
+impl LLen {
+    pub fn new(key: impl Into<Bytes>) -> LLen {
+        LLen { key: key.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LLen, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(LLen { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_list(db, &self.key, |list| {
+            Frame::Integer(list.map_or(0, |list| list.len()) as i64)
+        })
+    }
+}
+
+/// `LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]`
+///
+/// 值等于 element 的元素的位置。不带 COUNT 时返回第一个匹配的位置，找不到时返回 nil；
+/// 带 COUNT 时返回数组，COUNT 为 0 表示返回所有匹配的位置
+#[derive(Debug)]
+pub struct LPos {
+    key: Bytes,
+    element: Bytes,
+    /// 为负数时从表尾开始查找
+    rank: i64,
+    count: Option<usize>,
+    /// 最多比较的元素个数，0 表示不限
+    maxlen: usize,
+}
+
+impl LPos {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LPos, ParseError> {
+        let key = parse.next_bytes()?;
+        let element = parse.next_bytes()?;
+        let mut lpos = LPos { key, element, rank: 1, count: None, maxlen: 0 };
+        while parse.has_remaining() {
+            let option = parse.next_string()?.to_uppercase();
+            let value = parse.next_int().map_err(|err| match err {
+                ParseError::EndOfStream => "ERR syntax error".into(),
+                err => err,
+            })?;
+            match &option[..] {
+                "RANK" if value == 0 => {
+                    return Err("ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... \
+                        or use negative to start from the end of the list".into());
+                },
+                "RANK" => lpos.rank = value,
+                "COUNT" if value < 0 => return Err("ERR COUNT can't be negative".into()),
+                "COUNT" => lpos.count = Some(value as usize),
+                "MAXLEN" if value < 0 => return Err("ERR MAXLEN can't be negative".into()),
+                "MAXLEN" => lpos.maxlen = value as usize,
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        Ok(lpos)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_list(db, &self.key, |list| {
+            let positions = list.map_or_else(Vec::new, |list| {
+                list.positions(&self.element, self.rank, self.count.unwrap_or(1), self.maxlen)
+            });
+            match self.count {
+                None => positions.first().map_or(Frame::Null, |&index| Frame::Integer(index as i64)),
+                Some(_) => Frame::Array(positions.into_iter().map(|index| Frame::Integer(index as i64)).collect()),
+            }
+        })
+    }
+}
+
+/// `LREM key count element`
+///
+/// 删除值等于 element 的元素：count 为正数时从表头开始最多删除 count 个，为负数时从表尾开始，
+/// 为 0 时全部删除。返回删除的个数，列表为空后 key 会被删除
+#[derive(Debug)]
+pub struct LRem {
+    key: Bytes,
+    count: i64,
+    element: Bytes,
+}
+
+impl LRem {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LRem, ParseError> {
+        let key = parse.next_bytes()?;
+        let count = parse.next_int()?;
+        let element = parse.next_bytes()?;
+        Ok(LRem { key, count, element })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update_typed(&self.key, |value: &mut Option<List>| {
+            let Some(list) = value else {
+                return Frame::Integer(0);
+            };
+            let removed = list.remove(&self.element, self.count);
+            if list.is_empty() {
+                *value = None;
+            }
+            Frame::Integer(removed as i64)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `LSET key index element`，把第 index 个元素替换为 element，负数表示从表尾倒数
+#[derive(Debug)]
+pub struct LSet {
+    key: Bytes,
+    index: i64,
+    element: Bytes,
+}
+
+impl LSet {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LSet, ParseError> {
+        let key = parse.next_bytes()?;
+        let index = parse.next_int()?;
+        let element = parse.next_bytes()?;
+        Ok(LSet { key, index, element })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let limits = db.encoding_limits().list;
+        db.update_typed(&self.key, |value: &mut Option<List>| {
+            let Some(list) = value else {
+                return Frame::Error("ERR no such key".into());
+            };
+            if list.set(self.index, self.element, &limits) {
+                Frame::Simple("OK".into())
+            } else {
+                Frame::Error("ERR index out of range".into())
+            }
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `LINSERT key BEFORE|AFTER pivot element`
+///
+/// 在第一个值等于 pivot 的元素之前（之后）插入 element，返回插入后的长度。
+/// 找不到 pivot 时返回 -1，key 不存在时返回 0
+#[derive(Debug)]
+pub struct LInsert {
+    key: Bytes,
+    after: bool,
+    pivot: Bytes,
+    element: Bytes,
+}
+
+impl LInsert {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LInsert, ParseError> {
+        let key = parse.next_bytes()?;
+        let after = match parse.next_string()?.to_uppercase().as_str() {
+            "BEFORE" => false,
+            "AFTER" => true,
+            _ => return Err("ERR syntax error".into()),
+        };
+        let pivot = parse.next_bytes()?;
+        let element = parse.next_bytes()?;
+        Ok(LInsert { key, after, pivot, element })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let limits = db.encoding_limits().list;
+        db.update_typed(&self.key, |value: &mut Option<List>| {
+            let Some(list) = value else {
+                return Frame::Integer(0);
+            };
+            match list.insert(&self.pivot, self.element, self.after, &limits) {
+                Some(len) => Frame::Integer(len as i64),
+                None => Frame::Integer(-1),
+            }
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// 以只读方式访问 key 对应的列表，key 不存在时 f 收到 `None`，类型不符时回复 WRONGTYPE 错误
+fn with_list(db: &Db, key: &[u8], f: impl FnOnce(Option<&List>) -> Frame) -> Frame {
+    db.with_typed(key, |list: Option<&mut List>| f(list.map(|list| &*list))).unwrap_or_else(Frame::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use std::time::Duration;
+
+    use crate::{db::Db, frame::{Frame, Protocol}};
+
+    use super::{LMove, LRange, Push};
+
+    fn items(db: &Db, key: &str) -> Frame {
+        LRange::new(Bytes::from(key.to_string()), 0, -1).apply(db)
+    }
+
+    fn bulks(values: &[&'static str]) -> Frame {
+        Frame::Array(values.iter().map(|v| Frame::Bulk(Bytes::from(*v))).collect())
+    }
+
+    #[test]
+    fn lmove() {
+        let db = Db::new();
+        Push::back("a", ["1", "2", "3"].map(Bytes::from).to_vec()).apply(&db);
+        assert_eq!(LMove::new("a", "b", false, true).apply(&db), Frame::Bulk(Bytes::from("3")));
+        assert_eq!(LMove::new("a", "b", true, false).apply(&db), Frame::Bulk(Bytes::from("1")));
+        assert_eq!((items(&db, "a"), items(&db, "b")), (bulks(&["2"]), bulks(&["3", "1"])));
+
+        // 同一个 key 时旋转列表
+        assert_eq!(LMove::new("b", "b", true, false).apply(&db), Frame::Bulk(Bytes::from("3")));
+        assert_eq!(items(&db, "b"), bulks(&["1", "3"]));
+
+        // 弹出最后一个元素后 source 被删除
+        LMove::new("a", "b", true, true).apply(&db);
+        assert!(!db.exists(b"a"));
+        assert_eq!(LMove::new("a", "b", true, true).apply(&db), Frame::Null);
+
+        // 目标类型不符时不做任何修改
+        db.set(Bytes::from("s"), Bytes::from("v"), None);
+        assert!(matches!(LMove::new("b", "s", true, true).apply(&db), Frame::Error(_)));
+        assert_eq!(items(&db, "b"), bulks(&["2", "1", "3"]));
+        assert!(matches!(LMove::new("s", "b", true, true).apply(&db), Frame::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn blocking_move() {
+        let db = Db::new();
+        let blmove = |to: &str| LMove::new("src", to.to_string(), true, true);
+        assert_eq!(blmove("d").block(Duration::from_millis(20)).block_on(&db, &mut Protocol::Resp2).await, Frame::Null);
+        // 事务、脚本中通过 apply 执行时不阻塞
+        assert_eq!(blmove("d").block(Duration::ZERO).apply(&db), Frame::Null);
+
+        // 先阻塞的连接先得到元素
+        let mut movers = vec![];
+        for to in ["first", "second"] {
+            let (db, cmd) = (db.clone(), blmove(to).block(Duration::ZERO));
+            movers.push(tokio::spawn(async move { cmd.block_on(&db, &mut Protocol::Resp2).await }));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Push::back("src", vec![Bytes::from("1")]).apply(&db);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(items(&db, "first"), bulks(&["1"]));
+        assert!(!movers[1].is_finished());
+        Push::back("src", vec![Bytes::from("2"), Bytes::from("3")]).apply(&db);
+        for mover in movers {
+            assert!(matches!(mover.await.unwrap(), Frame::Bulk(_)));
+        }
+        assert_eq!((items(&db, "second"), items(&db, "src")), (bulks(&["2"]), bulks(&["3"])));
+    }
+}
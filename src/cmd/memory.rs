@@ -0,0 +1,103 @@
+//! `MEMORY` 命令，查看 key 及整个键空间的内存占用
+
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// 统计中出现的类型，没有这种类型的 key 时也输出 0
+const TYPES: [&str; 6] = ["string", "list", "hash", "set", "zset", "stream"];
+
+/// `MEMORY <subcommand>`
+#[derive(Debug)]
+pub enum Memory {
+    /// `MEMORY USAGE key [SAMPLES count]`，key 占用的字节数，key 不存在时返回 nil。
+    ///
+    /// 与 redis 不同，总是遍历整个值，SAMPLES 只为兼容而接受
+    Usage(Bytes),
+    /// `MEMORY STATS`，key 的总数、内存占用，以及按值的类型分别汇总的结果
+    Stats,
+}
+
+impl Memory {
+    pub fn usage(key: impl Into<Bytes>) -> Memory {
+        Memory::Usage(key.into())
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Memory, ParseError> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_lowercase().as_str() {
+            "usage" => {
+                let key = parse.next_bytes()?;
+                if parse.has_remaining() && (parse.next_string()?.to_uppercase() != "SAMPLES" || parse.next_int()? < 0) {
+                    return Err("ERR syntax error".into());
+                }
+                Ok(Memory::Usage(key))
+            },
+            "stats" => Ok(Memory::Stats),
+            _ => Err(format!("ERR unknown subcommand '{}'. Try MEMORY HELP.", subcommand).into()),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self {
+            Memory::Usage(key) => db.memory_usage(&key).map_or(Frame::Null, |bytes| Frame::Integer(bytes as i64)),
+            Memory::Stats => {
+                let stats = db.memory_stats();
+                let field = |name: String| Frame::Bulk(Bytes::from(name));
+                let (keys, bytes) = stats.values().fold((0, 0), |(keys, bytes), total| (keys + total.keys, bytes + total.bytes));
+                let mut fields = vec![
+                    (field("keys.count".into()), Frame::Integer(keys as i64)),
+                    (field("dataset.bytes".into()), Frame::Integer(bytes as i64)),
+                ];
+                for name in TYPES {
+                    let total = stats.get(name).copied().unwrap_or_default();
+                    fields.push((field(format!("{}.keys", name)), Frame::Integer(total.keys as i64)));
+                    fields.push((field(format!("{}.bytes", name)), Frame::Integer(total.bytes as i64)));
+                }
+                Frame::Map(fields)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{db::Db, frame::Frame};
+
+    use super::Memory;
+
+    fn stat(frame: &Frame, name: &str) -> i64 {
+        let Frame::Map(fields) = frame else { panic!("unexpected reply {:?}", frame) };
+        fields
+            .iter()
+            .find_map(|(field, value)| match (field, value) {
+                (Frame::Bulk(field), Frame::Integer(n)) if field == name => Some(*n),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn usage_and_stats() {
+        let db = Db::new();
+        assert_eq!(Memory::usage("missing").apply(&db), Frame::Null);
+
+        db.set(Bytes::from("s"), Bytes::from(vec![b'x'; 1000]), None);
+        let Frame::Integer(small) = Memory::usage("s").apply(&db) else { panic!() };
+        assert!(small > 1000);
+        db.set(Bytes::from("s"), Bytes::from(vec![b'x'; 5000]), None);
+        let Frame::Integer(large) = Memory::usage("s").apply(&db) else { panic!() };
+        assert!(large > small + 3000);
+
+        db.set(Bytes::from("n"), Bytes::from("1"), None);
+        let stats = Memory::Stats.apply(&db);
+        assert_eq!(stat(&stats, "keys.count"), 2);
+        assert_eq!(stat(&stats, "string.keys"), 2);
+        assert_eq!(stat(&stats, "list.keys"), 0);
+        assert_eq!(stat(&stats, "dataset.bytes"), db.used_memory() as i64);
+    }
+}
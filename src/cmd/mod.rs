@@ -1,2 +1,789 @@
-mod command;
-pub use command::*;
\ No newline at end of file
+//! 命令层：把客户端发来的 `Frame::Array` 解析成具体的命令，并在数据库上执行。
+//! redis 命令格式可参见[这儿](https://redis.io/commands/)
+
+mod parse;
+use parse::{Parse, ParseError};
+
+mod get;
+pub use get::{Get, GetDel, GetEx, MGet};
+
+mod set;
+pub use set::{Expiration, GetSet, MSet, Set, SetNx};
+
+mod keyspace;
+pub use keyspace::{Copy, DbSize, Del, Exists, FlushAll, Keys, RandomKey, Rename, Scan, Type, Unlink};
+
+mod expire;
+pub use expire::Expire;
+
+mod ttl;
+pub use ttl::Ttl;
+
+mod persist;
+pub use persist::Persist;
+
+mod incr;
+pub use incr::{IncrBy, IncrByFloat};
+
+mod strings;
+pub use strings::{Append, GetRange, SetRange, StrLen};
+
+mod bitops;
+pub use bitops::{BitCount, BitOp, BitOperation, GetBit, SetBit};
+
+mod list;
+pub use list::{LIndex, LInsert, LLen, LMove, LPos, LRange, LRem, LSet, Pop, Push};
+
+mod hash;
+pub use hash::{HDel, HExists, HGet, HGetAll, HIncrBy, HIncrByFloat, HLen, HRandField, HScan, HSet, HSetNx};
+
+mod sets;
+pub use sets::{SAdd, SCard, SInterCard, SIsMember, SMembers, SPop, SRandMember, SRem, SScan, SetAlgebra, SetOp};
+
+mod zset;
+pub use zset::{Aggregate, LexLimit, ZAdd, ZCard, ZCount, ZIncrBy, ZLexCount, ZPop, ZRange, ZRangeByLex, ZRangeByScore, ZRank, ZRem, ZScan, ZScore, ZStore};
+
+mod sort;
+pub use sort::Sort;
+
+mod geo;
+pub use geo::{GeoAdd, GeoDist, GeoPos, GeoSearch};
+
+mod stream;
+pub use stream::{ReadFrom, Trim, XAck, XAdd, XClaim, XGroup, XLen, XPending, XRange, XRead};
+
+mod object;
+pub use object::Object;
+
+mod pubsub;
+pub use pubsub::{Publish, Subscribe, Unsubscribe};
+
+mod multi;
+pub use multi::{Discard, Exec, Multi, Unwatch, Watch};
+
+mod ping;
+pub use ping::Ping;
+
+mod hello;
+pub use hello::Hello;
+
+mod save;
+pub use save::{BgSave, Save};
+
+mod info;
+pub use info::Info;
+
+mod script;
+pub use script::{Eval, Script};
+
+mod config;
+pub use config::Config;
+
+mod slowlog;
+pub use slowlog::SlowLog;
+
+mod cluster;
+pub use cluster::Cluster;
+
+mod memory;
+pub use memory::Memory;
+
+mod client;
+pub use client::{Client, Kill};
+
+mod auth;
+pub use auth::Auth;
+
+mod acl;
+pub use acl::Acl;
+
+mod debug;
+pub use debug::Debug;
+
+mod shutdown;
+pub use shutdown::Shutdown;
+
+mod wait;
+pub use wait::Wait;
+
+mod replconf;
+pub use replconf::ReplConf;
+
+mod unknown;
+pub use unknown::Unknown;
+
+use crate::{acl::{Categories, KeySpec}, connection::Reply, db::Db, evict::OutOfMemory, frame::{Frame, Protocol}, pubsub::ChannelKind};
+
+/// 支持的命令
+#[derive(Debug)]
+pub enum Command {
+    Get(Get),
+    MGet(MGet),
+    Set(Set),
+    SetNx(SetNx),
+    GetSet(GetSet),
+    GetDel(GetDel),
+    GetEx(GetEx),
+    MSet(MSet),
+    Del(Del),
+    Unlink(Unlink),
+    FlushAll(FlushAll),
+    Exists(Exists),
+    Keys(Keys),
+    DbSize(DbSize),
+    RandomKey(RandomKey),
+    Type(Type),
+    Rename(Rename),
+    Copy(Copy),
+    Scan(Scan),
+    Expire(Expire),
+    Ttl(Ttl),
+    Persist(Persist),
+    IncrBy(IncrBy),
+    IncrByFloat(IncrByFloat),
+    Append(Append),
+    StrLen(StrLen),
+    GetRange(GetRange),
+    SetRange(SetRange),
+    SetBit(SetBit),
+    GetBit(GetBit),
+    BitCount(BitCount),
+    BitOp(BitOp),
+    Push(Push),
+    Pop(Pop),
+    LRange(LRange),
+    LLen(LLen),
+    LIndex(LIndex),
+    LPos(LPos),
+    LRem(LRem),
+    LSet(LSet),
+    LInsert(LInsert),
+    LMove(LMove),
+    HSet(HSet),
+    HGet(HGet),
+    HDel(HDel),
+    HGetAll(HGetAll),
+    HLen(HLen),
+    HExists(HExists),
+    HSetNx(HSetNx),
+    HIncrBy(HIncrBy),
+    HIncrByFloat(HIncrByFloat),
+    HRandField(HRandField),
+    HScan(HScan),
+    SAdd(SAdd),
+    SRem(SRem),
+    SMembers(SMembers),
+    SScan(SScan),
+    SIsMember(SIsMember),
+    SCard(SCard),
+    SPop(SPop),
+    SRandMember(SRandMember),
+    SInterCard(SInterCard),
+    SetAlgebra(SetAlgebra),
+    Sort(Sort),
+    ZAdd(ZAdd),
+    ZIncrBy(ZIncrBy),
+    ZRem(ZRem),
+    ZScore(ZScore),
+    ZScan(ZScan),
+    ZCard(ZCard),
+    ZCount(ZCount),
+    ZRangeByScore(ZRangeByScore),
+    ZRank(ZRank),
+    ZRange(ZRange),
+    ZPop(ZPop),
+    ZLexCount(ZLexCount),
+    ZRangeByLex(ZRangeByLex),
+    ZStore(ZStore),
+    GeoAdd(GeoAdd),
+    GeoPos(GeoPos),
+    GeoDist(GeoDist),
+    GeoSearch(GeoSearch),
+    XAdd(XAdd),
+    XRange(XRange),
+    XLen(XLen),
+    XRead(XRead),
+    XGroup(XGroup),
+    XAck(XAck),
+    XPending(XPending),
+    XClaim(XClaim),
+    Object(Object),
+    Publish(Publish),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Watch(Watch),
+    Unwatch(Unwatch),
+    Ping(Ping),
+    Hello(Hello),
+    Save(Save),
+    BgSave(BgSave),
+    Info(Info),
+    Eval(Eval),
+    Script(Script),
+    Config(Config),
+    SlowLog(SlowLog),
+    Cluster(Cluster),
+    Memory(Memory),
+    Client(Client),
+    Auth(Auth),
+    Acl(Acl),
+    Debug(Debug),
+    Shutdown(Shutdown),
+    Wait(Wait),
+    ReplConf(ReplConf),
+    Unknown(Unknown),
+}
+
+impl Command {
+    /// 从客户端发来的 frame 中解析出命令。
+    ///
+    /// 参数个数不对、参数格式错误等都会返回 `Err`，其内容即回复给客户端的错误信息。
+    pub fn from_frame(frame: Frame) -> crate::Result<Command> {
+        let mut parse = Parse::new(frame)?;
+        let command_name = parse.next_string()?.to_lowercase();
+        match Self::parse_command(&command_name, &mut parse) {
+            Ok(command) => Ok(command),
+            Err(ParseError::EndOfStream) | Err(ParseError::Trailing) => {
+                Err(format!("ERR wrong number of arguments for '{}' command", command_name).into())
+            },
+            Err(ParseError::Other(err)) => Err(err),
+        }
+    }
+
+    fn parse_command(command_name: &str, parse: &mut Parse) -> Result<Command, ParseError> {
+        let command = match command_name {
+            "get" => Command::Get(Get::parse_frames(parse)?),
+            "set" => Command::Set(Set::parse_frames(parse)?),
+            "setnx" => Command::SetNx(SetNx::parse_frames(parse)?),
+            "getset" => Command::GetSet(GetSet::parse_frames(parse)?),
+            "getdel" => Command::GetDel(GetDel::parse_frames(parse)?),
+            "getex" => Command::GetEx(GetEx::parse_frames(parse)?),
+            "mget" => Command::MGet(MGet::parse_frames(parse)?),
+            "mset" => Command::MSet(MSet::parse_frames(parse, false)?),
+            "msetnx" => Command::MSet(MSet::parse_frames(parse, true)?),
+            "del" => Command::Del(Del::parse_frames(parse)?),
+            "unlink" => Command::Unlink(Unlink::parse_frames(parse)?),
+            "flushall" => Command::FlushAll(FlushAll::parse_frames(parse, false)?),
+            "flushdb" => Command::FlushAll(FlushAll::parse_frames(parse, true)?),
+            "exists" => Command::Exists(Exists::parse_frames(parse)?),
+            "keys" => Command::Keys(Keys::parse_frames(parse)?),
+            "dbsize" => Command::DbSize(DbSize::parse_frames(parse)?),
+            "randomkey" => Command::RandomKey(RandomKey::parse_frames(parse)?),
+            "type" => Command::Type(Type::parse_frames(parse)?),
+            "rename" => Command::Rename(Rename::parse_frames(parse, false)?),
+            "renamenx" => Command::Rename(Rename::parse_frames(parse, true)?),
+            "copy" => Command::Copy(Copy::parse_frames(parse)?),
+            "scan" => Command::Scan(Scan::parse_frames(parse)?),
+            "expire" => Command::Expire(Expire::parse_frames(parse, 1000, false)?),
+            "pexpire" => Command::Expire(Expire::parse_frames(parse, 1, false)?),
+            "expireat" => Command::Expire(Expire::parse_frames(parse, 1000, true)?),
+            "pexpireat" => Command::Expire(Expire::parse_frames(parse, 1, true)?),
+            "ttl" => Command::Ttl(Ttl::parse_frames(parse, false, false)?),
+            "pttl" => Command::Ttl(Ttl::parse_frames(parse, true, false)?),
+            "expiretime" => Command::Ttl(Ttl::parse_frames(parse, false, true)?),
+            "pexpiretime" => Command::Ttl(Ttl::parse_frames(parse, true, true)?),
+            "persist" => Command::Persist(Persist::parse_frames(parse)?),
+            "incr" => Command::IncrBy(IncrBy::parse_frames(parse, "incr")?),
+            "decr" => Command::IncrBy(IncrBy::parse_frames(parse, "decr")?),
+            "incrby" => Command::IncrBy(IncrBy::parse_frames(parse, "incrby")?),
+            "decrby" => Command::IncrBy(IncrBy::parse_frames(parse, "decrby")?),
+            "incrbyfloat" => Command::IncrByFloat(IncrByFloat::parse_frames(parse)?),
+            "append" => Command::Append(Append::parse_frames(parse)?),
+            "strlen" => Command::StrLen(StrLen::parse_frames(parse)?),
+            "getrange" => Command::GetRange(GetRange::parse_frames(parse)?),
+            "setrange" => Command::SetRange(SetRange::parse_frames(parse)?),
+            "setbit" => Command::SetBit(SetBit::parse_frames(parse)?),
+            "getbit" => Command::GetBit(GetBit::parse_frames(parse)?),
+            "bitcount" => Command::BitCount(BitCount::parse_frames(parse)?),
+            "bitop" => Command::BitOp(BitOp::parse_frames(parse)?),
+            "lpush" => Command::Push(Push::parse_frames(parse, true)?),
+            "rpush" => Command::Push(Push::parse_frames(parse, false)?),
+            "lpop" => Command::Pop(Pop::parse_frames(parse, true)?),
+            "rpop" => Command::Pop(Pop::parse_frames(parse, false)?),
+            "lrange" => Command::LRange(LRange::parse_frames(parse)?),
+            "llen" => Command::LLen(LLen::parse_frames(parse)?),
+            "lindex" => Command::LIndex(LIndex::parse_frames(parse)?),
+            "lpos" => Command::LPos(LPos::parse_frames(parse)?),
+            "lrem" => Command::LRem(LRem::parse_frames(parse)?),
+            "lset" => Command::LSet(LSet::parse_frames(parse)?),
+            "linsert" => Command::LInsert(LInsert::parse_frames(parse)?),
+            "lmove" => Command::LMove(LMove::parse_frames(parse, false, false)?),
+            "rpoplpush" => Command::LMove(LMove::parse_frames(parse, true, false)?),
+            "blmove" => Command::LMove(LMove::parse_frames(parse, false, true)?),
+            "brpoplpush" => Command::LMove(LMove::parse_frames(parse, true, true)?),
+            "hset" => Command::HSet(HSet::parse_frames(parse)?),
+            "hget" => Command::HGet(HGet::parse_frames(parse)?),
+            "hdel" => Command::HDel(HDel::parse_frames(parse)?),
+            "hgetall" => Command::HGetAll(HGetAll::parse_frames(parse)?),
+            "hlen" => Command::HLen(HLen::parse_frames(parse)?),
+            "hexists" => Command::HExists(HExists::parse_frames(parse)?),
+            "hsetnx" => Command::HSetNx(HSetNx::parse_frames(parse)?),
+            "hincrby" => Command::HIncrBy(HIncrBy::parse_frames(parse)?),
+            "hincrbyfloat" => Command::HIncrByFloat(HIncrByFloat::parse_frames(parse)?),
+            "hrandfield" => Command::HRandField(HRandField::parse_frames(parse)?),
+            "hscan" => Command::HScan(HScan::parse_frames(parse)?),
+            "sadd" => Command::SAdd(SAdd::parse_frames(parse)?),
+            "srem" => Command::SRem(SRem::parse_frames(parse)?),
+            "smembers" => Command::SMembers(SMembers::parse_frames(parse)?),
+            "sscan" => Command::SScan(SScan::parse_frames(parse)?),
+            "sismember" => Command::SIsMember(SIsMember::parse_frames(parse)?),
+            "scard" => Command::SCard(SCard::parse_frames(parse)?),
+            "spop" => Command::SPop(SPop::parse_frames(parse)?),
+            "srandmember" => Command::SRandMember(SRandMember::parse_frames(parse)?),
+            "sintercard" => Command::SInterCard(SInterCard::parse_frames(parse)?),
+            "sinter" => Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Inter, false)?),
+            "sinterstore" => Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Inter, true)?),
+            "sunion" => Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Union, false)?),
+            "sunionstore" => Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Union, true)?),
+            "sdiff" => Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Diff, false)?),
+            "sdiffstore" => Command::SetAlgebra(SetAlgebra::parse_frames(parse, SetOp::Diff, true)?),
+            "sort" => Command::Sort(Sort::parse_frames(parse)?),
+            "zadd" => Command::ZAdd(ZAdd::parse_frames(parse)?),
+            "zincrby" => Command::ZIncrBy(ZIncrBy::parse_frames(parse)?),
+            "zrem" => Command::ZRem(ZRem::parse_frames(parse)?),
+            "zscore" => Command::ZScore(ZScore::parse_frames(parse)?),
+            "zscan" => Command::ZScan(ZScan::parse_frames(parse)?),
+            "zcard" => Command::ZCard(ZCard::parse_frames(parse)?),
+            "zcount" => Command::ZCount(ZCount::parse_frames(parse)?),
+            "zrangebyscore" => Command::ZRangeByScore(ZRangeByScore::parse_frames(parse)?),
+            "zrank" => Command::ZRank(ZRank::parse_frames(parse, false)?),
+            "zrevrank" => Command::ZRank(ZRank::parse_frames(parse, true)?),
+            "zrange" => Command::ZRange(ZRange::parse_frames(parse, false)?),
+            "zrevrange" => Command::ZRange(ZRange::parse_frames(parse, true)?),
+            "zpopmin" => Command::ZPop(ZPop::parse_frames(parse, false)?),
+            "zpopmax" => Command::ZPop(ZPop::parse_frames(parse, true)?),
+            "zlexcount" => Command::ZLexCount(ZLexCount::parse_frames(parse)?),
+            "zrangebylex" => Command::ZRangeByLex(ZRangeByLex::parse_frames(parse)?),
+            "zunionstore" => Command::ZStore(ZStore::parse_frames(parse, false)?),
+            "zinterstore" => Command::ZStore(ZStore::parse_frames(parse, true)?),
+            "geoadd" => Command::GeoAdd(GeoAdd::parse_frames(parse)?),
+            "geopos" => Command::GeoPos(GeoPos::parse_frames(parse)?),
+            "geodist" => Command::GeoDist(GeoDist::parse_frames(parse)?),
+            "geosearch" => Command::GeoSearch(GeoSearch::parse_frames(parse)?),
+            "xadd" => Command::XAdd(XAdd::parse_frames(parse)?),
+            "xrange" => Command::XRange(XRange::parse_frames(parse, false)?),
+            "xrevrange" => Command::XRange(XRange::parse_frames(parse, true)?),
+            "xlen" => Command::XLen(XLen::parse_frames(parse)?),
+            "xread" => Command::XRead(XRead::parse_frames(parse, false)?),
+            "xreadgroup" => Command::XRead(XRead::parse_frames(parse, true)?),
+            "xgroup" => Command::XGroup(XGroup::parse_frames(parse)?),
+            "xack" => Command::XAck(XAck::parse_frames(parse)?),
+            "xpending" => Command::XPending(XPending::parse_frames(parse)?),
+            "xclaim" => Command::XClaim(XClaim::parse_frames(parse)?),
+            "object" => Command::Object(Object::parse_frames(parse)?),
+            "publish" => Command::Publish(Publish::parse_frames(parse, false)?),
+            "spublish" => Command::Publish(Publish::parse_frames(parse, true)?),
+            "subscribe" => Command::Subscribe(Subscribe::parse_frames(parse, ChannelKind::Channel)?),
+            "psubscribe" => Command::Subscribe(Subscribe::parse_frames(parse, ChannelKind::Pattern)?),
+            "ssubscribe" => Command::Subscribe(Subscribe::parse_frames(parse, ChannelKind::Shard)?),
+            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(parse, ChannelKind::Channel)?),
+            "punsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(parse, ChannelKind::Pattern)?),
+            "sunsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(parse, ChannelKind::Shard)?),
+            "multi" => Command::Multi(Multi::parse_frames(parse)?),
+            "exec" => Command::Exec(Exec::parse_frames(parse)?),
+            "discard" => Command::Discard(Discard::parse_frames(parse)?),
+            "watch" => Command::Watch(Watch::parse_frames(parse)?),
+            "unwatch" => Command::Unwatch(Unwatch::parse_frames(parse)?),
+            "ping" => Command::Ping(Ping::parse_frames(parse)?),
+            "hello" => Command::Hello(Hello::parse_frames(parse)?),
+            "save" => Command::Save(Save::parse_frames(parse)?),
+            "bgsave" => Command::BgSave(BgSave::parse_frames(parse)?),
+            "info" => Command::Info(Info::parse_frames(parse)?),
+            "eval" => Command::Eval(Eval::parse_frames(parse, false)?),
+            "evalsha" => Command::Eval(Eval::parse_frames(parse, true)?),
+            "script" => Command::Script(Script::parse_frames(parse)?),
+            "config" => Command::Config(Config::parse_frames(parse)?),
+            "slowlog" => Command::SlowLog(SlowLog::parse_frames(parse)?),
+            "cluster" => Command::Cluster(Cluster::parse_frames(parse)?),
+            "memory" => Command::Memory(Memory::parse_frames(parse)?),
+            "client" => Command::Client(Client::parse_frames(parse)?),
+            "auth" => Command::Auth(Auth::parse_frames(parse)?),
+            "acl" => Command::Acl(Acl::parse_frames(parse)?),
+            "debug" => Command::Debug(Debug::parse_frames(parse)?),
+            "shutdown" => Command::Shutdown(Shutdown::parse_frames(parse)?),
+            "wait" => Command::Wait(Wait::parse_frames(parse)?),
+            "replconf" => Command::ReplConf(ReplConf::parse_frames(parse)?),
+            // 未知命令不再检查参数
+            _ => return Ok(Command::Unknown(Unknown::new(command_name))),
+        };
+        parse.finish()?;
+        Ok(command)
+    }
+
+    /// 在数据库上执行命令，返回需要回复给客户端的 frame。
+    ///
+    /// protocol 为连接当前使用的协议版本，`HELLO` 会修改它。
+    /// 订阅、事务相关的命令需要连接自己的状态，由连接的处理循环直接执行，这里只返回错误
+    pub fn apply(self, db: &Db, protocol: &mut Protocol) -> Frame {
+        if let Command::Eval(cmd) = self {
+            // 脚本与 EXEC 一样持有写锁，执行期间不会穿插其他命令
+            let _guard = db.exec_guard();
+            return cmd.apply(db);
+        }
+        // 与 EXEC 互斥，事务执行期间不会穿插其他命令
+        let _guard = db.command_guard();
+        self.execute(db, protocol)
+    }
+
+    /// 同 [`Command::apply`]，回复直接编码到 reply。GET、SET 这类回复简单的命令不构造 `Frame`，
+    /// 其他命令编码 `apply` 返回的 frame。会修改协议的 `HELLO` 不能通过这里执行
+    pub fn apply_reply(self, db: &Db, reply: Reply) {
+        if !matches!(self, Command::Get(_) | Command::Set(_)) {
+            let mut protocol = reply.protocol();
+            return reply.frame(&self.apply(db, &mut protocol));
+        }
+        let _guard = db.command_guard();
+        if db.evict().is_err() && self.may_grow() {
+            return reply.error(&OutOfMemory.to_string());
+        }
+        match self {
+            Command::Get(cmd) => cmd.reply(db, reply),
+            Command::Set(cmd) => cmd.reply(db, reply),
+            _ => unreachable!(),
+        }
+    }
+
+    /// 执行命令，不获取 [`Db::command_guard`]，供 EXEC 在持有写锁时调用
+    pub(crate) fn execute(self, db: &Db, protocol: &mut Protocol) -> Frame {
+        use Command::*;
+        // 内存超出 maxmemory 时先淘汰 key，淘汰不了则拒绝可能增加内存的命令
+        if db.evict().is_err() && self.may_grow() {
+            return Frame::Error(OutOfMemory.to_string());
+        }
+        match self {
+            Get(cmd) => cmd.apply(db),
+            Set(cmd) => cmd.apply(db),
+            SetNx(cmd) => cmd.apply(db),
+            GetSet(cmd) => cmd.apply(db),
+            GetDel(cmd) => cmd.apply(db),
+            GetEx(cmd) => cmd.apply(db),
+            MGet(cmd) => cmd.apply(db),
+            MSet(cmd) => cmd.apply(db),
+            Del(cmd) => cmd.apply(db),
+            Unlink(cmd) => cmd.apply(db),
+            FlushAll(cmd) => cmd.apply(db),
+            Exists(cmd) => cmd.apply(db),
+            Keys(cmd) => cmd.apply(db),
+            DbSize(cmd) => cmd.apply(db),
+            RandomKey(cmd) => cmd.apply(db),
+            Type(cmd) => cmd.apply(db),
+            Rename(cmd) => cmd.apply(db),
+            Copy(cmd) => cmd.apply(db),
+            Scan(cmd) => cmd.apply(db),
+            Expire(cmd) => cmd.apply(db),
+            Ttl(cmd) => cmd.apply(db),
+            Persist(cmd) => cmd.apply(db),
+            IncrBy(cmd) => cmd.apply(db),
+            IncrByFloat(cmd) => cmd.apply(db),
+            Append(cmd) => cmd.apply(db),
+            StrLen(cmd) => cmd.apply(db),
+            GetRange(cmd) => cmd.apply(db),
+            SetRange(cmd) => cmd.apply(db),
+            SetBit(cmd) => cmd.apply(db),
+            GetBit(cmd) => cmd.apply(db),
+            BitCount(cmd) => cmd.apply(db),
+            BitOp(cmd) => cmd.apply(db),
+            Push(cmd) => cmd.apply(db),
+            Pop(cmd) => cmd.apply(db),
+            LRange(cmd) => cmd.apply(db),
+            LLen(cmd) => cmd.apply(db),
+            LIndex(cmd) => cmd.apply(db),
+            LPos(cmd) => cmd.apply(db),
+            LRem(cmd) => cmd.apply(db),
+            LSet(cmd) => cmd.apply(db),
+            LInsert(cmd) => cmd.apply(db),
+            LMove(cmd) => cmd.apply(db),
+            HSet(cmd) => cmd.apply(db),
+            HGet(cmd) => cmd.apply(db),
+            HDel(cmd) => cmd.apply(db),
+            HGetAll(cmd) => cmd.apply(db),
+            HLen(cmd) => cmd.apply(db),
+            HExists(cmd) => cmd.apply(db),
+            HSetNx(cmd) => cmd.apply(db),
+            HIncrBy(cmd) => cmd.apply(db),
+            HIncrByFloat(cmd) => cmd.apply(db),
+            HRandField(cmd) => cmd.apply(db),
+            HScan(cmd) => cmd.apply(db),
+            SAdd(cmd) => cmd.apply(db),
+            SRem(cmd) => cmd.apply(db),
+            SMembers(cmd) => cmd.apply(db),
+            SScan(cmd) => cmd.apply(db),
+            SIsMember(cmd) => cmd.apply(db),
+            SCard(cmd) => cmd.apply(db),
+            SPop(cmd) => cmd.apply(db),
+            SRandMember(cmd) => cmd.apply(db),
+            SInterCard(cmd) => cmd.apply(db),
+            SetAlgebra(cmd) => cmd.apply(db),
+            Sort(cmd) => cmd.apply(db),
+            ZAdd(cmd) => cmd.apply(db),
+            ZIncrBy(cmd) => cmd.apply(db),
+            ZRem(cmd) => cmd.apply(db),
+            ZScore(cmd) => cmd.apply(db),
+            ZScan(cmd) => cmd.apply(db),
+            ZCard(cmd) => cmd.apply(db),
+            ZCount(cmd) => cmd.apply(db),
+            ZRangeByScore(cmd) => cmd.apply(db),
+            ZRank(cmd) => cmd.apply(db),
+            ZRange(cmd) => cmd.apply(db),
+            ZPop(cmd) => cmd.apply(db),
+            ZLexCount(cmd) => cmd.apply(db),
+            ZRangeByLex(cmd) => cmd.apply(db),
+            ZStore(cmd) => cmd.apply(db),
+            GeoAdd(cmd) => cmd.apply(db),
+            GeoPos(cmd) => cmd.apply(db),
+            GeoDist(cmd) => cmd.apply(db),
+            GeoSearch(cmd) => cmd.apply(db),
+            XAdd(cmd) => cmd.apply(db),
+            XRange(cmd) => cmd.apply(db),
+            XLen(cmd) => cmd.apply(db),
+            XRead(cmd) => cmd.apply(db),
+            XGroup(cmd) => cmd.apply(db),
+            XAck(cmd) => cmd.apply(db),
+            XPending(cmd) => cmd.apply(db),
+            XClaim(cmd) => cmd.apply(db),
+            Object(cmd) => cmd.apply(db),
+            Publish(cmd) => cmd.apply(db),
+            cmd @ (Subscribe(_) | Unsubscribe(_) | Multi(_) | Exec(_) | Discard(_) | Watch(_) | Client(_) | Auth(_) | Acl(_) | ReplConf(_)) => {
+                Frame::Error(format!("ERR '{}' is unsupported in this context", cmd.get_name()))
+            },
+            // 事务中排队的 UNWATCH 执行时，EXEC 已经取消了所有 WATCH
+            Unwatch(_) => Frame::Simple("OK".into()),
+            Ping(cmd) => cmd.apply(),
+            Hello(cmd) => cmd.apply(protocol),
+            Save(cmd) => cmd.apply(db),
+            BgSave(cmd) => cmd.apply(db),
+            Info(cmd) => cmd.apply(db),
+            Eval(cmd) => cmd.apply(db),
+            Script(cmd) => cmd.apply(db),
+            Config(cmd) => cmd.apply(db),
+            SlowLog(cmd) => cmd.apply(db),
+            Cluster(cmd) => cmd.apply(db),
+            Memory(cmd) => cmd.apply(db),
+            Debug(cmd) => cmd.apply(db),
+            Shutdown(cmd) => cmd.apply(),
+            Wait(cmd) => cmd.apply(db),
+            Unknown(cmd) => cmd.apply(),
+        }
+    }
+
+    /// 在 RESP2 的订阅模式下执行命令。此时连接上只能收发订阅消息，PING 的回复也以数组的形式返回
+    pub fn apply_subscribed(self) -> Frame {
+        match self {
+            Command::Ping(cmd) => cmd.apply_subscribed(),
+            cmd => Frame::Error(format!(
+                "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                cmd.get_name(),
+            )),
+        }
+    }
+
+    /// 命令执行后内存占用是否可能增加，内存超出 maxmemory 且无法淘汰时这些命令会被拒绝
+    fn may_grow(&self) -> bool {
+        use Command::*;
+        matches!(self, Set(_) | SetNx(_) | Copy(_) | GetSet(_) | MSet(_) | IncrBy(_) | IncrByFloat(_) | Append(_) | SetRange(_) | SetBit(_) | BitOp(_) | Push(_) | LSet(_) | LInsert(_) | LMove(_) | HSet(_) | HSetNx(_) | HIncrBy(_) | HIncrByFloat(_) | SAdd(_) | ZAdd(_) | ZIncrBy(_) | ZStore(_) | GeoAdd(_) | XAdd(_) | XGroup(_))
+            || matches!(self, SetAlgebra(cmd) if cmd.is_store())
+            || matches!(self, Sort(cmd) if cmd.is_store())
+    }
+
+    /// 命令所属的分类，用于 ACL 中的 `@分类` 规则
+    pub fn categories(&self) -> Categories {
+        use Command::*;
+        match self {
+            Get(_) | MGet(_) | StrLen(_) | GetRange(_) => Categories::READ | Categories::STRING,
+            Set(_) | SetNx(_) | GetSet(_) | GetDel(_) | GetEx(_) | MSet(_) | IncrBy(_) | IncrByFloat(_) | Append(_) | SetRange(_) => Categories::WRITE | Categories::STRING,
+            GetBit(_) | BitCount(_) => Categories::READ | Categories::BITMAP,
+            SetBit(_) | BitOp(_) => Categories::WRITE | Categories::BITMAP,
+            Exists(_) | Type(_) | Ttl(_) | Scan(_) | Object(_) | Memory(memory::Memory::Usage(_)) => Categories::KEYSPACE | Categories::READ,
+            Keys(_) => Categories::KEYSPACE | Categories::READ | Categories::DANGEROUS,
+            DbSize(_) | RandomKey(_) => Categories::KEYSPACE | Categories::READ,
+            Del(_) | Unlink(_) | Rename(_) | Copy(_) | Expire(_) | Persist(_) => Categories::KEYSPACE | Categories::WRITE,
+            FlushAll(_) => Categories::KEYSPACE | Categories::WRITE | Categories::DANGEROUS,
+            LRange(_) | LLen(_) | LIndex(_) | LPos(_) => Categories::READ | Categories::LIST,
+            Push(_) | Pop(_) | LRem(_) | LSet(_) | LInsert(_) | LMove(_) => Categories::WRITE | Categories::LIST,
+            HGet(_) | HGetAll(_) | HLen(_) | HExists(_) | HScan(_) | HRandField(_) => Categories::READ | Categories::HASH,
+            HSet(_) | HDel(_) | HSetNx(_) | HIncrBy(_) | HIncrByFloat(_) => Categories::WRITE | Categories::HASH,
+            SMembers(_) | SIsMember(_) | SCard(_) | SRandMember(_) | SInterCard(_) | SScan(_) => Categories::READ | Categories::SET,
+            SetAlgebra(cmd) if !cmd.is_store() => Categories::READ | Categories::SET,
+            SAdd(_) | SRem(_) | SPop(_) | SetAlgebra(_) => Categories::WRITE | Categories::SET,
+            Sort(cmd) if !cmd.is_store() => Categories::READ | Categories::LIST | Categories::SET | Categories::SORTEDSET,
+            Sort(_) => Categories::WRITE | Categories::LIST | Categories::SET | Categories::SORTEDSET,
+            ZScore(_) | ZScan(_) | ZCard(_) | ZCount(_) | ZRangeByScore(_) | ZRank(_) | ZRange(_) | ZLexCount(_) | ZRangeByLex(_) => {
+                Categories::READ | Categories::SORTEDSET
+            },
+            ZAdd(_) | ZIncrBy(_) | ZRem(_) | ZPop(_) | ZStore(_) => Categories::WRITE | Categories::SORTEDSET,
+            GeoPos(_) | GeoDist(_) | GeoSearch(_) => Categories::READ | Categories::GEO,
+            GeoAdd(_) => Categories::WRITE | Categories::GEO,
+            XRead(cmd) if !cmd.is_group() => Categories::READ | Categories::STREAM,
+            XRange(_) | XLen(_) | XPending(_) => Categories::READ | Categories::STREAM,
+            XAdd(_) | XRead(_) | XGroup(_) | XAck(_) | XClaim(_) => Categories::WRITE | Categories::STREAM,
+            Publish(_) | Subscribe(_) | Unsubscribe(_) => Categories::PUBSUB,
+            Multi(_) | Exec(_) | Discard(_) | Watch(_) | Unwatch(_) => Categories::TRANSACTION,
+            Eval(_) | Script(_) => Categories::SCRIPTING,
+            Client(client::Client::List | client::Client::Kill(_)) => Categories::ADMIN | Categories::CONNECTION | Categories::DANGEROUS,
+            Ping(_) | Hello(_) | Auth(_) | Client(_) | Acl(acl::Acl::WhoAmI) | Wait(_) => Categories::CONNECTION,
+            Save(_) | BgSave(_) | Config(_) | SlowLog(_) | Acl(_) | Debug(_) | Shutdown(_) | ReplConf(_) => Categories::ADMIN | Categories::DANGEROUS,
+            Info(_) | Memory(_) => Categories::DANGEROUS,
+            Cluster(_) | Unknown(_) => Categories::default(),
+        }
+    }
+
+    /// 命令的参数中哪些是 key，用于检查 ACL 中的 key 模式
+    pub fn key_spec(&self) -> KeySpec {
+        use Command::*;
+        match self {
+            MGet(_) | Del(_) | Unlink(_) | Exists(_) | SetAlgebra(_) | Watch(_) => KeySpec::ALL,
+            MSet(_) => KeySpec::Range { first: 1, last: -1, step: 2 },
+            Rename(_) | Copy(_) | LMove(_) => KeySpec::Range { first: 1, last: 2, step: 1 },
+            BitOp(_) => KeySpec::Range { first: 2, last: -1, step: 1 },
+            // OBJECT subcommand key、DEBUG OBJECT key、MEMORY USAGE key、XGROUP subcommand key
+            Object(_) | Debug(debug::Debug::Object(_)) | Memory(memory::Memory::Usage(_)) | XGroup(_) => KeySpec::Range { first: 2, last: 2, step: 1 },
+            Eval(_) => KeySpec::NumKeys { pos: 2, dest: false },
+            SInterCard(_) => KeySpec::NumKeys { pos: 1, dest: false },
+            ZStore(_) => KeySpec::NumKeys { pos: 2, dest: true },
+            Sort(_) => KeySpec::Sort,
+            XRead(_) => KeySpec::Streams,
+            Keys(_) | DbSize(_) | RandomKey(_) | Scan(_) | FlushAll(_) | Publish(_) | Subscribe(_) | Unsubscribe(_) | Multi(_) | Exec(_) | Discard(_) | Unwatch(_)
+                | Ping(_) | Hello(_) | Save(_) | BgSave(_) | Info(_) | Script(_) | Config(_) | SlowLog(_) | Cluster(_) | Memory(_) | Client(_)
+                | Auth(_) | Acl(_) | Debug(_) | Shutdown(_) | Wait(_) | ReplConf(_) | Unknown(_) => KeySpec::None,
+            _ => KeySpec::SINGLE,
+        }
+    }
+
+    /// 集群模式下用于计算槽的参数。与 [`Command::key_spec`] 相同，
+    /// 只是分片频道也像 key 一样属于某个槽，但不受 ACL 的 key 模式限制
+    pub fn slot_spec(&self) -> KeySpec {
+        match self {
+            Command::Publish(cmd) if cmd.is_shard() => KeySpec::SINGLE,
+            Command::Subscribe(cmd) if cmd.kind() == ChannelKind::Shard => KeySpec::ALL,
+            Command::Unsubscribe(cmd) if cmd.kind() == ChannelKind::Shard => KeySpec::ALL,
+            _ => self.key_spec(),
+        }
+    }
+
+    /// 命令名，主要用于日志
+    pub fn get_name(&self) -> &str {
+        match self {
+            Command::Get(_) => "get",
+            Command::Set(_) => "set",
+            Command::SetNx(_) => "setnx",
+            Command::GetSet(_) => "getset",
+            Command::GetDel(_) => "getdel",
+            Command::GetEx(_) => "getex",
+            Command::MGet(_) => "mget",
+            Command::MSet(cmd) => cmd.name(),
+            Command::Del(_) => "del",
+            Command::Unlink(_) => "unlink",
+            Command::FlushAll(cmd) => cmd.name(),
+            Command::Exists(_) => "exists",
+            Command::Keys(_) => "keys",
+            Command::DbSize(_) => "dbsize",
+            Command::RandomKey(_) => "randomkey",
+            Command::Type(_) => "type",
+            Command::Rename(cmd) => cmd.name(),
+            Command::Copy(_) => "copy",
+            Command::Scan(_) => "scan",
+            Command::Expire(cmd) => cmd.name(),
+            Command::Ttl(cmd) => cmd.name(),
+            Command::Persist(_) => "persist",
+            Command::IncrBy(cmd) => cmd.name(),
+            Command::IncrByFloat(_) => "incrbyfloat",
+            Command::Append(_) => "append",
+            Command::StrLen(_) => "strlen",
+            Command::GetRange(_) => "getrange",
+            Command::SetRange(_) => "setrange",
+            Command::SetBit(_) => "setbit",
+            Command::GetBit(_) => "getbit",
+            Command::BitCount(_) => "bitcount",
+            Command::BitOp(_) => "bitop",
+            Command::Push(cmd) => cmd.name(),
+            Command::Pop(cmd) => cmd.name(),
+            Command::LRange(_) => "lrange",
+            Command::LLen(_) => "llen",
+            Command::LIndex(_) => "lindex",
+            Command::LPos(_) => "lpos",
+            Command::LRem(_) => "lrem",
+            Command::LSet(_) => "lset",
+            Command::LInsert(_) => "linsert",
+            Command::LMove(cmd) => cmd.name(),
+            Command::HSet(_) => "hset",
+            Command::HGet(_) => "hget",
+            Command::HDel(_) => "hdel",
+            Command::HGetAll(_) => "hgetall",
+            Command::HLen(_) => "hlen",
+            Command::HExists(_) => "hexists",
+            Command::HSetNx(_) => "hsetnx",
+            Command::HIncrBy(_) => "hincrby",
+            Command::HIncrByFloat(_) => "hincrbyfloat",
+            Command::HRandField(_) => "hrandfield",
+            Command::HScan(_) => "hscan",
+            Command::SAdd(_) => "sadd",
+            Command::SRem(_) => "srem",
+            Command::SMembers(_) => "smembers",
+            Command::SScan(_) => "sscan",
+            Command::SIsMember(_) => "sismember",
+            Command::SCard(_) => "scard",
+            Command::SPop(_) => "spop",
+            Command::SRandMember(_) => "srandmember",
+            Command::SInterCard(_) => "sintercard",
+            Command::SetAlgebra(cmd) => cmd.name(),
+            Command::Sort(_) => "sort",
+            Command::ZAdd(_) => "zadd",
+            Command::ZIncrBy(_) => "zincrby",
+            Command::ZRem(_) => "zrem",
+            Command::ZScore(_) => "zscore",
+            Command::ZScan(_) => "zscan",
+            Command::ZCard(_) => "zcard",
+            Command::ZCount(_) => "zcount",
+            Command::ZRangeByScore(_) => "zrangebyscore",
+            Command::ZRank(cmd) => cmd.name(),
+            Command::ZRange(cmd) => cmd.name(),
+            Command::ZPop(cmd) => cmd.name(),
+            Command::ZLexCount(_) => "zlexcount",
+            Command::ZRangeByLex(_) => "zrangebylex",
+            Command::ZStore(cmd) => cmd.name(),
+            Command::GeoAdd(_) => "geoadd",
+            Command::GeoPos(_) => "geopos",
+            Command::GeoDist(_) => "geodist",
+            Command::GeoSearch(_) => "geosearch",
+            Command::XAdd(_) => "xadd",
+            Command::XRange(cmd) => cmd.name(),
+            Command::XLen(_) => "xlen",
+            Command::XRead(cmd) => cmd.name(),
+            Command::XGroup(_) => "xgroup",
+            Command::XAck(_) => "xack",
+            Command::XPending(_) => "xpending",
+            Command::XClaim(_) => "xclaim",
+            Command::Object(_) => "object",
+            Command::Publish(cmd) => cmd.name(),
+            Command::Subscribe(cmd) => cmd.name(),
+            Command::Unsubscribe(cmd) => cmd.name(),
+            Command::Multi(_) => "multi",
+            Command::Exec(_) => "exec",
+            Command::Discard(_) => "discard",
+            Command::Watch(_) => "watch",
+            Command::Unwatch(_) => "unwatch",
+            Command::Ping(_) => "ping",
+            Command::Hello(_) => "hello",
+            Command::Save(_) => "save",
+            Command::BgSave(_) => "bgsave",
+            Command::Info(_) => "info",
+            Command::Eval(cmd) => cmd.name(),
+            Command::Script(_) => "script",
+            Command::Config(_) => "config",
+            Command::SlowLog(_) => "slowlog",
+            Command::Cluster(_) => "cluster",
+            Command::Memory(_) => "memory",
+            Command::Client(_) => "client",
+            Command::Auth(_) => "auth",
+            Command::Acl(_) => "acl",
+            Command::Debug(_) => "debug",
+            Command::Shutdown(_) => "shutdown",
+            Command::Wait(_) => "wait",
+            Command::ReplConf(_) => "replconf",
+            Command::Unknown(cmd) => cmd.get_name(),
+        }
+    }
+}
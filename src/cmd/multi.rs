@@ -0,0 +1,83 @@
+//! 事务相关命令。事务状态属于连接，见 [`crate::transaction::Transaction`]
+
+use bytes::Bytes;
+
+use crate::{frame::{Frame, Protocol}, transaction::Transaction};
+
+use super::{Parse, ParseError};
+
+/// `MULTI`，之后的命令只排队，直到 EXEC 时一起执行
+#[derive(Debug, Default)]
+pub struct Multi;
+
+impl Multi {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Multi, ParseError> {
+        Ok(Multi)
+    }
+
+    pub fn apply(self, transaction: &mut Transaction) -> Frame {
+        transaction.multi()
+    }
+}
+
+/// `EXEC`，执行排队的命令，返回各命令的回复。WATCH 的 key 被修改过时返回 Null
+#[derive(Debug, Default)]
+pub struct Exec;
+
+impl Exec {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Exec, ParseError> {
+        Ok(Exec)
+    }
+
+    pub fn apply(self, transaction: &mut Transaction, protocol: &mut Protocol) -> Frame {
+        transaction.exec(protocol)
+    }
+}
+
+/// `DISCARD`，放弃排队的命令
+#[derive(Debug, Default)]
+pub struct Discard;
+
+impl Discard {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Discard, ParseError> {
+        Ok(Discard)
+    }
+
+    pub fn apply(self, transaction: &mut Transaction) -> Frame {
+        transaction.discard()
+    }
+}
+
+/// `WATCH key [key ...]`，这些 key 在 EXEC 之前被修改的话，事务不会执行
+#[derive(Debug)]
+pub struct Watch {
+    keys: Vec<Bytes>,
+}
+
+impl Watch {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Watch, ParseError> {
+        let mut keys = vec![parse.next_bytes()?];
+        while parse.has_remaining() {
+            keys.push(parse.next_bytes()?);
+        }
+        Ok(Watch { keys })
+    }
+
+    pub fn apply(self, transaction: &mut Transaction) -> Frame {
+        transaction.watch(self.keys)
+    }
+}
+
+/// `UNWATCH`，取消所有 WATCH。在事务中与其他命令一样排队，执行时什么也不做
+#[derive(Debug, Default)]
+pub struct Unwatch;
+
+impl Unwatch {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Unwatch, ParseError> {
+        Ok(Unwatch)
+    }
+
+    pub fn apply(self, transaction: &mut Transaction) -> Frame {
+        transaction.unwatch()
+    }
+}
@@ -0,0 +1,67 @@
+use bytes::Bytes;
+
+use crate::{db::{Db, now_ms}, evict::EvictionPolicy, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// 共享对象的引用计数，与 redis 的 `OBJ_SHARED_REFCOUNT` 相同
+pub(crate) const SHARED_REFCOUNT: i64 = i32::MAX as i64;
+
+/// `OBJECT <subcommand> key`，查看 key 对应对象的内部信息。查看本身不算作对 key 的访问
+#[derive(Debug)]
+pub enum Object {
+    /// `OBJECT ENCODING key`，返回底层编码，key 不存在时返回 nil
+    Encoding(Bytes),
+    /// `OBJECT REFCOUNT key`，共享的小整数返回 [`SHARED_REFCOUNT`]，其他对象都是 1
+    RefCount(Bytes),
+    /// `OBJECT IDLETIME key`，距离上次访问的秒数，淘汰策略为 LFU 时不可用
+    IdleTime(Bytes),
+    /// `OBJECT FREQ key`，LFU 计数器，淘汰策略为 LFU 时才可用
+    Freq(Bytes),
+}
+
+impl Object {
+    pub fn encoding(key: impl Into<Bytes>) -> Object {
+        Object::Encoding(key.into())
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Object, ParseError> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_lowercase().as_str() {
+            "encoding" => Ok(Object::Encoding(parse.next_bytes()?)),
+            "refcount" => Ok(Object::RefCount(parse.next_bytes()?)),
+            "idletime" => Ok(Object::IdleTime(parse.next_bytes()?)),
+            "freq" => Ok(Object::Freq(parse.next_bytes()?)),
+            _ => Err(format!("ERR unknown subcommand '{}'. Try OBJECT HELP.", subcommand).into()),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let lfu = db.eviction_policy() == EvictionPolicy::AllKeysLfu;
+        let key = match &self {
+            Object::IdleTime(_) if lfu => return Frame::Error(
+                "ERR An LFU maxmemory policy is selected, idle time not tracked. \
+                 Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust."
+                    .into(),
+            ),
+            Object::Freq(_) if !lfu => return Frame::Error(
+                "ERR An LFU maxmemory policy is not selected, access frequency not tracked. \
+                 Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust."
+                    .into(),
+            ),
+            Object::Encoding(key) | Object::RefCount(key) | Object::IdleTime(key) | Object::Freq(key) => key,
+        };
+        let now = now_ms();
+        db.peek(key, |found| {
+            let Some((value, access)) = found else {
+                return Frame::Null;
+            };
+            match self {
+                Object::Encoding(_) => Frame::Bulk(Bytes::from(value.encoding().as_str())),
+                Object::RefCount(_) => Frame::Integer(if value.is_shared() { SHARED_REFCOUNT } else { 1 }),
+                Object::IdleTime(_) => Frame::Integer((access.idle(now) / 1000) as i64),
+                Object::Freq(_) => Frame::Integer(access.freq(now) as i64),
+            }
+        })
+    }
+}
@@ -0,0 +1,148 @@
+use std::{fmt, vec};
+
+use bytes::Bytes;
+
+use crate::frame::Frame;
+
+/// 命令解析工具。客户端发来的命令都是 `Frame::Array`，第一个元素为命令名，后续为参数，
+/// `Parse` 像游标一样逐个取出参数。
+pub(crate) struct Parse {
+    parts: vec::IntoIter<Frame>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ParseError {
+    /// 参数已取完
+    EndOfStream,
+    /// 命令解析完成后仍有多余参数
+    Trailing,
+    Other(crate::Error),
+}
+
+impl Parse {
+    pub(crate) fn new(frame: Frame) -> Result<Parse, ParseError> {
+        let array = match frame {
+            Frame::Array(array) => array,
+            _ => return Err("protocol error; expected array".into()),
+        };
+        Ok(Parse { parts: array.into_iter() })
+    }
+
+    fn next(&mut self) -> Result<Frame, ParseError> {
+        self.parts.next().ok_or(ParseError::EndOfStream)
+    }
+
+    /// 以 utf8 字符串的形式取出下一个参数，用于命令名、选项等
+    pub(crate) fn next_string(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Frame::Simple(s) => Ok(s),
+            Frame::Bulk(data) => std::str::from_utf8(&data[..])
+                .map(|s| s.to_string())
+                .map_err(|_| "protocol error; invalid string".into()),
+            _ => Err("protocol error; expected simple frame or bulk frame".into()),
+        }
+    }
+
+    /// 以原始字节的形式取出下一个参数，用于 key、value 等二进制安全的数据
+    pub(crate) fn next_bytes(&mut self) -> Result<Bytes, ParseError> {
+        match self.next()? {
+            Frame::Simple(s) => Ok(Bytes::from(s.into_bytes())),
+            Frame::Bulk(data) => Ok(data),
+            _ => Err("protocol error; expected simple frame or bulk frame".into()),
+        }
+    }
+
+    /// 取出下一个参数并解析为有符号整数
+    pub(crate) fn next_int(&mut self) -> Result<i64, ParseError> {
+        use atoi::atoi;
+        const MSG: &str = "ERR value is not an integer or out of range";
+        match self.next()? {
+            Frame::Integer(v) => Ok(v),
+            Frame::Simple(s) => atoi::<i64>(s.as_bytes()).ok_or_else(|| MSG.into()),
+            Frame::Bulk(data) => atoi::<i64>(&data).ok_or_else(|| MSG.into()),
+            _ => Err("protocol error; expected int frame".into()),
+        }
+    }
+
+    /// 取出下一个参数并解析为浮点数，支持 `inf`、`+inf`、`-inf`，不接受 NaN
+    pub(crate) fn next_float(&mut self) -> Result<f64, ParseError> {
+        const MSG: &str = "ERR value is not a valid float";
+        let value = match self.next()? {
+            Frame::Integer(v) => v as f64,
+            Frame::Double(v) => v,
+            Frame::Simple(s) => s.parse().map_err(|_| MSG)?,
+            Frame::Bulk(data) => std::str::from_utf8(&data).ok().and_then(|s| s.parse().ok()).ok_or(MSG)?,
+            _ => return Err("protocol error; expected float frame".into()),
+        };
+        if f64::is_nan(value) {
+            return Err(MSG.into());
+        }
+        Ok(value)
+    }
+
+    /// 剩余未取出的参数个数
+    pub(crate) fn remaining(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// 是否还有未取出的参数
+    pub(crate) fn has_remaining(&self) -> bool {
+        self.remaining() > 0
+    }
+
+    /// 确认所有参数都已被取出
+    pub(crate) fn finish(&mut self) -> Result<(), ParseError> {
+        if self.parts.next().is_none() {
+            Ok(())
+        } else {
+            Err(ParseError::Trailing)
+        }
+    }
+}
+
+impl From<String> for ParseError {
+    fn from(src: String) -> ParseError {
+        ParseError::Other(src.into())
+    }
+}
+
+impl From<&str> for ParseError {
+    fn from(src: &str) -> ParseError {
+        src.to_string().into()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::EndOfStream => "protocol error; unexpected end of stream".fmt(f),
+            ParseError::Trailing => "protocol error; expected end of frame".fmt(f),
+            ParseError::Other(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::Frame;
+
+    use super::{Parse, ParseError};
+
+    #[test]
+    fn typed_arguments() {
+        let frame = Frame::array(["zadd", "1.5", "-inf", "nan", "x", "7"]);
+        let mut parse = Parse::new(frame).unwrap();
+        assert_eq!(parse.remaining(), 6);
+        assert_eq!(parse.next_string().unwrap(), "zadd");
+        assert_eq!(parse.next_float().unwrap(), 1.5);
+        assert_eq!(parse.next_float().unwrap(), f64::NEG_INFINITY);
+        assert_eq!(parse.next_float().unwrap_err().to_string(), "ERR value is not a valid float");
+        assert!(parse.next_int().is_err());
+        assert_eq!(parse.remaining(), 1);
+        assert!(matches!(parse.finish(), Err(ParseError::Trailing)));
+        assert!(matches!(parse.next_bytes(), Err(ParseError::EndOfStream)));
+        assert!(parse.finish().is_ok());
+    }
+}
@@ -0,0 +1,26 @@
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `PERSIST key`，移除 key 的过期时间。确实移除时返回 1，key 不存在或没有过期时间返回 0
+#[derive(Debug)]
+pub struct Persist {
+    key: Bytes,
+}
+
+impl Persist {
+    pub fn new(key: impl Into<Bytes>) -> Persist {
+        Persist { key: key.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Persist, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(Persist { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.persist(&self.key) as i64)
+    }
+}
@@ -0,0 +1,45 @@
+use bytes::Bytes;
+
+use crate::frame::Frame;
+
+use super::{Parse, ParseError};
+
+/// `PING [message]`，无参数时返回 PONG，否则原样返回 message
+#[derive(Debug, Default)]
+pub struct Ping {
+    msg: Option<Bytes>,
+}
+
+impl Ping {
+    pub fn new(msg: Option<Bytes>) -> Ping {
+        Ping { msg }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Ping, ParseError> {
+        if parse.has_remaining() {
+            Ok(Ping::new(Some(parse.next_bytes()?)))
+        } else {
+            Ok(Ping::default())
+        }
+    }
+
+    pub(crate) fn apply(self) -> Frame {
+        match self.msg {
+            None => Frame::Simple("PONG".into()),
+            Some(msg) => Frame::Bulk(msg),
+        }
+    }
+
+    /// 订阅模式下的回复为 `["pong", message]`，没有 message 时为空字符串
+    pub(crate) fn apply_subscribed(self) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("pong")),
+            Frame::Bulk(self.msg.unwrap_or_default()),
+        ])
+    }
+
+    /// 转换成发送给服务端的 frame，供客户端使用
+    pub(crate) fn into_frame(self) -> Frame {
+        Frame::array([Bytes::from("ping")].into_iter().chain(self.msg))
+    }
+}
@@ -0,0 +1,129 @@
+//! 发布订阅相关命令。订阅状态属于连接，见 [`crate::pubsub::Subscriber`]
+
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame, pubsub::{ChannelKind, Subscriber}};
+
+use super::{Parse, ParseError};
+
+/// `PUBLISH channel message`，返回收到消息的订阅者数量（包括模式订阅）
+///
+/// `SPUBLISH shardchannel message` 发布到分片频道，只有订阅了这个分片频道的连接能收到
+#[derive(Debug)]
+pub struct Publish {
+    channel: Bytes,
+    message: Bytes,
+    shard: bool,
+}
+
+impl Publish {
+    pub fn new(channel: impl Into<Bytes>, message: Bytes) -> Publish {
+        Publish { channel: channel.into(), message, shard: false }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, shard: bool) -> Result<Publish, ParseError> {
+        let channel = parse.next_bytes()?;
+        let message = parse.next_bytes()?;
+        Ok(Publish { channel, message, shard })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.shard { "spublish" } else { "publish" }
+    }
+
+    /// 是否发布到分片频道
+    pub fn is_shard(&self) -> bool {
+        self.shard
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let receivers = if self.shard {
+            db.spublish(&self.channel, self.message)
+        } else {
+            db.publish(&self.channel, self.message)
+        };
+        Frame::Integer(receivers as i64)
+    }
+
+    /// 转换成发送给服务端的 frame，供客户端使用
+    pub(crate) fn into_frame(self) -> Frame {
+        Frame::array([Bytes::from("publish"), self.channel, self.message])
+    }
+}
+
+/// `SUBSCRIBE channel [channel ...]` / `PSUBSCRIBE pattern [pattern ...]` / `SSUBSCRIBE shardchannel [shardchannel ...]`
+///
+/// 每个频道（模式）回复一条确认，之后订阅到的消息由连接的处理循环推送
+#[derive(Debug)]
+pub struct Subscribe {
+    channels: Vec<Bytes>,
+    kind: ChannelKind,
+}
+
+impl Subscribe {
+    pub(crate) fn parse_frames(parse: &mut Parse, kind: ChannelKind) -> Result<Subscribe, ParseError> {
+        // 至少需要一个频道
+        let mut channels = vec![parse.next_bytes()?];
+        while parse.has_remaining() {
+            channels.push(parse.next_bytes()?);
+        }
+        Ok(Subscribe { channels, kind })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self.kind {
+            ChannelKind::Channel => "subscribe",
+            ChannelKind::Pattern => "psubscribe",
+            ChannelKind::Shard => "ssubscribe",
+        }
+    }
+
+    pub fn kind(&self) -> ChannelKind {
+        self.kind
+    }
+
+    pub fn apply(self, subscriber: &mut Subscriber) -> Vec<Frame> {
+        match self.kind {
+            ChannelKind::Channel => subscriber.subscribe(self.channels),
+            ChannelKind::Pattern => subscriber.psubscribe(self.channels),
+            ChannelKind::Shard => subscriber.ssubscribe(self.channels),
+        }
+    }
+}
+
+/// `UNSUBSCRIBE [channel ...]` / `PUNSUBSCRIBE [pattern ...]` / `SUNSUBSCRIBE [shardchannel ...]`，不指定时取消这一种的所有订阅
+#[derive(Debug)]
+pub struct Unsubscribe {
+    channels: Vec<Bytes>,
+    kind: ChannelKind,
+}
+
+impl Unsubscribe {
+    pub(crate) fn parse_frames(parse: &mut Parse, kind: ChannelKind) -> Result<Unsubscribe, ParseError> {
+        let mut channels = vec![];
+        while parse.has_remaining() {
+            channels.push(parse.next_bytes()?);
+        }
+        Ok(Unsubscribe { channels, kind })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self.kind {
+            ChannelKind::Channel => "unsubscribe",
+            ChannelKind::Pattern => "punsubscribe",
+            ChannelKind::Shard => "sunsubscribe",
+        }
+    }
+
+    pub fn kind(&self) -> ChannelKind {
+        self.kind
+    }
+
+    pub fn apply(self, subscriber: &mut Subscriber) -> Vec<Frame> {
+        match self.kind {
+            ChannelKind::Channel => subscriber.unsubscribe(self.channels),
+            ChannelKind::Pattern => subscriber.punsubscribe(self.channels),
+            ChannelKind::Shard => subscriber.sunsubscribe(self.channels),
+        }
+    }
+}
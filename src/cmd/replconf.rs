@@ -0,0 +1,85 @@
+//! `REPLCONF`，副本与主节点之间交换复制相关的信息，见 [`crate::replication`]
+
+use crate::{db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `REPLCONF option value [option value ...]`
+///
+/// - `ACK offset`：副本报告已经处理到的复制偏移量，不回复。发送过 ACK 的连接被当作副本；
+/// - `listening-port`、`ip-address`、`capa`：副本握手时发送的信息，只回复 OK。
+#[derive(Debug, PartialEq)]
+pub enum ReplConf {
+    Ack(u64),
+    Options,
+}
+
+impl ReplConf {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ReplConf, ParseError> {
+        // 与 redis 一样，选项与值必须成对出现
+        if !parse.remaining().is_multiple_of(2) {
+            return Err("ERR syntax error".into());
+        }
+        let mut ack = None;
+        while parse.has_remaining() {
+            let option = parse.next_string()?.to_lowercase();
+            match option.as_str() {
+                "ack" => {
+                    let offset = u64::try_from(parse.next_int()?).map_err(|_| "ERR value is out of range")?;
+                    ack.get_or_insert(offset);
+                },
+                // 之后的 FACK 是 AOF 的偏移量，toyredis 没有 AOF，忽略
+                "fack" if ack.is_some() => {
+                    parse.next_int()?;
+                },
+                "listening-port" | "ip-address" | "capa" => {
+                    parse.next_bytes()?;
+                },
+                _ => return Err(format!("ERR Unrecognized REPLCONF option: {}", option).into()),
+            }
+        }
+        Ok(ack.map_or(ReplConf::Options, ReplConf::Ack))
+    }
+
+    /// client 为发送命令的连接的 id。ACK 不需要回复，返回 `None`
+    pub fn apply(self, db: &Db, client: u64) -> Option<Frame> {
+        match self {
+            ReplConf::Ack(offset) => {
+                db.replication().ack(client, offset);
+                None
+            },
+            ReplConf::Options => Some(Frame::Simple("OK".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{cmd::Command, db::Db, frame::Frame};
+
+    use super::ReplConf;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+        Command::from_frame(frame).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn ack() {
+        let db = Db::new();
+        let Ok(Command::ReplConf(cmd)) = parse(&["replconf", "ACK", "42", "FACK", "0"]) else { panic!() };
+        assert_eq!(cmd, ReplConf::Ack(42));
+        assert_eq!(cmd.apply(&db, 7), None);
+        assert_eq!((db.replication().replicas(), db.replication().acked(42)), (1, 1));
+
+        let Ok(Command::ReplConf(cmd)) = parse(&["replconf", "listening-port", "6380", "capa", "psync2"]) else { panic!() };
+        assert_eq!(cmd.apply(&db, 8), Some(Frame::Simple("OK".into())));
+        assert_eq!(db.replication().replicas(), 1);
+
+        assert_eq!(parse(&["replconf", "ack"]).unwrap_err(), "ERR syntax error");
+        assert_eq!(parse(&["replconf", "ack", "-1"]).unwrap_err(), "ERR value is out of range");
+        assert_eq!(parse(&["replconf", "foo", "1"]).unwrap_err(), "ERR Unrecognized REPLCONF option: foo");
+    }
+}
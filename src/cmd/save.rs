@@ -0,0 +1,47 @@
+//! 快照相关命令，快照格式见 [`crate::rdb`]
+
+use crate::{db::Db, frame::Frame, rdb};
+
+use super::{Parse, ParseError};
+
+/// `SAVE`，在当前连接上同步保存快照，完成后回复 OK
+#[derive(Debug, Default)]
+pub struct Save;
+
+impl Save {
+    pub fn new() -> Save {
+        Save
+    }
+
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Save, ParseError> {
+        Ok(Save)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match rdb::save(db) {
+            Ok(()) => Frame::Simple("OK".into()),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+}
+
+/// `BGSAVE`，复制一份当前的数据后立即回复，由后台任务写入快照文件
+#[derive(Debug, Default)]
+pub struct BgSave;
+
+impl BgSave {
+    pub fn new() -> BgSave {
+        BgSave
+    }
+
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<BgSave, ParseError> {
+        Ok(BgSave)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match rdb::bgsave(db) {
+            Ok(()) => Frame::Simple("Background saving started".into()),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+}
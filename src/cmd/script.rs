@@ -0,0 +1,152 @@
+//! 脚本相关命令，脚本的编译与执行见 [`crate::script`]
+
+use bytes::Bytes;
+
+use crate::{db::Db, frame::{Frame, Protocol}, script::Host};
+
+use super::{Command, Parse, ParseError};
+
+/// `EVAL script numkeys [key ...] [arg ...]` / `EVALSHA sha1 numkeys [key ...] [arg ...]`
+///
+/// 执行脚本，脚本中可以通过 `KEYS`、`ARGV` 访问参数。EVAL 执行的脚本也会被缓存，之后可以用 EVALSHA 执行。
+/// 脚本执行期间独占数据库，见 [`Command::apply`]
+#[derive(Debug)]
+pub struct Eval {
+    /// EVAL 为源码，EVALSHA 为源码的 SHA1
+    script: Bytes,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+    sha: bool,
+}
+
+impl Eval {
+    pub fn new(script: impl Into<Bytes>, keys: Vec<Bytes>, args: Vec<Bytes>) -> Eval {
+        Eval { script: script.into(), keys, args, sha: false }
+    }
+
+    /// `EVALSHA`
+    pub fn sha(sha: impl Into<Bytes>, keys: Vec<Bytes>, args: Vec<Bytes>) -> Eval {
+        Eval { script: sha.into(), keys, args, sha: true }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, sha: bool) -> Result<Eval, ParseError> {
+        let script = parse.next_bytes()?;
+        let numkeys = parse.next_int()?;
+        if numkeys < 0 {
+            return Err("ERR Number of keys can't be negative".into());
+        }
+        let mut keys = vec![];
+        for _ in 0..numkeys {
+            match parse.next_bytes() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => return Err("ERR Number of keys can't be greater than number of args".into()),
+                Err(err) => return Err(err),
+            }
+        }
+        let mut args = vec![];
+        while parse.has_remaining() {
+            args.push(parse.next_bytes()?);
+        }
+        Ok(Eval { script, keys, args, sha })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.sha { "evalsha" } else { "eval" }
+    }
+
+    /// 执行脚本，调用方需要保证期间不会执行其他命令
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let program = if self.sha {
+            match db.script(&String::from_utf8_lossy(&self.script)) {
+                Some(program) => program,
+                None => return Frame::Error("NOSCRIPT No matching script. Please use EVAL.".into()),
+            }
+        } else {
+            match db.load_script(&self.script) {
+                Ok((_, program)) => program,
+                Err(err) => return Frame::Error(format!("ERR Error compiling script: {}", err)),
+            }
+        };
+        program.run(&self.keys, &self.args, &mut DbHost { db })
+    }
+}
+
+/// 脚本中的 `redis.call` 在数据库上直接执行命令
+struct DbHost<'a> {
+    db: &'a Db,
+}
+
+impl Host for DbHost<'_> {
+    fn call(&mut self, args: Vec<Bytes>) -> Frame {
+        let cmd = match Command::from_frame(Frame::Array(args.into_iter().map(Frame::Bulk).collect())) {
+            Ok(cmd) => cmd,
+            Err(err) => return Frame::Error(err.to_string()),
+        };
+        // 脚本中不能嵌套执行脚本，也不能执行依赖连接状态的命令
+        if matches!(
+            cmd,
+            Command::Eval(_) | Command::Script(_) | Command::Multi(_) | Command::Exec(_) | Command::Discard(_)
+                | Command::Watch(_) | Command::Unwatch(_) | Command::Subscribe(_) | Command::Unsubscribe(_)
+                | Command::Hello(_) | Command::Client(_) | Command::Auth(_) | Command::Acl(_) | Command::Shutdown(_)
+        ) {
+            return Frame::Error("ERR This Redis command is not allowed from script".into());
+        }
+        if !matches!(cmd, Command::Unknown(_)) {
+            self.db.stats().record_command(cmd.get_name());
+        }
+        // 回复统一按 RESP2 转换为脚本中的值
+        cmd.execute(self.db, &mut Protocol::Resp2)
+    }
+}
+
+/// `SCRIPT <subcommand>`，管理脚本缓存
+#[derive(Debug)]
+pub enum Script {
+    /// `SCRIPT LOAD script`，编译并缓存脚本，返回其 SHA1
+    Load(Bytes),
+    /// `SCRIPT EXISTS sha1 [sha1 ...]`，各个脚本是否在缓存中
+    Exists(Vec<Bytes>),
+    /// `SCRIPT FLUSH [ASYNC | SYNC]`，清除所有缓存的脚本
+    Flush,
+}
+
+impl Script {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Script, ParseError> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_lowercase().as_str() {
+            "load" => Ok(Script::Load(parse.next_bytes()?)),
+            "exists" => {
+                let mut shas = vec![parse.next_bytes()?];
+                while parse.has_remaining() {
+                    shas.push(parse.next_bytes()?);
+                }
+                Ok(Script::Exists(shas))
+            },
+            "flush" => {
+                // 缓存只在内存中，同步与异步没有区别
+                if parse.has_remaining() && !matches!(&parse.next_string()?.to_uppercase()[..], "ASYNC" | "SYNC") {
+                    return Err("ERR SCRIPT FLUSH only support SYNC|ASYNC option".into());
+                }
+                Ok(Script::Flush)
+            },
+            _ => Err(format!("ERR unknown subcommand '{}'. Try SCRIPT HELP.", subcommand).into()),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self {
+            Script::Load(source) => match db.load_script(&source) {
+                Ok((sha, _)) => Frame::Bulk(Bytes::from(sha)),
+                Err(err) => Frame::Error(format!("ERR Error compiling script: {}", err)),
+            },
+            Script::Exists(shas) => Frame::Array(shas
+                .iter()
+                .map(|sha| Frame::Integer(db.script(&String::from_utf8_lossy(sha)).is_some() as i64))
+                .collect()),
+            Script::Flush => {
+                db.flush_scripts();
+                Frame::Simple("OK".into())
+            },
+        }
+    }
+}
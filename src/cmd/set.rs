@@ -0,0 +1,234 @@
+use bytes::Bytes;
+
+use crate::{connection::Reply, db::{Db, now_ms}, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `SET key value [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds]`
+///
+/// 设置 key 的值，已存在则覆盖。不带过期选项时，key 原有的过期时间会被清除
+#[derive(Debug)]
+pub struct Set {
+    key: Bytes,
+    value: Bytes,
+    expire: Option<Expiration>,
+}
+
+/// SET 的过期选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiration {
+    /// `EX seconds`，相对时间（秒）
+    Seconds(u64),
+    /// `PX milliseconds`，相对时间（毫秒）
+    Milliseconds(u64),
+    /// `EXAT unix-time-seconds`，绝对时间（秒）
+    UnixSeconds(u64),
+    /// `PXAT unix-time-milliseconds`，绝对时间（毫秒）
+    UnixMilliseconds(u64),
+}
+
+impl Expiration {
+    /// 换算成过期的 unix 时间戳（毫秒）
+    pub fn to_unix_ms(self, now: u64) -> u64 {
+        match self {
+            Expiration::Seconds(s) => now.saturating_add(s.saturating_mul(1000)),
+            Expiration::Milliseconds(ms) => now.saturating_add(ms),
+            Expiration::UnixSeconds(s) => s.saturating_mul(1000),
+            Expiration::UnixMilliseconds(ms) => ms,
+        }
+    }
+
+    /// 解析 `EX`、`PX`、`EXAT`、`PXAT` 选项及其后的时间，option 为已经读出的选项名（大写），
+    /// 不是过期选项时返回 `None`。command 为命令名，用于错误信息
+    pub(crate) fn parse_option(option: &str, parse: &mut Parse, command: &str) -> Result<Option<Expiration>, ParseError> {
+        let make: fn(u64) -> Expiration = match option {
+            "EX" => Expiration::Seconds,
+            "PX" => Expiration::Milliseconds,
+            "EXAT" => Expiration::UnixSeconds,
+            "PXAT" => Expiration::UnixMilliseconds,
+            _ => return Ok(None),
+        };
+        // 缺少过期时间时，redis 同样回复 syntax error
+        let time = parse.next_int().map_err(|err| match err {
+            ParseError::EndOfStream => "ERR syntax error".into(),
+            err => err,
+        })?;
+        if time <= 0 {
+            return Err(format!("ERR invalid expire time in '{}' command", command).into());
+        }
+        Ok(Some(make(time as u64)))
+    }
+
+    fn option_name(self) -> &'static str {
+        match self {
+            Expiration::Seconds(_) => "EX",
+            Expiration::Milliseconds(_) => "PX",
+            Expiration::UnixSeconds(_) => "EXAT",
+            Expiration::UnixMilliseconds(_) => "PXAT",
+        }
+    }
+
+    fn value(self) -> u64 {
+        match self {
+            Expiration::Seconds(v)
+            | Expiration::Milliseconds(v)
+            | Expiration::UnixSeconds(v)
+            | Expiration::UnixMilliseconds(v) => v,
+        }
+    }
+}
+
+impl Set {
+    pub fn new(key: impl Into<Bytes>, value: Bytes, expire: Option<Expiration>) -> Set {
+        Set { key: key.into(), value, expire }
+    }
+
+    pub fn key(&self) -> &Bytes {
+        &self.key
+    }
+
+    pub fn value(&self) -> &Bytes {
+        &self.value
+    }
+
+    pub fn expire(&self) -> Option<Expiration> {
+        self.expire
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Set, ParseError> {
+        let key = parse.next_bytes()?;
+        let value = parse.next_bytes()?;
+        let mut expire = None;
+        while parse.has_remaining() {
+            let option = parse.next_string()?.to_uppercase();
+            // 过期选项只能出现一次
+            if expire.is_some() {
+                return Err("ERR syntax error".into());
+            }
+            match Expiration::parse_option(&option, parse, "set")? {
+                Some(expiration) => expire = Some(expiration),
+                None => return Err("ERR syntax error".into()),
+            }
+        }
+        Ok(Set { key, value, expire })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let expire_at = self.expire.map(|expire| expire.to_unix_ms(now_ms()));
+        db.set(self.key, self.value, expire_at);
+        Frame::Simple("OK".into())
+    }
+
+    /// 同 [`Set::apply`]，直接回复 `+OK`
+    pub(crate) fn reply(self, db: &Db, reply: Reply) {
+        let expire_at = self.expire.map(|expire| expire.to_unix_ms(now_ms()));
+        db.set(self.key, self.value, expire_at);
+        reply.ok()
+    }
+
+    /// 转换成发送给服务端的 frame，供客户端使用
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::from("set"), self.key.into(), self.value.into()];
+        if let Some(expire) = self.expire {
+            frames.push(expire.option_name().into());
+            frames.push(expire.value().to_string().into());
+        }
+        Frame::Array(frames)
+    }
+}
+
+/// `GETSET key value`
+///
+/// 设置 key 的值并返回原来的值，key 不存在时返回 Null。与 SET 一样会清除原有的过期时间
+#[derive(Debug)]
+pub struct GetSet {
+    key: Bytes,
+    value: Bytes,
+}
+
+impl GetSet {
+    pub fn new(key: impl Into<Bytes>, value: Bytes) -> GetSet {
+        GetSet { key: key.into(), value }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetSet, ParseError> {
+        let key = parse.next_bytes()?;
+        let value = parse.next_bytes()?;
+        Ok(GetSet { key, value })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.getset(self.key, self.value) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+}
+
+/// `MSET key value [key value ...]` / `MSETNX key value [key value ...]`
+///
+/// 原子地设置多个 key 的值。MSETNX 只在所有 key 都不存在时才设置，返回是否设置成功
+#[derive(Debug)]
+pub struct MSet {
+    pairs: Vec<(Bytes, Bytes)>,
+    nx: bool,
+}
+
+impl MSet {
+    pub fn new(pairs: Vec<(Bytes, Bytes)>) -> MSet {
+        MSet { pairs, nx: false }
+    }
+
+    /// `MSETNX`
+    pub fn nx(pairs: Vec<(Bytes, Bytes)>) -> MSet {
+        MSet { pairs, nx: true }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, nx: bool) -> Result<MSet, ParseError> {
+        let mut pairs = Vec::with_capacity(parse.remaining() / 2);
+        pairs.push((parse.next_bytes()?, parse.next_bytes()?));
+        while parse.has_remaining() {
+            pairs.push((parse.next_bytes()?, parse.next_bytes()?));
+        }
+        Ok(MSet { pairs, nx })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.nx { "msetnx" } else { "mset" }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let set = db.mset(self.pairs, self.nx);
+        if self.nx {
+            Frame::Integer(set as i64)
+        } else {
+            Frame::Simple("OK".into())
+        }
+    }
+}
+
+/// `SETNX key value`
+///
+/// key 不存在时才设置，返回是否设置成功
+#[derive(Debug)]
+pub struct SetNx {
+    key: Bytes,
+    value: Bytes,
+}
+
+impl SetNx {
+    pub fn new(key: impl Into<Bytes>, value: Bytes) -> SetNx {
+        SetNx { key: key.into(), value }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SetNx, ParseError> {
+        let key = parse.next_bytes()?;
+        let value = parse.next_bytes()?;
+        Ok(SetNx { key, value })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.mset(vec![(self.key, self.value)], true) as i64)
+    }
+}
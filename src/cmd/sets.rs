@@ -0,0 +1,583 @@
+//! 集合相关命令，数据保存在 [`Set`] 中。
+//! 文件名避免与字符串的 `SET` 命令（[`super::set`]）混淆
+
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use crate::{db::{Db, WrongType}, frame::Frame, glob, object::Typed, types::Set};
+
+use super::{Parse, ParseError, keyspace::{ScanOptions, scan_reply}};
+
+/// `SADD key member [member ...]`
+///
+/// 加入元素，返回新增的元素数量
+#[derive(Debug)]
+pub struct SAdd {
+    key: Bytes,
+    members: Vec<Bytes>,
+}
+
+impl SAdd {
+    pub fn new(key: impl Into<Bytes>, members: Vec<Bytes>) -> SAdd {
+        SAdd { key: key.into(), members }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SAdd, ParseError> {
+        let key = parse.next_bytes()?;
+        let members = parse_non_empty(parse)?;
+        Ok(SAdd { key, members })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let limits = db.encoding_limits().set;
+        db.update_typed(&self.key, |value| {
+            let set = value.get_or_insert_with(Set::new);
+            let added = self.members
+                .iter()
+                .filter(|member| set.insert(member, &limits))
+                .count();
+            Frame::Integer(added as i64)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `SREM key member [member ...]`
+///
+/// 删除元素，返回实际删除的数量。集合为空时 key 也会被删除
+#[derive(Debug)]
+pub struct SRem {
+    key: Bytes,
+    members: Vec<Bytes>,
+}
+
+impl SRem {
+    pub fn new(key: impl Into<Bytes>, members: Vec<Bytes>) -> SRem {
+        SRem { key: key.into(), members }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SRem, ParseError> {
+        let key = parse.next_bytes()?;
+        let members = parse_non_empty(parse)?;
+        Ok(SRem { key, members })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update_typed(&self.key, |value: &mut Option<Set>| {
+            let Some(set) = value else {
+                return Frame::Integer(0);
+            };
+            let removed = self.members
+                .iter()
+                .filter(|member| set.remove(member))
+                .count();
+            if set.is_empty() {
+                *value = None;
+            }
+            Frame::Integer(removed as i64)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `SMEMBERS key`，返回所有的元素，顺序不确定
+#[derive(Debug)]
+pub struct SMembers {
+    key: Bytes,
+}
+
+impl SMembers {
+    pub fn new(key: impl Into<Bytes>) -> SMembers {
+        SMembers { key: key.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SMembers, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(SMembers { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_set(db, &self.key, |set| {
+            let members = set.map_or_else(Vec::new, |set| set.members());
+            Frame::Array(members.into_iter().map(Frame::Bulk).collect())
+        })
+    }
+}
+
+/// `SSCAN key cursor [MATCH pattern] [COUNT count]`
+///
+/// 增量遍历集合，回复 `[下一次的 cursor, [member ...]]`，语义同 `SCAN`。
+/// intset 编码时一次返回全部
+#[derive(Debug)]
+pub struct SScan {
+    key: Bytes,
+    options: ScanOptions,
+}
+
+impl SScan {
+    pub fn new(key: impl Into<Bytes>, cursor: u64) -> SScan {
+        SScan { key: key.into(), options: ScanOptions::new(cursor) }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SScan, ParseError> {
+        let key = parse.next_bytes()?;
+        let options = ScanOptions::parse(parse, |_, _| Ok(false))?;
+        Ok(SScan { key, options })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let ScanOptions { cursor, pattern, count } = self.options;
+        with_set(db, &self.key, |set| {
+            let (cursor, members) = match set {
+                Some(set) => set.scan(cursor, count, |member| {
+                    pattern.as_ref().is_none_or(|pattern| glob::matches(pattern, member))
+                }),
+                None => (0, vec![]),
+            };
+            scan_reply(cursor, members.into_iter().map(Frame::Bulk).collect())
+        })
+    }
+}
+
+/// `SISMEMBER key member`，member 在集合中时返回 1，否则返回 0
+#[derive(Debug)]
+pub struct SIsMember {
+    key: Bytes,
+    member: Bytes,
+}
+
+impl SIsMember {
+    pub fn new(key: impl Into<Bytes>, member: impl Into<Bytes>) -> SIsMember {
+        SIsMember { key: key.into(), member: member.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SIsMember, ParseError> {
+        let key = parse.next_bytes()?;
+        let member = parse.next_bytes()?;
+        Ok(SIsMember { key, member })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_set(db, &self.key, |set| {
+            let exists = set.is_some_and(|set| set.contains(&self.member));
+            Frame::Integer(exists as i64)
+        })
+    }
+}
+
+/// `SCARD key`，返回元素个数，key 不存在时为 0
+#[derive(Debug)]
+pub struct SCard {
+    key: Bytes,
+}
+
+impl SCard {
+    pub fn new(key: impl Into<Bytes>) -> SCard {
+        SCard { key: key.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SCard, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(SCard { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_set(db, &self.key, |set| {
+            Frame::Integer(set.map_or(0, |set| set.len()) as i64)
+        })
+    }
+}
+
+/// `SPOP key [count]`
+///
+/// 随机删除元素并返回。不带 count 时返回单个元素，key 不存在时返回 nil；带 count 时返回数组。
+/// 集合为空后 key 会被删除
+#[derive(Debug)]
+pub struct SPop {
+    key: Bytes,
+    count: Option<usize>,
+}
+
+impl SPop {
+    pub fn new(key: impl Into<Bytes>, count: Option<usize>) -> SPop {
+        SPop { key: key.into(), count }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SPop, ParseError> {
+        let key = parse.next_bytes()?;
+        let count = if parse.has_remaining() {
+            match parse.next_int()? {
+                count if count < 0 => return Err("ERR value is out of range, must be positive".into()),
+                count => Some(count as usize),
+            }
+        } else {
+            None
+        };
+        Ok(SPop { key, count })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update_typed(&self.key, |value: &mut Option<Set>| {
+            let set = match value {
+                Some(set) => set,
+                None if self.count.is_some() => return Frame::Array(vec![]),
+                None => return Frame::Null,
+            };
+            let mut members = set.pop_random(self.count.unwrap_or(1));
+            if set.is_empty() {
+                *value = None;
+            }
+            match self.count {
+                None => members.pop().map_or(Frame::Null, Frame::Bulk),
+                Some(_) => Frame::Array(members.into_iter().map(Frame::Bulk).collect()),
+            }
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `SRANDMEMBER key [count]`
+///
+/// 随机返回元素，不修改集合。不带 count 时返回单个元素，key 不存在时返回 nil；带 count 时返回数组，
+/// count 为正数时元素互不相同，为负数时可能重复
+#[derive(Debug)]
+pub struct SRandMember {
+    key: Bytes,
+    count: Option<i64>,
+}
+
+impl SRandMember {
+    pub fn new(key: impl Into<Bytes>, count: Option<i64>) -> SRandMember {
+        SRandMember { key: key.into(), count }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SRandMember, ParseError> {
+        let key = parse.next_bytes()?;
+        let count = if parse.has_remaining() {
+            match parse.next_int()? {
+                // 与 redis 一样限制负数的范围
+                count if count < -(i64::MAX / 2) => return Err("ERR value is out of range".into()),
+                count => Some(count),
+            }
+        } else {
+            None
+        };
+        Ok(SRandMember { key, count })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_set(db, &self.key, |set| match self.count {
+            None => set.and_then(|set| set.random_members(1).pop()).map_or(Frame::Null, Frame::Bulk),
+            Some(count) => {
+                let members = set.map_or_else(Vec::new, |set| set.random_members(count));
+                Frame::Array(members.into_iter().map(Frame::Bulk).collect())
+            },
+        })
+    }
+}
+
+/// `SINTERCARD numkeys key [key ...] [LIMIT limit]`
+///
+/// 交集的元素个数，不返回元素本身。limit 不为 0 时数到 limit 个就停止
+#[derive(Debug)]
+pub struct SInterCard {
+    keys: Vec<Bytes>,
+    limit: usize,
+}
+
+impl SInterCard {
+    pub fn new(keys: Vec<Bytes>, limit: usize) -> SInterCard {
+        SInterCard { keys, limit }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SInterCard, ParseError> {
+        let numkeys = parse.next_int()?;
+        if numkeys <= 0 {
+            return Err("ERR numkeys should be greater than 0".into());
+        }
+        if numkeys as usize > parse.remaining() {
+            return Err("ERR Number of keys can't be greater than number of args".into());
+        }
+        let mut keys = Vec::with_capacity(numkeys as usize);
+        for _ in 0..numkeys {
+            keys.push(parse.next_bytes()?);
+        }
+        let mut limit = 0;
+        while parse.has_remaining() {
+            if !parse.next_string()?.eq_ignore_ascii_case("limit") {
+                return Err("ERR syntax error".into());
+            }
+            limit = match parse.next_int().map_err(|err| match err {
+                ParseError::EndOfStream => "ERR syntax error".into(),
+                err => err,
+            })? {
+                limit if limit < 0 => return Err("ERR LIMIT can't be negative".into()),
+                limit => limit as usize,
+            };
+        }
+        Ok(SInterCard { keys, limit })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match inter_card(db, &self.keys, self.limit) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => Frame::Error(err.to_string()),
+        }
+    }
+}
+
+/// 集合运算的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// `SINTER key [key ...]` / `SUNION key [key ...]` / `SDIFF key [key ...]`，
+/// 以及把结果保存到 destination 的 `SINTERSTORE destination key [key ...]` 等。
+///
+/// 不存在的 key 视为空集合。不保存时返回结果中的所有元素，顺序不确定；
+/// 保存时 destination 原有的值（以及过期时间）被覆盖，结果为空时删除 destination，返回结果的元素个数
+#[derive(Debug)]
+pub struct SetAlgebra {
+    op: SetOp,
+    keys: Vec<Bytes>,
+    destination: Option<Bytes>,
+}
+
+impl SetAlgebra {
+    pub fn new(op: SetOp, keys: Vec<Bytes>) -> SetAlgebra {
+        SetAlgebra { op, keys, destination: None }
+    }
+
+    /// 把结果保存到 destination，即 `*STORE` 形式
+    pub fn store(mut self, destination: impl Into<Bytes>) -> SetAlgebra {
+        self.destination = Some(destination.into());
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, op: SetOp, store: bool) -> Result<SetAlgebra, ParseError> {
+        let destination = match store {
+            true => Some(parse.next_bytes()?),
+            false => None,
+        };
+        let keys = parse_non_empty(parse)?;
+        Ok(SetAlgebra { op, keys, destination })
+    }
+
+    /// 是否为 `*STORE` 形式
+    pub(crate) fn is_store(&self) -> bool {
+        self.destination.is_some()
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match (self.op, self.destination.is_some()) {
+            (SetOp::Inter, false) => "sinter",
+            (SetOp::Inter, true) => "sinterstore",
+            (SetOp::Union, false) => "sunion",
+            (SetOp::Union, true) => "sunionstore",
+            (SetOp::Diff, false) => "sdiff",
+            (SetOp::Diff, true) => "sdiffstore",
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let members = match self.op {
+            SetOp::Inter => inter(db, &self.keys),
+            SetOp::Union => union(db, &self.keys),
+            SetOp::Diff => diff(db, &self.keys),
+        };
+        let members = match members {
+            Ok(members) => members,
+            Err(err) => return Frame::Error(err.to_string()),
+        };
+        let destination = match self.destination {
+            Some(destination) => destination,
+            None => return Frame::Array(members.into_iter().map(Frame::Bulk).collect()),
+        };
+        let len = members.len();
+        if members.is_empty() {
+            db.del(&destination);
+        } else {
+            let limits = db.encoding_limits().set;
+            let mut set = Set::new();
+            for member in members {
+                set.insert(&member, &limits);
+            }
+            db.insert(destination, set.into_object(), None);
+        }
+        Frame::Integer(len as i64)
+    }
+}
+
+/// 交集。先检查所有 key 的类型，再从最小的集合出发，依次用其他集合过滤，
+/// 已经为空时不再访问剩下的集合
+fn inter(db: &Db, keys: &[Bytes]) -> Result<Vec<Bytes>, WrongType> {
+    let sets = by_len(db, keys)?;
+    let mut members = match sets.first() {
+        Some((len, key)) if *len > 0 => set_members(db, key)?,
+        _ => return Ok(vec![]),
+    };
+    for (_, key) in &sets[1..] {
+        if members.is_empty() {
+            break;
+        }
+        retain(db, key, &mut members, true)?;
+    }
+    Ok(members)
+}
+
+/// 交集的元素个数，limit 不为 0 时数到 limit 个就停止。
+/// 过程与 [`inter`] 相同，只是最后一个集合只用来计数，不再收集元素
+fn inter_card(db: &Db, keys: &[Bytes], limit: usize) -> Result<usize, WrongType> {
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    let sets = by_len(db, keys)?;
+    let mut members = match sets.first() {
+        Some((len, key)) if *len > 0 => set_members(db, key)?,
+        _ => return Ok(0),
+    };
+    let Some(((_, last), middle)) = sets[1..].split_last() else {
+        return Ok(members.len().min(limit));
+    };
+    for (_, key) in middle {
+        if members.is_empty() {
+            return Ok(0);
+        }
+        retain(db, key, &mut members, true)?;
+    }
+    read_set(db, last, |set| match set {
+        Some(set) => members.iter().filter(|member| set.contains(member)).take(limit).count(),
+        None => 0,
+    })
+}
+
+/// 检查所有 key 的类型，按集合的元素个数从小到大排序
+fn by_len<'a>(db: &Db, keys: &'a [Bytes]) -> Result<Vec<(usize, &'a Bytes)>, WrongType> {
+    let mut sets = Vec::with_capacity(keys.len());
+    for key in keys {
+        let len = read_set(db, key, |set| set.map_or(0, |set| set.len()))?;
+        sets.push((len, key));
+    }
+    sets.sort_by_key(|(len, _)| *len);
+    Ok(sets)
+}
+
+fn union(db: &Db, keys: &[Bytes]) -> Result<Vec<Bytes>, WrongType> {
+    let mut members = HashSet::new();
+    for key in keys {
+        members.extend(set_members(db, key)?);
+    }
+    Ok(members.into_iter().collect())
+}
+
+/// 差集：第一个集合中不在其他任何集合中的元素
+fn diff(db: &Db, keys: &[Bytes]) -> Result<Vec<Bytes>, WrongType> {
+    let mut members = set_members(db, &keys[0])?;
+    for key in &keys[1..] {
+        retain(db, key, &mut members, false)?;
+    }
+    Ok(members)
+}
+
+/// key 对应集合的所有元素，key 不存在时为空
+fn set_members(db: &Db, key: &[u8]) -> Result<Vec<Bytes>, WrongType> {
+    read_set(db, key, |set| set.map_or_else(Vec::new, |set| set.members()))
+}
+
+/// 只保留在（`contained` 为假时：不在）key 对应集合中的元素
+fn retain(db: &Db, key: &[u8], members: &mut Vec<Bytes>, contained: bool) -> Result<(), WrongType> {
+    read_set(db, key, |set| match set {
+        Some(set) => members.retain(|member| set.contains(member) == contained),
+        None if contained => members.clear(),
+        None => {},
+    })
+}
+
+/// 剩余的所有参数，至少一个
+fn parse_non_empty(parse: &mut Parse) -> Result<Vec<Bytes>, ParseError> {
+    let mut members = vec![parse.next_bytes()?];
+    while parse.has_remaining() {
+        members.push(parse.next_bytes()?);
+    }
+    Ok(members)
+}
+
+/// 访问 key 对应的集合，key 不存在时 f 收到 `None`，类型不符时回复 WRONGTYPE 错误
+fn with_set(db: &Db, key: &[u8], f: impl FnOnce(Option<&mut Set>) -> Frame) -> Frame {
+    read_set(db, key, f).unwrap_or_else(Frame::from)
+}
+
+/// 同 [`with_set`]，类型不符时返回 `Err`。
+/// Dict 查找时会顺带做一步 rehash，所以需要可变引用
+fn read_set<R>(db: &Db, key: &[u8], f: impl FnOnce(Option<&mut Set>) -> R) -> Result<R, WrongType> {
+    db.with_typed(key, f)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{db::Db, frame::Frame, object::ObjectEncoding};
+
+    use super::{SAdd, SInterCard, SMembers, SetAlgebra, SetOp};
+
+    fn keys(keys: &[&'static str]) -> Vec<Bytes> {
+        keys.iter().map(|key| Bytes::from(*key)).collect()
+    }
+
+    fn sorted(frame: Frame) -> Vec<Frame> {
+        match frame {
+            Frame::Array(mut members) => {
+                members.sort_by_key(|member| format!("{:?}", member));
+                members
+            },
+            frame => panic!("unexpected reply {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn algebra() {
+        let db = Db::new();
+        SAdd::new("a", keys(&["1", "2", "3", "x"])).apply(&db);
+        SAdd::new("b", keys(&["2", "3", "4"])).apply(&db);
+        SAdd::new("c", keys(&["3", "x"])).apply(&db);
+        let bulks = |members: &[&'static str]| members.iter().map(|m| Frame::Bulk(Bytes::from(*m))).collect::<Vec<_>>();
+
+        assert_eq!(sorted(SetAlgebra::new(SetOp::Inter, keys(&["a", "b", "c"])).apply(&db)), bulks(&["3"]));
+        assert_eq!(sorted(SetAlgebra::new(SetOp::Inter, keys(&["a", "missing"])).apply(&db)), bulks(&[]));
+        assert_eq!(sorted(SetAlgebra::new(SetOp::Union, keys(&["b", "c", "missing"])).apply(&db)), bulks(&["2", "3", "4", "x"]));
+        assert_eq!(sorted(SetAlgebra::new(SetOp::Diff, keys(&["a", "b", "missing"])).apply(&db)), bulks(&["1", "x"]));
+
+        assert_eq!(SetAlgebra::new(SetOp::Inter, keys(&["a", "b"])).store("d").apply(&db), Frame::Integer(2));
+        assert_eq!(sorted(SMembers::new("d").apply(&db)), bulks(&["2", "3"]));
+        assert_eq!(db.lookup_read(b"d", |value| value.unwrap().encoding()), ObjectEncoding::IntSet);
+        assert_eq!(SetAlgebra::new(SetOp::Diff, keys(&["c", "a"])).store("d").apply(&db), Frame::Integer(0));
+        assert!(!db.exists(b"d"));
+
+        db.set(Bytes::from("str"), Bytes::from("v"), None);
+        // 即使交集已经确定为空，也要检查所有 key 的类型
+        assert!(matches!(SetAlgebra::new(SetOp::Inter, keys(&["missing", "str"])).apply(&db), Frame::Error(_)));
+        assert!(matches!(SetAlgebra::new(SetOp::Union, keys(&["a", "str"])).store("d").apply(&db), Frame::Error(_)));
+        assert!(!db.exists(b"d"));
+    }
+
+    #[test]
+    fn inter_card() {
+        let db = Db::new();
+        SAdd::new("a", keys(&["1", "2", "3", "4", "x"])).apply(&db);
+        SAdd::new("b", keys(&["2", "3", "4", "5"])).apply(&db);
+        SAdd::new("c", keys(&["3", "4", "x"])).apply(&db);
+        assert_eq!(SInterCard::new(keys(&["a"]), 0).apply(&db), Frame::Integer(5));
+        assert_eq!(SInterCard::new(keys(&["a"]), 2).apply(&db), Frame::Integer(2));
+        assert_eq!(SInterCard::new(keys(&["a", "b"]), 0).apply(&db), Frame::Integer(3));
+        assert_eq!(SInterCard::new(keys(&["a", "b", "c"]), 0).apply(&db), Frame::Integer(2));
+        assert_eq!(SInterCard::new(keys(&["a", "b", "c"]), 1).apply(&db), Frame::Integer(1));
+        assert_eq!(SInterCard::new(keys(&["a", "missing"]), 0).apply(&db), Frame::Integer(0));
+
+        db.set(Bytes::from("str"), Bytes::from("v"), None);
+        assert!(matches!(SInterCard::new(keys(&["missing", "str"]), 0).apply(&db), Frame::Error(_)));
+    }
+}
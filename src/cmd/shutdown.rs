@@ -0,0 +1,23 @@
+use crate::frame::Frame;
+
+use super::{Parse, ParseError};
+
+/// `SHUTDOWN`，让服务端停止接受新连接，等正在执行的命令完成后退出。
+///
+/// 命令本身只回复 OK，退出流程由连接的处理循环在回复之后发起
+#[derive(Debug, Default)]
+pub struct Shutdown;
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown
+    }
+
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Shutdown, ParseError> {
+        Ok(Shutdown)
+    }
+
+    pub(crate) fn apply(self) -> Frame {
+        Frame::Simple("OK".into())
+    }
+}
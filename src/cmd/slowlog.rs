@@ -0,0 +1,63 @@
+use crate::{db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `SLOWLOG GET` 不指定个数时返回的记录数
+const DEFAULT_GET_COUNT: usize = 10;
+
+/// `SLOWLOG <subcommand>`，查看慢查询日志，见 [`crate::slowlog`]
+#[derive(Debug)]
+pub enum SlowLog {
+    /// `SLOWLOG GET [count]`，最新的 count 条记录，count 为 -1 时返回所有记录
+    Get(Option<usize>),
+    /// `SLOWLOG LEN`
+    Len,
+    /// `SLOWLOG RESET`，清空日志
+    Reset,
+}
+
+impl SlowLog {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SlowLog, ParseError> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_lowercase().as_str() {
+            "get" => {
+                let count = match parse.has_remaining() {
+                    true => match parse.next_int()? {
+                        -1 => None,
+                        count if count < 0 => return Err("ERR count should be greater than or equal to -1".into()),
+                        count => Some(count as usize),
+                    },
+                    false => Some(DEFAULT_GET_COUNT),
+                };
+                Ok(SlowLog::Get(count))
+            },
+            "len" => Ok(SlowLog::Len),
+            "reset" => Ok(SlowLog::Reset),
+            _ => Err(format!("ERR unknown subcommand '{}'. Try SLOWLOG HELP.", subcommand).into()),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let log = db.slowlog();
+        match self {
+            SlowLog::Get(count) => Frame::Array(log.get(count).iter().map(|entry| entry.to_frame()).collect()),
+            SlowLog::Len => Frame::Integer(log.len() as i64),
+            SlowLog::Reset => {
+                log.reset();
+                Frame::Simple("OK".into())
+            },
+        }
+    }
+
+    /// 转换成发送给服务端的 frame，供客户端使用
+    pub(crate) fn into_frame(self) -> Frame {
+        match self {
+            SlowLog::Get(count) => {
+                let count = count.map_or(-1, |count| count as i64);
+                Frame::array(["slowlog".to_string(), "get".to_string(), count.to_string()])
+            },
+            SlowLog::Len => Frame::array(["slowlog", "len"]),
+            SlowLog::Reset => Frame::array(["slowlog", "reset"]),
+        }
+    }
+}
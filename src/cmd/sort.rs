@@ -0,0 +1,264 @@
+//! `SORT` 命令，对列表、集合、有序集合的元素排序。
+//!
+//! BY、GET 的模式中第一个 `*` 替换为元素后得到另一个 key，读取它的值；`key->field` 形式读取哈希的字段，
+//! `#` 表示元素本身。模式指向的 key 不存在或者类型不符时视为不存在，不会报错
+
+use bytes::Bytes;
+
+use crate::{db::{Db, WrongType}, frame::Frame, object::{RedisObject, Typed}, types::{Hash, List}};
+
+use super::{Parse, ParseError, incr::parse_float};
+
+/// `SORT key [BY pattern] [LIMIT offset count] [GET pattern [GET pattern ...]] [ASC|DESC] [ALPHA] [STORE destination]`
+///
+/// 默认按数值排序，ALPHA 时按字节序排序，排序依据相同时再按元素本身的字节序排列，结果是确定的。
+/// BY 的模式中没有 `*` 时不排序，保持元素原来的顺序（集合的顺序不确定）。
+/// 有 GET 时对每个元素依次返回各模式读到的值，读不到时为 nil。
+///
+/// STORE 时把结果保存为 destination 的列表，nil 保存为空字符串，结果为空时删除 destination，返回结果的元素个数
+#[derive(Debug)]
+pub struct Sort {
+    key: Bytes,
+    by: Option<Bytes>,
+    /// (offset, count)，count 为负数表示取到末尾
+    limit: Option<(i64, i64)>,
+    get: Vec<Bytes>,
+    desc: bool,
+    alpha: bool,
+    destination: Option<Bytes>,
+}
+
+impl Sort {
+    pub fn new(key: impl Into<Bytes>) -> Sort {
+        Sort { key: key.into(), by: None, limit: None, get: vec![], desc: false, alpha: false, destination: None }
+    }
+
+    pub fn by(mut self, pattern: impl Into<Bytes>) -> Sort {
+        self.by = Some(pattern.into());
+        self
+    }
+
+    pub fn limit(mut self, offset: i64, count: i64) -> Sort {
+        self.limit = Some((offset, count));
+        self
+    }
+
+    pub fn get(mut self, pattern: impl Into<Bytes>) -> Sort {
+        self.get.push(pattern.into());
+        self
+    }
+
+    pub fn desc(mut self) -> Sort {
+        self.desc = true;
+        self
+    }
+
+    pub fn alpha(mut self) -> Sort {
+        self.alpha = true;
+        self
+    }
+
+    pub fn store(mut self, destination: impl Into<Bytes>) -> Sort {
+        self.destination = Some(destination.into());
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Sort, ParseError> {
+        let mut sort = Sort::new(parse.next_bytes()?);
+        while parse.has_remaining() {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "ASC" => sort.desc = false,
+                "DESC" => sort.desc = true,
+                "ALPHA" => sort.alpha = true,
+                "LIMIT" => sort.limit = Some((parse.next_int()?, parse.next_int()?)),
+                "BY" => sort.by = Some(parse.next_bytes()?),
+                "GET" => sort.get.push(parse.next_bytes()?),
+                "STORE" => sort.destination = Some(parse.next_bytes()?),
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        Ok(sort)
+    }
+
+    /// 是否有 STORE 选项
+    pub(crate) fn is_store(&self) -> bool {
+        self.destination.is_some()
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let elements = match self.sorted(db) {
+            Ok(elements) => elements,
+            Err(err) => return Frame::Error(err),
+        };
+        let values: Vec<Option<Bytes>> = match self.get.is_empty() {
+            true => elements.into_iter().map(Some).collect(),
+            false => elements
+                .iter()
+                .flat_map(|element| self.get.iter().map(move |pattern| lookup(db, pattern, element)))
+                .collect(),
+        };
+        let Some(destination) = self.destination else {
+            return Frame::Array(values.into_iter().map(|value| value.map_or(Frame::Null, Frame::Bulk)).collect());
+        };
+        let len = values.len();
+        if values.is_empty() {
+            db.del(&destination);
+        } else {
+            let limits = db.encoding_limits().list;
+            let mut list = List::new();
+            for value in values {
+                list.push_back(value.unwrap_or_default(), &limits);
+            }
+            db.insert(destination, list.into_object(), None);
+        }
+        Frame::Integer(len as i64)
+    }
+
+    /// 读出 key 的元素，排序后按 LIMIT 截取
+    fn sorted(&self, db: &Db) -> Result<Vec<Bytes>, String> {
+        let elements = db.lookup_read(&self.key, |value| match value {
+            None => Ok(vec![]),
+            Some(RedisObject::List(list)) => Ok(list.range(0, -1)),
+            Some(RedisObject::Set(set)) => Ok(set.members()),
+            Some(RedisObject::ZSet(zset)) => Ok(zset.range_by_rank(0, -1, false).into_iter().map(|(member, _)| member).collect()),
+            Some(_) => Err(WrongType.to_string()),
+        })?;
+        let dontsort = self.by.as_ref().is_some_and(|pattern| !pattern.contains(&b'*'));
+        let mut elements = match dontsort {
+            true => elements,
+            false => self.sort(db, elements)?,
+        };
+        if let Some((offset, count)) = self.limit {
+            let start = (offset.max(0) as usize).min(elements.len());
+            let end = match count {
+                0.. => start.saturating_add(count as usize).min(elements.len()),
+                _ => elements.len(),
+            };
+            elements.truncate(end);
+            elements.drain(..start);
+        }
+        Ok(elements)
+    }
+
+    fn sort(&self, db: &Db, elements: Vec<Bytes>) -> Result<Vec<Bytes>, String> {
+        let mut weighted = Vec::with_capacity(elements.len());
+        for element in elements {
+            let weight = match &self.by {
+                Some(pattern) => lookup(db, pattern, &element),
+                None => Some(element.clone()),
+            };
+            let weight = match (self.alpha, weight) {
+                (true, weight) => Weight::Alpha(weight),
+                // 读不到的值按 0 排序
+                (false, None) => Weight::Score(0.0),
+                (false, Some(weight)) => match parse_float(&weight) {
+                    Some(score) => Weight::Score(score),
+                    None => return Err("ERR One or more scores can't be converted into double".into()),
+                },
+            };
+            weighted.push((weight, element));
+        }
+        weighted.sort_by(|(a, a_element), (b, b_element)| {
+            let ordering = match (a, b) {
+                (Weight::Score(a), Weight::Score(b)) => a.total_cmp(b),
+                // nil 排在最前
+                (Weight::Alpha(a), Weight::Alpha(b)) => a.cmp(b),
+                _ => unreachable!(),
+            };
+            let ordering = ordering.then_with(|| a_element.cmp(b_element));
+            if self.desc {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        Ok(weighted.into_iter().map(|(_, element)| element).collect())
+    }
+}
+
+/// 排序的依据，一次排序中所有元素使用同一种
+enum Weight {
+    Score(f64),
+    Alpha(Option<Bytes>),
+}
+
+/// 按模式读取元素对应的值，见模块的说明
+fn lookup(db: &Db, pattern: &[u8], element: &Bytes) -> Option<Bytes> {
+    if pattern == b"#" {
+        return Some(element.clone());
+    }
+    let star = pattern.iter().position(|&c| c == b'*')?;
+    // `->` 出现在 `*` 之后、并且后面还有字段名时才是哈希的字段
+    let arrow = pattern[star + 1..]
+        .windows(2)
+        .position(|window| window == b"->")
+        .map(|i| star + 1 + i)
+        .filter(|&i| i + 2 < pattern.len());
+    let end = arrow.unwrap_or(pattern.len());
+    let key = [&pattern[..star], &element[..], &pattern[star + 1..end]].concat();
+    match arrow {
+        Some(arrow) => db.with_typed(&key, |hash: Option<&mut Hash>| hash?.get(&pattern[arrow + 2..])).ok().flatten(),
+        None => db.get(&key).ok().flatten(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{cmd::{HSet, LRange, Push, SAdd}, db::Db, frame::Frame};
+
+    use super::Sort;
+
+    fn bulks(values: &[&'static str]) -> Frame {
+        Frame::Array(values.iter().map(|value| Frame::Bulk(Bytes::from(*value))).collect())
+    }
+
+    fn values(values: &[&'static str]) -> Vec<Bytes> {
+        values.iter().map(|value| Bytes::from(*value)).collect()
+    }
+
+    #[test]
+    fn numeric_and_alpha() {
+        let db = Db::new();
+        Push::back("l", values(&["3", "10", "1.5", "-2", "10"])).apply(&db);
+        assert_eq!(Sort::new("l").apply(&db), bulks(&["-2", "1.5", "3", "10", "10"]));
+        assert_eq!(Sort::new("l").desc().limit(1, 2).apply(&db), bulks(&["10", "3"]));
+        assert_eq!(Sort::new("l").alpha().apply(&db), bulks(&["-2", "1.5", "10", "10", "3"]));
+        assert_eq!(Sort::new("l").limit(-1, -1).apply(&db), Sort::new("l").apply(&db));
+        assert_eq!(Sort::new("l").limit(10, 1).apply(&db), bulks(&[]));
+        assert_eq!(Sort::new("missing").apply(&db), bulks(&[]));
+
+        SAdd::new("s", values(&["b", "a", "c"])).apply(&db);
+        assert_eq!(Sort::new("s").alpha().apply(&db), bulks(&["a", "b", "c"]));
+        assert!(matches!(Sort::new("s").apply(&db), Frame::Error(err) if err.contains("converted into double")));
+        db.set(Bytes::from("str"), Bytes::from("v"), None);
+        assert!(matches!(Sort::new("str").apply(&db), Frame::Error(err) if err.starts_with("WRONGTYPE")));
+    }
+
+    #[test]
+    fn by_and_get() {
+        let db = Db::new();
+        SAdd::new("ids", values(&["1", "2", "3"])).apply(&db);
+        for (id, weight, name) in [("1", "30", "one"), ("2", "10", "two"), ("3", "20", "three")] {
+            db.set(Bytes::from(format!("w_{}", id)), Bytes::from(weight), None);
+            HSet::new(format!("user:{}", id), vec![(Bytes::from("name"), Bytes::from(name))]).apply(&db);
+        }
+        assert_eq!(Sort::new("ids").by("w_*").apply(&db), bulks(&["2", "3", "1"]));
+        assert_eq!(Sort::new("ids").by("user:*->name").alpha().apply(&db), bulks(&["1", "3", "2"]));
+        let reply = Sort::new("ids").by("w_*").get("#").get("user:*->name").get("missing_*").apply(&db);
+        let Frame::Array(reply) = reply else { panic!() };
+        assert_eq!(reply[..3], [Frame::Bulk(Bytes::from("2")), Frame::Bulk(Bytes::from("two")), Frame::Null]);
+
+        // 模式中没有 `*` 时不排序
+        Push::back("l", values(&["b", "c", "a"])).apply(&db);
+        assert_eq!(Sort::new("l").by("nosort").apply(&db), bulks(&["b", "c", "a"]));
+        // 读不到的值按 0 排序，再按元素本身排列
+        assert_eq!(Sort::new("l").by("missing_*").apply(&db), bulks(&["a", "b", "c"]));
+
+        assert_eq!(Sort::new("ids").by("w_*").get("missing_*").store("dest").apply(&db), Frame::Integer(3));
+        assert_eq!(LRange::new("dest", 0, -1).apply(&db), bulks(&["", "", ""]));
+        assert_eq!(Sort::new("missing").store("dest").apply(&db), Frame::Integer(0));
+        assert!(!db.exists(b"dest"));
+    }
+}
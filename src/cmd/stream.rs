@@ -0,0 +1,1028 @@
+//! 流相关命令，数据保存在 [`Stream`] 中
+
+use std::{collections::BTreeMap, ops::Bound, time::Duration};
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+use crate::{db::{Db, now_ms}, frame::{Frame, Protocol}, types::{ConsumerGroup, NewId, Stream, StreamEntry, StreamId}};
+
+use super::{Command, Parse, ParseError};
+
+const INVALID_ID: &str = "ERR Invalid stream ID specified as stream command argument";
+
+/// 解析 `ms-seq` 形式的 ID，省略 seq 时使用 missing_seq
+fn parse_id(arg: &[u8], missing_seq: u64) -> Result<StreamId, ParseError> {
+    let number = |digits: &[u8]| std::str::from_utf8(digits).ok()?.parse::<u64>().ok();
+    let id = match arg.iter().position(|&c| c == b'-') {
+        Some(dash) => number(&arg[..dash]).zip(number(&arg[dash + 1..])).map(|(ms, seq)| StreamId::new(ms, seq)),
+        None => number(arg).map(|ms| StreamId::new(ms, missing_seq)),
+    };
+    id.ok_or_else(|| INVALID_ID.into())
+}
+
+/// 区间的一端：`-`、`+` 表示最小、最大的 ID，`(` 开头表示不包括这个 ID。
+/// 省略 seq 时起点取 0，终点取最大值，使区间包括这一毫秒内的所有条目。
+///
+/// `-`、`+` 不论出现在哪一端都是具体的 ID，`+ -` 这样反过来的区间为空
+fn parse_bound(arg: &[u8], start: bool) -> Result<Bound<StreamId>, ParseError> {
+    let missing_seq = if start { 0 } else { u64::MAX };
+    match arg {
+        b"-" => Ok(Bound::Included(StreamId::MIN)),
+        b"+" => Ok(Bound::Included(StreamId::MAX)),
+        [b'(', id @ ..] => Ok(Bound::Excluded(parse_id(id, missing_seq)?)),
+        id => Ok(Bound::Included(parse_id(id, missing_seq)?)),
+    }
+}
+
+/// 条目回复为 [ID, [field, value, ...]]
+fn entry_frame((id, fields): StreamEntry) -> Frame {
+    let fields = fields.into_iter().flat_map(|(field, value)| [Frame::Bulk(field), Frame::Bulk(value)]);
+    Frame::Array(vec![Frame::Bulk(Bytes::from(id.to_string())), Frame::Array(fields.collect())])
+}
+
+/// XADD 的裁剪条件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trim {
+    /// 只保留最新的若干个条目
+    MaxLen(usize),
+    /// 删除 ID 小于它的条目
+    MinId(StreamId),
+}
+
+/// `XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] *|id field value [field value ...]`
+///
+/// 添加条目，返回它的 ID。`*` 时由当前时间生成 ID，`ms-*` 时只自动生成序号。
+/// NOMKSTREAM 时 key 不存在则不添加，返回 nil。
+///
+/// 添加后按 MAXLEN 或 MINID 裁剪。`~` 在 redis 中只删除整个节点，这里与 `=` 一样总是精确裁剪，
+/// LIMIT 限制的是近似裁剪的工作量，所以同样被忽略
+#[derive(Debug)]
+pub struct XAdd {
+    key: Bytes,
+    id: NewId,
+    fields: Vec<(Bytes, Bytes)>,
+    nomkstream: bool,
+    trim: Option<Trim>,
+}
+
+impl XAdd {
+    pub fn new(key: impl Into<Bytes>, id: NewId, fields: Vec<(Bytes, Bytes)>) -> XAdd {
+        XAdd { key: key.into(), id, fields, nomkstream: false, trim: None }
+    }
+
+    pub fn nomkstream(mut self) -> XAdd {
+        self.nomkstream = true;
+        self
+    }
+
+    pub fn trim(mut self, trim: Trim) -> XAdd {
+        self.trim = Some(trim);
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XAdd, ParseError> {
+        let key = parse.next_bytes()?;
+        let mut nomkstream = false;
+        let mut trim = None;
+        let mut approx = false;
+        // 选项都在 ID 之前
+        let id = loop {
+            let arg = parse.next_bytes()?;
+            match arg.to_ascii_uppercase().as_slice() {
+                b"NOMKSTREAM" => nomkstream = true,
+                option @ (b"MAXLEN" | b"MINID") => {
+                    let mut threshold = parse.next_bytes()?;
+                    if matches!(&threshold[..], b"=" | b"~") {
+                        approx = &threshold[..] == b"~";
+                        threshold = parse.next_bytes()?;
+                    }
+                    trim = Some(match option {
+                        b"MAXLEN" => {
+                            let maxlen = atoi::atoi::<i64>(&threshold).ok_or("ERR value is not an integer or out of range")?;
+                            Trim::MaxLen(usize::try_from(maxlen).map_err(|_| "ERR The MAXLEN argument must be >= 0.")?)
+                        },
+                        _ => Trim::MinId(parse_id(&threshold, 0)?),
+                    });
+                },
+                b"LIMIT" => {
+                    parse.next_int()?;
+                    if !approx {
+                        return Err("ERR syntax error, LIMIT cannot be used without the special ~ option".into());
+                    }
+                },
+                _ => break arg,
+            }
+        };
+        let id = match &id[..] {
+            b"*" => NewId::Auto,
+            [ms @ .., b'-', b'*'] => NewId::Ms(parse_id(ms, 0)?.ms),
+            id => match parse_id(id, 0)? {
+                StreamId::MIN => return Err("ERR The ID specified in XADD must be greater than 0-0".into()),
+                id => NewId::Explicit(id),
+            },
+        };
+        let mut fields = vec![];
+        while parse.has_remaining() {
+            let field = parse.next_bytes()?;
+            if !parse.has_remaining() {
+                return Err("ERR wrong number of arguments for 'xadd' command".into());
+            }
+            fields.push((field, parse.next_bytes()?));
+        }
+        if fields.is_empty() {
+            return Err("ERR wrong number of arguments for 'xadd' command".into());
+        }
+        Ok(XAdd { key, id, fields, nomkstream, trim })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let reply = db.update_typed(&self.key, |value: &mut Option<Stream>| {
+            if value.is_none() && self.nomkstream {
+                return Frame::Null;
+            }
+            let created = value.is_none();
+            let Some(id) = value.get_or_insert_with(Stream::new).next_id(self.id, now_ms()) else {
+                if created {
+                    *value = None;
+                }
+                let err = match self.id {
+                    NewId::Auto => "ERR The stream has exhausted the last possible ID, unable to add more items",
+                    _ => "ERR The ID specified in XADD is equal or smaller than the target stream top item",
+                };
+                return Frame::Error(err.into());
+            };
+            let stream = value.as_mut().unwrap();
+            stream.insert(id, &self.fields);
+            match self.trim {
+                Some(Trim::MaxLen(maxlen)) => stream.trim_len(maxlen),
+                Some(Trim::MinId(min)) => stream.trim_min_id(min),
+                None => 0,
+            };
+            Frame::Bulk(Bytes::from(id.to_string()))
+        });
+        // 唤醒阻塞在这个 key 上的 XREAD、XREADGROUP
+        if matches!(reply, Ok(Frame::Bulk(_))) {
+            db.waiters().signal(&self.key);
+        }
+        reply.unwrap_or_else(Frame::from)
+    }
+}
+
+/// `XRANGE key start end [COUNT count]` 与 `XREVRANGE key end start [COUNT count]`
+///
+/// 返回区间内的条目，XREVRANGE 按 ID 从大到小，区间的写法见 [`parse_bound`]。COUNT 为 0 时返回空数组
+#[derive(Debug)]
+pub struct XRange {
+    key: Bytes,
+    start: Bound<StreamId>,
+    end: Bound<StreamId>,
+    count: Option<usize>,
+    rev: bool,
+}
+
+impl XRange {
+    pub fn new(key: impl Into<Bytes>, start: Bound<StreamId>, end: Bound<StreamId>) -> XRange {
+        XRange { key: key.into(), start, end, count: None, rev: false }
+    }
+
+    pub fn count(mut self, count: usize) -> XRange {
+        self.count = Some(count);
+        self
+    }
+
+    pub fn rev(mut self) -> XRange {
+        self.rev = true;
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, rev: bool) -> Result<XRange, ParseError> {
+        let key = parse.next_bytes()?;
+        let (first, second) = (parse.next_bytes()?, parse.next_bytes()?);
+        let (start, end) = if rev { (second, first) } else { (first, second) };
+        let mut range = XRange::new(key, parse_bound(&start, true)?, parse_bound(&end, false)?);
+        range.rev = rev;
+        while parse.has_remaining() {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "COUNT" => range.count = Some(parse.next_int()?.max(0) as usize),
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        Ok(range)
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.rev {
+            "xrevrange"
+        } else {
+            "xrange"
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.with_typed(&self.key, |stream: Option<&mut Stream>| {
+            let entries = stream.map(|stream| stream.range(self.start, self.end, self.count, self.rev)).unwrap_or_default();
+            Frame::Array(entries.into_iter().map(entry_frame).collect())
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `XLEN key`，条目个数
+#[derive(Debug)]
+pub struct XLen {
+    key: Bytes,
+}
+
+impl XLen {
+    pub fn new(key: impl Into<Bytes>) -> XLen {
+        XLen { key: key.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XLen, ParseError> {
+        Ok(XLen { key: parse.next_bytes()? })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.with_typed(&self.key, |stream: Option<&mut Stream>| Frame::Integer(stream.map_or(0, |stream| stream.len()) as i64))
+            .unwrap_or_else(Frame::from)
+    }
+}
+
+/// XREAD、XREADGROUP 从哪里开始读取
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadFrom {
+    /// `$`：执行时流中最大的 ID 之后，即只读取之后新添加的条目，只用于 XREAD
+    Last,
+    /// `>`：消费者组中还没有投递过的条目，只用于 XREADGROUP
+    New,
+    /// ID 大于它的条目。XREADGROUP 中表示读取消费者自己的待确认条目
+    After(StreamId),
+}
+
+/// XREADGROUP 的组与消费者
+#[derive(Debug, Clone)]
+struct ReadGroup {
+    group: Bytes,
+    consumer: Bytes,
+    noack: bool,
+}
+
+/// `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]` 与
+/// `XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key [key ...] id [id ...]`
+///
+/// 从多个流中读取 ID 之后的条目，回复为 [[key, [条目, ...]], ...]，只包含有条目的流，都没有时回复 nil。
+///
+/// XREADGROUP 以 group 中 consumer 的身份读取：ID 为 `>` 时读取组内的新条目，投递给 consumer 并加入待确认列表，
+/// NOACK 时不加入；其他 ID 读取 consumer 自己的待确认条目（历史），已经从流中删除的条目回复为 [ID, nil]，
+/// 历史总是回复，没有时为空数组。
+///
+/// BLOCK 只在连接的处理循环中生效，见 [`XRead::block`]；在事务、脚本中与不带 BLOCK 时一样立即回复
+#[derive(Debug, Clone)]
+pub struct XRead {
+    streams: Vec<(Bytes, ReadFrom)>,
+    count: Option<usize>,
+    block: Option<Duration>,
+    group: Option<ReadGroup>,
+}
+
+impl XRead {
+    pub fn new(streams: Vec<(Bytes, ReadFrom)>) -> XRead {
+        XRead { streams, count: None, block: None, group: None }
+    }
+
+    pub fn count(mut self, count: usize) -> XRead {
+        self.count = Some(count);
+        self
+    }
+
+    /// timeout 为 0 时一直等待
+    pub fn block(mut self, timeout: Duration) -> XRead {
+        self.block = Some(timeout);
+        self
+    }
+
+    /// 以 group 中 consumer 的身份读取，即 XREADGROUP
+    pub fn group(mut self, group: impl Into<Bytes>, consumer: impl Into<Bytes>, noack: bool) -> XRead {
+        self.group = Some(ReadGroup { group: group.into(), consumer: consumer.into(), noack });
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, group: bool) -> Result<XRead, ParseError> {
+        let mut read = XRead::new(vec![]);
+        let mut noack = false;
+        loop {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "COUNT" => read.count = Some(parse.next_int()?).filter(|&count| count > 0).map(|count| count as usize),
+                "BLOCK" => {
+                    let timeout = parse.next_int()?;
+                    if timeout < 0 {
+                        return Err("ERR timeout is negative".into());
+                    }
+                    read.block = Some(Duration::from_millis(timeout as u64));
+                },
+                "GROUP" if group => read = read.group(parse.next_bytes()?, parse.next_bytes()?, false),
+                "NOACK" if group => noack = true,
+                "STREAMS" => break,
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        let mut args = vec![];
+        while parse.has_remaining() {
+            args.push(parse.next_bytes()?);
+        }
+        if args.is_empty() || args.len() % 2 != 0 {
+            let (name, symbol) = if group { ("xreadgroup", '>') } else { ("xread", '$') };
+            return Err(format!("ERR Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.", name, symbol).into());
+        }
+        match &mut read.group {
+            Some(read_group) => read_group.noack = noack,
+            None if group => return Err("ERR Missing GROUP option for XREADGROUP".into()),
+            None => {},
+        }
+        let (keys, ids) = args.split_at(args.len() / 2);
+        for (key, id) in keys.iter().zip(ids) {
+            let from = match (&id[..], group) {
+                (b"$", false) => ReadFrom::Last,
+                (b">", true) => ReadFrom::New,
+                (b"$", true) => {
+                    return Err("ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.".into());
+                },
+                (b">", false) => {
+                    return Err("ERR The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option.".into());
+                },
+                (id, _) => ReadFrom::After(parse_id(id, 0)?),
+            };
+            read.streams.push((key.clone(), from));
+        }
+        Ok(read)
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.group.is_some() {
+            "xreadgroup"
+        } else {
+            "xread"
+        }
+    }
+
+    pub(crate) fn is_group(&self) -> bool {
+        self.group.is_some()
+    }
+
+    /// 是否指定了 BLOCK
+    pub fn is_blocking(&self) -> bool {
+        self.block.is_some()
+    }
+
+    pub(crate) fn apply(mut self, db: &Db) -> Frame {
+        self.resolve(db);
+        let now = now_ms();
+        let mut replies = vec![];
+        for (key, from) in &self.streams {
+            let entries = match &self.group {
+                Some(group) => db.update_typed(key, |stream: &mut Option<Stream>| self.read_group(key, group, stream.as_mut(), *from, now)),
+                None => db.with_typed(key, |stream: Option<&mut Stream>| Ok(self.read(stream, *from))),
+            };
+            match entries {
+                Ok(Ok(Some(entries))) => replies.push(Frame::Array(vec![Frame::Bulk(key.clone()), Frame::Array(entries)])),
+                Ok(Ok(None)) => {},
+                Ok(Err(err)) => return Frame::Error(err),
+                Err(err) => return err.into(),
+            }
+        }
+        if replies.is_empty() {
+            Frame::Null
+        } else {
+            Frame::Array(replies)
+        }
+    }
+
+    /// 阻塞读取：没有可读的条目时等待这些 key 被 XADD 写入，被唤醒后重新读取，超时后回复 nil。
+    ///
+    /// 等待期间不持有任何锁。`$` 在第一次读取前确定为当时最大的 ID，等待期间添加的条目都会被读到
+    pub async fn block_on(mut self, db: &Db, protocol: &mut Protocol) -> Frame {
+        let Some(timeout) = self.block else {
+            return Command::XRead(self).apply(db, protocol);
+        };
+        let deadline = Instant::now() + timeout;
+        {
+            let _guard = db.command_guard();
+            self.resolve(db);
+        }
+        let keys: Vec<Bytes> = self.streams.iter().map(|(key, _)| key.clone()).collect();
+        loop {
+            let waiter = db.waiters().register(&keys);
+            let reply = Command::XRead(self.clone()).apply(db, protocol);
+            if reply != Frame::Null {
+                return reply;
+            }
+            if timeout.is_zero() {
+                waiter.wait().await;
+            } else if tokio::time::timeout_at(deadline, waiter.wait()).await.is_err() {
+                return Frame::Null;
+            }
+        }
+    }
+
+    /// 把 `$` 替换为流当前最大的 ID
+    fn resolve(&mut self, db: &Db) {
+        for (key, from) in &mut self.streams {
+            if *from == ReadFrom::Last {
+                let last_id = db.with_typed(key, |stream: Option<&mut Stream>| stream.map_or(StreamId::MIN, |stream| stream.last_id()));
+                // 类型不符时留给读取时报错
+                *from = ReadFrom::After(last_id.unwrap_or(StreamId::MAX));
+            }
+        }
+    }
+
+    /// XREAD 读取一个流，没有条目时返回 `None`
+    fn read(&self, stream: Option<&mut Stream>, from: ReadFrom) -> Option<Vec<Frame>> {
+        let ReadFrom::After(after) = from else {
+            return None;
+        };
+        let entries = stream?.range(Bound::Excluded(after), Bound::Unbounded, self.count, false);
+        (!entries.is_empty()).then(|| entries.into_iter().map(entry_frame).collect())
+    }
+
+    /// XREADGROUP 读取一个流，没有需要回复的内容时返回 `None`
+    fn read_group(&self, key: &[u8], group: &ReadGroup, stream: Option<&mut Stream>, from: ReadFrom, now: u64) -> Result<Option<Vec<Frame>>, String> {
+        let no_group = || {
+            format!(
+                "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(&group.group)
+            )
+        };
+        let stream = stream.ok_or_else(no_group)?;
+        match from {
+            ReadFrom::After(after) => {
+                let entries = stream.read_pending(&group.group, &group.consumer, after, self.count, now).ok_or_else(no_group)?;
+                let entries = entries.into_iter().map(|(id, fields)| match fields {
+                    Some(fields) => entry_frame((id, fields)),
+                    None => Frame::Array(vec![Frame::Bulk(Bytes::from(id.to_string())), Frame::Null]),
+                });
+                Ok(Some(entries.collect()))
+            },
+            _ => {
+                let entries = stream.read_group(&group.group, &group.consumer, self.count, group.noack, now).ok_or_else(no_group)?;
+                Ok((!entries.is_empty()).then(|| entries.into_iter().map(entry_frame).collect()))
+            },
+        }
+    }
+}
+
+/// key 不存在时 XGROUP 的错误
+const NO_KEY: &str = "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.";
+
+fn no_group(key: &[u8], group: &[u8]) -> String {
+    format!("NOGROUP No such key '{}' or consumer group '{}'", String::from_utf8_lossy(key), String::from_utf8_lossy(group))
+}
+
+/// `XGROUP <subcommand>`，管理消费者组。id 为 `None` 表示 `$`，即流当前最大的 ID
+#[derive(Debug)]
+pub enum XGroup {
+    /// `XGROUP CREATE key group id|$ [MKSTREAM]`，创建消费者组，id 之后的条目是组内的新条目。
+    /// MKSTREAM 时 key 不存在则创建空的流
+    Create { key: Bytes, group: Bytes, id: Option<StreamId>, mkstream: bool },
+    /// `XGROUP SETID key group id|$`，修改组的 last_id
+    SetId { key: Bytes, group: Bytes, id: Option<StreamId> },
+    /// `XGROUP DESTROY key group`，删除消费者组，返回删除的个数。阻塞在这个组上的 XREADGROUP 会收到 NOGROUP 错误
+    Destroy { key: Bytes, group: Bytes },
+    /// `XGROUP CREATECONSUMER key group consumer`，返回是否新建了消费者
+    CreateConsumer { key: Bytes, group: Bytes, consumer: Bytes },
+    /// `XGROUP DELCONSUMER key group consumer`，删除消费者及其待确认条目，返回删除的待确认条目个数
+    DelConsumer { key: Bytes, group: Bytes, consumer: Bytes },
+}
+
+impl XGroup {
+    pub fn create(key: impl Into<Bytes>, group: impl Into<Bytes>, id: Option<StreamId>, mkstream: bool) -> XGroup {
+        XGroup::Create { key: key.into(), group: group.into(), id, mkstream }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XGroup, ParseError> {
+        let subcommand = parse.next_string()?;
+        let group_id = |arg: Bytes| match &arg[..] {
+            b"$" => Ok(None),
+            id => parse_id(id, 0).map(Some),
+        };
+        let xgroup = match subcommand.to_lowercase().as_str() {
+            "create" => {
+                let (key, group) = (parse.next_bytes()?, parse.next_bytes()?);
+                let id = group_id(parse.next_bytes()?)?;
+                let mut mkstream = false;
+                while parse.has_remaining() {
+                    match parse.next_string()?.to_uppercase().as_str() {
+                        "MKSTREAM" => mkstream = true,
+                        _ => return Err("ERR syntax error".into()),
+                    }
+                }
+                XGroup::Create { key, group, id, mkstream }
+            },
+            "setid" => XGroup::SetId { key: parse.next_bytes()?, group: parse.next_bytes()?, id: group_id(parse.next_bytes()?)? },
+            "destroy" => XGroup::Destroy { key: parse.next_bytes()?, group: parse.next_bytes()? },
+            "createconsumer" => XGroup::CreateConsumer { key: parse.next_bytes()?, group: parse.next_bytes()?, consumer: parse.next_bytes()? },
+            "delconsumer" => XGroup::DelConsumer { key: parse.next_bytes()?, group: parse.next_bytes()?, consumer: parse.next_bytes()? },
+            _ => return Err(format!("ERR unknown subcommand '{}'. Try XGROUP HELP.", subcommand).into()),
+        };
+        Ok(xgroup)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let key = match &self {
+            XGroup::Create { key, .. }
+            | XGroup::SetId { key, .. }
+            | XGroup::Destroy { key, .. }
+            | XGroup::CreateConsumer { key, .. }
+            | XGroup::DelConsumer { key, .. } => key.clone(),
+        };
+        let destroy = matches!(self, XGroup::Destroy { .. });
+        let reply = db.update_typed(&key, |value: &mut Option<Stream>| {
+            if value.is_none() {
+                match self {
+                    XGroup::Create { mkstream: true, .. } => *value = Some(Stream::new()),
+                    _ => return Frame::Error(NO_KEY.into()),
+                }
+            }
+            let stream = value.as_mut().unwrap();
+            match self {
+                XGroup::Create { group, id, .. } => {
+                    let id = id.unwrap_or(stream.last_id());
+                    match stream.create_group(group, id) {
+                        true => Frame::Simple("OK".into()),
+                        false => Frame::Error("BUSYGROUP Consumer Group name already exists".into()),
+                    }
+                },
+                XGroup::Destroy { group, .. } => Frame::Integer(stream.destroy_group(&group) as i64),
+                XGroup::SetId { group, id, .. } => {
+                    let id = id.unwrap_or(stream.last_id());
+                    on_group(stream, &key, &group, |group| {
+                        group.set_last_id(id);
+                        Frame::Simple("OK".into())
+                    })
+                },
+                XGroup::CreateConsumer { group, consumer, .. } => {
+                    on_group(stream, &key, &group, |group| Frame::Integer(group.touch(&consumer, now_ms()) as i64))
+                },
+                XGroup::DelConsumer { group, consumer, .. } => {
+                    on_group(stream, &key, &group, |group| Frame::Integer(group.remove_consumer(&consumer).unwrap_or(0) as i64))
+                },
+            }
+        });
+        if destroy {
+            db.waiters().signal(&key);
+        }
+        reply.unwrap_or_else(Frame::from)
+    }
+}
+
+/// 在消费者组上执行 f，组不存在时回复 NOGROUP 错误
+fn on_group(stream: &mut Stream, key: &[u8], group: &[u8], f: impl FnOnce(&mut ConsumerGroup) -> Frame) -> Frame {
+    match stream.group_mut(group) {
+        Some(group) => f(group),
+        None => Frame::Error(format!(
+            "NOGROUP No such consumer group '{}' for key name '{}'",
+            String::from_utf8_lossy(group),
+            String::from_utf8_lossy(key)
+        )),
+    }
+}
+
+/// `XACK key group id [id ...]`，确认条目，从组的待确认列表中删除，返回确认的个数。key 或组不存在时返回 0
+#[derive(Debug)]
+pub struct XAck {
+    key: Bytes,
+    group: Bytes,
+    ids: Vec<StreamId>,
+}
+
+impl XAck {
+    pub fn new(key: impl Into<Bytes>, group: impl Into<Bytes>, ids: Vec<StreamId>) -> XAck {
+        XAck { key: key.into(), group: group.into(), ids }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XAck, ParseError> {
+        let (key, group) = (parse.next_bytes()?, parse.next_bytes()?);
+        let mut ids = vec![parse_id(&parse.next_bytes()?, 0)?];
+        while parse.has_remaining() {
+            ids.push(parse_id(&parse.next_bytes()?, 0)?);
+        }
+        Ok(XAck { key, group, ids })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update_typed(&self.key, |stream: &mut Option<Stream>| {
+            let Some(group) = stream.as_mut().and_then(|stream| stream.group_mut(&self.group)) else {
+                return Frame::Integer(0);
+            };
+            Frame::Integer(self.ids.iter().filter(|&&id| group.ack(id)).count() as i64)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// XPENDING 列出待确认条目时的条件
+#[derive(Debug)]
+struct PendingRange {
+    /// 只列出空闲时间（距离最近一次投递）不少于这个毫秒数的条目
+    min_idle: u64,
+    start: Bound<StreamId>,
+    end: Bound<StreamId>,
+    count: usize,
+    consumer: Option<Bytes>,
+}
+
+/// `XPENDING key group [[IDLE min-idle-time] start end count [consumer]]`
+///
+/// 只有 key 与 group 时回复概要：[待确认条目个数, 最小 ID, 最大 ID, [[消费者, 条目个数], ...]]，
+/// 没有待确认条目时后三项为 nil。
+/// 指定区间时列出区间内的待确认条目，每项为 [ID, 消费者, 空闲毫秒数, 投递次数]
+#[derive(Debug)]
+pub struct XPending {
+    key: Bytes,
+    group: Bytes,
+    range: Option<PendingRange>,
+}
+
+impl XPending {
+    pub fn new(key: impl Into<Bytes>, group: impl Into<Bytes>) -> XPending {
+        XPending { key: key.into(), group: group.into(), range: None }
+    }
+
+    /// 列出区间内的待确认条目，最多 count 个
+    pub fn range(mut self, start: Bound<StreamId>, end: Bound<StreamId>, count: usize) -> XPending {
+        self.range = Some(PendingRange { min_idle: 0, start, end, count, consumer: None });
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XPending, ParseError> {
+        let mut pending = XPending::new(parse.next_bytes()?, parse.next_bytes()?);
+        if !parse.has_remaining() {
+            return Ok(pending);
+        }
+        let mut start = parse.next_bytes()?;
+        let mut min_idle = 0;
+        if start.eq_ignore_ascii_case(b"IDLE") {
+            min_idle = parse.next_int()?.max(0) as u64;
+            start = parse.next_bytes()?;
+        }
+        let (start, end) = (parse_bound(&start, true)?, parse_bound(&parse.next_bytes()?, false)?);
+        let count = parse.next_int()?.max(0) as usize;
+        let consumer = if parse.has_remaining() { Some(parse.next_bytes()?) } else { None };
+        pending.range = Some(PendingRange { min_idle, start, end, count, consumer });
+        Ok(pending)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let now = now_ms();
+        db.with_typed(&self.key, |stream: Option<&mut Stream>| {
+            let Some(group) = stream.and_then(|stream| stream.group_mut(&self.group)) else {
+                return Frame::Error(no_group(&self.key, &self.group));
+            };
+            let Some(range) = &self.range else {
+                return summary(group);
+            };
+            let entries = group
+                .pending_range(range.start, range.end)
+                .filter(|(_, entry)| range.consumer.as_ref().is_none_or(|consumer| *consumer == entry.consumer))
+                .filter(|(_, entry)| now.saturating_sub(entry.delivery_time) >= range.min_idle)
+                .take(range.count)
+                .map(|(id, entry)| {
+                    Frame::Array(vec![
+                        Frame::Bulk(Bytes::from(id.to_string())),
+                        Frame::Bulk(entry.consumer.clone()),
+                        Frame::Integer(now.saturating_sub(entry.delivery_time) as i64),
+                        Frame::Integer(entry.delivery_count as i64),
+                    ])
+                });
+            Frame::Array(entries.collect())
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// XPENDING 的概要
+fn summary(group: &ConsumerGroup) -> Frame {
+    let pending = group.pending();
+    let (Some(first), Some(last)) = (pending.keys().next(), pending.keys().next_back()) else {
+        return Frame::Array(vec![Frame::Integer(0), Frame::Null, Frame::Null, Frame::Null]);
+    };
+    let mut consumers = BTreeMap::new();
+    for entry in pending.values() {
+        *consumers.entry(&entry.consumer).or_insert(0) += 1;
+    }
+    // 与 redis 一样，个数以字符串回复
+    let consumers = consumers
+        .into_iter()
+        .map(|(consumer, count): (&Bytes, usize)| Frame::Array(vec![Frame::Bulk(consumer.clone()), Frame::Bulk(Bytes::from(count.to_string()))]));
+    Frame::Array(vec![
+        Frame::Integer(pending.len() as i64),
+        Frame::Bulk(Bytes::from(first.to_string())),
+        Frame::Bulk(Bytes::from(last.to_string())),
+        Frame::Array(consumers.collect()),
+    ])
+}
+
+/// `XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID id]`
+///
+/// 把空闲时间不少于 min-idle-time 毫秒的待确认条目转给 consumer，返回认领到的条目，JUSTID 时只返回 ID。
+///
+/// - 认领会重置投递时间，IDLE、TIME 分别指定认领后的空闲时间与投递时间；
+/// - 投递次数加一，JUSTID 时不变，RETRYCOUNT 直接指定；
+/// - FORCE 时不在待确认列表中、但仍在流中的条目也被认领；
+/// - 已经从流中删除的条目从待确认列表中移除，不会返回；
+/// - LASTID 大于组的 last_id 时更新它
+#[derive(Debug)]
+pub struct XClaim {
+    key: Bytes,
+    group: Bytes,
+    consumer: Bytes,
+    min_idle: u64,
+    ids: Vec<StreamId>,
+    idle: Option<u64>,
+    time: Option<u64>,
+    retry_count: Option<u64>,
+    force: bool,
+    justid: bool,
+    last_id: Option<StreamId>,
+}
+
+impl XClaim {
+    pub fn new(key: impl Into<Bytes>, group: impl Into<Bytes>, consumer: impl Into<Bytes>, min_idle: u64, ids: Vec<StreamId>) -> XClaim {
+        XClaim {
+            key: key.into(),
+            group: group.into(),
+            consumer: consumer.into(),
+            min_idle,
+            ids,
+            idle: None,
+            time: None,
+            retry_count: None,
+            force: false,
+            justid: false,
+            last_id: None,
+        }
+    }
+
+    pub fn force(mut self) -> XClaim {
+        self.force = true;
+        self
+    }
+
+    pub fn justid(mut self) -> XClaim {
+        self.justid = true;
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XClaim, ParseError> {
+        let (key, group, consumer) = (parse.next_bytes()?, parse.next_bytes()?, parse.next_bytes()?);
+        let min_idle = parse.next_int()?.max(0) as u64;
+        let mut claim = XClaim::new(key, group, consumer, min_idle, vec![parse_id(&parse.next_bytes()?, 0)?]);
+        // ID 之后是选项，第一个不是 ID 的参数开始都按选项解析
+        let mut options = false;
+        while parse.has_remaining() {
+            let arg = parse.next_bytes()?;
+            if !options {
+                if let Ok(id) = parse_id(&arg, 0) {
+                    claim.ids.push(id);
+                    continue;
+                }
+                options = true;
+            }
+            match arg.to_ascii_uppercase().as_slice() {
+                b"IDLE" => claim.idle = Some(parse.next_int()?.max(0) as u64),
+                b"TIME" => claim.time = Some(parse.next_int()?.max(0) as u64),
+                b"RETRYCOUNT" => claim.retry_count = Some(parse.next_int()?.max(0) as u64),
+                b"FORCE" => claim.force = true,
+                b"JUSTID" => claim.justid = true,
+                b"LASTID" => claim.last_id = Some(parse_id(&parse.next_bytes()?, 0)?),
+                _ => return Err(format!("ERR Unrecognized XCLAIM option '{}'", String::from_utf8_lossy(&arg)).into()),
+            }
+        }
+        Ok(claim)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let now = now_ms();
+        let delivery_time = match (self.time, self.idle) {
+            (Some(time), _) => time,
+            (None, Some(idle)) => now.saturating_sub(idle),
+            (None, None) => now,
+        };
+        db.update_typed(&self.key, |stream: &mut Option<Stream>| {
+            let Some(stream) = stream else {
+                return Frame::Error(no_group(&self.key, &self.group));
+            };
+            // 先读出条目的内容，再修改消费者组
+            let contents: Vec<_> = self.ids.iter().map(|&id| stream.get(id)).collect();
+            let Some(group) = stream.group_mut(&self.group) else {
+                return Frame::Error(no_group(&self.key, &self.group));
+            };
+            if let Some(last_id) = self.last_id.filter(|&last_id| last_id > group.last_id()) {
+                group.set_last_id(last_id);
+            }
+            group.touch(&self.consumer, now);
+            let mut claimed = vec![];
+            for (&id, fields) in self.ids.iter().zip(contents) {
+                let Some(fields) = fields else {
+                    group.ack(id);
+                    continue;
+                };
+                match group.pending_mut(id) {
+                    Some(entry) if now.saturating_sub(entry.delivery_time) < self.min_idle => continue,
+                    Some(entry) => {
+                        entry.consumer = self.consumer.clone();
+                        entry.delivery_time = delivery_time;
+                        if !self.justid {
+                            entry.delivery_count += 1;
+                        }
+                    },
+                    None if self.force => group.deliver(id, &self.consumer, delivery_time),
+                    None => continue,
+                }
+                if let Some(retry_count) = self.retry_count {
+                    group.pending_mut(id).unwrap().delivery_count = retry_count;
+                }
+                claimed.push(match self.justid {
+                    true => Frame::Bulk(Bytes::from(id.to_string())),
+                    false => entry_frame((id, fields)),
+                });
+            }
+            Frame::Array(claimed)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use bytes::Bytes;
+
+    use std::time::Duration;
+
+    use crate::{cmd::Command, db::Db, frame::{Frame, Protocol}, types::{NewId, StreamId}};
+
+    use super::{ReadFrom, Trim, XAck, XAdd, XClaim, XGroup, XLen, XPending, XRange, XRead, parse_bound, parse_id};
+
+    fn add(db: &Db, id: NewId, value: &'static str) -> Frame {
+        XAdd::new("s", id, vec![(Bytes::from("f"), Bytes::from(value))]).apply(db)
+    }
+
+    fn ids(frame: Frame) -> Vec<String> {
+        let Frame::Array(entries) = frame else { panic!() };
+        entries
+            .into_iter()
+            .map(|entry| match entry {
+                Frame::Array(entry) => entry[0].as_str().unwrap().to_string(),
+                _ => panic!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_ids() {
+        assert_eq!(parse_id(b"12-3", 0).unwrap(), StreamId::new(12, 3));
+        assert_eq!(parse_id(b"12", 7).unwrap(), StreamId::new(12, 7));
+        for invalid in [&b""[..], b"-", b"1-", b"-1", b"a-1", b"1-2-3", b"-1-0"] {
+            assert!(parse_id(invalid, 0).is_err(), "{:?}", invalid);
+        }
+        assert_eq!(parse_bound(b"-", true).unwrap(), Bound::Included(StreamId::MIN));
+        assert_eq!(parse_bound(b"-", false).unwrap(), Bound::Included(StreamId::MIN));
+        assert_eq!(parse_bound(b"+", true).unwrap(), Bound::Included(StreamId::MAX));
+        assert_eq!(parse_bound(b"5", false).unwrap(), Bound::Included(StreamId::new(5, u64::MAX)));
+        assert_eq!(parse_bound(b"(5-1", true).unwrap(), Bound::Excluded(StreamId::new(5, 1)));
+    }
+
+    #[test]
+    fn inverted_ranges() {
+        let db = Db::new();
+        add(&db, NewId::Explicit(StreamId::new(1, 0)), "a");
+        add(&db, NewId::Explicit(StreamId::new(2, 0)), "b");
+        XGroup::create("s", "g", Some(StreamId::MIN), false).apply(&db);
+        XRead::new(vec![(Bytes::from("s"), ReadFrom::New)]).group("g", "alice", false).apply(&db);
+        let run = |args: &[&str]| {
+            let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+            Command::from_frame(frame).unwrap().apply(&db, &mut Protocol::Resp2)
+        };
+        assert_eq!(ids(run(&["xrange", "s", "-", "+"])), ["1-0", "2-0"]);
+        assert_eq!(ids(run(&["xrevrange", "s", "+", "-"])), ["2-0", "1-0"]);
+        for args in [&["xrange", "s", "+", "-"][..], &["xrevrange", "s", "-", "+"], &["xrange", "s", "-", "-"], &["xpending", "s", "g", "+", "-", "10"]] {
+            assert_eq!(run(args), Frame::Array(vec![]), "{:?}", args);
+        }
+        assert_eq!(ids(run(&["xpending", "s", "g", "-", "+", "10"])), ["1-0", "2-0"]);
+    }
+
+    #[test]
+    fn add_and_range() {
+        let db = Db::new();
+        assert_eq!(add(&db, NewId::Explicit(StreamId::new(1, 1)), "a"), Frame::Bulk(Bytes::from("1-1")));
+        assert_eq!(add(&db, NewId::Ms(1), "b"), Frame::Bulk(Bytes::from("1-2")));
+        assert_eq!(add(&db, NewId::Ms(3), "c"), Frame::Bulk(Bytes::from("3-0")));
+        assert!(matches!(add(&db, NewId::Explicit(StreamId::new(2, 0)), "x"), Frame::Error(err) if err.contains("equal or smaller")));
+        let Frame::Bulk(auto) = add(&db, NewId::Auto, "d") else { panic!() };
+        assert!(auto.ends_with(b"-0"));
+        assert_eq!(XLen::new("s").apply(&db), Frame::Integer(4));
+
+        let all = XRange::new("s", Bound::Unbounded, Bound::Unbounded);
+        assert_eq!(ids(all.count(2).apply(&db)), ["1-1", "1-2"]);
+        let first = XRange::new("s", Bound::Unbounded, Bound::Included(StreamId::new(1, u64::MAX))).rev().apply(&db);
+        assert_eq!(ids(first), ["1-2", "1-1"]);
+        let Frame::Array(entries) = XRange::new("s", Bound::Excluded(StreamId::new(1, 2)), Bound::Unbounded).count(1).apply(&db) else { panic!() };
+        assert_eq!(entries[0], Frame::Array(vec![Frame::Bulk(Bytes::from("3-0")), Frame::Array(vec![Frame::Bulk(Bytes::from("f")), Frame::Bulk(Bytes::from("c"))])]));
+        assert_eq!(ids(XRange::new("s", Bound::Unbounded, Bound::Unbounded).count(0).apply(&db)), Vec::<String>::new());
+        assert_eq!(ids(XRange::new("missing", Bound::Unbounded, Bound::Unbounded).apply(&db)), Vec::<String>::new());
+
+        // 添加后裁剪，只剩最新的条目
+        let trimmed = XAdd::new("s", NewId::Auto, vec![(Bytes::from("f"), Bytes::from("e"))]).trim(Trim::MaxLen(2)).apply(&db);
+        let Frame::Bulk(last) = trimmed else { panic!() };
+        assert_eq!(ids(XRange::new("s", Bound::Unbounded, Bound::Unbounded).apply(&db)), [std::str::from_utf8(&auto).unwrap(), std::str::from_utf8(&last).unwrap()]);
+        XAdd::new("s", NewId::Auto, vec![(Bytes::from("f"), Bytes::from("g"))]).trim(Trim::MinId(StreamId::MAX)).apply(&db);
+        assert_eq!(XLen::new("s").apply(&db), Frame::Integer(0));
+        // 流被裁剪为空后仍然存在
+        assert!(db.exists(b"s"));
+
+        assert_eq!(XAdd::new("new", NewId::Auto, vec![(Bytes::from("f"), Bytes::from("v"))]).nomkstream().apply(&db), Frame::Null);
+        assert!(!db.exists(b"new"));
+        db.set(Bytes::from("str"), Bytes::from("v"), None);
+        assert!(matches!(XLen::new("str").apply(&db), Frame::Error(err) if err.starts_with("WRONGTYPE")));
+    }
+
+    /// XREADGROUP 回复中的 ID
+    fn read_ids(frame: Frame) -> Vec<String> {
+        let Frame::Array(mut streams) = frame else { panic!("{:?}", frame) };
+        let Frame::Array(mut stream) = streams.remove(0) else { panic!() };
+        ids(stream.remove(1))
+    }
+
+    #[test]
+    fn consumer_groups() {
+        let db = Db::new();
+        assert!(matches!(XGroup::create("s", "g", None, false).apply(&db), Frame::Error(err) if err.contains("MKSTREAM")));
+        assert_eq!(XGroup::create("s", "g", None, true).apply(&db), Frame::Simple("OK".into()));
+        assert!(matches!(XGroup::create("s", "g", None, false).apply(&db), Frame::Error(err) if err.starts_with("BUSYGROUP")));
+        for i in 1..=3 {
+            add(&db, NewId::Explicit(StreamId::new(i, 0)), "v");
+        }
+
+        let read = |consumer: &str, from: ReadFrom| XRead::new(vec![(Bytes::from("s"), from)]).group("g", consumer.to_string(), false);
+        assert_eq!(read_ids(read("alice", ReadFrom::New).count(2).apply(&db)), ["1-0", "2-0"]);
+        assert_eq!(read_ids(read("bob", ReadFrom::New).apply(&db)), ["3-0"]);
+        // 没有新条目时回复 nil，历史总是回复
+        assert_eq!(read("bob", ReadFrom::New).apply(&db), Frame::Null);
+        assert_eq!(read_ids(read("alice", ReadFrom::After(StreamId::MIN)).apply(&db)), ["1-0", "2-0"]);
+        assert!(matches!(XRead::new(vec![(Bytes::from("s"), ReadFrom::New)]).group("nope", "c", false).apply(&db), Frame::Error(err) if err.starts_with("NOGROUP")));
+
+        let Frame::Array(summary) = XPending::new("s", "g").apply(&db) else { panic!() };
+        assert_eq!(summary[0], Frame::Integer(3));
+        assert_eq!(summary[1], Frame::Bulk(Bytes::from("1-0")));
+        assert_eq!(summary[2], Frame::Bulk(Bytes::from("3-0")));
+        assert_eq!(
+            summary[3],
+            Frame::Array(vec![
+                Frame::Array(vec![Frame::Bulk(Bytes::from("alice")), Frame::Bulk(Bytes::from("2"))]),
+                Frame::Array(vec![Frame::Bulk(Bytes::from("bob")), Frame::Bulk(Bytes::from("1"))]),
+            ])
+        );
+
+        assert_eq!(XAck::new("s", "g", vec![StreamId::new(1, 0), StreamId::new(9, 0)]).apply(&db), Frame::Integer(1));
+        assert_eq!(XAck::new("s", "missing", vec![StreamId::new(2, 0)]).apply(&db), Frame::Integer(0));
+        let Frame::Array(pending) = XPending::new("s", "g").range(Bound::Unbounded, Bound::Unbounded, 10).apply(&db) else { panic!() };
+        assert_eq!(pending.len(), 2);
+        let Frame::Array(first) = &pending[0] else { panic!() };
+        assert_eq!(first[0], Frame::Bulk(Bytes::from("2-0")));
+        assert_eq!(first[1], Frame::Bulk(Bytes::from("alice")));
+        assert_eq!(first[3], Frame::Integer(1));
+
+        // 刚投递的条目空闲时间不够，不会被认领；FORCE 可以认领不在待确认列表中的条目
+        assert_eq!(XClaim::new("s", "g", "bob", 60_000, vec![StreamId::new(2, 0)]).apply(&db), Frame::Array(vec![]));
+        let claimed = XClaim::new("s", "g", "bob", 0, vec![StreamId::new(2, 0), StreamId::new(1, 0)]).force().justid().apply(&db);
+        assert_eq!(claimed, Frame::Array(vec![Frame::Bulk(Bytes::from("2-0")), Frame::Bulk(Bytes::from("1-0"))]));
+        assert_eq!(read_ids(read("bob", ReadFrom::After(StreamId::MIN)).apply(&db)), ["1-0", "2-0", "3-0"]);
+        assert_eq!(read_ids(read("alice", ReadFrom::After(StreamId::MIN)).apply(&db)), Vec::<String>::new());
+
+        let destroy = XGroup::Destroy { key: Bytes::from("s"), group: Bytes::from("g") };
+        assert_eq!(destroy.apply(&db), Frame::Integer(1));
+        assert!(matches!(XPending::new("s", "g").apply(&db), Frame::Error(err) if err.starts_with("NOGROUP")));
+    }
+
+    #[tokio::test]
+    async fn blocking_read() {
+        let db = Db::new();
+        add(&db, NewId::Explicit(StreamId::new(1, 0)), "a");
+        let read = XRead::new(vec![(Bytes::from("s"), ReadFrom::Last)]).block(Duration::from_millis(20));
+        assert_eq!(read.clone().block_on(&db, &mut Protocol::Resp2).await, Frame::Null);
+
+        // `$` 在开始等待时确定，之后添加的条目会唤醒读取
+        let reader = {
+            let db = db.clone();
+            tokio::spawn(async move { read.block(Duration::ZERO).block_on(&db, &mut Protocol::Resp2).await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        add(&db, NewId::Explicit(StreamId::new(2, 0)), "b");
+        let Frame::Array(mut streams) = reader.await.unwrap() else { panic!() };
+        let Frame::Array(mut stream) = streams.remove(0) else { panic!() };
+        assert_eq!(ids(stream.remove(1)), ["2-0"]);
+    }
+}
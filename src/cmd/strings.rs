@@ -0,0 +1,249 @@
+//! 按字节操作字符串的命令，值保存在 SDS 中，二进制安全。
+//! int 编码的值读取时按其字符串形式处理，修改时转换回 SDS（见 [`RedisObject::as_sds_mut`]）
+
+use bytes::Bytes;
+
+use crate::{db::{Db, WrongType}, ds::perfstr::{SmartString, range_of, sds::SDS}, frame::Frame, object::{RedisObject, int_to_bytes}};
+
+use super::{Parse, ParseError};
+
+/// 字符串的最大长度，对应 redis 默认的 `proto-max-bulk-len`（512MB）
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// `APPEND key value`
+///
+/// 在字符串尾部追加 value，key 不存在时等同于 SET，返回追加后的长度
+#[derive(Debug)]
+pub struct Append {
+    key: Bytes,
+    value: Bytes,
+}
+
+impl Append {
+    pub fn new(key: impl Into<Bytes>, value: impl Into<Bytes>) -> Append {
+        Append { key: key.into(), value: value.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Append, ParseError> {
+        let key = parse.next_bytes()?;
+        let value = parse.next_bytes()?;
+        Ok(Append { key, value })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update(&self.key, |value| {
+            let sds = match value.get_or_insert_with(|| RedisObject::String(SDS::empty())).as_sds_mut() {
+                Some(sds) => sds,
+                None => return Frame::Error(WrongType.to_string()),
+            };
+            if sds.len() + self.value.len() > MAX_STRING_LEN {
+                return Frame::Error("ERR string exceeds maximum allowed size (proto-max-bulk-len)".into());
+            }
+            // 利用 SDS 的预分配，连续追加时不必每次都重新分配
+            sds.reserve(self.value.len());
+            sds.append(&self.value);
+            Frame::Integer(sds.len() as i64)
+        })
+    }
+}
+
+/// `STRLEN key`
+///
+/// 字符串的字节数，key 不存在时返回 0
+#[derive(Debug)]
+pub struct StrLen {
+    key: Bytes,
+}
+
+impl StrLen {
+    pub fn new(key: impl Into<Bytes>) -> StrLen {
+        StrLen { key: key.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<StrLen, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(StrLen { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.lookup_read(&self.key, |value| match value.map_or(Some(0), |value| string_len(value)) {
+            Some(len) => Frame::Integer(len as i64),
+            None => Frame::Error(WrongType.to_string()),
+        })
+    }
+}
+
+/// `GETRANGE key start end`
+///
+/// 返回 [start, end] 闭区间的内容，下标可以为负数，表示从尾部倒数
+#[derive(Debug)]
+pub struct GetRange {
+    key: Bytes,
+    start: i64,
+    end: i64,
+}
+
+impl GetRange {
+    pub fn new(key: impl Into<Bytes>, start: i64, end: i64) -> GetRange {
+        GetRange { key: key.into(), start, end }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetRange, ParseError> {
+        let key = parse.next_bytes()?;
+        let start = parse.next_int()?;
+        let end = parse.next_int()?;
+        Ok(GetRange { key, start, end })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.lookup_read(&self.key, |value| match value {
+            None => Frame::Bulk(Bytes::new()),
+            Some(RedisObject::String(sds)) => Frame::Bulk(Bytes::copy_from_slice(sds.get_range(self.start, self.end))),
+            Some(RedisObject::Int(n)) => {
+                let bytes = int_to_bytes(*n);
+                let range = range_of(bytes.len(), self.start, self.end);
+                Frame::Bulk(range.map_or_else(Bytes::new, |(start, end)| bytes.slice(start..end)))
+            },
+            Some(_) => Frame::Error(WrongType.to_string()),
+        })
+    }
+}
+
+/// `SETRANGE key offset value`
+///
+/// 从 offset 开始用 value 覆盖字符串，不足的部分以 0 填充，返回修改后的长度。
+/// key 不存在且 value 为空时不会创建 key
+#[derive(Debug)]
+pub struct SetRange {
+    key: Bytes,
+    offset: usize,
+    value: Bytes,
+}
+
+impl SetRange {
+    pub fn new(key: impl Into<Bytes>, offset: usize, value: impl Into<Bytes>) -> SetRange {
+        SetRange { key: key.into(), offset, value: value.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SetRange, ParseError> {
+        let key = parse.next_bytes()?;
+        let offset = usize::try_from(parse.next_int()?).map_err(|_| "ERR offset is out of range")?;
+        let value = parse.next_bytes()?;
+        Ok(SetRange { key, offset, value })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update(&self.key, |value| {
+            // value 为空时不做修改，也不转换编码
+            if self.value.is_empty() {
+                return match value.as_ref().map_or(Some(0), string_len) {
+                    Some(len) => Frame::Integer(len as i64),
+                    None => Frame::Error(WrongType.to_string()),
+                };
+            }
+            // 先检查长度，避免 key 不存在时留下一个空字符串
+            if self.offset + self.value.len() > MAX_STRING_LEN {
+                return Frame::Error("ERR string exceeds maximum allowed size (proto-max-bulk-len)".into());
+            }
+            let sds = match value.get_or_insert_with(|| RedisObject::String(SDS::empty())).as_sds_mut() {
+                Some(sds) => sds,
+                None => return Frame::Error(WrongType.to_string()),
+            };
+            // 一次分配到位，超出原长度时补齐的 0 与 value 写在同一块空间中
+            sds.reserve((self.offset + self.value.len()).saturating_sub(sds.len()));
+            sds.set_range(self.offset, &self.value);
+            Frame::Integer(sds.len() as i64)
+        })
+    }
+}
+
+/// 字符串对象的字节数，不是字符串时返回 `None`
+fn string_len(value: &RedisObject) -> Option<usize> {
+    match value {
+        RedisObject::String(sds) => Some(sds.len()),
+        RedisObject::Int(n) => Some(int_to_bytes(*n).len()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{db::Db, frame::Frame, object::{ObjectEncoding, RedisObject}, types::Hash};
+
+    use super::{Append, GetRange, SetRange, StrLen};
+
+    #[test]
+    fn getrange_negative() {
+        let db = Db::new();
+        db.set(Bytes::from("s"), Bytes::from("abc"), None);
+        db.set(Bytes::from("e"), Bytes::new(), None);
+        db.set(Bytes::from("n"), Bytes::from("12"), None);
+        let range = |key: &'static str, start, end| GetRange::new(key, start, end).apply(&db);
+        assert_eq!(range("s", -2, -1), Frame::Bulk(Bytes::from("bc")));
+        assert_eq!(range("s", 0, -4), Frame::Bulk(Bytes::new()));
+        assert_eq!(range("s", -10, -4), Frame::Bulk(Bytes::new()));
+        assert_eq!(range("e", 0, -1), Frame::Bulk(Bytes::new()));
+        assert_eq!(range("n", 0, -3), Frame::Bulk(Bytes::new()));
+        assert_eq!(range("n", 0, -1), Frame::Bulk(Bytes::from("12")));
+    }
+
+    #[test]
+    fn int_encoded() {
+        let db = Db::new();
+        let encoding = |key: &[u8]| db.lookup_read(key, |value| value.unwrap().encoding());
+        db.set(Bytes::from("n"), Bytes::from("-123"), None);
+        assert_eq!(encoding(b"n"), ObjectEncoding::Int);
+        // 读取时不改变编码
+        assert_eq!(StrLen::new("n").apply(&db), Frame::Integer(4));
+        assert_eq!(GetRange::new("n", 1, 2).apply(&db), Frame::Bulk(Bytes::from("12")));
+        assert_eq!(encoding(b"n"), ObjectEncoding::Int);
+        // 按字节修改时转换回 raw 编码，结果仍是数字也不再转换为 int
+        assert_eq!(Append::new("n", "4").apply(&db), Frame::Integer(5));
+        assert_eq!(encoding(b"n"), ObjectEncoding::Raw);
+        assert_eq!(db.get(b"n").unwrap(), Some(Bytes::from("-1234")));
+        db.set(Bytes::from("m"), Bytes::from("100"), None);
+        assert_eq!(SetRange::new("m", 0, "9").apply(&db), Frame::Integer(3));
+        assert_eq!(encoding(b"m"), ObjectEncoding::Raw);
+        assert_eq!(db.get(b"m").unwrap(), Some(Bytes::from("900")));
+        // value 为空的 SETRANGE 不做修改
+        db.set(Bytes::from("k"), Bytes::from("7"), None);
+        assert_eq!(SetRange::new("k", 3, "").apply(&db), Frame::Integer(1));
+        assert_eq!(encoding(b"k"), ObjectEncoding::Int);
+    }
+
+    #[test]
+    fn append_and_setrange() {
+        let db = Db::new();
+        assert_eq!(StrLen::new("s").apply(&db), Frame::Integer(0));
+        assert_eq!(Append::new("s", "hello").apply(&db), Frame::Integer(5));
+        assert_eq!(Append::new("s", " world").apply(&db), Frame::Integer(11));
+        assert_eq!(StrLen::new("s").apply(&db), Frame::Integer(11));
+        assert_eq!(SetRange::new("s", 6, "redis").apply(&db), Frame::Integer(11));
+        assert_eq!(db.get(b"s").unwrap(), Some(Bytes::from("hello redis")));
+        assert_eq!(GetRange::new("s", 0, 4).apply(&db), Frame::Bulk(Bytes::from("hello")));
+        assert_eq!(GetRange::new("s", 6, 100).apply(&db), Frame::Bulk(Bytes::from("redis")));
+        assert_eq!(GetRange::new("missing", 0, -1).apply(&db), Frame::Bulk(Bytes::new()));
+
+        // 超出原长度时以 0 填充
+        assert_eq!(SetRange::new("z", 3, "ab").apply(&db), Frame::Integer(5));
+        assert_eq!(db.get(b"z").unwrap(), Some(Bytes::from_static(b"\0\0\0ab")));
+        // key 不存在且 value 为空时不创建 key，超出最大长度时也不会留下空字符串
+        assert_eq!(SetRange::new("e", 10, "").apply(&db), Frame::Integer(0));
+        assert!(!db.exists(b"e"));
+        assert!(matches!(SetRange::new("e", super::MAX_STRING_LEN, "x").apply(&db), Frame::Error(_)));
+        assert!(!db.exists(b"e"));
+
+        db.update(&Bytes::from("h"), |value| *value = Some(RedisObject::Hash(Hash::new())));
+        let replies = [
+            Append::new("h", "x").apply(&db),
+            StrLen::new("h").apply(&db),
+            GetRange::new("h", 0, 1).apply(&db),
+            SetRange::new("h", 0, "x").apply(&db),
+        ];
+        for reply in replies {
+            assert!(matches!(&reply, Frame::Error(msg) if msg.starts_with("WRONGTYPE")), "{:?}", reply);
+        }
+    }
+}
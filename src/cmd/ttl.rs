@@ -0,0 +1,62 @@
+use bytes::Bytes;
+
+use crate::{db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `TTL key` / `PTTL key` / `EXPIRETIME key` / `PEXPIRETIME key`
+///
+/// 返回 key 剩余的存活时间，EXPIRETIME/PEXPIRETIME 返回过期的 unix 时间戳。key 不存在返回 -2，未设置过期时间返回 -1
+#[derive(Debug)]
+pub struct Ttl {
+    key: Bytes,
+    /// PTTL、PEXPIRETIME 以毫秒返回，TTL、EXPIRETIME 以秒返回
+    in_ms: bool,
+    /// 是否为 EXPIRETIME/PEXPIRETIME
+    absolute: bool,
+}
+
+impl Ttl {
+    pub fn new(key: impl Into<Bytes>, in_ms: bool) -> Ttl {
+        Ttl { key: key.into(), in_ms, absolute: false }
+    }
+
+    /// `EXPIRETIME key` / `PEXPIRETIME key`
+    pub fn expire_time(key: impl Into<Bytes>, in_ms: bool) -> Ttl {
+        Ttl { key: key.into(), in_ms, absolute: true }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, in_ms: bool, absolute: bool) -> Result<Ttl, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(Ttl { key, in_ms, absolute })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match (self.in_ms, self.absolute) {
+            (false, false) => "ttl",
+            (true, false) => "pttl",
+            (false, true) => "expiretime",
+            (true, true) => "pexpiretime",
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        if self.absolute {
+            let when = match db.expire_time(&self.key) {
+                None => -2,
+                Some(None) => -1,
+                Some(Some(when)) if self.in_ms => when as i64,
+                Some(Some(when)) => (when / 1000) as i64,
+            };
+            return Frame::Integer(when);
+        }
+        let ttl = match db.ttl(&self.key) {
+            None => -2,
+            Some(None) => -1,
+            Some(Some(ms)) if self.in_ms => ms as i64,
+            // 与 redis 一样四舍五入到秒
+            Some(Some(ms)) => ((ms + 500) / 1000) as i64,
+        };
+        Frame::Integer(ttl)
+    }
+}
@@ -0,0 +1,41 @@
+use crate::frame::Frame;
+
+/// 未支持的命令
+#[derive(Debug)]
+pub struct Unknown {
+    command_name: String,
+}
+
+impl Unknown {
+    pub(crate) fn new(key: impl ToString) -> Unknown {
+        Unknown { command_name: key.to_string() }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.command_name
+    }
+
+    pub(crate) fn apply(self) -> Frame {
+        Frame::Error(format!("ERR unknown command '{}'", self.command_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{cmd::Command, db::Db, frame::{Frame, Protocol}};
+
+    #[test]
+    fn unknown_command() {
+        let frame = Frame::Array(["FooBar", "a"].iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+        let cmd = Command::from_frame(frame).unwrap();
+        // 命令名转换为小写，参数不会被解析
+        let Command::Unknown(unknown) = &cmd else { panic!("{:?}", cmd) };
+        assert_eq!(unknown.get_name(), "foobar");
+        assert_eq!(cmd.get_name(), "foobar");
+        let db = Db::new();
+        assert_eq!(cmd.apply(&db, &mut Protocol::Resp2), Frame::Error("ERR unknown command 'foobar'".into()));
+        assert_eq!(db.dbsize(), 0);
+    }
+}
@@ -0,0 +1,123 @@
+//! `WAIT` 命令，等待副本通过 `REPLCONF ACK` 确认之前的写入，见 [`crate::replication`]
+
+use std::time::Duration;
+
+use crate::{db::Db, frame::Frame};
+
+use super::{Parse, ParseError};
+
+/// `WAIT numreplicas timeout`，等待之前的写入被 numreplicas 个副本确认，返回确认的副本数。
+/// timeout 为毫秒，0 表示一直等待。超时时同样返回当时已经确认的副本数
+#[derive(Debug)]
+pub struct Wait {
+    numreplicas: i64,
+    /// `None` 表示一直等待
+    timeout: Option<Duration>,
+}
+
+impl Wait {
+    pub fn new(numreplicas: i64, timeout: Option<Duration>) -> Wait {
+        Wait { numreplicas, timeout }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Wait, ParseError> {
+        let numreplicas = parse.next_int()?;
+        let timeout = parse.next_int().map_err(|err| match err {
+            ParseError::EndOfStream => err,
+            _ => "ERR timeout is not an integer or out of range".into(),
+        })?;
+        let timeout = match timeout {
+            ..0 => return Err("ERR timeout is negative".into()),
+            0 => None,
+            ms => Some(Duration::from_millis(ms as u64)),
+        };
+        Ok(Wait { numreplicas, timeout })
+    }
+
+    /// numreplicas 为正数时可能需要等待，由连接的处理循环调用 [`Wait::block_on`]
+    pub fn is_blocking(&self) -> bool {
+        self.numreplicas > 0
+    }
+
+    /// 等到 numreplicas 个副本确认了 offset 或者超时，回复确认了 offset 的副本数。
+    /// offset 为这个连接最后一次写入之后主节点的复制偏移量
+    pub async fn block_on(self, db: &Db, offset: u64) -> Frame {
+        let replication = db.replication();
+        let wait = replication.wait_for(offset, self.numreplicas as usize);
+        let acked = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, wait).await.unwrap_or_else(|_| replication.acked(offset)),
+            None => wait.await,
+        };
+        Frame::Integer(acked as i64)
+    }
+
+    /// 不需要等待，或者在不能阻塞的事务、脚本中执行时，立即回复已经确认了当前偏移量的副本数
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let replication = db.replication();
+        Frame::Integer(replication.acked(replication.offset()) as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use tokio::time::{Instant, timeout};
+
+    use crate::{cmd::Command, db::Db, frame::Frame};
+
+    use super::Wait;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+        Command::from_frame(frame).map_err(|err| err.to_string())
+    }
+
+    #[tokio::test]
+    async fn block_until_timeout() {
+        let db = Db::new();
+        let Ok(Command::Wait(wait)) = parse(&["wait", "0", "0"]) else { panic!() };
+        assert!(!wait.is_blocking());
+        assert_eq!(wait.apply(&db), Frame::Integer(0));
+
+        // 没有副本确认时等到超时，回复 0
+        let Ok(Command::Wait(wait)) = parse(&["wait", "1", "50"]) else { panic!() };
+        assert!(wait.is_blocking());
+        let started = Instant::now();
+        assert_eq!(wait.block_on(&db, 0).await, Frame::Integer(0));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        // timeout 为 0 时一直等待
+        assert!(timeout(Duration::from_millis(50), Wait::new(1, None).block_on(&db, 0)).await.is_err());
+
+        assert_eq!(parse(&["wait", "1", "-1"]).unwrap_err(), "ERR timeout is negative");
+        assert_eq!(parse(&["wait", "1", "x"]).unwrap_err(), "ERR timeout is not an integer or out of range");
+        assert_eq!(parse(&["wait", "x", "0"]).unwrap_err(), "ERR value is not an integer or out of range");
+        assert!(parse(&["wait", "1"]).unwrap_err().contains("wrong number of arguments"));
+    }
+
+    #[tokio::test]
+    async fn acked_replicas() {
+        let db = Db::new();
+        let replication = db.replication();
+        let offset = replication.advance(100);
+        replication.ack(1, 100);
+        replication.ack(2, 60);
+        // 已经有足够的副本确认时立即返回
+        assert_eq!(Wait::new(1, None).block_on(&db, offset).await, Frame::Integer(1));
+        assert_eq!(Wait::new(1, None).apply(&db), Frame::Integer(1));
+        // 超时时回复已经确认的副本数
+        let wait = Wait::new(3, Some(Duration::from_millis(30)));
+        assert_eq!(wait.block_on(&db, offset).await, Frame::Integer(1));
+
+        // 等待期间副本确认了新的偏移量
+        let wait = Wait::new(2, None).block_on(&db, offset);
+        let ack = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            replication.ack(2, 120);
+        };
+        let (reply, _) = tokio::join!(timeout(Duration::from_secs(5), wait), ack);
+        assert_eq!(reply.unwrap(), Frame::Integer(2));
+    }
+}
@@ -0,0 +1,920 @@
+//! 有序集合相关命令，数据保存在 [`ZSet`] 中
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use crate::{db::{Db, WrongType}, ds::skiplist::{Bound, LexBound}, frame::Frame, glob, object::{RedisObject, Typed}, types::{AddFlags, AddOutcome, ZSet}};
+
+use super::{Parse, ParseError, keyspace::{ScanOptions, scan_reply}};
+
+/// 累加后的分数不是数字时的错误
+const NOT_A_NUMBER: &str = "ERR resulting score is not a number (NaN)";
+
+/// `ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]`
+///
+/// 新增 member 或更新其分数，返回新增的 member 数量，`CH` 时返回新增与分数有变化的 member 数量。
+/// `INCR` 时与 `ZINCRBY` 相同，只能有一对 score member，返回新的分数，被 NX/XX/GT/LT 跳过时返回 nil
+#[derive(Debug)]
+pub struct ZAdd {
+    key: Bytes,
+    members: Vec<(f64, Bytes)>,
+    flags: AddFlags,
+    /// 是否同时统计分数有变化的 member
+    ch: bool,
+}
+
+impl ZAdd {
+    pub fn new(key: impl Into<Bytes>, members: Vec<(f64, Bytes)>) -> ZAdd {
+        ZAdd { key: key.into(), members, flags: AddFlags::default(), ch: false }
+    }
+
+    /// 设置 NX/XX/GT/LT/INCR 选项
+    pub fn flags(mut self, flags: AddFlags) -> ZAdd {
+        self.flags = flags;
+        self
+    }
+
+    /// 返回新增与分数有变化的 member 数量
+    pub fn ch(mut self) -> ZAdd {
+        self.ch = true;
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZAdd, ParseError> {
+        let key = parse.next_bytes()?;
+        let mut flags = AddFlags::default();
+        let mut ch = false;
+        // 选项都在第一个 score 之前
+        let first = loop {
+            let arg = parse.next_string()?;
+            match arg.to_uppercase().as_str() {
+                "NX" => flags.nx = true,
+                "XX" => flags.xx = true,
+                "GT" => flags.gt = true,
+                "LT" => flags.lt = true,
+                "CH" => ch = true,
+                "INCR" => flags.incr = true,
+                _ => break to_score(&arg)?,
+            }
+        };
+        // 至少需要一对 score member
+        let mut members = vec![(first, parse.next_bytes()?)];
+        while parse.has_remaining() {
+            let score = parse_score(parse)?;
+            if !parse.has_remaining() {
+                return Err("ERR syntax error".into());
+            }
+            members.push((score, parse.next_bytes()?));
+        }
+        if flags.nx && flags.xx {
+            return Err("ERR XX and NX options at the same time are not compatible".into());
+        }
+        if [flags.nx, flags.gt, flags.lt].into_iter().filter(|&set| set).count() > 1 {
+            return Err("ERR GT, LT, and/or NX options at the same time are not compatible".into());
+        }
+        if flags.incr && members.len() > 1 {
+            return Err("ERR INCR option supports a single increment-element pair".into());
+        }
+        Ok(ZAdd { key, members, flags, ch })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let limits = db.encoding_limits().zset;
+        db.update_typed(&self.key, |value| {
+            let zset = value.get_or_insert_with(ZSet::new);
+            let mut changed = 0;
+            let mut reply = Frame::Null;
+            for (score, member) in self.members {
+                match zset.add(member, score, self.flags, &limits) {
+                    AddOutcome::Added(score) => {
+                        changed += 1;
+                        reply = Frame::Bulk(format_score(score));
+                    },
+                    AddOutcome::Updated(score) => {
+                        if self.ch {
+                            changed += 1;
+                        }
+                        reply = Frame::Bulk(format_score(score));
+                    },
+                    AddOutcome::Unchanged(score) => reply = Frame::Bulk(format_score(score)),
+                    AddOutcome::Skipped => {},
+                    AddOutcome::NotANumber => return Frame::Error(NOT_A_NUMBER.into()),
+                }
+            }
+            // XX 时 key 不存在的话不会新建
+            if zset.is_empty() {
+                *value = None;
+            }
+            if self.flags.incr {
+                reply
+            } else {
+                Frame::Integer(changed)
+            }
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `ZINCRBY key increment member`
+///
+/// 给 member 的分数加上增量，member 不存在时视为 0，返回新的分数
+#[derive(Debug)]
+pub struct ZIncrBy {
+    key: Bytes,
+    increment: f64,
+    member: Bytes,
+}
+
+impl ZIncrBy {
+    pub fn new(key: impl Into<Bytes>, increment: f64, member: impl Into<Bytes>) -> ZIncrBy {
+        ZIncrBy { key: key.into(), increment, member: member.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZIncrBy, ParseError> {
+        let key = parse.next_bytes()?;
+        let increment = parse_score(parse)?;
+        let member = parse.next_bytes()?;
+        Ok(ZIncrBy { key, increment, member })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let flags = AddFlags { incr: true, ..AddFlags::default() };
+        ZAdd::new(self.key, vec![(self.increment, self.member)]).flags(flags).apply(db)
+    }
+}
+
+/// `ZREM key member [member ...]`
+///
+/// 删除 member，返回实际删除的数量。集合为空时 key 也会被删除
+#[derive(Debug)]
+pub struct ZRem {
+    key: Bytes,
+    members: Vec<Bytes>,
+}
+
+impl ZRem {
+    pub fn new(key: impl Into<Bytes>, members: Vec<Bytes>) -> ZRem {
+        ZRem { key: key.into(), members }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZRem, ParseError> {
+        let key = parse.next_bytes()?;
+        let mut members = vec![parse.next_bytes()?];
+        while parse.has_remaining() {
+            members.push(parse.next_bytes()?);
+        }
+        Ok(ZRem { key, members })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update_typed(&self.key, |value: &mut Option<ZSet>| {
+            let Some(zset) = value else {
+                return Frame::Integer(0);
+            };
+            let removed = self.members
+                .iter()
+                .filter(|member| zset.remove(member))
+                .count();
+            if zset.is_empty() {
+                *value = None;
+            }
+            Frame::Integer(removed as i64)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `ZSCORE key member`，返回 member 的分数，不存在时返回 nil
+#[derive(Debug)]
+pub struct ZScore {
+    key: Bytes,
+    member: Bytes,
+}
+
+impl ZScore {
+    pub fn new(key: impl Into<Bytes>, member: impl Into<Bytes>) -> ZScore {
+        ZScore { key: key.into(), member: member.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZScore, ParseError> {
+        let key = parse.next_bytes()?;
+        let member = parse.next_bytes()?;
+        Ok(ZScore { key, member })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_zset(db, &self.key, |zset| {
+            match zset.and_then(|zset| zset.score(&self.member)) {
+                Some(score) => Frame::Bulk(format_score(score)),
+                None => Frame::Null,
+            }
+        })
+    }
+}
+
+/// `ZSCAN key cursor [MATCH pattern] [COUNT count]`
+///
+/// 增量遍历有序集合，回复 `[下一次的 cursor, [member score ...]]`，语义同 `SCAN`。
+/// ziplist 编码时一次返回全部
+#[derive(Debug)]
+pub struct ZScan {
+    key: Bytes,
+    options: ScanOptions,
+}
+
+impl ZScan {
+    pub fn new(key: impl Into<Bytes>, cursor: u64) -> ZScan {
+        ZScan { key: key.into(), options: ScanOptions::new(cursor) }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZScan, ParseError> {
+        let key = parse.next_bytes()?;
+        let options = ScanOptions::parse(parse, |_, _| Ok(false))?;
+        Ok(ZScan { key, options })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let ScanOptions { cursor, pattern, count } = self.options;
+        with_zset(db, &self.key, |zset| {
+            let (cursor, entries) = match zset {
+                Some(zset) => zset.scan(cursor, count, |member| {
+                    pattern.as_ref().is_none_or(|pattern| glob::matches(pattern, member))
+                }),
+                None => (0, vec![]),
+            };
+            let mut frames = Vec::with_capacity(entries.len() * 2);
+            for (member, score) in entries {
+                frames.push(Frame::Bulk(member));
+                frames.push(Frame::Bulk(format_score(score)));
+            }
+            scan_reply(cursor, frames)
+        })
+    }
+}
+
+/// `ZCARD key`，返回集合中的 member 数量
+#[derive(Debug)]
+pub struct ZCard {
+    key: Bytes,
+}
+
+impl ZCard {
+    pub fn new(key: impl Into<Bytes>) -> ZCard {
+        ZCard { key: key.into() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZCard, ParseError> {
+        let key = parse.next_bytes()?;
+        Ok(ZCard { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_zset(db, &self.key, |zset| {
+            Frame::Integer(zset.map_or(0, |zset| zset.len()) as i64)
+        })
+    }
+}
+
+/// `ZCOUNT key min max`，返回分数在范围内的 member 数量。
+///
+/// min/max 默认包含边界，以 `(` 开头表示不包含，也可以是 `-inf`/`+inf`
+#[derive(Debug)]
+pub struct ZCount {
+    key: Bytes,
+    min: Bound,
+    max: Bound,
+}
+
+impl ZCount {
+    pub fn new(key: impl Into<Bytes>, min: Bound, max: Bound) -> ZCount {
+        ZCount { key: key.into(), min, max }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZCount, ParseError> {
+        let key = parse.next_bytes()?;
+        let min = parse_bound(parse)?;
+        let max = parse_bound(parse)?;
+        Ok(ZCount { key, min, max })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_zset(db, &self.key, |zset| {
+            let count = zset.map_or(0, |zset| zset.count(Some(self.min), Some(self.max)));
+            Frame::Integer(count as i64)
+        })
+    }
+}
+
+/// `ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]`
+///
+/// 按分数从小到大返回范围内的 member，min/max 的格式同 `ZCOUNT`。
+/// `count` 为负数表示不限制数量
+#[derive(Debug)]
+pub struct ZRangeByScore {
+    key: Bytes,
+    min: Bound,
+    max: Bound,
+    with_scores: bool,
+    /// (offset, count)
+    limit: Option<(i64, i64)>,
+}
+
+impl ZRangeByScore {
+    pub fn new(key: impl Into<Bytes>, min: Bound, max: Bound) -> ZRangeByScore {
+        ZRangeByScore { key: key.into(), min, max, with_scores: false, limit: None }
+    }
+
+    /// 同时返回分数
+    pub fn with_scores(mut self) -> ZRangeByScore {
+        self.with_scores = true;
+        self
+    }
+
+    /// 跳过前 offset 个，最多返回 count 个
+    pub fn limit(mut self, offset: i64, count: i64) -> ZRangeByScore {
+        self.limit = Some((offset, count));
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZRangeByScore, ParseError> {
+        let key = parse.next_bytes()?;
+        let min = parse_bound(parse)?;
+        let max = parse_bound(parse)?;
+        let mut cmd = ZRangeByScore::new(key, min, max);
+        while parse.has_remaining() {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "WITHSCORES" => cmd.with_scores = true,
+                "LIMIT" if parse.has_remaining() => {
+                    let offset = parse.next_int()?;
+                    if !parse.has_remaining() {
+                        return Err("ERR syntax error".into());
+                    }
+                    cmd.limit = Some((offset, parse.next_int()?));
+                },
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        Ok(cmd)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let (offset, limit) = match self.limit {
+            // 与 redis 一致，offset 为负数时返回空
+            Some((offset, _)) if offset < 0 => return Frame::Array(vec![]),
+            Some((_, 0)) => return Frame::Array(vec![]),
+            Some((offset, count)) if count < 0 => (offset as usize, 0),
+            Some((offset, count)) => (offset as usize, count as usize),
+            None => (0, 0),
+        };
+        with_zset(db, &self.key, |zset| {
+            let range = match zset {
+                Some(zset) => zset.range_by_score(Some(self.min), Some(self.max), offset, limit),
+                None => vec![],
+            };
+            range_frame(range, self.with_scores)
+        })
+    }
+}
+
+/// `ZLEXCOUNT key min max`，所有分数都相同时，返回 member 在字典序范围内的数量。
+///
+/// min/max 以 `[` 开头表示包含边界，以 `(` 开头表示不包含，`-`/`+` 表示无穷小/无穷大
+#[derive(Debug)]
+pub struct ZLexCount {
+    key: Bytes,
+    min: LexLimit,
+    max: LexLimit,
+}
+
+impl ZLexCount {
+    pub fn new(key: impl Into<Bytes>, min: LexLimit, max: LexLimit) -> ZLexCount {
+        ZLexCount { key: key.into(), min, max }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZLexCount, ParseError> {
+        let key = parse.next_bytes()?;
+        let min = parse_lex_limit(parse)?;
+        let max = parse_lex_limit(parse)?;
+        Ok(ZLexCount { key, min, max })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_zset(db, &self.key, |zset| {
+            let count = match (zset, lex_range(self.min, self.max)) {
+                (Some(zset), Some((min, max))) => zset.lex_count(min, max),
+                _ => 0,
+            };
+            Frame::Integer(count as i64)
+        })
+    }
+}
+
+/// `ZRANGEBYLEX key min max [LIMIT offset count]`
+///
+/// 所有分数都相同时，按字典序返回范围内的 member，min/max 的格式同 `ZLEXCOUNT`。
+/// `count` 为负数表示不限制数量
+#[derive(Debug)]
+pub struct ZRangeByLex {
+    key: Bytes,
+    min: LexLimit,
+    max: LexLimit,
+    /// (offset, count)
+    limit: Option<(i64, i64)>,
+}
+
+impl ZRangeByLex {
+    pub fn new(key: impl Into<Bytes>, min: LexLimit, max: LexLimit) -> ZRangeByLex {
+        ZRangeByLex { key: key.into(), min, max, limit: None }
+    }
+
+    /// 跳过前 offset 个，最多返回 count 个
+    pub fn limit(mut self, offset: i64, count: i64) -> ZRangeByLex {
+        self.limit = Some((offset, count));
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZRangeByLex, ParseError> {
+        let key = parse.next_bytes()?;
+        let min = parse_lex_limit(parse)?;
+        let max = parse_lex_limit(parse)?;
+        let mut cmd = ZRangeByLex::new(key, min, max);
+        while parse.has_remaining() {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "LIMIT" if parse.has_remaining() => {
+                    let offset = parse.next_int()?;
+                    if !parse.has_remaining() {
+                        return Err("ERR syntax error".into());
+                    }
+                    cmd.limit = Some((offset, parse.next_int()?));
+                },
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        Ok(cmd)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let (offset, limit) = match self.limit {
+            Some((offset, _)) if offset < 0 => return Frame::Array(vec![]),
+            Some((_, 0)) => return Frame::Array(vec![]),
+            Some((offset, count)) if count < 0 => (offset as usize, 0),
+            Some((offset, count)) => (offset as usize, count as usize),
+            None => (0, 0),
+        };
+        with_zset(db, &self.key, |zset| {
+            let range = match (zset, lex_range(self.min, self.max)) {
+                (Some(zset), Some((min, max))) => zset.range_by_lex(min, max, offset, limit),
+                _ => vec![],
+            };
+            range_frame(range, false)
+        })
+    }
+}
+
+/// 字典序范围的一端
+#[derive(Debug, Clone)]
+pub enum LexLimit {
+    /// `-`，比任何 member 都小
+    Min,
+    /// `+`，比任何 member 都大
+    Max,
+    /// `[member` 或 `(member`
+    Bound(LexBound<Bytes>),
+}
+
+/// 字典序范围的上下界，`None` 表示无穷
+type LexRange = (Option<LexBound<Bytes>>, Option<LexBound<Bytes>>);
+
+/// 转换为 [`ZSet`] 使用的上下界，`None` 表示无穷。min 为 `+` 或 max 为 `-` 时范围一定为空，返回 `None`
+fn lex_range(min: LexLimit, max: LexLimit) -> Option<LexRange> {
+    let min = match min {
+        LexLimit::Min => None,
+        LexLimit::Max => return None,
+        LexLimit::Bound(bound) => Some(bound),
+    };
+    let max = match max {
+        LexLimit::Min => return None,
+        LexLimit::Max => None,
+        LexLimit::Bound(bound) => Some(bound),
+    };
+    Some((min, max))
+}
+
+/// `ZRANK key member` / `ZREVRANK key member`
+///
+/// 返回 member 按分数从小到大（从大到小）的排名，从 0 开始，不存在时返回 nil
+#[derive(Debug)]
+pub struct ZRank {
+    key: Bytes,
+    member: Bytes,
+    /// 是否按分数从大到小排名，即 ZREVRANK
+    reverse: bool,
+}
+
+impl ZRank {
+    /// `ZRANK key member`
+    pub fn new(key: impl Into<Bytes>, member: impl Into<Bytes>) -> ZRank {
+        ZRank { key: key.into(), member: member.into(), reverse: false }
+    }
+
+    /// `ZREVRANK key member`
+    pub fn rev(key: impl Into<Bytes>, member: impl Into<Bytes>) -> ZRank {
+        ZRank { key: key.into(), member: member.into(), reverse: true }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, reverse: bool) -> Result<ZRank, ParseError> {
+        let key = parse.next_bytes()?;
+        let member = parse.next_bytes()?;
+        Ok(ZRank { key, member, reverse })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.reverse {
+            "zrevrank"
+        } else {
+            "zrank"
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_zset(db, &self.key, |zset| {
+            match zset.and_then(|zset| zset.rank(&self.member, self.reverse)) {
+                Some(rank) => Frame::Integer(rank as i64),
+                None => Frame::Null,
+            }
+        })
+    }
+}
+
+/// `ZRANGE key start stop [WITHSCORES]` / `ZREVRANGE key start stop [WITHSCORES]`
+///
+/// 按排名返回 [start, stop] 内的 member，负数表示从最后一名倒数。
+/// ZREVRANGE 按分数从大到小排名
+#[derive(Debug)]
+pub struct ZRange {
+    key: Bytes,
+    start: i64,
+    stop: i64,
+    /// 是否按分数从大到小排名，即 ZREVRANGE
+    reverse: bool,
+    with_scores: bool,
+}
+
+impl ZRange {
+    /// `ZRANGE key start stop`
+    pub fn new(key: impl Into<Bytes>, start: i64, stop: i64) -> ZRange {
+        ZRange { key: key.into(), start, stop, reverse: false, with_scores: false }
+    }
+
+    /// `ZREVRANGE key start stop`
+    pub fn rev(key: impl Into<Bytes>, start: i64, stop: i64) -> ZRange {
+        ZRange { reverse: true, ..ZRange::new(key, start, stop) }
+    }
+
+    /// 同时返回分数
+    pub fn with_scores(mut self) -> ZRange {
+        self.with_scores = true;
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, reverse: bool) -> Result<ZRange, ParseError> {
+        let key = parse.next_bytes()?;
+        let start = parse.next_int()?;
+        let stop = parse.next_int()?;
+        let mut cmd = ZRange { key, start, stop, reverse, with_scores: false };
+        if parse.has_remaining() {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "WITHSCORES" => cmd.with_scores = true,
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        Ok(cmd)
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.reverse {
+            "zrevrange"
+        } else {
+            "zrange"
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        with_zset(db, &self.key, |zset| {
+            let range = match zset {
+                Some(zset) => zset.range_by_rank(self.start, self.stop, self.reverse),
+                None => vec![],
+            };
+            range_frame(range, self.with_scores)
+        })
+    }
+}
+
+/// `ZPOPMIN key [count]` / `ZPOPMAX key [count]`
+///
+/// 删除并返回分数最小（最大）的 count 个 member 及其分数，count 默认为 1。集合为空时 key 也会被删除
+#[derive(Debug)]
+pub struct ZPop {
+    key: Bytes,
+    count: usize,
+    /// 是否弹出分数最大的，即 ZPOPMAX
+    max: bool,
+}
+
+impl ZPop {
+    /// `ZPOPMIN key`
+    pub fn min(key: impl Into<Bytes>) -> ZPop {
+        ZPop { key: key.into(), count: 1, max: false }
+    }
+
+    /// `ZPOPMAX key`
+    pub fn max(key: impl Into<Bytes>) -> ZPop {
+        ZPop { key: key.into(), count: 1, max: true }
+    }
+
+    /// 最多弹出 count 个 member
+    pub fn count(mut self, count: usize) -> ZPop {
+        self.count = count;
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, max: bool) -> Result<ZPop, ParseError> {
+        let key = parse.next_bytes()?;
+        let count = if parse.has_remaining() {
+            match parse.next_int()? {
+                count if count < 0 => return Err("ERR value is out of range, must be positive".into()),
+                count => count as usize,
+            }
+        } else {
+            1
+        };
+        Ok(ZPop { key, count, max })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.max {
+            "zpopmax"
+        } else {
+            "zpopmin"
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update_typed(&self.key, |value: &mut Option<ZSet>| {
+            let Some(zset) = value else {
+                return Frame::Array(vec![]);
+            };
+            let popped = zset.pop(self.count, self.max);
+            if zset.is_empty() {
+                *value = None;
+            }
+            range_frame(popped, true)
+        })
+        .unwrap_or_else(Frame::from)
+    }
+}
+
+/// `ZUNIONSTORE` / `ZINTERSTORE` 合并分数的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // 与 redis 一样，`+inf` 加 `-inf` 的结果记为 0
+            Aggregate::Sum => zero_if_nan(a + b),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+/// `ZUNIONSTORE destination numkeys key [key ...] [WEIGHTS weight [weight ...]] [AGGREGATE SUM | MIN | MAX]` /
+/// `ZINTERSTORE ...`
+///
+/// 计算多个有序集合的并集（交集），保存到 destination，返回结果的 member 数量。
+/// 普通集合视为所有 member 的分数都为 1，不存在的 key 视为空集合。
+/// 每个集合的分数先乘以对应的权重（默认为 1），同一 member 的多个分数按 AGGREGATE 合并（默认求和）。
+/// destination 原有的值（以及过期时间）被覆盖，结果为空时删除 destination
+#[derive(Debug)]
+pub struct ZStore {
+    destination: Bytes,
+    keys: Vec<Bytes>,
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+    /// 是否求交集，即 ZINTERSTORE
+    inter: bool,
+}
+
+impl ZStore {
+    /// `ZUNIONSTORE destination numkeys key [key ...]`
+    pub fn union(destination: impl Into<Bytes>, keys: Vec<Bytes>) -> ZStore {
+        let weights = vec![1f64; keys.len()];
+        ZStore { destination: destination.into(), keys, weights, aggregate: Aggregate::Sum, inter: false }
+    }
+
+    /// `ZINTERSTORE destination numkeys key [key ...]`
+    pub fn inter(destination: impl Into<Bytes>, keys: Vec<Bytes>) -> ZStore {
+        ZStore { inter: true, ..ZStore::union(destination, keys) }
+    }
+
+    /// 设置各个集合的权重，个数需要与 key 相同
+    pub fn weights(mut self, weights: Vec<f64>) -> ZStore {
+        assert_eq!(weights.len(), self.keys.len(), "the number of weights must match the number of keys");
+        self.weights = weights;
+        self
+    }
+
+    pub fn aggregate(mut self, aggregate: Aggregate) -> ZStore {
+        self.aggregate = aggregate;
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse, inter: bool) -> Result<ZStore, ParseError> {
+        let destination = parse.next_bytes()?;
+        let numkeys = parse.next_int()?;
+        let name = if inter { "zinterstore" } else { "zunionstore" };
+        if numkeys <= 0 {
+            return Err(format!("ERR at least 1 input key is needed for '{}' command", name).into());
+        }
+        // key 的数量多于实际参数时是语法错误，而不是参数个数错误
+        let syntax_error = |err| match err {
+            ParseError::EndOfStream => "ERR syntax error".into(),
+            err => err,
+        };
+        let mut keys = vec![];
+        for _ in 0..numkeys {
+            keys.push(parse.next_bytes().map_err(syntax_error)?);
+        }
+        let mut zstore = ZStore::union(destination, keys);
+        zstore.inter = inter;
+        while parse.has_remaining() {
+            let option = parse.next_string()?;
+            match option.to_uppercase().as_str() {
+                "WEIGHTS" => {
+                    for weight in zstore.weights.iter_mut() {
+                        let arg = parse.next_string().map_err(syntax_error)?;
+                        *weight = match arg.parse::<f64>() {
+                            Ok(w) if !w.is_nan() => w,
+                            _ => return Err("ERR weight value is not a float".into()),
+                        };
+                    }
+                },
+                "AGGREGATE" => {
+                    zstore.aggregate = match parse.next_string().map_err(syntax_error)?.to_uppercase().as_str() {
+                        "SUM" => Aggregate::Sum,
+                        "MIN" => Aggregate::Min,
+                        "MAX" => Aggregate::Max,
+                        _ => return Err("ERR syntax error".into()),
+                    };
+                },
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        Ok(zstore)
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        if self.inter {
+            "zinterstore"
+        } else {
+            "zunionstore"
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        // 先读出所有集合，同时检查类型
+        let mut sources = Vec::with_capacity(self.keys.len());
+        for (key, weight) in self.keys.iter().zip(self.weights) {
+            match scored_members(db, key) {
+                Ok(members) => sources.push((members, weight)),
+                Err(err) => return Frame::Error(err.to_string()),
+            }
+        }
+        let weighted = |score: f64, weight: f64| zero_if_nan(score * weight);
+        let mut result: HashMap<Bytes, f64> = HashMap::new();
+        if self.inter {
+            // 从最小的集合出发，依次用其他集合过滤
+            sources.sort_by_key(|(members, _)| members.len());
+            let mut sources = sources.into_iter();
+            if let Some((members, weight)) = sources.next() {
+                result.extend(members.into_iter().map(|(member, score)| (member, weighted(score, weight))));
+            }
+            for (members, weight) in sources {
+                if result.is_empty() {
+                    break;
+                }
+                let members: HashMap<Bytes, f64> = members.into_iter().collect();
+                result.retain(|member, score| match members.get(member) {
+                    Some(other) => {
+                        *score = self.aggregate.apply(*score, weighted(*other, weight));
+                        true
+                    },
+                    None => false,
+                });
+            }
+        } else {
+            for (members, weight) in sources {
+                for (member, score) in members {
+                    let score = weighted(score, weight);
+                    result
+                        .entry(member)
+                        .and_modify(|current| *current = self.aggregate.apply(*current, score))
+                        .or_insert(score);
+                }
+            }
+        }
+        let len = result.len();
+        if result.is_empty() {
+            db.del(&self.destination);
+        } else {
+            let zset = ZSet::from_members(result, &db.encoding_limits().zset);
+            db.insert(self.destination, zset.into_object(), None);
+        }
+        Frame::Integer(len as i64)
+    }
+}
+
+/// key 对应集合中所有的 (member, score)。普通集合的分数都为 1，key 不存在时为空
+fn scored_members(db: &Db, key: &[u8]) -> Result<Vec<(Bytes, f64)>, WrongType> {
+    db.lookup_read(key, |value| match value {
+        Some(RedisObject::ZSet(zset)) => Ok(zset.range_by_rank(0, -1, false)),
+        Some(RedisObject::Set(set)) => Ok(set.members().into_iter().map(|member| (member, 1f64)).collect()),
+        Some(_) => Err(WrongType),
+        None => Ok(vec![]),
+    })
+}
+
+/// 乘以权重、求和时 `inf` 与 0、`+inf` 与 `-inf` 运算的结果为 NaN，与 redis 一样记为 0
+fn zero_if_nan(score: f64) -> f64 {
+    if score.is_nan() {
+        0f64
+    } else {
+        score
+    }
+}
+
+/// 以只读方式访问 key 对应的有序集合，key 不存在时 f 收到 `None`，类型不符时回复 WRONGTYPE 错误
+fn with_zset(db: &Db, key: &[u8], f: impl FnOnce(Option<&mut ZSet>) -> Frame) -> Frame {
+    db.with_typed(key, f).unwrap_or_else(Frame::from)
+}
+
+/// 范围查询的回复，`with_scores` 时 member 之后紧跟其分数
+fn range_frame(range: Vec<(Bytes, f64)>, with_scores: bool) -> Frame {
+    let mut frames = Vec::with_capacity(range.len() * if with_scores { 2 } else { 1 });
+    for (member, score) in range {
+        frames.push(Frame::Bulk(member));
+        if with_scores {
+            frames.push(Frame::Bulk(format_score(score)));
+        }
+    }
+    Frame::Array(frames)
+}
+
+/// 解析分数，支持 `inf`/`+inf`/`-inf`
+fn parse_score(parse: &mut Parse) -> Result<f64, ParseError> {
+    parse.next_float()
+}
+
+fn to_score(s: &str) -> Result<f64, ParseError> {
+    match s.parse::<f64>() {
+        Ok(score) if !score.is_nan() => Ok(score),
+        _ => Err("ERR value is not a valid float".into()),
+    }
+}
+
+/// 解析范围边界，`(` 开头表示不包含边界
+fn parse_bound(parse: &mut Parse) -> Result<Bound, ParseError> {
+    let s = parse.next_string()?;
+    let (s, exclusive) = match s.strip_prefix('(') {
+        Some(s) => (s, true),
+        None => (s.as_str(), false),
+    };
+    match s.parse::<f64>() {
+        Ok(bound) if !bound.is_nan() => Ok(Bound::new(bound, exclusive)),
+        _ => Err("ERR min or max is not a float".into()),
+    }
+}
+
+/// 解析字典序范围的一端，见 [`LexLimit`]
+fn parse_lex_limit(parse: &mut Parse) -> Result<LexLimit, ParseError> {
+    let s = parse.next_bytes()?;
+    match s.first() {
+        Some(b'-') if s.len() == 1 => Ok(LexLimit::Min),
+        Some(b'+') if s.len() == 1 => Ok(LexLimit::Max),
+        Some(b'[') => Ok(LexLimit::Bound(LexBound::new_inclusive(s.slice(1..)))),
+        Some(b'(') => Ok(LexLimit::Bound(LexBound::new_exclusive(s.slice(1..)))),
+        _ => Err("ERR min or max not valid string range item".into()),
+    }
+}
+
+/// 分数回复给客户端时的格式，整数不带小数点，无穷为 `inf`/`-inf`
+fn format_score(score: f64) -> Bytes {
+    Bytes::from(score.to_string())
+}
@@ -0,0 +1,423 @@
+//! 服务端配置。
+//!
+//! 配置文件的格式与 redis.conf 相同：每行一个 `名称 值`，`#` 开头的行为注释，值可以用双引号括起来。
+//! 启动时先读取配置文件，再用命令行中 `--名称 值` 形式的参数覆盖，如
+//! `server toyredis.conf --port 6380`。
+//!
+//! 运行期间可以通过 `CONFIG GET` 查看配置，通过 `CONFIG SET` 修改其中可以动态调整的部分，
+//! 监听地址、分片数等只在启动时生效的配置不能修改。
+
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::{cluster::SlotMap, connection::{OutputLimit, OutputLimits}, db::{DEFAULT_SHARDS, DEFAULT_SNAPSHOT_PATH}, evict::EvictionPolicy, frame::ProtocolLimits, object::EncodingLimits, slowlog};
+
+/// 默认监听的地址
+pub const DEFAULT_BIND: &str = "127.0.0.1";
+
+/// 默认监听的端口
+pub const DEFAULT_PORT: u16 = 6379;
+
+/// 所有配置项的名称，以及运行期间能否修改
+const PARAMS: &[(&str, bool)] = &[
+    ("bind", false),
+    ("port", false),
+    ("shards", false),
+    ("io-threads", false),
+    ("io-threads-pinning", false),
+    ("maxmemory", true),
+    ("maxmemory-policy", true),
+    ("appendonly", true),
+    ("dbfilename", true),
+    ("list-max-ziplist-entries", true),
+    ("list-max-ziplist-value", true),
+    ("hash-max-ziplist-entries", true),
+    ("hash-max-ziplist-value", true),
+    ("set-max-intset-entries", true),
+    ("zset-max-ziplist-entries", true),
+    ("zset-max-ziplist-value", true),
+    ("slowlog-log-slower-than", true),
+    ("slowlog-max-len", true),
+    ("client-output-buffer-limit", true),
+    ("requirepass", true),
+    ("proto-max-bulk-len", true),
+    ("proto-max-multibulk-len", true),
+    ("proto-max-nesting", true),
+    ("client-query-buffer-limit", true),
+    ("timeout", true),
+    ("activerehashing", true),
+    ("lazyfree-lazy-user-flush", true),
+    ("cluster-enabled", false),
+    ("cluster-nodes", false),
+];
+
+/// 服务端配置
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// 监听的地址
+    pub bind: String,
+    pub port: u16,
+    /// 键空间的分片数，见 [`crate::db::Db`]
+    pub shards: usize,
+    /// 处理连接的线程数。大于 1 时每个线程运行自己的运行时与监听 socket（SO_REUSEPORT），
+    /// 由内核把新连接分给各个线程，所有线程共用同一个 [`crate::db::Db`]
+    pub io_threads: usize,
+    /// io-threads 大于 1 时，是否把每个线程绑定到一个 CPU 核心
+    pub io_threads_pinning: bool,
+    /// 内存上限（字节），0 表示不限制
+    pub maxmemory: usize,
+    pub maxmemory_policy: EvictionPolicy,
+    /// 是否开启 AOF
+    pub appendonly: bool,
+    /// 快照文件的路径
+    pub dbfilename: PathBuf,
+    /// 各类型使用 ziplist、intset 编码的阈值
+    pub limits: EncodingLimits,
+    /// 执行时长超过多少微秒的命令记入慢查询日志，负数表示不记录，见 [`crate::slowlog`]
+    pub slowlog_log_slower_than: i64,
+    /// 慢查询日志最多保留的记录数
+    pub slowlog_max_len: usize,
+    /// 各类连接的输出缓冲区限制，见 [`crate::connection::Output`]
+    pub output_limits: OutputLimits,
+    /// default 用户的密码，为空时不需要密码，见 [`crate::acl`]
+    pub requirepass: String,
+    /// 解析请求时的限制，见 [`ProtocolLimits`]
+    pub proto_limits: ProtocolLimits,
+    /// 空闲超过这么多秒的连接被断开，0 表示不断开。订阅了频道的连接不受影响
+    pub timeout: u64,
+    /// 是否在后台推进键空间的 rehash，见 [`crate::db::Db`]
+    pub activerehashing: bool,
+    /// 不指定 ASYNC 或 SYNC 时，FLUSHALL、FLUSHDB 是否在后台释放旧的键空间
+    pub lazyfree_lazy_user_flush: bool,
+    /// 是否开启集群模式，见 [`crate::cluster`]。本节点的地址为 `bind:port`
+    pub cluster_enabled: bool,
+    /// 集群中各个节点负责的槽
+    pub cluster_nodes: SlotMap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: DEFAULT_BIND.to_string(),
+            port: DEFAULT_PORT,
+            shards: DEFAULT_SHARDS,
+            io_threads: 1,
+            io_threads_pinning: false,
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::default(),
+            appendonly: false,
+            dbfilename: PathBuf::from(DEFAULT_SNAPSHOT_PATH),
+            limits: EncodingLimits::default(),
+            slowlog_log_slower_than: slowlog::DEFAULT_LOG_SLOWER_THAN,
+            slowlog_max_len: slowlog::DEFAULT_MAX_LEN,
+            output_limits: OutputLimits::default(),
+            requirepass: String::new(),
+            proto_limits: ProtocolLimits::default(),
+            timeout: 0,
+            activerehashing: true,
+            lazyfree_lazy_user_flush: false,
+            cluster_enabled: false,
+            cluster_nodes: SlotMap::default(),
+        }
+    }
+}
+
+impl Config {
+    /// 解析配置文件的内容，未出现的配置项使用默认值
+    pub fn parse(text: &str) -> crate::Result<Config> {
+        let mut config = Config::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            config.set(name, value).map_err(|err| format!("config file line {}: {}", i + 1, err))?;
+        }
+        Ok(config)
+    }
+
+    /// 读取配置文件
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Config> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|err| format!("can't open config file '{}': {}", path.display(), err))?;
+        Config::parse(&text)
+    }
+
+    /// 从命令行参数中读取配置：第一个参数不以 `--` 开头时为配置文件的路径，
+    /// 之后的 `--名称 值` 覆盖配置文件中的值
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> crate::Result<Config> {
+        let mut args = args.into_iter().peekable();
+        let mut config = match args.next_if(|arg| !arg.starts_with("--")) {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        while let Some(arg) = args.next() {
+            let name = arg.strip_prefix("--").ok_or_else(|| format!("unexpected argument '{}'", arg))?;
+            let value = args.next().ok_or_else(|| format!("--{} requires a value", name))?;
+            config.set(name, &value)?;
+        }
+        Ok(config)
+    }
+
+    /// 监听的地址，`bind:port`
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.bind, self.port)
+    }
+
+    /// 所有名称匹配 glob 模式的配置项及其值
+    pub fn get(&self, pattern: &[u8]) -> Vec<(&'static str, String)> {
+        PARAMS
+            .iter()
+            .filter(|(name, _)| crate::glob::matches(pattern, name.as_bytes()))
+            .map(|(name, _)| (*name, self.value(name)))
+            .collect()
+    }
+
+    /// 修改配置项，名称不区分大小写
+    pub fn set(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        let limits = &mut self.limits;
+        let proto = &mut self.proto_limits;
+        match name.to_lowercase().as_str() {
+            "bind" => self.bind = value.to_string(),
+            "port" => self.port = parse_number(value)?,
+            "shards" => match parse_number(value)? {
+                0 => return Err("shards must be positive".into()),
+                shards => self.shards = shards,
+            },
+            "io-threads" => match parse_number(value)? {
+                0 => return Err("io-threads must be positive".into()),
+                threads => self.io_threads = threads,
+            },
+            "io-threads-pinning" => self.io_threads_pinning = parse_bool(value)?,
+            "maxmemory" => self.maxmemory = parse_memory(value)?,
+            "maxmemory-policy" => self.maxmemory_policy = value.parse()?,
+            "appendonly" => self.appendonly = parse_bool(value)?,
+            "dbfilename" => self.dbfilename = PathBuf::from(value),
+            "list-max-ziplist-entries" => limits.list.max_entries = parse_number(value)?,
+            "list-max-ziplist-value" => limits.list.max_value = parse_number(value)?,
+            "hash-max-ziplist-entries" => limits.hash.max_entries = parse_number(value)?,
+            "hash-max-ziplist-value" => limits.hash.max_value = parse_number(value)?,
+            "set-max-intset-entries" => limits.set.max_entries = parse_number(value)?,
+            "zset-max-ziplist-entries" => limits.zset.max_entries = parse_number(value)?,
+            "zset-max-ziplist-value" => limits.zset.max_value = parse_number(value)?,
+            "slowlog-log-slower-than" => self.slowlog_log_slower_than = parse_number(value)?,
+            "slowlog-max-len" => self.slowlog_max_len = parse_number(value)?,
+            "client-output-buffer-limit" => self.output_limits = parse_output_limits(self.output_limits, value)?,
+            "requirepass" => self.requirepass = value.to_string(),
+            "proto-max-bulk-len" => proto.max_bulk_len = parse_memory(value)?,
+            "proto-max-multibulk-len" => proto.max_multibulk_len = parse_number(value)?,
+            "proto-max-nesting" => match parse_number(value)? {
+                0 => return Err("proto-max-nesting must be positive".into()),
+                nesting => proto.max_nesting = nesting,
+            },
+            "client-query-buffer-limit" => proto.max_query_buffer = parse_memory(value)?,
+            "timeout" => self.timeout = parse_number(value)?,
+            "activerehashing" => self.activerehashing = parse_bool(value)?,
+            "lazyfree-lazy-user-flush" => self.lazyfree_lazy_user_flush = parse_bool(value)?,
+            "cluster-enabled" => self.cluster_enabled = parse_bool(value)?,
+            "cluster-nodes" => self.cluster_nodes = SlotMap::parse(value)?,
+            _ => return Err(format!("unknown option '{}'", name).into()),
+        }
+        Ok(())
+    }
+
+    /// 运行期间修改配置项，只在启动时生效的配置项不能修改
+    pub fn set_mutable(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        let name = name.to_lowercase();
+        match PARAMS.iter().find(|(param, _)| *param == name) {
+            Some((_, true)) => self.set(&name, value),
+            Some((_, false)) => Err("can't set immutable config".into()),
+            None => Err("unknown option".into()),
+        }
+    }
+
+    fn value(&self, name: &str) -> String {
+        let limits = &self.limits;
+        match name {
+            "bind" => self.bind.clone(),
+            "port" => self.port.to_string(),
+            "shards" => self.shards.to_string(),
+            "io-threads" => self.io_threads.to_string(),
+            "io-threads-pinning" => if self.io_threads_pinning { "yes" } else { "no" }.to_string(),
+            "maxmemory" => self.maxmemory.to_string(),
+            "maxmemory-policy" => self.maxmemory_policy.to_string(),
+            "appendonly" => if self.appendonly { "yes" } else { "no" }.to_string(),
+            "dbfilename" => self.dbfilename.display().to_string(),
+            "list-max-ziplist-entries" => limits.list.max_entries.to_string(),
+            "list-max-ziplist-value" => limits.list.max_value.to_string(),
+            "hash-max-ziplist-entries" => limits.hash.max_entries.to_string(),
+            "hash-max-ziplist-value" => limits.hash.max_value.to_string(),
+            "set-max-intset-entries" => limits.set.max_entries.to_string(),
+            "zset-max-ziplist-entries" => limits.zset.max_entries.to_string(),
+            "zset-max-ziplist-value" => limits.zset.max_value.to_string(),
+            "slowlog-log-slower-than" => self.slowlog_log_slower_than.to_string(),
+            "slowlog-max-len" => self.slowlog_max_len.to_string(),
+            "client-output-buffer-limit" => {
+                let limits = &self.output_limits;
+                [("normal", limits.normal), ("pubsub", limits.pubsub)]
+                    .map(|(class, limit)| format!("{} {} {} {}", class, limit.hard, limit.soft, limit.soft_seconds))
+                    .join(" ")
+            },
+            "requirepass" => self.requirepass.clone(),
+            "proto-max-bulk-len" => self.proto_limits.max_bulk_len.to_string(),
+            "proto-max-multibulk-len" => self.proto_limits.max_multibulk_len.to_string(),
+            "proto-max-nesting" => self.proto_limits.max_nesting.to_string(),
+            "client-query-buffer-limit" => self.proto_limits.max_query_buffer.to_string(),
+            "timeout" => self.timeout.to_string(),
+            "activerehashing" => if self.activerehashing { "yes" } else { "no" }.to_string(),
+            "lazyfree-lazy-user-flush" => if self.lazyfree_lazy_user_flush { "yes" } else { "no" }.to_string(),
+            "cluster-enabled" => if self.cluster_enabled { "yes" } else { "no" }.to_string(),
+            "cluster-nodes" => self.cluster_nodes.to_string(),
+            _ => unreachable!("unknown option '{}'", name),
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> crate::Result<T> {
+    value.parse().map_err(|_| format!("argument '{}' couldn't be parsed into an integer", value).into())
+}
+
+fn parse_bool(value: &str) -> crate::Result<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(format!("argument '{}' must be 'yes' or 'no'", value).into()),
+    }
+}
+
+/// 解析内存大小，与 redis 一样支持 `k`、`kb`、`m`、`mb`、`g`、`gb` 单位（不区分大小写），
+/// 其中 `k` 为 1000，`kb` 为 1024
+fn parse_memory(value: &str) -> crate::Result<usize> {
+    let lower = value.to_lowercase();
+    let units: [(&str, usize); 6] = [
+        ("kb", 1 << 10),
+        ("mb", 1 << 20),
+        ("gb", 1 << 30),
+        ("k", 1000),
+        ("m", 1000 * 1000),
+        ("g", 1000 * 1000 * 1000),
+    ];
+    let (number, unit) = units
+        .iter()
+        .find_map(|(suffix, unit)| lower.strip_suffix(suffix).map(|number| (number, *unit)))
+        .unwrap_or((&lower, 1));
+    let number: usize = number.parse().map_err(|_| format!("argument '{}' isn't a valid memory size", value))?;
+    number.checked_mul(unit).ok_or_else(|| format!("argument '{}' is too large", value).into())
+}
+
+/// 解析 `<class> <hard> <soft> <soft seconds> [...]`，class 为 `normal` 或者 `pubsub`，
+/// 只修改出现了的 class，其他 class 保持 limits 中的值
+fn parse_output_limits(mut limits: OutputLimits, value: &str) -> crate::Result<OutputLimits> {
+    let args: Vec<_> = value.split_whitespace().collect();
+    if args.is_empty() || args.len() % 4 != 0 {
+        return Err("wrong number of arguments".into());
+    }
+    for args in args.chunks(4) {
+        let limit = match args[0].to_lowercase().as_str() {
+            "normal" => &mut limits.normal,
+            "pubsub" => &mut limits.pubsub,
+            _ => return Err(format!("invalid client class '{}'", args[0]).into()),
+        };
+        *limit = OutputLimit { hard: parse_memory(args[1])?, soft: parse_memory(args[2])?, soft_seconds: parse_number(args[3])? };
+    }
+    Ok(limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evict::EvictionPolicy;
+
+    use super::{Config, parse_memory};
+
+    #[test]
+    fn parse_file() {
+        let config = Config::parse("
+            # comment
+            bind 0.0.0.0
+            port 6380
+
+            maxmemory 100mb
+            maxmemory-policy allkeys-lru
+            appendonly yes
+            dbfilename \"my dump.rdb\"
+            HASH-MAX-ZIPLIST-ENTRIES 16
+        ").unwrap();
+        assert_eq!(config.addr(), "0.0.0.0:6380");
+        assert_eq!(config.maxmemory, 100 * 1024 * 1024);
+        assert_eq!(config.maxmemory_policy, EvictionPolicy::AllKeysLru);
+        assert!(config.appendonly);
+        assert_eq!(config.dbfilename.to_str(), Some("my dump.rdb"));
+        assert_eq!(config.limits.hash.max_entries, 16);
+        assert_eq!(config.limits.list.max_entries, 128);
+
+        let err = Config::parse("port 6379\nnosuchoption 1").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        assert!(Config::parse("port 99999").is_err());
+        assert!(Config::parse("shards 0").is_err());
+        assert!(Config::parse("io-threads 0").is_err());
+        assert!(Config::parse("cluster-nodes \"127.0.0.1:7000 0-100 127.0.0.1:7001 100\"").is_err());
+        assert!(Config::parse("appendonly maybe").is_err());
+    }
+
+    #[test]
+    fn args_override_file() {
+        let args = ["--port", "7000", "--shards", "4", "--io-threads", "2"].map(String::from);
+        let config = Config::from_args(args).unwrap();
+        assert_eq!((config.port, config.shards, config.io_threads), (7000, 4, 2));
+        assert!(Config::from_args(["--port".to_string()]).is_err());
+        assert!(Config::from_args(["/nonexistent/toyredis.conf".to_string()]).is_err());
+    }
+
+    #[test]
+    fn get_and_set() {
+        let config = Config::default();
+        assert_eq!(config.get(b"port"), vec![("port", "6379".to_string())]);
+        let names: Vec<_> = config.get(b"*-max-ziplist-entries").into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["list-max-ziplist-entries", "hash-max-ziplist-entries", "zset-max-ziplist-entries"]);
+
+        let mut config = Config::default();
+        config.set_mutable("MAXMEMORY", "1mb").unwrap();
+        assert_eq!(config.maxmemory, 1 << 20);
+        config.set_mutable("slowlog-log-slower-than", "-1").unwrap();
+        assert_eq!(config.get(b"slowlog-*"), vec![
+            ("slowlog-log-slower-than", "-1".to_string()),
+            ("slowlog-max-len", "128".to_string()),
+        ]);
+        assert!(config.set_mutable("slowlog-max-len", "-1").is_err());
+        config.set_mutable("proto-max-bulk-len", "1mb").unwrap();
+        assert_eq!(config.proto_limits.max_bulk_len, 1 << 20);
+        assert!(config.set_mutable("proto-max-nesting", "0").is_err());
+        config.set_mutable("timeout", "300").unwrap();
+        assert_eq!(config.get(b"timeout"), vec![("timeout", "300".to_string())]);
+        config.set_mutable("activerehashing", "no").unwrap();
+        assert!(!config.activerehashing);
+        assert!(config.set_mutable("activerehashing", "1").is_err());
+        config.set_mutable("lazyfree-lazy-user-flush", "yes").unwrap();
+        assert!(config.lazyfree_lazy_user_flush);
+        assert!(config.set_mutable("timeout", "-1").is_err());
+
+        assert_eq!(config.get(b"client-output-buffer-limit")[0].1, "normal 0 0 0 pubsub 33554432 8388608 60");
+        config.set_mutable("client-output-buffer-limit", "pubsub 64mb 16mb 30").unwrap();
+        assert_eq!(config.output_limits.pubsub.hard, 64 << 20);
+        assert_eq!(config.output_limits.normal.hard, 0);
+        assert!(config.set_mutable("client-output-buffer-limit", "pubsub 64mb 16mb").is_err());
+        assert!(config.set_mutable("client-output-buffer-limit", "replica 0 0 0").is_err());
+        assert!(config.set_mutable("port", "6380").is_err());
+        assert!(config.set_mutable("nosuchoption", "1").is_err());
+    }
+
+    #[test]
+    fn memory_units() {
+        assert_eq!(parse_memory("1024").unwrap(), 1024);
+        assert_eq!(parse_memory("1k").unwrap(), 1000);
+        assert_eq!(parse_memory("1KB").unwrap(), 1024);
+        assert_eq!(parse_memory("2gb").unwrap(), 2 << 30);
+        assert!(parse_memory("1tb").is_err());
+        assert!(parse_memory("-1").is_err());
+    }
+}
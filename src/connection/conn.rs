@@ -1,32 +1,75 @@
 use std::io::Cursor;
 
-use bytes::{BytesMut, Buf};
-use tokio::io::{AsyncReadExt, self, AsyncWriteExt};
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt, self, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
 use crate::Result;
 
-use crate::frame::Frame;
+use crate::frame::{Frame, Protocol, ProtocolLimits};
+
+/// 每次从 socket 读取时，缓冲区中至少预留的空间
+const READ_BUFFER_SIZE: usize = 4096;
+
+/// 解析出超过这个字节数的 frame 后，把缓冲区中剩余的数据移到新的小缓冲区，见 [`Connection::read_frame`]
+const BIG_FRAME_SIZE: usize = 32 * 1024;
 
 
 /// 对一个客户端连接的抽象，负责数据读写。redis协议可参见[这儿](https://redis.io/docs/reference/protocol-spec/)
-struct Connection {
-    stream: TcpStream,
+///
+/// 服务端把 socket 拆成读、写两半，读的一半由 `Connection<OwnedReadHalf>` 负责，
+/// 写的一半交给 [`super::Output`]，回复先进入输出队列，由单独的任务写出
+pub struct Connection<S = TcpStream> {
+    stream: S,
     /// stream 本身是面向连接的，单次读取可能不是正好一个 frame，所以需要一个缓冲区将数据暂存
     buffer: BytesMut, 
+    /// 写出 frame 时会有很多次小的写入，先编码到这里，再一次性发送
+    write_buffer: BytesMut,
+    /// 写出 frame 时使用的协议版本
+    protocol: Protocol,
+    /// 解析对端发来的 frame 时的限制
+    limits: ProtocolLimits,
 }
 
-impl Connection {
-    pub fn new(stream: TcpStream) -> Self {
-        Self { stream, buffer: BytesMut::with_capacity(4096) }
+impl<S: AsyncRead + Unpin> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream, buffer: BytesMut::with_capacity(READ_BUFFER_SIZE), write_buffer: BytesMut::new(), protocol: Protocol::default(), limits: ProtocolLimits::default() }
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// 切换协议版本，之后写出的 frame 都按新版本编码
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    /// 修改解析 frame 时的限制，对缓冲区中还没有解析的数据同样生效
+    pub fn set_limits(&mut self, limits: ProtocolLimits) {
+        self.limits = limits;
     }
 
+    /// 读取一个 frame，对端关闭连接时返回 `None`。
+    ///
+    /// 缓冲区按需增长：已经读到 bulk string 的长度时，按声明的长度预留空间，但每次最多翻倍。
+    /// 对端可以只发送请求头而不发送数据，不能据此一次分配整个 frame，否则几个请求头就能占用大量内存；
+    /// 成倍增长时大的值也只需要扩容、复制对数次。解析出大的 frame 之后缓冲区缩回默认大小，
+    /// 不会因为一次大的请求一直占用内存。缓冲区超过 [`ProtocolLimits::max_query_buffer`] 时返回错误
     pub async fn read_frame(&mut self) 
         -> Result<Option<Frame>> {
             loop {
                 // 先尝试从 buffer 中读取一个 frame
-                if let Some(frame) = self.parse_frame()? {
+                let mut needed = 0;
+                if let Some(frame) = self.parse_frame(&mut needed)? {
                     return Ok(Some(frame));
                 }
+                if needed.max(self.buffer.len()) > self.limits.max_query_buffer {
+                    return Err("query buffer limit exceeded".into());
+                }
+                // 解析出的 frame 仍引用着之前的内存，缓冲区切走数据后剩余的容量可能很小，
+                // 每次读取前保证有足够的空间，避免一次只读几十个字节
+                let wanted = needed.saturating_sub(self.buffer.len()).min(self.buffer.len() * 2);
+                self.buffer.reserve(wanted.max(READ_BUFFER_SIZE));
                 // 0 表示 EOF，即客户端关闭了连接
                 if 0 == self.stream.read_buf(&mut self.buffer).await? {
                     if self.buffer.is_empty() {
@@ -38,74 +81,28 @@ impl Connection {
             }
     }
 
-    pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
-        match frame {
-            Frame::Array(val) => {
-                self.stream.write_u8(b'*').await?;
-                self.write_decimal(val.len() as u64).await?;
-                for entry in val {
-                    self.write_value(entry).await?;
-                }
-            }
-            _ => self.write_value(frame).await?,
-            
-        }
-        self.stream.flush().await
-    }
-
-    async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
-        match frame {
-            Frame::Simple(val) => {
-                self.stream.write_u8(b'+').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(b"\r\n").await?;
-            }
-            Frame::Error(val) => {
-                self.stream.write_u8(b'-').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(b"\r\n").await?;
-            }
-            Frame::Integer(val) => {
-                self.stream.write_u8(b':').await?;
-                self.write_decimal(*val).await?;
-            }
-            Frame::Null => {
-                self.stream.write_all(b"$-1\r\n").await?;
-            }
-            Frame::Bulk(data) => {
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(data.len() as u64).await?;
-                self.stream.write_all(data).await?;
-                self.stream.write_all(b"\r\n").await?;
-            }
-            Frame::Array(_val) => todo!(),
-        }
-        Ok(())
-    }
-
-    async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
-        use std::io::Write;
-        // todo why not use u64.to_string() instead?
-        let mut buf = [0u8; 20];
-        let mut buf = Cursor::new(&mut buf[..]);
-        write!(buf, "{}", val);
-
-        let pos = buf.position() as usize;
-        self.stream.write_all(&buf.get_ref()[..pos]).await?;
-        self.stream.write_all(b"\r\n").await?;
-        Ok(())
+    /// 只从缓冲区中解析 frame，不读取 socket。缓冲区中没有完整的 frame 时返回 `None`。
+    ///
+    /// 客户端使用 pipeline 时，一次读取可能收到多条命令，可以用它把已经到达的命令都取出来
+    pub fn read_buffered_frame(&mut self) -> Result<Option<Frame>> {
+        self.parse_frame(&mut 0)
     }
 
-    fn parse_frame(&mut self) -> Result<Option<Frame>> {
+    /// 数据不完整时，needed 为缓冲区至少需要的字节数，见 [`Frame::check_needed`]
+    fn parse_frame(&mut self, needed: &mut usize) -> Result<Option<Frame>> {
         use crate::frame::Error::Incomplete;
         let mut buf = Cursor::new(&self.buffer[..]);
-        match Frame::check(&mut buf) {
+        match Frame::check_needed(&mut buf, &self.limits, needed) {
             Ok(_) => {
                 let len = buf.position() as usize;
-                // 回滚 cursor
-                buf.set_position(0);
-                let frame = Frame::parse(&mut buf)?;
-                buf.advance(len);
+                // 把完整的 frame 从缓冲区中切出来，bulk string 直接引用这块内存，不再复制
+                let mut data = self.buffer.split_to(len).freeze();
+                if len > BIG_FRAME_SIZE {
+                    // 剩余的部分与 frame 共用同一块大的内存，留着它的话，frame 释放后这块内存也不会释放
+                    let rest = std::mem::replace(&mut self.buffer, BytesMut::with_capacity(READ_BUFFER_SIZE));
+                    self.buffer.extend_from_slice(&rest);
+                }
+                let frame = Frame::parse(&mut data, &self.limits)?;
                 Ok(Some(frame))
             },
             // 数据不完整，需要从 socket 中重新读取到 buffer，再次尝试解析
@@ -114,4 +111,115 @@ impl Connection {
             Err(e) => Err(e.into()),
         }
     }
-}
\ No newline at end of file
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    /// 写出一个 frame。使用 RESP2 时，RESP3 类型会先转换为 RESP2 中对应的类型
+    pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.write_frames(std::slice::from_ref(frame)).await
+    }
+
+    /// 依次写出多个 frame，全部编码完才发送，pipeline 的多个回复可以一起发送
+    pub async fn write_frames(&mut self, frames: &[Frame]) -> io::Result<()> {
+        for frame in frames {
+            encode_frame(frame, self.protocol, &mut self.write_buffer);
+        }
+        self.stream.write_all(&self.write_buffer).await?;
+        self.write_buffer.clear();
+        Ok(())
+    }
+}
+
+/// 按 protocol 编码 frame，追加到 dst 末尾。使用 RESP2 时，RESP3 类型会先转换为 RESP2 中对应的类型
+pub fn encode_frame(frame: &Frame, protocol: Protocol, dst: &mut BytesMut) {
+    match protocol {
+        Protocol::Resp2 => frame.to_resp2().encode_as(protocol, dst),
+        Protocol::Resp3 => frame.encode(dst),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use tokio::{io::AsyncWriteExt, net::{TcpListener, TcpStream}};
+
+    use crate::frame::{Frame, Protocol, ProtocolLimits};
+
+    use super::{Connection, READ_BUFFER_SIZE};
+
+    /// 建立一对互相连接的 Connection
+    async fn pair() -> (Connection, Connection) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (Connection::new(client), Connection::new(server))
+    }
+
+    #[tokio::test]
+    async fn write_frames() {
+        let (mut client, mut server) = pair().await;
+        let frames = [
+            Frame::Simple("OK".into()),
+            Frame::Array(vec![Frame::Bulk(Bytes::from("a")), Frame::Integer(-1), Frame::Null]),
+            Frame::Push(vec![Frame::Bulk(Bytes::from("message"))]),
+        ];
+        server.write_frames(&frames).await.unwrap();
+        assert_eq!(client.read_frame().await.unwrap(), Some(frames[0].clone()));
+        assert_eq!(client.read_frame().await.unwrap(), Some(frames[1].clone()));
+        // RESP2 下 push 被转换为数组
+        assert_eq!(client.read_frame().await.unwrap(), Some(Frame::Array(vec![Frame::Bulk(Bytes::from("message"))])));
+
+        server.set_protocol(Protocol::Resp3);
+        server.write_frame(&frames[2]).await.unwrap();
+        assert_eq!(client.read_frame().await.unwrap(), Some(frames[2].clone()));
+
+        drop(server);
+        assert_eq!(client.read_frame().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn read_buffered_frames() {
+        let (mut client, mut server) = pair().await;
+        let frames: Vec<Frame> = (0..3).map(Frame::Integer).collect();
+        client.write_frames(&frames).await.unwrap();
+        assert_eq!(server.read_buffered_frame().unwrap(), None);
+        assert_eq!(server.read_frame().await.unwrap(), Some(Frame::Integer(0)));
+        // 三个 frame 很小，一次读取就会全部进入缓冲区
+        assert_eq!(server.read_buffered_frame().unwrap(), Some(Frame::Integer(1)));
+        assert_eq!(server.read_buffered_frame().unwrap(), Some(Frame::Integer(2)));
+        assert_eq!(server.read_buffered_frame().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn buffer_growth() {
+        let (mut client, mut server) = pair().await;
+        let big = Frame::Bulk(Bytes::from(vec![b'x'; 1 << 20]));
+        // 只发出头部时不按声明的长度预留空间。read_frame 被取消时已经读到的数据仍在缓冲区中
+        client.stream.write_all(b"$1048576\r\nxx").await.unwrap();
+        let timeout = std::time::Duration::from_millis(50);
+        assert!(tokio::time::timeout(timeout, server.read_frame()).await.is_err());
+        assert!(server.buffer.capacity() <= 2 * READ_BUFFER_SIZE, "{}", server.buffer.capacity());
+        // 数据到达后缓冲区随之成倍增长，仍远小于声明的长度
+        client.stream.write_all(&[b'x'; 100_000]).await.unwrap();
+        assert!(tokio::time::timeout(timeout, server.read_frame()).await.is_err());
+        assert!(server.buffer.capacity() < 1 << 19, "{}", server.buffer.capacity());
+        client.stream.write_all(&[b'x'; (1 << 20) - 100_002]).await.unwrap();
+        client.stream.write_all(b"\r\n:1\r\n").await.unwrap();
+        let frame = server.read_frame().await.unwrap();
+        assert_eq!(frame, Some(big));
+        // 大的 frame 之后缓冲区缩回默认大小，剩余的数据仍在
+        assert!(server.buffer.capacity() <= 2 * READ_BUFFER_SIZE, "{}", server.buffer.capacity());
+        assert_eq!(server.read_frame().await.unwrap(), Some(Frame::Integer(1)));
+
+        // 声明的长度超出缓冲区上限时不等数据到齐
+        server.set_limits(ProtocolLimits { max_query_buffer: 1000, ..ProtocolLimits::default() });
+        client.stream.write_all(b"$2000\r\n").await.unwrap();
+        assert!(server.read_frame().await.is_err());
+
+        // 默认限制下声明 512MB 的请求头也不会分配对应的内存
+        let (mut client, mut server) = pair().await;
+        client.stream.write_all(b"$536870912\r\n").await.unwrap();
+        assert!(tokio::time::timeout(timeout, server.read_frame()).await.is_err());
+        assert!(server.buffer.capacity() <= 2 * READ_BUFFER_SIZE, "{}", server.buffer.capacity());
+    }
+}
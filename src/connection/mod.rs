@@ -1,4 +1,12 @@
 mod conn;
+mod output;
+mod reply;
+#[cfg(feature = "uring")]
+mod uring;
 
 
-pub use conn::*;
\ No newline at end of file
+pub use conn::*;
+pub use output::*;
+pub use reply::*;
+#[cfg(feature = "uring")]
+pub use uring::*;
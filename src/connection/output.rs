@@ -0,0 +1,230 @@
+//! 服务端连接的输出队列。
+//!
+//! 回复编码后放进队列，由单独的任务写到 socket，连接的处理循环不会因为客户端读得慢而阻塞。
+//! 队列中尚未写出的字节数即为连接的输出缓冲区大小，超出 `client-output-buffer-limit` 时断开连接，
+//! 避免读得慢的客户端（特别是订阅了大量消息的客户端）占用无限的内存：
+//! - 超出硬限制时立即断开；
+//! - 持续超出软限制一段时间后断开，期间回落到软限制以下则重新计时。
+
+use std::{fmt, sync::{Arc, atomic::{AtomicUsize, Ordering}}, time::{Duration, Instant}};
+
+use bytes::{Bytes, BytesMut};
+use tokio::{io::{AsyncWrite, AsyncWriteExt}, sync::mpsc, task::JoinHandle};
+
+use crate::frame::{Frame, Protocol};
+
+use super::{Reply, encode_frame};
+
+/// 一类连接的输出缓冲区限制，0 表示不限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OutputLimit {
+    /// 硬限制（字节）
+    pub hard: usize,
+    /// 软限制（字节）
+    pub soft: usize,
+    /// 持续超出软限制多久后断开
+    pub soft_seconds: u64,
+}
+
+/// 各类连接的输出缓冲区限制，默认值与 redis 相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimits {
+    /// 普通连接
+    pub normal: OutputLimit,
+    /// 订阅了频道或模式的连接
+    pub pubsub: OutputLimit,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        OutputLimits {
+            normal: OutputLimit::default(),
+            pubsub: OutputLimit { hard: 32 << 20, soft: 8 << 20, soft_seconds: 60 },
+        }
+    }
+}
+
+/// 输出缓冲区超出限制，连接需要被断开
+#[derive(Debug)]
+pub struct OutputLimitExceeded {
+    pub pending: usize,
+}
+
+impl fmt::Display for OutputLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "output buffer limit exceeded ({} bytes pending)", self.pending)
+    }
+}
+
+impl std::error::Error for OutputLimitExceeded {}
+
+/// 连接的输出队列，drop 时不等待队列中的数据写出，需要写出时调用 [`Output::close`]
+pub struct Output {
+    tx: Option<mpsc::UnboundedSender<Bytes>>,
+    /// 已经进入队列但还没有写出的字节数
+    pending: Arc<AtomicUsize>,
+    /// 开始持续超出软限制的时间
+    soft_exceeded_since: Option<Instant>,
+    writer: JoinHandle<()>,
+    buffer: BytesMut,
+}
+
+impl Output {
+    /// 启动写出任务，需要在 tokio 运行时中调用
+    pub fn new<W: AsyncWrite + Unpin + Send + 'static>(stream: W) -> Output {
+        Output::start(|rx, written| tokio::spawn(write_loop(stream, rx, written)))
+    }
+
+    /// 与 [`Output::new`] 相同，但写出任务留在当前线程，用于不能跨线程的 stream（例如 io_uring 的连接）。
+    /// 需要在 [`tokio::task::LocalSet`] 中调用
+    pub fn new_local<W: AsyncWrite + Unpin + 'static>(stream: W) -> Output {
+        Output::start(|rx, written| tokio::task::spawn_local(write_loop(stream, rx, written)))
+    }
+
+    fn start(spawn: impl FnOnce(mpsc::UnboundedReceiver<Bytes>, Arc<AtomicUsize>) -> JoinHandle<()>) -> Output {
+        let (tx, rx) = mpsc::unbounded_channel::<Bytes>();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let writer = spawn(rx, pending.clone());
+        Output { tx: Some(tx), pending, soft_exceeded_since: None, writer, buffer: BytesMut::new() }
+    }
+
+    /// 尚未写出的字节数
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// 按 protocol 编码 frames 并放进队列，之后按 limit 检查输出缓冲区的大小
+    pub fn write_frames(&mut self, frames: &[Frame], protocol: Protocol, limit: OutputLimit) -> Result<(), OutputLimitExceeded> {
+        for frame in frames {
+            encode_frame(frame, protocol, &mut self.buffer);
+        }
+        self.flush(limit)
+    }
+
+    /// 按 protocol 把一条回复直接编码到缓冲区，调用 [`Output::flush`] 后才会放进队列
+    pub fn reply(&mut self, protocol: Protocol) -> Reply<'_> {
+        Reply::new(&mut self.buffer, protocol)
+    }
+
+    /// 把缓冲区中已经编码的回复放进队列，之后按 limit 检查输出缓冲区的大小
+    pub fn flush(&mut self, limit: OutputLimit) -> Result<(), OutputLimitExceeded> {
+        if !self.buffer.is_empty() {
+            let data = self.buffer.split().freeze();
+            self.pending.fetch_add(data.len(), Ordering::Relaxed);
+            // 写出任务只会在写出失败时提前退出，此时数据已经没有必要发送
+            if let Some(tx) = &self.tx {
+                let _ = tx.send(data);
+            }
+        }
+        self.check_limit(limit, Instant::now())
+    }
+
+    fn check_limit(&mut self, limit: OutputLimit, now: Instant) -> Result<(), OutputLimitExceeded> {
+        let pending = self.pending();
+        if limit.hard > 0 && pending > limit.hard {
+            return Err(OutputLimitExceeded { pending });
+        }
+        if limit.soft == 0 || pending <= limit.soft {
+            self.soft_exceeded_since = None;
+            return Ok(());
+        }
+        let since = *self.soft_exceeded_since.get_or_insert(now);
+        if now.duration_since(since) >= Duration::from_secs(limit.soft_seconds) {
+            return Err(OutputLimitExceeded { pending });
+        }
+        Ok(())
+    }
+
+    /// 等待队列中的数据全部写出
+    pub async fn close(mut self) {
+        self.tx.take();
+        let _ = (&mut self.writer).await;
+    }
+}
+
+/// 写出任务：依次把队列中的数据写到 stream，写出后从 written 中扣除
+async fn write_loop<W: AsyncWrite + Unpin>(mut stream: W, mut rx: mpsc::UnboundedReceiver<Bytes>, written: Arc<AtomicUsize>) {
+    while let Some(data) = rx.recv().await {
+        // 写出失败说明连接已经断开，处理循环会在读请求时发现
+        if stream.write_all(&data).await.is_err() {
+            return;
+        }
+        written.fetch_sub(data.len(), Ordering::Relaxed);
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        // 没有调用 close 时直接丢弃未写出的数据，socket 随写出任务一起关闭
+        self.writer.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use tokio::io::AsyncReadExt;
+
+    use crate::frame::{Frame, Protocol};
+
+    use super::{Output, OutputLimit};
+
+    #[tokio::test]
+    async fn write_and_close() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut output = Output::new(client);
+        let frames = [Frame::Simple("OK".into()), Frame::Bulk(Bytes::from("v"))];
+        output.write_frames(&frames, Protocol::Resp2, OutputLimit::default()).unwrap();
+        output.close().await;
+        let mut data = vec![];
+        server.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data, b"+OK\r\n$1\r\nv\r\n");
+    }
+
+    #[tokio::test]
+    async fn local_writer() {
+        tokio::task::LocalSet::new().run_until(async {
+            let (client, mut server) = tokio::io::duplex(64);
+            let mut output = Output::new_local(client);
+            output.reply(Protocol::Resp2).ok();
+            output.flush(OutputLimit::default()).unwrap();
+            output.close().await;
+            let mut data = vec![];
+            server.read_to_end(&mut data).await.unwrap();
+            assert_eq!(data, b"+OK\r\n");
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        // 对端不读取，写出任务写满管道后阻塞，之后的数据都积压在队列中
+        let (client, _server) = tokio::io::duplex(16);
+        let mut output = Output::new(client);
+        let reply = [Frame::Bulk(Bytes::from(vec![b'x'; 100]))];
+        let limit = OutputLimit { hard: 1000, soft: 300, soft_seconds: 10 };
+        for _ in 0..3 {
+            output.write_frames(&reply, Protocol::Resp2, limit).unwrap();
+        }
+        tokio::task::yield_now().await;
+        assert!(output.pending() > 300);
+
+        // 超出软限制未满 10 秒
+        let now = Instant::now();
+        output.soft_exceeded_since = None;
+        output.check_limit(limit, now).unwrap();
+        output.check_limit(limit, now + Duration::from_secs(9)).unwrap();
+        assert!(output.check_limit(limit, now + Duration::from_secs(10)).is_err());
+        // 回落到软限制以下后重新计时
+        let relaxed = OutputLimit { soft: 1000, ..limit };
+        output.check_limit(relaxed, now + Duration::from_secs(10)).unwrap();
+        output.check_limit(limit, now + Duration::from_secs(20)).unwrap();
+
+        let err = (0..10)
+            .find_map(|_| output.write_frames(&reply, Protocol::Resp2, limit).err())
+            .unwrap();
+        assert!(err.pending > 1000);
+        assert!(output.write_frames(&[], Protocol::Resp2, OutputLimit::default()).is_ok());
+    }
+}
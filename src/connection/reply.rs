@@ -0,0 +1,88 @@
+//! 不经过 [`Frame`] 的回复。
+//!
+//! GET、SET 这类调用频繁、回复简单的命令，构造 `Frame` 再编码需要额外的分配：
+//! `Frame::Simple` 需要一个 `String`，GET 需要先把值复制成 `Bytes`，RESP2 的连接还要先转换一遍。
+//! [`Reply`] 直接把回复编码到输出缓冲区，复杂的回复仍然使用 `Frame`，见 [`Reply::frame`]。
+
+use bytes::{BufMut, BytesMut};
+
+use crate::frame::{Frame, Protocol, encode_decimal, encode_line};
+
+use super::encode_frame;
+
+/// 一条回复，按连接的协议直接编码到输出缓冲区，由 [`super::Output::reply`] 创建。
+/// 各方法都会消耗 `Reply`，保证每条命令只回复一次
+pub struct Reply<'a> {
+    dst: &'a mut BytesMut,
+    protocol: Protocol,
+}
+
+impl<'a> Reply<'a> {
+    pub fn new(dst: &'a mut BytesMut, protocol: Protocol) -> Reply<'a> {
+        Reply { dst, protocol }
+    }
+
+    /// 编码时使用的协议
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// `+OK`
+    pub fn ok(self) {
+        self.dst.put_slice(b"+OK\r\n");
+    }
+
+    /// bulk string，data 直接复制到输出缓冲区
+    pub fn bulk(self, data: &[u8]) {
+        self.dst.put_u8(b'$');
+        encode_decimal(data.len() as i64, self.dst);
+        self.dst.put_slice(data);
+        self.dst.put_slice(b"\r\n");
+    }
+
+    /// 空值，RESP2 为 `$-1`，RESP3 为 `_`
+    pub fn null(self) {
+        Frame::Null.encode_as(self.protocol, self.dst);
+    }
+
+    pub fn error(self, message: &str) {
+        encode_line(b'-', message.as_bytes(), self.dst);
+    }
+
+    /// 其他回复按 frame 编码
+    pub fn frame(self, frame: &Frame) {
+        encode_frame(frame, self.protocol, self.dst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+
+    use crate::{connection::encode_frame, frame::{Frame, Protocol}};
+
+    use super::Reply;
+
+    #[test]
+    fn same_as_frame() {
+        let cases: [(fn(Reply), Frame); 5] = [
+            (|reply| reply.ok(), Frame::Simple("OK".into())),
+            (|reply| reply.bulk(b"v\r\n"), Frame::Bulk(Bytes::from("v\r\n"))),
+            (|reply| reply.bulk(b""), Frame::Bulk(Bytes::new())),
+            (|reply| reply.null(), Frame::Null),
+            (|reply| reply.error("ERR x"), Frame::Error("ERR x".into())),
+        ];
+        for protocol in [Protocol::Resp2, Protocol::Resp3] {
+            for (write, frame) in &cases {
+                let mut dst = BytesMut::new();
+                write(Reply::new(&mut dst, protocol));
+                let mut expected = BytesMut::new();
+                encode_frame(frame, protocol, &mut expected);
+                assert_eq!(dst, expected, "{:?} {:?}", protocol, frame);
+            }
+        }
+        let mut dst = BytesMut::new();
+        Reply::new(&mut dst, Protocol::Resp2).frame(&Frame::Boolean(true));
+        assert_eq!(&dst[..], b":1\r\n");
+    }
+}
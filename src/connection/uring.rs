@@ -0,0 +1,88 @@
+//! 基于 io_uring 的连接，需要启用 `uring` feature。
+//!
+//! tokio-uring 的读写接口取得缓冲区的所有权，操作完成后再交还，与 tokio 的 [`AsyncRead`]/[`AsyncWrite`] 不同。
+//! [`UringStream`] 把进行中的读写操作保存下来，对外实现 tokio 的读写 trait，
+//! [`super::Connection`] 与 [`super::Output`] 不需要区分连接来自哪种后端。
+//!
+//! tokio-uring 的 socket 不能跨线程使用，连接任务需要通过 `tokio_uring::spawn` 创建，输出队列使用 [`super::Output::new_local`]
+
+use std::{future::Future, io, net::Shutdown, pin::Pin, rc::Rc, task::{Context, Poll, ready}};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_uring::net::TcpStream;
+
+/// 一次读写操作，完成时交还缓冲区
+type Op = Pin<Box<dyn Future<Output = (io::Result<usize>, Vec<u8>)>>>;
+
+/// 把 tokio-uring 的 [`TcpStream`] 适配为 [`AsyncRead`] + [`AsyncWrite`]
+pub struct UringStream {
+    stream: Rc<TcpStream>,
+    /// 进行中的读操作。读取被取消（例如 `select!` 的其他分支先完成）时操作仍然保留，下次读取时继续等待它完成
+    read: Option<Op>,
+    /// 读到的数据超出调用方缓冲区的部分，留给下次读取
+    unread: Vec<u8>,
+    /// 进行中的写操作
+    write: Option<Op>,
+}
+
+impl UringStream {
+    pub fn new(stream: TcpStream) -> UringStream {
+        UringStream::from_rc(Rc::new(stream))
+    }
+
+    fn from_rc(stream: Rc<TcpStream>) -> UringStream {
+        UringStream { stream, read: None, unread: Vec::new(), write: None }
+    }
+
+    /// 拆成读、写两半，与 tokio 的 `TcpStream::into_split` 对应。两半共用同一个 socket，各自只应读或者只应写
+    pub fn into_split(self) -> (UringStream, UringStream) {
+        let writer = UringStream::from_rc(self.stream.clone());
+        (self, writer)
+    }
+}
+
+impl AsyncRead for UringStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.unread.is_empty() {
+            let op = this.read.get_or_insert_with(|| {
+                let stream = this.stream.clone();
+                let len = buf.remaining();
+                Box::pin(async move { stream.read(Vec::with_capacity(len)).await })
+            });
+            let (res, data) = ready!(op.as_mut().poll(cx));
+            this.read = None;
+            res?;
+            this.unread = data;
+        }
+        let n = this.unread.len().min(buf.remaining());
+        buf.put_slice(&this.unread[..n]);
+        this.unread.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for UringStream {
+    /// 返回 `Pending` 后，调用方需要用同样的数据再次调用，进行中的写操作使用的是第一次传入的数据。
+    /// [`tokio::io::AsyncWriteExt::write_all`] 满足这个要求
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let op = this.write.get_or_insert_with(|| {
+            let stream = this.stream.clone();
+            let data = buf.to_vec();
+            Box::pin(async move { stream.write(data).await })
+        });
+        let (res, _) = ready!(op.as_mut().poll(cx));
+        this.write = None;
+        Poll::Ready(res)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // 写操作完成时数据已经交给内核，没有需要刷新的缓冲
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.stream.shutdown(Shutdown::Write))
+    }
+}
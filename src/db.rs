@@ -0,0 +1,1977 @@
+//! 服务端的键空间。
+//!
+//! 在使用 Tokio 编写异步代码时，一个常见的错误无条件地使用 tokio::sync::Mutex ，而真相是：Tokio 提供的异步锁只应该在跨多个 .await调用时使用，而且 Tokio 的 Mutex 实际上内部使用的也是 std::sync::Mutex。
+//! 多补充几句，在异步代码中，关于锁的使用有以下经验之谈：
+//! - 锁如果在多个 .await 过程中持有，应该使用 Tokio 提供的锁，原因是 .await的过程中锁可能在线程间转移，若使用标准库的同步锁存在死锁的可能性，例如某个任务刚获取完锁，还没使用完就因为 .await 让出了当前线程的所有权，结果下个任务又去获取了锁，造成死锁
+//! - 锁竞争不多的情况下，使用 std::sync::Mutex
+//! - 锁竞争多，可以考虑使用三方库提供的性能更高的锁，例如 parking_lot::Mutex
+
+use std::{cmp::Ordering, collections::{BTreeMap, HashMap, HashSet, hash_map::RandomState}, fmt, hash::BuildHasher, mem::size_of, path::PathBuf, sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak, atomic::{self, AtomicBool, AtomicU64}}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use bytes::Bytes;
+use rand::Rng;
+
+use tokio::sync::broadcast;
+
+use crate::{acl::Acl, blocking::Waiters, clients::Clients, cluster::Cluster, config::Config, connection::OutputLimits, ds::{MemSize, dict::Dict, perfstr::{SmartString, sds::SDS}}, evict::{Access, EVICTION_SAMPLES, EvictionPolicy, OutOfMemory, lru_clock}, expires::{ExpireFlags, Expires}, lazyfree::LazyFree, frame::{Frame, ProtocolLimits}, glob, object::{EncodingLimits, RedisObject, Typed}, pubsub::Registry, rdb, replication::Replication, script::{Engine, Program, Scripts}, slowlog::SlowLog, stats::Stats};
+
+/// 后台定期任务的执行间隔，对应 redis 默认的 hz 10
+const CRON_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 后台定期任务每次推进 rehash 最多花费的时间，所有分片共用
+const ACTIVE_REHASH_BUDGET: Duration = Duration::from_millis(1);
+
+/// 编码快照时每次锁住分片遍历的 slot 数
+const SNAPSHOT_BATCH: usize = 64;
+
+/// 默认的分片数
+pub const DEFAULT_SHARDS: usize = 16;
+
+/// 默认的快照文件
+pub const DEFAULT_SNAPSHOT_PATH: &str = "dump.rdb";
+
+/// 数据库句柄，内部用 Arc 共享，clone 只会增加引用计数。
+///
+/// 与 redis 一样，每个分片的键空间都是一个支持渐进式 rehash 的 [`Dict`]，key 为 SDS，
+/// 所以 `SCAN` 在遍历过程中即使发生扩容也不会遗漏 key。对外的接口中 key 使用 `Bytes`：
+/// Vec<u8> 在 copy 时，底层数据（堆）也会被复制一次，而 Bytes 内部使用类似 Arc 的机制实现，可以避免没必要的数据拷贝。
+/// value 则是 [`RedisObject`]，同一类型可能有不同的底层编码。
+///
+/// # 分片
+/// 键空间按 key 的 hash 分成若干分片，每个分片有自己的锁，
+/// 这样访问不同 key 的连接大多不会互相阻塞。单个 key 上的操作只会锁住它所在的分片。
+///
+/// # 过期
+/// 与 redis 一样，过期的 key 通过两种方式删除：
+/// - 惰性删除：访问 key 时检查是否已过期，过期则删除并当作不存在处理；
+/// - 主动删除：后台任务定期扫描设置了过期时间的 key，删除已过期的部分。
+///
+/// 读命令通过 [`Db::lookup_read`]（以及基于它的 [`Db::with_typed`]）访问 key，计入命中率；
+/// 写命令通过 [`Db::lookup_write`]、[`Db::update`] 等访问，不计入命中率。两者都会先删除已过期的 key，
+/// 过期时间一过，即使还没被主动删除，key 对所有命令都不存在。
+/// 两种方式删除过期的 key 都经过同一个位置，并计入 `expired_keys`，见 [`Db::expired_keys`]
+///
+/// # 事务
+/// 命令执行期间持有一把读写锁的读锁，`EXEC` 持有写锁执行事务中的所有命令，期间不会穿插其他连接的命令。
+/// 被 `WATCH` 的 key 会记录版本号，key 每次被修改（包括删除、过期）版本号都会加一。
+///
+/// # 内存
+/// 每个分片记录其中 key 占用内存的估计值，key 被写入时更新。设置了 maxmemory 时，
+/// 每条命令执行前都会检查内存占用，超出则按淘汰策略删除 key，见 [`crate::evict`]。
+///
+/// # 快照
+/// [`Db::snapshot`] 只短暂锁住所有分片装上写屏障，之后 key 第一次被访问之前先把它当前的值写入快照，
+/// 编码其余的 key 时逐个分片、每次只锁住一小段，见 [`Snapshot`]。
+#[derive(Clone)]
+pub struct Db {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    shards: Vec<Mutex<Shard>>,
+    /// 计算 key 所在的分片
+    hasher: RandomState,
+    /// 当前的配置，其中的编码阈值、maxmemory 等可以在运行期间修改
+    config: RwLock<Config>,
+    /// 发布订阅的频道，与键空间无关
+    pubsub: Mutex<Registry>,
+    /// 编译过的脚本
+    scripts: Mutex<Scripts>,
+    /// 是否正在保存快照，同一时刻只允许一个保存任务
+    saving: AtomicBool,
+    /// 是否有快照正在进行，同一时刻只允许一个快照
+    snapshotting: Mutex<bool>,
+    /// 快照结束时通知等待的 [`Db::snapshot`]
+    snapshot_done: Condvar,
+    /// 是否进行主动过期，`DEBUG SET-ACTIVE-EXPIRE` 可以暂停
+    active_expire: AtomicBool,
+    /// 普通命令持有读锁，EXEC 持有写锁
+    exec_lock: RwLock<()>,
+    /// 累计淘汰的 key 数
+    evicted_keys: AtomicU64,
+    /// 运行统计
+    stats: Stats,
+    slowlog: SlowLog,
+    /// 存活的连接
+    clients: Clients,
+    /// 用户及其权限
+    acl: RwLock<Acl>,
+    /// 在后台释放 `UNLINK`、`FLUSHALL ASYNC` 删除的值
+    lazyfree: LazyFree,
+    /// 阻塞等待 key 被写入的连接
+    waiters: Waiters,
+    /// 复制偏移量与副本的确认
+    replication: Replication,
+    /// 集群模式下槽的分配，只在启动时读取配置
+    cluster: Option<Cluster>,
+}
+
+#[derive(Default)]
+struct Shard {
+    entries: Dict<SDS, Entry>,
+    /// 设置了过期时间的 key，按过期时间排序，主动过期时只需取出已过期的部分
+    expires: Expires,
+    /// 被 WATCH 的 key，只有这部分 key 需要记录版本号
+    watched: HashMap<Bytes, Watch>,
+    /// 分片中所有 key 占用内存的估计值
+    used_memory: usize,
+    /// 快照进行中时的写屏障，见 [`Db::snapshot`]
+    snapshot: Option<Capture>,
+    /// 累计因过期删除的 key 数
+    expired_keys: u64,
+}
+
+/// 快照在一个分片上的写屏障
+struct Capture {
+    /// 已经写入快照的 key，以及在快照开始之后才创建的 key，这些 key 不会再写入
+    done: HashSet<Bytes>,
+    /// 写入的 key，快照编码完这个分片后拼接到快照中
+    encoder: rdb::Encoder,
+    /// 快照开始的时间，此时已经过期的 key 不写入
+    now: u64,
+}
+
+/// 键空间在某一时刻的快照，由 [`Db::snapshot`] 创建，[`Snapshot::encode`] 编码。
+/// 没有编码就被回收时移除写屏障
+pub struct Snapshot {
+    db: Db,
+}
+
+#[derive(Default)]
+struct Watch {
+    version: u64,
+    /// 正在 WATCH 这个 key 的次数，为 0 时不再记录
+    watchers: usize,
+}
+
+/// 某种类型的值的 key 个数与内存占用，见 [`Db::memory_stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeMemory {
+    pub keys: usize,
+    pub bytes: usize,
+}
+
+/// 对 key 执行了与其值类型不符的操作
+#[derive(Debug)]
+pub struct WrongType;
+
+impl fmt::Display for WrongType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "WRONGTYPE Operation against a key holding the wrong kind of value".fmt(f)
+    }
+}
+
+impl std::error::Error for WrongType {}
+
+impl From<WrongType> for Frame {
+    fn from(err: WrongType) -> Frame {
+        Frame::Error(err.to_string())
+    }
+}
+
+/// COPY 的源与目标是同一个 key
+#[derive(Debug)]
+pub struct SameObject;
+
+impl fmt::Display for SameObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "ERR source and destination objects are the same".fmt(f)
+    }
+}
+
+impl std::error::Error for SameObject {}
+
+impl From<SameObject> for Frame {
+    fn from(err: SameObject) -> Frame {
+        Frame::Error(err.to_string())
+    }
+}
+
+/// 键空间中的一项，除了值以外还记录了 key 的元数据
+struct Entry {
+    value: RedisObject,
+    /// 过期时间，unix 时间戳（毫秒）。`None` 表示永不过期
+    expire_at: Option<u64>,
+    /// 写入时估计的内存占用，包括 key 本身
+    size: usize,
+    access: Access,
+}
+
+impl Entry {
+    fn new(value: RedisObject, expire_at: Option<u64>) -> Entry {
+        Entry { value, expire_at, size: 0, access: Access::new(lru_clock()) }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expire_at, Some(when) if when <= now)
+    }
+}
+
+impl Default for Db {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Db {
+    /// 使用默认分片数创建数据库，见 [`Db::with_shards`]
+    pub fn new() -> Self {
+        Self::with_shards(DEFAULT_SHARDS)
+    }
+
+    /// 创建有 shards 个分片的数据库，其他配置使用默认值，见 [`Db::with_config`]
+    pub fn with_shards(shards: usize) -> Self {
+        Self::with_config(Config { shards, ..Config::default() })
+    }
+
+    /// 按配置创建数据库。如果当前处于 tokio 运行时中，会同时启动后台定期任务（主动过期、推进 rehash），
+    /// 任务在所有 `Db` 句柄都被回收后自动退出。
+    pub fn with_config(config: Config) -> Self {
+        assert!(config.shards > 0, "at least one shard is required");
+        let acl = Acl::new(&config.requirepass);
+        let cluster = config.cluster_enabled.then(|| Cluster::new(config.addr(), config.cluster_nodes.clone()));
+        let shared = Shared {
+            shards: (0..config.shards).map(|_| Mutex::default()).collect(),
+            hasher: RandomState::new(),
+            config: RwLock::new(config),
+            pubsub: Mutex::default(),
+            scripts: Mutex::default(),
+            saving: AtomicBool::new(false),
+            snapshotting: Mutex::new(false),
+            snapshot_done: Condvar::new(),
+            active_expire: AtomicBool::new(true),
+            exec_lock: RwLock::default(),
+            evicted_keys: AtomicU64::new(0),
+            stats: Stats::default(),
+            slowlog: SlowLog::default(),
+            clients: Clients::default(),
+            acl: RwLock::new(acl),
+            lazyfree: LazyFree::default(),
+            waiters: Waiters::default(),
+            replication: Replication::default(),
+            cluster,
+        };
+        let db = Db { shared: Arc::new(shared) };
+        if tokio::runtime::Handle::try_current().is_ok() {
+            tokio::spawn(cron_task(Arc::downgrade(&db.shared)));
+        }
+        db
+    }
+
+    /// 分片数
+    pub fn shards(&self) -> usize {
+        self.shared.shards.len()
+    }
+
+    /// key 所在分片的下标
+    fn shard_index(&self, key: &[u8]) -> usize {
+        self.shared.hasher.hash_one(key) as usize % self.shared.shards.len()
+    }
+
+    /// 锁住 key 所在的分片
+    fn shard(&self, key: &[u8]) -> MutexGuard<'_, Shard> {
+        self.shared.shards[self.shard_index(key)].lock().unwrap()
+    }
+
+    /// 获取 key 对应的字符串
+    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>, WrongType> {
+        self.lookup_read(key, |value| match value {
+            Some(value) => value.as_bytes().map(Some).ok_or(WrongType),
+            None => Ok(None),
+        })
+    }
+
+    /// 设置 key 的值，已存在则覆盖。`expire_at` 为过期的 unix 时间戳（毫秒），
+    /// 与 redis 一致，覆盖时原有的过期时间会被清除
+    pub fn set(&self, key: Bytes, value: Bytes, expire_at: Option<u64>) {
+        self.insert(key, RedisObject::string(&value), expire_at);
+    }
+
+    /// 设置 key 的值与过期时间，已存在则覆盖
+    pub(crate) fn insert(&self, key: Bytes, value: RedisObject, expire_at: Option<u64>) {
+        self.shard(&key).insert(key, value, expire_at);
+    }
+
+    /// 设置 key 的值并返回原来的值，原有的过期时间会被清除。
+    /// 原来的值不是字符串时返回 `WrongType`，不做修改
+    pub fn getset(&self, key: Bytes, value: Bytes) -> Result<Option<Bytes>, WrongType> {
+        let mut state = self.shard(&key);
+        let old = match state.lookup(&key) {
+            Some(entry) => Some(entry.value.as_bytes().ok_or(WrongType)?),
+            None => None,
+        };
+        self.shared.stats.record_lookup(old.is_some());
+        state.insert(key, RedisObject::string(&value), None);
+        Ok(old)
+    }
+
+    /// 获取 key 对应的字符串并删除 key。原来的值不是字符串时返回 `WrongType`，不做修改
+    pub fn getdel(&self, key: &[u8]) -> Result<Option<Bytes>, WrongType> {
+        let mut state = self.shard(key);
+        let value = match state.lookup(key) {
+            Some(entry) => Some(entry.value.as_bytes().ok_or(WrongType)?),
+            None => None,
+        };
+        self.shared.stats.record_lookup(value.is_some());
+        if value.is_some() {
+            state.remove(key);
+        }
+        Ok(value)
+    }
+
+    /// 获取 key 对应的字符串，同时修改 key 的过期时间（unix 时间戳，毫秒）：
+    /// expire_at 为 `None` 时不修改，为 `Some(None)` 时移除过期时间，过期时间已经过去的话 key 会被删除。
+    /// 原来的值不是字符串时返回 `WrongType`，不做修改
+    pub fn getex(&self, key: &[u8], expire_at: Option<Option<u64>>) -> Result<Option<Bytes>, WrongType> {
+        let mut state = self.shard(key);
+        let value = match state.lookup(key) {
+            Some(entry) => Some(entry.value.as_bytes().ok_or(WrongType)?),
+            None => None,
+        };
+        self.shared.stats.record_lookup(value.is_some());
+        if let (Some(_), Some(expire_at)) = (&value, expire_at) {
+            state.set_expire(key, expire_at);
+        }
+        Ok(value)
+    }
+
+    /// 一次获取多个 key 的字符串值，不存在或者不是字符串的 key 对应 `None`。
+    /// 所有 key 所在的分片同时加锁，结果是同一时刻的值
+    pub fn mget(&self, keys: &[Bytes]) -> Vec<Option<Bytes>> {
+        let mut shards = self.lock_shards(keys);
+        keys.iter()
+            .map(|key| {
+                let state = shards[self.shard_index(key)].as_mut().unwrap();
+                let value = state.lookup(key).map(|entry| entry.value.as_bytes());
+                self.shared.stats.record_lookup(value.is_some());
+                value.flatten()
+            })
+            .collect()
+    }
+
+    /// 原子地设置多个 key 的值，原有的过期时间会被清除，同一个 key 出现多次时以最后一次为准。
+    /// `nx` 为真时只要有一个 key 已存在就不做任何修改，返回 false
+    pub fn mset(&self, pairs: Vec<(Bytes, Bytes)>, nx: bool) -> bool {
+        let keys: Vec<Bytes> = pairs.iter().map(|(key, _)| key.clone()).collect();
+        let mut shards = self.lock_shards(&keys);
+        if nx && keys.iter().any(|key| shards[self.shard_index(key)].as_mut().unwrap().lookup(key).is_some()) {
+            return false;
+        }
+        for (key, value) in pairs {
+            let state = shards[self.shard_index(&key)].as_mut().unwrap();
+            state.insert(key, RedisObject::string(&value), None);
+        }
+        true
+    }
+
+    /// 锁住 keys 所在的所有分片，按分片下标取用。按下标顺序加锁，避免与其他多 key 操作互相等待造成死锁
+    fn lock_shards(&self, keys: &[Bytes]) -> Vec<Option<MutexGuard<'_, Shard>>> {
+        let mut locked = vec![false; self.shared.shards.len()];
+        for key in keys {
+            locked[self.shard_index(key)] = true;
+        }
+        self.shared.shards
+            .iter()
+            .zip(locked)
+            .map(|(shard, locked)| locked.then(|| shard.lock().unwrap()))
+            .collect()
+    }
+
+    /// 读命令在锁内访问 key 对应的值，key 不存在（或已过期）时传入 `None`，计入命中率。
+    /// 持有锁期间执行，f 中不要做耗时操作
+    pub fn lookup_read<R>(&self, key: &[u8], f: impl FnOnce(Option<&mut RedisObject>) -> R) -> R {
+        let mut state = self.shard(key);
+        let value = state.lookup(key).map(|entry| &mut entry.value);
+        self.shared.stats.record_lookup(value.is_some());
+        f(value)
+    }
+
+    /// 同 [`Db::lookup_read`]，供写命令使用，不计入命中率
+    pub fn lookup_write<R>(&self, key: &[u8], f: impl FnOnce(Option<&mut RedisObject>) -> R) -> R {
+        let mut state = self.shard(key);
+        f(state.lookup(key).map(|entry| &mut entry.value))
+    }
+
+    /// 同 [`Db::lookup_read`]，但只接受 T 类型的值：key 的值是其他类型时不调用 f，返回 `WrongType`
+    pub fn with_typed<T: Typed, R>(&self, key: &[u8], f: impl FnOnce(Option<&mut T>) -> R) -> Result<R, WrongType> {
+        self.lookup_read(key, |value| match value {
+            Some(value) => T::downcast_mut(value).map(|value| f(Some(value))).ok_or(WrongType),
+            None => Ok(f(None)),
+        })
+    }
+
+    /// 在锁内查看 key 对应的值及其访问记录，不算作一次访问，也不计入命中率，供 `OBJECT`、`DEBUG OBJECT` 使用
+    pub(crate) fn peek<R>(&self, key: &[u8], f: impl FnOnce(Option<(&RedisObject, &Access)>) -> R) -> R {
+        let mut state = self.shard(key);
+        f(state.peek(key).map(|entry| (&entry.value, &entry.access)))
+    }
+
+    /// 在锁内修改 key 对应的值：f 把值置为 `Some` 即新建或覆盖 key，置为 `None` 即删除 key。
+    /// key 原有的过期时间会保留。
+    ///
+    /// 无法得知 f 是否真的修改了值，所以 key 存在或者被新建时都视为修改，见 [`Db::version`]
+    pub fn update<R>(&self, key: &Bytes, f: impl FnOnce(&mut Option<RedisObject>) -> R) -> R {
+        let mut state = self.shard(key);
+        let mut detached = state.detach(key);
+        let ret = f(&mut detached.value);
+        state.restore(key, detached);
+        ret
+    }
+
+    /// 同 [`Db::update`]，但只接受 T 类型的值：key 的值是其他类型时不调用 f，返回 `WrongType`，值保持不变
+    pub fn update_typed<T: Typed, R>(&self, key: &Bytes, f: impl FnOnce(&mut Option<T>) -> R) -> Result<R, WrongType> {
+        self.update(key, |value| {
+            if value.as_mut().is_some_and(|value| T::downcast_mut(value).is_none()) {
+                return Err(WrongType);
+            }
+            let mut typed = value.take().and_then(T::from_object);
+            let ret = f(&mut typed);
+            *value = typed.map(T::into_object);
+            Ok(ret)
+        })
+    }
+
+    /// 在锁内同时修改两个 key 对应的值，规则与 [`Db::update`] 相同。
+    /// 两个 key 所在的分片一起加锁，其他连接看不到只修改了一个 key 的中间状态。
+    ///
+    /// 两个 key 相同时 f 的第二个参数为 `None`，只通过第一个参数修改
+    pub fn update_pair<R>(
+        &self,
+        first: &Bytes,
+        second: &Bytes,
+        f: impl FnOnce(&mut Option<RedisObject>, Option<&mut Option<RedisObject>>) -> R,
+    ) -> R {
+        let mut shards = self.lock_shards(&[first.clone(), second.clone()]);
+        let (i, j) = (self.shard_index(first), self.shard_index(second));
+        let mut a = shards[i].as_mut().unwrap().detach(first);
+        if first == second {
+            let ret = f(&mut a.value, None);
+            shards[i].as_mut().unwrap().restore(first, a);
+            return ret;
+        }
+        let mut b = shards[j].as_mut().unwrap().detach(second);
+        let ret = f(&mut a.value, Some(&mut b.value));
+        shards[i].as_mut().unwrap().restore(first, a);
+        shards[j].as_mut().unwrap().restore(second, b);
+        ret
+    }
+
+    /// 删除 key，返回 key 是否存在
+    pub fn del(&self, key: &[u8]) -> bool {
+        let mut state = self.shard(key);
+        state.lookup(key).is_some() && state.remove(key)
+    }
+
+    /// 删除 key，返回 key 是否存在。与 [`Db::del`] 不同，在锁内只摘下 key，大的值交给后台线程释放
+    pub fn unlink(&self, key: &[u8]) -> bool {
+        let value = {
+            let mut state = self.shard(key);
+            if state.lookup(key).is_none() {
+                return false;
+            }
+            state.pop(key).unwrap().value
+        };
+        let effort = value.free_effort();
+        self.shared.lazyfree.free_if_large(value, effort);
+        true
+    }
+
+    /// 删除所有 key，返回删除的 key 数（包括已过期但还没被删除的 key）。
+    /// lazy 为真时旧的键空间交给后台线程释放，否则在释放分片锁之后直接释放
+    pub fn flushall(&self, lazy: bool) -> usize {
+        let mut removed = 0;
+        for shard in self.shared.shards.iter() {
+            let entries = shard.lock().unwrap().clear();
+            removed += entries.value_cnt() as usize;
+            if lazy && entries.value_cnt() > 0 {
+                self.shared.lazyfree.free(entries);
+            }
+        }
+        removed
+    }
+
+    /// 交给后台线程但还没释放完的对象数
+    pub fn lazyfree_pending(&self) -> usize {
+        self.shared.lazyfree.pending()
+    }
+
+    /// key 是否存在
+    pub fn exists(&self, key: &[u8]) -> bool {
+        let mut state = self.shard(key);
+        let exists = state.lookup(key).is_some();
+        self.shared.stats.record_lookup(exists);
+        exists
+    }
+
+    /// key 的总数以及其中设置了过期时间的数量，包括已过期但还没被删除的 key
+    pub fn key_counts(&self) -> (usize, usize) {
+        self.shared.shards.iter().fold((0, 0), |(keys, expires), shard| {
+            let shard = shard.lock().unwrap();
+            (keys + shard.entries.value_cnt() as usize, expires + shard.expires.len())
+        })
+    }
+
+    /// key 的总数，不包括已过期但还没被删除的 key。已过期的 key 从过期时间的索引中取出，不需要遍历键空间
+    pub fn dbsize(&self) -> usize {
+        let now = now_ms();
+        self.shared.shards.iter().map(|shard| {
+            let shard = shard.lock().unwrap();
+            shard.entries.value_cnt() as usize - shard.expires.expired(now).count()
+        }).sum()
+    }
+
+    /// 随机返回一个 key，键空间为空时返回 `None`。
+    ///
+    /// 先按各分片的 key 数加权随机选一个分片，再用 [`Dict::random_entry`] 在分片中选取，rehash 期间同样可以选到两张表中的 key。
+    /// 选中已过期的 key 时删除它并重新选
+    pub fn random_key(&self) -> Option<Bytes> {
+        let shards = &self.shared.shards;
+        loop {
+            let counts: Vec<u64> = shards.iter().map(|shard| shard.lock().unwrap().entries.value_cnt()).collect();
+            let total: u64 = counts.iter().sum();
+            if total == 0 {
+                return None;
+            }
+            let (mut n, mut i) = (rand::thread_rng().gen_range(0..total), 0);
+            while n >= counts[i] {
+                n -= counts[i];
+                i += 1;
+            }
+            let mut shard = shards[i].lock().unwrap();
+            // 统计之后锁被释放过，分片可能已经被清空
+            let Some((key, _)) = shard.entries.random_entry() else {
+                continue;
+            };
+            let key = Bytes::copy_from_slice(key.val());
+            if !shard.expire_if_needed(&key, now_ms()) {
+                return Some(key);
+            }
+        }
+    }
+
+    /// 所有匹配 glob 模式的 key，顺序不确定。需要逐个分片扫描整个键空间
+    pub fn keys(&self, pattern: &[u8]) -> Vec<Bytes> {
+        let now = now_ms();
+        let mut keys = vec![];
+        for shard in &self.shared.shards {
+            let shard = shard.lock().unwrap();
+            keys.extend(shard.entries
+                .iter()
+                .filter(|(key, entry)| !entry.is_expired(now) && glob::matches(pattern, key.val()))
+                .map(|(key, _)| Bytes::copy_from_slice(key.val())));
+        }
+        keys
+    }
+
+    /// 从 cursor 开始遍历一部分 key，返回下一次遍历的 cursor 以及这次遍历到的、满足 filter 的 key。
+    /// cursor 为 0 表示从头开始，返回的 cursor 为 0 表示遍历完成。
+    ///
+    /// count 只是提示：收集到 count 个 key，或者遍历了 count * 10 个 slot 后就返回，可能返回多于或少于 count 个 key。
+    /// 分片一个接一个地遍历，cursor 除以分片数的余数为分片下标，商为该分片 Dict 的 cursor，见 [`Dict::scan`]。
+    /// 遍历开始前就存在、且一直没被删除的 key 一定会被返回，但可能返回多次
+    pub fn scan(&self, cursor: u64, count: usize, mut filter: impl FnMut(&[u8], &RedisObject) -> bool) -> (u64, Vec<Bytes>) {
+        let shards = self.shared.shards.len() as u64;
+        let (mut shard, mut cursor) = (cursor % shards, cursor / shards);
+        let now = now_ms();
+        let mut keys = vec![];
+        for _ in 0..count.saturating_mul(10).max(1) {
+            let state = self.shared.shards[shard as usize].lock().unwrap();
+            cursor = state.entries.scan(cursor, |key, entry| {
+                if !entry.is_expired(now) && filter(key.val(), &entry.value) {
+                    keys.push(Bytes::copy_from_slice(key.val()));
+                }
+            });
+            if cursor == 0 {
+                // 当前分片遍历完成，继续下一个分片
+                shard += 1;
+                if shard == shards {
+                    return (0, keys);
+                }
+            }
+            if keys.len() >= count {
+                break;
+            }
+        }
+        (cursor * shards + shard, keys)
+    }
+
+    /// 把 from 重命名为 to，过期时间随之转移，to 已存在时会被覆盖。
+    /// from 不存在时返回 `None`；`nx` 为真且 to 已存在时不做修改，返回 `Some(false)`
+    pub fn rename(&self, from: &[u8], to: Bytes, nx: bool) -> Option<bool> {
+        let (mut src, mut dst) = self.lock_pair(from, &to);
+        src.lookup(from)?;
+        let exists = match &mut dst {
+            Some(dst) => dst.lookup(&to).is_some(),
+            None => src.lookup(&to).is_some(),
+        };
+        if nx && exists {
+            return Some(false);
+        }
+        if from == to {
+            return Some(true);
+        }
+        let entry = src.take(from).unwrap();
+        src.expires.remove(from);
+        src.touch(from);
+        let dst = dst.as_deref_mut().unwrap_or(&mut *src);
+        dst.remove(&to);
+        dst.touch(&to);
+        if let Some(when) = entry.expire_at {
+            dst.expires.insert(to.clone(), when);
+        }
+        dst.put(&to, entry);
+        Some(true)
+    }
+
+    /// 把 from 的值深拷贝到 to，过期时间一并复制，见 [`RedisObject::clone_value`]。
+    /// from 不存在，或者 `replace` 为假且 to 已存在时不做修改，返回 false。
+    /// 与 redis 一样，from 与 to 相同时不论 from 是否存在都返回 `SameObject`
+    pub fn copy(&self, from: &[u8], to: Bytes, replace: bool) -> Result<bool, SameObject> {
+        if from == &to[..] {
+            return Err(SameObject);
+        }
+        let (mut src, mut dst) = self.lock_pair(from, &to);
+        let Some(entry) = src.lookup(from) else {
+            return Ok(false);
+        };
+        let (value, expire_at) = (entry.value.clone_value(), entry.expire_at);
+        let dst = dst.as_deref_mut().unwrap_or(&mut *src);
+        if !replace && dst.lookup(&to).is_some() {
+            return Ok(false);
+        }
+        dst.insert(to, value, expire_at);
+        Ok(true)
+    }
+
+    /// 锁住两个 key 所在的分片，在同一分片时第二个为 `None`
+    fn lock_pair(&self, first: &[u8], second: &[u8]) -> (MutexGuard<'_, Shard>, Option<MutexGuard<'_, Shard>>) {
+        let (i, j) = (self.shard_index(first), self.shard_index(second));
+        let shards = &self.shared.shards;
+        // 两个 key 在不同分片时，按下标顺序加锁，避免互相等待造成死锁
+        match i.cmp(&j) {
+            Ordering::Equal => (shards[i].lock().unwrap(), None),
+            Ordering::Less => {
+                let first = shards[i].lock().unwrap();
+                (first, Some(shards[j].lock().unwrap()))
+            },
+            Ordering::Greater => {
+                let second = shards[j].lock().unwrap();
+                (shards[i].lock().unwrap(), Some(second))
+            },
+        }
+    }
+
+    /// 设置 key 的过期时间（unix 时间戳，毫秒），返回 key 是否存在。
+    /// 过期时间已经过去的话，key 会被直接删除
+    pub fn expire_at(&self, key: &[u8], when: u64) -> bool {
+        self.expire_at_if(key, when, ExpireFlags::default())
+    }
+
+    /// 与 [`Db::expire_at`] 相同，但只在原来的过期时间满足 flags 时修改，返回是否修改了（包括 key 被删除）
+    pub fn expire_at_if(&self, key: &[u8], when: u64, flags: ExpireFlags) -> bool {
+        let mut state = self.shard(key);
+        if state.lookup(key).is_none() || !flags.allows(state.expires.get(key), when) {
+            return false;
+        }
+        state.set_expire(key, Some(when));
+        true
+    }
+
+    /// 移除 key 的过期时间，返回是否确实移除了
+    pub fn persist(&self, key: &[u8]) -> bool {
+        let mut state = self.shard(key);
+        state.lookup(key).is_some() && state.set_expire(key, None)
+    }
+
+    /// 查询 key 过期的时间点（unix 时间戳，毫秒）。
+    /// key 不存在时返回 `None`，未设置过期时间时返回 `Some(None)`
+    pub fn expire_time(&self, key: &[u8]) -> Option<Option<u64>> {
+        let mut state = self.shard(key);
+        state.lookup(key).map(|entry| entry.expire_at)
+    }
+
+    /// 查询 key 剩余的存活时间（毫秒）。
+    /// key 不存在时返回 `None`，未设置过期时间时返回 `Some(None)`
+    pub fn ttl(&self, key: &[u8]) -> Option<Option<u64>> {
+        let mut state = self.shard(key);
+        let now = now_ms();
+        state.lookup(key)
+            .map(|entry| entry.expire_at.map(|when| when.saturating_sub(now)))
+    }
+
+    /// 开始 WATCH key，返回 key 当前的版本号。同一个 key 可以被多次 WATCH，每次都需要对应一次 [`Db::unwatch`]
+    pub(crate) fn watch(&self, key: &Bytes) -> u64 {
+        let mut state = self.shard(key);
+        // 已经过期的 key 先删除，之后再过期才算作修改
+        state.lookup(key);
+        let watch = state.watched.entry(key.clone()).or_default();
+        watch.watchers += 1;
+        watch.version
+    }
+
+    pub(crate) fn unwatch(&self, key: &[u8]) {
+        let mut state = self.shard(key);
+        if let Some(watch) = state.watched.get_mut(key) {
+            watch.watchers -= 1;
+            if watch.watchers == 0 {
+                state.watched.remove(key);
+            }
+        }
+    }
+
+    /// 被 WATCH 的 key 的版本号，key 每次被修改（包括删除、过期）都会加一。
+    /// 只保证修改时版本号一定变化，没有修改时也可能变化
+    pub(crate) fn version(&self, key: &[u8]) -> u64 {
+        let mut state = self.shard(key);
+        state.lookup(key);
+        state.watched.get(key).map_or(0, |watch| watch.version)
+    }
+
+    /// 普通命令执行期间持有的读锁，不同连接的命令可以并发执行
+    pub(crate) fn command_guard(&self) -> RwLockReadGuard<'_, ()> {
+        self.shared.exec_lock.read().unwrap()
+    }
+
+    /// EXEC 执行期间持有的写锁，保证事务中的命令执行时不会穿插其他命令
+    pub(crate) fn exec_guard(&self) -> RwLockWriteGuard<'_, ()> {
+        self.shared.exec_lock.write().unwrap()
+    }
+
+    /// 订阅频道，见 [`crate::pubsub`]
+    pub fn subscribe(&self, channel: Bytes) -> broadcast::Receiver<Bytes> {
+        self.shared.pubsub.lock().unwrap().subscribe(channel)
+    }
+
+    /// 订阅匹配 glob 模式的频道，收到的消息为 (频道, 消息)
+    pub fn psubscribe(&self, pattern: Bytes) -> broadcast::Receiver<(Bytes, Bytes)> {
+        self.shared.pubsub.lock().unwrap().psubscribe(pattern)
+    }
+
+    /// 发布消息，返回收到消息的订阅者数量
+    pub fn publish(&self, channel: &Bytes, message: Bytes) -> usize {
+        self.shared.pubsub.lock().unwrap().publish(channel, message)
+    }
+
+    /// 订阅分片频道
+    pub fn ssubscribe(&self, channel: Bytes) -> broadcast::Receiver<Bytes> {
+        self.shared.pubsub.lock().unwrap().ssubscribe(channel)
+    }
+
+    /// 向分片频道发布消息，返回收到消息的订阅者数量
+    pub fn spublish(&self, channel: &Bytes, message: Bytes) -> usize {
+        self.shared.pubsub.lock().unwrap().spublish(channel, message)
+    }
+
+    /// 把整个键空间编码为快照，见 [`crate::rdb`]。得到的是调用时刻一致的数据，见 [`Db::snapshot`]
+    pub fn dump(&self) -> Vec<u8> {
+        self.snapshot().encode()
+    }
+
+    /// 开始一次快照，返回的 [`Snapshot`] 编码出的是调用时刻的数据，之后的修改不会影响它。
+    ///
+    /// 调用时按下标顺序锁住所有分片，只为每个分片装上写屏障而不复制数据。之后 key 第一次被访问之前，
+    /// 它当前的值会先被写入快照，编码时跳过这些 key，相当于 redis fork 之后由操作系统完成的写时复制。
+    /// 无法区分读写，只读的访问也会触发写屏障。
+    ///
+    /// 同一时刻只能有一个快照，已经有快照在进行时阻塞当前线程等待它结束，不能在 tokio 的工作线程中调用
+    pub fn snapshot(&self) -> Snapshot {
+        let mut snapshotting = self.shared.snapshotting.lock().unwrap();
+        while *snapshotting {
+            snapshotting = self.shared.snapshot_done.wait(snapshotting).unwrap();
+        }
+        *snapshotting = true;
+        drop(snapshotting);
+
+        let mut shards: Vec<_> = self.shared.shards.iter().map(|shard| shard.lock().unwrap()).collect();
+        let now = now_ms();
+        for shard in shards.iter_mut() {
+            shard.snapshot = Some(Capture { done: HashSet::new(), encoder: rdb::Encoder::fragment(), now });
+        }
+        Snapshot { db: self.clone() }
+    }
+
+    /// 加载快照中的 key，已存在的 key 会被覆盖，已过期的 key 会被忽略。返回加载的 key 数量
+    pub fn load(&self, data: &[u8]) -> crate::Result<usize> {
+        let now = now_ms();
+        let mut loaded = 0;
+        rdb::decode(data, |key, value, expire_at| {
+            if expire_at.is_none_or(|when| when > now) {
+                self.insert(key, value, expire_at);
+                loaded += 1;
+            }
+        })?;
+        Ok(loaded)
+    }
+
+    /// 把整个键空间编码为快照，清空后再加载回来，返回加载的 key 数量，对应 `DEBUG RELOAD`，用于检查快照编解码是否一致。
+    ///
+    /// 与 [`Db::dump`] 不同，期间锁住所有分片，其他连接看不到清空了一半的键空间。
+    /// 先解码出所有 key 再清空，解码失败时键空间保持不变
+    pub fn reload(&self) -> crate::Result<usize> {
+        let mut shards: Vec<_> = self.shared.shards.iter().map(|shard| shard.lock().unwrap()).collect();
+        let now = now_ms();
+        let mut encoder = rdb::Encoder::new();
+        for shard in shards.iter() {
+            for (key, entry) in shard.entries.iter() {
+                if !entry.is_expired(now) {
+                    encoder.write_entry(key.val(), &entry.value, entry.expire_at);
+                }
+            }
+        }
+        let mut decoded = vec![];
+        rdb::decode(&encoder.finish(), |key, value, expire_at| decoded.push((key, value, expire_at)))?;
+
+        let old: Vec<_> = shards.iter_mut().map(|shard| shard.clear()).collect();
+        let loaded = decoded.len();
+        for (key, value, expire_at) in decoded {
+            shards[self.shard_index(&key)].insert(key, value, expire_at);
+        }
+        // 旧的键空间在释放分片锁之后再释放
+        drop(shards);
+        drop(old);
+        Ok(loaded)
+    }
+
+    /// 更换脚本引擎，已缓存的脚本会被清除
+    pub fn set_script_engine(&self, engine: Box<dyn Engine>) {
+        self.shared.scripts.lock().unwrap().set_engine(engine);
+    }
+
+    /// 编译并缓存脚本，返回其 SHA1
+    pub(crate) fn load_script(&self, source: &[u8]) -> Result<(String, Arc<dyn Program>), String> {
+        self.shared.scripts.lock().unwrap().load(source)
+    }
+
+    /// 缓存中 SHA1 为 sha 的脚本
+    pub(crate) fn script(&self, sha: &str) -> Option<Arc<dyn Program>> {
+        self.shared.scripts.lock().unwrap().get(sha)
+    }
+
+    /// 清除所有缓存的脚本
+    pub(crate) fn flush_scripts(&self) {
+        self.shared.scripts.lock().unwrap().flush();
+    }
+
+    /// 当前配置的副本
+    pub fn config(&self) -> Config {
+        self.shared.config.read().unwrap().clone()
+    }
+
+    /// 在锁内修改配置，期间其他连接读不到修改了一半的配置
+    pub fn update_config<R>(&self, f: impl FnOnce(&mut Config) -> R) -> R {
+        f(&mut self.shared.config.write().unwrap())
+    }
+
+    /// 快照文件的路径
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.shared.config.read().unwrap().dbfilename.clone()
+    }
+
+    pub fn set_snapshot_path(&self, path: impl Into<PathBuf>) {
+        self.shared.config.write().unwrap().dbfilename = path.into();
+    }
+
+    /// 是否正在后台保存快照
+    pub fn is_saving(&self) -> bool {
+        self.shared.saving.load(atomic::Ordering::Acquire)
+    }
+
+    /// 标记开始保存快照，已经在保存时返回 false
+    pub(crate) fn begin_save(&self) -> bool {
+        !self.shared.saving.swap(true, atomic::Ordering::AcqRel)
+    }
+
+    pub(crate) fn end_save(&self) {
+        self.shared.saving.store(false, atomic::Ordering::Release);
+    }
+
+    /// 所有 key 占用内存的估计值（字节）
+    pub fn used_memory(&self) -> usize {
+        self.shared.shards.iter().map(|shard| shard.lock().unwrap().used_memory).sum()
+    }
+
+    /// key 实际占用的内存（字节），会遍历整个值，key 不存在时返回 `None`。不算作对 key 的访问
+    pub fn memory_usage(&self, key: &[u8]) -> Option<usize> {
+        self.peek(key, |found| found.map(|(value, _)| entry_size(key, value.mem_size())))
+    }
+
+    /// 按值的类型汇总 key 的个数与内存占用的估计值，包括已过期但还没被删除的 key
+    pub fn memory_stats(&self) -> BTreeMap<&'static str, TypeMemory> {
+        let mut stats = BTreeMap::new();
+        for shard in self.shared.shards.iter() {
+            let shard = shard.lock().unwrap();
+            for (_, entry) in shard.entries.iter() {
+                let total: &mut TypeMemory = stats.entry(entry.value.type_name()).or_default();
+                total.keys += 1;
+                total.bytes += entry.size;
+            }
+        }
+        stats
+    }
+
+    /// 累计因过期删除的 key 数，包括惰性删除与主动删除
+    pub fn expired_keys(&self) -> u64 {
+        self.shared.shards.iter().map(|shard| shard.lock().unwrap().expired_keys).sum()
+    }
+
+    /// 内存上限（字节），0 表示不限制
+    pub fn maxmemory(&self) -> usize {
+        self.shared.config.read().unwrap().maxmemory
+    }
+
+    pub fn set_maxmemory(&self, maxmemory: usize) {
+        self.shared.config.write().unwrap().maxmemory = maxmemory;
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.shared.config.read().unwrap().maxmemory_policy
+    }
+
+    pub fn set_eviction_policy(&self, policy: EvictionPolicy) {
+        self.shared.config.write().unwrap().maxmemory_policy = policy;
+    }
+
+    /// 暂停或恢复主动过期，暂停期间已过期的 key 只会在访问时被删除
+    pub fn set_active_expire(&self, enabled: bool) {
+        self.shared.active_expire.store(enabled, atomic::Ordering::Relaxed);
+    }
+
+    /// 累计淘汰的 key 数
+    pub fn evicted_keys(&self) -> u64 {
+        self.shared.evicted_keys.load(atomic::Ordering::Relaxed)
+    }
+
+    /// 内存占用超出 maxmemory 时按淘汰策略删除 key，直到回到 maxmemory 以下。
+    /// 策略为 noeviction，或者没有可以淘汰的 key 时返回 [`OutOfMemory`]
+    pub fn evict(&self) -> Result<(), OutOfMemory> {
+        let maxmemory = self.maxmemory();
+        if maxmemory == 0 {
+            return Ok(());
+        }
+        let policy = self.eviction_policy();
+        while self.used_memory() > maxmemory {
+            if policy == EvictionPolicy::NoEviction {
+                return Err(OutOfMemory);
+            }
+            // 从每个分片抽样，淘汰所有样本中得分最高的 key
+            let now = now_ms();
+            let mut best: Option<(u64, usize, Bytes)> = None;
+            for (i, shard) in self.shared.shards.iter().enumerate() {
+                for (score, key) in shard.lock().unwrap().sample(policy, now) {
+                    if best.as_ref().is_none_or(|(best, ..)| score > *best) {
+                        best = Some((score, i, key));
+                    }
+                }
+            }
+            let (_, i, key) = best.ok_or(OutOfMemory)?;
+            // 抽样之后锁被释放过，key 可能已经被删除
+            if self.shared.shards[i].lock().unwrap().remove(&key) {
+                self.shared.evicted_keys.fetch_add(1, atomic::Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+
+    /// 运行统计，见 [`crate::stats`]
+    pub fn stats(&self) -> &Stats {
+        &self.shared.stats
+    }
+
+    /// 存活的连接，见 [`crate::clients`]
+    pub fn clients(&self) -> &Clients {
+        &self.shared.clients
+    }
+
+    /// 阻塞命令的等待登记，见 [`crate::blocking`]
+    pub fn waiters(&self) -> &Waiters {
+        &self.shared.waiters
+    }
+
+    /// 复制偏移量与副本的确认，见 [`crate::replication`]
+    pub fn replication(&self) -> &Replication {
+        &self.shared.replication
+    }
+
+    /// 用户及其权限，见 [`crate::acl`]
+    pub fn acl(&self) -> RwLockReadGuard<'_, Acl> {
+        self.shared.acl.read().unwrap()
+    }
+
+    /// 集群模式下槽的分配，没有开启集群模式时为 `None`，见 [`crate::cluster`]
+    pub fn cluster(&self) -> Option<&Cluster> {
+        self.shared.cluster.as_ref()
+    }
+
+    pub fn update_acl<R>(&self, f: impl FnOnce(&mut Acl) -> R) -> R {
+        f(&mut self.shared.acl.write().unwrap())
+    }
+
+    /// 慢查询日志，见 [`crate::slowlog`]
+    pub fn slowlog(&self) -> &SlowLog {
+        &self.shared.slowlog
+    }
+
+    /// 命令 command 从 started_at（unix 时间戳，秒）开始执行了 duration，按当前配置决定是否记入慢查询日志
+    pub fn record_duration(&self, command: &Frame, started_at: u64, duration: Duration) {
+        let (threshold, max_len) = {
+            let config = self.shared.config.read().unwrap();
+            (config.slowlog_log_slower_than, config.slowlog_max_len)
+        };
+        self.shared.slowlog.record(command, started_at, duration, threshold, max_len);
+    }
+
+    /// 连接空闲多久之后被断开，为 0 时不断开
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.shared.config.read().unwrap().timeout)
+    }
+
+    /// 各类连接的输出缓冲区限制
+    pub fn output_limits(&self) -> OutputLimits {
+        self.shared.config.read().unwrap().output_limits
+    }
+
+    /// 解析请求时的限制
+    pub fn proto_limits(&self) -> ProtocolLimits {
+        self.shared.config.read().unwrap().proto_limits
+    }
+
+    /// 各类型使用 ziplist 编码的阈值
+    pub fn encoding_limits(&self) -> EncodingLimits {
+        self.shared.config.read().unwrap().limits
+    }
+
+    /// 修改编码阈值。已经转换过编码的值不会再转换回去
+    pub fn set_encoding_limits(&self, limits: EncodingLimits) {
+        self.shared.config.write().unwrap().limits = limits;
+    }
+}
+
+impl Shared {
+    /// 在 budget 内逐个推进各分片键空间的 rehash。正被其他连接锁住的分片跳过，
+    /// 这些分片上的操作本身就会推进 rehash。返回处理过的分片中还在 rehash 的个数
+    fn active_rehash(&self, budget: Duration) -> usize {
+        let deadline = Instant::now() + budget;
+        let mut rehashing = 0;
+        for shard in self.shards.iter() {
+            let Ok(mut shard) = shard.try_lock() else {
+                continue;
+            };
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if shard.entries.rehash_for(remaining) {
+                rehashing += 1;
+            }
+        }
+        rehashing
+    }
+
+    /// 逐个分片删除已过期的 key，同一时刻只锁住一个分片
+    fn purge_expired_keys(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().purge_expired_keys())
+            .sum()
+    }
+}
+
+impl Shard {
+    /// 查找 key，已过期的 key 会在这里被删除（惰性删除）。找到的 key 会记录一次访问
+    fn lookup(&mut self, key: &[u8]) -> Option<&mut Entry> {
+        let entry = self.peek(key)?;
+        entry.access.hit(lru_clock());
+        Some(entry)
+    }
+
+    /// 与 [`Shard::lookup`] 相同，但不记录访问
+    fn peek(&mut self, key: &[u8]) -> Option<&mut Entry> {
+        self.capture(key);
+        if self.expire_if_needed(key, now_ms()) {
+            return None;
+        }
+        self.entries.get_mut(key)
+    }
+
+    /// key 在 now 时已过期的话删除它，返回是否删除了。
+    ///
+    /// 惰性删除与主动删除都经过这里，之后需要把删除传播出去（如写入 AOF）的话也在这里处理
+    fn expire_if_needed(&mut self, key: &[u8], now: u64) -> bool {
+        if !self.entries.get(key).is_some_and(|entry| entry.is_expired(now)) {
+            return false;
+        }
+        self.remove(key);
+        self.expired_keys += 1;
+        true
+    }
+
+    fn remove(&mut self, key: &[u8]) -> bool {
+        self.pop(key).is_some()
+    }
+
+    /// 删除 key 并返回它的 entry，维护 expires 与版本号
+    fn pop(&mut self, key: &[u8]) -> Option<Entry> {
+        self.expires.remove(key);
+        let entry = self.take(key)?;
+        self.touch(key);
+        Some(entry)
+    }
+
+    /// 设置 key 的值与过期时间，已存在则覆盖
+    fn insert(&mut self, key: Bytes, value: RedisObject, expire_at: Option<u64>) {
+        self.touch(&key);
+        match expire_at {
+            Some(when) => self.expires.insert(key.clone(), when),
+            None => {
+                self.expires.remove(&key);
+            },
+        }
+        self.put(&key, Entry::new(value, expire_at));
+    }
+
+    /// 修改已存在的 key 的过期时间，`None` 为移除过期时间，过期时间已经过去的话直接删除 key。
+    /// 返回 key 是否有变化
+    fn set_expire(&mut self, key: &[u8], expire_at: Option<u64>) -> bool {
+        self.capture(key);
+        let Some(entry) = self.entries.get_mut(key) else {
+            return false;
+        };
+        if expire_at.is_some_and(|when| when <= now_ms()) {
+            return self.remove(key);
+        }
+        if expire_at.is_none() && entry.expire_at.is_none() {
+            return false;
+        }
+        entry.expire_at = expire_at;
+        match expire_at {
+            Some(when) => self.expires.insert(Bytes::copy_from_slice(key), when),
+            None => {
+                self.expires.remove(key);
+            },
+        }
+        self.touch(key);
+        true
+    }
+
+    /// 写入 entry 并估计其内存占用，已存在的 key 被覆盖。不维护 expires
+    fn put(&mut self, key: &[u8], mut entry: Entry) {
+        self.capture(key);
+        entry.size = entry_size(key, entry.value.mem_usage());
+        self.used_memory += entry.size;
+        if let Some(old) = self.entries.insert(SDS::new(key), entry) {
+            self.used_memory -= old.size;
+        }
+    }
+
+    /// 从 Dict 中取出 entry。不维护 expires
+    fn take(&mut self, key: &[u8]) -> Option<Entry> {
+        self.capture(key);
+        let entry = self.entries.remove(key)?;
+        self.used_memory -= entry.size;
+        Some(entry)
+    }
+
+    /// 取出 key 的值及其过期时间、访问记录，修改后用 [`Shard::restore`] 放回
+    fn detach(&mut self, key: &[u8]) -> Detached {
+        match self.lookup(key) {
+            Some(_) => {
+                let entry = self.take(key).unwrap();
+                Detached { value: Some(entry.value), expire_at: entry.expire_at, access: Some(entry.access) }
+            },
+            None => Detached { value: None, expire_at: None, access: None },
+        }
+    }
+
+    /// 放回 [`Shard::detach`] 取出的值，值为 `None` 时删除 key。
+    /// 无法得知值是否真的被修改，key 原来存在或者被新建时都视为修改
+    fn restore(&mut self, key: &[u8], detached: Detached) {
+        if detached.access.is_some() || detached.value.is_some() {
+            self.touch(key);
+        }
+        match detached.value {
+            Some(value) => {
+                let mut entry = Entry::new(value, detached.expire_at);
+                if let Some(access) = detached.access {
+                    entry.access = access;
+                }
+                self.put(key, entry);
+            },
+            None => {
+                self.expires.remove(key);
+            },
+        }
+    }
+
+    /// 按淘汰策略抽样若干 key，返回它们的得分
+    fn sample(&mut self, policy: EvictionPolicy, now: u64) -> Vec<(u64, Bytes)> {
+        let mut samples = vec![];
+        if policy == EvictionPolicy::VolatileTtl {
+            // 最早过期的 key 得分最高，不需要随机抽样
+            let keys: Vec<Bytes> = self.expires.soonest().take(EVICTION_SAMPLES).map(|(_, key)| key.clone()).collect();
+            for key in keys {
+                if let Some(entry) = self.entries.get(&key[..]) {
+                    samples.push((entry.access.score(policy, entry.expire_at, now), key));
+                }
+            }
+            return samples;
+        }
+        let score = |key: &SDS, entry: &Entry| (entry.access.score(policy, entry.expire_at, now), Bytes::copy_from_slice(key.val()));
+        // 从随机的位置开始遍历，跳过空的 slot
+        let mut cursor = rand::random::<u64>();
+        for _ in 0..EVICTION_SAMPLES * 10 {
+            cursor = self.entries.scan(cursor, |key, entry| samples.push(score(key, entry)));
+            if samples.len() >= EVICTION_SAMPLES {
+                return samples;
+            }
+        }
+        // key 很少而 slot 很多时，可能一直遇到空的 slot
+        if samples.is_empty() {
+            samples.extend(self.entries.iter().take(EVICTION_SAMPLES).map(|(key, entry)| score(key, entry)));
+        }
+        samples
+    }
+
+    /// 快照进行中时，在 key 第一次被访问之前把它当前的值写入快照，见 [`Db::snapshot`]。
+    /// 所有拿到 entry 可变引用或者修改 Dict 的路径都要先调用它
+    fn capture(&mut self, key: &[u8]) {
+        let Shard { entries, snapshot: Some(capture), .. } = self else {
+            return;
+        };
+        if capture.done.contains(key) {
+            return;
+        }
+        capture.done.insert(Bytes::copy_from_slice(key));
+        if let Some(entry) = entries.get(key) {
+            capture.write(key, entry);
+        }
+    }
+
+    /// 把还没写入快照的 key 全部写入，清空分片之前调用
+    /// 取出所有 key，被 WATCH 且存在的 key 增加版本号
+    fn clear(&mut self) -> Dict<SDS, Entry> {
+        self.capture_all();
+        for (key, watch) in self.watched.iter_mut() {
+            if self.entries.get(&key[..]).is_some() {
+                watch.version += 1;
+            }
+        }
+        self.expires = Expires::default();
+        self.used_memory = 0;
+        std::mem::take(&mut self.entries)
+    }
+
+    fn capture_all(&mut self) {
+        let Shard { entries, snapshot: Some(capture), .. } = self else {
+            return;
+        };
+        for (key, entry) in entries.iter() {
+            capture.write_once(key, entry);
+        }
+    }
+
+    /// key 被修改，被 WATCH 时增加其版本号
+    fn touch(&mut self, key: &[u8]) {
+        if let Some(watch) = self.watched.get_mut(key) {
+            watch.version += 1;
+        }
+    }
+
+    fn purge_expired_keys(&mut self) -> usize {
+        let now = now_ms();
+        let expired: Vec<Bytes> = self.expires.expired(now).cloned().collect();
+        for key in &expired {
+            self.expire_if_needed(key, now);
+        }
+        expired.len()
+    }
+}
+
+impl Capture {
+    fn write(&mut self, key: &[u8], entry: &Entry) {
+        if !entry.is_expired(self.now) {
+            self.encoder.write_entry(key, &entry.value, entry.expire_at);
+        }
+    }
+
+    /// 写入还没写入过的 key。Dict 在 rehash 期间遍历可能返回同一个 key 多次
+    fn write_once(&mut self, key: &SDS, entry: &Entry) {
+        if !self.done.contains(key.val()) {
+            self.done.insert(Bytes::copy_from_slice(key.val()));
+            self.write(key.val(), entry);
+        }
+    }
+}
+
+impl Snapshot {
+    /// 编码快照。逐个分片遍历，每次只锁住分片遍历一小段 slot，期间其他连接可以继续读写
+    pub fn encode(self) -> Vec<u8> {
+        let mut encoder = rdb::Encoder::new();
+        for shard in &self.db.shared.shards {
+            let mut cursor = 0;
+            loop {
+                let mut shard = shard.lock().unwrap();
+                let Shard { entries, snapshot, .. } = &mut *shard;
+                let capture = snapshot.as_mut().unwrap();
+                for _ in 0..SNAPSHOT_BATCH {
+                    cursor = entries.scan(cursor, |key, entry| capture.write_once(key, entry));
+                    if cursor == 0 {
+                        break;
+                    }
+                }
+                if cursor == 0 {
+                    encoder.append(snapshot.take().unwrap().encoder);
+                    break;
+                }
+            }
+        }
+        encoder.finish()
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        for shard in &self.db.shared.shards {
+            shard.lock().unwrap().snapshot = None;
+        }
+        *self.db.shared.snapshotting.lock().unwrap() = false;
+        self.db.shared.snapshot_done.notify_one();
+    }
+}
+
+/// 键空间中一项占用的内存：key、值在堆上的部分，加上 Dict 节点中的 Entry、SDS 的头部以及指向下一个节点的指针
+fn entry_size(key: &[u8], value_size: usize) -> usize {
+    key.len() + value_size + size_of::<Entry>() + size_of::<SDS>() + size_of::<usize>()
+}
+
+/// 从分片中取出、正在被修改的值，见 [`Db::update`]
+struct Detached {
+    value: Option<RedisObject>,
+    expire_at: Option<u64>,
+    /// key 原来不存在时为 `None`
+    access: Option<Access>,
+}
+
+/// 后台定期任务，对应 redis 的 serverCron：
+/// - 主动过期：扫描并删除已过期的 key；
+/// - 推进 rehash：很少被访问的分片也能完成 rehash，尽早释放旧表。
+async fn cron_task(shared: Weak<Shared>) {
+    let mut interval = tokio::time::interval(CRON_INTERVAL);
+    loop {
+        interval.tick().await;
+        // 所有 Db 都已回收，任务退出
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+        if shared.active_expire.load(atomic::Ordering::Relaxed) {
+            shared.purge_expired_keys();
+        }
+        if shared.config.read().unwrap().activerehashing {
+            shared.active_rehash(ACTIVE_REHASH_BUDGET);
+        }
+    }
+}
+
+/// 当前的 unix 时间戳（毫秒）
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch")
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use bytes::Bytes;
+
+    use crate::{evict::{EvictionPolicy, LRU_CLOCK_RESOLUTION}, expires::ExpireFlags, object::{RedisObject, ZipLimits}, types::{List, ZSet}};
+
+    use super::{Db, now_ms};
+
+    #[test]
+    fn lazy_expire() {
+        let db = Db::new();
+        db.set(Bytes::from("k"), Bytes::from("v"), Some(now_ms() + 20));
+        assert_eq!(db.get(b"k").unwrap(), Some(Bytes::from("v")));
+        assert!(matches!(db.ttl(b"k"), Some(Some(ms)) if ms <= 20));
+        thread::sleep(Duration::from_millis(30));
+        assert!(!db.exists(b"k"));
+        assert_eq!(db.get(b"k").unwrap(), None);
+        assert_eq!(db.ttl(b"k"), None);
+        assert_eq!(db.expired_keys(), 1);
+
+        // 读、写两种访问都把过期的 key 当作不存在，只有读计入命中率
+        db.set(Bytes::from("r"), Bytes::from("v"), Some(now_ms() + 10));
+        db.set(Bytes::from("w"), Bytes::from("v"), Some(now_ms() + 10));
+        thread::sleep(Duration::from_millis(20));
+        let misses = db.stats().keyspace_misses();
+        assert!(db.lookup_read(b"r", |value| value.is_none()));
+        assert!(db.lookup_write(b"w", |value| value.is_none()));
+        assert_eq!(db.stats().keyspace_misses(), misses + 1);
+        assert_eq!(db.expired_keys(), 3);
+        assert_eq!(db.key_counts(), (0, 0));
+    }
+
+    #[test]
+    fn peek_without_touch() {
+        let db = Db::new();
+        db.set(Bytes::from("k"), Bytes::from("v"), None);
+        thread::sleep(Duration::from_millis(30));
+        let idle = || db.peek(b"k", |found| found.map(|(_, access)| access.idle(now_ms())));
+        assert!(idle().unwrap() >= 30);
+        // 查看不算作访问
+        assert!(idle().unwrap() >= 30);
+        db.get(b"k").unwrap();
+        assert!(idle().unwrap() < 30);
+
+        db.set(Bytes::from("e"), Bytes::from("v"), Some(now_ms()));
+        assert!(db.peek(b"e", |found| found.is_none()));
+        assert_eq!(db.key_counts().0, 1);
+    }
+
+    #[test]
+    fn expire_and_persist() {
+        let db = Db::new();
+        assert!(!db.expire_at(b"k", now_ms() + 1000));
+        db.set(Bytes::from("k"), Bytes::from("v"), None);
+        assert_eq!(db.ttl(b"k"), Some(None));
+        assert!(!db.persist(b"k"));
+
+        assert!(db.expire_at(b"k", now_ms() + 1000));
+        assert!(matches!(db.ttl(b"k"), Some(Some(_))));
+        assert!(db.persist(b"k"));
+        assert_eq!(db.ttl(b"k"), Some(None));
+
+        // SET 会清除原有的过期时间
+        db.expire_at(b"k", now_ms() + 1000);
+        db.set(Bytes::from("k"), Bytes::from("v2"), None);
+        assert_eq!(db.ttl(b"k"), Some(None));
+
+        // 过去的时间点直接删除 key
+        assert!(db.expire_at(b"k", now_ms() - 1));
+        assert!(!db.exists(b"k"));
+
+        // 按原来的过期时间决定是否修改
+        db.set(Bytes::from("k"), Bytes::from("v"), None);
+        let when = now_ms() + 10_000;
+        assert!(!db.expire_at_if(b"k", when, ExpireFlags { xx: true, ..Default::default() }));
+        assert!(!db.expire_at_if(b"k", when, ExpireFlags { gt: true, ..Default::default() }));
+        assert!(db.expire_at_if(b"k", when, ExpireFlags { nx: true, ..Default::default() }));
+        assert!(!db.expire_at_if(b"k", when + 1, ExpireFlags { nx: true, ..Default::default() }));
+        assert!(!db.expire_at_if(b"k", when + 1, ExpireFlags { lt: true, ..Default::default() }));
+        assert!(db.expire_at_if(b"k", when + 1, ExpireFlags { gt: true, ..Default::default() }));
+        assert_eq!(db.expire_time(b"k"), Some(Some(when + 1)));
+        assert_eq!(db.expire_time(b"missing"), None);
+    }
+
+    #[test]
+    fn getdel_and_getex() {
+        let db = Db::new();
+        assert_eq!(db.getdel(b"k").unwrap(), None);
+        db.set(Bytes::from("k"), Bytes::from("v"), None);
+        assert_eq!(db.getex(b"k", None).unwrap(), Some(Bytes::from("v")));
+        assert_eq!(db.ttl(b"k"), Some(None));
+        db.getex(b"k", Some(Some(now_ms() + 1000))).unwrap();
+        assert!(matches!(db.ttl(b"k"), Some(Some(_))));
+        db.getex(b"k", Some(None)).unwrap();
+        assert_eq!(db.ttl(b"k"), Some(None));
+        assert_eq!(db.getex(b"k", Some(Some(now_ms() - 1))).unwrap(), Some(Bytes::from("v")));
+        assert!(!db.exists(b"k"));
+
+        db.set(Bytes::from("k"), Bytes::from("v"), None);
+        assert_eq!(db.getdel(b"k").unwrap(), Some(Bytes::from("v")));
+        assert!(!db.exists(b"k"));
+
+        db.update(&Bytes::from("l"), |value| *value = Some(RedisObject::List(List::new())));
+        assert!(db.getdel(b"l").is_err());
+        assert!(db.getex(b"l", Some(None)).is_err());
+        assert!(db.exists(b"l"));
+    }
+
+    #[test]
+    fn active_rehash() {
+        let db = Db::with_shards(1);
+        // 写到开始扩容为止，之后不再访问
+        let mut i = 0;
+        while db.shared.shards[0].lock().unwrap().entries.rehash_progress().is_none() {
+            db.set(Bytes::from(i.to_string()), Bytes::from("v"), None);
+            i += 1;
+        }
+        // 分片被锁住时跳过
+        {
+            let locked = db.shared.shards[0].lock().unwrap();
+            db.shared.active_rehash(Duration::from_millis(10));
+            assert!(locked.entries.rehash_progress().is_some());
+        }
+        assert_eq!(db.shared.active_rehash(Duration::from_millis(10)), 0);
+        assert_eq!(db.shared.shards[0].lock().unwrap().entries.rehash_progress(), None);
+        assert_eq!(db.dbsize(), i);
+    }
+
+    #[test]
+    fn purge_expired() {
+        let db = Db::new();
+        for i in 0..10u8 {
+            let expire_at = if i % 2 == 0 { Some(now_ms() - 1) } else { None };
+            db.set(Bytes::copy_from_slice(&[i]), Bytes::from("v"), expire_at);
+        }
+        assert_eq!(db.shared.purge_expired_keys(), 5);
+        assert_eq!(db.shared.purge_expired_keys(), 0);
+        assert!(db.exists(&[1]));
+        assert!(!db.exists(&[2]));
+
+        // 修改过期时间、改名、覆盖、删除后索引保持同步
+        db.set(Bytes::from("a"), Bytes::from("v"), Some(now_ms() + 10_000));
+        db.set(Bytes::from("b"), Bytes::from("v"), Some(now_ms() + 10_000));
+        assert!(db.expire_at(b"a", now_ms() + 20));
+        assert_eq!(db.rename(b"b", Bytes::from("c"), false), Some(true));
+        db.set(Bytes::from("d"), Bytes::from("v"), Some(now_ms() + 10));
+        db.set(Bytes::from("d"), Bytes::from("v"), None);
+        assert_eq!(db.key_counts(), (8, 2));
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(db.shared.purge_expired_keys(), 1);
+        assert_eq!(db.key_counts(), (7, 1));
+        assert!(db.persist(b"c"));
+        assert_eq!(db.key_counts(), (7, 0));
+    }
+
+    #[test]
+    fn expired_keys_stat() {
+        let db = Db::new();
+        for i in 0..10u8 {
+            let expire_at = if i < 6 { Some(now_ms() - 1) } else { None };
+            db.set(Bytes::copy_from_slice(&[i]), Bytes::from("v"), expire_at);
+        }
+        // 主动删除、随机选中、惰性删除都计入 expired_keys，已删除的 key 不会重复计数
+        assert!(db.lookup_write(&[0], |value| value.is_none()));
+        assert_eq!(db.expired_keys(), 1);
+        for _ in 0..20 {
+            let key = db.random_key().unwrap();
+            assert!(key[0] >= 6, "{:?}", key);
+        }
+        let expired = db.expired_keys();
+        assert!(expired >= 1);
+        assert_eq!(db.shared.purge_expired_keys() as u64, 6 - expired);
+        assert_eq!(db.expired_keys(), 6);
+        assert!(!db.exists(&[1]));
+        assert_eq!(db.expired_keys(), 6);
+        assert_eq!(db.key_counts(), (4, 0));
+    }
+
+    #[tokio::test]
+    async fn pause_active_expire() {
+        let db = Db::new();
+        db.set_active_expire(false);
+        db.set(Bytes::from("k"), Bytes::from("v"), Some(now_ms() - 1));
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(db.key_counts(), (1, 1));
+        db.set_active_expire(true);
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(db.key_counts(), (0, 0));
+    }
+
+    #[test]
+    fn sharded() {
+        for shards in [1, 4] {
+            let db = Db::with_shards(shards);
+            assert_eq!(db.shards(), shards);
+            for i in 0..100 {
+                db.set(Bytes::from(format!("k{}", i)), Bytes::from(i.to_string()), None);
+            }
+            for i in 0..100 {
+                assert_eq!(db.get(format!("k{}", i).as_bytes()).unwrap(), Some(Bytes::from(i.to_string())));
+            }
+        }
+
+        // key 应当分散到各个分片
+        let db = Db::with_shards(4);
+        for i in 0..100 {
+            db.set(Bytes::from(format!("k{}", i)), Bytes::new(), None);
+        }
+        assert!(db.shared.shards.iter().all(|shard| shard.lock().unwrap().entries.value_cnt() > 0));
+    }
+
+    #[test]
+    fn rename_and_keys() {
+        let db = Db::with_shards(4);
+        for i in 0..20 {
+            db.set(Bytes::from(format!("k{}", i)), Bytes::from(i.to_string()), None);
+        }
+        db.expire_at(b"k0", now_ms() + 1000);
+        let mut keys = db.keys(b"k1?");
+        keys.sort();
+        assert_eq!(keys, (10..20).map(|i| Bytes::from(format!("k{}", i))).collect::<Vec<_>>());
+
+        // 跨分片重命名，过期时间随之转移
+        for i in 0..20 {
+            let to = Bytes::from(format!("n{}", i));
+            assert_eq!(db.rename(format!("k{}", i).as_bytes(), to.clone(), false), Some(true));
+            assert_eq!(db.get(&to).unwrap(), Some(Bytes::from(i.to_string())));
+        }
+        assert!(db.keys(b"k*").is_empty());
+        assert!(matches!(db.ttl(b"n0"), Some(Some(_))));
+        assert_eq!(db.ttl(b"n1"), Some(None));
+        assert_eq!(db.rename(b"k0", Bytes::from("x"), false), None);
+
+        assert_eq!(db.rename(b"n1", Bytes::from("n2"), true), Some(false));
+        assert_eq!(db.rename(b"n1", Bytes::from("n1"), false), Some(true));
+        assert_eq!(db.rename(b"n1", Bytes::from("n0"), false), Some(true));
+        // 覆盖时目标原有的过期时间也一并被替换
+        assert_eq!(db.ttl(b"n0"), Some(None));
+        assert_eq!(db.get(b"n0").unwrap(), Some(Bytes::from("1")));
+        assert_eq!(db.keys(b"*").len(), 19);
+    }
+
+    #[test]
+    fn scan() {
+        let db = Db::with_shards(4);
+        for i in 0..100 {
+            db.set(Bytes::from(format!("k{}", i)), Bytes::new(), None);
+        }
+        let (mut cursor, mut keys) = (0, vec![]);
+        loop {
+            let (next, batch) = db.scan(cursor, 10, |key, _| key != b"k0");
+            keys.extend(batch);
+            // 遍历过程中新增的 key 不影响原有 key 的遍历
+            db.set(Bytes::from(format!("new{}", next)), Bytes::new(), None);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        keys.retain(|key| key.starts_with(b"k"));
+        keys.sort();
+        keys.dedup();
+        let mut expected: Vec<Bytes> = (1..100).map(|i| Bytes::from(format!("k{}", i))).collect();
+        expected.sort();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn update_value() {
+        let db = Db::new();
+        let key = Bytes::from("z");
+        db.update(&key, |value| {
+            let mut zset = ZSet::new();
+            zset.insert(Bytes::from("m"), 1f64, &ZipLimits::default());
+            *value = Some(RedisObject::ZSet(zset));
+        });
+        assert!(db.get(&key).is_err());
+        assert!(db.expire_at(&key, now_ms() + 1000));
+
+        // 修改值不影响过期时间
+        db.update(&key, |value| match value {
+            Some(RedisObject::ZSet(zset)) => zset.insert(Bytes::from("n"), 2f64, &ZipLimits::default()),
+            _ => unreachable!(),
+        });
+        assert!(matches!(db.ttl(&key), Some(Some(_))));
+        db.lookup_read(&key, |value| assert!(matches!(value, Some(RedisObject::ZSet(zset)) if zset.len() == 2)));
+
+        db.update(&key, |value| *value = None);
+        assert!(!db.exists(&key));
+        assert_eq!(db.ttl(&key), None);
+    }
+
+    #[test]
+    fn typed_access() {
+        let db = Db::new();
+        let (list, string) = (Bytes::from("list"), Bytes::from("string"));
+        db.set(string.clone(), Bytes::from("v"), None);
+        let len = db.update_typed(&list, |value: &mut Option<List>| {
+            value.get_or_insert_with(List::new).push_back(Bytes::from("a"), &ZipLimits::default());
+            1
+        });
+        assert_eq!(len.unwrap(), 1);
+        assert_eq!(db.with_typed(&list, |value: Option<&mut List>| value.map(|list| list.len())).unwrap(), Some(1));
+        assert!(db.with_typed(&list, |_: Option<&mut ZSet>| ()).is_err());
+        assert!(db.with_typed(b"missing", |value: Option<&mut ZSet>| value.is_none()).unwrap());
+
+        // 类型不符时不调用 f，原来的值保持不变
+        assert!(db.update_typed(&string, |_: &mut Option<List>| unreachable!()).is_err());
+        assert_eq!(db.get(&string).unwrap(), Some(Bytes::from("v")));
+
+        db.update_typed(&list, |value: &mut Option<List>| *value = None).unwrap();
+        assert!(!db.exists(&list));
+    }
+
+    #[test]
+    fn unlink_and_flushall() {
+        let db = Db::with_shards(4);
+        let key = Bytes::from("big");
+        db.update(&key, |value| {
+            let mut list = List::new();
+            for i in 0..1000 {
+                list.push_back(Bytes::from(i.to_string()), &ZipLimits { max_entries: 0, max_value: 0 });
+            }
+            *value = Some(RedisObject::List(list));
+        });
+        db.set(Bytes::from("small"), Bytes::from("v"), None);
+        assert!(db.unlink(&key));
+        assert!(!db.unlink(&key));
+        assert!(db.unlink(b"small"));
+
+        for i in 0..100 {
+            db.set(Bytes::from(format!("k{}", i)), Bytes::from("v"), Some(now_ms() + 10_000));
+        }
+        let version = db.watch(&Bytes::from("k0"));
+        assert_eq!(db.flushall(true), 100);
+        assert_eq!((db.key_counts(), db.used_memory()), ((0, 0), 0));
+        assert!(db.version(b"k0") > version);
+        assert_eq!(db.flushall(false), 0);
+
+        for _ in 0..100 {
+            if db.lazyfree_pending() == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(db.lazyfree_pending(), 0);
+    }
+
+    #[test]
+    fn snapshot_isolation() {
+        let db = Db::with_shards(4);
+        for i in 0..1000 {
+            db.set(Bytes::from(format!("k{}", i)), Bytes::from(format!("v{}", i)), None);
+        }
+        let snapshot = db.snapshot();
+        db.set(Bytes::from("k0"), Bytes::from("changed"), None);
+        db.del(b"k1");
+        db.rename(b"k2", Bytes::from("k3"), false);
+        db.expire_at(b"k4", now_ms() + 10_000);
+        db.set(Bytes::from("new"), Bytes::from("v"), None);
+        // 编码期间其他线程继续写入
+        let writer = {
+            let db = db.clone();
+            thread::spawn(move || {
+                for i in 0..1000 {
+                    db.set(Bytes::from(format!("k{}", i)), Bytes::from("later"), None);
+                }
+            })
+        };
+        let data = snapshot.encode();
+        writer.join().unwrap();
+        db.flushall(false);
+
+        let restored = Db::new();
+        assert_eq!(restored.load(&data).unwrap(), 1000);
+        for i in 0..1000 {
+            assert_eq!(restored.get(format!("k{}", i).as_bytes()).unwrap(), Some(Bytes::from(format!("v{}", i))));
+        }
+        assert!(!restored.exists(b"new"));
+        assert_eq!(restored.ttl(b"k4"), Some(None));
+
+        // 写屏障已经移除，可以开始下一次快照
+        db.set(Bytes::from("k"), Bytes::from("v"), None);
+        drop(db.snapshot());
+        assert_eq!(Db::new().load(&db.dump()).unwrap(), 1);
+    }
+
+    #[test]
+    fn reload() {
+        let db = Db::with_shards(4);
+        for i in 0..100 {
+            db.set(Bytes::from(format!("k{}", i)), Bytes::from(format!("v{}", i)), None);
+        }
+        db.expire_at(b"k0", now_ms() + 10_000);
+        db.update(&Bytes::from("list"), |value| {
+            let mut list = List::new();
+            list.push_back(Bytes::from("a"), &ZipLimits::default());
+            *value = Some(RedisObject::List(list));
+        });
+        let used = db.used_memory();
+        let version = db.watch(&Bytes::from("k1"));
+
+        assert_eq!(db.reload().unwrap(), 101);
+        assert_eq!(db.key_counts(), (101, 1));
+        assert_eq!(db.used_memory(), used);
+        assert_eq!(db.get(b"k99").unwrap(), Some(Bytes::from("v99")));
+        assert!(matches!(db.ttl(b"k0"), Some(Some(_))));
+        assert!(db.version(b"k1") > version);
+        assert_eq!(Db::new().load(&db.dump()).unwrap(), 101);
+    }
+
+    #[test]
+    fn dbsize_and_random_key() {
+        let db = Db::with_shards(4);
+        assert_eq!((db.dbsize(), db.random_key()), (0, None));
+        for i in 0..100 {
+            db.set(Bytes::from(format!("k{}", i)), Bytes::from("v"), None);
+            db.set(Bytes::from(format!("e{}", i)), Bytes::from("v"), Some(now_ms()));
+        }
+        assert_eq!((db.dbsize(), db.key_counts().0), (100, 200));
+        for _ in 0..100 {
+            assert!(db.random_key().unwrap().starts_with(b"k"));
+        }
+        for i in 0..100 {
+            db.del(format!("k{}", i).as_bytes());
+        }
+        // 只剩下已过期的 key，选中后都会被删除
+        assert_eq!(db.random_key(), None);
+        assert_eq!(db.key_counts().0, 0);
+    }
+
+    #[test]
+    fn copy() {
+        let db = Db::with_shards(4);
+        let tiny = ZipLimits { max_entries: 1, max_value: 1 };
+        let mut zset = ZSet::new();
+        for i in 0..100 {
+            zset.insert(Bytes::from(format!("m{}", i)), i as f64, &tiny);
+        }
+        let (src, dst) = (Bytes::from("src"), Bytes::from("dst"));
+        db.update(&src, |value| *value = Some(RedisObject::ZSet(zset)));
+        db.expire_at(&src, now_ms() + 10_000);
+        assert!(!db.copy(b"missing", dst.clone(), false).unwrap());
+        assert!(db.copy(b"missing", Bytes::from("missing"), false).is_err());
+        assert!(db.copy(&src, src.clone(), true).is_err());
+        assert!(db.copy(&src, dst.clone(), false).unwrap());
+        assert!(db.ttl(&dst).unwrap().is_some());
+
+        // 修改原来的值不影响拷贝
+        db.update(&src, |value| {
+            let Some(RedisObject::ZSet(zset)) = value else { panic!() };
+            zset.insert(Bytes::from("new"), -1.0, &tiny);
+        });
+        let members = |key: &[u8]| db.lookup_read(key, |value| match value.unwrap() {
+            RedisObject::ZSet(zset) => (zset.encoding(), zset.range_by_score(None, None, 0, 0)),
+            other => panic!("unexpected {}", other.type_name()),
+        });
+        let (encoding, copied) = members(&dst);
+        assert_eq!(encoding, members(&src).0);
+        assert_eq!(copied.len(), 100);
+        assert_eq!(copied[0], (Bytes::from("m0"), 0.0));
+
+        // 目标已存在时只有 replace 才覆盖
+        assert!(!db.copy(&src, dst.clone(), false).unwrap());
+        assert!(db.copy(&src, dst.clone(), true).unwrap());
+        assert_eq!(members(&dst).1.len(), 101);
+    }
+
+    #[test]
+    fn update_pair() {
+        let db = Db::with_shards(4);
+        let (a, b) = (Bytes::from("a"), Bytes::from("b"));
+        db.set(a.clone(), Bytes::from("1"), Some(now_ms() + 10_000));
+        db.update_pair(&a, &b, |a, b| *b.unwrap() = a.take());
+        assert!(!db.exists(b"a"));
+        assert_eq!(db.get(b"b").unwrap(), Some(Bytes::from("1")));
+        // 新建的 key 没有过期时间
+        assert_eq!(db.ttl(b"b"), Some(None));
+
+        let n = db.update_pair(&b, &b, |value, other| {
+            assert!(other.is_none());
+            *value = Some(RedisObject::string(b"2"));
+            2
+        });
+        assert_eq!(n, 2);
+        assert_eq!(db.get(b"b").unwrap(), Some(Bytes::from("2")));
+    }
+
+    #[test]
+    fn multi_keys() {
+        let db = Db::with_shards(4);
+        let pairs = |range: std::ops::Range<i32>| range.map(|i| (Bytes::from(format!("k{}", i)), Bytes::from(i.to_string()))).collect::<Vec<_>>();
+        assert!(db.mset(pairs(0..10), false));
+        db.expire_at(b"k0", now_ms() + 1000);
+        // 有一个 key 已存在，都不会写入
+        assert!(!db.mset(pairs(9..20), true));
+        assert!(!db.exists(b"k10"));
+        assert!(db.mset(pairs(10..20), true));
+
+        db.update(&Bytes::from("list"), |value| *value = Some(RedisObject::List(List::new())));
+        let keys = [&b"k0"[..], b"missing", b"list", b"k19"].map(Bytes::from_static);
+        assert_eq!(db.mget(&keys), [Some(Bytes::from("0")), None, None, Some(Bytes::from("19"))]);
+
+        // 覆盖时清除过期时间
+        assert!(db.mset(vec![(Bytes::from("k0"), Bytes::from("a")), (Bytes::from("k0"), Bytes::from("b"))], false));
+        assert_eq!(db.ttl(b"k0"), Some(None));
+        assert_eq!(db.get(b"k0").unwrap(), Some(Bytes::from("b")));
+
+        db.expire_at(b"k1", now_ms() + 1000);
+        assert_eq!(db.getset(Bytes::from("k1"), Bytes::from("x")).unwrap(), Some(Bytes::from("1")));
+        assert_eq!(db.ttl(b"k1"), Some(None));
+        assert_eq!(db.getset(Bytes::from("new"), Bytes::from("y")).unwrap(), None);
+        assert_eq!(db.get(b"new").unwrap(), Some(Bytes::from("y")));
+        assert!(db.getset(Bytes::from("list"), Bytes::from("z")).is_err());
+        db.lookup_read(b"list", |value| assert!(matches!(value, Some(RedisObject::List(_)))));
+    }
+
+    #[test]
+    fn used_memory() {
+        let db = Db::with_shards(4);
+        assert_eq!(db.used_memory(), 0);
+        db.set(Bytes::from("k"), Bytes::from(vec![b'x'; 100]), None);
+        let used = db.used_memory();
+        assert!(used > 100);
+        db.set(Bytes::from("k"), Bytes::from(vec![b'x'; 1000]), None);
+        assert!(db.used_memory() >= used + 900);
+
+        // 重命名后按新的 key 计算
+        let used = db.used_memory();
+        db.rename(b"k", Bytes::from("k".repeat(11)), false);
+        assert_eq!(db.used_memory(), used + 10);
+
+        let key = Bytes::from("z");
+        db.update(&key, |value| {
+            let mut zset = ZSet::new();
+            for i in 0..1000 {
+                zset.insert(Bytes::from(format!("member:{}", i)), i as f64, &ZipLimits::default());
+            }
+            *value = Some(RedisObject::ZSet(zset));
+        });
+        assert!(db.used_memory() > used + 1000 * 10);
+
+        db.del(&key);
+        db.expire_at(&"k".repeat(11).into_bytes(), now_ms() - 1);
+        assert_eq!(db.used_memory(), 0);
+    }
+
+    #[test]
+    fn evict() {
+        let fill = |policy| {
+            let db = Db::with_shards(4);
+            for i in 0..100 {
+                let expire_at = if i % 2 == 0 { Some(now_ms() + 10000 + i) } else { None };
+                db.set(Bytes::from(format!("k{}", i)), Bytes::from(vec![b'x'; 100]), expire_at);
+            }
+            db.set_eviction_policy(policy);
+            db
+        };
+
+        // 没有设置 maxmemory 时不淘汰
+        let db = fill(EvictionPolicy::AllKeysRandom);
+        assert!(db.evict().is_ok());
+        assert_eq!(db.keys(b"*").len(), 100);
+
+        let db = fill(EvictionPolicy::NoEviction);
+        db.set_maxmemory(db.used_memory() / 2);
+        assert!(db.evict().is_err());
+        assert_eq!(db.evicted_keys(), 0);
+
+        for policy in [EvictionPolicy::AllKeysRandom, EvictionPolicy::AllKeysLru, EvictionPolicy::AllKeysLfu] {
+            let db = fill(policy);
+            // 访问时间取自粗粒度的时钟，等待两个周期才能保证 k1 比其他 key 新
+            thread::sleep(LRU_CLOCK_RESOLUTION * 2);
+            for _ in 0..100 {
+                db.get(b"k1").unwrap();
+            }
+            db.set_maxmemory(db.used_memory() / 2);
+            assert!(db.evict().is_ok());
+            assert!(db.used_memory() <= db.maxmemory());
+            assert_eq!(db.keys(b"*").len() as u64, 100 - db.evicted_keys());
+            if policy != EvictionPolicy::AllKeysRandom {
+                assert!(db.exists(b"k1"), "{}", policy);
+            }
+        }
+
+        // 只淘汰设置了过期时间的 key，最快过期的先被淘汰
+        let db = fill(EvictionPolicy::VolatileTtl);
+        db.set_maxmemory(db.used_memory() - 1);
+        assert!(db.evict().is_ok());
+        assert_eq!(db.evicted_keys(), 1);
+        db.set_maxmemory(1);
+        assert!(db.evict().is_err());
+        assert_eq!(db.evicted_keys(), 50);
+        assert!((0..100).all(|i| db.exists(format!("k{}", i).as_bytes()) == (i % 2 == 1)));
+    }
+}
@@ -1,11 +1,60 @@
 //! adlist(A generic doubly linked list)，即 redis 自定义的双端链表。由于
 //! 在 rust 中标准库有链表实现，这里准备直接复用。但为了抽象，还是将它定义为 trait
-//! 
-//! 
-//! 
+//!
+//! 对应 redis `adlist.c` 中的 `listAddNodeHead`、`listSearchKey`、`listInsertNode`、`listRotate*` 等操作。
+//! 节点的增删都通过 trait 的方法完成，不对外暴露节点指针，所以接口是安全的。
+//!
 mod stdlib;
 
-pub trait Adlist {
-    
-}
+pub trait Adlist<T> {
+    /// 元素个数
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 在表头插入
+    fn push_front(&mut self, value: T);
+
+    /// 在表尾插入
+    fn push_back(&mut self, value: T);
+
+    fn pop_front(&mut self) -> Option<T>;
+
+    fn pop_back(&mut self) -> Option<T>;
+
+    /// 第 index 个元素
+    fn get(&self, index: usize) -> Option<&T>;
 
+    /// 第一个满足 pred 的元素的位置
+    fn search(&self, pred: impl Fn(&T) -> bool) -> Option<usize>;
+
+    /// 在第 index 个元素之前插入，index 越界时返回 false
+    fn insert_before(&mut self, index: usize, value: T) -> bool;
+
+    /// 在第 index 个元素之后插入，index 越界时返回 false
+    fn insert_after(&mut self, index: usize, value: T) -> bool;
+
+    /// 删除第 index 个元素
+    fn remove(&mut self, index: usize) -> Option<T>;
+
+    /// 把表尾元素移到表头
+    fn rotate_tail_to_head(&mut self) {
+        if let Some(value) = self.pop_back() {
+            self.push_front(value);
+        }
+    }
+
+    /// 把表头元素移到表尾
+    fn rotate_head_to_tail(&mut self) {
+        if let Some(value) = self.pop_front() {
+            self.push_back(value);
+        }
+    }
+
+    /// 从头到尾遍历，也可以用 `rev()` 从尾到头遍历
+    fn iter<'a>(&'a self) -> impl DoubleEndedIterator<Item = &'a T> + ExactSizeIterator
+    where
+        T: 'a;
+}
@@ -1,2 +1,140 @@
-use std::collections::LinkedList;
+//! 基于标准库 `std::collections::LinkedList` 的 Adlist 实现。
+//!
+//! 标准库的链表没有稳定的游标接口，在中间插入、删除时先在该位置拆成两段，操作后再拼回去，
+//! 拆分需要从较近的一端走到该位置，与 redis 按下标查找节点的开销相同。
 
+use std::{collections::LinkedList, mem::size_of};
+
+use crate::ds::MemSize;
+
+use super::Adlist;
+
+/// 每个节点除了元素还有前后两个指针
+impl<T: MemSize> MemSize for LinkedList<T> {
+    fn mem_size(&self) -> usize {
+        let node = size_of::<T>() + 2 * size_of::<usize>();
+        self.iter().map(|value| node + value.mem_size()).sum()
+    }
+}
+
+impl<T> Adlist<T> for LinkedList<T> {
+    fn len(&self) -> usize {
+        LinkedList::len(self)
+    }
+
+    fn push_front(&mut self, value: T) {
+        LinkedList::push_front(self, value)
+    }
+
+    fn push_back(&mut self, value: T) {
+        LinkedList::push_back(self, value)
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        LinkedList::pop_front(self)
+    }
+
+    fn pop_back(&mut self) -> Option<T> {
+        LinkedList::pop_back(self)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        let len = LinkedList::len(self);
+        if index >= len {
+            return None;
+        }
+        // 从较近的一端开始找
+        if index < len / 2 {
+            LinkedList::iter(self).nth(index)
+        } else {
+            LinkedList::iter(self).nth_back(len - 1 - index)
+        }
+    }
+
+    fn search(&self, pred: impl Fn(&T) -> bool) -> Option<usize> {
+        LinkedList::iter(self).position(pred)
+    }
+
+    fn insert_before(&mut self, index: usize, value: T) -> bool {
+        if index >= LinkedList::len(self) {
+            return false;
+        }
+        let mut tail = self.split_off(index);
+        tail.push_front(value);
+        self.append(&mut tail);
+        true
+    }
+
+    fn insert_after(&mut self, index: usize, value: T) -> bool {
+        if index >= LinkedList::len(self) {
+            return false;
+        }
+        let mut tail = self.split_off(index + 1);
+        tail.push_front(value);
+        self.append(&mut tail);
+        true
+    }
+
+    fn remove(&mut self, index: usize) -> Option<T> {
+        if index >= LinkedList::len(self) {
+            return None;
+        }
+        let mut tail = self.split_off(index);
+        let value = tail.pop_front();
+        self.append(&mut tail);
+        value
+    }
+
+    fn iter<'a>(&'a self) -> impl DoubleEndedIterator<Item = &'a T> + ExactSizeIterator
+    where
+        T: 'a,
+    {
+        LinkedList::iter(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::LinkedList;
+
+    use super::Adlist;
+
+    fn items(list: &LinkedList<i32>) -> Vec<i32> {
+        Adlist::iter(list).copied().collect()
+    }
+
+    #[test]
+    fn basis() {
+        let mut list = LinkedList::new();
+        assert!(Adlist::is_empty(&list));
+        Adlist::push_back(&mut list, 2);
+        Adlist::push_front(&mut list, 1);
+        Adlist::push_back(&mut list, 3);
+        assert_eq!(Adlist::len(&list), 3);
+        assert_eq!(items(&list), [1, 2, 3]);
+        assert_eq!(Adlist::iter(&list).rev().copied().collect::<Vec<_>>(), [3, 2, 1]);
+        assert_eq!(Adlist::get(&list, 0), Some(&1));
+        assert_eq!(Adlist::get(&list, 2), Some(&3));
+        assert_eq!(Adlist::get(&list, 3), None);
+        assert_eq!(list.search(|v| *v == 2), Some(1));
+        assert_eq!(list.search(|v| *v == 4), None);
+
+        assert!(list.insert_before(0, 0));
+        assert!(list.insert_after(3, 4));
+        assert!(list.insert_after(1, 10));
+        assert!(!list.insert_before(6, 5));
+        assert!(!list.insert_after(6, 5));
+        assert_eq!(items(&list), [0, 1, 10, 2, 3, 4]);
+
+        assert_eq!(Adlist::remove(&mut list, 2), Some(10));
+        assert_eq!(Adlist::remove(&mut list, 5), None);
+        list.rotate_tail_to_head();
+        assert_eq!(items(&list), [4, 0, 1, 2, 3]);
+        list.rotate_head_to_tail();
+        assert_eq!(items(&list), [0, 1, 2, 3, 4]);
+
+        assert_eq!(Adlist::pop_front(&mut list), Some(0));
+        assert_eq!(Adlist::pop_back(&mut list), Some(4));
+        assert_eq!(Adlist::len(&list), 3);
+    }
+}
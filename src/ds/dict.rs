@@ -1,40 +1,71 @@
 //! 一般的 hash table 性能没问题，但有一个问题是在 rehash 时，会导致所有数据被 copy 并 rehash.
 //! redis 版本的 hash table 用两个常规的 hash table 换着用
-//! redis 的 sds 采用 siphash 方法，这在 std::hash 中有提供，所以直接使用
+//! redis 的 sds 采用 siphash 方法，默认使用带进程级随机密钥的 SipHash-1-3（见 [`super::siphash`]）
 //! 
 
-use std::{hash::{Hash, Hasher, BuildHasher}, collections::hash_map::{RandomState}, borrow::{Borrow}, fmt::Debug};
+use std::{hash::{Hash, BuildHasher}, borrow::{Borrow}, mem::size_of, time::{Duration, Instant}};
 
-use super::perfstr::sds::SDS;
+use rand::Rng;
+
+use super::{MemSize, siphash::SipHashBuilder};
 
 /// redis 版本 hash table，由两个 hash table 交替组成，支持渐进式 rehash（即将单次全部 rehash 这样的耗时逻辑处理成一次请求处理若干个 slot 的渐进方式）。
-pub struct Dict<V, S: BuildHasher = DefaultHasherBuilder> {
-    main_table: HashTable<SDS, V, S>,
-    back_table: Option<HashTable<SDS, V, S>>,
+///
+/// key 可以是任意 `Hash + Eq` 的类型，键空间、集合等使用 [`SDS`](super::perfstr::sds::SDS)
+pub struct Dict<K: Hash, V, S: BuildHasher = DefaultHasherBuilder> {
+    main_table: HashTable<K, V, S>,
+    back_table: Option<HashTable<K, V, S>>,
     /// 正在 rehashing?
     /// rehash 所在的 slot index，这个只针对 main_table
     rehash_idx: Option<usize>,
     hasher_builder: S,
+    resize_policy: ResizePolicy,
+}
+
+/// 是否允许扩容、缩容，对应 redis 的 `dictSetResizeEnabled`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResizePolicy {
+    /// 按负载因子正常扩容、缩容
+    #[default]
+    Enable,
+    /// 尽量避免：负载因子比正常阈值偏离 [`FORCE_RESIZE_RATIO`] 倍时才扩容、缩容。
+    /// redis 在子进程做持久化期间使用，减少写时复制的内存页
+    Avoid,
+    /// 禁止扩容、缩容，已经开始的 rehash 仍会继续
+    Forbid,
+}
+
+/// 负载因子（数据量 / slot 数）低于该百分比时缩容
+const MIN_FILL_PERCENT: u64 = 10;
+/// [`ResizePolicy::Avoid`] 时，负载因子达到该值才扩容，低于正常阈值的该分之一才缩容
+const FORCE_RESIZE_RATIO: u64 = 5;
+
+impl<K: Hash + Eq, V> Default for Dict<K, V, DefaultHasherBuilder> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<V: Default> Dict<V, DefaultHasherBuilder> {
+impl<K: Hash + Eq, V> Dict<K, V, DefaultHasherBuilder> {
     pub fn new() -> Self {
         Self { 
             main_table: HashTable::with_capacity_and_hasher(4, DefaultHasherBuilder::default()), 
             back_table: None, 
             rehash_idx: None,
             hasher_builder: DefaultHasherBuilder::default(),
+            resize_policy: ResizePolicy::default(),
         }
     }
 }
 
-impl <V: Default, S: BuildHasher + Clone> Dict<V, S> {
+impl <K: Hash + Eq, V, S: BuildHasher + Clone> Dict<K, V, S> {
     pub fn new_with_hasher(hasher_builder: S) ->Self {
         Self {
             main_table: HashTable::with_capacity_and_hasher(4, hasher_builder.clone()),
             back_table: None,
             rehash_idx: None,
-            hasher_builder: hasher_builder,
+            hasher_builder,
+            resize_policy: ResizePolicy::default(),
         }
     }
 
@@ -42,57 +73,121 @@ impl <V: Default, S: BuildHasher + Clone> Dict<V, S> {
         self.rehash_idx.is_some()
     }
 
-    fn start_rehashing(&mut self) {
+    pub fn resize_policy(&self) -> ResizePolicy {
+        self.resize_policy
+    }
+
+    /// 设置是否允许扩容、缩容，如持久化遍历期间暂时禁止，结束后再恢复
+    pub fn set_resize_policy(&mut self, policy: ResizePolicy) {
+        self.resize_policy = policy;
+    }
+
+    /// 开始渐进式 rehash，新表的 slot 数为不小于 size 的 2 的幂
+    fn start_rehashing(&mut self, size: u64) {
         if self.is_rehashing() {
             return
         }
-        // 每次扩2倍
-        self.back_table = Some(HashTable::with_capacity_and_hasher(2*self.main_table.slots_cnt(), self.hasher_builder.clone())); 
+        self.back_table = Some(HashTable::with_capacity_and_hasher(size, self.hasher_builder.clone()));
         self.rehash_idx = Some(0);
     }
 
-    /// 渐进 rehash。每步(step)只 rehash 几个 slots。
-    /// 10个空 slot 也算一步
+    /// 需要扩容时开始 rehash，每次扩 2 倍
+    fn try_expand(&mut self) {
+        let slots = self.main_table.slots_cnt();
+        let need_expand = match self.resize_policy {
+            ResizePolicy::Enable => self.main_table.need_expand(),
+            ResizePolicy::Avoid => self.main_table.cnt >= FORCE_RESIZE_RATIO * slots,
+            ResizePolicy::Forbid => false,
+        };
+        if need_expand {
+            self.start_rehashing(2 * slots);
+        }
+    }
+
+    /// 负载因子低于 [`MIN_FILL_PERCENT`] 时开始缩容，缩到能容纳现有数据的最小 slot 数，
+    /// 与扩容一样通过渐进式 rehash 完成。已经在 rehash 或者表已经是最小时什么也不做。
+    ///
+    /// 删除时会自动调用，返回是否开始了缩容
+    pub fn try_shrink(&mut self) -> bool {
+        let slots = self.main_table.slots_cnt();
+        if self.is_rehashing() || slots <= 1 << MIN_EXP {
+            return false;
+        }
+        let fill = self.main_table.cnt * 100;
+        let need_shrink = match self.resize_policy {
+            ResizePolicy::Enable => fill < MIN_FILL_PERCENT * slots,
+            ResizePolicy::Avoid => fill * FORCE_RESIZE_RATIO < MIN_FILL_PERCENT * slots,
+            ResizePolicy::Forbid => false,
+        };
+        if need_shrink {
+            self.start_rehashing(self.main_table.cnt);
+        }
+        need_shrink
+    }
+
+    /// 渐进 rehash。每步(step)迁移一个非空 slot 的链表，最多 step 步。
+    /// 与 redis 的 dictRehash 一样，最多访问 10 * step 个空 slot，避免表很稀疏时单次耗时过长
     fn try_rehash_step(&mut self, mut step: usize) {
-        if !self.is_rehashing() {
+        let Some(mut idx) = self.rehash_idx else {
             return;
-        }
-        let start_idx = self.rehash_idx.unwrap();
-        let mut latest_idx = start_idx;
-        let max_slots_idx_to_check = (10 * step + start_idx).max(self.main_table.slots_cnt() as usize - 1);
-        for idx in start_idx..=max_slots_idx_to_check {
-            latest_idx = idx;
-            let mut cursor = &mut self.main_table.slots[idx];
-            if cursor.is_none() {
+        };
+        let slots = self.main_table.slots.len();
+        let mut empty_visits = 10 * step;
+        while idx < slots && step > 0 && self.main_table.cnt > 0 {
+            // 取下整条链表，该 slot 随之清空
+            let mut chain = self.main_table.slots[idx].take();
+            idx += 1;
+            if chain.is_none() {
                 // 本来就没有
+                empty_visits -= 1;
+                if empty_visits == 0 {
+                    break;
+                }
                 continue
             }
-            loop {
-                match cursor {
-                    None => break,
-                    Some(node) => {
-                        let key = std::mem::replace(&mut node.k, SDS::empty());
-                        let value = std::mem::take(&mut node.v);
-                        self.back_table.as_mut().unwrap().insert(key, value);
-                        self.main_table.cnt -= 1;
-                        cursor = &mut node.next;
-                    },
-                }
+            while let Some(mut node) = chain {
+                chain = node.next.take();
+                let Node { k, v, .. } = *node;
+                self.back_table.as_mut().unwrap().insert(k, v);
+                self.main_table.cnt -= 1;
             }
-            self.main_table.slots[idx] = None; // 清空该 slot
             step -= 1;
-            if step <= 0 || self.main_table.cnt == 0 {
-                break;
-            }
         }
-        if self.main_table.cnt == 0 || latest_idx >= self.main_table.slots_cnt() as usize {
+        if self.main_table.cnt == 0 || idx >= slots {
             // 已经 rehash 完成
+            debug_assert_eq!(self.main_table.cnt, 0);
             self.rehash_idx = None;
             let new_table = self.back_table.take().unwrap();
             self.main_table = new_table;
             return
         }
-        self.rehash_idx = Some(latest_idx);
+        // 下一次从还没迁移的第一个 slot 开始
+        self.rehash_idx = Some(idx);
+    }
+
+    /// 正在 rehash 时返回 (已经迁移的 slot 数, 旧表的 slot 总数)，没有在 rehash 时返回 `None`
+    pub fn rehash_progress(&self) -> Option<(usize, usize)> {
+        self.rehash_idx.map(|idx| (idx, self.main_table.slots.len()))
+    }
+
+    /// 对应 redis 的 dictRehashMilliseconds，每次推进 100 步，直到完成或者用完 budget。
+    /// 用于在后台推进很少被访问的 Dict 的 rehash，返回是否还在 rehash
+    pub fn rehash_for(&mut self, budget: Duration) -> bool {
+        let started = Instant::now();
+        while self.is_rehashing() {
+            self.try_rehash_step(100);
+            if started.elapsed() >= budget {
+                break;
+            }
+        }
+        self.is_rehashing()
+    }
+
+    /// 一次完成正在进行的 rehash，没有在 rehash 时什么也不做
+    pub fn force_finish_rehash(&mut self) {
+        while self.is_rehashing() {
+            self.try_rehash_step(self.main_table.slots.len());
+        }
     }
 
     /// 返回当前表中所有的值数量
@@ -104,7 +199,7 @@ impl <V: Default, S: BuildHasher + Clone> Dict<V, S> {
         }
     }
     /// 新增 kv
-    pub fn insert(&mut self, key: SDS, v: V) -> Option<V> {
+    pub fn insert(&mut self, key: K, v: V) -> Option<V> {
         self.try_rehash_step(1);
         if self.is_rehashing() {
             let old_in_main = self.main_table.remove(&key);
@@ -122,25 +217,52 @@ impl <V: Default, S: BuildHasher + Clone> Dict<V, S> {
             let old = self.main_table.insert(key, v);
             if old.is_none() {
                 // 新增的，且不在 rehashing ，则考虑开启 rehashing
-                if self.main_table.need_expand() {
-                    self.start_rehashing();
-                }
+                self.try_expand();
             }
             old
         }
     }
 
     /// 删除
-    pub fn remove(&mut self, key: &SDS) -> Option<V> {
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         self.try_rehash_step(1);
         let new_val = self.back_table
             .as_mut()
             .and_then(|t| t.remove(key));
-        if new_val.is_some() {
+        let removed = if new_val.is_some() {
             new_val
         } else {
             self.main_table.remove(key)
+        };
+        if removed.is_some() {
+            self.try_shrink();
         }
+        removed
+    }
+
+    /// 两张表的 slot 总数，用于估计占用的内存
+    pub fn slots_cnt(&self) -> u64 {
+        self.main_table.slots_cnt() + self.back_table.as_ref().map_or(0, |table| table.slots_cnt())
+    }
+
+    /// 遍历所有的 kv，顺序不确定。正在 rehash 时会依次遍历两张表
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.main_table
+            .iter()
+            .chain(self.back_table.iter().flat_map(|table| table.iter()))
+    }
+
+    /// 遍历所有的 key，顺序同 [`Dict::iter`]
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// 遍历所有的 value，顺序同 [`Dict::iter`]
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
     }
 
     /// 查找 value
@@ -151,7 +273,10 @@ impl <V: Default, S: BuildHasher + Clone> Dict<V, S> {
     ///     let mut d = Dict::new();
     ///     let _ = d.insert(SDS::new("key".as_bytes()), 1);
     /// ```
-    pub fn get(&mut self, key: &SDS) -> Option<&V> {
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         if self.value_cnt() == 0 {
             return None;
         }
@@ -160,15 +285,103 @@ impl <V: Default, S: BuildHasher + Clone> Dict<V, S> {
             .and_then(|table| table.get(key))
             .or_else(|| self.main_table.get(key))
     }
+
+    /// 查找 value 并返回可变引用
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.value_cnt() == 0 {
+            return None;
+        }
+        self.try_rehash_step(1);
+        let in_back = self.back_table
+            .as_ref()
+            .is_some_and(|table| table.get(key).is_some());
+        if in_back {
+            self.back_table.as_mut().unwrap().get_mut(key)
+        } else {
+            self.main_table.get_mut(key)
+        }
+    }
+
+    /// 随机返回一个 kv，用于 RANDOMKEY、SRANDMEMBER 等。与 redis 的 dictGetRandomKey 一样先推进一步 rehash，
+    /// 再在两张表的所有 slot 中随机选一个非空的 slot，最后在 slot 的链表中随机选一个节点。
+    /// 链表长度不同时各个 kv 被选中的概率并不完全相同
+    pub fn random_entry(&mut self) -> Option<(&K, &V)> {
+        if self.value_cnt() == 0 {
+            return None;
+        }
+        self.try_rehash_step(1);
+        let back = match &self.back_table {
+            Some(back) => back,
+            None => return self.main_table.random_entry(),
+        };
+        // 主表中 rehash_idx 之前的 slot 都已经迁移走了，不用再选
+        let start = self.rehash_idx.unwrap_or(0);
+        let main_slots = self.main_table.slots.len();
+        let mut rng = rand::thread_rng();
+        loop {
+            let idx = rng.gen_range(start..main_slots + back.slots.len());
+            let entry = if idx < main_slots {
+                self.main_table.random_in_slot(idx, &mut rng)
+            } else {
+                back.random_in_slot(idx - main_slots, &mut rng)
+            };
+            if entry.is_some() {
+                return entry;
+            }
+        }
+    }
+
+    /// 从 cursor 开始遍历一小部分 kv，对每个 kv 调用 f，返回下一次遍历的 cursor，返回 0 表示遍历完成。
+    /// 第一次遍历时 cursor 传 0。
+    ///
+    /// 与 redis 的 dictScan 一样，cursor 按 slot 下标的二进制逆序递增（高位先加一），
+    /// 这样在两次调用之间即使表被扩容或者正在 rehash，遍历开始前就存在、且一直没被删除的 kv 都会被访问到，
+    /// 不会遗漏；代价是少数 kv 可能被访问多次。
+    /// - 不在 rehash 时，每次遍历 cursor 指向的一个 slot；
+    /// - rehash 时，小表中的 slot `i` 在大表中扩展为低位与 `i` 相同的若干 slot，每次遍历小表的这个 slot 以及大表中所有扩展出的 slot。
+    pub fn scan(&self, cursor: u64, mut f: impl FnMut(&K, &V)) -> u64 {
+        let (small, large) = match &self.back_table {
+            Some(back) if back.slot_cnt_exp >= self.main_table.slot_cnt_exp => (&self.main_table, back),
+            Some(back) => (back, &self.main_table),
+            None => {
+                let mask = self.main_table.slots_cnt() - 1;
+                self.main_table.scan_slot((cursor & mask) as usize, &mut f);
+                return next_cursor(cursor, mask);
+            },
+        };
+        let small_mask = small.slots_cnt() - 1;
+        let large_mask = large.slots_cnt() - 1;
+        small.scan_slot((cursor & small_mask) as usize, &mut f);
+        let mut cursor = cursor;
+        loop {
+            large.scan_slot((cursor & large_mask) as usize, &mut f);
+            cursor = next_cursor(cursor, large_mask);
+            // 大表中比小表多出的那几位都已遍历一遍（进位到了小表的位上）
+            if cursor & (small_mask ^ large_mask) == 0 {
+                return cursor;
+            }
+        }
+    }
+}
+
+/// cursor 按二进制逆序加一：mask 之外的位置 1 后逆序，加一再逆序回来，相当于从 mask 内的最高位开始进位
+fn next_cursor(cursor: u64, mask: u64) -> u64 {
+    let cursor = (cursor | !mask).reverse_bits();
+    cursor.wrapping_add(1).reverse_bits()
 }
 
 #[cfg(test)]
 mod dict_tests {
     use std::hash::{BuildHasher, Hasher};
 
-    use crate::ds::perfstr::sds::SDS;
+    use bytes::Bytes;
+
+    use crate::ds::{perfstr::{SmartString, sds::SDS}, siphash::SipHashBuilder};
 
-    use super::Dict;
+    use super::{Dict, ResizePolicy};
 
     #[test]
     fn test_basis() {
@@ -181,8 +394,289 @@ mod dict_tests {
     }
 
     #[test]
-    fn test_expand_with_default_hasher() {
+    fn test_grow() {
         let mut dict = Dict::new();
+        for i in 0..1000 {
+            dict.insert(SDS::new(i.to_string().as_bytes()), i);
+        }
+        assert_eq!(dict.value_cnt(), 1000);
+        for i in 0..1000 {
+            assert_eq!(dict.get(&SDS::new(i.to_string().as_bytes())), Some(&i));
+        }
+        // 遍历时每个 kv 恰好出现一次
+        let mut values: Vec<i32> = dict.iter().map(|(_, v)| *v).collect();
+        values.sort();
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_iter_while_rehashing() {
+        // 每次插入后都完整遍历一遍，rehash 进行到一半时两张表中都有数据，也不会遗漏或重复
+        let mut dict = Dict::new();
+        let mut rehashing_seen = false;
+        for i in 0..100 {
+            dict.insert(SDS::new(i.to_string().as_bytes()), i);
+            rehashing_seen |= dict.is_rehashing();
+            let mut values: Vec<i32> = dict.values().copied().collect();
+            values.sort();
+            assert_eq!(values, (0..=i).collect::<Vec<_>>());
+            let mut keys: Vec<i32> = dict.keys().map(|k| k.parse_int().unwrap() as i32).collect();
+            keys.sort();
+            assert_eq!(keys, values);
+        }
+        assert!(rehashing_seen);
+    }
+
+    #[test]
+    fn test_shrink() {
+        let mut dict = Dict::new();
+        for i in 0..1000 {
+            dict.insert(SDS::new(i.to_string().as_bytes()), i);
+        }
+        dict.force_finish_rehash();
+        assert_eq!(dict.slots_cnt(), 1024);
+
+        // 禁止时只删除不缩容
+        dict.set_resize_policy(ResizePolicy::Forbid);
+        for i in 50..1000 {
+            assert_eq!(dict.remove(&SDS::new(i.to_string().as_bytes())), Some(i));
+        }
+        assert!(!dict.is_rehashing());
+        assert_eq!(dict.slots_cnt(), 1024);
+        // Avoid 时负载因子还不够低
+        dict.set_resize_policy(ResizePolicy::Avoid);
+        assert!(!dict.try_shrink());
+
+        dict.set_resize_policy(ResizePolicy::Enable);
+        assert!(dict.try_shrink());
+        dict.force_finish_rehash();
+        assert_eq!(dict.slots_cnt(), 64);
+        for i in 0..50 {
+            assert_eq!(dict.get(&SDS::new(i.to_string().as_bytes())), Some(&i));
+        }
+        // 删除时自动缩容，最小为 4 个 slot
+        for i in 0..50 {
+            dict.remove(&SDS::new(i.to_string().as_bytes()));
+            dict.force_finish_rehash();
+        }
+        assert_eq!(dict.value_cnt(), 0);
+        assert_eq!(dict.slots_cnt(), 4);
+        assert!(!dict.try_shrink());
+    }
+
+    #[test]
+    fn test_rehash_progress() {
+        let mut dict = Dict::new();
+        assert_eq!(dict.rehash_progress(), None);
+        for i in 0..63 {
+            dict.insert(SDS::new(i.to_string().as_bytes()), i);
+        }
+        dict.force_finish_rehash();
+        assert_eq!(dict.slots_cnt(), 64);
+
+        // 插入第 64 个时开始扩容，每次操作推进一步，进度单调增加且不超过旧表的 slot 数
+        dict.insert(SDS::new(b"63"), 63);
+        let mut last = (0, 64);
+        assert_eq!(dict.rehash_progress(), Some(last));
+        while let Some(progress) = dict.rehash_progress() {
+            assert!(progress.0 >= last.0 && progress.0 <= progress.1);
+            assert_eq!(progress.1, 64);
+            last = progress;
+            // 迁移到一半时两张表中的 kv 都能查到
+            for i in 0..=63 {
+                assert_eq!(dict.get(&SDS::new(i.to_string().as_bytes())), Some(&i));
+            }
+        }
+        assert_eq!(dict.slots_cnt(), 128);
+
+        // 很稀疏的表缩容时，每步最多访问 10 个空 slot
+        let mut dict = Dict::new();
+        for i in 0..1024 {
+            dict.insert(i, i);
+        }
+        dict.force_finish_rehash();
+        dict.set_resize_policy(ResizePolicy::Forbid);
+        for i in 1..1024 {
+            dict.remove(&i);
+        }
+        dict.set_resize_policy(ResizePolicy::Enable);
+        let slots = dict.slots_cnt() as usize;
+        assert!(dict.try_shrink());
+        let mut steps = 0;
+        let mut last = 0;
+        while let Some((done, total)) = dict.rehash_progress() {
+            assert!(done - last <= 10);
+            assert_eq!(total, slots);
+            last = done;
+            dict.try_rehash_step(1);
+            steps += 1;
+        }
+        assert!(steps > 1);
+        assert_eq!((dict.slots_cnt(), dict.get(&0)), (4, Some(&0)));
+
+        // 中途强制完成。插入过程中每次也会推进一步，插入到开始扩容为止，免得扩容在插入过程中就已经完成
+        let mut cnt = 1;
+        while dict.rehash_progress().is_none() {
+            dict.insert(cnt, cnt);
+            cnt += 1;
+        }
+        dict.force_finish_rehash();
+        assert_eq!(dict.rehash_progress(), None);
+        assert_eq!(dict.value_cnt(), cnt as u64);
+    }
+
+    #[test]
+    fn test_expand_policy() {
+        let mut dict = Dict::new();
+        dict.set_resize_policy(ResizePolicy::Forbid);
+        for i in 0..100 {
+            dict.insert(SDS::new(i.to_string().as_bytes()), i);
+        }
+        assert!(!dict.is_rehashing());
+        assert_eq!(dict.slots_cnt(), 4);
+
+        // Avoid 时负载因子达到 5 才扩容
+        dict.set_resize_policy(ResizePolicy::Avoid);
+        dict.insert(SDS::new(b"100"), 100);
+        assert!(dict.is_rehashing());
+        dict.force_finish_rehash();
+        assert_eq!(dict.slots_cnt(), 8);
+        for i in 0..=100 {
+            assert_eq!(dict.get(&SDS::new(i.to_string().as_bytes())), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_random_entry() {
+        let mut dict = Dict::new();
+        assert!(dict.random_entry().is_none());
+        // 每插入一个都随机取若干次，覆盖 rehash 进行中的情况，取到的都是已有的 kv
+        let mut seen = [false; 20];
+        for i in 0..20 {
+            dict.insert(SDS::new(i.to_string().as_bytes()), i);
+            for _ in 0..10 {
+                let (k, v) = dict.random_entry().unwrap();
+                assert_eq!(k.val(), v.to_string().as_bytes());
+                assert!(*v <= i);
+                seen[*v] = true;
+            }
+        }
+        // 多取几次，每个 kv 都应当能被取到
+        for _ in 0..2000 {
+            seen[*dict.random_entry().unwrap().1] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+
+        for i in 0..20 {
+            dict.remove(&SDS::new(i.to_string().as_bytes()));
+        }
+        assert!(dict.random_entry().is_none());
+    }
+
+    #[test]
+    fn test_remove_while_rehashing() {
+        // rehash 过程中旧表的 kv 被删光，之后的 rehash 步骤不应越界
+        for n in 1..100 {
+            let mut dict = Dict::new();
+            for i in 0..n {
+                dict.insert(SDS::new(i.to_string().as_bytes()), i);
+            }
+            for i in (0..n).rev() {
+                assert_eq!(dict.remove(&SDS::new(i.to_string().as_bytes())), Some(i));
+            }
+            assert_eq!(dict.value_cnt(), 0);
+            dict.insert(SDS::new(b"k"), 0);
+            assert_eq!(dict.get(&SDS::new(b"k")), Some(&0));
+        }
+    }
+
+    #[test]
+    fn test_value_without_default() {
+        // 值类型不需要实现 Default，rehash 与删除都直接转移所有权
+        struct Value(String);
+        let mut dict = Dict::new();
+        for i in 0..100 {
+            dict.insert(SDS::new(i.to_string().as_bytes()), Value(i.to_string()));
+            if i % 3 == 0 {
+                let removed = dict.remove(&SDS::new((i / 3).to_string().as_bytes()));
+                assert_eq!(removed.map(|v| v.0), Some((i / 3).to_string()));
+            }
+        }
+        dict.force_finish_rehash();
+        assert_eq!(dict.value_cnt(), 66);
+        for (key, value) in dict.iter() {
+            assert_eq!(key.val(), value.0.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_generic_key() {
+        // 不是 SDS 的 key，如 key → 过期时间这样的内部索引
+        let mut dict: Dict<u64, u64> = Dict::new();
+        for i in 0..100 {
+            assert_eq!(dict.insert(i, i * 10), None);
+        }
+        assert_eq!(dict.insert(7, 0), Some(70));
+        assert_eq!(dict.get(&7), Some(&0));
+        assert_eq!(dict.remove(&8), Some(80));
+        assert_eq!(dict.get(&8), None);
+        dict.force_finish_rehash();
+        let mut keys: Vec<u64> = dict.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, (0..100).filter(|&i| i != 8).collect::<Vec<_>>());
+
+        let mut bytes: Dict<Bytes, usize> = Dict::new();
+        bytes.insert(Bytes::from("k"), 1);
+        assert_eq!(bytes.get(&b"k"[..]), Some(&1));
+    }
+
+    /// 从头到尾 scan 一遍，每步之间调用一次 between
+    fn scan_all<V: Copy>(dict: &mut Dict<SDS, V>, mut between: impl FnMut(&mut Dict<SDS, V>)) -> Vec<V> {
+        let mut values = vec![];
+        let mut cursor = 0;
+        loop {
+            cursor = dict.scan(cursor, |_, v| values.push(*v));
+            if cursor == 0 {
+                return values;
+            }
+            between(dict);
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let mut dict = Dict::new();
+        assert!(scan_all(&mut dict, |_| {}).is_empty());
+        for i in 0..100 {
+            dict.insert(SDS::new(i.to_string().as_bytes()), i);
+        }
+        // 不修改时每个 kv 恰好出现一次
+        let mut values = scan_all(&mut dict, |_| {});
+        values.sort();
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+
+        // 遍历过程中不断插入，表会经历扩容与 rehash，原有的 kv 都不会被遗漏
+        let mut next = 100;
+        let mut rehashing_seen = false;
+        let values = scan_all(&mut dict, |dict| {
+            for _ in 0..4 {
+                if next < 1000 {
+                    dict.insert(SDS::new(next.to_string().as_bytes()), next);
+                    next += 1;
+                }
+            }
+            rehashing_seen |= dict.is_rehashing();
+        });
+        assert!(rehashing_seen);
+        for i in 0..100 {
+            assert!(values.contains(&i), "{} missing", i);
+        }
+    }
+
+    #[test]
+    fn test_expand_with_default_hasher() {
+        // 固定密钥，保证下面各个 key 落在哪个 slot 是确定的
+        let mut dict = Dict::new_with_hasher(SipHashBuilder::with_keys(0, 0));
         assert_eq!(dict.main_table.slot_cnt_exp, 2);
         assert_eq!(dict.main_table.slots.len(), 1 << 2);
         assert_eq!(dict.main_table.cnt, 0);
@@ -246,7 +740,7 @@ mod dict_tests {
         }
 
         fn write(&mut self, bytes: &[u8]) {
-            if bytes.len() > 0 {
+            if !bytes.is_empty() {
                 self.first_byte = bytes[0];
             }
         }
@@ -287,6 +781,27 @@ mod dict_tests {
     }
 }
 
+/// 深拷贝：逐个插入到新的 Dict 中。新 Dict 不处于 rehash 状态，slot 数可能与原来不同
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> Clone for Dict<K, V, S> {
+    fn clone(&self) -> Self {
+        let mut dict = Self::new_with_hasher(self.hasher_builder.clone());
+        dict.resize_policy = self.resize_policy;
+        for (key, value) in self.iter() {
+            dict.insert(key.clone(), value.clone());
+        }
+        dict
+    }
+}
+
+/// slot 数组，加上每个节点及其 key、value 的内容
+impl<K: Hash + Eq + MemSize, V: MemSize, S: BuildHasher + Clone> MemSize for Dict<K, V, S> {
+    fn mem_size(&self) -> usize {
+        let node = size_of::<Node<K, V>>();
+        self.slots_cnt() as usize * size_of::<HashEntry<K, V>>()
+            + self.iter().map(|(key, value)| node + key.mem_size() + value.mem_size()).sum::<usize>()
+    }
+}
+
 /// 非 rust 内置的 hash table，用于对齐 redis 实现，自己实现主要是为了支持渐进式 rehash。
 struct HashTable<K: Hash, V, S> 
 where S: BuildHasher {
@@ -310,7 +825,7 @@ struct Node<K, V> {
 
 impl<K: Hash, V> Node<K, V> {
     fn new(k: K, v: V) -> Self {
-        Self { k: k, v: v, next:None }
+        Self { k, v, next:None }
     }
 }
 
@@ -322,17 +837,19 @@ macro_rules! remain {
 
 
 const MIN_EXP: u64 = 2;
-type DefaultHasherBuilder = RandomState;
+/// 默认的 BuildHasher，测试中可以用 `Dict::new_with_hasher` 注入固定密钥的 [`SipHashBuilder`]
+pub type DefaultHasherBuilder = SipHashBuilder;
 
-impl<K, V: Default> HashTable<K, V, DefaultHasherBuilder> 
+impl<K, V> HashTable<K, V, DefaultHasherBuilder> 
 where K: Eq + Hash,
 {
+    #[allow(dead_code)]
     pub fn with_capacity(size: u64) -> Self {
         Self::with_capacity_and_hasher(size, DefaultHasherBuilder::default())
     }
 }
 
-impl<K, V: Default, S> HashTable<K, V, S>
+impl<K, V, S> HashTable<K, V, S>
 where K: Eq + Hash,
 S: BuildHasher,
 {
@@ -352,29 +869,87 @@ S: BuildHasher,
     /// 需要扩展？
     /// 参考 redis 版本，使用最简单的数据量>=slots 数量来判断
     pub fn need_expand(&self) -> bool {
-        return self.cnt >= self.slots_cnt()
+        self.cnt >= self.slots_cnt()
     }
 
+    /// 计算容纳 size 个 slot 所需的最小指数，不小于 MIN_EXP
     fn compute_exp(size: u64) -> u64 {
-        assert!(size <= 63);
-        for i in MIN_EXP..size {
-            if 1u64 << i >= size {
-                return i
-            }
+        let mut exp = MIN_EXP;
+        while exp < 63 && (1u64 << exp) < size {
+            exp += 1;
         }
-        64
+        exp
     }
 
     fn gen_hash<T>(&self, key: T) -> u64
         where T: Hash, 
     {
-        let mut hasher = self.hasher_builder.build_hasher();
-        // let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.hasher_builder.hash_one(key)
     }
 
-    ///
+    /// 按 slot 顺序遍历所有的 kv
+    fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.slots.iter().flat_map(|slot| {
+            let mut cursor = slot.as_deref();
+            std::iter::from_fn(move || {
+                let node = cursor?;
+                cursor = node.next.as_deref();
+                Some((&node.k, &node.v))
+            })
+        })
+    }
+
+    /// 随机选一个非空的 slot，再在其中随机选一个 kv，表为空时返回 `None`
+    fn random_entry(&self) -> Option<(&K, &V)> {
+        if self.cnt == 0 {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        loop {
+            let idx = rng.gen_range(0..self.slots.len());
+            if let Some(entry) = self.random_in_slot(idx, &mut rng) {
+                return Some(entry);
+            }
+        }
+    }
+
+    /// 在 slot 的链表中随机选一个 kv，slot 为空时返回 `None`
+    fn random_in_slot(&self, idx: usize, rng: &mut impl Rng) -> Option<(&K, &V)> {
+        let chain = || std::iter::successors(self.slots[idx].as_deref(), |node| node.next.as_deref());
+        let len = chain().count();
+        if len == 0 {
+            return None;
+        }
+        chain().nth(rng.gen_range(0..len)).map(|node| (&node.k, &node.v))
+    }
+
+    /// 对 slot 中的每个 kv 调用 f
+    fn scan_slot(&self, idx: usize, f: &mut impl FnMut(&K, &V)) {
+        let mut cursor = self.slots[idx].as_deref();
+        while let Some(node) = cursor {
+            f(&node.k, &node.v);
+            cursor = node.next.as_deref();
+        }
+    }
+
+    /// 查找 key 对应的值，返回可变引用
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.gen_hash(key);
+        let slot_idx = remain!(hash, self.slot_cnt_exp);
+        let mut cursor = self.slots[slot_idx].as_deref_mut();
+        while let Some(cur) = cursor {
+            if key.borrow() == cur.k.borrow() {
+                return Some(&mut cur.v)
+            }
+            cursor = cur.next.as_deref_mut();
+        }
+        None
+    }
+
+    /// 查找 key 对应的值
     pub fn get<Q>(&self, key: &Q) -> Option<&V>
     where K: Borrow<Q>,
         Q: Hash + Eq + ?Sized, 
@@ -423,9 +998,6 @@ S: BuildHasher,
     {
         let hash = self.gen_hash(key);
         let slot_idx = remain!(hash, self.slot_cnt_exp);
-        if self.slots[slot_idx].is_none() {
-            return None
-        }
         let mut fast = &mut self.slots[slot_idx];
         loop {
             match fast {
@@ -433,10 +1005,11 @@ S: BuildHasher,
                     return None
                 },
                 Some(node) if node.k.borrow() == key.borrow() => {
-                    let v = std::mem::take(&mut node.v);
+                    // 把节点从链表中摘下，取得其所有权
+                    let mut node = fast.take().unwrap();
                     *fast = node.next.take();
                     self.cnt -= 1;
-                    return Some(v);
+                    return Some(node.v);
                 }, 
                 Some(node) => {
                     fast = &mut node.next;
@@ -473,4 +1046,16 @@ mod test_hashtable {
         assert_eq!(table.remove(&"second".to_string()).unwrap(), 2);
         assert_eq!(table.cnt, 1); 
     }
+
+    #[test]
+    fn compute_exp() {
+        let table = HashTable::<String, i32, _>::with_capacity(1);
+        assert_eq!(table.slot_cnt_exp, MIN_EXP);
+        let table = HashTable::<String, i32, _>::with_capacity(5);
+        assert_eq!(table.slot_cnt_exp, 3);
+        let table = HashTable::<String, i32, _>::with_capacity(64);
+        assert_eq!(table.slot_cnt_exp, 6);
+        let table = HashTable::<String, i32, _>::with_capacity(1000);
+        assert_eq!(table.slot_cnt_exp, 10);
+    }
 }
\ No newline at end of file
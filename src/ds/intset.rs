@@ -0,0 +1,239 @@
+//! intset：有序、不重复的整数集合，对应 redis 的 `intset.c`。
+//!
+//! 所有元素以相同的宽度（2、4、8 字节，小端）连续存放并保持升序，查找时二分。
+//! 加入的整数超出当前宽度时整体升级为更宽的编码，删除元素后也不会降级。
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::MemSize;
+
+/// redis 中 intset 的头部：4 字节的编码与 4 字节的元素个数
+const INTSET_HEADER_SIZE: usize = 8;
+
+/// 元素的宽度，值即字节数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Encoding {
+    Int16 = 2,
+    Int32 = 4,
+    Int64 = 8,
+}
+
+impl Encoding {
+    /// 能容纳 value 的最小宽度
+    fn of(value: i64) -> Encoding {
+        if i16::try_from(value).is_ok() {
+            Encoding::Int16
+        } else if i32::try_from(value).is_ok() {
+            Encoding::Int32
+        } else {
+            Encoding::Int64
+        }
+    }
+
+    fn size(self) -> usize {
+        self as usize
+    }
+
+    fn read(self, buf: &[u8]) -> i64 {
+        match self {
+            Encoding::Int16 => LittleEndian::read_i16(buf) as i64,
+            Encoding::Int32 => LittleEndian::read_i32(buf) as i64,
+            Encoding::Int64 => LittleEndian::read_i64(buf),
+        }
+    }
+
+    fn write(self, buf: &mut [u8], value: i64) {
+        match self {
+            Encoding::Int16 => LittleEndian::write_i16(buf, value as i16),
+            Encoding::Int32 => LittleEndian::write_i32(buf, value as i32),
+            Encoding::Int64 => LittleEndian::write_i64(buf, value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IntSet {
+    encoding: Encoding,
+    contents: Vec<u8>,
+}
+
+impl Default for IntSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IntSet {
+    pub fn new() -> Self {
+        IntSet { encoding: Encoding::Int16, contents: vec![] }
+    }
+
+    /// 元素个数
+    pub fn len(&self) -> usize {
+        self.contents.len() / self.encoding.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contents.is_empty()
+    }
+
+    /// 按 redis 的内存布局计算的字节数，包括头部
+    pub fn blob_len(&self) -> usize {
+        INTSET_HEADER_SIZE + self.contents.len()
+    }
+
+    /// 第 index 个元素（升序）
+    pub fn get(&self, index: usize) -> Option<i64> {
+        (index < self.len()).then(|| self.read(index))
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        // 超出当前宽度的整数一定不在集合中
+        Encoding::of(value) <= self.encoding && self.search(value).is_ok()
+    }
+
+    /// 加入 value，返回是否为新增的元素
+    pub fn insert(&mut self, value: i64) -> bool {
+        if Encoding::of(value) > self.encoding {
+            self.upgrade_and_add(value);
+            return true;
+        }
+        let index = match self.search(value) {
+            Ok(_) => return false,
+            Err(index) => index,
+        };
+        let size = self.encoding.size();
+        let offset = index * size;
+        self.contents.splice(offset..offset, [0; 8][..size].iter().copied());
+        self.encoding.write(&mut self.contents[offset..offset + size], value);
+        true
+    }
+
+    /// 删除 value，返回其是否存在
+    pub fn remove(&mut self, value: i64) -> bool {
+        if Encoding::of(value) > self.encoding {
+            return false;
+        }
+        match self.search(value) {
+            Ok(index) => {
+                let size = self.encoding.size();
+                self.contents.drain(index * size..(index + 1) * size);
+                true
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// 按升序遍历，也可以用 `rev()` 按降序遍历
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = i64> + ExactSizeIterator + '_ {
+        (0..self.len()).map(move |index| self.read(index))
+    }
+
+    fn read(&self, index: usize) -> i64 {
+        let size = self.encoding.size();
+        self.encoding.read(&self.contents[index * size..])
+    }
+
+    /// 二分查找，找到时返回 `Ok(下标)`，否则返回 `Err(应当插入的位置)`
+    fn search(&self, value: i64) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let current = self.read(mid);
+            if current == value {
+                return Ok(mid);
+            } else if current < value {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Err(low)
+    }
+
+    /// 升级到能容纳 value 的宽度，再加入 value。
+    /// 需要升级说明 value 比所有元素都大或者都小，只会加在两端
+    fn upgrade_and_add(&mut self, value: i64) {
+        let encoding = Encoding::of(value);
+        let size = encoding.size();
+        let mut contents = vec![0; (self.len() + 1) * size];
+        let values = self.iter().collect::<Vec<_>>();
+        let (first, rest) = if value < 0 {
+            (0, 1)
+        } else {
+            (values.len(), 0)
+        };
+        encoding.write(&mut contents[first * size..], value);
+        for (index, v) in values.into_iter().enumerate() {
+            encoding.write(&mut contents[(index + rest) * size..], v);
+        }
+        self.encoding = encoding;
+        self.contents = contents;
+    }
+}
+
+/// 按实际分配的空间计算，可能比 [`IntSet::blob_len`] 大
+impl MemSize for IntSet {
+    fn mem_size(&self) -> usize {
+        self.contents.capacity()
+    }
+}
+
+impl FromIterator<i64> for IntSet {
+    fn from_iter<I: IntoIterator<Item = i64>>(iter: I) -> Self {
+        let mut set = IntSet::new();
+        for value in iter {
+            set.insert(value);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, IntSet};
+
+    #[test]
+    fn basis() {
+        let mut set = IntSet::new();
+        assert!(set.is_empty());
+        for value in [5, -3, 100, 7, 5] {
+            set.insert(value);
+        }
+        assert_eq!(set.len(), 4);
+        assert_eq!(set.iter().collect::<Vec<_>>(), [-3, 5, 7, 100]);
+        assert_eq!(set.iter().rev().collect::<Vec<_>>(), [100, 7, 5, -3]);
+        assert!(!set.insert(7));
+        assert!(set.contains(-3));
+        assert!(!set.contains(6));
+        assert!(!set.contains(i64::MAX));
+        assert_eq!(set.get(1), Some(5));
+        assert_eq!(set.get(4), None);
+
+        assert!(set.remove(5));
+        assert!(!set.remove(5));
+        assert!(!set.remove(i64::MIN));
+        assert_eq!(set.iter().collect::<Vec<_>>(), [-3, 7, 100]);
+        assert_eq!(set.blob_len(), 8 + 3 * 2);
+    }
+
+    #[test]
+    fn upgrade() {
+        let mut set: IntSet = [1, 2, 3].into_iter().collect();
+        assert_eq!(set.encoding, Encoding::Int16);
+
+        assert!(set.insert(70000));
+        assert_eq!(set.encoding, Encoding::Int32);
+        assert_eq!(set.iter().collect::<Vec<_>>(), [1, 2, 3, 70000]);
+
+        assert!(set.insert(i64::MIN));
+        assert_eq!(set.encoding, Encoding::Int64);
+        assert_eq!(set.iter().collect::<Vec<_>>(), [i64::MIN, 1, 2, 3, 70000]);
+        assert!(set.contains(70000));
+
+        // 删除后不降级
+        assert!(set.remove(i64::MIN));
+        assert_eq!(set.encoding, Encoding::Int64);
+        assert_eq!(set.blob_len(), 8 + 4 * 8);
+    }
+}
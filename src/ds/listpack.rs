@@ -1,16 +1,433 @@
-/// listpack -- suitable to store lists of string elements in a representation which is 
-/// - space efficient
-/// - can be efficiently accessed from left to right and from right to left.
-/// 
-/// refers to [here](https://github.com/antirez/listpack)
-/// 
+//! listpack -- suitable to store lists of string elements in a representation which is
+//! - space efficient
+//! - can be efficiently accessed from left to right and from right to left.
+//!
+//! refers to [here](https://github.com/antirez/listpack)
+//!
+//! # 格式
+//! ```text
+//! <total-bytes> <num-elements> <element-1> ... <element-N> <end-byte>
+//! ```
+//! - total-bytes：4 字节，整个 listpack 的字节数（小端）；
+//! - num-elements：2 字节，元素个数（小端），达到 65535 时表示需要遍历才能知道；
+//! - end-byte：固定为 `0xFF`。
+//!
+//! 每个元素为 `<encoding-type><element-data><element-tot-len>`。与 ziplist 不同，元素不记录前一个元素的长度，
+//! 而是在末尾记录自身（encoding + data）的长度 backlen，从右往左遍历时先读 backlen 就能找到元素的开头，
+//! 这样修改一个元素不会影响其他元素，也就没有 ziplist 的连锁更新问题。
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::MemSize;
+
+const LP_HEADER_SIZE: usize = 6;
+const LP_NUM_ELEMENTS_OFF: usize = 4;
+/// 元素个数未知
+const LP_NUM_ELEMENTS_UNKNOWN: usize = 0xffff;
+const LP_EOF: u8 = 0xff;
+
+/// `0xxxxxxx`，7 位无符号整数
+const LP_ENCODING_7BIT_UINT: u8 = 0b0000_0000;
+/// `10xxxxxx`，长度不超过 63 的字符串
+const LP_ENCODING_6BIT_STR: u8 = 0b1000_0000;
+/// `110xxxxx yyyyyyyy`，13 位有符号整数
+const LP_ENCODING_13BIT_INT: u8 = 0b1100_0000;
+/// `1110xxxx yyyyyyyy`，长度不超过 4095 的字符串
+const LP_ENCODING_12BIT_STR: u8 = 0b1110_0000;
+/// `11110000` 后跟 4 字节长度的字符串
+const LP_ENCODING_32BIT_STR: u8 = 0xf0;
+const LP_ENCODING_16BIT_INT: u8 = 0xf1;
+const LP_ENCODING_24BIT_INT: u8 = 0xf2;
+const LP_ENCODING_32BIT_INT: u8 = 0xf3;
+const LP_ENCODING_64BIT_INT: u8 = 0xf4;
 
 /// 压缩链表中的节点。
-/// 
+///
 /// Nodes of the listpack.
-/// 
-/// refers to 
-enum ListpackEntry {
+///
+/// refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListpackEntry {
     String(Vec<u8>),
     Integer(i64),
-}
\ No newline at end of file
+}
+
+impl ListpackEntry {
+    /// 与 redis 一样，能无损表示为 i64 的字符串按整数保存
+    fn from_bytes(value: &[u8]) -> Self {
+        std::str::from_utf8(value)
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .filter(|i| i.to_string().as_bytes() == value)
+            .map_or_else(|| ListpackEntry::String(value.to_vec()), ListpackEntry::Integer)
+    }
+
+    /// encoding 与 data 编码后的字节，不含 backlen
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            ListpackEntry::Integer(i) => {
+                let i = *i;
+                if (0..=127).contains(&i) {
+                    vec![LP_ENCODING_7BIT_UINT | i as u8]
+                } else if (-4096..4096).contains(&i) {
+                    let v = (i as u16) & 0x1fff;
+                    vec![LP_ENCODING_13BIT_INT | (v >> 8) as u8, v as u8]
+                } else if i >= i16::MIN as i64 && i <= i16::MAX as i64 {
+                    let mut buf = vec![LP_ENCODING_16BIT_INT, 0, 0];
+                    LittleEndian::write_i16(&mut buf[1..], i as i16);
+                    buf
+                } else if (-(1 << 23)..(1 << 23)).contains(&i) {
+                    let mut buf = vec![LP_ENCODING_24BIT_INT, 0, 0, 0];
+                    LittleEndian::write_i24(&mut buf[1..], i as i32);
+                    buf
+                } else if i >= i32::MIN as i64 && i <= i32::MAX as i64 {
+                    let mut buf = vec![LP_ENCODING_32BIT_INT, 0, 0, 0, 0];
+                    LittleEndian::write_i32(&mut buf[1..], i as i32);
+                    buf
+                } else {
+                    let mut buf = vec![LP_ENCODING_64BIT_INT; 9];
+                    LittleEndian::write_i64(&mut buf[1..], i);
+                    buf
+                }
+            },
+            ListpackEntry::String(s) => {
+                let len = s.len();
+                let mut buf = if len < 64 {
+                    vec![LP_ENCODING_6BIT_STR | len as u8]
+                } else if len < 4096 {
+                    vec![LP_ENCODING_12BIT_STR | (len >> 8) as u8, len as u8]
+                } else {
+                    let mut buf = vec![LP_ENCODING_32BIT_STR, 0, 0, 0, 0];
+                    LittleEndian::write_u32(&mut buf[1..], len as u32);
+                    buf
+                };
+                buf.extend_from_slice(s);
+                buf
+            },
+        }
+    }
+
+    /// 解析 src 开头的元素，返回元素及其 encoding + data 的长度
+    fn decode(src: &[u8]) -> (Self, usize) {
+        let enc = src[0];
+        if enc & 0b1000_0000 == LP_ENCODING_7BIT_UINT {
+            (ListpackEntry::Integer(enc as i64), 1)
+        } else if enc & 0b1100_0000 == LP_ENCODING_6BIT_STR {
+            let len = (enc & 0b0011_1111) as usize;
+            (ListpackEntry::String(src[1..1 + len].to_vec()), 1 + len)
+        } else if enc & 0b1110_0000 == LP_ENCODING_13BIT_INT {
+            let v = ((enc & 0b0001_1111) as u16) << 8 | src[1] as u16;
+            // 左移再算术右移，完成 13 位的符号扩展
+            (ListpackEntry::Integer(((v << 3) as i16 >> 3) as i64), 2)
+        } else if enc & 0b1111_0000 == LP_ENCODING_12BIT_STR {
+            let len = ((enc & 0b0000_1111) as usize) << 8 | src[1] as usize;
+            (ListpackEntry::String(src[2..2 + len].to_vec()), 2 + len)
+        } else {
+            match enc {
+                LP_ENCODING_32BIT_STR => {
+                    let len = LittleEndian::read_u32(&src[1..]) as usize;
+                    (ListpackEntry::String(src[5..5 + len].to_vec()), 5 + len)
+                },
+                LP_ENCODING_16BIT_INT => (ListpackEntry::Integer(LittleEndian::read_i16(&src[1..]) as i64), 3),
+                LP_ENCODING_24BIT_INT => (ListpackEntry::Integer(LittleEndian::read_i24(&src[1..]) as i64), 4),
+                LP_ENCODING_32BIT_INT => (ListpackEntry::Integer(LittleEndian::read_i32(&src[1..]) as i64), 5),
+                LP_ENCODING_64BIT_INT => (ListpackEntry::Integer(LittleEndian::read_i64(&src[1..])), 9),
+                _ => panic!("invalid listpack encoding {:#x}", enc),
+            }
+        }
+    }
+}
+
+/// 编码 backlen：每个字节保存 7 位，从右往左读；除最左边的字节外，最高位都置 1 表示左边还有
+fn encode_backlen(len: usize) -> Vec<u8> {
+    let mut buf = vec![(len & 127) as u8];
+    let mut rest = len >> 7;
+    while rest > 0 {
+        buf[0] |= 128;
+        buf.insert(0, (rest & 127) as u8);
+        rest >>= 7;
+    }
+    buf
+}
+
+/// backlen 占用的字节数
+fn backlen_size(len: usize) -> usize {
+    let mut size = 1;
+    let mut rest = len >> 7;
+    while rest > 0 {
+        size += 1;
+        rest >>= 7;
+    }
+    size
+}
+
+/// 从 end（backlen 最右边字节之后的位置）往左解析 backlen，返回其值及占用的字节数
+fn decode_backlen(src: &[u8], end: usize) -> (usize, usize) {
+    let mut val = 0;
+    let mut size = 0;
+    loop {
+        let b = src[end - size - 1];
+        val |= ((b & 127) as usize) << (7 * size);
+        size += 1;
+        if b & 128 == 0 {
+            return (val, size);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Listpack(Vec<u8>);
+
+/// 按实际分配的空间计算，可能比 [`Listpack::blob_len`] 大
+impl MemSize for Listpack {
+    fn mem_size(&self) -> usize {
+        self.0.capacity()
+    }
+}
+
+impl Default for Listpack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Listpack {
+    pub fn new() -> Self {
+        let mut src = vec![0u8; LP_HEADER_SIZE + 1];
+        LittleEndian::write_u32(&mut src, (LP_HEADER_SIZE + 1) as u32);
+        src[LP_HEADER_SIZE] = LP_EOF;
+        Self(src)
+    }
+
+    /// 整个 listpack 占用的字节数
+    pub fn blob_len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// 元素个数。超过头部能记录的范围时需要遍历
+    pub fn len(&self) -> usize {
+        match LittleEndian::read_u16(&self.0[LP_NUM_ELEMENTS_OFF..]) as usize {
+            LP_NUM_ELEMENTS_UNKNOWN => self.offsets().count(),
+            len => len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.len() == LP_HEADER_SIZE + 1
+    }
+
+    /// 在表尾追加
+    pub fn append(&mut self, value: &[u8]) {
+        self.insert(self.eof_offset(), &ListpackEntry::from_bytes(value));
+    }
+
+    /// 在表头插入
+    pub fn prepend(&mut self, value: &[u8]) {
+        self.insert(LP_HEADER_SIZE, &ListpackEntry::from_bytes(value));
+    }
+
+    /// 第 index 个元素，负数表示从表尾倒数，-1 为最后一个
+    pub fn get(&self, index: isize) -> Option<ListpackEntry> {
+        let offset = self.offset_of(index)?;
+        Some(ListpackEntry::decode(&self.0[offset..]).0)
+    }
+
+    /// 删除并返回第 index 个元素，负数表示从表尾倒数
+    pub fn remove(&mut self, index: isize) -> Option<ListpackEntry> {
+        let offset = self.offset_of(index)?;
+        let (entry, len) = ListpackEntry::decode(&self.0[offset..]);
+        let size = len + backlen_size(len);
+        self.0.drain(offset..offset + size);
+        self.update_header(-1);
+        Some(entry)
+    }
+
+    /// 从头到尾遍历，也可以用 `rev()` 从尾到头遍历
+    pub fn iter(&self) -> ListpackIter<'_> {
+        ListpackIter {
+            listpack: self,
+            front: LP_HEADER_SIZE,
+            back: self.eof_offset(),
+        }
+    }
+
+    /// 表尾 `0xFF` 的偏移
+    fn eof_offset(&self) -> usize {
+        self.0.len() - 1
+    }
+
+    /// 从头到尾各元素的偏移
+    fn offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut iter = self.iter();
+        std::iter::from_fn(move || iter.next_offset())
+    }
+
+    fn offset_of(&self, index: isize) -> Option<usize> {
+        let mut iter = self.iter();
+        if index >= 0 {
+            for _ in 0..index {
+                iter.next_offset()?;
+            }
+            iter.next_offset()
+        } else {
+            for _ in 1..index.unsigned_abs() {
+                iter.next_back_offset()?;
+            }
+            iter.next_back_offset()
+        }
+    }
+
+    fn insert(&mut self, offset: usize, entry: &ListpackEntry) {
+        let mut bytes = entry.encode();
+        bytes.extend(encode_backlen(bytes.len()));
+        self.0.splice(offset..offset, bytes);
+        self.update_header(1);
+    }
+
+    /// 元素增减后更新头部的总字节数与元素个数。与 redis 一样，元素个数一旦未知就不再记录
+    fn update_header(&mut self, delta: isize) {
+        let total = self.0.len();
+        LittleEndian::write_u32(&mut self.0, total as u32);
+        let len = LittleEndian::read_u16(&self.0[LP_NUM_ELEMENTS_OFF..]) as usize;
+        if len != LP_NUM_ELEMENTS_UNKNOWN {
+            let len = len.saturating_add_signed(delta).min(LP_NUM_ELEMENTS_UNKNOWN);
+            LittleEndian::write_u16(&mut self.0[LP_NUM_ELEMENTS_OFF..], len as u16);
+        }
+    }
+}
+
+/// 双向遍历 listpack：从左往右按元素长度前进，从右往左按 backlen 后退
+pub struct ListpackIter<'a> {
+    listpack: &'a Listpack,
+    /// 下一个从头部取出的元素的偏移
+    front: usize,
+    /// 下一个从尾部取出的元素结束的位置
+    back: usize,
+}
+
+impl<'a> ListpackIter<'a> {
+    fn next_offset(&mut self) -> Option<usize> {
+        if self.front >= self.back {
+            return None;
+        }
+        let offset = self.front;
+        let (_, len) = ListpackEntry::decode(&self.listpack.0[offset..]);
+        self.front += len + backlen_size(len);
+        Some(offset)
+    }
+
+    fn next_back_offset(&mut self) -> Option<usize> {
+        if self.front >= self.back {
+            return None;
+        }
+        let (len, size) = decode_backlen(&self.listpack.0, self.back);
+        self.back -= len + size;
+        Some(self.back)
+    }
+}
+
+impl<'a> Iterator for ListpackIter<'a> {
+    type Item = ListpackEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.next_offset()?;
+        Some(ListpackEntry::decode(&self.listpack.0[offset..]).0)
+    }
+}
+
+impl<'a> DoubleEndedIterator for ListpackIter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let offset = self.next_back_offset()?;
+        Some(ListpackEntry::decode(&self.listpack.0[offset..]).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backlen_size, decode_backlen, encode_backlen, Listpack, ListpackEntry};
+
+    #[test]
+    fn encodings() {
+        let ints = [0, 127, 128, -1, 4095, -4096, 4096, i16::MIN as i64, 1 << 20, -(1 << 23), i32::MAX as i64, i64::MIN, i64::MAX];
+        for i in ints {
+            let entry = ListpackEntry::Integer(i);
+            let bytes = entry.encode();
+            assert_eq!(ListpackEntry::decode(&bytes), (entry, bytes.len()));
+        }
+        assert_eq!(ListpackEntry::Integer(5).encode().len(), 1);
+        assert_eq!(ListpackEntry::Integer(-5).encode().len(), 2);
+        assert_eq!(ListpackEntry::Integer(1 << 40).encode().len(), 9);
+
+        for len in [0, 63, 64, 4095, 4096, 70000] {
+            let entry = ListpackEntry::String(vec![b'x'; len]);
+            let bytes = entry.encode();
+            assert_eq!(ListpackEntry::decode(&bytes), (entry, bytes.len()));
+        }
+
+        for len in [0, 127, 128, 16383, 16384, 1 << 21, u32::MAX as usize] {
+            let bytes = encode_backlen(len);
+            assert_eq!(decode_backlen(&bytes, bytes.len()), (len, bytes.len()));
+            assert_eq!(backlen_size(len), bytes.len());
+        }
+        assert_eq!(ListpackEntry::from_bytes(b"-12"), ListpackEntry::Integer(-12));
+        assert_eq!(ListpackEntry::from_bytes(b"012"), ListpackEntry::String(b"012".to_vec()));
+    }
+
+    #[test]
+    fn basis() {
+        let mut lp = Listpack::new();
+        assert!(lp.is_empty());
+        assert_eq!(lp.get(0), None);
+        lp.append(b"b");
+        lp.append(b"100000");
+        lp.prepend(&[b'a'; 200]);
+        lp.append(b"c");
+        assert_eq!(lp.len(), 4);
+        assert_eq!(lp.blob_len(), 6 + (2 + 200 + 2) + (2 + 1) + (4 + 1) + (2 + 1) + 1);
+
+        let all = vec![
+            ListpackEntry::String(vec![b'a'; 200]),
+            ListpackEntry::String(b"b".to_vec()),
+            ListpackEntry::Integer(100000),
+            ListpackEntry::String(b"c".to_vec()),
+        ];
+        assert_eq!(lp.iter().collect::<Vec<_>>(), all);
+        assert_eq!(lp.iter().rev().collect::<Vec<_>>(), all.iter().rev().cloned().collect::<Vec<_>>());
+        assert_eq!(lp.get(2), Some(all[2].clone()));
+        assert_eq!(lp.get(-4), Some(all[0].clone()));
+        assert_eq!(lp.get(4), None);
+        assert_eq!(lp.get(-5), None);
+
+        // 两端交替取，相遇后停止
+        let mut iter = lp.iter();
+        assert_eq!(iter.next_back(), Some(all[3].clone()));
+        assert_eq!(iter.next(), Some(all[0].clone()));
+        assert_eq!(iter.next_back(), Some(all[2].clone()));
+        assert_eq!(iter.next(), Some(all[1].clone()));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        assert_eq!(lp.remove(1), Some(all[1].clone()));
+        assert_eq!(lp.remove(-1), Some(all[3].clone()));
+        assert_eq!(lp.remove(5), None);
+        assert_eq!(lp.iter().collect::<Vec<_>>(), [all[0].clone(), all[2].clone()]);
+        lp.remove(0);
+        lp.remove(0);
+        assert!(lp.is_empty());
+        assert_eq!(lp.len(), 0);
+        assert_eq!(lp.blob_len(), Listpack::new().blob_len());
+    }
+
+    #[test]
+    fn many_elements() {
+        // 超过 65535 个元素后，元素个数需要遍历得到
+        let mut lp = Listpack::new();
+        for i in 0..70000 {
+            lp.append(i.to_string().as_bytes());
+        }
+        assert_eq!(lp.len(), 70000);
+        assert_eq!(lp.get(-1), Some(ListpackEntry::Integer(69999)));
+        lp.remove(0);
+        assert_eq!(lp.len(), 69999);
+    }
+}
@@ -7,4 +7,38 @@ pub mod skiplist;
 /// 压缩链表
 pub mod listpack;
 pub mod ziplist;
-pub mod error;
\ No newline at end of file
+/// 整数集合
+pub mod intset;
+/// 带密钥的 SipHash-1-3
+pub mod siphash;
+pub mod error;
+
+use bytes::Bytes;
+
+/// 数据结构在堆上占用的内存（字节），不包括结构体本身。
+///
+/// 会遍历全部元素，供 `MEMORY USAGE` 使用；maxmemory 需要的估计值见 [`crate::object::RedisObject::mem_usage`]，只抽样少量元素
+pub trait MemSize {
+    fn mem_size(&self) -> usize;
+}
+
+impl MemSize for Bytes {
+    fn mem_size(&self) -> usize {
+        self.len()
+    }
+}
+
+/// 集合的 Dict 没有 value
+impl MemSize for () {
+    fn mem_size(&self) -> usize {
+        0
+    }
+}
+
+/// 有序集合的 Dict 中保存的分数
+impl MemSize for f64 {
+    fn mem_size(&self) -> usize {
+        0
+    }
+}
+
@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 
 /// 系统内的 string 实现，key/value 等使用到的 string 都将用这个 trait 的实现来代替
 /// 为什么不直接使用内置的 String 或者 &str 呢？
@@ -5,10 +6,88 @@
 pub trait SmartString {
     /// 返回字符串长度
     fn len(&self) -> usize;
-    /// 
+    /// 是否为空字符串
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// 在尾部追加数据
     fn append(&mut self, data: &[u8]);
 
     fn val(&self) -> &[u8];
+
+    /// 从 offset 开始用 data 覆盖原有内容，超出原长度的部分会延长字符串，offset 超过长度时中间以 0 填充。
+    /// data 为空时不做任何修改
+    fn set_range(&mut self, offset: usize, data: &[u8]);
+
+    /// 对应 GETRANGE，返回 [start, end] 闭区间的内容。下标可以为负数，表示从尾部倒数，超出范围的部分被截掉
+    fn get_range(&self, start: i64, end: i64) -> &[u8] {
+        match range_of(self.len(), start, end) {
+            Some((start, end)) => &self.val()[start..end],
+            None => &[],
+        }
+    }
+
+    /// 对应 sdsrange，只保留 [start, end] 闭区间的内容，下标规则与 `get_range` 相同
+    fn trim(&mut self, start: i64, end: i64);
+
+    /// 对应 sdscmp，按字节比较，内容相同时较短的字符串较小
+    fn cmp(&self, other: &[u8]) -> Ordering {
+        self.val().cmp(other)
+    }
+
+    /// 是否以 prefix 开头
+    fn starts_with(&self, prefix: &[u8]) -> bool {
+        self.val().starts_with(prefix)
+    }
+
+    /// 在 mid 处分为 `[0, mid)` 与 `[mid, len)` 两部分，mid 超过长度时 panic
+    fn split_at(&self, mid: usize) -> (&[u8], &[u8]) {
+        self.val().split_at(mid)
+    }
+
+    /// 内容按 [`parse_int`] 的规则解析为整数
+    fn parse_int(&self) -> Option<i64> {
+        parse_int(self.val())
+    }
+
+    /// 内容按 [`parse_float`] 的规则解析为浮点数
+    fn parse_float(&self) -> Option<f64> {
+        parse_float(self.val())
+    }
+}
+
+/// 按 redis `string2ll` 的规则解析整数：不允许前导 0、`+` 号和空白，
+/// 保证整数转换回字符串后与原内容完全一致
+pub fn parse_int(value: &[u8]) -> Option<i64> {
+    let digits = value.strip_prefix(b"-").unwrap_or(value);
+    let canonical = match digits {
+        [] => false,
+        [b'0'] => digits.len() == value.len(),
+        [b'1'..=b'9', rest @ ..] => rest.iter().all(u8::is_ascii_digit),
+        _ => false,
+    };
+    if !canonical {
+        return None;
+    }
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+/// 解析浮点数，不接受 NaN
+pub fn parse_float(data: &[u8]) -> Option<f64> {
+    std::str::from_utf8(data)
+        .ok()?
+        .parse::<f64>()
+        .ok()
+        .filter(|n| !n.is_nan())
+}
+
+/// 把可能为负数的闭区间 [start, end] 转换为 `[start, end)` 的下标，区间为空时返回 None
+pub(crate) fn range_of(len: usize, start: i64, end: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { len + end } else { end.min(len - 1) };
+    // end 可能为负数，区间非空时才能转换为下标
+    (start <= end && start < len).then(|| (start as usize, end as usize + 1))
 }
 
 pub mod sds;
\ No newline at end of file
@@ -2,25 +2,93 @@
 //! 由于 redis 本身是用 C 实现的，C原始的 `char*` 是以 '\0' 结尾的简单字符数组，无法方便地实现 O(1) 获取长度、方便地 append 等功能，所以提供了这一版本。
 //! 在本库中，我也将用 rust 实现这一版本。至于不用 rust 内置 string 的原因，在前面已说清楚
 
-use super::SmartString;
+use crate::ds::MemSize;
+
+use super::{SmartString, range_of};
 
 
 /// 最大预分配空间，高于该值就不再二倍方式增长。
-const MAX_PREALLOC: usize = 1024*1024;
+pub const MAX_PREALLOC: usize = 1024*1024;
+
+/// SDS 扩容时预分配空间的策略。
+///
+/// 默认的 [`Greedy`] 与 redis 相同，需要其他策略时实现这个 trait，通过 [`SDS::with_policy`] 创建字符串
+pub trait GrowthPolicy {
+    /// 字符串需要增长到 len 字节时实际分配的字节数，不能小于 len
+    fn alloc_size(&self, len: usize) -> usize;
+}
+
+/// 对应 sdsMakeRoomFor：不超过 [`MAX_PREALLOC`] 时按两倍分配，否则多分配 [`MAX_PREALLOC`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Greedy;
+
+impl GrowthPolicy for Greedy {
+    fn alloc_size(&self, len: usize) -> usize {
+        if 2*len <= MAX_PREALLOC {
+            2*len
+        } else {
+            len + MAX_PREALLOC
+        }
+    }
+}
+
+/// 对应 sdsMakeRoomForNonGreedy：只分配需要的空间，不预分配
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Exact;
+
+impl GrowthPolicy for Exact {
+    fn alloc_size(&self, len: usize) -> usize {
+        len
+    }
+}
+
+/// 不超过这个长度的内容直接存放在结构体内，不在堆上分配
+pub const INLINE_CAP: usize = 22;
+
+/// SDS 的存储：短的内容放在结构体内，超出 [`INLINE_CAP`] 时转移到堆上
+#[derive(Clone)]
+enum Buf {
+    Inline([u8; INLINE_CAP]),
+    Heap(Vec<u8>),
+}
+
+impl Buf {
+    /// 全部可用的空间，包括空闲部分
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Buf::Inline(data) => data,
+            Buf::Heap(data) => data,
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        match self {
+            Buf::Inline(data) => data,
+            Buf::Heap(data) => data,
+        }
+    }
+}
 
 /// SDS(Simple Dynamic String)
 /// 
 /// # Hash
 /// 由于 SipHash 在 rust 中已标记为 deprecated，故暂时使用 default hash 替代(todo check why SipHash is deprecated?)
 /// 
-#[derive(Clone, Eq)]
-pub struct SDS {
+/// # 扩容策略
+/// 由类型参数 P 决定，默认为 [`Greedy`]。策略是零大小的类型时不占用额外的空间
+///
+/// # 短字符串
+/// 不超过 [`INLINE_CAP`] 字节的内容存放在结构体内，典型的短 key 不需要堆分配。
+/// 追加等操作超出这个长度时按扩容策略转移到堆上，[`SDS::shrink_to_fit`] 时如果内容足够短会转移回来
+#[derive(Clone)]
+pub struct SDS<P: GrowthPolicy = Greedy> {
     /// 当前字符串大小
     cur_len: usize,
     /// 已分配的的空间中，空闲的空间字节数
     free: usize,
     /// 真正的字符串数据，没有 '\0' 结尾
-    data: Vec<u8>, 
+    buf: Buf,
+    policy: P,
 }
 
 impl SDS {
@@ -28,19 +96,75 @@ impl SDS {
     /// #Return
     ///     返回一个空的字符串
     pub fn empty() -> Self {
-        Self { cur_len: 0, free: 0, data: vec![], }
+        Self::with_policy(&[], Greedy)
     }
 
     /// 初始化一个 SDS
     pub fn new(init: &[u8]) -> Self {
-        let mut inst = Self::empty();
+        Self::with_policy(init, Greedy)
+    }
+}
+
+impl<P: GrowthPolicy> SDS<P> {
+    /// 使用指定的扩容策略初始化一个 SDS
+    pub fn with_policy(init: &[u8], policy: P) -> Self {
+        let mut inst = Self { cur_len: 0, free: INLINE_CAP, buf: Buf::Inline([0; INLINE_CAP]), policy };
         inst.append(init);
         inst
     }
 
-    /// 清除所有内容。
+    /// 在堆上分配的空间，包括预分配的空闲部分。内容存放在结构体内时为 0
+    pub fn alloc_size(&self) -> usize {
+        match &self.buf {
+            Buf::Inline(_) => 0,
+            Buf::Heap(data) => data.len(),
+        }
+    }
+
+    /// 不需要扩容就能容纳的字节数，即当前长度加上空闲空间
+    pub fn capacity(&self) -> usize {
+        self.buf.as_slice().len()
+    }
+
+    /// 内容是否存放在结构体内
+    pub fn is_inline(&self) -> bool {
+        matches!(self.buf, Buf::Inline(_))
+    }
+
+    /// 空闲空间的字节数
+    pub fn free(&self) -> usize {
+        self.free
+    }
+
+    /// 确保至少有 additional 字节的空闲空间，不够时按扩容策略一次分配。
+    /// 已知要写入的总量时先调用它，避免多次写入各自扩容
+    pub fn reserve(&mut self, additional: usize) {
+        self.expand(additional);
+    }
+
+    /// 对应 sdsRemoveFreeSpace，释放预分配的空闲空间，用于大量删除内容之后回收内存。
+    /// 内容不超过 [`INLINE_CAP`] 时转移回结构体内
+    pub fn shrink_to_fit(&mut self) {
+        let Buf::Heap(data) = &mut self.buf else {
+            return;
+        };
+        if self.cur_len <= INLINE_CAP {
+            let mut inline = [0; INLINE_CAP];
+            inline[..self.cur_len].copy_from_slice(&data[..self.cur_len]);
+            self.buf = Buf::Inline(inline);
+            self.free = INLINE_CAP - self.cur_len;
+            return;
+        }
+        data.truncate(self.cur_len);
+        data.shrink_to_fit();
+        self.free = 0;
+    }
+
+    /// 清除所有内容，扩容策略保持不变。
     pub fn clear(&mut self) {
-        *self = Self::empty();
+        self.cur_len = 0;
+        self.free = INLINE_CAP;
+        self.buf = Buf::Inline([0; INLINE_CAP]);
     }
 
     fn expand(&mut self, required_len: usize) {
@@ -48,119 +172,290 @@ impl SDS {
             // 已经够了
             return;
         }
-        let mut new_size = required_len + self.cur_len;
-        if 2*new_size <= MAX_PREALLOC {
-            new_size *= 2;
-        } else {
-            new_size += MAX_PREALLOC;
-        }
-        // let mut new_data = Vec::with_capacity(new_size);
+        // 结构体内的空间不够，新的空间一定在堆上
+        let new_size = self.policy.alloc_size(required_len + self.cur_len);
+        debug_assert!(new_size >= required_len + self.cur_len);
         let mut new_data = vec![0u8; new_size];
-        new_data[..self.cur_len].clone_from_slice(&self.data[..self.cur_len]);
+        new_data[..self.cur_len].clone_from_slice(&self.buf.as_slice()[..self.cur_len]);
         self.free = new_size - self.cur_len;
-        self.data = new_data;
+        self.buf = Buf::Heap(new_data);
+    }
+}
+
+impl<P: GrowthPolicy> MemSize for SDS<P> {
+    fn mem_size(&self) -> usize {
+        self.alloc_size()
     }
 }
 
-impl SmartString for SDS {
+impl<P: GrowthPolicy> SmartString for SDS<P> {
     fn len(&self) -> usize {
         self.cur_len
     }
 
     fn append(&mut self, data: &[u8]) {
         self.expand(data.len());
-        self.data[self.cur_len..self.cur_len+data.len()].copy_from_slice(data);
+        self.buf.as_mut_slice()[self.cur_len..self.cur_len+data.len()].copy_from_slice(data);
         self.cur_len += data.len();
         self.free -= data.len();
     }
 
     fn val(&self) -> &[u8] {
-        &self.data[..self.cur_len]
+        &self.buf.as_slice()[..self.cur_len]
+    }
+
+    fn set_range(&mut self, offset: usize, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let new_len = offset + data.len();
+        if new_len > self.cur_len {
+            self.expand(new_len - self.cur_len);
+            // 空闲空间中可能残留着 trim 之前的内容，需要清零
+            if offset > self.cur_len {
+                self.buf.as_mut_slice()[self.cur_len..offset].fill(0);
+            }
+            self.free -= new_len - self.cur_len;
+            self.cur_len = new_len;
+        }
+        self.buf.as_mut_slice()[offset..new_len].copy_from_slice(data);
+    }
+
+    fn trim(&mut self, start: i64, end: i64) {
+        // 与 sdsrange 一样只移动数据，不释放空间
+        let (start, end) = range_of(self.cur_len, start, end).unwrap_or((0, 0));
+        self.buf.as_mut_slice().copy_within(start..end, 0);
+        self.cur_len = end - start;
+        self.free = self.capacity() - self.cur_len;
     }
 }
 
-impl PartialEq for SDS {
+impl<P: GrowthPolicy> PartialEq for SDS<P> {
     fn eq(&self, other: &Self) -> bool {
         self.val() == other.val()
     }
 }
 
-impl std::hash::Hash for SDS {
+impl<P: GrowthPolicy> Eq for SDS<P> {}
+
+impl<P: GrowthPolicy> std::hash::Hash for SDS<P> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        let cur_data = &self.data[..self.cur_len];
-        cur_data.hash(state);
+        self.val().hash(state);
+    }
+}
+
+/// 与 `[u8]` 的 `Hash`、`Eq` 结果一致，所以 Dict 可以直接用字节切片查找，不必先构造 SDS
+impl<P: GrowthPolicy> std::borrow::Borrow<[u8]> for SDS<P> {
+    fn borrow(&self) -> &[u8] {
+        self.val()
     }
 }
 
 
 #[cfg(test)]
 pub mod test {
+    use std::cmp::Ordering;
+
     use crate::ds::perfstr::SmartString;
 
-    use super::SDS;
+    use crate::ds::MemSize;
+
+    use super::{Exact, INLINE_CAP, SDS};
     use super::MAX_PREALLOC;
 
     #[test]
+    #[allow(unused_assignments)]
     fn basis() {
         let mut sds = SDS::empty();
         assert_eq!(sds.len(), 0);
-        assert_eq!(sds.free, 0);
-        assert_eq!(sds.data.len(), 0);
+        assert_eq!(sds.free, INLINE_CAP);
+        assert_eq!(sds.alloc_size(), 0);
 
+        // 短的内容不在堆上分配
         let piece = "little string".as_bytes();
         let mut last_len = 0;
         let mut last_cap = 0;
         sds.append(piece);
         assert_eq!(sds.len(), piece.len());
-        assert_eq!(sds.data.len(), 2*piece.len());
-        assert_eq!(sds.free, sds.data.len() - sds.len());
+        assert!(sds.is_inline());
+        assert_eq!(sds.alloc_size(), 0);
+        assert_eq!(sds.free, INLINE_CAP - sds.len());
 
         assert_eq!(sds.val(), piece);
 
         last_len = sds.len();
-        last_cap = sds.data.len();
 
         let append = " again".as_bytes();
         sds.append(append);
         assert_eq!(sds.len(), last_len+append.len());
         assert_eq!(sds.val(), [piece, append].concat());
-        assert_eq!(sds.data.len(), last_cap);
-        assert_eq!(sds.free, sds.data.len() - sds.len());
+        assert!(sds.is_inline());
+        assert_eq!(sds.free, sds.capacity() - sds.len());
 
         last_len = sds.len();
-        last_cap = sds.data.len();
 
+        // 超出结构体内的空间，按扩容策略转移到堆上
         sds.append("1234567890".as_bytes());
         assert_eq!(sds.len(), last_len+10);
-        assert_eq!(sds.data.len(), 2*(last_len+10));
-        assert_eq!(sds.free, sds.data.len() - sds.len());
+        assert!(!sds.is_inline());
+        assert_eq!(sds.val(), [piece, append, b"1234567890"].concat());
+        assert_eq!(sds.alloc_size(), 2*(last_len+10));
+        assert_eq!(sds.free, sds.alloc_size() - sds.len());
 
         last_len = sds.len();
-        last_cap = sds.data.len();
 
         sds.append(&vec![1u8; MAX_PREALLOC]);
         assert_eq!(sds.len(), last_len+MAX_PREALLOC);
-        assert_eq!(sds.data.len(), sds.len() + MAX_PREALLOC);
-        assert_eq!(sds.free, sds.data.len() - sds.len());
+        assert_eq!(sds.alloc_size(), sds.len() + MAX_PREALLOC);
+        assert_eq!(sds.free, sds.alloc_size() - sds.len());
         
         last_len = sds.len();
-        last_cap = sds.data.len();
         sds.append(&vec![2u8; MAX_PREALLOC]);
         assert_eq!(sds.len(), last_len+MAX_PREALLOC);
-        assert_eq!(sds.data.len(), sds.len());
-        assert_eq!(sds.free, sds.data.len() - sds.len());
+        assert_eq!(sds.alloc_size(), sds.len());
+        assert_eq!(sds.free, sds.alloc_size() - sds.len());
 
         last_len = sds.len();
-        last_cap = sds.data.len();
+        last_cap = sds.alloc_size();
         println!("last len: {}, last_cap: {}", last_len, last_cap);
-        sds.append(&vec![1]);
+        sds.append(&[1]);
         assert_eq!(sds.len(), last_len + 1);
-        assert_eq!(sds.data.len(), last_cap+1+MAX_PREALLOC);
+        assert_eq!(sds.alloc_size(), last_cap+1+MAX_PREALLOC);
 
         sds.clear();
         assert_eq!(sds.len(), 0);
+        assert_eq!(sds.free, INLINE_CAP);
+        assert_eq!(sds.alloc_size(), 0); 
+
+    }
+
+    #[test]
+    fn inline() {
+        let mut sds = SDS::new(&[b'a'; INLINE_CAP]);
+        assert!(sds.is_inline());
+        assert_eq!((sds.alloc_size(), sds.free()), (0, 0));
+        assert_eq!(sds.mem_size(), 0);
+
+        sds.append(b"b");
+        assert!(!sds.is_inline());
+        assert_eq!(&sds.val()[INLINE_CAP-1..], b"ab");
+        assert_eq!(sds.mem_size(), sds.alloc_size());
+
+        // 删掉内容之后收缩，足够短时回到结构体内
+        sds.trim(0, -3);
+        assert_eq!(sds.len(), INLINE_CAP - 1);
+        assert!(!sds.is_inline());
+        sds.shrink_to_fit();
+        assert!(sds.is_inline());
+        assert_eq!(sds.val(), &[b'a'; INLINE_CAP - 1]);
+        assert_eq!((sds.alloc_size(), sds.free()), (0, 1));
+        assert!(sds == SDS::new(&[b'a'; INLINE_CAP - 1]));
+
+        // 在结构体内修改时空闲部分同样需要清零
+        sds.trim(0, 1);
+        sds.set_range(4, b"x");
+        assert_eq!(sds.val(), b"aa\0\0x");
+        assert!(sds.is_inline());
+        sds.set_range(INLINE_CAP, b"y");
+        assert!(!sds.is_inline());
+        assert_eq!(sds.len(), INLINE_CAP + 1);
+        assert_eq!(&sds.val()[..5], b"aa\0\0x");
+        assert!(sds.val()[5..INLINE_CAP].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn range() {
+        let mut sds = SDS::new(b"Hello World");
+        assert_eq!(sds.get_range(0, 4), b"Hello");
+        assert_eq!(sds.get_range(-5, -1), b"World");
+        assert_eq!(sds.get_range(-100, 100), b"Hello World");
+        assert_eq!(sds.get_range(5, 3), b"");
+        assert_eq!(sds.get_range(20, 30), b"");
+        assert_eq!(sds.get_range(0, -100), b"");
+
+        sds.set_range(6, b"Redis");
+        assert_eq!(sds.val(), b"Hello Redis");
+        sds.set_range(10, b"!!");
+        assert_eq!(sds.val(), b"Hello Redi!!");
+        sds.set_range(20, b"");
+        assert_eq!(sds.len(), 12);
+
+        sds.trim(1, -2);
+        assert_eq!(sds.val(), b"ello Redi!");
+        assert_eq!(sds.free, sds.capacity() - sds.len());
+        // trim 之后空闲空间中残留的内容不能出现在补齐的部分
+        sds.set_range(12, b"x");
+        assert_eq!(sds.val(), b"ello Redi!\0\0x");
+        sds.trim(5, 1);
+        assert!(sds.is_empty());
+
+        // 负数下标换算后 end 为 -1 的空区间
+        assert_eq!(sds.get_range(0, -1), b"");
+        sds.trim(0, -1);
+        assert!(sds.is_empty());
+        let mut sds = SDS::new(b"abc");
+        assert_eq!(sds.get_range(0, -4), b"");
+        assert_eq!(sds.get_range(-10, -4), b"");
+        sds.trim(0, -4);
+        assert!(sds.is_empty());
+
+        let mut sds = SDS::empty();
+        sds.set_range(30, b"abc");
+        assert_eq!(&sds.val()[28..], b"\0\0abc");
+        assert!(sds.alloc_size() > sds.len());
+        sds.shrink_to_fit();
+        assert_eq!(sds.alloc_size(), sds.len());
         assert_eq!(sds.free, 0);
-        assert_eq!(sds.data.len(), 0); 
+        sds.append(b"d");
+        assert_eq!(&sds.val()[28..], b"\0\0abcd");
+    }
+
+    #[test]
+    fn helpers() {
+        let sds = SDS::new(b"ab\0c");
+        assert_eq!(sds.cmp(b"ab\0c"), Ordering::Equal);
+        assert_eq!(sds.cmp(b"ab"), Ordering::Greater);
+        assert_eq!(sds.cmp(b"ab\0d"), Ordering::Less);
+        assert_eq!(sds.cmp(b"ab\xff"), Ordering::Less);
+        assert!(sds.starts_with(b"ab\0"));
+        assert!(!sds.starts_with(b"ab\0cd"));
+        assert_eq!(sds.split_at(2), (&b"ab"[..], &b"\0c"[..]));
+        assert_eq!(sds.split_at(4), (&b"ab\0c"[..], &b""[..]));
+
+        assert_eq!(SDS::new(b"-123").parse_int(), Some(-123));
+        for invalid in [&b""[..], b"+1", b"01", b"-0", b" 1", b"1a", b"99999999999999999999"] {
+            assert_eq!(SDS::new(invalid).parse_int(), None);
+        }
+        assert_eq!(SDS::new(b"1.5e3").parse_float(), Some(1500.0));
+        assert_eq!(SDS::new(b"-inf").parse_float(), Some(f64::NEG_INFINITY));
+        assert_eq!(SDS::new(b"nan").parse_float(), None);
+        assert_eq!(SDS::new(b"\xff").parse_float(), None);
+    }
 
+    #[test]
+    fn reserve_and_policy() {
+        let mut sds = SDS::new(b"abc");
+        sds.reserve(100);
+        assert!(sds.free() >= 100);
+        let cap = sds.alloc_size();
+        sds.append(&[b'x'; 100]);
+        assert_eq!(sds.alloc_size(), cap);
+        sds.reserve(0);
+        assert_eq!(sds.alloc_size(), cap);
+        sds.shrink_to_fit();
+        assert_eq!((sds.alloc_size(), sds.free()), (103, 0));
+
+        // 不预分配时每次扩容都正好满足需要
+        let mut exact = SDS::with_policy(&[b'a'; INLINE_CAP], Exact);
+        assert_eq!(exact.alloc_size(), 0);
+        exact.append(b"b");
+        assert_eq!((exact.alloc_size(), exact.free()), (INLINE_CAP + 1, 0));
+        exact.set_range(INLINE_CAP + 3, b"f");
+        assert_eq!(&exact.val()[INLINE_CAP..], b"b\0\0f");
+        assert_eq!(exact.alloc_size(), INLINE_CAP + 4);
+        exact.clear();
+        exact.append(b"g");
+        assert_eq!(exact.alloc_size(), 0);
+        assert!(exact == SDS::with_policy(b"g", Exact));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,183 @@
+//! SipHash-1-3，对应 redis 的 `siphash.c`。
+//!
+//! Dict 的 key 可能由客户端任意构造，如果哈希函数固定，攻击者可以构造大量落在同一 slot 的 key，
+//! 让查找退化为遍历链表（HashDoS）。与 redis 一样使用带密钥的 SipHash：密钥在进程启动后随机生成，
+//! 也可以为单个 Dict 单独生成，或者在测试中指定固定的密钥以得到确定的结果。
+//!
+//! 每个 8 字节的分组只做 1 轮压缩，结束时做 3 轮，比 SipHash-2-4 更快，对哈希表的场景已经足够。
+
+use std::{hash::{BuildHasher, Hasher}, sync::OnceLock};
+
+/// 生成 [`SipHasher13`] 的 `BuildHasher`，保存 128 位的密钥
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SipHashBuilder {
+    k0: u64,
+    k1: u64,
+}
+
+impl Default for SipHashBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SipHashBuilder {
+    /// 使用进程级的随机密钥，同一进程中的所有 Dict 相同
+    pub fn new() -> Self {
+        static PROCESS_KEYS: OnceLock<(u64, u64)> = OnceLock::new();
+        let (k0, k1) = *PROCESS_KEYS.get_or_init(rand::random);
+        Self { k0, k1 }
+    }
+
+    /// 单独生成随机密钥
+    pub fn random() -> Self {
+        Self::with_keys(rand::random(), rand::random())
+    }
+
+    /// 指定密钥，用于测试等需要确定结果的场景
+    pub fn with_keys(k0: u64, k1: u64) -> Self {
+        Self { k0, k1 }
+    }
+}
+
+impl BuildHasher for SipHashBuilder {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
+/// SipHash-1-3 的流式实现，可以多次 `write`，结果与一次性写入所有数据相同
+#[derive(Debug, Clone)]
+pub struct SipHasher13 {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    /// 还不满 8 字节的数据，按小端放在低位
+    tail: u64,
+    ntail: usize,
+    /// 已写入的总字节数
+    length: usize,
+}
+
+impl SipHasher13 {
+    pub fn new_with_keys(k0: u64, k1: u64) -> Self {
+        Self {
+            v0: k0 ^ 0x736f6d6570736575,
+            v1: k1 ^ 0x646f72616e646f6d,
+            v2: k0 ^ 0x6c7967656e657261,
+            v3: k1 ^ 0x7465646279746573,
+            tail: 0,
+            ntail: 0,
+            length: 0,
+        }
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13) ^ self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16) ^ self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21) ^ self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17) ^ self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    /// 压缩一个 8 字节的分组
+    fn compress(&mut self, m: u64) {
+        self.v3 ^= m;
+        self.round();
+        self.v0 ^= m;
+    }
+}
+
+impl Hasher for SipHasher13 {
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.length += bytes.len();
+        // 先把上次剩下的不满 8 字节的数据补齐
+        if self.ntail > 0 {
+            let fill = (8 - self.ntail).min(bytes.len());
+            for (i, &b) in bytes[..fill].iter().enumerate() {
+                self.tail |= (b as u64) << (8 * (self.ntail + i));
+            }
+            self.ntail += fill;
+            bytes = &bytes[fill..];
+            if self.ntail < 8 {
+                return;
+            }
+            self.compress(self.tail);
+            self.tail = 0;
+            self.ntail = 0;
+        }
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            self.compress(u64::from_le_bytes(chunk.try_into().unwrap()));
+        }
+        for (i, &b) in chunks.remainder().iter().enumerate() {
+            self.tail |= (b as u64) << (8 * i);
+        }
+        self.ntail = chunks.remainder().len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = self.clone();
+        let b = ((self.length as u64 & 0xff) << 56) | self.tail;
+        state.compress(b);
+        state.v2 ^= 0xff;
+        for _ in 0..3 {
+            state.round();
+        }
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{BuildHasher, Hasher};
+
+    use super::{SipHashBuilder, SipHasher13};
+
+    fn hash(k0: u64, k1: u64, data: &[u8]) -> u64 {
+        let mut hasher = SipHasher13::new_with_keys(k0, k1);
+        hasher.write(data);
+        hasher.finish()
+    }
+
+    #[test]
+    fn known_values() {
+        // 密钥为 0 时与 std 的 `DefaultHasher::new()`（同为 SipHash-1-3）直接 write 的结果一致
+        let data: Vec<u8> = (0..16).collect();
+        assert_eq!(hash(0, 0, &data[..0]), 0xd1fba762150c532c);
+        assert_eq!(hash(0, 0, &data[..7]), 0x2f098ab0c751325a);
+        assert_eq!(hash(0, 0, &data[..8]), 0xead411e67ebe2eea);
+        assert_eq!(hash(0, 0, &data[..15]), 0xf30eb725bb91c9ea);
+    }
+
+    #[test]
+    fn streaming() {
+        let data: Vec<u8> = (0..100).collect();
+        let expected = hash(1, 2, &data);
+        // 任意分段写入的结果都与一次写入相同
+        for split in [1, 3, 7, 8, 9, 16, 50] {
+            let mut hasher = SipHasher13::new_with_keys(1, 2);
+            for chunk in data.chunks(split) {
+                hasher.write(chunk);
+            }
+            assert_eq!(hasher.finish(), expected, "{}", split);
+        }
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(SipHashBuilder::new(), SipHashBuilder::new());
+        assert_ne!(SipHashBuilder::random(), SipHashBuilder::random());
+        let builder = SipHashBuilder::with_keys(1, 2);
+        assert_eq!(builder.hash_one(b"key"), builder.hash_one(b"key"));
+        assert_ne!(builder.hash_one(b"key"), SipHashBuilder::with_keys(2, 1).hash_one(b"key"));
+    }
+}
@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod skiplist;
 
 pub use skiplist::*;
\ No newline at end of file
@@ -1,13 +1,15 @@
 use rand::Rng;
 use core::cmp::Ordering;
-use std::fmt::Debug;
+use std::{fmt::Debug, mem::size_of};
+
+use crate::ds::MemSize;
 
 #[derive(Debug)]
 pub struct Skiplist<Member: PartialEq> {
     // /// 指向 level-0 的头部
     // head: *mut Node<Member>,
-    // /// 指向 level-0 的尾部
-    // tail: *mut Node<Member>,
+    /// 指向 level-0 的尾部，即分数最大的节点
+    tail: *mut Node<Member>,
     /// 各层的链表头
     level_links: Vec<*mut Node<Member>>,
     /// 各层距离下一个节点的距离（中间的节点数）。这是为了提高查找效率
@@ -64,6 +66,10 @@ impl<T: PartialEq + PartialOrd> PartialOrd for Node<T> {
     }
 }
 
+// 所有节点都由 Skiplist 独占，裸指针不会泄露到外部，因此可以跨线程转移和共享
+unsafe impl<M: PartialEq + Send> Send for Skiplist<M> {}
+unsafe impl<M: PartialEq + Sync> Sync for Skiplist<M> {}
+
 impl<M: PartialEq> Drop for Skiplist<M> {
     fn drop(&mut self) {
         if self.length == 0 {
@@ -71,7 +77,7 @@ impl<M: PartialEq> Drop for Skiplist<M> {
         }
         let mut next = self.level_links[0];
         while !next.is_null() {
-            let tail = unsafe {(*next).levels[0]};
+            let tail = unsafe {(&(*next).levels)[0]};
             unsafe {
                 (*next).backward = std::ptr::null_mut();
                 let _ = Box::from_raw(next);
@@ -84,6 +90,7 @@ impl<M: PartialEq> Drop for Skiplist<M> {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct RangeItem<T> {
     /// 分数
     pub score: f64,
@@ -92,6 +99,7 @@ pub struct RangeItem<T> {
     skiplevel: usize,
 }
 
+#[allow(dead_code)]
 impl<T> RangeItem<T> {
     fn new(score: f64, data: T, skiplevel: usize) -> Self {
         Self { score, data, skiplevel }
@@ -100,6 +108,7 @@ impl<T> RangeItem<T> {
 
 
 /// 边界
+#[derive(Debug, Clone, Copy)]
 pub struct Bound {
     /// 边界分数
     bound: f64,
@@ -120,18 +129,83 @@ impl Bound {
         Self { bound, exclusive: false }
     }
 
+    /// 作为下界时，score 是否在范围内
+    pub fn check_min(&self, score: f64) -> bool {
+        score > self.bound || (score == self.bound && !self.exclusive)
+    }
+
+    /// 作为上界时，score 是否在范围内
+    pub fn check_max(&self, score: f64) -> bool {
+        score < self.bound || (score == self.bound && !self.exclusive)
+    }
+
     fn toggle(&self) -> Self {
         Self { exclusive: !self.exclusive, ..(*self) }
     }
 }
 
+/// 按 member 字典序比较的边界，用于 `zrangebylex`。
+/// 只有所有节点的分数都相同时，节点才按 member 排序，此时结果才有意义
+#[derive(Debug, Clone)]
+pub struct LexBound<T> {
+    /// 边界 member
+    bound: T,
+    /// 是否排除边界
+    exclusive: bool,
+}
+
+impl<T: Ord> LexBound<T> {
+    pub fn new(bound: T, exclusive: bool) -> Self {
+        Self { bound, exclusive }
+    }
+
+    pub fn new_exclusive(bound: T) -> Self {
+        Self { bound, exclusive: true }
+    }
+
+    pub fn new_inclusive(bound: T) -> Self {
+        Self { bound, exclusive: false }
+    }
+
+    /// 作为下界时，data 是否在范围内
+    pub fn check_min(&self, data: &T) -> bool {
+        *data > self.bound || (*data == self.bound && !self.exclusive)
+    }
+
+    /// 作为上界时，data 是否在范围内
+    pub fn check_max(&self, data: &T) -> bool {
+        *data < self.bound || (*data == self.bound && !self.exclusive)
+    }
+}
+
+impl<Member: Ord> Default for Skiplist<Member> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 深拷贝：按顺序逐个复制节点，每个节点保持原来的层数，得到结构完全相同的跳表
+impl<Member: Ord + Clone> Clone for Skiplist<Member> {
+    fn clone(&self) -> Self {
+        let mut list = Self::new();
+        list.skip_percentage = self.skip_percentage;
+        if self.is_empty() {
+            return list;
+        }
+        for item in self.range_by_rank(0, self.length - 1) {
+            list.do_insert(item.data.clone(), item.score, item.skiplevel);
+        }
+        list
+    }
+}
+
 impl<Member> Skiplist<Member>
 where Member: Ord 
 {
     pub fn new() -> Self {
         Self { 
             // head: std::ptr::null_mut(), 
-            // tail: std::ptr::null_mut(), 
+            tail: std::ptr::null_mut(),
             level_links: vec![],
             level: 0, 
             length: 0,
@@ -150,12 +224,27 @@ where Member: Ord
         }
     }
 
+    /// 节点数
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
     pub fn insert(&mut self, data: Member, score: f64) {
         let level = self.random_level();
         self.do_insert(data, score, level);
     }
 
     fn do_insert(&mut self, data: Member, score: f64, level: usize) -> Option<*mut Node<Member>> {
+        if self.length == 0 {
+            // 节点全部删除后可能残留空的层，重新开始
+            self.level_links.clear();
+            self.level_spans.clear();
+            self.level = 0;
+        }
         // empty skiplist, insert node directly
         let new_node  = Box::new(Node::new(data, score, level));
         // 消费掉 Box 外壳，并返回内部数据指针。这是 rust 主动分配堆数据的经典操作
@@ -172,6 +261,7 @@ where Member: Ord
             // 原来为空，直接加上
             self.length += 1;
             self.level = level;
+            self.tail = new_node;
             return Some(new_node);
         }
         // 指向上一个，空表示在 skiplist 起点
@@ -181,7 +271,7 @@ where Member: Ord
                 self.level_links[level_cursor]
             } else {
                 unsafe {
-                    (*slow).levels[level_cursor]
+                    (&(*slow).levels)[level_cursor]
                 }
             };
             while !next.is_null() {
@@ -195,13 +285,13 @@ where Member: Ord
                     Ordering::Less => {
                         // 就在当前区间
                         unsafe {
-                            (*new_node).levels[level_cursor] = next;
+                            (&mut (*new_node).levels)[level_cursor] = next;
                         }
                         if slow.is_null() {
                             self.level_links[level_cursor] = new_node;
                         } else {
                             unsafe {
-                                (*slow).levels[level_cursor] = new_node;
+                                (&mut (*slow).levels)[level_cursor] = new_node;
                             }
                         }
                         if level_cursor > 0 {
@@ -227,7 +317,7 @@ where Member: Ord
                         // 后一个区间，slow 就移位
                         slow = next;
                         next = unsafe {
-                            (*slow).levels[level_cursor]
+                            (&(*slow).levels)[level_cursor]
                         };
                     },
                 }
@@ -237,14 +327,12 @@ where Member: Ord
                 self.level_links[level_cursor] = new_node;
             } else {
                 unsafe {
-                    (*slow).levels[level_cursor] = new_node;
+                    (&mut (*slow).levels)[level_cursor] = new_node;
                 }
             }
-            if level_cursor == 0 {
-                if !slow.is_null() {
-                    unsafe {
-                        (*new_node).backward = slow;
-                    }
+            if level_cursor == 0 && !slow.is_null() {
+                unsafe {
+                    (*new_node).backward = slow;
                 }
             }
         }
@@ -268,23 +356,23 @@ where Member: Ord
                     }
                     let span_after = slow_span - span_before;
                     unsafe {
-                        (*new_node).spans[level_cursor] = span_after;
+                        (&mut (*new_node).spans)[level_cursor] = span_after;
                     }
                     if slow.is_null() {
                         self.level_spans[level_cursor] = span_before;
                     } else {
                         unsafe {
-                            (*slow).spans[level_cursor] = span_before;
+                            (&mut (*slow).spans)[level_cursor] = span_before;
                         }
                     }
                     continue 'out2;
                 } else {
                     slow = next;
                     slow_span = unsafe {
-                        (*slow).spans[level_cursor]
+                        (&(*slow).spans)[level_cursor]
                     };
                     next = unsafe {
-                        (*next).levels[level_cursor]
+                        (&(*next).levels)[level_cursor]
                     };
                 }
             }
@@ -296,7 +384,7 @@ where Member: Ord
                 self.level_links[level_cursor]
             } else {
                 unsafe {
-                    (*slow).levels[level_cursor]
+                    (&(*slow).levels)[level_cursor]
                 }
             };
             while !next.is_null() {
@@ -305,14 +393,14 @@ where Member: Ord
                         self.level_spans[level_cursor] += 1;
                     } else {
                         unsafe {
-                            (*slow).spans[level_cursor] += 1;
+                            (&mut (*slow).spans)[level_cursor] += 1;
                         }
                     }
                     continue 'out3;
                 } else {
                     slow = next;
                     next = unsafe {
-                        (*next).levels[level_cursor]
+                        (&(*next).levels)[level_cursor]
                     };
                 }
             }
@@ -320,7 +408,7 @@ where Member: Ord
                 self.level_spans[level_cursor] += 1;
             } else {
                 unsafe {
-                    (*slow).spans[level_cursor] += 1;
+                    (&mut (*slow).spans)[level_cursor] += 1;
                 }
             } 
         }
@@ -328,12 +416,25 @@ where Member: Ord
         if level > self.level {
             self.level = level;
         }
+        if unsafe {(&(*new_node).levels)[0]}.is_null() {
+            self.tail = new_node;
+        }
         Some(new_node)
     }
 
     fn do_find(&self, score: f64, data: &Member) -> Option<&Node<Member>> {
+        let node = self.find_node(score, data);
+        if node.is_null() {
+            None
+        } else {
+            Some(unsafe {&*node})
+        }
+    }
+
+    /// 查找 (score, data) 对应的节点，不存在时返回空指针
+    fn find_node(&self, score: f64, data: &Member) -> *mut Node<Member> {
         if self.length == 0 {
-            return None
+            return std::ptr::null_mut()
         }
         let mut slow: *mut Node<Member> = std::ptr::null_mut();
         'out: for level_cursor in (0..self.level).rev() {
@@ -341,7 +442,7 @@ where Member: Ord
                 self.level_links[level_cursor]
             } else {
                 unsafe {
-                    (*slow).levels[level_cursor]
+                    (&(*slow).levels)[level_cursor]
                 }
             };
             while !next.is_null() {
@@ -356,22 +457,22 @@ where Member: Ord
                         if level_cursor > 0 {
                             continue 'out;
                         }
-                        return None
+                        return std::ptr::null_mut()
                     },
                     Ordering::Equal => {
-                        return Some(unsafe{&(*next)})
+                        return next
                     },
                     Ordering::Greater => {
                         slow = next;
                         next = unsafe {
-                            (*slow).levels[level_cursor]
+                            (&(*slow).levels)[level_cursor]
                         };
                         continue
                     },
                 };
             }
         }
-        None
+        std::ptr::null_mut()
     }
 
     /// 查找 (score, data) 是否在表内
@@ -386,6 +487,7 @@ where Member: Ord
         let count = self.length;
         self.length = 0;
         self.level = 0;
+        self.tail = std::ptr::null_mut();
         while !self.level_links[0].is_null() {
             let node = unsafe {
                 Box::from_raw(self.level_links[0])
@@ -398,9 +500,69 @@ where Member: Ord
     }
 
     pub fn remove(&mut self, score: f64, data: &Member) -> bool {
-        if self.length == 0 {
+        self.take(score, data).is_some()
+    }
+
+    /// 修改 member 的分数，返回 (old_score, member) 是否在表内。
+    /// 新分数不改变节点的前后顺序时直接原地修改，否则摘下节点后按新分数重新插入
+    pub fn update_score(&mut self, data: &Member, old_score: f64, new_score: f64) -> bool {
+        let node = self.find_node(old_score, data);
+        if node.is_null() {
             return false;
         }
+        let (backward, forward) = unsafe {((*node).backward, (&(*node).levels)[0])};
+        let after_backward = backward.is_null()
+            || Self::cmp(unsafe {((*backward).score, &(*backward).data)}, (new_score, data)) == Ordering::Less;
+        let before_forward = forward.is_null()
+            || Self::cmp((new_score, data), unsafe {((*forward).score, &(*forward).data)}) == Ordering::Less;
+        if after_backward && before_forward {
+            unsafe {
+                (*node).score = new_score;
+            }
+            return true;
+        }
+        // 前面已经找到，一定能摘下来
+        let data = self.take(old_score, data).unwrap();
+        self.insert(data, new_score);
+        true
+    }
+
+    /// 删除 (score, data) 对应的节点，并返回节点中的数据
+    fn take(&mut self, score: f64, data: &Member) -> Option<Member> {
+        let node = self.unlink(score, data);
+        if node.is_null() {
+            None
+        } else {
+            Some(unsafe {Box::from_raw(node)}.data)
+        }
+    }
+
+    /// 删除并返回分数最小的 (score, data)，O(log n)
+    pub fn pop_front(&mut self) -> Option<(f64, Member)> {
+        let first = self.level_links.first().copied().unwrap_or(std::ptr::null_mut());
+        self.pop_node(first)
+    }
+
+    /// 删除并返回分数最大的 (score, data)，O(log n)
+    pub fn pop_back(&mut self) -> Option<(f64, Member)> {
+        self.pop_node(self.tail)
+    }
+
+    fn pop_node(&mut self, node: *mut Node<Member>) -> Option<(f64, Member)> {
+        if self.length == 0 || node.is_null() {
+            return None;
+        }
+        let node = self.unlink(unsafe {(*node).score}, unsafe {&(*node).data});
+        let node = unsafe {Box::from_raw(node)};
+        Some((node.score, node.data))
+    }
+
+    /// 把 (score, data) 对应的节点从各层摘下并修正跨度，返回该节点，不存在时返回空指针。
+    /// 节点的内存由调用方负责释放
+    fn unlink(&mut self, score: f64, data: &Member) -> *mut Node<Member> {
+        if self.length == 0 {
+            return std::ptr::null_mut();
+        }
         let mut to_remove: *mut Node<Member> = std::ptr::null_mut();
         let mut slow: *mut Node<Member> = std::ptr::null_mut();
         'out: for cur_level in (0..self.level).rev() {
@@ -408,7 +570,7 @@ where Member: Ord
                 self.level_links[cur_level]
             } else {
                 unsafe {
-                    (*slow).levels[cur_level]
+                    (&(*slow).levels)[cur_level]
                 }
             };
             while !next.is_null() {
@@ -425,22 +587,22 @@ where Member: Ord
                             continue 'out;
                         }
                         // 扫描完成，没有发现
-                        return false;
+                        return std::ptr::null_mut();
                     },
                     Ordering::Equal => {
                         if slow.is_null() {
-                            self.level_links[cur_level] = unsafe {(*next).levels[cur_level]};
+                            self.level_links[cur_level] = unsafe {(&(*next).levels)[cur_level]};
                         } else {
                             unsafe {
-                                (*slow).levels[cur_level] = (*next).levels[cur_level];
+                                (&mut (*slow).levels)[cur_level] = (&(*next).levels)[cur_level];
                             }
                         }
                         if cur_level == 0 {
-                            if !slow.is_null() {
-                                if !(unsafe {(*next).levels[0]}.is_null()) {
-                                    unsafe {
-                                        (*(*next).levels[0]).backward = slow;
-                                    }
+                            // 删除的是首个节点时，后继的 backward 要置空
+                            let after = unsafe {(&(*next).levels)[0]};
+                            if !after.is_null() {
+                                unsafe {
+                                    (*after).backward = slow;
                                 }
                             }
                             self.length -= 1;
@@ -453,7 +615,7 @@ where Member: Ord
                     Ordering::Greater => {
                         slow = next;
                         next = unsafe {
-                            (*slow).levels[cur_level]
+                            (&(*slow).levels)[cur_level]
                         };
                         continue;
                     },
@@ -469,7 +631,7 @@ where Member: Ord
             for level in 1..item_level {
                 // null for the start list
                 let span_after = unsafe {
-                    (*to_remove).spans[level]
+                    (&(*to_remove).spans)[level]
                 };
                 let mut slow: *mut Node<Member> = std::ptr::null_mut(); 
                 let mut next = self.level_links[level];
@@ -481,14 +643,14 @@ where Member: Ord
                             self.level_spans[level] += span_after;
                         } else {
                             unsafe {
-                                (*slow).spans[level] += span_after;
+                                (&mut (*slow).spans)[level] += span_after;
                             }
                         };
                         break;
                     } else {
                         slow = next;
                         next = unsafe {
-                            (*slow).levels[level]
+                            (&(*slow).levels)[level]
                         };
                     }
                 }
@@ -504,22 +666,24 @@ where Member: Ord
                             self.level_spans[level] -= 1;
                         } else {
                             unsafe {
-                                (*slow).spans[level] -= 1;
+                                (&mut (*slow).spans)[level] -= 1;
                             }
                         };
                         break;
                     } else {
                         slow = next;
                         next = unsafe {
-                            (*slow).levels[level]
+                            (&(*slow).levels)[level]
                         };
                     }
                 }
             }
-            let _ = unsafe{Box::from_raw(to_remove)};
-            return true
+            if to_remove == self.tail {
+                self.tail = unsafe {(*to_remove).backward};
+            }
+            return to_remove
         }
-        false
+        std::ptr::null_mut()
     }
 
     /// 随机当前结点的该跳的层次
@@ -536,6 +700,7 @@ where Member: Ord
         }
     }
 
+    #[cfg(test)]
     fn do_range_tuple(&self, min: Option<Bound>, max: Option<Bound>, offset: usize, limit: usize) -> Vec<(f64, &Member, usize)> {
         self.do_range(min, max, offset, limit)
             .into_iter()
@@ -551,7 +716,7 @@ where Member: Ord
                 self.level_links[level]
             } else {
                 unsafe {
-                    (*slow).levels[level]
+                    (&(*slow).levels)[level]
                 }
             };
             while !next.is_null() {
@@ -562,7 +727,7 @@ where Member: Ord
                     self.level_spans[level]
                 } else {
                     unsafe {
-                        (*slow).spans[level]
+                        (&(*slow).spans)[level]
                     }
                 };
                 if next_score > up.bound || (up.bound == next_score && up.exclusive) {
@@ -572,7 +737,7 @@ where Member: Ord
                     count += span + 1;
                     slow = next;
                     next = unsafe {
-                        (*slow).levels[level]
+                        (&(*slow).levels)[level]
                     };
                 }
             }
@@ -580,14 +745,144 @@ where Member: Ord
         count
     }
 
+    /// (score, data) 的排名（从 0 开始），不在表内时返回 `None`。
+    /// 从最高层开始沿各层的跨度向前跳，经过的节点数之和即排名，O(log n)
+    pub fn rank(&self, score: f64, data: &Member) -> Option<usize> {
+        let mut traversed = 0;
+        let mut slow: *mut Node<Member> = std::ptr::null_mut();
+        for level in (0..self.level).rev() {
+            loop {
+                let (next, span) = self.forward(slow, level);
+                if next.is_null() {
+                    break;
+                }
+                match Self::cmp((score, data), unsafe {((*next).score, &(*next).data)}) {
+                    Ordering::Less => break,
+                    Ordering::Equal => return Some(traversed + span),
+                    Ordering::Greater => {
+                        traversed += span + 1;
+                        slow = next;
+                    },
+                }
+            }
+        }
+        None
+    }
+
+    /// 排名为 rank（从 0 开始）的数据，O(log n)
+    pub fn get_by_rank(&self, rank: usize) -> Option<RangeItem<&Member>> {
+        let node = self.node_by_rank(rank);
+        if node.is_null() {
+            return None;
+        }
+        Some(unsafe {RangeItem::new((*node).score, &(*node).data, (*node).levels.len())})
+    }
+
+    /// 排名在 [start, stop] 之间的数据（从 0 开始，包含两端），支持 `zrange key start stop` 操作。
+    /// 先用 O(log n) 找到 start，之后沿第 0 层向后遍历
+    pub fn range_by_rank(&self, start: usize, stop: usize) -> Vec<RangeItem<&Member>> {
+        let mut result = vec![];
+        let mut cursor = self.node_by_rank(start);
+        for _ in start..=stop {
+            if cursor.is_null() {
+                break;
+            }
+            result.push(unsafe {RangeItem::new((*cursor).score, &(*cursor).data, (*cursor).levels.len())});
+            cursor = unsafe {(&(*cursor).levels)[0]};
+        }
+        result
+    }
+
+    /// 排名为 rank（从 0 开始）的节点，超出范围时返回空指针
+    fn node_by_rank(&self, rank: usize) -> *mut Node<Member> {
+        if rank >= self.length {
+            return std::ptr::null_mut();
+        }
+        // 经过的节点数，等于 rank + 1 时 slow 就是要找的节点
+        let mut traversed = 0;
+        let mut slow: *mut Node<Member> = std::ptr::null_mut();
+        for level in (0..self.level).rev() {
+            loop {
+                let (next, span) = self.forward(slow, level);
+                if next.is_null() || traversed + span + 1 > rank + 1 {
+                    break;
+                }
+                traversed += span + 1;
+                slow = next;
+                if traversed == rank + 1 {
+                    return slow;
+                }
+            }
+        }
+        unreachable!("rank {} not found in skiplist of length {}", rank, self.length)
+    }
+
+    /// slow 在 level 层的下一个节点及两者之间的节点数，slow 为空表示从链表头开始
+    fn forward(&self, slow: *mut Node<Member>, level: usize) -> (*mut Node<Member>, usize) {
+        if slow.is_null() {
+            (self.level_links[level], self.level_spans[level])
+        } else {
+            unsafe {((&(*slow).levels)[level], (&(*slow).spans)[level])}
+        }
+    }
+
     /// 获取指定范围内的数据量，支持 `zcount (start end` 操作
     pub fn range_count(&self, min: Option<Bound>, max: Option<Bound>) -> usize {
         match (min, max) {
             (None, None) => self.length,
             (None, Some(max)) => self.count_element_upto(&max),
             (Some(min), None) => self.length - self.count_element_upto(&min.toggle()),
-            (Some(min), Some(max)) => self.count_element_upto(&max) - self.count_element_upto(&min.toggle()),
+            (Some(min), Some(max)) => self.count_element_upto(&max).saturating_sub(self.count_element_upto(&min.toggle())),
+        }
+    }
+
+    /// 从头开始连续满足 pred 的节点数。要求 pred 对靠前的节点成立、对靠后的节点不成立，
+    /// 这样可以沿各层的跨度跳过，O(log n)
+    fn count_while(&self, pred: impl Fn(&Member) -> bool) -> usize {
+        let mut count = 0;
+        let mut slow: *mut Node<Member> = std::ptr::null_mut();
+        for level in (0..self.level).rev() {
+            loop {
+                let (next, span) = self.forward(slow, level);
+                if next.is_null() || !pred(unsafe {&(*next).data}) {
+                    break;
+                }
+                count += span + 1;
+                slow = next;
+            }
+        }
+        count
+    }
+
+    /// member 在字典序范围内的节点数，支持 `zlexcount key min max` 操作。`None` 表示 `-`/`+`
+    pub fn lex_count(&self, min: Option<LexBound<Member>>, max: Option<LexBound<Member>>) -> usize {
+        let (start, stop) = self.lex_ranks(min, max);
+        stop.saturating_sub(start)
+    }
+
+    /// 获取 member 在字典序范围内的数据，支持 `zrangebylex key min max LIMIT offset count` 操作。
+    /// `limit` 为 0 表示不限制数量
+    pub fn range_by_lex(&self, min: Option<LexBound<Member>>, max: Option<LexBound<Member>>, offset: usize, limit: usize) -> Vec<RangeItem<&Member>> {
+        let (start, stop) = self.lex_ranks(min, max);
+        let start = start.saturating_add(offset);
+        let stop = if limit == 0 { stop } else { stop.min(start.saturating_add(limit)) };
+        if start >= stop {
+            return vec![];
         }
+        self.range_by_rank(start, stop - 1)
+    }
+
+    /// 字典序范围对应的排名区间 [start, stop)
+    fn lex_ranks(&self, min: Option<LexBound<Member>>, max: Option<LexBound<Member>>) -> (usize, usize) {
+        let start = min.map_or(0, |min| self.count_while(|data| !min.check_min(data)));
+        let stop = max.map_or(self.length, |max| self.count_while(|data| max.check_max(data)));
+        (start, stop)
+    }
+
+    /// 获取指定分数范围内的数据，支持 `zrangebyscore key min max LIMIT offset count` 操作。
+    /// `limit` 为 0 表示不限制数量
+    pub fn range(&self, min: Option<Bound>, max: Option<Bound>, offset: usize, limit: usize) -> Vec<RangeItem<&Member>> {
+        self.do_range(min, max, offset, limit)
     }
 
     fn do_range(&self, min: Option<Bound>, max: Option<Bound>, mut offset: usize, mut limit: usize) -> Vec<RangeItem<&Member>> {
@@ -600,13 +895,15 @@ where Member: Ord
         }
         let mut first = self.level_links[0];
         if let Some(min) = min {
+            // 所有节点都小于 min 时，范围为空
+            first = std::ptr::null_mut();
             let mut slow: *mut Node<Member> = std::ptr::null_mut();
             'out: for level in (0..self.level).rev() {
                 let mut next = if slow.is_null() {
                     self.level_links[level]
                 } else {
                     unsafe {
-                        (*slow).levels[level]
+                        (&(*slow).levels)[level]
                     }
                 };
                 while !next.is_null() {
@@ -615,7 +912,7 @@ where Member: Ord
                         // 起始点在下一个区间
                         slow = next;
                         next = unsafe {
-                            (*slow).levels[level]
+                            (&(*slow).levels)[level]
                         };
                         continue
                     } else {
@@ -647,7 +944,7 @@ where Member: Ord
         while !cursor.is_null() {
             if offset > 0 {
                 offset -= 1;
-                cursor = unsafe {(*cursor).levels[0]};
+                cursor = unsafe {(&(*cursor).levels)[0]};
                 continue;
             }
             if limit == 0 {
@@ -665,12 +962,27 @@ where Member: Ord
                 data: unsafe{&(*cursor).data},
                 skiplevel: unsafe{(*cursor).levels.len()},
             });
-            cursor = unsafe{(*cursor).levels[0]};
+            cursor = unsafe{(&(*cursor).levels)[0]};
         }
         result
     }
 }
 
+/// 表头的各层链接，加上每个节点的各层指针、跨度及其 member 的内容
+impl<M: PartialEq + MemSize> MemSize for Skiplist<M> {
+    fn mem_size(&self) -> usize {
+        let ptr = size_of::<usize>();
+        let mut size = (self.level_links.capacity() + self.level_spans.capacity()) * ptr;
+        let mut next = if self.length == 0 { std::ptr::null_mut() } else { self.level_links[0] };
+        while !next.is_null() {
+            let node = unsafe { &*next };
+            size += size_of::<Node<M>>() + (node.levels.capacity() + node.spans.capacity()) * ptr + node.data.mem_size();
+            next = node.levels[0];
+        }
+        size
+    }
+}
+
 impl<Member: PartialEq> Node<Member> {
     pub fn new(data: Member, score: f64, level: usize) -> Self {
         Self {
@@ -687,7 +999,7 @@ impl<Member: PartialEq> Node<Member> {
 mod test {
     use crate::ds::skiplist::skiplist::Bound;
 
-    use super::Skiplist;
+    use super::{LexBound, RangeItem, Skiplist};
 
     #[test]
     fn basis() {
@@ -711,40 +1023,40 @@ mod test {
         let inserted_22 = list.do_insert(22, 22f64, 1).unwrap();
         for level in 0..list.level {
             assert_eq!(list.level_spans[level], 0);
-            assert_eq!(unsafe{(*inserted_22).spans[level]}, 0);
+            assert_eq!(unsafe{(&(*inserted_22).spans)[level]}, 0);
         }
         let inserted_19 = list.do_insert(19, 19f64, 2).unwrap();
         assert_eq!(unsafe {
-            (*inserted_19).spans[0]
+            (&(*inserted_19).spans)[0]
         }, 0);
-        assert_eq!(unsafe{(*inserted_19).spans[1]}, 1);
+        assert_eq!(unsafe{(&(*inserted_19).spans)[1]}, 1);
         let inserted_7 = list.do_insert(7, 7f64, 4).unwrap();
-        assert_eq!(unsafe{(*inserted_7).spans[0]}, 0);
-        assert_eq!(unsafe{(*inserted_7).spans[1]}, 0);
-        assert_eq!(unsafe{(*inserted_7).spans[2]}, 2);
-        assert_eq!(unsafe{(*inserted_7).spans[3]}, 2);
-        let inserted_3 = list.do_insert(3, 3f64, 1);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[0]}, 0);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[1]}, 0);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[2]}, 2);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[3]}, 2);
+        list.do_insert(3, 3f64, 1);
         assert_eq!(list.level_spans[0], 0);
         assert_eq!(list.level_spans[1], 1);
         assert_eq!(list.level_spans[2], 1);
         assert_eq!(list.level_spans[3], 1);
         let inserted_37 = list.do_insert(37, 37f64, 3).unwrap();
         for l in 0..3 {
-            assert_eq!(unsafe{(*inserted_37).spans[l]}, 0);
+            assert_eq!(unsafe{(&(*inserted_37).spans)[l]}, 0);
         }
-        assert_eq!(unsafe{(*inserted_19).spans[1]}, 1);
-        assert_eq!(unsafe{(*inserted_7).spans[2]}, 2);
-        assert_eq!(unsafe{(*inserted_7).spans[3]}, 3);
+        assert_eq!(unsafe{(&(*inserted_19).spans)[1]}, 1);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[2]}, 2);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[3]}, 3);
 
-        let inserted_11 = list.do_insert(11, 11f64, 1).unwrap();
-        assert_eq!(unsafe{(*inserted_7).spans[1]}, 1);
-        assert_eq!(unsafe{(*inserted_7).spans[2]}, 3);
-        assert_eq!(unsafe{(*inserted_7).spans[3]}, 4);
+        list.do_insert(11, 11f64, 1).unwrap();
+        assert_eq!(unsafe{(&(*inserted_7).spans)[1]}, 1);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[2]}, 3);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[3]}, 4);
 
         list.do_insert(26, 26f64, 1);
-        assert_eq!(unsafe{(*inserted_19).spans[1]}, 2);
-        assert_eq!(unsafe{(*inserted_7).spans[2]}, 4);
-        assert_eq!(unsafe{(*inserted_7).spans[3]}, 5);
+        assert_eq!(unsafe{(&(*inserted_19).spans)[1]}, 2);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[2]}, 4);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[3]}, 5);
 
         // (-inf, 3]
         assert_eq!(list.count_element_upto(&Bound::new_inclusive(3f64)), 1);
@@ -783,9 +1095,9 @@ mod test {
         ), list.length);
         // remove and check span again
         list.remove(22f64, &22);
-        assert_eq!(unsafe{(*inserted_19).spans[1]}, 1);
-        assert_eq!(unsafe{(*inserted_7).spans[2]}, 3);
-        assert_eq!(unsafe{(*inserted_7).spans[3]}, 4);
+        assert_eq!(unsafe{(&(*inserted_19).spans)[1]}, 1);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[2]}, 3);
+        assert_eq!(unsafe{(&(*inserted_7).spans)[3]}, 4);
 
         list.remove(7f64, &7);
         assert_eq!(list.level_spans[1], 2);
@@ -793,7 +1105,7 @@ mod test {
         assert_eq!(list.level_spans[3], 5);
 
         list.remove(37f64, &37);
-        assert_eq!(unsafe{(*inserted_19).spans[1]}, 1);
+        assert_eq!(unsafe{(&(*inserted_19).spans)[1]}, 1);
         assert_eq!(list.level_spans[2], 4);
         assert_eq!(list.level_spans[3], 4);
 
@@ -904,4 +1216,112 @@ mod test {
         let r = list.do_range_tuple(None, None, 0, 0);
         assert_eq!(r, vec![]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn check_rank() {
+        let mut list = Skiplist::new();
+        let levels = [1, 2, 4, 1, 3, 1, 1, 2];
+        let scores = [22, 19, 7, 3, 37, 11, 26, 19];
+        for (i, (&score, &level)) in scores.iter().zip(levels.iter()).enumerate() {
+            list.do_insert(i as i32, score as f64, level);
+        }
+        // 按 (score, data) 排序后的顺序
+        let mut expected: Vec<(f64, i32)> = scores.iter().enumerate().map(|(i, &score)| (score as f64, i as i32)).collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for (rank, (score, data)) in expected.iter().enumerate() {
+            assert_eq!(list.rank(*score, data), Some(rank));
+            let item = list.get_by_rank(rank).unwrap();
+            assert_eq!((item.score, *item.data), (*score, *data));
+        }
+        assert_eq!(list.rank(19f64, &0), None);
+        assert_eq!(list.rank(100f64, &0), None);
+        assert!(list.get_by_rank(expected.len()).is_none());
+
+        let r: Vec<(f64, i32)> = list.range_by_rank(2, 4).iter().map(|item| (item.score, *item.data)).collect();
+        assert_eq!(r, expected[2..=4]);
+        let r: Vec<(f64, i32)> = list.range_by_rank(6, 100).iter().map(|item| (item.score, *item.data)).collect();
+        assert_eq!(r, expected[6..]);
+        assert!(list.range_by_rank(8, 10).is_empty());
+
+        // 删除后跨度依然正确
+        assert!(list.remove(7f64, &2));
+        expected.retain(|&(score, _)| score != 7f64);
+        for (rank, (score, data)) in expected.iter().enumerate() {
+            assert_eq!(list.rank(*score, data), Some(rank));
+            assert_eq!(*list.get_by_rank(rank).unwrap().data, *data);
+        }
+    }
+
+    #[test]
+    fn check_update_score() {
+        let mut list = Skiplist::new();
+        for (data, level) in [(1, 1), (2, 3), (3, 2), (4, 1), (5, 4)] {
+            list.do_insert(data, (data * 10) as f64, level);
+        }
+        let node = list.find_node(30f64, &3);
+        // 仍在 20 与 40 之间，原地修改
+        assert!(list.update_score(&3, 30f64, 35f64));
+        assert_eq!(list.find_node(35f64, &3), node);
+        assert!(!list.exists(30f64, &3));
+
+        // 越过其他节点，需要重新插入
+        assert!(list.update_score(&1, 10f64, 100f64));
+        assert!(list.update_score(&5, 50f64, 0f64));
+        let r: Vec<(f64, i32)> = list.range(None, None, 0, 0).iter().map(|item| (item.score, *item.data)).collect();
+        assert_eq!(r, vec![(0f64, 5), (20f64, 2), (35f64, 3), (40f64, 4), (100f64, 1)]);
+        for (rank, (score, data)) in r.iter().enumerate() {
+            assert_eq!(list.rank(*score, data), Some(rank));
+        }
+        assert_eq!(list.len(), 5);
+
+        assert!(!list.update_score(&1, 10f64, 20f64));
+        assert!(!list.update_score(&6, 60f64, 20f64));
+    }
+
+    #[test]
+    fn check_pop() {
+        let mut list = Skiplist::new();
+        assert!(list.pop_front().is_none());
+        assert!(list.pop_back().is_none());
+        for (data, level) in [(3, 1), (1, 3), (5, 2), (2, 1), (4, 4)] {
+            list.do_insert(data, data as f64, level);
+        }
+        assert_eq!(list.pop_front(), Some((1f64, 1)));
+        assert_eq!(list.pop_back(), Some((5f64, 5)));
+        // 尾部节点被删除后，tail 指向前一个节点
+        assert!(list.remove(4f64, &4));
+        assert_eq!(list.pop_back(), Some((3f64, 3)));
+        list.do_insert(6, 6f64, 2);
+        assert_eq!(list.rank(6f64, &6), Some(1));
+        assert_eq!(list.pop_back(), Some((6f64, 6)));
+        assert_eq!(list.pop_back(), Some((2f64, 2)));
+        assert!(list.is_empty());
+        assert!(list.pop_front().is_none());
+
+        list.do_insert(7, 7f64, 1);
+        assert_eq!(list.pop_front(), Some((7f64, 7)));
+        list.do_insert(8, 8f64, 1);
+        list.clear();
+        assert!(list.pop_back().is_none());
+    }
+
+    #[test]
+    fn check_lex() {
+        let mut list = Skiplist::new();
+        for (data, level) in [("b", 1), ("d", 3), ("a", 2), ("e", 1), ("c", 4)] {
+            list.do_insert(data, 0f64, level);
+        }
+        let data = |items: Vec<RangeItem<&&'static str>>| items.into_iter().map(|item| *item.data).collect::<Vec<_>>();
+        assert_eq!(data(list.range_by_lex(None, None, 0, 0)), ["a", "b", "c", "d", "e"]);
+        assert_eq!(data(list.range_by_lex(Some(LexBound::new_inclusive("b")), Some(LexBound::new_exclusive("d")), 0, 0)), ["b", "c"]);
+        assert_eq!(data(list.range_by_lex(Some(LexBound::new_exclusive("b")), None, 1, 2)), ["d", "e"]);
+        assert_eq!(data(list.range_by_lex(Some(LexBound::new_inclusive("bb")), Some(LexBound::new_inclusive("dd")), 0, 0)), ["c", "d"]);
+        assert!(list.range_by_lex(Some(LexBound::new_inclusive("d")), Some(LexBound::new_inclusive("b")), 0, 0).is_empty());
+        assert!(list.range_by_lex(None, None, 5, 0).is_empty());
+
+        assert_eq!(list.lex_count(None, None), 5);
+        assert_eq!(list.lex_count(Some(LexBound::new_exclusive("a")), Some(LexBound::new_inclusive("c"))), 2);
+        assert_eq!(list.lex_count(None, Some(LexBound::new_exclusive("a"))), 0);
+        assert_eq!(list.lex_count(Some(LexBound::new_inclusive("z")), None), 0);
+    }
+}
@@ -11,7 +11,7 @@ use std::{mem, vec};
 
 use byteorder::{BigEndian, ByteOrder};
 
-use super::error::{ZLResult, ZLError};
+use super::{MemSize, error::{ZLResult, ZLError}};
 
 const ZIPLIST_BYTES_OFF: usize = 0;
 const ZIPLIST_BYTES_SIZE: usize = 4;
@@ -38,10 +38,7 @@ enum Encoding {
 
 impl Encoding {
     fn is_str(&self) -> bool {
-        match self {
-            Encoding::String(_) => true,
-            _ => false,
-        }
+        matches!(self, Encoding::String(_))
     }
     /// 获取编码本身所占的字节数。
     fn encoding_len(&self) -> usize {
@@ -63,7 +60,7 @@ impl Encoding {
                     1 + mem::size_of::<i8>()
                 } else if *i >= i16::MIN as i64 && *i <= i16::MAX as i64 {
                     1 + mem::size_of::<i16>()
-                } else if *i >= -(1<<23) && *i <= (1<<23) - 1 {
+                } else if *i >= -(1<<23) && *i < (1<<23) {
                     1 + 3
                 } else if *i >= i32::MIN as i64 && *i <= i32::MAX as i64 {
                     1 + mem::size_of::<i32>()
@@ -168,10 +165,10 @@ impl Encoding {
             _ => panic!("not possible"),
         };
         let mut v = src[0] as usize & 0b0011_1111;
-        for i in 1..sz {
+        for b in &src[1..sz] {
             // 大端模式
             v <<= 8;
-            v |= src[i] as usize;
+            v |= *b as usize;
         }
         Ok(Self::String(v))
     }
@@ -230,7 +227,7 @@ impl IntoIterator for Encoding {
 
     fn into_iter(self) -> Self::IntoIter {
         Self::IntoIter {
-            enc: self.clone(),
+            enc: self,
             offset: 0,
         }
     }
@@ -241,6 +238,7 @@ pub enum ZipEntryValue {
     Int(i64),
 }
 
+#[allow(dead_code)]
 impl ZipEntryValue {
     fn unwrap_bytes(&self) -> &[u8] {
         match self {
@@ -268,7 +266,7 @@ pub struct ZipEntry{
 impl ZipEntry {
     fn parse(src: &[u8]) -> Self {
         let prevrawlen = Self::parse_prevrawlen(src);
-        let prevrawlen_size = Self::prevrawlen_size(prevrawlen);
+        let prevrawlen_size = Self::parse_prevrawlen_size(src);
         let encoding = Encoding::parse(&src[prevrawlen_size..]).unwrap();
         Self{
             prevrawlen,
@@ -287,32 +285,42 @@ impl ZipEntry {
         }
     }
 
+    /// prevrawlen 实际占用的字节数。与 redis 一样，5 字节的编码也可能保存较小的值，所以要看首字节
+    #[inline]
+    fn parse_prevrawlen_size(src: &[u8]) -> usize {
+        if src[0] < 0xfe {
+            1
+        } else {
+            5
+        }
+    }
+
     fn parse_prevrawlen(src: &[u8]) -> usize {
         if src[0] < 0xfe {
             return src[0] as usize;
         }
         let mut v: usize = 0;
-        for i in 1..=4 {
+        for b in &src[1..=4] {
             v <<= 8;
-            v |= src[i] as usize;
+            v |= *b as usize;
         }
         v
     }
 
-    fn encode_prevrawlen(prevrawlen: usize) -> Vec<u8> {
-        if prevrawlen < 0xfe {
+    /// 按指定的宽度（1 或 5 字节）编码 prevrawlen
+    fn encode_prevrawlen(prevrawlen: usize, prevrawlen_size: usize) -> Vec<u8> {
+        if prevrawlen_size == 1 {
             vec![prevrawlen as u8]
         } else {
             let mut v = vec![0u8; 5];
             v[0] = 0xfe;
-            BigEndian::write_u32(&mut v, prevrawlen as u32);
+            BigEndian::write_u32(&mut v[1..], prevrawlen as u32);
             v
         }
     }
 
     fn check_len(src: &[u8]) -> usize {
-        let prevrawlen = Self::parse_prevrawlen(src);
-        let prevrawlen_size = Self::prevrawlen_size(prevrawlen);
+        let prevrawlen_size = Self::parse_prevrawlen_size(src);
         let encoding = Encoding::parse(&src[prevrawlen_size..]).unwrap();
         prevrawlen_size + encoding.encoding_len_with_content()
     }
@@ -325,7 +333,7 @@ impl ZipEntry {
         self.prevrawlen_size + self.encoding.encoding_len_with_content()
     }
 
-    fn value<'a>(&self, bytes: &[u8]) -> ZipEntryValue {
+    fn value(&self, bytes: &[u8]) -> ZipEntryValue {
         let header_size = self.header_size();
         match self.encoding {
             Encoding::String(sz) => ZipEntryValue::Bytes(bytes[header_size..header_size+sz].to_vec()),
@@ -334,35 +342,44 @@ impl ZipEntry {
     }
 
 
-    fn iter<'a>(&self, bytes: &'a [u8]) -> std::iter::Chain<std::iter::Chain<vec::IntoIter<u8>, EncodingIter>, std::iter::Cloned<std::slice::Iter<'a, u8>>>   {
-        let prevrawlen_bytes = if self.prevrawlen_size == 1 {
-            vec![self.prevrawlen as u8]
-        } else {
-            let mut v = vec![0u8; self.prevrawlen_size];
-            v[0] = 0xfe;
-            BigEndian::write_u32(&mut v, self.prevrawlen as u32);
-            v
-        };
+    /// 编码后的 entry 字节流，content 为字符串的原始内容
+    fn iter<'a>(&self, content: &'a [u8]) -> std::iter::Chain<std::iter::Chain<vec::IntoIter<u8>, EncodingIter>, std::iter::Cloned<std::slice::Iter<'a, u8>>>   {
+        let prevrawlen_bytes = Self::encode_prevrawlen(self.prevrawlen, self.prevrawlen_size);
         let content_iter = if self.encoding.is_str() {
-            bytes[self.header_size()..].iter().cloned::<'a, _>()
+            content.iter().cloned::<'a, _>()
         } else {
             "".as_bytes().iter().cloned::<'a, _>()
         };
         prevrawlen_bytes
             .into_iter()
-            .chain(self.encoding.into_iter())
+            .chain(self.encoding)
             .chain(content_iter)
     }
 }
 
 /// mutable zip entry
+#[allow(dead_code)]
 struct ZipEntryMut<'a> {
     list: &'a mut ZipList,
     offset: usize,
 }
 
+#[derive(Clone)]
 pub struct ZipList(Vec<u8>);
 
+/// 按实际分配的空间计算，可能比 [`ZipList::blob_len`] 大
+impl MemSize for ZipList {
+    fn mem_size(&self) -> usize {
+        self.0.capacity()
+    }
+}
+
+impl Default for ZipList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ZipList {
     pub fn new() -> Self {
         let mut src = vec![0u8; ZIPLIST_HEADER_SIZE];
@@ -371,6 +388,11 @@ impl ZipList {
         Self(src)
     }
 
+    /// 整个 ziplist 占用的字节数
+    pub fn blob_len(&self) -> usize {
+        self.0.len()
+    }
+
     fn set_tail_offset(&mut self, tail_offset: usize) {
         BigEndian::write_u32(&mut self.0[ZIPLIST_TAILOFF_OFF..], tail_offset as u32);
     }
@@ -406,7 +428,6 @@ impl ZipList {
     }
 
     fn set_bytes_size(&mut self, sz: usize) {
-        println!("set_bytes_size: {}", sz);
         BigEndian::write_u32(&mut self.0[ZIPLIST_BYTES_OFF..], sz as u32);
     }
 
@@ -427,7 +448,7 @@ impl ZipList {
         };
         let required_len = prevrawlen_size + encoding.encoding_len_with_content();
         self.0.splice(tail_offset..tail_offset, vec![0u8; required_len]);
-        (&mut self.0[tail_offset..]).iter_mut().zip(ze.iter(content)).for_each(|(a, b)| *a = b);
+        self.0[tail_offset..].iter_mut().zip(ze.iter(content)).for_each(|(a, b)| *a = b);
         self.set_bytes_size(self.bytes_size() + required_len);
         self.set_tail_offset(tail_offset);
         self.set_entry_cnt(cnt + 1);
@@ -444,7 +465,20 @@ impl ZipList {
         self.push_tail(encoding, &[])
     }
 
+    /// 在表头插入字符串
+    pub fn push_front_string(&mut self, content: &[u8]) -> ZLResult<()> {
+        self.insert(ZIPLIST_HEADER_SIZE, Encoding::String(content.len()), content)
+    }
+
+    /// 在表头插入整数
+    pub fn push_front_int(&mut self, val: i64) -> ZLResult<()> {
+        self.insert(ZIPLIST_HEADER_SIZE, Encoding::Integer(val), &[])
+    }
+
     fn count_entry(&self) -> usize {
+        if self.bytes_size() == ZIPLIST_HEADER_SIZE {
+            return 0
+        }
         let mut cnt = 0;
         let mut offset = self.tail_offset();
         while offset >= ZIPLIST_CONTENT_OFF {
@@ -458,50 +492,198 @@ impl ZipList {
         cnt
     }
 
-    pub fn iter(&self) -> ZipListIter {
+    /// 从头到尾遍历，返回各 entry 的偏移及其头部信息。也可以用 `rev()` 从尾到头遍历
+    pub fn iter(&self) -> ZipListIter<'_> {
         ZipListIter{
             ziplist: self,
-            cur_offset: self.tail_offset(),
+            front: ZIPLIST_CONTENT_OFF,
+            back: self.tail_offset(),
+            remaining: self.get_entry_cnt(),
+        }
+    }
+
+    /// 从头到尾遍历各 entry 的值
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = ZipEntryValue> + ExactSizeIterator + '_ {
+        self.iter().map(move |(offset, entry)| entry.value(&self.0[offset..]))
+    }
+
+    /// 第 index 个 entry 的值，负数表示从表尾倒数，-1 为最后一个。
+    /// 负数时从表尾开始经 prevrawlen 往前走，不需要从头遍历
+    pub fn get(&self, index: isize) -> Option<ZipEntryValue> {
+        let (offset, entry) = if index >= 0 {
+            self.iter().nth(index as usize)?
+        } else {
+            self.iter().rev().nth(index.unsigned_abs() - 1)?
+        };
+        Some(entry.value(&self.0[offset..]))
+    }
+
+    /// 第一个值等于 value 的 entry 的位置。整数编码的 entry 按其十进制表示比较
+    pub fn find(&self, value: &[u8]) -> Option<usize> {
+        self.matches(value).position(|matched| matched)
+    }
+
+    /// 从头到尾依次给出各 entry 的值是否等于 value，比较规则与 [`ZipList::find`] 相同。
+    /// 配合 `enumerate`、`rev` 可以从任意一端查找多个位置，不需要取出 entry 的值
+    pub fn matches<'a>(&'a self, value: &'a [u8]) -> impl DoubleEndedIterator<Item = bool> + ExactSizeIterator + 'a {
+        // value 能无损地表示为整数时，才可能等于整数编码的 entry
+        let int = std::str::from_utf8(value)
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .filter(|i| i.to_string().as_bytes() == value);
+        self.iter().map(move |(offset, entry)| match entry.encoding {
+            Encoding::String(sz) => {
+                let start = offset + entry.header_size();
+                &self.0[start..start+sz] == value
+            },
+            Encoding::Integer(i) => int == Some(i),
+        })
+    }
+
+    /// 在第 index 个 entry 之前插入字符串，index 等于 entry 数时追加到表尾
+    pub fn insert_at(&mut self, index: usize, value: &[u8]) -> ZLResult<()> {
+        let offset = self.offset_of(index).ok_or(ZLError::OutOfRange(index))?;
+        self.insert(offset, Encoding::String(value.len()), value)
+    }
+
+    /// 把第 index 个 entry 替换为字符串，返回原来的值
+    pub fn replace_at(&mut self, index: usize, value: &[u8]) -> ZLResult<ZipEntryValue> {
+        let old = self.delete_at(index)?;
+        self.insert_at(index, value)?;
+        Ok(old)
+    }
+
+    /// 删除第 index 个 entry，返回其值
+    pub fn delete_at(&mut self, index: usize) -> ZLResult<ZipEntryValue> {
+        match self.offset_of(index) {
+            Some(offset) if offset < self.bytes_size() => Ok(self.delete(offset)),
+            _ => Err(ZLError::OutOfRange(index)),
+        }
+    }
+
+    /// 第 index 个 entry 的偏移，index 等于 entry 数时为表尾之后的位置
+    fn offset_of(&self, index: usize) -> Option<usize> {
+        let mut iter = self.iter();
+        for _ in 0..index {
+            iter.next()?;
+        }
+        Some(iter.front)
+    }
+
+    /// 在 offset 处插入 entry，原来在 offset 处的 entry 成为它的后继
+    fn insert(&mut self, offset: usize, encoding: Encoding, content: &[u8]) -> ZLResult<()> {
+        let at_end = offset >= self.bytes_size();
+        let prevrawlen = if !at_end {
+            ZipEntry::parse(&self.0[offset..]).prevrawlen
+        } else if self.bytes_size() > ZIPLIST_HEADER_SIZE {
+            ZipEntry::check_len(&self.0[self.tail_offset()..])
+        } else {
+            0
+        };
+        let ze = ZipEntry{
+            prevrawlen,
+            prevrawlen_size: ZipEntry::prevrawlen_size(prevrawlen),
+            encoding,
+        };
+        let size = ze.entry_size();
+        self.0.splice(offset..offset, ze.iter(content));
+        self.set_bytes_size(self.bytes_size() + size);
+        if at_end {
+            self.set_tail_offset(offset);
+        } else {
+            self.set_tail_offset(self.tail_offset() + size);
+            self.cascade_update(offset + size, size);
+        }
+        self.incr_entry_cnt(1);
+        Ok(())
+    }
+
+    /// 删除 offset 处的 entry，返回其值
+    fn delete(&mut self, offset: usize) -> ZipEntryValue {
+        let entry = ZipEntry::parse(&self.0[offset..]);
+        let val = entry.value(&self.0[offset..]);
+        let size = entry.entry_size();
+        let is_tail = offset == self.tail_offset();
+        self.0.drain(offset..offset+size);
+        self.set_bytes_size(self.bytes_size() - size);
+        if is_tail {
+            self.set_tail_offset(offset - entry.prevrawlen);
+        } else {
+            self.set_tail_offset(self.tail_offset() - size);
+            // 后继的前驱变成了被删除 entry 的前驱
+            self.cascade_update(offset, entry.prevrawlen);
+        }
+        self.incr_entry_cnt(-1);
+        val
+    }
+
+    /// offset 处 entry 的前驱长度变为 prevlen，更新它的 prevrawlen。
+    ///
+    /// 1 字节放不下时扩展为 5 字节，entry 自身变大又改变了下一个 entry 的 prevrawlen，于是连锁更新下去，
+    /// 直到某个 entry 的大小不再变化。与 redis 一样，5 字节的编码不会缩回 1 字节，避免反复伸缩
+    fn cascade_update(&mut self, mut offset: usize, mut prevlen: usize) {
+        while offset < self.bytes_size() {
+            let entry = ZipEntry::parse(&self.0[offset..]);
+            if entry.prevrawlen == prevlen {
+                break;
+            }
+            let required = ZipEntry::prevrawlen_size(prevlen);
+            if required <= entry.prevrawlen_size {
+                let bytes = ZipEntry::encode_prevrawlen(prevlen, entry.prevrawlen_size);
+                self.0[offset..offset+entry.prevrawlen_size].copy_from_slice(&bytes);
+                break;
+            }
+            let grow = required - entry.prevrawlen_size;
+            self.0.splice(offset..offset+entry.prevrawlen_size, ZipEntry::encode_prevrawlen(prevlen, required));
+            self.set_bytes_size(self.bytes_size() + grow);
+            if offset < self.tail_offset() {
+                self.set_tail_offset(self.tail_offset() + grow);
+            }
+            prevlen = entry.entry_size() + grow;
+            offset += prevlen;
         }
     }
 
+    /// entry 数增加 delta。计数已经溢出（0xffff）时需要重新数一遍
+    fn incr_entry_cnt(&mut self, delta: isize) {
+        let cnt = self.read_entry_cnt();
+        if cnt < 0xffff {
+            self.set_entry_cnt(cnt.saturating_add_signed(delta));
+        } else {
+            self.set_entry_cnt(self.count_entry());
+        }
+    }
+
+    /// 弹出表尾的 entry。表尾的偏移直接记录在头部，其前驱由 prevrawlen 得到，不需要遍历
+    pub fn pop_tail(&mut self) -> Option<ZipEntryValue> {
+        if self.bytes_size() == ZIPLIST_HEADER_SIZE {
+            return None
+        }
+        Some(self.delete(self.tail_offset()))
+    }
+
     pub fn pop_front(&mut self) -> Option<ZipEntryValue> {
-        if self.read_entry_cnt() == 0 {
+        let ori_cnt = self.read_entry_cnt();
+        if ori_cnt == 0 {
             return None
         }
         let first = ZipEntry::parse(&self.0[ZIPLIST_HEADER_SIZE..]);
         let val = first.value(&self.0[ZIPLIST_HEADER_SIZE..]);
-        let mut cur_offset = ZIPLIST_HEADER_SIZE;
-        // 指向原来的下一个 entry 开头
-        let mut next_off = cur_offset + first.entry_size();
-        let mut last_size = 0usize;
-        let ori_bytes = self.bytes_size();
-        // 从 first.entry_size 变成了 0
-        let mut prevlen_changed = true;
-        while next_off < ori_bytes {
-            let entry = ZipEntry::parse(&self.0[next_off..]);
-            let entry_size = entry.entry_size();
-            if prevlen_changed  {
-                if entry.prevrawlen_size == last_size {
-                    // 这次没变化，后面就不再变化了
-                    prevlen_changed = false;
-                }
-                let prevlen_bytes = ZipEntry::encode_prevrawlen(last_size);
-                self.0[cur_offset..].copy_from_slice(&prevlen_bytes);
-                cur_offset += prevlen_bytes.len();
-                self.0.copy_within(next_off+entry.prevrawlen_size..next_off+entry_size, cur_offset);
-                cur_offset += entry_size - entry.prevrawlen_size;
-                last_size = prevlen_bytes.len() + entry_size - entry.prevrawlen_size;
+        let first_size = first.entry_size();
+        self.0.drain(ZIPLIST_HEADER_SIZE..ZIPLIST_HEADER_SIZE+first_size);
+        self.set_bytes_size(self.bytes_size() - first_size);
+        if self.bytes_size() > ZIPLIST_HEADER_SIZE {
+            // 新的首个 entry 没有前驱，prevrawlen 置 0。
+            // 与 redis 一样保留原有的编码宽度，这样它自身的大小不变，后续 entry 也就不需要连锁更新
+            if self.0[ZIPLIST_HEADER_SIZE] < 0xfe {
+                self.0[ZIPLIST_HEADER_SIZE] = 0;
             } else {
-                last_size = entry_size;
-                self.0.copy_within(next_off..next_off+entry_size, cur_offset);
-                cur_offset += entry_size;
+                BigEndian::write_u32(&mut self.0[ZIPLIST_HEADER_SIZE+1..], 0);
             }
-            next_off += entry_size;
+            self.set_tail_offset(self.tail_offset() - first_size);
+        } else {
+            self.set_tail_offset(ZIPLIST_HEADER_SIZE);
         }
-        self.set_bytes_size(ori_bytes-first.entry_size());
-        self.set_tail_offset(cur_offset);
-        let ori_cnt = self.read_entry_cnt();
         if ori_cnt < 0xffff {
             self.set_entry_cnt(ori_cnt-1);
         } else {
@@ -512,32 +694,59 @@ impl ZipList {
 
 }
 
+/// 双向遍历 ziplist：从头往后按 entry 大小前进，从尾往前按 prevrawlen 后退
 pub struct ZipListIter<'a> {
     ziplist: &'a ZipList,
-    cur_offset: usize,
+    /// 下一个从头部取出的 entry 的偏移
+    front: usize,
+    /// 下一个从尾部取出的 entry 的偏移
+    back: usize,
+    /// 尚未取出的 entry 数，两端相遇时为 0
+    remaining: usize,
 }
 
 impl<'a> Iterator for ZipListIter<'a> {
     type Item = (usize, ZipEntry);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cur_offset >= self.ziplist.bytes_size() {
+        if self.remaining == 0 {
             return None;
         }
-        let ori_offset = self.cur_offset;
-        let entry = ZipEntry::parse(&self.ziplist.0[self.cur_offset..]);
-        self.cur_offset += entry.entry_size();
+        let ori_offset = self.front;
+        let entry = ZipEntry::parse(&self.ziplist.0[self.front..]);
+        self.front += entry.entry_size();
+        self.remaining -= 1;
         Some((ori_offset, entry))
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
 }
 
+impl<'a> DoubleEndedIterator for ZipListIter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let ori_offset = self.back;
+        let entry = ZipEntry::parse(&self.ziplist.0[self.back..]);
+        self.back -= entry.prevrawlen;
+        self.remaining -= 1;
+        Some((ori_offset, entry))
+    }
+}
+
+impl<'a> ExactSizeIterator for ZipListIter<'a> {}
+
 #[cfg(test)]
 mod tests {
-    use crate::ds::ziplist::{ZipEntry, Encoding};
+    use crate::ds::ziplist::Encoding;
 
-    use super::{ZipList, ZIPLIST_HEADER_SIZE};
+    use super::{ZipEntry, ZipList, ZIPLIST_HEADER_SIZE};
 
     #[test]
+    #[allow(unused_assignments)]
     fn push_and_pop() {
         let mut zl = ZipList::new();
         assert_eq!(zl.bytes_size(), ZIPLIST_HEADER_SIZE);
@@ -576,10 +785,187 @@ mod tests {
         assert_eq!(zl.tail_offset(), last_tail_offset + prevrawlen);
 
         let mut iter = zl.iter();
-        let (offset, entry) = iter.next().unwrap();
+        let (_offset, _entry) = iter.next().unwrap();
         
     }
 
+    #[test]
+    fn pop_front_all() {
+        let mut zl = ZipList::new();
+        let items: Vec<Vec<u8>> = vec![vec![b'a'; 3], vec![b'b'; 300], vec![b'c'; 10], vec![b'd'; 70000], vec![b'e'; 1]];
+        for item in &items {
+            zl.push_tail_string(item).unwrap();
+        }
+        let values: Vec<Vec<u8>> = zl.values().map(|v| v.unwrap_bytes().to_vec()).collect();
+        assert_eq!(values, items);
+
+        for (idx, item) in items.iter().enumerate() {
+            assert_eq!(zl.pop_front().unwrap().unwrap_bytes(), &item[..]);
+            assert_eq!(zl.get_entry_cnt(), items.len() - idx - 1);
+            assert_eq!(zl.count_entry(), items.len() - idx - 1);
+            let values: Vec<Vec<u8>> = zl.values().map(|v| v.unwrap_bytes().to_vec()).collect();
+            assert_eq!(values, items[idx+1..]);
+        }
+        assert!(zl.pop_front().is_none());
+        assert_eq!(zl.bytes_size(), ZIPLIST_HEADER_SIZE);
+
+        // 清空后可以继续使用
+        zl.push_tail_string(b"again").unwrap();
+        assert_eq!(zl.values().next().unwrap().unwrap_bytes(), b"again");
+    }
+
+    #[test]
+    fn insert_and_delete_at() {
+        let mut zl = ZipList::new();
+        let values = |zl: &ZipList| zl.values().map(|v| v.unwrap_bytes().to_vec()).collect::<Vec<_>>();
+        zl.insert_at(0, b"b").unwrap();
+        zl.insert_at(0, b"a").unwrap();
+        zl.insert_at(2, b"d").unwrap();
+        zl.insert_at(2, b"c").unwrap();
+        assert_eq!(values(&zl), [b"a", b"b", b"c", b"d"]);
+        assert!(zl.insert_at(5, b"x").is_err());
+        assert_eq!(zl.get_entry_cnt(), 4);
+        assert_eq!(zl.blob_len(), zl.bytes_size());
+
+        assert_eq!(zl.delete_at(1).unwrap().unwrap_bytes(), b"b");
+        assert_eq!(zl.delete_at(2).unwrap().unwrap_bytes(), b"d");
+        assert!(zl.delete_at(2).is_err());
+        assert_eq!(values(&zl), [b"a", b"c"]);
+        // 删除表尾后，tail 指向新的最后一个 entry
+        zl.push_tail_string(b"e").unwrap();
+        assert_eq!(values(&zl), [b"a", b"c", b"e"]);
+        assert_eq!(zl.count_entry(), 3);
+        for _ in 0..3 {
+            zl.delete_at(0).unwrap();
+        }
+        assert_eq!(zl.bytes_size(), ZIPLIST_HEADER_SIZE);
+        assert_eq!(zl.tail_offset(), ZIPLIST_HEADER_SIZE);
+    }
+
+    #[test]
+    fn cascade_update() {
+        // 每个 entry 都是 253 字节：1 字节 prevrawlen + 2 字节编码 + 250 字节内容，
+        // 前面插入一个大 entry 后，它们的 prevrawlen 依次需要扩展为 5 字节
+        let mut zl = ZipList::new();
+        let mut items: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 250]).collect();
+        for item in &items {
+            zl.push_tail_string(item).unwrap();
+        }
+        let before = zl.bytes_size();
+        let big = vec![9u8; 300];
+        zl.insert_at(0, &big).unwrap();
+        items.insert(0, big.clone());
+        assert_eq!(zl.bytes_size(), before + (1 + 2 + 300) + 5 * 4);
+        assert_eq!(zl.values().map(|v| v.unwrap_bytes().to_vec()).collect::<Vec<_>>(), items);
+        assert_eq!(zl.count_entry(), items.len());
+        // 从表尾经 prevrawlen 往前走，应当能回到表头
+        let mut offset = zl.tail_offset();
+        for _ in 1..items.len() {
+            offset -= ZipEntry::parse(&zl.0[offset..]).prevrawlen;
+        }
+        assert_eq!(offset, ZIPLIST_HEADER_SIZE);
+
+        // 删除大 entry 后，已扩展的 prevrawlen 保持 5 字节，只有第一个 entry 需要修改
+        let before = zl.bytes_size();
+        zl.delete_at(0).unwrap();
+        items.remove(0);
+        assert_eq!(zl.bytes_size(), before - (1 + 2 + 300));
+        assert_eq!(zl.values().map(|v| v.unwrap_bytes().to_vec()).collect::<Vec<_>>(), items);
+
+        // 在中间插入、删除
+        zl.insert_at(3, &big).unwrap();
+        items.insert(3, big);
+        zl.delete_at(1).unwrap();
+        items.remove(1);
+        assert_eq!(zl.values().map(|v| v.unwrap_bytes().to_vec()).collect::<Vec<_>>(), items);
+        assert_eq!(zl.count_entry(), items.len());
+        assert_eq!(zl.pop_front().unwrap().unwrap_bytes(), &items[0][..]);
+    }
+
+    #[test]
+    fn deque() {
+        let mut zl = ZipList::new();
+        assert!(zl.pop_tail().is_none());
+        zl.push_front_int(7).unwrap();
+        zl.push_front_string(&[b'x'; 300]).unwrap();
+        zl.push_tail_string(b"tail").unwrap();
+        zl.push_front_int(-1000).unwrap();
+        assert_eq!(zl.get_entry_cnt(), 4);
+        assert_eq!(zl.count_entry(), 4);
+
+        assert_eq!(zl.pop_tail().unwrap().unwrap_bytes(), b"tail");
+        assert_eq!(zl.pop_tail().unwrap().unwrap_int(), 7);
+        // 弹出后仍然可以在表尾追加
+        zl.push_tail_int(100).unwrap();
+        assert_eq!(zl.pop_tail().unwrap().unwrap_int(), 100);
+        assert_eq!(zl.pop_tail().unwrap().unwrap_bytes(), &[b'x'; 300][..]);
+        assert_eq!(zl.pop_front().unwrap().unwrap_int(), -1000);
+        assert!(zl.pop_tail().is_none());
+        assert_eq!(zl.bytes_size(), ZIPLIST_HEADER_SIZE);
+        assert_eq!(zl.tail_offset(), ZIPLIST_HEADER_SIZE);
+    }
+
+    #[test]
+    fn get_and_find() {
+        let mut zl = ZipList::new();
+        assert!(zl.get(0).is_none());
+        assert!(zl.get(-1).is_none());
+        zl.push_tail_string(b"a").unwrap();
+        zl.push_tail_int(-300).unwrap();
+        zl.push_tail_string(&[b'b'; 300]).unwrap();
+        zl.push_tail_string(b"c").unwrap();
+
+        assert_eq!(zl.get(0).unwrap().unwrap_bytes(), b"a");
+        assert_eq!(zl.get(1).unwrap().unwrap_int(), -300);
+        assert_eq!(zl.get(-1).unwrap().unwrap_bytes(), b"c");
+        assert_eq!(zl.get(-2).unwrap().unwrap_bytes(), &[b'b'; 300][..]);
+        assert_eq!(zl.get(-4).unwrap().unwrap_bytes(), b"a");
+        assert!(zl.get(4).is_none());
+        assert!(zl.get(-5).is_none());
+
+        assert_eq!(zl.find(b"a"), Some(0));
+        assert_eq!(zl.find(b"-300"), Some(1));
+        assert_eq!(zl.find(&[b'b'; 300]), Some(2));
+        assert_eq!(zl.find(b"c"), Some(3));
+        assert_eq!(zl.find(b"-0300"), None);
+        assert_eq!(zl.find(b"d"), None);
+
+        zl.push_tail_string(b"a").unwrap();
+        let from_tail: Vec<usize> = zl.matches(b"a").enumerate().rev().filter(|(_, m)| *m).map(|(i, _)| i).collect();
+        assert_eq!(from_tail, [4, 0]);
+        assert_eq!(zl.replace_at(1, b"x").unwrap().unwrap_int(), -300);
+        assert_eq!(zl.replace_at(4, b"y").unwrap().unwrap_bytes(), b"a");
+        assert!(zl.replace_at(5, b"z").is_err());
+        assert_eq!(zl.find(b"x"), Some(1));
+        assert_eq!(zl.get(-1).unwrap().unwrap_bytes(), b"y");
+        assert_eq!(zl.get_entry_cnt(), 5);
+    }
+
+    #[test]
+    fn double_ended_iter() {
+        let mut zl = ZipList::new();
+        assert_eq!(zl.iter().len(), 0);
+        assert!(zl.iter().next_back().is_none());
+        let items: Vec<Vec<u8>> = vec![vec![b'a'; 3], vec![b'b'; 300], vec![b'c'; 10], vec![b'd'; 70000]];
+        for item in &items {
+            zl.push_tail_string(item).unwrap();
+        }
+        let values = zl.values();
+        assert_eq!(values.len(), 4);
+        let reversed: Vec<Vec<u8>> = values.rev().map(|v| v.unwrap_bytes().to_vec()).collect();
+        assert_eq!(reversed, items.iter().rev().cloned().collect::<Vec<_>>());
+
+        // 两端交替取，相遇后停止
+        let mut iter = zl.values();
+        assert_eq!(iter.next().unwrap().unwrap_bytes(), &items[0][..]);
+        assert_eq!(iter.next_back().unwrap().unwrap_bytes(), &items[3][..]);
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next_back().unwrap().unwrap_bytes(), &items[2][..]);
+        assert_eq!(iter.next().unwrap().unwrap_bytes(), &items[1][..]);
+        assert!(iter.next().is_none());
+        assert!(iter.next_back().is_none());
+    }
+
     #[test]
     fn move_bytes() {
         let mut v = Vec::new();
@@ -0,0 +1,216 @@
+//! maxmemory 与 key 的淘汰策略。
+//!
+//! 与 redis 一样，淘汰是近似的：每一轮从各个分片中抽样少量 key，按策略给每个 key 打分，
+//! 淘汰其中得分最高的那个，直到占用的内存回到 maxmemory 以下。内存占用本身也只是估计值，
+//! 见 [`crate::object::RedisObject::mem_usage`]。
+//!
+//! key 被访问时记录的时间来自粗粒度的 [`lru_clock`]，由后台线程定期更新，访问 key 时不需要读取系统时间。
+//! 时钟的精度为 [`LRU_CLOCK_RESOLUTION`]，同一个周期内访问的 key 在 LRU 看来一样新。
+
+use std::{fmt, str::FromStr, sync::{Once, atomic::{AtomicU64, Ordering}}, thread, time::Duration};
+
+use crate::db::now_ms;
+
+/// 每个分片每一轮抽样的 key 数
+pub(crate) const EVICTION_SAMPLES: usize = 5;
+
+/// [`lru_clock`] 的更新周期
+pub const LRU_CLOCK_RESOLUTION: Duration = Duration::from_millis(10);
+
+static LRU_CLOCK: AtomicU64 = AtomicU64::new(0);
+
+static LRU_CLOCK_STARTED: Once = Once::new();
+
+/// 粗粒度的当前时间，unix 时间戳（毫秒），落后于实际时间不超过 [`LRU_CLOCK_RESOLUTION`]。
+///
+/// 整个进程共用一个时钟，后台线程在第一次调用时启动，之后一直运行
+pub fn lru_clock() -> u64 {
+    LRU_CLOCK_STARTED.call_once(|| {
+        LRU_CLOCK.store(now_ms(), Ordering::Relaxed);
+        thread::Builder::new()
+            .name("lru-clock".into())
+            .spawn(|| loop {
+                thread::sleep(LRU_CLOCK_RESOLUTION);
+                LRU_CLOCK.store(now_ms(), Ordering::Relaxed);
+            })
+            .expect("failed to spawn the lru clock thread");
+    });
+    LRU_CLOCK.load(Ordering::Relaxed)
+}
+
+/// LFU 计数器的初始值，新 key 不至于刚写入就被淘汰
+const LFU_INIT_VAL: u8 = 5;
+
+/// LFU 计数器对数递增的因子，越大计数器增长越慢
+const LFU_LOG_FACTOR: f64 = 10.0;
+
+/// LFU 计数器每隔多久没有访问就减一（毫秒）
+const LFU_DECAY_MS: u64 = 60 * 1000;
+
+/// 内存超出 maxmemory 时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// 不淘汰，拒绝可能增加内存的命令
+    #[default]
+    NoEviction,
+    /// 在所有 key 中淘汰最久没有访问的
+    AllKeysLru,
+    /// 在所有 key 中淘汰访问频率最低的
+    AllKeysLfu,
+    /// 在所有 key 中随机淘汰
+    AllKeysRandom,
+    /// 在设置了过期时间的 key 中淘汰最快过期的
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu",
+            EvictionPolicy::AllKeysRandom => "allkeys-random",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<EvictionPolicy> {
+        let policy = match s.to_lowercase().as_str() {
+            "noeviction" => EvictionPolicy::NoEviction,
+            "allkeys-lru" => EvictionPolicy::AllKeysLru,
+            "allkeys-lfu" => EvictionPolicy::AllKeysLfu,
+            "allkeys-random" => EvictionPolicy::AllKeysRandom,
+            "volatile-ttl" => EvictionPolicy::VolatileTtl,
+            _ => return Err(format!("unknown maxmemory policy '{}'", s).into()),
+        };
+        Ok(policy)
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// 内存超出 maxmemory，且没有可以淘汰的 key
+#[derive(Debug)]
+pub struct OutOfMemory;
+
+impl fmt::Display for OutOfMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "OOM command not allowed when used memory > 'maxmemory'.".fmt(f)
+    }
+}
+
+impl std::error::Error for OutOfMemory {}
+
+/// key 的访问记录，供 LRU、LFU 打分。
+///
+/// LFU 计数器与 redis 一样只有 8 位：访问时按概率对数递增，长时间没有访问则随时间衰减
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Access {
+    /// 最近一次访问的时间，取自 [`lru_clock`]
+    last: u64,
+    counter: u8,
+}
+
+impl Access {
+    pub(crate) fn new(now: u64) -> Access {
+        Access { last: now, counter: LFU_INIT_VAL }
+    }
+
+    /// 记录一次访问
+    pub(crate) fn hit(&mut self, now: u64) {
+        let counter = self.freq(now);
+        // 计数器越大，递增的概率越小
+        let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+        self.counter = if counter < u8::MAX && rand::random::<f64>() < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+            counter + 1
+        } else {
+            counter
+        };
+        self.last = now;
+    }
+
+    /// 按策略打分，得分越高越应该被淘汰。`expire_at` 为 key 的过期时间
+    pub(crate) fn score(&self, policy: EvictionPolicy, expire_at: Option<u64>, now: u64) -> u64 {
+        match policy {
+            EvictionPolicy::NoEviction => 0,
+            EvictionPolicy::AllKeysLru => now.saturating_sub(self.last),
+            EvictionPolicy::AllKeysLfu => (u8::MAX - self.freq(now)) as u64,
+            EvictionPolicy::AllKeysRandom => rand::random(),
+            EvictionPolicy::VolatileTtl => expire_at.map_or(0, |when| u64::MAX - when),
+        }
+    }
+
+    /// 距离上次访问的时间（毫秒）
+    pub(crate) fn idle(&self, now: u64) -> u64 {
+        now.saturating_sub(self.last)
+    }
+
+    /// 按距离上次访问的时间衰减后的 LFU 计数器
+    pub(crate) fn freq(&self, now: u64) -> u8 {
+        let periods = now.saturating_sub(self.last) / LFU_DECAY_MS;
+        self.counter.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::db::now_ms;
+
+    use super::{Access, EvictionPolicy, LFU_DECAY_MS, LRU_CLOCK_RESOLUTION, lru_clock};
+
+    #[test]
+    fn policy_names() {
+        for policy in [
+            EvictionPolicy::NoEviction,
+            EvictionPolicy::AllKeysLru,
+            EvictionPolicy::AllKeysLfu,
+            EvictionPolicy::AllKeysRandom,
+            EvictionPolicy::VolatileTtl,
+        ] {
+            assert_eq!(policy.as_str().parse::<EvictionPolicy>().unwrap(), policy);
+        }
+        assert_eq!("ALLKEYS-LRU".parse::<EvictionPolicy>().unwrap(), EvictionPolicy::AllKeysLru);
+        assert!("volatile-lru".parse::<EvictionPolicy>().is_err());
+    }
+
+    #[test]
+    fn access_score() {
+        let now = 1_000_000;
+        let (mut hot, cold) = (Access::new(now), Access::new(now - 100));
+        for _ in 0..1000 {
+            hot.hit(now);
+        }
+        assert!(hot.counter > cold.counter);
+        assert!(hot.score(EvictionPolicy::AllKeysLru, None, now) < cold.score(EvictionPolicy::AllKeysLru, None, now));
+        assert!(hot.score(EvictionPolicy::AllKeysLfu, None, now) < cold.score(EvictionPolicy::AllKeysLfu, None, now));
+
+        // 长时间不访问，计数器衰减
+        let later = now + 3 * LFU_DECAY_MS;
+        assert_eq!(hot.freq(later), hot.counter - 3);
+        assert_eq!(hot.idle(later), 3 * LFU_DECAY_MS);
+
+        let ttl = |expire_at| cold.score(EvictionPolicy::VolatileTtl, expire_at, now);
+        assert!(ttl(Some(now + 10)) > ttl(Some(now + 1000)));
+        assert_eq!(ttl(None), 0);
+    }
+
+    #[test]
+    fn clock() {
+        let start = lru_clock();
+        assert!(start <= now_ms());
+        thread::sleep(LRU_CLOCK_RESOLUTION * 3);
+        let now = lru_clock();
+        assert!(now > start);
+        assert!(now_ms() - now <= LRU_CLOCK_RESOLUTION.as_millis() as u64 * 2);
+    }
+}
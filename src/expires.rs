@@ -0,0 +1,130 @@
+//! 设置了过期时间的 key 的索引，每个分片一份。
+//!
+//! 除了 key → 过期时间以外，还按过期时间排序保存一份：
+//! - 主动过期时只需从头取出过期时间不晚于当前时间的一段，不必扫描所有设置了过期时间的 key；
+//! - `volatile-ttl` 淘汰时可以直接取到最早过期的 key。
+//!
+//! 索引只记录过期时间，key 的值仍然保存在分片的 Dict 中，两者由分片一起维护。
+
+use std::collections::{BTreeSet, HashMap};
+
+use bytes::Bytes;
+
+/// 修改过期时间的条件，对应 `EXPIRE` 的 NX/XX/GT/LT 选项。没有过期时间视为永不过期
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExpireFlags {
+    /// 只在 key 没有过期时间时设置
+    pub nx: bool,
+    /// 只在 key 已有过期时间时设置
+    pub xx: bool,
+    /// 只在新的过期时间晚于原来的时设置
+    pub gt: bool,
+    /// 只在新的过期时间早于原来的时设置
+    pub lt: bool,
+}
+
+impl ExpireFlags {
+    /// 原来的过期时间为 current 时，是否允许修改为 when
+    pub fn allows(self, current: Option<u64>, when: u64) -> bool {
+        if (self.nx && current.is_some()) || (self.xx && current.is_none()) {
+            return false;
+        }
+        (!self.gt || current.is_some_and(|current| when > current)) && (!self.lt || current.is_none_or(|current| when < current))
+    }
+}
+
+#[derive(Default)]
+pub struct Expires {
+    /// key 的过期时间，unix 时间戳（毫秒）
+    when: HashMap<Bytes, u64>,
+    /// 按 (过期时间, key) 排序
+    order: BTreeSet<(u64, Bytes)>,
+}
+
+impl Expires {
+    /// 设置 key 的过期时间，已存在则覆盖
+    pub fn insert(&mut self, key: Bytes, when: u64) {
+        if let Some(old) = self.when.insert(key.clone(), when) {
+            if old == when {
+                return;
+            }
+            self.order.remove(&(old, key.clone()));
+        }
+        self.order.insert((when, key));
+    }
+
+    /// 移除 key 的过期时间，返回原来的过期时间
+    pub fn remove(&mut self, key: &[u8]) -> Option<u64> {
+        let (key, when) = self.when.remove_entry(key)?;
+        self.order.remove(&(when, key));
+        Some(when)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<u64> {
+        self.when.get(key).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.when.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.when.is_empty()
+    }
+
+    /// 过期时间不晚于 now 的 key，最早过期的在前。已过期的 key 都排在最前面，遍历到未过期的 key 即停止
+    pub fn expired(&self, now: u64) -> impl Iterator<Item = &Bytes> {
+        self.soonest().take_while(move |(when, _)| *when <= now).map(|(_, key)| key)
+    }
+
+    /// 按过期时间从早到晚遍历 (过期时间, key)
+    pub fn soonest(&self) -> impl Iterator<Item = (u64, &Bytes)> {
+        self.order.iter().map(|(when, key)| (*when, key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{ExpireFlags, Expires};
+
+    #[test]
+    fn ordered_by_time() {
+        let mut expires = Expires::default();
+        expires.insert(Bytes::from("c"), 30);
+        expires.insert(Bytes::from("a"), 10);
+        expires.insert(Bytes::from("b"), 20);
+        expires.insert(Bytes::from(""), 20);
+        // 覆盖原来的过期时间
+        expires.insert(Bytes::from("c"), 5);
+        assert_eq!(expires.len(), 4);
+        assert_eq!(expires.get(b"c"), Some(5));
+
+        let expired: Vec<_> = expires.expired(20).cloned().collect();
+        assert_eq!(expired, ["c", "a", "", "b"]);
+        assert_eq!(expires.expired(19).count(), 2);
+        assert_eq!(expires.expired(4).count(), 0);
+        assert_eq!(expires.soonest().next(), Some((5, &Bytes::from("c"))));
+
+        assert_eq!(expires.remove(b"a"), Some(10));
+        assert_eq!(expires.remove(b"a"), None);
+        assert_eq!(expires.expired(u64::MAX).count(), 3);
+        assert_eq!(expires.soonest().count(), 3);
+    }
+
+    #[test]
+    fn conditions() {
+        let flags = |nx, xx, gt, lt| ExpireFlags { nx, xx, gt, lt };
+        let all = [flags(false, false, false, false), flags(true, false, false, false), flags(false, true, false, false), flags(false, false, true, false), flags(false, false, false, true), flags(false, true, false, true)];
+        let cases = [
+            (None, [true, true, false, false, true, false]),
+            (Some(10), [true, false, true, true, false, false]),
+            (Some(30), [true, false, true, false, true, true]),
+        ];
+        for (current, expected) in cases {
+            assert_eq!(all.map(|flags| flags.allows(current, 20)), expected, "{:?}", current);
+        }
+        assert!(!all[3].allows(Some(20), 20) && !all[4].allows(Some(20), 20));
+    }
+}
@@ -1,18 +1,299 @@
-use std::{io::Cursor, num::TryFromIntError, string::FromUtf8Error, fmt};
+use std::{fmt::{self, Write}, io::Cursor, num::TryFromIntError, string::FromUtf8Error};
 
-use bytes::{Bytes, Buf};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
+/// RESP 数据帧。`Double` 及之后的类型是 RESP3 新增的，见 [`Protocol`]。
+///
+/// `Debug` 输出中 bulk string 只保留开头的 [`DEBUG_BULK_LEN`] 字节，避免日志被大的值刷屏
+#[derive(Clone, PartialEq)]
 pub enum Frame {
     Simple(String),
     Error(String),
-    Integer(u64),
+    Integer(i64),
     Bulk(Bytes),
+    /// RESP2 中为 `$-1\r\n`，RESP3 中为 `_\r\n`
     Null,
     Array(Vec<Frame>),
+    /// `,1.23\r\n`，包括 `inf`、`-inf`、`nan`
+    Double(f64),
+    /// `#t\r\n` 或者 `#f\r\n`
+    Boolean(bool),
+    /// `(12345678901234567890\r\n`，超出 i64 范围的整数，原样保存十进制表示
+    BigNumber(String),
+    /// `%2\r\n` 后跟 2 对 key、value
+    Map(Vec<(Frame, Frame)>),
+    /// `~2\r\n` 后跟 2 个元素
+    Set(Vec<Frame>),
+    /// `>2\r\n` 后跟 2 个元素，服务端主动推送的数据，如 pub/sub 消息
+    Push(Vec<Frame>),
+    /// `=15\r\ntxt:Some string\r\n`，format 为 3 个字节，如 `txt`、`mkd`
+    Verbatim { format: String, data: Bytes },
+}
+
+/// 连接使用的协议版本，通过 `HELLO` 命令协商，默认为 RESP2。
+///
+/// 使用 RESP2 时，RESP3 新增的类型会被转换成 RESP2 中最接近的类型再发送，见 [`Frame::to_resp2`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    /// 协议版本号，即 `HELLO` 的 protover 参数
+    pub fn version(&self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
+
+/// 解析 frame 时的限制。长度、元素个数都由对端声明，不加限制的话，恶意的输入可以让服务端
+/// 为一个永远收不全的 frame 缓存大量数据，或者用层层嵌套的数组耗尽栈空间
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProtocolLimits {
+    /// bulk string 的最大长度（字节），verbatim string 同样适用
+    pub max_bulk_len: usize,
+    /// 数组、集合、push 类型的最大元素个数，map 按 key、value 的对数计算
+    pub max_multibulk_len: usize,
+    /// 最多嵌套的层数，不包含其他聚合类型的数组为 1 层
+    pub max_nesting: usize,
+    /// 连接的读缓冲区（还没有解析的请求）的最大字节数，超出时断开连接，见 [`crate::connection::Connection`]
+    pub max_query_buffer: usize,
+}
+
+impl Default for ProtocolLimits {
+    /// 与 redis 的 `proto-max-bulk-len`、`client-query-buffer-limit` 以及多元素请求的上限相同
+    fn default() -> Self {
+        ProtocolLimits { max_bulk_len: 512 << 20, max_multibulk_len: i32::MAX as usize, max_nesting: 128, max_query_buffer: 1 << 30 }
+    }
+}
+
+impl ProtocolLimits {
+    fn check_bulk_len(&self, len: u64) -> Result<usize, Error> {
+        match usize::try_from(len) {
+            Ok(len) if len <= self.max_bulk_len => Ok(len),
+            _ => Err("protocol error; invalid bulk length".into()),
+        }
+    }
+
+    fn check_multibulk_len(&self, len: u64) -> Result<usize, Error> {
+        match usize::try_from(len) {
+            Ok(len) if len <= self.max_multibulk_len => Ok(len),
+            _ => Err("protocol error; invalid multibulk length".into()),
+        }
+    }
+
+    /// 进入第 depth 层聚合类型之前检查嵌套层数
+    fn check_nesting(&self, depth: usize) -> Result<(), Error> {
+        if depth > self.max_nesting {
+            return Err("protocol error; too many nested aggregates".into());
+        }
+        Ok(())
+    }
+}
+
+/// `Debug` 输出中 bulk string、verbatim string 最多显示的字节数
+pub const DEBUG_BULK_LEN: usize = 64;
+
+/// 超过 [`DEBUG_BULK_LEN`] 字节时只显示开头的部分
+struct BoundedBytes<'a>(&'a Bytes);
+
+impl fmt::Debug for BoundedBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.len() <= DEBUG_BULK_LEN {
+            return fmt::Debug::fmt(self.0, f);
+        }
+        write!(f, "{:?}... ({} more bytes)", self.0.slice(..DEBUG_BULK_LEN), self.0.len() - DEBUG_BULK_LEN)
+    }
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Frame::Simple(val) => f.debug_tuple("Simple").field(val).finish(),
+            Frame::Error(val) => f.debug_tuple("Error").field(val).finish(),
+            Frame::Integer(val) => f.debug_tuple("Integer").field(val).finish(),
+            Frame::Bulk(data) => f.debug_tuple("Bulk").field(&BoundedBytes(data)).finish(),
+            Frame::Null => f.write_str("Null"),
+            Frame::Array(items) => f.debug_tuple("Array").field(items).finish(),
+            Frame::Double(val) => f.debug_tuple("Double").field(val).finish(),
+            Frame::Boolean(val) => f.debug_tuple("Boolean").field(val).finish(),
+            Frame::BigNumber(val) => f.debug_tuple("BigNumber").field(val).finish(),
+            Frame::Map(entries) => f.debug_tuple("Map").field(entries).finish(),
+            Frame::Set(items) => f.debug_tuple("Set").field(items).finish(),
+            Frame::Push(items) => f.debug_tuple("Push").field(items).finish(),
+            Frame::Verbatim { format, data } => {
+                f.debug_struct("Verbatim").field("format", format).field("data", &BoundedBytes(data)).finish()
+            },
+        }
+    }
+}
+
+impl From<&str> for Frame {
+    /// 字符串转换为 bulk string，与客户端发送的命令参数一致
+    fn from(src: &str) -> Frame {
+        Frame::Bulk(Bytes::copy_from_slice(src.as_bytes()))
+    }
+}
+
+impl From<String> for Frame {
+    fn from(src: String) -> Frame {
+        Frame::Bulk(Bytes::from(src))
+    }
+}
+
+impl From<Bytes> for Frame {
+    fn from(src: Bytes) -> Frame {
+        Frame::Bulk(src)
+    }
+}
+
+impl From<i64> for Frame {
+    fn from(src: i64) -> Frame {
+        Frame::Integer(src)
+    }
+}
+
+impl From<Vec<Frame>> for Frame {
+    fn from(src: Vec<Frame>) -> Frame {
+        Frame::Array(src)
+    }
+}
+
+/// frame 的类型与期望的不符，由 `TryFrom<Frame>` 以及 `as_*` 系列方法返回
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnexpectedFrame {
+    /// 期望的类型
+    pub expected: &'static str,
+    /// 实际的类型，见 [`Frame::kind`]
+    pub actual: &'static str,
+}
+
+impl fmt::Display for UnexpectedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "protocol error; unexpected {} frame, expected {}", self.actual, self.expected)
+    }
+}
+
+impl std::error::Error for UnexpectedFrame {}
+
+impl UnexpectedFrame {
+    fn new(expected: &'static str, frame: &Frame) -> UnexpectedFrame {
+        UnexpectedFrame { expected, actual: frame.kind() }
+    }
+}
+
+/// bulk string 或者 simple string 的内容
+impl TryFrom<Frame> for Bytes {
+    type Error = UnexpectedFrame;
+
+    fn try_from(frame: Frame) -> Result<Bytes, UnexpectedFrame> {
+        match frame {
+            Frame::Bulk(data) => Ok(data),
+            Frame::Simple(val) => Ok(Bytes::from(val)),
+            frame => Err(UnexpectedFrame::new("bulk", &frame)),
+        }
+    }
+}
+
+/// bulk string 或者 simple string 的内容，不是合法 UTF-8 的字节会被替换
+impl TryFrom<Frame> for String {
+    type Error = UnexpectedFrame;
+
+    fn try_from(frame: Frame) -> Result<String, UnexpectedFrame> {
+        match frame {
+            Frame::Simple(val) => Ok(val),
+            Frame::Bulk(data) => Ok(String::from_utf8_lossy(&data).into_owned()),
+            frame => Err(UnexpectedFrame::new("string", &frame)),
+        }
+    }
+}
+
+impl TryFrom<Frame> for i64 {
+    type Error = UnexpectedFrame;
+
+    fn try_from(frame: Frame) -> Result<i64, UnexpectedFrame> {
+        frame.as_int()
+    }
+}
+
+/// 数组的元素，RESP3 的集合、push 类型同样适用
+impl TryFrom<Frame> for Vec<Frame> {
+    type Error = UnexpectedFrame;
+
+    fn try_from(frame: Frame) -> Result<Vec<Frame>, UnexpectedFrame> {
+        match frame {
+            Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => Ok(items),
+            frame => Err(UnexpectedFrame::new("array", &frame)),
+        }
+    }
 }
 
 impl Frame {
-    pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+    /// 由若干元素组成的数组，如 `Frame::array(["get", "key"])`
+    pub fn array<T: Into<Frame>>(items: impl IntoIterator<Item = T>) -> Frame {
+        Frame::Array(items.into_iter().map(Into::into).collect())
+    }
+
+    /// 类型名，用于错误信息
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Frame::Simple(_) => "simple",
+            Frame::Error(_) => "error",
+            Frame::Integer(_) => "integer",
+            Frame::Bulk(_) => "bulk",
+            Frame::Null => "null",
+            Frame::Array(_) => "array",
+            Frame::Double(_) => "double",
+            Frame::Boolean(_) => "boolean",
+            Frame::BigNumber(_) => "big number",
+            Frame::Map(_) => "map",
+            Frame::Set(_) => "set",
+            Frame::Push(_) => "push",
+            Frame::Verbatim { .. } => "verbatim",
+        }
+    }
+
+    /// bulk string 的内容
+    pub fn as_bulk(&self) -> Result<&Bytes, UnexpectedFrame> {
+        match self {
+            Frame::Bulk(data) => Ok(data),
+            frame => Err(UnexpectedFrame::new("bulk", frame)),
+        }
+    }
+
+    pub fn as_int(&self) -> Result<i64, UnexpectedFrame> {
+        match self {
+            Frame::Integer(val) => Ok(*val),
+            frame => Err(UnexpectedFrame::new("integer", frame)),
+        }
+    }
+
+    /// simple string 或者 UTF-8 编码的 bulk string 的内容
+    pub fn as_str(&self) -> Result<&str, UnexpectedFrame> {
+        match self {
+            Frame::Simple(val) => Ok(val),
+            Frame::Bulk(data) => std::str::from_utf8(data).map_err(|_| UnexpectedFrame::new("string", self)),
+            frame => Err(UnexpectedFrame::new("string", frame)),
+        }
+    }
+
+    /// 检查缓冲区中是否有一个完整的 frame，cursor 移到 frame 之后。超出 limits 时返回错误
+    pub fn check(src: &mut Cursor<&[u8]>, limits: &ProtocolLimits) -> Result<(), Error> {
+        Frame::check_nested(src, limits, 0, &mut 0)
+    }
+
+    /// 与 [`Frame::check`] 相同。数据不完整时，如果已经读到了 bulk string 的长度，needed 为 src 至少需要的字节数，
+    /// 否则不修改 needed。读取大的 bulk string 时可以据此一次分配足够的缓冲区
+    pub fn check_needed(src: &mut Cursor<&[u8]>, limits: &ProtocolLimits, needed: &mut usize) -> Result<(), Error> {
+        Frame::check_nested(src, limits, 0, needed)
+    }
+
+    /// depth 为当前 frame 所在的聚合类型的层数
+    fn check_nested(src: &mut Cursor<&[u8]>, limits: &ProtocolLimits, depth: usize, needed: &mut usize) -> Result<(), Error> {
         match get_u8(src)? {
             // +xxx\r\n 或者 -xxx\r\n
             b'+' | b'-' => {
@@ -26,34 +307,86 @@ impl Frame {
             // },
             // :123\r\n
             b':' => {
-                let _ = get_decimal(src)?;
+                let _ = get_int(src)?;
                 Ok(())
             },
             // `$123\r\n` 或者 `$-1\r\n'
             b'$' => {
                 if b'-' == peek_u8(src)? {
-                    // Skip '-1\r\n'
-                    skip(src, 4);
+                    // 与 parse 一致，必须是 `-1\r\n`
+                    if get_line(src)? != b"-1" {
+                        return Err("protocol error; invalid frame format".into());
+                    }
                 } else {
-                    let len: usize = get_decimal(src)?.try_into()?;
-                    // skip that number of bytes + 2 (\r\n).
-                    skip(src, len+2);
+                    let len = limits.check_bulk_len(get_decimal(src)?)?;
+                    *needed = src.position() as usize + len + 2;
+                    skip_data(src, len)?;
                 }
                 Ok(())
             },
             // `*12` 后端跟 12 个元素
-            b'*' => {
-                let len = get_decimal(src)?;
+            b'*' | b'~' | b'>' => {
+                let len = limits.check_multibulk_len(get_decimal(src)?)?;
+                limits.check_nesting(depth + 1)?;
                 for _ in 0..len {
-                    Frame::check(src)?;
+                    Frame::check_nested(src, limits, depth + 1, needed)?;
                 }
                 Ok(())
             }
+            // `%12` 后跟 12 对 key、value
+            b'%' => {
+                let len = limits.check_multibulk_len(get_decimal(src)?)?;
+                limits.check_nesting(depth + 1)?;
+                for _ in 0..len.saturating_mul(2) {
+                    Frame::check_nested(src, limits, depth + 1, needed)?;
+                }
+                Ok(())
+            }
+            // `_\r\n`
+            b'_' => {
+                get_line(src)?;
+                Ok(())
+            }
+            // `,1.23\r\n`
+            b',' => {
+                get_double(src)?;
+                Ok(())
+            }
+            // `#t\r\n`
+            b'#' => {
+                get_line(src)?;
+                Ok(())
+            }
+            // `(123\r\n`
+            b'(' => {
+                get_big_number(src)?;
+                Ok(())
+            }
+            // `=15\r\ntxt:xxx\r\n`
+            b'=' => {
+                let len = limits.check_bulk_len(get_decimal(src)?)?;
+                *needed = src.position() as usize + len + 2;
+                skip_data(src, len)?;
+                Ok(())
+            }
             actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
         }
     }
 
-    pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+    /// 从 src 的开头解析一个 frame，src 前进到 frame 之后；出错时 src 不变。
+    /// 通常先用 [`Frame::check`] 确认数据完整。超出 limits 时返回错误。
+    ///
+    /// bulk string 等二进制内容是 src 的切片，与 src 共享内存，不复制数据
+    pub fn parse(src: &mut Bytes, limits: &ProtocolLimits) -> Result<Frame, Error> {
+        let mut cursor = Cursor::new(&src[..]);
+        let frame = Frame::parse_nested(&mut cursor, src, limits, 0)?;
+        let len = cursor.position() as usize;
+        src.advance(len);
+        Ok(frame)
+    }
+
+    /// src 是 owner 的内容，用于切出二进制内容
+    fn parse_nested(src: &mut Cursor<&[u8]>, owner: &Bytes, limits: &ProtocolLimits, depth: usize) -> Result<Frame, Error> {
         match get_u8(src)? {
             b'+' => {
                 let line = get_line(src)?.to_vec();
@@ -67,7 +400,7 @@ impl Frame {
                 Ok(Frame::Error(string))
             }
             b':' => {
-                let n = get_decimal(src)?;
+                let n = get_int(src)?;
                 Ok(Frame::Integer(n))
             }
             b'$' => {
@@ -80,27 +413,227 @@ impl Frame {
                     Ok(Frame::Null)
                 } else {
                     // $lenxxxx\r\n，len 表示后续 xxx 的长度，为 bulk write 的数据
-                    let len = get_decimal(src)?.try_into()?;
+                    let len = limits.check_bulk_len(get_decimal(src)?)?;
                     let n = len+2; // 跳过 \r\n
                     if src.remaining() < n {
                         return Err(Error::Incomplete)
                     }
-                    let data = Bytes::copy_from_slice(&src.chunk()[..len]);
+                    let data = owner.slice_ref(&src.chunk()[..len]);
                     skip(src, n)?;
                     Ok(Frame::Bulk(data))
                 }
             }
-            b'*' => {
-                let len = get_decimal(src)? as usize;
-                let mut out = Vec::with_capacity(len);
+            b'*' => Ok(Frame::Array(parse_aggregate(src, owner, limits, depth + 1)?)),
+            b'~' => Ok(Frame::Set(parse_aggregate(src, owner, limits, depth + 1)?)),
+            b'>' => Ok(Frame::Push(parse_aggregate(src, owner, limits, depth + 1)?)),
+            b'%' => {
+                let len = limits.check_multibulk_len(get_decimal(src)?)?;
+                limits.check_nesting(depth + 1)?;
+                let mut out = Vec::with_capacity(capacity_hint(src, len.saturating_mul(2)) / 2);
                 for _ in 0..len {
-                    out.push(Frame::parse(src)?);
+                    let key = Frame::parse_nested(src, owner, limits, depth + 1)?;
+                    let value = Frame::parse_nested(src, owner, limits, depth + 1)?;
+                    out.push((key, value));
+                }
+                Ok(Frame::Map(out))
+            }
+            b'_' => {
+                if !get_line(src)?.is_empty() {
+                    return Err("protocol error; invalid frame format".into());
                 }
-                Ok(Frame::Array(out))
+                Ok(Frame::Null)
             }
-            _ => unimplemented!(),
+            b',' => Ok(Frame::Double(get_double(src)?)),
+            b'#' => match get_line(src)? {
+                b"t" => Ok(Frame::Boolean(true)),
+                b"f" => Ok(Frame::Boolean(false)),
+                _ => Err("protocol error; invalid frame format".into()),
+            },
+            b'(' => Ok(Frame::BigNumber(get_big_number(src)?)),
+            b'=' => {
+                let len = limits.check_bulk_len(get_decimal(src)?)?;
+                if src.remaining() < len + 2 {
+                    return Err(Error::Incomplete)
+                }
+                // 内容的前 4 个字节为 `xxx:`
+                let content = &src.chunk()[..len];
+                if len < 4 || content[3] != b':' {
+                    return Err("protocol error; invalid frame format".into());
+                }
+                let format = String::from_utf8(content[..3].to_vec())?;
+                let data = owner.slice_ref(&content[4..]);
+                skip(src, len+2)?;
+                Ok(Frame::Verbatim { format, data })
+            }
+            actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
+        }
+    }
+
+    /// 编码为 RESP 格式，追加到 dst 末尾，[`Frame::parse`] 可以原样解析回来。
+    ///
+    /// 所有类型按原样编码，`Null` 编码为 RESP3 的 `_\r\n`。需要发给 RESP2 的客户端时，
+    /// 使用 [`crate::connection::encode_frame`] 按连接的协议编码
+    pub fn encode(&self, dst: &mut BytesMut) {
+        self.encode_as(Protocol::Resp3, dst)
+    }
+
+    /// [`Frame::encode`] 编码后的字节数，可以用来预留空间
+    pub fn encoded_len(&self) -> usize {
+        // 类型字节，长度或内容，`\r\n`
+        let line = |len: usize| 1 + len + 2;
+        match self {
+            Frame::Simple(val) | Frame::Error(val) | Frame::BigNumber(val) => line(val.len()),
+            Frame::Integer(val) => line(decimal_len(*val)),
+            Frame::Null => 3,
+            Frame::Boolean(_) => 4,
+            Frame::Bulk(data) => line(decimal_len(data.len() as i64)) + data.len() + 2,
+            Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+                line(decimal_len(items.len() as i64)) + items.iter().map(Frame::encoded_len).sum::<usize>()
+            },
+            Frame::Map(entries) => {
+                let items: usize = entries.iter().map(|(key, value)| key.encoded_len() + value.encoded_len()).sum();
+                line(decimal_len(entries.len() as i64)) + items
+            },
+            Frame::Double(val) => line(format_double(*val).len()),
+            Frame::Verbatim { format, data } => {
+                let len = format.len() + 1 + data.len();
+                line(decimal_len(len as i64)) + len + 2
+            },
         }
     }
+
+    /// 按 protocol 编码，只影响 `Null` 的编码。RESP3 类型不做转换，调用方需要先用 [`Frame::to_resp2`] 转换
+    pub(crate) fn encode_as(&self, protocol: Protocol, dst: &mut BytesMut) {
+        match self {
+            Frame::Simple(val) => encode_line(b'+', val.as_bytes(), dst),
+            Frame::Error(val) => encode_line(b'-', val.as_bytes(), dst),
+            Frame::Integer(val) => {
+                dst.put_u8(b':');
+                encode_decimal(*val, dst);
+            }
+            Frame::Null => match protocol {
+                Protocol::Resp2 => dst.put_slice(b"$-1\r\n"),
+                Protocol::Resp3 => dst.put_slice(b"_\r\n"),
+            },
+            Frame::Bulk(data) => {
+                dst.put_u8(b'$');
+                encode_decimal(data.len() as i64, dst);
+                dst.put_slice(data);
+                dst.put_slice(b"\r\n");
+            }
+            Frame::Array(val) => encode_aggregate(b'*', val, protocol, dst),
+            Frame::Set(val) => encode_aggregate(b'~', val, protocol, dst),
+            Frame::Push(val) => encode_aggregate(b'>', val, protocol, dst),
+            Frame::Map(val) => {
+                dst.put_u8(b'%');
+                encode_decimal(val.len() as i64, dst);
+                for (key, value) in val {
+                    key.encode_as(protocol, dst);
+                    value.encode_as(protocol, dst);
+                }
+            }
+            Frame::Double(val) => encode_line(b',', format_double(*val).as_bytes(), dst),
+            Frame::Boolean(val) => dst.put_slice(if *val { b"#t\r\n" } else { b"#f\r\n" }),
+            Frame::BigNumber(val) => encode_line(b'(', val.as_bytes(), dst),
+            Frame::Verbatim { format, data } => {
+                dst.put_u8(b'=');
+                encode_decimal((format.len() + 1 + data.len()) as i64, dst);
+                dst.put_slice(format.as_bytes());
+                dst.put_u8(b':');
+                dst.put_slice(data);
+                dst.put_slice(b"\r\n");
+            }
+        }
+    }
+
+    /// [`Frame::encode`] 的结果转为可打印的字符串，`\r\n` 及其他不可打印的字节被转义，用于日志与调试
+    pub fn to_resp_string(&self) -> String {
+        let mut dst = BytesMut::with_capacity(self.encoded_len());
+        self.encode(&mut dst);
+        dst.escape_ascii().to_string()
+    }
+
+    /// 转换成 RESP2 可以表示的 frame：
+    /// - `Double`、`BigNumber`、`Verbatim` 转为 bulk string，与 redis 一致；
+    /// - `Boolean` 转为整数 1/0；
+    /// - `Map` 展开为 key、value 交替的数组，`Set`、`Push` 转为数组。
+    pub fn to_resp2(&self) -> Frame {
+        match self {
+            Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+                Frame::Array(items.iter().map(Frame::to_resp2).collect())
+            }
+            Frame::Map(entries) => Frame::Array(
+                entries
+                    .iter()
+                    .flat_map(|(key, value)| [key.to_resp2(), value.to_resp2()])
+                    .collect(),
+            ),
+            Frame::Double(val) => Frame::Bulk(Bytes::from(format_double(*val))),
+            Frame::Boolean(val) => Frame::Integer(*val as i64),
+            Frame::BigNumber(val) => Frame::Bulk(Bytes::from(val.clone())),
+            Frame::Verbatim { data, .. } => Frame::Bulk(data.clone()),
+            frame => frame.clone(),
+        }
+    }
+}
+
+/// RESP3 中浮点数的文本表示，无穷与 NaN 分别为 `inf`、`-inf`、`nan`
+pub(crate) fn format_double(val: f64) -> String {
+    if val.is_nan() {
+        "nan".to_string()
+    } else if val.is_infinite() {
+        if val > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        val.to_string()
+    }
+}
+
+/// 单行的类型：类型字节、内容、`\r\n`。
+/// 与 redis 一样把内容中的 `\r`、`\n` 替换为空格，否则包含用户输入的错误信息会被客户端解析成多个回复
+pub(crate) fn encode_line(kind: u8, line: &[u8], dst: &mut BytesMut) {
+    dst.put_u8(kind);
+    if line.iter().any(|&b| b == b'\r' || b == b'\n') {
+        dst.extend(line.iter().map(|&b| if b == b'\r' || b == b'\n' { b' ' } else { b }));
+    } else {
+        dst.put_slice(line);
+    }
+    dst.put_slice(b"\r\n");
+}
+
+/// 数组、集合、push 类型：类型字节、长度，然后依次是各个元素
+fn encode_aggregate(kind: u8, items: &[Frame], protocol: Protocol, dst: &mut BytesMut) {
+    dst.put_u8(kind);
+    encode_decimal(items.len() as i64, dst);
+    for item in items {
+        item.encode_as(protocol, dst);
+    }
+}
+
+pub(crate) fn encode_decimal(val: i64, dst: &mut BytesMut) {
+    write!(dst, "{}\r\n", val).unwrap();
+}
+
+/// 整数的十进制表示的长度，包括负号
+fn decimal_len(val: i64) -> usize {
+    let digits = val.unsigned_abs().checked_ilog10().map_or(1, |n| n as usize + 1);
+    digits + (val < 0) as usize
+}
+
+/// 解析数组、集合、push 类型共用的 `长度\r\n` 加元素列表，depth 为这个聚合类型所在的层数
+fn parse_aggregate(src: &mut Cursor<&[u8]>, owner: &Bytes, limits: &ProtocolLimits, depth: usize) -> Result<Vec<Frame>, Error> {
+    let len = limits.check_multibulk_len(get_decimal(src)?)?;
+    limits.check_nesting(depth)?;
+    let mut out = Vec::with_capacity(capacity_hint(src, len));
+    for _ in 0..len {
+        out.push(Frame::parse_nested(src, owner, limits, depth)?);
+    }
+    Ok(out)
+}
+
+/// 为声明了 len 个元素的聚合类型预留的容量。每个元素至少占 3 个字节（如 `_\r\n`），
+/// 按缓冲区中剩余的数据估计上限，声明的个数再大也不会预先分配超出数据量的内存
+fn capacity_hint(src: &Cursor<&[u8]>, len: usize) -> usize {
+    len.min(src.remaining() / 3)
 }
 
 #[derive(Debug)]
@@ -160,30 +693,418 @@ fn peek_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
     Ok(src.chunk()[0])
 }
 
+/// 取出一行，不包含行尾的 `\r\n`，cursor 移到下一行的开头。
+///
+/// 行内不允许出现单独的 `\r`；缓冲区以 `\r` 结尾时还不能确定后面是否为 `\n`，按数据不完整处理
 fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
     let start = src.position() as usize;
-    let ori_data = src.get_ref();
-    let end = ori_data.len() as usize;
-    for _i in start..end {
-        // if ori_data[i] == b'\r' && ori_data[i+1] == b'\n' {
-        //     src.set_position((i+2) as u64); // 跳过\r\n
-            // return Ok(&ori_data[start..i]);
-        // }
+    let ori_data: &'a [u8] = src.get_ref();
+    let end = ori_data.len();
+    for i in start..end {
+        if ori_data[i] != b'\r' {
+            continue;
+        }
+        return match ori_data.get(i+1) {
+            Some(b'\n') => {
+                src.set_position((i+2) as u64); // 跳过\r\n
+                Ok(&ori_data[start..i])
+            }
+            Some(_) => Err("protocol error; expected '\\n' after '\\r'".into()),
+            None => Err(Error::Incomplete),
+        };
     }
     Err(Error::Incomplete)
 }
 
-/// 解析出行首的数字
+/// 解析出行首的数字，用于长度。与 redis 一致，整行只能是十进制数字，不接受 `+` 号或者其他字符
 fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
     let line = get_line(src)?;
+    if line.is_empty() || !line.iter().all(u8::is_ascii_digit) {
+        return Err("protocol error; invalid frame format".into());
+    }
     use atoi::atoi;
     atoi::<u64>(line).ok_or_else(||  "protocol error; invalid frame format".into())
 }
 
+/// 解析出行首的有符号整数，用于 `:` 类型的 frame
+fn get_int(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
+    let line = get_line(src)?;
+    use atoi::atoi;
+    atoi::<i64>(line).ok_or_else(||  "protocol error; invalid frame format".into())
+}
+
+/// 解析 `,` 类型的浮点数
+fn get_double(src: &mut Cursor<&[u8]>) -> Result<f64, Error> {
+    let line = get_line(src)?;
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
+/// 解析 `(` 类型的大整数，只检查格式，不做转换
+fn get_big_number(src: &mut Cursor<&[u8]>) -> Result<String, Error> {
+    let line = get_line(src)?;
+    let digits = line.strip_prefix(b"-").or_else(|| line.strip_prefix(b"+")).unwrap_or(line);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return Err("protocol error; invalid frame format".into());
+    }
+    Ok(String::from_utf8(line.to_vec())?)
+}
+
+/// 跳过 len 字节的数据以及之后的 `\r\n`
+fn skip_data(src: &mut Cursor<&[u8]>, len: usize) -> Result<(), Error> {
+    skip(src, len)?;
+    match (get_u8(src)?, get_u8(src)?) {
+        (b'\r', b'\n') => Ok(()),
+        _ => Err("protocol error; invalid frame format".into()),
+    }
+}
+
 fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
     if src.remaining() < n {
         return Err(Error::Incomplete);
     }
     src.advance(n);
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bytes::{Bytes, BytesMut};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::{Error, Frame, ProtocolLimits};
+
+    /// 与 `Connection::parse_frame` 一样，先 check 再 parse，返回 frame 及消耗的字节数
+    fn parse(data: &[u8]) -> Result<(Frame, usize), Error> {
+        parse_with(data, &ProtocolLimits::default())
+    }
+
+    fn parse_with(data: &[u8], limits: &ProtocolLimits) -> Result<(Frame, usize), Error> {
+        let mut src = Cursor::new(data);
+        Frame::check(&mut src, limits)?;
+        let len = src.position() as usize;
+        let mut data = Bytes::copy_from_slice(data);
+        let frame = Frame::parse(&mut data, limits)?;
+        assert_eq!(data.len(), src.get_ref().len() - len);
+        Ok((frame, len))
+    }
+
+    fn bulk(data: &'static str) -> Frame {
+        Frame::Bulk(Bytes::from(data))
+    }
+
+    #[test]
+    fn simple_types() {
+        assert_eq!(parse(b"+OK\r\n").unwrap(), (Frame::Simple("OK".into()), 5));
+        assert_eq!(parse(b"+\r\n").unwrap().0, Frame::Simple("".into()));
+        assert_eq!(parse(b"-ERR oops\r\n").unwrap().0, Frame::Error("ERR oops".into()));
+        assert_eq!(parse(b":42\r\n").unwrap().0, Frame::Integer(42));
+        assert_eq!(parse(b":-7\r\n").unwrap().0, Frame::Integer(-7));
+        // 只消耗第一个 frame
+        assert_eq!(parse(b"+a\r\n+b\r\n").unwrap(), (Frame::Simple("a".into()), 4));
+    }
+
+    #[test]
+    fn bulk_strings() {
+        assert_eq!(parse(b"$5\r\nhello\r\n").unwrap(), (bulk("hello"), 11));
+        assert_eq!(parse(b"$0\r\n\r\n").unwrap().0, bulk(""));
+        // bulk string 是二进制安全的，可以包含 \r\n
+        assert_eq!(parse(b"$4\r\na\r\nb\r\n").unwrap().0, bulk("a\r\nb"));
+        assert_eq!(parse(b"$-1\r\n").unwrap().0, Frame::Null);
+        // check 与 parse 一样只接受 `$-1`
+        assert!(Frame::check(&mut Cursor::new(&b"$-12\r\n"[..]), &ProtocolLimits::default()).is_err());
+        // 长度与内容不符
+        assert!(matches!(parse(b"$2\r\nabc\r\n"), Err(Error::Other(_))));
+
+        // 读到长度之后就知道整个 frame 至少需要多少字节
+        let mut needed = 0;
+        let mut src = Cursor::new(&b"*2\r\n$3\r\nset\r\n$100\r\nab"[..]);
+        assert!(matches!(Frame::check_needed(&mut src, &ProtocolLimits::default(), &mut needed), Err(Error::Incomplete)));
+        assert_eq!(needed, 19 + 100 + 2);
+    }
+
+    #[test]
+    fn zero_copy() {
+        let mut src = Bytes::from_static(b"*2\r\n$3\r\nset\r\n=6\r\ntxt:hi\r\n+next\r\n");
+        let range = src.as_ptr_range();
+        let frame = Frame::parse(&mut src, &ProtocolLimits::default()).unwrap();
+        let Frame::Array(items) = frame else { panic!("not an array") };
+        // 内容直接引用 src 的内存
+        let (Frame::Bulk(set), Frame::Verbatim { data, .. }) = (&items[0], &items[1]) else { panic!("unexpected {:?}", items) };
+        assert_eq!((&set[..], &data[..]), (&b"set"[..], &b"hi"[..]));
+        assert!(range.contains(&set.as_ptr()) && range.contains(&data.as_ptr()));
+        assert_eq!(src, Bytes::from_static(b"+next\r\n"));
+    }
+
+    #[test]
+    fn nested_arrays() {
+        let expected = Frame::Array(vec![
+            bulk("set"),
+            Frame::Array(vec![Frame::Integer(1), Frame::Array(vec![])]),
+            Frame::Simple("x".into()),
+        ]);
+        let data = b"*3\r\n$3\r\nset\r\n*2\r\n:1\r\n*0\r\n+x\r\n";
+        assert_eq!(parse(data).unwrap(), (expected, data.len()));
+    }
+
+    #[test]
+    fn partial_frames() {
+        let data: &[u8] = b"*2\r\n$5\r\nhello\r\n:100\r\n";
+        // 任何一个前缀都是不完整的 frame
+        for end in 0..data.len() {
+            assert!(matches!(parse(&data[..end]), Err(Error::Incomplete)), "{:?}", &data[..end]);
+        }
+        assert!(parse(data).is_ok());
+    }
+
+    #[test]
+    fn cr_without_lf() {
+        assert!(matches!(parse(b"+OK\rX\r\n"), Err(Error::Other(_))));
+        assert!(matches!(parse(b":1\r2\r\n"), Err(Error::Other(_))));
+        // \r 之后还没收到数据，可能是不完整的
+        assert!(matches!(parse(b"+OK\r"), Err(Error::Incomplete)));
+        assert!(matches!(parse(b"?\r\n"), Err(Error::Other(_))));
+    }
+
+    #[test]
+    fn resp3_types() {
+        assert_eq!(parse(b"_\r\n").unwrap().0, Frame::Null);
+        assert_eq!(parse(b",3.25\r\n").unwrap().0, Frame::Double(3.25));
+        assert_eq!(parse(b",-inf\r\n").unwrap().0, Frame::Double(f64::NEG_INFINITY));
+        assert_eq!(parse(b"#t\r\n").unwrap().0, Frame::Boolean(true));
+        assert_eq!(parse(b"#f\r\n").unwrap().0, Frame::Boolean(false));
+        assert!(parse(b"#x\r\n").is_err());
+        assert_eq!(parse(b"(-12345678901234567890\r\n").unwrap().0, Frame::BigNumber("-12345678901234567890".into()));
+        assert!(parse(b"(12a\r\n").is_err());
+        assert_eq!(
+            parse(b"=6\r\ntxt:hi\r\n").unwrap().0,
+            Frame::Verbatim { format: "txt".into(), data: Bytes::from("hi") },
+        );
+        assert_eq!(
+            parse(b"%2\r\n+a\r\n:1\r\n+b\r\n~1\r\n#t\r\n").unwrap().0,
+            Frame::Map(vec![
+                (Frame::Simple("a".into()), Frame::Integer(1)),
+                (Frame::Simple("b".into()), Frame::Set(vec![Frame::Boolean(true)])),
+            ]),
+        );
+        assert_eq!(parse(b">1\r\n+msg\r\n").unwrap().0, Frame::Push(vec![Frame::Simple("msg".into())]));
+        assert!(matches!(parse(b"%1\r\n+a\r\n"), Err(Error::Incomplete)));
+    }
+
+    #[test]
+    fn limits() {
+        let limits = ProtocolLimits { max_bulk_len: 5, max_multibulk_len: 2, max_nesting: 2, ..ProtocolLimits::default() };
+        let rejected = |data: &[u8]| matches!(parse_with(data, &limits), Err(Error::Other(_)));
+        assert!(parse_with(b"$5\r\nhello\r\n", &limits).is_ok());
+        // 超出限制时不必等数据到齐
+        assert!(rejected(b"$6\r\n"));
+        assert!(rejected(b"=10\r\n"));
+        assert!(rejected(b"$99999999999999999999\r\n"));
+        assert!(parse_with(b"*2\r\n:1\r\n*1\r\n:2\r\n", &limits).is_ok());
+        assert!(rejected(b"*3\r\n"));
+        assert!(rejected(b"%3\r\n"));
+        assert!(rejected(b"*1\r\n*1\r\n*1\r\n"));
+        for data in [&b"*+1\r\n"[..], b"*1x\r\n", b"*\r\n", b"$+1\r\n"] {
+            assert!(rejected(data), "{:?}", data);
+        }
+        assert_eq!(parse_with(b"*0\r\n", &limits).unwrap().0, Frame::Array(vec![]));
+
+        // 默认的限制下，声明了大量元素的数组只是不完整，深层嵌套不会导致栈溢出
+        assert!(matches!(parse(b"*2147483647\r\n:1\r\n"), Err(Error::Incomplete)));
+        assert!(matches!(parse(b"*2147483648\r\n"), Err(Error::Other(_))));
+        assert!(matches!(parse(&b"*1\r\n".repeat(100_000)), Err(Error::Other(_))));
+
+        // 不经过 check 直接 parse 也不会按声明的个数预先分配内存
+        let mut src = Bytes::from_static(b"*100000000\r\n:1\r\n");
+        assert!(matches!(Frame::parse(&mut src, &ProtocolLimits::default()), Err(Error::Incomplete)));
+        assert_eq!(src.len(), 16);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let frames = [
+            Frame::Simple("OK".into()),
+            Frame::Error("ERR oops".into()),
+            Frame::Integer(0),
+            Frame::Integer(i64::MIN),
+            Frame::Integer(1234567),
+            bulk(""),
+            Frame::Bulk(Bytes::from(vec![b'x'; 1000])),
+            Frame::Null,
+            Frame::Array(vec![bulk("set"), Frame::Array(vec![]), Frame::Null]),
+            Frame::Double(-0.5),
+            Frame::Double(f64::INFINITY),
+            Frame::Boolean(false),
+            Frame::BigNumber("-12345678901234567890".into()),
+            Frame::Map(vec![(Frame::Simple("k".into()), Frame::Set(vec![Frame::Integer(-1)]))]),
+            Frame::Push(vec![bulk("message"), bulk("a\r\nb")]),
+            Frame::Verbatim { format: "txt".into(), data: Bytes::from("hi") },
+        ];
+        for frame in frames {
+            let mut dst = BytesMut::new();
+            frame.encode(&mut dst);
+            assert_eq!(dst.len(), frame.encoded_len(), "{:?}", frame);
+            assert_eq!(parse(&dst).unwrap(), (frame, dst.len()));
+        }
+        let mut dst = BytesMut::new();
+        Frame::Array(vec![bulk("get"), bulk("key")]).encode(&mut dst);
+        assert_eq!(&dst[..], b"*2\r\n$3\r\nget\r\n$3\r\nkey\r\n");
+    }
+
+    #[test]
+    fn encode_line_breaks() {
+        for (frame, encoded) in [
+            (Frame::Error("ERR unknown command 'a\r\n+OK\r\n'".into()), &b"-ERR unknown command 'a  +OK  '\r\n"[..]),
+            (Frame::Simple("a\nb\rc".into()), b"+a b c\r\n"),
+        ] {
+            let mut dst = BytesMut::new();
+            frame.encode(&mut dst);
+            assert_eq!(&dst[..], encoded);
+            assert_eq!(dst.len(), frame.encoded_len());
+            // 只解析出一个回复
+            assert_eq!(parse(&dst).unwrap().1, dst.len());
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Frame::from("a"), bulk("a"));
+        assert_eq!(Frame::from(Bytes::from("b")), bulk("b"));
+        assert_eq!(Frame::from(-1), Frame::Integer(-1));
+        assert_eq!(Frame::array(["get", "k"]), Frame::Array(vec![bulk("get"), bulk("k")]));
+        assert_eq!(Frame::array([Frame::from(1), "x".to_string().into()]), Frame::Array(vec![Frame::Integer(1), bulk("x")]));
+
+        assert_eq!(Bytes::try_from(Frame::Simple("OK".into())).unwrap(), "OK");
+        assert_eq!(String::try_from(bulk("v")).unwrap(), "v");
+        assert_eq!(i64::try_from(Frame::Integer(7)).unwrap(), 7);
+        assert_eq!(Vec::<Frame>::try_from(Frame::Set(vec![Frame::Null])).unwrap(), [Frame::Null]);
+        let err = i64::try_from(bulk("7")).unwrap_err();
+        assert_eq!((err.expected, err.actual), ("integer", "bulk"));
+        assert_eq!(err.to_string(), "protocol error; unexpected bulk frame, expected integer");
+
+        assert_eq!(bulk("v").as_bulk().unwrap(), "v");
+        assert!(Frame::Simple("v".into()).as_bulk().is_err());
+        assert_eq!(Frame::Simple("v".into()).as_str().unwrap(), "v");
+        assert!(Frame::Bulk(Bytes::from_static(b"\xff")).as_str().is_err());
+        assert_eq!(Frame::Null.as_int().unwrap_err().actual, "null");
+    }
+
+    #[test]
+    fn diagnostics() {
+        assert_eq!(format!("{:?}", Frame::array(["get", "k"])), r#"Array([Bulk(b"get"), Bulk(b"k")])"#);
+        assert_eq!(format!("{:?}", Frame::Null), "Null");
+        let long = Frame::Bulk(Bytes::from(vec![b'x'; 100]));
+        assert_eq!(format!("{:?}", long), format!("Bulk(b\"{}\"... (36 more bytes))", "x".repeat(64)));
+        let verbatim = Frame::Verbatim { format: "txt".into(), data: Bytes::from("hi") };
+        assert_eq!(format!("{:?}", verbatim), r#"Verbatim { format: "txt", data: b"hi" }"#);
+
+        assert_eq!(Frame::array(["get", "k"]).to_resp_string(), r"*2\r\n$3\r\nget\r\n$1\r\nk\r\n");
+        assert_eq!(Frame::Bulk(Bytes::from_static(b"\xff")).to_resp_string(), r"$1\r\n\xff\r\n");
+    }
+
+    #[test]
+    fn to_resp2() {
+        let frame = Frame::Map(vec![
+            (Frame::Simple("double".into()), Frame::Double(1.5)),
+            (Frame::Simple("set".into()), Frame::Set(vec![Frame::Boolean(true), Frame::Boolean(false)])),
+            (Frame::Simple("big".into()), Frame::BigNumber("12345678901234567890".into())),
+            (Frame::Simple("text".into()), Frame::Verbatim { format: "txt".into(), data: Bytes::from("hi") }),
+            (Frame::Simple("inf".into()), Frame::Double(f64::NEG_INFINITY)),
+        ]);
+        let expected = Frame::Array(vec![
+            Frame::Simple("double".into()),
+            Frame::Bulk(Bytes::from("1.5")),
+            Frame::Simple("set".into()),
+            Frame::Array(vec![Frame::Integer(1), Frame::Integer(0)]),
+            Frame::Simple("big".into()),
+            Frame::Bulk(Bytes::from("12345678901234567890")),
+            Frame::Simple("text".into()),
+            Frame::Bulk(Bytes::from("hi")),
+            Frame::Simple("inf".into()),
+            Frame::Bulk(Bytes::from("-inf")),
+        ]);
+        assert_eq!(frame.to_resp2(), expected);
+        assert_eq!(Frame::Null.to_resp2(), Frame::Null);
+    }
+
+    /// 随机生成一个可以原样编码、解析的 frame，depth 为剩余可以嵌套的层数。
+    /// NaN 与自身不相等，不生成
+    fn random_frame(rng: &mut StdRng, depth: usize) -> Frame {
+        // 单行类型的内容不能包含 \r、\n
+        let line = |rng: &mut StdRng| -> String { (0..rng.gen_range(0..8)).map(|_| rng.gen_range(' '..='~')).collect() };
+        let data = |rng: &mut StdRng| -> Bytes { (0..rng.gen_range(0..16)).map(|_| rng.gen::<u8>()).collect() };
+        let items = |rng: &mut StdRng| -> Vec<Frame> { (0..rng.gen_range(0..4)).map(|_| random_frame(rng, depth - 1)).collect() };
+        let kinds = if depth == 0 { 9 } else { 13 };
+        match rng.gen_range(0..kinds) {
+            0 => Frame::Simple(line(rng)),
+            1 => Frame::Error(line(rng)),
+            2 => Frame::Integer(rng.gen()),
+            3 => Frame::Bulk(data(rng)),
+            4 => Frame::Null,
+            5 => match rng.gen_range(0..4) {
+                0 => Frame::Double(f64::INFINITY),
+                1 => Frame::Double(f64::NEG_INFINITY),
+                2 => Frame::Double(rng.gen_range(-1e6..1e6)),
+                _ => Frame::Double(f64::from_bits(rng.gen::<u64>() & !(0x7ff << 52)) * rng.gen_range(-1e300..1e300)),
+            },
+            6 => Frame::Boolean(rng.gen()),
+            7 => {
+                let digits: String = (0..rng.gen_range(1..30)).map(|_| rng.gen_range('0'..='9')).collect();
+                Frame::BigNumber(if rng.gen() { format!("-{}", digits) } else { digits })
+            },
+            8 => Frame::Verbatim { format: (0..3).map(|_| rng.gen_range('a'..='z')).collect(), data: data(rng) },
+            9 => Frame::Array(items(rng)),
+            10 => Frame::Set(items(rng)),
+            11 => Frame::Push(items(rng)),
+            _ => Frame::Map((0..rng.gen_range(0..3)).map(|_| (random_frame(rng, depth - 1), random_frame(rng, depth - 1))).collect()),
+        }
+    }
+
+    #[test]
+    fn random_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0x7e5);
+        for _ in 0..2000 {
+            let frame = random_frame(&mut rng, 3);
+            let mut dst = BytesMut::new();
+            frame.encode(&mut dst);
+            assert_eq!(dst.len(), frame.encoded_len(), "{:?}", frame);
+            assert_eq!(parse(&dst).unwrap(), (frame.clone(), dst.len()));
+            // 截断后总是不完整，不会被解析成别的 frame
+            let end = rng.gen_range(0..dst.len());
+            assert!(matches!(parse(&dst[..end]), Err(Error::Incomplete)), "{:?} {:?}", frame, &dst[..end]);
+        }
+    }
+
+    #[test]
+    fn random_noise() {
+        let mut rng = StdRng::seed_from_u64(0xbad);
+        let limits = ProtocolLimits::default();
+        for _ in 0..20_000 {
+            // 一半是随机字节，一半是改动了一个字节的合法数据
+            let data: Vec<u8> = if rng.gen() {
+                (0..rng.gen_range(0..32)).map(|_| rng.gen_range(b' '..=b'~')).collect()
+            } else {
+                let mut dst = BytesMut::new();
+                random_frame(&mut rng, 2).encode(&mut dst);
+                let i = rng.gen_range(0..dst.len());
+                dst[i] = rng.gen();
+                dst.to_vec()
+            };
+            // 不 panic 即可。check 只检查格式，不检查内容（如 simple string 是否为 UTF-8），
+            // 通过时 parse 仍可能出错，但不会认为数据不完整，成功时消耗同样多的字节
+            let mut cursor = Cursor::new(&data[..]);
+            let checked = Frame::check(&mut cursor, &limits).map(|_| cursor.position() as usize);
+            let mut src = Bytes::from(data.clone());
+            let parsed = Frame::parse(&mut src, &limits).map(|_| data.len() - src.len());
+            if let Ok(len) = checked {
+                match parsed {
+                    Ok(parsed) => assert_eq!(parsed, len, "{:?}", data),
+                    Err(err) => assert!(!matches!(err, Error::Incomplete), "{:?}", data),
+                }
+            }
+        }
+    }
+}
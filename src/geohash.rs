@@ -0,0 +1,334 @@
+//! 经纬度的 geohash 编码与范围查找，对应 redis 的 `geohash.c`、`geohash_helper.c`。
+//!
+//! 经度、纬度各自按 26 位二分，交错成 52 位整数（纬度在偶数位，经度在奇数位），作为有序集合中 member 的分数。
+//! 52 位整数可以用 f64 精确表示，前缀相同的 geohash 位于同一个格子中，在有序集合中也是连续的一段分数。
+//!
+//! 按半径或矩形查找时，先根据范围的大小选择格子的精度，再取中心所在的格子及其周围 8 个格子，
+//! 逐个格子按分数范围取出 member，最后按实际距离过滤。
+
+/// geohash 的最大精度，经度、纬度各 26 位
+pub const STEP_MAX: u8 = 26;
+
+/// 可以编码的经纬度范围，纬度的范围与 web 墨卡托投影相同
+pub const LONG_MIN: f64 = -180.0;
+pub const LONG_MAX: f64 = 180.0;
+pub const LAT_MIN: f64 = -85.05112878;
+pub const LAT_MAX: f64 = 85.05112878;
+
+/// 计算距离时使用的地球半径（米），与 redis 相同
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+
+/// 墨卡托投影下赤道周长的一半（米），用来估算格子的精度
+const MERCATOR_MAX: f64 = 20037726.37;
+
+/// 一个格子：交错后的 bits 以及精度 step（经度、纬度各 step 位）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeoHash {
+    pub bits: u64,
+    pub step: u8,
+}
+
+/// 格子覆盖的经纬度范围
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Area {
+    pub long_min: f64,
+    pub long_max: f64,
+    pub lat_min: f64,
+    pub lat_max: f64,
+}
+
+/// 查找的范围，长度都以米为单位
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Radius(f64),
+    /// 宽（东西方向）与高（南北方向）
+    Box { width: f64, height: f64 },
+}
+
+/// 经纬度是否在可以编码的范围内
+pub fn is_valid(longitude: f64, latitude: f64) -> bool {
+    (LONG_MIN..=LONG_MAX).contains(&longitude) && (LAT_MIN..=LAT_MAX).contains(&latitude)
+}
+
+/// 以 step 的精度编码经纬度，调用前需要用 [`is_valid`] 检查范围
+pub fn encode(longitude: f64, latitude: f64, step: u8) -> GeoHash {
+    let cells = (1u64 << step) as f64;
+    let offset = |value: f64, min: f64, max: f64| (((value - min) / (max - min) * cells) as u64).min((1 << step) - 1) as u32;
+    let lat = offset(latitude, LAT_MIN, LAT_MAX);
+    let long = offset(longitude, LONG_MIN, LONG_MAX);
+    GeoHash { bits: spread(lat) | (spread(long) << 1), step }
+}
+
+/// 以最大精度编码，结果即有序集合中的分数
+pub fn encode_score(longitude: f64, latitude: f64) -> f64 {
+    encode(longitude, latitude, STEP_MAX).bits as f64
+}
+
+/// 分数对应的经纬度，取格子的中心
+pub fn decode_score(score: f64) -> (f64, f64) {
+    GeoHash { bits: score as u64, step: STEP_MAX }.area().center()
+}
+
+/// 两点之间的球面距离（米），haversine 公式
+pub fn distance(long1: f64, lat1: f64, long2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((long2.to_radians() - long1.to_radians()) / 2.0).sin();
+    2.0 * EARTH_RADIUS_IN_METERS * (u * u + lat1.cos() * lat2.cos() * v * v).sqrt().asin()
+}
+
+impl GeoHash {
+    /// 格子覆盖的范围
+    pub fn area(self) -> Area {
+        let cells = (1u64 << self.step) as f64;
+        let lat = squash(self.bits) as f64;
+        let long = squash(self.bits >> 1) as f64;
+        let scale = |offset: f64, min: f64, max: f64| min + offset / cells * (max - min);
+        Area {
+            long_min: scale(long, LONG_MIN, LONG_MAX),
+            long_max: scale(long + 1.0, LONG_MIN, LONG_MAX),
+            lat_min: scale(lat, LAT_MIN, LAT_MAX),
+            lat_max: scale(lat + 1.0, LAT_MIN, LAT_MAX),
+        }
+    }
+
+    /// 格子中的点在有序集合中的分数范围 [min, max)
+    pub fn score_range(self) -> (f64, f64) {
+        let shift = 2 * (STEP_MAX - self.step);
+        ((self.bits << shift) as f64, ((self.bits + 1) << shift) as f64)
+    }
+
+    /// 东西方向移动 d 个格子（d 为 1 或 -1），越过经度 180° 时回到另一侧
+    fn move_x(self, d: i8) -> GeoHash {
+        let x = self.bits & 0xaaaa_aaaa_aaaa_aaaa;
+        let y = self.bits & 0x5555_5555_5555_5555;
+        let zz = 0x5555_5555_5555_5555u64 >> (64 - 2 * self.step as u32);
+        let x = match d {
+            1.. => x.wrapping_add(zz + 1),
+            _ => (x | zz).wrapping_sub(zz + 1),
+        };
+        let x = x & (0xaaaa_aaaa_aaaa_aaaau64 >> (64 - 2 * self.step as u32));
+        GeoHash { bits: x | y, step: self.step }
+    }
+
+    /// 南北方向移动 d 个格子
+    fn move_y(self, d: i8) -> GeoHash {
+        let x = self.bits & 0xaaaa_aaaa_aaaa_aaaa;
+        let y = self.bits & 0x5555_5555_5555_5555;
+        let zz = 0xaaaa_aaaa_aaaa_aaaau64 >> (64 - 2 * self.step as u32);
+        let y = match d {
+            1.. => y.wrapping_add(zz + 1),
+            _ => (y | zz).wrapping_sub(zz + 1),
+        };
+        let y = y & (0x5555_5555_5555_5555u64 >> (64 - 2 * self.step as u32));
+        GeoHash { bits: x | y, step: self.step }
+    }
+}
+
+impl Area {
+    /// 格子的中心，超出可以编码的范围时截断
+    pub fn center(self) -> (f64, f64) {
+        let long = ((self.long_min + self.long_max) / 2.0).clamp(LONG_MIN, LONG_MAX);
+        let lat = ((self.lat_min + self.lat_max) / 2.0).clamp(LAT_MIN, LAT_MAX);
+        (long, lat)
+    }
+}
+
+impl Shape {
+    /// (longitude, latitude) 是否在以 center 为中心的范围内，在的话返回到中心的距离（米）
+    pub fn contains(self, center: (f64, f64), longitude: f64, latitude: f64) -> Option<f64> {
+        let (long, lat) = center;
+        match self {
+            Shape::Radius(radius) => Some(distance(long, lat, longitude, latitude)).filter(|&d| d <= radius),
+            Shape::Box { width, height } => {
+                // 先分别检查南北、东西方向的距离
+                if distance(long, lat, long, latitude) > height / 2.0 || distance(long, latitude, longitude, latitude) > width / 2.0 {
+                    return None;
+                }
+                Some(distance(long, lat, longitude, latitude))
+            },
+        }
+    }
+
+    /// 以 (longitude, latitude) 为中心时覆盖的经纬度范围
+    fn bounds(self, longitude: f64, latitude: f64) -> Area {
+        let (width, height) = match self {
+            Shape::Radius(radius) => (radius, radius),
+            Shape::Box { width, height } => (width / 2.0, height / 2.0),
+        };
+        let lat_delta = (height / EARTH_RADIUS_IN_METERS).to_degrees();
+        let long_delta = |lat: f64| (width / EARTH_RADIUS_IN_METERS / lat.to_radians().cos()).to_degrees();
+        // 越靠近极点，同样的距离跨过的经度越多，取离赤道较远的一边
+        let long_delta = match latitude < 0.0 {
+            true => long_delta(latitude - lat_delta),
+            false => long_delta(latitude + lat_delta),
+        };
+        Area {
+            long_min: longitude - long_delta,
+            long_max: longitude + long_delta,
+            lat_min: latitude - lat_delta,
+            lat_max: latitude + lat_delta,
+        }
+    }
+
+    /// 覆盖范围的外接圆半径（米）
+    fn radius(self) -> f64 {
+        match self {
+            Shape::Radius(radius) => radius,
+            Shape::Box { width, height } => (width / 2.0).hypot(height / 2.0),
+        }
+    }
+}
+
+/// 以 (longitude, latitude) 为中心查找 shape 时需要检查的格子，最多 9 个，已去重
+pub fn search_areas(longitude: f64, latitude: f64, shape: Shape) -> Vec<GeoHash> {
+    let bounds = shape.bounds(longitude, latitude);
+    let mut step = estimate_step(shape.radius(), latitude);
+    let mut center = encode(longitude, latitude, step);
+    // 精度过高时周围的格子可能覆盖不到整个范围，降低一级精度
+    let [_, north, south, east, west, ..] = neighbors(center).map(GeoHash::area);
+    if step > 1
+        && (north.lat_max < bounds.lat_max || south.lat_min > bounds.lat_min || east.long_max < bounds.long_max || west.long_min > bounds.long_min)
+    {
+        step -= 1;
+        center = encode(longitude, latitude, step);
+    }
+    let area = center.area();
+    let mut areas: Vec<Option<GeoHash>> = neighbors(center).into_iter().map(Some).collect();
+    // 中心格子已经覆盖某一侧的范围时，不需要检查那一侧的格子
+    if step >= 2 {
+        let (n, s, e, w, ne, nw, se, sw) = (1, 2, 3, 4, 5, 6, 7, 8);
+        let mut exclude = |cells: [usize; 3]| cells.iter().for_each(|&i| areas[i] = None);
+        if area.lat_min < bounds.lat_min {
+            exclude([s, sw, se]);
+        }
+        if area.lat_max > bounds.lat_max {
+            exclude([n, ne, nw]);
+        }
+        if area.long_min < bounds.long_min {
+            exclude([w, sw, nw]);
+        }
+        if area.long_max > bounds.long_max {
+            exclude([e, se, ne]);
+        }
+    }
+    let mut unique: Vec<GeoHash> = vec![];
+    for hash in areas.into_iter().flatten() {
+        if !unique.contains(&hash) {
+            unique.push(hash);
+        }
+    }
+    unique
+}
+
+/// 格子本身与周围的 8 个格子，顺序为 中心、北、南、东、西、东北、西北、东南、西南
+fn neighbors(hash: GeoHash) -> [GeoHash; 9] {
+    let (n, s) = (hash.move_y(1), hash.move_y(-1));
+    [hash, n, s, hash.move_x(1), hash.move_x(-1), n.move_x(1), n.move_x(-1), s.move_x(1), s.move_x(-1)]
+}
+
+/// 按范围的大小估算格子的精度：范围每扩大一倍，精度降低一级
+fn estimate_step(mut range: f64, latitude: f64) -> u8 {
+    if range == 0.0 {
+        return STEP_MAX;
+    }
+    let mut step: i32 = 1;
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    // 再降低两级，让周围的 8 个格子足以覆盖整个范围
+    step -= 2;
+    // 高纬度地区格子在东西方向上更窄
+    if !(-66.0..=66.0).contains(&latitude) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&latitude) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP_MAX as i32) as u8
+}
+
+/// 把 32 位整数的各位分散到 64 位整数的偶数位上
+fn spread(value: u32) -> u64 {
+    let mut x = value as u64;
+    x = (x | (x << 16)) & 0x0000_ffff_0000_ffff;
+    x = (x | (x << 8)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    x = (x | (x << 1)) & 0x5555_5555_5555_5555;
+    x
+}
+
+/// [`spread`] 的逆操作，取出偶数位
+fn squash(value: u64) -> u32 {
+    let mut x = value & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x >> 4)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x >> 8)) & 0x0000_ffff_0000_ffff;
+    x = (x | (x >> 16)) & 0x0000_0000_ffff_ffff;
+    x as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GeoHash, Shape, decode_score, distance, encode, encode_score, search_areas};
+
+    #[test]
+    fn encode_and_decode() {
+        // redis 文档中的例子：Palermo
+        let score = encode_score(13.361389, 38.115556);
+        assert_eq!(score, 3479099956230698.0);
+        let (long, lat) = decode_score(score);
+        assert!((long - 13.361389).abs() < 1e-5 && (lat - 38.115556).abs() < 1e-5);
+
+        let hash = encode(13.361389, 38.115556, 10);
+        let area = hash.area();
+        assert!(area.long_min <= 13.361389 && 13.361389 <= area.long_max);
+        assert!(area.lat_min <= 38.115556 && 38.115556 <= area.lat_max);
+        let (min, max) = hash.score_range();
+        assert!(min <= score && score < max);
+
+        // 范围的边界也可以编码
+        assert!(encode_score(180.0, 85.05112878) < (1u64 << 52) as f64);
+    }
+
+    #[test]
+    fn neighbors() {
+        let hash = encode(13.361389, 38.115556, 10);
+        let area = hash.area();
+        let (north, east) = (hash.move_y(1).area(), hash.move_x(1).area());
+        assert!((north.lat_min - area.lat_max).abs() < 1e-9 && north.long_min == area.long_min);
+        assert!((east.long_min - area.long_max).abs() < 1e-9 && east.lat_min == area.lat_min);
+        assert_eq!(hash.move_x(1).move_x(-1), hash);
+        assert_eq!(hash.move_y(-1).move_y(1), hash);
+        // 经度 180° 的东边是 -180°
+        let edge = GeoHash { bits: encode(179.99, 0.0, 5).bits, step: 5 };
+        assert_eq!(edge.move_x(1).area().long_min, -180.0);
+    }
+
+    #[test]
+    fn distances_and_areas() {
+        // Palermo 到 Catania
+        let d = distance(13.361389, 38.115556, 15.087269, 37.502669);
+        assert!((d - 166274.1516).abs() < 1.0, "{}", d);
+
+        let center = (15.0, 37.0);
+        assert!(Shape::Radius(200_000.0).contains(center, 13.361389, 38.115556).is_some());
+        assert!(Shape::Radius(100_000.0).contains(center, 13.361389, 38.115556).is_none());
+        assert!(Shape::Box { width: 400_000.0, height: 400_000.0 }.contains(center, 13.361389, 38.115556).is_some());
+        assert!(Shape::Box { width: 400_000.0, height: 100_000.0 }.contains(center, 13.361389, 38.115556).is_none());
+
+        // 要找的点一定位于某个格子中
+        let areas = search_areas(15.0, 37.0, Shape::Radius(200_000.0));
+        assert!(!areas.is_empty() && areas.len() <= 9);
+        for (long, lat) in [(13.361389, 38.115556), (15.087269, 37.502669)] {
+            let score = encode_score(long, lat);
+            assert!(areas.iter().any(|area| {
+                let (min, max) = area.score_range();
+                min <= score && score < max
+            }));
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! glob 风格的模式匹配，对应 redis 的 `stringmatchlen`，用于 KEYS 等命令。
+//!
+//! key 是二进制安全的，所以模式和字符串都按字节处理：
+//! - `?` 匹配任意一个字节，`*` 匹配任意多个字节；
+//! - `[abc]` 匹配其中任意一个，`[^abc]` 匹配不在其中的，`[a-z]` 匹配范围内的；
+//! - `\` 转义下一个字节。
+
+/// 字符串是否完整匹配模式。
+///
+/// 遇到 `*` 时记下它之后的模式位置与当前的字符串位置，之后不匹配时只回到最近的一个 `*`，
+/// 让它多匹配一个字节后重试。更早的 `*` 不需要再回溯：它们能多匹配的部分最近的 `*` 同样能匹配。
+/// 耗时最多为 O(模式长度 × 字符串长度)，`*a*a*a...b` 这样的模式也不会导致指数级的回溯
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // 最近的 `*` 之后的模式位置，以及它当前匹配到的字符串位置
+    let mut star: Option<(usize, usize)> = None;
+    loop {
+        if p < pattern.len() {
+            if pattern[p] == b'*' {
+                // 连续的 * 等价于一个
+                while p < pattern.len() && pattern[p] == b'*' {
+                    p += 1;
+                }
+                if p == pattern.len() {
+                    return true;
+                }
+                star = Some((p, s));
+                continue;
+            }
+            if let Some(next) = string.get(s).and_then(|&c| match_one(pattern, p, c)) {
+                p = next;
+                s += 1;
+                continue;
+            }
+        } else if s == string.len() {
+            return true;
+        }
+        // 不匹配，让最近的 * 多匹配一个字节
+        match star {
+            Some((star_p, star_s)) if star_s < string.len() => {
+                star = Some((star_p, star_s + 1));
+                p = star_p;
+                s = star_s + 1;
+            },
+            _ => return false,
+        }
+    }
+}
+
+/// 用 pattern[p..] 开头的一个元素（`?`、字符类或者一个字节）匹配字节 c，匹配时返回这个元素之后的模式位置
+fn match_one(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+    match pattern[p] {
+        b'?' => Some(p + 1),
+        b'[' => {
+            let (matched, rest) = match_class(&pattern[p + 1..], c);
+            matched.then(|| pattern.len() - rest.len())
+        },
+        // 转义的字节按字面匹配，末尾单独的 \ 匹配它自己
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        x => (x == c).then_some(p + 1),
+    }
+}
+
+/// 匹配 `[...]` 字符类，pattern 从 `[` 之后开始。返回是否匹配，以及 `]` 之后剩余的模式。
+/// 缺少 `]` 时与 redis 一样，把模式末尾当作字符类的结束
+fn match_class(mut pattern: &[u8], c: u8) -> (bool, &[u8]) {
+    let negate = pattern.first() == Some(&b'^');
+    if negate {
+        pattern = &pattern[1..];
+    }
+    let mut matched = false;
+    loop {
+        match pattern {
+            [] => break,
+            [b']', rest @ ..] => {
+                pattern = rest;
+                break;
+            },
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == c;
+                pattern = rest;
+            },
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                let (lo, hi) = if start <= end { (*start, *end) } else { (*end, *start) };
+                matched |= (lo..=hi).contains(&c);
+                pattern = rest;
+            },
+            [x, rest @ ..] => {
+                matched |= *x == c;
+                pattern = rest;
+            },
+        }
+    }
+    (matched != negate, pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::matches;
+
+    #[test]
+    fn patterns() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*", "", true),
+            ("*", "anything", true),
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("h*llo", "hllo", true),
+            ("h*llo", "heeeello", true),
+            ("h*llo", "hello!", false),
+            ("h[ae]llo", "hallo", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[^e]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("h[b-a]llo", "hallo", true),
+            ("h[a-b]llo", "hcllo", false),
+            ("h\\*llo", "h*llo", true),
+            ("h\\*llo", "hello", false),
+            ("[\\]]", "]", true),
+            ("user:*:name", "user:42:name", true),
+            ("user:*:name", "user:42:age", false),
+            ("a*b*c", "aXbYc", true),
+            ("a*b*c", "aXbY", false),
+            ("[abc", "a", true),
+            ("", "", true),
+            ("", "a", false),
+        ];
+        for (pattern, string, expected) in cases {
+            assert_eq!(matches(pattern.as_bytes(), string.as_bytes()), *expected, "{} {}", pattern, string);
+        }
+        // 二进制安全
+        assert!(matches(b"\xff*", b"\xff\x00\x01"));
+    }
+
+    #[test]
+    fn edge_cases() {
+        let cases: &[(&[u8], &[u8], bool)] = &[
+            // 末尾单独的 \ 匹配它自己
+            (b"a\\", b"a\\", true),
+            (b"a\\", b"a", false),
+            (b"\\?", b"?", true),
+            (b"\\?", b"a", false),
+            (b"\\\\", b"\\", true),
+            // 字符类中的转义、范围和 ^
+            (b"[\\-]", b"-", true),
+            (b"[a\\-z]", b"b", false),
+            (b"[a\\-z]", b"-", true),
+            (b"[a-]", b"-", true),
+            (b"[^]", b"x", true),
+            (b"[]", b"x", false),
+            (b"[^a-c]", b"b", false),
+            (b"[^a-c]", b"d", true),
+            (b"[*?]", b"?", true),
+            (b"[*?]", b"a", false),
+            (b"[\x00-\x01]", b"\x01", true),
+            (b"[\x80-\xff]", b"\x7f", false),
+            // * 可以匹配空串，也可以跨过与后续模式相同的字节
+            (b"**a**", b"a", true),
+            (b"*a*b", b"aab", true),
+            (b"*?", b"", false),
+            (b"?*", b"x", true),
+        ];
+        for (pattern, string, expected) in cases {
+            assert_eq!(matches(pattern, string), *expected, "{} {}", pattern.escape_ascii(), string.escape_ascii());
+        }
+    }
+
+    #[test]
+    fn no_exponential_backtracking() {
+        let started = Instant::now();
+        assert!(!matches(b"*a*a*a*a*a*a*a*a*a*b", &[b'a'; 40]));
+        assert!(matches(b"*a*a*a*a*a*a*a*a*a*b", &[&[b'a'; 40][..], b"b"].concat()));
+        // 很多个 * 也不会栈溢出
+        assert!(matches(&b"a*".repeat(10_000), &[b'a'; 10_000]));
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+    }
+
+    /// 不含字符类的参考实现，按位置做动态规划
+    fn reference(pattern: &[u8], string: &[u8]) -> bool {
+        enum Token { Star, Any, Byte(u8) }
+        let mut tokens = vec![];
+        let mut i = 0;
+        while i < pattern.len() {
+            tokens.push(match pattern[i] {
+                b'*' => Token::Star,
+                b'?' => Token::Any,
+                b'\\' if i + 1 < pattern.len() => {
+                    i += 1;
+                    Token::Byte(pattern[i])
+                },
+                c => Token::Byte(c),
+            });
+            i += 1;
+        }
+        // matched[j]: 已处理的 token 能否匹配 string[..j]
+        let mut matched = vec![false; string.len() + 1];
+        matched[0] = true;
+        for token in &tokens {
+            let mut next = vec![false; string.len() + 1];
+            for j in 0..=string.len() {
+                next[j] = match token {
+                    Token::Star => matched[j] || (j > 0 && next[j - 1]),
+                    Token::Any => j > 0 && matched[j - 1],
+                    Token::Byte(c) => j > 0 && matched[j - 1] && string[j - 1] == *c,
+                };
+            }
+            matched = next;
+        }
+        matched[string.len()]
+    }
+
+    #[test]
+    fn adversarial() {
+        let kb = |unit: &[u8]| unit.repeat(1024 / unit.len());
+        let cases: Vec<(Vec<u8>, Vec<u8>)> = vec![
+            // 连续的 *x，最后一个字节不匹配
+            (b"*x".repeat(100), [&kb(b"x")[..], b"y"].concat()),
+            ([&b"*x".repeat(100)[..], b"y"].concat(), kb(b"x")),
+            ([&b"*x".repeat(100)[..], b"y"].concat(), [&kb(b"x")[..], b"y"].concat()),
+            (b"*?".repeat(200), kb(b"a")),
+            ([&b"a*".repeat(500)[..], b"b"].concat(), kb(b"a")),
+            (b"x*".repeat(600), kb(b"x")),
+            (kb(b"\\*"), kb(b"*")),
+        ];
+        for (pattern, string) in &cases {
+            let expected = reference(pattern, string);
+            let started = Instant::now();
+            assert_eq!(matches(pattern, string), expected, "{}", pattern.escape_ascii());
+            assert!(started.elapsed() < Duration::from_millis(100), "{} {:?}", pattern.escape_ascii(), started.elapsed());
+        }
+
+        // 字符类与 * 混合
+        let class_cases: &[(Vec<u8>, Vec<u8>, bool)] = &[
+            ([&b"*[ab]".repeat(50)[..], b"c"].concat(), kb(b"ab"), false),
+            ([&b"*[ab]".repeat(50)[..], b"c"].concat(), [&kb(b"ab")[..], b"c"].concat(), true),
+            ([&b"*[^c]".repeat(50)[..], b"*[c-d]"].concat(), kb(b"ab"), false),
+            (b"*[a-z]?".repeat(100), kb(b"q"), true),
+            ([&b"[ab]*".repeat(300)[..], b"[c"].concat(), kb(b"ba"), false),
+        ];
+        for (pattern, string, expected) in class_cases {
+            let started = Instant::now();
+            assert_eq!(matches(pattern, string), *expected, "{}", pattern.escape_ascii());
+            assert!(started.elapsed() < Duration::from_millis(100), "{} {:?}", pattern.escape_ascii(), started.elapsed());
+        }
+    }
+
+    /// 所有长度不超过 n、由 alphabet 组成的字符串
+    fn all_strings(alphabet: &[u8], n: usize) -> Vec<Vec<u8>> {
+        let mut all = vec![vec![]];
+        let mut last = vec![vec![]];
+        for _ in 0..n {
+            last = last.iter().flat_map(|s: &Vec<u8>| alphabet.iter().map(move |&c| [&s[..], &[c]].concat())).collect();
+            all.extend(last.iter().cloned());
+        }
+        all
+    }
+
+    #[test]
+    fn exhaustive() {
+        let strings = all_strings(b"ab*\\", 4);
+        for pattern in all_strings(b"ab*?\\", 4) {
+            for string in &strings {
+                assert_eq!(
+                    matches(&pattern, string),
+                    reference(&pattern, string),
+                    "{} {}", pattern.escape_ascii(), string.escape_ascii(),
+                );
+            }
+        }
+        // 字符类逐个字节与集合比较
+        let check = |pattern: &[u8], expected: fn(u8) -> bool| {
+            for c in 0..=255u8 {
+                assert_eq!(matches(pattern, &[c]), expected(c), "{} {}", pattern.escape_ascii(), c);
+            }
+        };
+        check(b"[a-cx]", |c| (b'a'..=b'c').contains(&c) || c == b'x');
+        check(b"[^a-cx]", |c| !((b'a'..=b'c').contains(&c) || c == b'x'));
+        check(b"[z-a]", |c| c.is_ascii_lowercase());
+        check(b"[\\]\\^]", |c| c == b']' || c == b'^');
+        check(b"[\x00-\x1f\x7f-\xff]", |c| !(0x20..0x7f).contains(&c));
+    }
+}
@@ -0,0 +1,92 @@
+//! 惰性释放（lazy free），对应 redis 的 `lazyfree.c`。
+//!
+//! 释放元素很多的哈希表、跳表需要逐个回收节点，耗时与元素个数成正比。`UNLINK`、`FLUSHALL ASYNC`
+//! 在锁内只把值从键空间中摘下来，之后交给后台线程释放，不会因为 drop 大的值而长时间占用分片锁或者阻塞事件循环。
+//!
+//! 后台线程在第一次需要时启动，[`LazyFree`] 被回收后自动退出。
+
+use std::{sync::{Arc, OnceLock, atomic::{AtomicUsize, Ordering}, mpsc}, thread};
+
+/// 释放的工作量超过这个值时才交给后台线程，小的值直接释放更快，与 redis 的 `LAZYFREE_THRESHOLD` 相同
+pub const LAZYFREE_THRESHOLD: usize = 64;
+
+type Garbage = Box<dyn Send>;
+
+/// 后台释放线程的句柄
+#[derive(Default)]
+pub struct LazyFree {
+    tx: OnceLock<mpsc::Sender<Garbage>>,
+    /// 已经交给后台线程但还没释放完的对象数
+    pending: Arc<AtomicUsize>,
+}
+
+impl LazyFree {
+    /// 在后台线程中释放 value
+    pub fn free<T: Send + 'static>(&self, value: T) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        let tx = self.tx.get_or_init(|| {
+            let (tx, rx) = mpsc::channel::<Garbage>();
+            let pending = self.pending.clone();
+            thread::Builder::new()
+                .name("lazyfree".into())
+                .spawn(move || {
+                    for garbage in rx {
+                        drop(garbage);
+                        pending.fetch_sub(1, Ordering::Relaxed);
+                    }
+                })
+                .expect("failed to spawn the lazyfree thread");
+            tx
+        });
+        // 线程只会在 Sender 被回收后退出，这里发送不会失败
+        tx.send(Box::new(value)).unwrap();
+    }
+
+    /// 工作量为 effort 的值：超过 [`LAZYFREE_THRESHOLD`] 时交给后台线程，否则直接释放
+    pub fn free_if_large<T: Send + 'static>(&self, value: T, effort: usize) {
+        if effort > LAZYFREE_THRESHOLD {
+            self.free(value);
+        }
+    }
+
+    /// 还没释放完的对象数，即 `INFO` 中的 `lazyfree_pending_objects`
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, thread, time::Duration};
+
+    use super::LazyFree;
+
+    /// drop 时记录下来
+    struct Tracked(Arc<AtomicBool>);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn free_in_background() {
+        let lazyfree = LazyFree::default();
+        let dropped = Arc::new(AtomicBool::new(false));
+        lazyfree.free(Tracked(dropped.clone()));
+        for _ in 0..100 {
+            if lazyfree.pending() == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(lazyfree.pending(), 0);
+        assert!(dropped.load(Ordering::Relaxed));
+
+        // 小的值直接释放
+        let dropped = Arc::new(AtomicBool::new(false));
+        lazyfree.free_if_large(Tracked(dropped.clone()), 1);
+        assert!(dropped.load(Ordering::Relaxed));
+    }
+}
@@ -1,7 +1,30 @@
+pub mod acl;
+pub mod blocking;
+pub mod client;
+pub mod clients;
+pub mod cluster;
+pub mod config;
 pub mod cmd;
 pub mod connection;
 pub mod frame;
 pub mod ds;
+pub mod db;
+pub mod evict;
+pub mod expires;
+pub mod lazyfree;
+pub mod types;
+pub mod object;
+pub mod server;
+pub mod shutdown;
+pub mod stats;
+pub mod glob;
+pub mod geohash;
+pub mod pubsub;
+pub mod rdb;
+pub mod replication;
+pub mod transaction;
+pub mod script;
+pub mod slowlog;
 
 // dyn trait 是 DST，使用时会导致不可编辑，所以用 Box 包裹
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
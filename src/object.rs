@@ -0,0 +1,392 @@
+//! redis 对象：键空间中保存的值。
+//!
+//! 与 redis 一样，同一种类型可以有多种底层编码：元素少且短时使用紧凑的 ziplist（集合为 intset），
+//! 超过阈值后转换成通用的数据结构，转换是单向的。
+//!
+//! | 类型 | 编码 |
+//! | --- | --- |
+//! | string | int / raw (SDS) |
+//! | list | ziplist → linkedlist |
+//! | hash | ziplist → hashtable (Dict) |
+//! | set | intset → hashtable (Dict) |
+//! | zset | ziplist → skiplist (Skiplist + Dict) |
+//! | stream | stream (BTreeMap + listpack) |
+//!
+//! redis 7 之后小对象改用 listpack，[`crate::ds::listpack`] 已经实现，但切换编码会改变快照格式，暂时沿用 ziplist。
+
+use std::{fmt, sync::OnceLock};
+
+use bytes::Bytes;
+
+use crate::{ds::{MemSize, perfstr::{SmartString, sds::SDS}}, types::{Hash, List, Set, Stream, ZSet}};
+
+pub(crate) use crate::ds::perfstr::parse_int;
+
+pub enum RedisObject {
+    String(SDS),
+    /// 可以表示为 i64 的字符串直接保存整数，INCR 等命令可以原地修改
+    Int(i64),
+    List(List),
+    Hash(Hash),
+    Set(Set),
+    ZSet(ZSet),
+    Stream(Stream),
+}
+
+/// 对象的底层编码，即 `OBJECT ENCODING` 的返回值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectEncoding {
+    Int,
+    Raw,
+    ZipList,
+    IntSet,
+    LinkedList,
+    HashTable,
+    SkipList,
+    Stream,
+}
+
+impl ObjectEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectEncoding::Int => "int",
+            ObjectEncoding::Raw => "raw",
+            ObjectEncoding::ZipList => "ziplist",
+            ObjectEncoding::IntSet => "intset",
+            ObjectEncoding::LinkedList => "linkedlist",
+            ObjectEncoding::HashTable => "hashtable",
+            ObjectEncoding::SkipList => "skiplist",
+            ObjectEncoding::Stream => "stream",
+        }
+    }
+}
+
+impl fmt::Display for ObjectEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// ziplist 编码的阈值，超过任一阈值就转换为通用编码。
+/// 对应 redis 的 `*-max-ziplist-entries` 与 `*-max-ziplist-value`
+#[derive(Debug, Clone, Copy)]
+pub struct ZipLimits {
+    /// ziplist 最多保存的元素个数。hash/zset 中一对 field value（member score）算一个
+    pub max_entries: usize,
+    /// ziplist 中单个元素的最大字节数
+    pub max_value: usize,
+}
+
+impl Default for ZipLimits {
+    fn default() -> Self {
+        Self { max_entries: 128, max_value: 64 }
+    }
+}
+
+impl ZipLimits {
+    /// 再加入 value 后（共 len 个元素）是否超过阈值
+    pub(crate) fn exceeded_by(&self, len: usize, value: &[u8]) -> bool {
+        len > self.max_entries || value.len() > self.max_value
+    }
+}
+
+/// intset 编码的阈值，元素个数超过后转换为 Dict。对应 redis 的 `set-max-intset-entries`
+#[derive(Debug, Clone, Copy)]
+pub struct IntSetLimits {
+    pub max_entries: usize,
+}
+
+impl Default for IntSetLimits {
+    fn default() -> Self {
+        Self { max_entries: 512 }
+    }
+}
+
+/// 各类型的编码阈值
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EncodingLimits {
+    pub list: ZipLimits,
+    pub hash: ZipLimits,
+    pub set: IntSetLimits,
+    pub zset: ZipLimits,
+}
+
+impl RedisObject {
+    /// 字符串对象，value 是整数的规范表示时使用 int 编码
+    pub fn string(value: &[u8]) -> Self {
+        match parse_int(value) {
+            Some(n) => RedisObject::Int(n),
+            None => RedisObject::String(SDS::new(value)),
+        }
+    }
+
+    /// 类型名，即 `TYPE` 命令的返回值
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RedisObject::String(_) | RedisObject::Int(_) => "string",
+            RedisObject::List(_) => "list",
+            RedisObject::Hash(_) => "hash",
+            RedisObject::Set(_) => "set",
+            RedisObject::ZSet(_) => "zset",
+            RedisObject::Stream(_) => "stream",
+        }
+    }
+
+    /// 深拷贝，`COPY` 使用。保持原来的编码，拷贝与原对象不共享任何数据
+    pub fn clone_value(&self) -> RedisObject {
+        match self {
+            RedisObject::String(s) => RedisObject::String(s.clone()),
+            RedisObject::Int(n) => RedisObject::Int(*n),
+            RedisObject::List(list) => RedisObject::List(list.clone()),
+            RedisObject::Hash(hash) => RedisObject::Hash(hash.clone()),
+            RedisObject::Set(set) => RedisObject::Set(set.clone()),
+            RedisObject::ZSet(zset) => RedisObject::ZSet(zset.clone()),
+            RedisObject::Stream(stream) => RedisObject::Stream(stream.clone()),
+        }
+    }
+
+    /// 当前的底层编码
+    pub fn encoding(&self) -> ObjectEncoding {
+        match self {
+            RedisObject::String(_) => ObjectEncoding::Raw,
+            RedisObject::Int(_) => ObjectEncoding::Int,
+            RedisObject::List(list) => list.encoding(),
+            RedisObject::Hash(hash) => hash.encoding(),
+            RedisObject::Set(set) => set.encoding(),
+            RedisObject::ZSet(zset) => zset.encoding(),
+            RedisObject::Stream(stream) => stream.encoding(),
+        }
+    }
+
+    /// 是否对应 redis 中的共享对象。redis 中 [0, [`SHARED_INTEGERS`]) 内的整数共用同一个对象，
+    /// 这里虽然每个 key 各自保存整数，`OBJECT REFCOUNT` 仍然按共享对象回复
+    pub fn is_shared(&self) -> bool {
+        matches!(self, RedisObject::Int(n) if (0..SHARED_INTEGERS).contains(n))
+    }
+
+    /// 值在堆上占用内存的估计值（字节），不包括 `RedisObject` 本身，用于 maxmemory。
+    /// 聚合类型只抽样少量元素估计，不保证精确
+    pub fn mem_usage(&self) -> usize {
+        match self {
+            RedisObject::String(sds) => sds.alloc_size(),
+            RedisObject::Int(_) => 0,
+            RedisObject::List(list) => list.mem_usage(),
+            RedisObject::Hash(hash) => hash.mem_usage(),
+            RedisObject::Set(set) => set.mem_usage(),
+            RedisObject::ZSet(zset) => zset.mem_usage(),
+            RedisObject::Stream(stream) => stream.mem_usage(),
+        }
+    }
+
+    /// 释放时需要回收的分配次数的估计值，用于决定是否交给后台释放，对应 redis 的 `lazyfreeGetFreeEffort`。
+    /// 紧凑编码只有一块内存，其他编码每个元素至少一个节点
+    pub fn free_effort(&self) -> usize {
+        match self {
+            RedisObject::List(list @ List::LinkedList(_)) => list.len(),
+            RedisObject::Hash(hash @ Hash::HashTable(_)) => hash.len(),
+            RedisObject::Set(set @ Set::HashTable(_)) => set.len(),
+            RedisObject::ZSet(zset @ ZSet::SkipList { .. }) => zset.len(),
+            RedisObject::Stream(stream) => stream.len(),
+            _ => 1,
+        }
+    }
+
+    /// 字符串对象的内容，其他类型返回 `None`
+    pub fn as_bytes(&self) -> Option<Bytes> {
+        match self {
+            RedisObject::String(sds) => Some(Bytes::copy_from_slice(sds.val())),
+            RedisObject::Int(n) => Some(int_to_bytes(*n)),
+            _ => None,
+        }
+    }
+
+    /// 是否为字符串对象，不论编码
+    pub fn is_string(&self) -> bool {
+        matches!(self, RedisObject::String(_) | RedisObject::Int(_))
+    }
+
+    /// 以整数的形式访问字符串对象，用于 INCR 等命令原地修改。
+    ///
+    /// raw 编码的内容是整数时会先转换为 int 编码；不是字符串或者内容不是整数时返回 `None`
+    pub fn as_int_mut(&mut self) -> Option<&mut i64> {
+        if let RedisObject::String(sds) = self {
+            *self = RedisObject::Int(sds.parse_int()?);
+        }
+        match self {
+            RedisObject::Int(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// 以 SDS 的形式访问字符串对象，用于 APPEND、SETRANGE 等按字节修改的命令。
+    ///
+    /// int 编码会先转换回 raw 编码，与 redis 一样修改后不再尝试转换为 int；不是字符串时返回 `None`
+    pub fn as_sds_mut(&mut self) -> Option<&mut SDS> {
+        if let RedisObject::Int(n) = self {
+            *self = RedisObject::String(SDS::new(n.to_string().as_bytes()));
+        }
+        match self {
+            RedisObject::String(sds) => Some(sds),
+            _ => None,
+        }
+    }
+}
+
+/// 值在堆上实际占用的内存，会遍历全部元素，见 [`MemSize`]
+impl MemSize for RedisObject {
+    fn mem_size(&self) -> usize {
+        match self {
+            RedisObject::String(sds) => sds.mem_size(),
+            RedisObject::Int(_) => 0,
+            RedisObject::List(list) => list.mem_size(),
+            RedisObject::Hash(hash) => hash.mem_size(),
+            RedisObject::Set(set) => set.mem_size(),
+            RedisObject::ZSet(zset) => zset.mem_size(),
+            RedisObject::Stream(stream) => stream.mem_size(),
+        }
+    }
+}
+
+/// 集合类的值类型。命令通过类型参数声明接受的类型，由 [`crate::db::Db::with_typed`]、
+/// [`crate::db::Db::update_typed`] 统一检查，类型不符时回复 WRONGTYPE 错误。
+///
+/// 字符串有 int、raw 两种编码，由 [`RedisObject::as_bytes`] 等方法处理，不实现这个 trait
+pub trait Typed: Sized {
+    /// 值是这个类型时返回它的可变引用
+    fn downcast_mut(object: &mut RedisObject) -> Option<&mut Self>;
+
+    /// 值是这个类型时取出，否则返回 `None`，值被丢弃
+    fn from_object(object: RedisObject) -> Option<Self>;
+
+    fn into_object(self) -> RedisObject;
+}
+
+macro_rules! impl_typed {
+    ($($variant:ident),*) => {
+        $(
+            impl Typed for $variant {
+                fn downcast_mut(object: &mut RedisObject) -> Option<&mut Self> {
+                    match object {
+                        RedisObject::$variant(value) => Some(value),
+                        _ => None,
+                    }
+                }
+
+                fn from_object(object: RedisObject) -> Option<Self> {
+                    match object {
+                        RedisObject::$variant(value) => Some(value),
+                        _ => None,
+                    }
+                }
+
+                fn into_object(self) -> RedisObject {
+                    RedisObject::$variant(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_typed!(List, Hash, Set, ZSet, Stream);
+
+/// 共享整数的个数，对应 redis 的 `OBJ_SHARED_INTEGERS`
+pub const SHARED_INTEGERS: i64 = 10000;
+
+/// 整数的字符串形式。
+///
+/// 与 redis 的 `shared.integers` 类似，[0, [`SHARED_INTEGERS`]) 内的整数使用预先生成的共享 `Bytes`，
+/// clone 只增加引用计数，读取计数器、intset 与 ziplist 中的小整数时不需要分配内存
+pub fn int_to_bytes(n: i64) -> Bytes {
+    static SHARED: OnceLock<Vec<Bytes>> = OnceLock::new();
+    match usize::try_from(n) {
+        Ok(index) if n < SHARED_INTEGERS => {
+            let shared = SHARED.get_or_init(|| (0..SHARED_INTEGERS).map(|i| Bytes::from(i.to_string())).collect());
+            shared[index].clone()
+        },
+        _ => Bytes::from(n.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{ds::perfstr::SmartString, types::{Hash, List, Set, ZSet}};
+
+    use super::{IntSetLimits, ObjectEncoding, RedisObject, SHARED_INTEGERS, ZipLimits, int_to_bytes, parse_int};
+
+    #[test]
+    fn encoding_transitions() {
+        let limits = ZipLimits { max_entries: 2, max_value: 8 };
+
+        let mut list = List::new();
+        list.push_back(Bytes::from("a"), &limits);
+        list.push_back(Bytes::from("b"), &limits);
+        let mut obj = RedisObject::List(list);
+        assert_eq!(obj.encoding(), ObjectEncoding::ZipList);
+        if let RedisObject::List(list) = &mut obj {
+            list.push_back(Bytes::from("c"), &limits);
+        }
+        assert_eq!(obj.encoding(), ObjectEncoding::LinkedList);
+
+        let mut hash = Hash::new();
+        hash.insert(Bytes::from("f"), Bytes::from("v"), &limits);
+        assert_eq!(hash.encoding(), ObjectEncoding::ZipList);
+        hash.insert(Bytes::from("f"), Bytes::from("too long value"), &limits);
+        assert_eq!(hash.encoding(), ObjectEncoding::HashTable);
+
+        let mut set = Set::new();
+        set.insert(b"1", &IntSetLimits::default());
+        assert_eq!(set.encoding(), ObjectEncoding::IntSet);
+        set.insert(b"a", &IntSetLimits::default());
+        assert_eq!(set.encoding(), ObjectEncoding::HashTable);
+
+        let mut zset = ZSet::new();
+        zset.insert(Bytes::from("a"), 1f64, &limits);
+        zset.insert(Bytes::from("b"), 2f64, &limits);
+        assert_eq!(zset.encoding(), ObjectEncoding::ZipList);
+        zset.insert(Bytes::from("c"), 3f64, &limits);
+        assert_eq!(zset.encoding(), ObjectEncoding::SkipList);
+
+        assert_eq!(RedisObject::string(b"v").encoding(), ObjectEncoding::Raw);
+        assert_eq!(RedisObject::string(b"v").as_bytes(), Some(Bytes::from("v")));
+    }
+
+    #[test]
+    fn int_encoding() {
+        for (value, int) in [("0", Some(0)), ("-12", Some(-12)), ("9223372036854775807", Some(i64::MAX)), ("-9223372036854775808", Some(i64::MIN))] {
+            assert_eq!(parse_int(value.as_bytes()), int);
+            let obj = RedisObject::string(value.as_bytes());
+            assert_eq!(obj.encoding(), ObjectEncoding::Int);
+            assert_eq!(obj.as_bytes(), Some(Bytes::from(value)));
+        }
+        for value in ["", "-", "-0", "007", "+1", " 1", "1 ", "1.0", "9223372036854775808"] {
+            assert_eq!(parse_int(value.as_bytes()), None, "{}", value);
+            assert_eq!(RedisObject::string(value.as_bytes()).encoding(), ObjectEncoding::Raw);
+        }
+
+        let mut obj = RedisObject::Int(41);
+        *obj.as_int_mut().unwrap() += 1;
+        assert_eq!(obj.as_bytes(), Some(Bytes::from("42")));
+        assert!(RedisObject::string(b"abc").as_int_mut().is_none());
+        assert!(RedisObject::List(List::new()).as_int_mut().is_none());
+
+        obj.as_sds_mut().unwrap().append(b"0");
+        assert_eq!(obj.encoding(), ObjectEncoding::Raw);
+        assert_eq!(obj.as_bytes(), Some(Bytes::from("420")));
+        assert_eq!(obj.as_int_mut().copied(), Some(420));
+        assert_eq!(obj.encoding(), ObjectEncoding::Int);
+        assert!(RedisObject::List(List::new()).as_sds_mut().is_none());
+    }
+
+    #[test]
+    fn shared_integers() {
+        // 共享的整数指向同一块内存
+        assert_eq!(int_to_bytes(42).as_ptr(), int_to_bytes(42).as_ptr());
+        assert_eq!(RedisObject::Int(42).as_bytes().unwrap().as_ptr(), int_to_bytes(42).as_ptr());
+        for n in [0, 42, SHARED_INTEGERS - 1, SHARED_INTEGERS, -1, i64::MIN, i64::MAX] {
+            assert_eq!(int_to_bytes(n), Bytes::from(n.to_string()));
+        }
+        assert_ne!(int_to_bytes(SHARED_INTEGERS).as_ptr(), int_to_bytes(SHARED_INTEGERS).as_ptr());
+    }
+}
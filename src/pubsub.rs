@@ -0,0 +1,340 @@
+//! 发布订阅。
+//!
+//! - [`Registry`] 保存在 `Db` 中，为每个频道（模式）维护一个 `broadcast` 通道，PUBLISH 时向其发送消息；
+//! - [`Subscriber`] 是每个连接的订阅状态。每个订阅对应一个转发任务，把 broadcast 收到的消息转成
+//!   push frame 后放进连接自己的 mpsc 队列，连接的处理循环从队列中取出消息写回客户端。
+//!
+//! 分片频道（`SSUBSCRIBE`、`SPUBLISH`）与普通频道分开保存，不会匹配模式订阅。
+//! 集群模式下分片频道与 key 一样属于某个槽，只能在负责这个槽的节点上订阅、发布，见 [`crate::cluster`]。
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use tokio::{sync::{broadcast, mpsc}, task::JoinHandle};
+
+use crate::{db::Db, frame::Frame, glob};
+
+/// 每个频道缓存的消息数，订阅者处理不过来时会丢弃最早的消息
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// 订阅的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelKind {
+    /// `SUBSCRIBE`
+    Channel,
+    /// `PSUBSCRIBE`
+    Pattern,
+    /// `SSUBSCRIBE`
+    Shard,
+}
+
+/// 频道及模式的注册表
+#[derive(Default)]
+pub(crate) struct Registry {
+    channels: HashMap<Bytes, broadcast::Sender<Bytes>>,
+    /// 模式订阅者除了消息本身，还需要知道消息来自哪个频道
+    patterns: HashMap<Bytes, broadcast::Sender<(Bytes, Bytes)>>,
+    /// 分片频道
+    shard_channels: HashMap<Bytes, broadcast::Sender<Bytes>>,
+}
+
+impl Registry {
+    pub(crate) fn subscribe(&mut self, channel: Bytes) -> broadcast::Receiver<Bytes> {
+        self.channels
+            .entry(channel)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    pub(crate) fn ssubscribe(&mut self, channel: Bytes) -> broadcast::Receiver<Bytes> {
+        self.shard_channels
+            .entry(channel)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    pub(crate) fn psubscribe(&mut self, pattern: Bytes) -> broadcast::Receiver<(Bytes, Bytes)> {
+        self.patterns
+            .entry(pattern)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// 向频道以及匹配频道的模式发送消息，返回收到消息的订阅者数量。
+    /// 顺带清理已经没有订阅者的频道和模式
+    pub(crate) fn publish(&mut self, channel: &Bytes, message: Bytes) -> usize {
+        self.channels.retain(|_, tx| tx.receiver_count() > 0);
+        self.patterns.retain(|_, tx| tx.receiver_count() > 0);
+        let mut receivers = self.channels
+            .get(channel)
+            .and_then(|tx| tx.send(message.clone()).ok())
+            .unwrap_or(0);
+        for (pattern, tx) in &self.patterns {
+            if glob::matches(pattern, channel) {
+                receivers += tx.send((channel.clone(), message.clone())).unwrap_or(0);
+            }
+        }
+        receivers
+    }
+
+    /// 向分片频道发送消息，返回收到消息的订阅者数量
+    pub(crate) fn spublish(&mut self, channel: &Bytes, message: Bytes) -> usize {
+        self.shard_channels.retain(|_, tx| tx.receiver_count() > 0);
+        self.shard_channels
+            .get(channel)
+            .and_then(|tx| tx.send(message).ok())
+            .unwrap_or(0)
+    }
+}
+
+/// 一个连接的订阅状态。
+///
+/// 订阅了至少一个频道或模式后，RESP2 连接进入订阅模式，只能执行订阅相关的命令和 PING
+pub struct Subscriber {
+    db: Db,
+    /// 订阅的频道及其转发任务
+    channels: HashMap<Bytes, JoinHandle<()>>,
+    /// 订阅的模式及其转发任务
+    patterns: HashMap<Bytes, JoinHandle<()>>,
+    /// 订阅的分片频道及其转发任务
+    shard_channels: HashMap<Bytes, JoinHandle<()>>,
+    messages_tx: mpsc::Sender<Frame>,
+    messages_rx: mpsc::Receiver<Frame>,
+}
+
+impl Subscriber {
+    pub fn new(db: Db) -> Subscriber {
+        let (messages_tx, messages_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        Subscriber { db, channels: HashMap::new(), patterns: HashMap::new(), shard_channels: HashMap::new(), messages_tx, messages_rx }
+    }
+
+    /// 订阅的频道、模式与分片频道总数
+    pub fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len() + self.shard_channels.len()
+    }
+
+    /// 是否处于订阅模式
+    pub fn is_active(&self) -> bool {
+        self.count() > 0
+    }
+
+    /// 订阅频道，每个频道回复一条 `subscribe` 确认。需要在 tokio 运行时中调用
+    pub fn subscribe(&mut self, channels: Vec<Bytes>) -> Vec<Frame> {
+        channels
+            .into_iter()
+            .map(|channel| {
+                if !self.channels.contains_key(&channel) {
+                    let rx = self.db.subscribe(channel.clone());
+                    let name = channel.clone();
+                    let task = forward(rx, self.messages_tx.clone(), move |message| {
+                        push_frame("message", vec![Frame::Bulk(name.clone()), Frame::Bulk(message)])
+                    });
+                    self.channels.insert(channel.clone(), task);
+                }
+                self.confirm("subscribe", Frame::Bulk(channel), ChannelKind::Channel)
+            })
+            .collect()
+    }
+
+    /// 订阅分片频道，每个频道回复一条 `ssubscribe` 确认。需要在 tokio 运行时中调用
+    pub fn ssubscribe(&mut self, channels: Vec<Bytes>) -> Vec<Frame> {
+        channels
+            .into_iter()
+            .map(|channel| {
+                if !self.shard_channels.contains_key(&channel) {
+                    let rx = self.db.ssubscribe(channel.clone());
+                    let name = channel.clone();
+                    let task = forward(rx, self.messages_tx.clone(), move |message| {
+                        push_frame("smessage", vec![Frame::Bulk(name.clone()), Frame::Bulk(message)])
+                    });
+                    self.shard_channels.insert(channel.clone(), task);
+                }
+                self.confirm("ssubscribe", Frame::Bulk(channel), ChannelKind::Shard)
+            })
+            .collect()
+    }
+
+    /// 订阅模式，每个模式回复一条 `psubscribe` 确认。需要在 tokio 运行时中调用
+    pub fn psubscribe(&mut self, patterns: Vec<Bytes>) -> Vec<Frame> {
+        patterns
+            .into_iter()
+            .map(|pattern| {
+                if !self.patterns.contains_key(&pattern) {
+                    let rx = self.db.psubscribe(pattern.clone());
+                    let name = pattern.clone();
+                    let task = forward(rx, self.messages_tx.clone(), move |(channel, message)| {
+                        push_frame("pmessage", vec![Frame::Bulk(name.clone()), Frame::Bulk(channel), Frame::Bulk(message)])
+                    });
+                    self.patterns.insert(pattern.clone(), task);
+                }
+                self.confirm("psubscribe", Frame::Bulk(pattern), ChannelKind::Pattern)
+            })
+            .collect()
+    }
+
+    /// 取消订阅频道，channels 为空时取消所有频道
+    pub fn unsubscribe(&mut self, channels: Vec<Bytes>) -> Vec<Frame> {
+        self.remove(ChannelKind::Channel, channels)
+    }
+
+    /// 取消订阅模式，patterns 为空时取消所有模式
+    pub fn punsubscribe(&mut self, patterns: Vec<Bytes>) -> Vec<Frame> {
+        self.remove(ChannelKind::Pattern, patterns)
+    }
+
+    /// 取消订阅分片频道，channels 为空时取消所有分片频道
+    pub fn sunsubscribe(&mut self, channels: Vec<Bytes>) -> Vec<Frame> {
+        self.remove(ChannelKind::Shard, channels)
+    }
+
+    /// 等待下一条订阅到的消息。没有订阅时会一直等待
+    pub async fn recv(&mut self) -> Frame {
+        // self 持有一个 sender，队列不会被关闭
+        self.messages_rx.recv().await.unwrap()
+    }
+
+    fn subscribed(&mut self, kind: ChannelKind) -> &mut HashMap<Bytes, JoinHandle<()>> {
+        match kind {
+            ChannelKind::Channel => &mut self.channels,
+            ChannelKind::Pattern => &mut self.patterns,
+            ChannelKind::Shard => &mut self.shard_channels,
+        }
+    }
+
+    fn remove(&mut self, kind: ChannelKind, names: Vec<Bytes>) -> Vec<Frame> {
+        let reply = match kind {
+            ChannelKind::Channel => "unsubscribe",
+            ChannelKind::Pattern => "punsubscribe",
+            ChannelKind::Shard => "sunsubscribe",
+        };
+        let names = match names.is_empty() {
+            false => names,
+            true => self.subscribed(kind).keys().cloned().collect(),
+        };
+        let mut frames = vec![];
+        for name in names {
+            if let Some(task) = self.subscribed(kind).remove(&name) {
+                task.abort();
+            }
+            frames.push(self.confirm(reply, Frame::Bulk(name), kind));
+        }
+        // 本来就没有订阅时，与 redis 一样回复一条 name 为 nil 的确认
+        if frames.is_empty() {
+            frames.push(self.confirm(reply, Frame::Null, kind));
+        }
+        frames
+    }
+
+    /// 订阅、取消订阅的确认：`[reply, name, 当前订阅数]`。
+    /// 与 redis 一样，分片频道的确认中只计算分片频道，其他确认中计算频道与模式
+    fn confirm(&self, reply: &'static str, name: Frame, kind: ChannelKind) -> Frame {
+        let count = match kind {
+            ChannelKind::Shard => self.shard_channels.len(),
+            _ => self.channels.len() + self.patterns.len(),
+        };
+        push_frame(reply, vec![name, Frame::Integer(count as i64)])
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        for task in self.channels.values().chain(self.patterns.values()).chain(self.shard_channels.values()) {
+            task.abort();
+        }
+    }
+}
+
+/// 把 broadcast 收到的消息转成 frame 放入连接的消息队列，直到连接关闭或者任务被取消
+fn forward<T>(mut rx: broadcast::Receiver<T>, tx: mpsc::Sender<Frame>, to_frame: impl Fn(T) -> Frame + Send + 'static) -> JoinHandle<()>
+where T: Clone + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(message) => {
+                    if tx.send(to_frame(message)).await.is_err() {
+                        return;
+                    }
+                },
+                // 处理不过来，丢弃了部分消息，继续接收之后的
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    })
+}
+
+/// 推送给订阅者的数据，RESP2 下会被转换为数组
+fn push_frame(kind: &'static str, mut items: Vec<Frame>) -> Frame {
+    items.insert(0, Frame::Bulk(Bytes::from(kind)));
+    Frame::Push(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{db::Db, frame::Frame};
+
+    use super::Subscriber;
+
+    fn bulk(data: &'static str) -> Frame {
+        Frame::Bulk(Bytes::from(data))
+    }
+
+    #[tokio::test]
+    async fn publish_and_subscribe() {
+        let db = Db::new();
+        let mut subscriber = Subscriber::new(db.clone());
+        let replies = subscriber.subscribe(vec![Bytes::from("news"), Bytes::from("news")]);
+        assert_eq!(replies[1], Frame::Push(vec![bulk("subscribe"), bulk("news"), Frame::Integer(1)]));
+        subscriber.psubscribe(vec![Bytes::from("n*")]);
+        assert_eq!(subscriber.count(), 2);
+
+        assert_eq!(db.publish(&Bytes::from("news"), Bytes::from("hi")), 2);
+        assert_eq!(db.publish(&Bytes::from("other"), Bytes::from("hi")), 0);
+        let mut messages = vec![subscriber.recv().await, subscriber.recv().await];
+        messages.sort_by_key(|frame| matches!(frame, Frame::Push(items) if items[0] == bulk("pmessage")));
+        assert_eq!(messages, vec![
+            Frame::Push(vec![bulk("message"), bulk("news"), bulk("hi")]),
+            Frame::Push(vec![bulk("pmessage"), bulk("n*"), bulk("news"), bulk("hi")]),
+        ]);
+
+        let replies = subscriber.unsubscribe(vec![]);
+        assert_eq!(replies, vec![Frame::Push(vec![bulk("unsubscribe"), bulk("news"), Frame::Integer(1)])]);
+        assert_eq!(
+            subscriber.unsubscribe(vec![]),
+            vec![Frame::Push(vec![bulk("unsubscribe"), Frame::Null, Frame::Integer(1)])],
+        );
+        subscriber.punsubscribe(vec![Bytes::from("n*")]);
+        assert!(!subscriber.is_active());
+
+        // 转发任务被取消后，订阅者数量随之减少
+        tokio::task::yield_now().await;
+        assert_eq!(db.publish(&Bytes::from("news"), Bytes::from("hi")), 0);
+    }
+
+    #[tokio::test]
+    async fn shard_channels() {
+        let db = Db::new();
+        let mut subscriber = Subscriber::new(db.clone());
+        subscriber.subscribe(vec![Bytes::from("news")]);
+        subscriber.psubscribe(vec![Bytes::from("*")]);
+        let replies = subscriber.ssubscribe(vec![Bytes::from("news")]);
+        // 分片频道单独计数
+        assert_eq!(replies, vec![Frame::Push(vec![bulk("ssubscribe"), bulk("news"), Frame::Integer(1)])]);
+        assert_eq!(subscriber.count(), 3);
+
+        // 与同名的普通频道互不相干，也不匹配模式
+        assert_eq!(db.spublish(&Bytes::from("news"), Bytes::from("hi")), 1);
+        assert_eq!(subscriber.recv().await, Frame::Push(vec![bulk("smessage"), bulk("news"), bulk("hi")]));
+        assert_eq!(db.spublish(&Bytes::from("other"), Bytes::from("hi")), 0);
+
+        let replies = subscriber.sunsubscribe(vec![]);
+        assert_eq!(replies, vec![Frame::Push(vec![bulk("sunsubscribe"), bulk("news"), Frame::Integer(0)])]);
+        assert!(subscriber.is_active());
+        tokio::task::yield_now().await;
+        assert_eq!(db.spublish(&Bytes::from("news"), Bytes::from("hi")), 0);
+        assert_eq!(db.publish(&Bytes::from("news"), Bytes::from("hi")), 2);
+    }
+}
@@ -0,0 +1,660 @@
+//! 快照持久化。格式参考 redis 的 RDB，但并不兼容：
+//!
+//! ```text
+//! "TOYRDB" 版本号（4 字节 ASCII）
+//! [0xFC 过期时间] 类型 key value
+//! ...
+//! 0xFF
+//! ```
+//! - 过期时间为 unix 时间戳（毫秒），8 字节小端，没有过期时间的 key 不写；
+//! - 长度使用变长编码：最高两位为 00 时剩余的 6 位即长度，为 01 时连同下一个字节共 14 位，
+//!   `0x80`、`0x81` 之后分别是 32 位、64 位的大端整数；
+//! - 字符串先写长度再写内容。内容是 32 位以内的整数时最高两位为 11，`0xC0`、`0xC1`、`0xC2`
+//!   之后分别是 8、16、32 位的小端整数；
+//! - 类型编号与 redis 一致，同时记录了值的编码，加载后的对象与保存时编码相同。
+//!   ziplist、intset 编码的值按元素逐个保存，加载时重建 ziplist、intset。
+
+use std::{collections::LinkedList, fs, io, path::Path};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use bytes::Bytes;
+
+use crate::{db::Db, ds::{dict::Dict, intset::IntSet, perfstr::{SmartString, sds::SDS}, skiplist::Skiplist, ziplist::ZipList}, object::{ObjectEncoding, RedisObject, int_to_bytes, parse_int}, types::{Hash, List, Set, Stream, StreamId, ZSet}};
+
+const MAGIC: &[u8] = b"TOYRDB";
+const VERSION: &[u8] = b"0001";
+
+const OPCODE_EXPIRE_MS: u8 = 0xFC;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_HASH: u8 = 4;
+/// 跳表编码的有序集合，分数以 8 字节的二进制浮点数保存
+const TYPE_ZSET: u8 = 5;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+/// ziplist 编码的有序集合，分数与 ziplist 中一样以字符串保存
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+/// 流。编号与 redis 相同，但格式不同：条目个数，每个条目的 ID（ms、seq 按长度编码）、field value 的对数与内容，
+/// 之后是流的最大 ID 与添加过的条目总数，最后是消费者组的个数，每个组依次为名称、last_id、
+/// 待确认条目（ID、消费者、投递时间、投递次数）与消费者（名称、最近活动时间）
+const TYPE_STREAM: u8 = 15;
+
+const LEN_32BIT: u8 = 0x80;
+const LEN_64BIT: u8 = 0x81;
+const ENC_INT8: u8 = 0xC0;
+const ENC_INT16: u8 = 0xC1;
+const ENC_INT32: u8 = 0xC2;
+
+/// 将键空间编码为快照
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder {
+    pub fn new() -> Encoder {
+        Encoder { buf: [MAGIC, VERSION].concat() }
+    }
+
+    /// 不带文件头的编码器，只用来写入一部分 key，之后用 [`Encoder::append`] 拼接到完整的快照中
+    pub fn fragment() -> Encoder {
+        Encoder { buf: vec![] }
+    }
+
+    /// 追加 [`Encoder::fragment`] 写入的 key
+    pub fn append(&mut self, fragment: Encoder) {
+        self.buf.extend_from_slice(&fragment.buf);
+    }
+
+    /// 写入一个 key。`expire_at` 为过期的 unix 时间戳（毫秒）
+    pub fn write_entry(&mut self, key: &[u8], value: &RedisObject, expire_at: Option<u64>) {
+        if let Some(when) = expire_at {
+            self.buf.push(OPCODE_EXPIRE_MS);
+            self.buf.extend_from_slice(&when.to_le_bytes());
+        }
+        self.buf.push(value_type(value));
+        self.write_string(key);
+        self.write_value(value);
+    }
+
+    /// 写入结束标记，返回快照数据
+    pub fn finish(mut self) -> Vec<u8> {
+        self.buf.push(OPCODE_EOF);
+        self.buf
+    }
+
+    fn write_value(&mut self, value: &RedisObject) {
+        match value {
+            RedisObject::String(sds) => self.write_string(sds.val()),
+            RedisObject::Int(n) => self.write_int(*n),
+            RedisObject::List(list) => {
+                self.write_len(list.len() as u64);
+                for item in list.range(0, -1) {
+                    self.write_string(&item);
+                }
+            },
+            RedisObject::Hash(hash) => {
+                let entries = hash.entries();
+                self.write_len(entries.len() as u64);
+                for (field, value) in entries {
+                    self.write_string(&field);
+                    self.write_string(&value);
+                }
+            },
+            RedisObject::Set(set) => {
+                let members = set.members();
+                self.write_len(members.len() as u64);
+                for member in members {
+                    self.write_string(&member);
+                }
+            },
+            RedisObject::ZSet(zset) => {
+                let members = zset.range_by_score(None, None, 0, 0);
+                let binary_scores = value_type(value) == TYPE_ZSET;
+                self.write_len(members.len() as u64);
+                for (member, score) in members {
+                    self.write_string(&member);
+                    if binary_scores {
+                        self.buf.extend_from_slice(&score.to_le_bytes());
+                    } else {
+                        self.write_string(score.to_string().as_bytes());
+                    }
+                }
+            },
+            RedisObject::Stream(stream) => {
+                self.write_len(stream.len() as u64);
+                for (id, fields) in stream.entries() {
+                    self.write_id(id);
+                    self.write_len(fields.len() as u64);
+                    for (field, value) in fields {
+                        self.write_string(&field);
+                        self.write_string(&value);
+                    }
+                }
+                self.write_id(stream.last_id());
+                self.write_len(stream.entries_added());
+                self.write_len(stream.groups().count() as u64);
+                for (name, group) in stream.groups() {
+                    self.write_string(name);
+                    self.write_id(group.last_id());
+                    self.write_len(group.pending().len() as u64);
+                    for (id, entry) in group.pending() {
+                        self.write_id(*id);
+                        self.write_string(&entry.consumer);
+                        self.write_len(entry.delivery_time);
+                        self.write_len(entry.delivery_count);
+                    }
+                    self.write_len(group.consumers().count() as u64);
+                    for (consumer, seen) in group.consumers() {
+                        self.write_string(consumer);
+                        self.write_len(seen);
+                    }
+                }
+            },
+        }
+    }
+
+    fn write_len(&mut self, len: u64) {
+        if len < 1 << 6 {
+            self.buf.push(len as u8);
+        } else if len < 1 << 14 {
+            self.buf.extend_from_slice(&(len as u16 | 0x4000).to_be_bytes());
+        } else if len <= u32::MAX as u64 {
+            self.buf.push(LEN_32BIT);
+            self.buf.extend_from_slice(&(len as u32).to_be_bytes());
+        } else {
+            self.buf.push(LEN_64BIT);
+            self.buf.extend_from_slice(&len.to_be_bytes());
+        }
+    }
+
+    fn write_id(&mut self, id: StreamId) {
+        self.write_len(id.ms);
+        self.write_len(id.seq);
+    }
+
+    fn write_string(&mut self, s: &[u8]) {
+        match parse_int(s) {
+            Some(n) if i32::try_from(n).is_ok() => self.write_int(n),
+            _ => {
+                self.write_len(s.len() as u64);
+                self.buf.extend_from_slice(s);
+            },
+        }
+    }
+
+    /// 整数形式的字符串，超过 32 位时按普通字符串保存
+    fn write_int(&mut self, n: i64) {
+        if let Ok(n) = i8::try_from(n) {
+            self.buf.push(ENC_INT8);
+            self.buf.extend_from_slice(&n.to_le_bytes());
+        } else if let Ok(n) = i16::try_from(n) {
+            self.buf.push(ENC_INT16);
+            self.buf.extend_from_slice(&n.to_le_bytes());
+        } else if let Ok(n) = i32::try_from(n) {
+            self.buf.push(ENC_INT32);
+            self.buf.extend_from_slice(&n.to_le_bytes());
+        } else {
+            let s = n.to_string();
+            self.write_len(s.len() as u64);
+            self.buf.extend_from_slice(s.as_bytes());
+        }
+    }
+}
+
+/// 值的类型编号，同时体现了值的编码
+fn value_type(value: &RedisObject) -> u8 {
+    match (value, value.encoding()) {
+        (RedisObject::String(_) | RedisObject::Int(_), _) => TYPE_STRING,
+        (RedisObject::List(_), ObjectEncoding::ZipList) => TYPE_LIST_ZIPLIST,
+        (RedisObject::List(_), _) => TYPE_LIST,
+        (RedisObject::Hash(_), ObjectEncoding::ZipList) => TYPE_HASH_ZIPLIST,
+        (RedisObject::Hash(_), _) => TYPE_HASH,
+        (RedisObject::Set(_), ObjectEncoding::IntSet) => TYPE_SET_INTSET,
+        (RedisObject::Set(_), _) => TYPE_SET,
+        (RedisObject::ZSet(_), ObjectEncoding::ZipList) => TYPE_ZSET_ZIPLIST,
+        (RedisObject::ZSet(_), _) => TYPE_ZSET,
+        (RedisObject::Stream(_), _) => TYPE_STREAM,
+    }
+}
+
+/// 值在快照中占用的字节数，不包括 key 与过期时间，供 `DEBUG OBJECT` 使用
+pub fn serialized_len(value: &RedisObject) -> usize {
+    let mut encoder = Encoder { buf: vec![] };
+    encoder.write_value(value);
+    encoder.buf.len()
+}
+
+/// 解析快照，对其中的每个 key 调用 f(key, value, expire_at)。数据不完整或格式错误时返回 `Err`
+pub fn decode(data: &[u8], mut f: impl FnMut(Bytes, RedisObject, Option<u64>)) -> crate::Result<()> {
+    let mut decoder = Decoder { data };
+    if decoder.take(MAGIC.len())? != MAGIC {
+        return Err("invalid snapshot: wrong signature".into());
+    }
+    if decoder.take(VERSION.len())? != VERSION {
+        return Err("invalid snapshot: unsupported version".into());
+    }
+    loop {
+        let mut kind = decoder.read_u8()?;
+        let mut expire_at = None;
+        if kind == OPCODE_EXPIRE_MS {
+            expire_at = Some(LittleEndian::read_u64(decoder.take(8)?));
+            kind = decoder.read_u8()?;
+        }
+        if kind == OPCODE_EOF {
+            if !decoder.data.is_empty() {
+                return Err("invalid snapshot: trailing data after EOF".into());
+            }
+            return Ok(());
+        }
+        let key = decoder.read_string()?;
+        let value = decoder.read_object(kind)?;
+        f(key, value, expire_at);
+    }
+}
+
+struct Decoder<'a> {
+    /// 尚未解析的数据
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> crate::Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err("invalid snapshot: unexpected end of file".into());
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn read_u8(&mut self) -> crate::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// 读取长度。遇到整数编码时返回 `Err`，由 [`Decoder::read_string`] 自行处理
+    fn read_len(&mut self) -> crate::Result<usize> {
+        let first = self.read_u8()?;
+        let len = match first >> 6 {
+            0 => first as u64,
+            1 => ((first as u64 & 0x3f) << 8) | self.read_u8()? as u64,
+            _ => match first {
+                LEN_32BIT => BigEndian::read_u32(self.take(4)?) as u64,
+                LEN_64BIT => BigEndian::read_u64(self.take(8)?),
+                _ => return Err(format!("invalid snapshot: unknown length encoding {:#x}", first).into()),
+            },
+        };
+        usize::try_from(len).map_err(|_| "invalid snapshot: length out of range".into())
+    }
+
+    fn read_id(&mut self) -> crate::Result<StreamId> {
+        Ok(StreamId::new(self.read_len()? as u64, self.read_len()? as u64))
+    }
+
+    fn read_string(&mut self) -> crate::Result<Bytes> {
+        let n = match self.data.first() {
+            Some(&ENC_INT8) => self.take(2)?[1] as i8 as i64,
+            Some(&ENC_INT16) => LittleEndian::read_i16(&self.take(3)?[1..]) as i64,
+            Some(&ENC_INT32) => LittleEndian::read_i32(&self.take(5)?[1..]) as i64,
+            _ => {
+                let len = self.read_len()?;
+                return Ok(Bytes::copy_from_slice(self.take(len)?));
+            },
+        };
+        Ok(int_to_bytes(n))
+    }
+
+    fn read_score(&mut self, kind: u8) -> crate::Result<f64> {
+        let score = if kind == TYPE_ZSET {
+            LittleEndian::read_f64(self.take(8)?)
+        } else {
+            let s = self.read_string()?;
+            std::str::from_utf8(&s).ok().and_then(|s| s.parse().ok()).unwrap_or(f64::NAN)
+        };
+        if score.is_nan() {
+            return Err("invalid snapshot: invalid zset score".into());
+        }
+        Ok(score)
+    }
+
+    fn read_object(&mut self, kind: u8) -> crate::Result<RedisObject> {
+        let object = match kind {
+            TYPE_STRING => RedisObject::string(&self.read_string()?),
+            TYPE_LIST_ZIPLIST | TYPE_HASH_ZIPLIST | TYPE_ZSET_ZIPLIST => {
+                let mut len = self.read_len()?;
+                if kind != TYPE_LIST_ZIPLIST {
+                    len = len.checked_mul(2).ok_or("invalid snapshot: length out of range")?;
+                }
+                let mut zl = ZipList::new();
+                for i in 0..len {
+                    // 有序集合的分数需要检查是否为合法的浮点数
+                    let item = if kind == TYPE_ZSET_ZIPLIST && i % 2 == 1 {
+                        Bytes::from(self.read_score(kind)?.to_string())
+                    } else {
+                        self.read_string()?
+                    };
+                    zl.push_tail_string(&item)?;
+                }
+                match kind {
+                    TYPE_LIST_ZIPLIST => RedisObject::List(List::ZipList(zl)),
+                    TYPE_HASH_ZIPLIST => RedisObject::Hash(Hash::ZipList(zl)),
+                    _ => RedisObject::ZSet(ZSet::ZipList(zl)),
+                }
+            },
+            TYPE_LIST => {
+                let len = self.read_len()?;
+                let mut list = LinkedList::new();
+                for _ in 0..len {
+                    list.push_back(self.read_string()?);
+                }
+                RedisObject::List(List::LinkedList(list))
+            },
+            TYPE_HASH => {
+                let len = self.read_len()?;
+                let mut dict = Dict::new();
+                for _ in 0..len {
+                    let field = self.read_string()?;
+                    dict.insert(SDS::new(&field), self.read_string()?);
+                }
+                RedisObject::Hash(Hash::HashTable(dict))
+            },
+            TYPE_SET_INTSET => {
+                let len = self.read_len()?;
+                let mut set = IntSet::new();
+                for _ in 0..len {
+                    let value = parse_int(&self.read_string()?).ok_or("invalid snapshot: invalid intset member")?;
+                    if !set.insert(value) {
+                        return Err("invalid snapshot: duplicated set member".into());
+                    }
+                }
+                RedisObject::Set(Set::IntSet(set))
+            },
+            TYPE_SET => {
+                let len = self.read_len()?;
+                let mut dict = Dict::new();
+                for _ in 0..len {
+                    if dict.insert(SDS::new(&self.read_string()?), ()).is_some() {
+                        return Err("invalid snapshot: duplicated set member".into());
+                    }
+                }
+                RedisObject::Set(Set::HashTable(dict))
+            },
+            TYPE_ZSET => {
+                let len = self.read_len()?;
+                let mut dict = Dict::new();
+                let mut list = Skiplist::new();
+                for _ in 0..len {
+                    let member = self.read_string()?;
+                    let score = self.read_score(kind)?;
+                    if dict.insert(SDS::new(&member), score).is_some() {
+                        return Err("invalid snapshot: duplicated zset member".into());
+                    }
+                    list.insert(member, score);
+                }
+                RedisObject::ZSet(ZSet::SkipList { dict: Box::new(dict), list })
+            },
+            TYPE_STREAM => {
+                let len = self.read_len()?;
+                let mut stream = Stream::new();
+                for _ in 0..len {
+                    let id = self.read_id()?;
+                    if id <= stream.last_id() {
+                        return Err("invalid snapshot: stream IDs out of order".into());
+                    }
+                    let mut fields = vec![];
+                    for _ in 0..self.read_len()? {
+                        fields.push((self.read_string()?, self.read_string()?));
+                    }
+                    stream.insert(id, &fields);
+                }
+                let last_id = self.read_id()?;
+                stream.set_meta(last_id, self.read_len()? as u64);
+                for _ in 0..self.read_len()? {
+                    let name = self.read_string()?;
+                    let last_id = self.read_id()?;
+                    if !stream.create_group(name.clone(), last_id) {
+                        return Err("invalid snapshot: duplicated consumer group".into());
+                    }
+                    let group = stream.group_mut(&name).unwrap();
+                    for _ in 0..self.read_len()? {
+                        let (id, consumer) = (self.read_id()?, self.read_string()?);
+                        let (delivery_time, delivery_count) = (self.read_len()? as u64, self.read_len()? as u64);
+                        group.deliver(id, &consumer, delivery_time);
+                        group.pending_mut(id).unwrap().delivery_count = delivery_count;
+                    }
+                    for _ in 0..self.read_len()? {
+                        let consumer = self.read_string()?;
+                        group.touch(&consumer, self.read_len()? as u64);
+                    }
+                }
+                RedisObject::Stream(stream)
+            },
+            _ => return Err(format!("invalid snapshot: unknown value type {}", kind).into()),
+        };
+        Ok(object)
+    }
+}
+
+/// 把键空间保存到 `db` 配置的快照文件，在当前线程中完成。已经有保存在进行时返回 `Err`
+pub fn save(db: &Db) -> crate::Result<()> {
+    if !db.begin_save() {
+        return Err("ERR Background save already in progress".into());
+    }
+    let result = write_file(&db.snapshot_path(), &db.dump());
+    db.end_save();
+    Ok(result?)
+}
+
+/// 在后台保存快照。保存的是后台任务开始时刻的数据，见 [`Db::snapshot`]：开始时只短暂锁住所有分片，
+/// 编码与写文件都在后台线程中进行，期间的修改不会影响这次保存的内容。
+/// [`Db::snapshot`] 可能需要等待之前的快照结束，所以也放在后台线程中，不会阻塞 tokio 的工作线程。
+/// 已经有后台保存在进行时返回 `Err`。需要在 tokio 运行时中调用
+pub fn bgsave(db: &Db) -> crate::Result<()> {
+    if !db.begin_save() {
+        return Err("ERR Background save already in progress".into());
+    }
+    let path = db.snapshot_path();
+    let db = db.clone();
+    tokio::task::spawn_blocking(move || {
+        // 快照在 end_save 之前结束，之后的 SAVE 不会在 Db::snapshot 中等待它
+        let data = db.snapshot().encode();
+        if let Err(err) = write_file(&path, &data) {
+            println!("background saving error: {}", err);
+        }
+        db.end_save();
+    });
+    Ok(())
+}
+
+/// 从 `db` 配置的快照文件加载数据，返回加载的 key 数量。文件不存在时什么也不做
+pub fn load(db: &Db) -> crate::Result<usize> {
+    let data = match fs::read(db.snapshot_path()) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    db.load(&data)
+}
+
+/// 先写入同一目录下的临时文件再重命名，保存中途出错也不会破坏已有的快照
+fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{db::{Db, now_ms}, object::{IntSetLimits, ObjectEncoding, RedisObject, ZipLimits}, types::{Hash, List, Set, Stream, StreamId, ZSet}};
+
+    use super::{bgsave, decode, load, serialized_len};
+
+    /// 依次构造各种类型、各种编码的值
+    fn populate(db: &Db) {
+        let small = ZipLimits::default();
+        let tiny = ZipLimits { max_entries: 1, max_value: 1 };
+        db.set(Bytes::from("int"), Bytes::from("-42"), None);
+        db.set(Bytes::from("big int"), Bytes::from(i64::MAX.to_string()), None);
+        db.set(Bytes::from("raw"), Bytes::from(vec![b'x'; 20000]), Some(now_ms() + 100_000));
+        for (key, limits) in [("ziplist", small), ("converted", tiny)] {
+            let mut list = List::new();
+            let mut hash = Hash::new();
+            let mut zset = ZSet::new();
+            for i in 0..100 {
+                list.push_back(Bytes::from(format!("item{}", i)), &limits);
+                hash.insert(Bytes::from(format!("f{}", i)), Bytes::from(i.to_string()), &limits);
+                zset.insert(Bytes::from(format!("m{}", i)), i as f64 / 3.0, &limits);
+            }
+            zset.insert(Bytes::from("inf"), f64::NEG_INFINITY, &limits);
+            let mut set = Set::new();
+            let set_limits = IntSetLimits { max_entries: limits.max_entries };
+            for i in -50..50 {
+                set.insert((i * 1000).to_string().as_bytes(), &set_limits);
+            }
+            db.update(&Bytes::from(format!("list:{}", key)), |value| *value = Some(RedisObject::List(list)));
+            db.update(&Bytes::from(format!("hash:{}", key)), |value| *value = Some(RedisObject::Hash(hash)));
+            db.update(&Bytes::from(format!("zset:{}", key)), |value| *value = Some(RedisObject::ZSet(zset)));
+            db.update(&Bytes::from(format!("set:{}", key)), |value| *value = Some(RedisObject::Set(set)));
+        }
+        let mut stream = Stream::new();
+        for i in 1..=10 {
+            stream.insert(StreamId::new(i, i), &[(Bytes::from("f"), Bytes::from(i.to_string())), (Bytes::from("g"), Bytes::from("v"))]);
+        }
+        stream.trim_len(5);
+        stream.create_group(Bytes::from("group"), StreamId::new(8, 8));
+        let group = stream.group_mut(b"group").unwrap();
+        for i in 6..=8 {
+            group.deliver(StreamId::new(i, i), &Bytes::from("consumer"), 1000 + i);
+        }
+        group.touch(&Bytes::from("consumer"), 1008);
+        group.touch(&Bytes::from("idle"), 42);
+        db.update(&Bytes::from("stream"), |value| *value = Some(RedisObject::Stream(stream)));
+    }
+
+    #[test]
+    fn value_len() {
+        assert_eq!(serialized_len(&RedisObject::Int(-42)), 2);
+        assert_eq!(serialized_len(&RedisObject::Int(i64::MAX)), 1 + 19);
+        let mut list = List::new();
+        list.push_back(Bytes::from("hello"), &ZipLimits::default());
+        list.push_back(Bytes::from("1000"), &ZipLimits::default());
+        // 元素个数 1 字节，"hello" 6 字节，1000 按 16 位整数保存 3 字节
+        assert_eq!(serialized_len(&RedisObject::List(list)), 10);
+    }
+
+    #[test]
+    fn round_trip() {
+        let db = Db::new();
+        populate(&db);
+        let data = db.dump();
+
+        let restored = Db::new();
+        assert_eq!(restored.load(&data).unwrap(), 12);
+        for key in db.keys(b"*") {
+            let encodings = [&db, &restored].map(|db| db.lookup_read(&key, |value| value.unwrap().encoding()));
+            assert_eq!(encodings[0], encodings[1], "{:?}", key);
+        }
+        assert_eq!(restored.lookup_read(b"int", |value| value.unwrap().encoding()), ObjectEncoding::Int);
+        assert_eq!(restored.lookup_read(b"list:converted", |value| value.unwrap().encoding()), ObjectEncoding::LinkedList);
+        assert_eq!(restored.lookup_read(b"set:ziplist", |value| value.unwrap().encoding()), ObjectEncoding::IntSet);
+        assert_eq!(restored.get(b"big int").unwrap(), Some(Bytes::from(i64::MAX.to_string())));
+        assert_eq!(restored.get(b"raw").unwrap().unwrap().len(), 20000);
+        assert!(restored.ttl(b"raw").unwrap().is_some());
+        assert!(restored.ttl(b"int").unwrap().is_none());
+        restored.lookup_read(b"stream", |value| {
+            let Some(RedisObject::Stream(stream)) = value else { panic!() };
+            assert_eq!(stream.entries().map(|(id, _)| id.ms).collect::<Vec<_>>(), [6, 7, 8, 9, 10]);
+            assert_eq!(stream.entries().next().unwrap().1[0], (Bytes::from("f"), Bytes::from("6")));
+            assert_eq!((stream.last_id(), stream.entries_added()), (StreamId::new(10, 10), 10));
+            let (name, group) = stream.groups().next().unwrap();
+            assert_eq!((&name[..], group.last_id(), group.pending().len()), (&b"group"[..], StreamId::new(8, 8), 3));
+            assert_eq!(group.pending()[&StreamId::new(7, 7)].delivery_time, 1007);
+            assert_eq!(group.consumers().map(|(_, seen)| seen).collect::<Vec<_>>(), [1008, 42]);
+        });
+        // 重新保存的结果应当一致（hashtable 的遍历顺序不确定，只比较长度）
+        assert_eq!(restored.dump().len(), data.len());
+        for (key, ty) in [("list", "list"), ("hash", "hash"), ("set", "set"), ("zset", "zset")] {
+            for encoding in ["ziplist", "converted"] {
+                let key = format!("{}:{}", key, encoding);
+                let dump = |db: &Db| db.lookup_read(key.as_bytes(), |value| match value.unwrap() {
+                    RedisObject::List(list) => format!("{:?}", list.range(0, -1)),
+                    RedisObject::Hash(hash) => {
+                        let mut entries = hash.entries();
+                        entries.sort();
+                        format!("{:?}", entries)
+                    },
+                    RedisObject::Set(set) => {
+                        let mut members = set.members();
+                        members.sort();
+                        format!("{:?}", members)
+                    },
+                    RedisObject::ZSet(zset) => format!("{:?}", zset.range_by_score(None, None, 0, 0)),
+                    other => panic!("unexpected {}", other.type_name()),
+                });
+                assert_eq!(dump(&db), dump(&restored), "{} {}", ty, key);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_data() {
+        let db = Db::new();
+        populate(&db);
+        db.del(b"raw");
+        let data = db.dump();
+        // 任意位置截断都应当返回错误而不是 panic
+        for len in 0..data.len() {
+            assert!(decode(&data[..len], |_, _, _| {}).is_err(), "{}", len);
+        }
+        assert!(decode(b"REDIS0009\xff", |_, _, _| {}).is_err());
+        assert!(decode(b"TOYRDB0001\x07\x01k\x00\xff", |_, _, _| {}).is_err());
+        assert!(decode(b"TOYRDB0001\xff\x00", |_, _, _| {}).is_err());
+        assert!(decode(b"TOYRDB0001\xff", |_, _, _| {}).is_ok());
+    }
+
+    #[test]
+    fn expired_keys_are_skipped() {
+        let db = Db::new();
+        db.set(Bytes::from("a"), Bytes::from("1"), Some(now_ms() + 50));
+        db.set(Bytes::from("b"), Bytes::from("2"), None);
+        let data = db.dump();
+        std::thread::sleep(std::time::Duration::from_millis(60));
+        let restored = Db::new();
+        assert_eq!(restored.load(&data).unwrap(), 1);
+        assert!(!restored.exists(b"a"));
+        assert!(restored.exists(b"b"));
+    }
+
+    #[tokio::test]
+    async fn bgsave_waits_off_runtime() {
+        let path = std::env::temp_dir().join(format!("toyredis-bgsave-{}.rdb", std::process::id()));
+        let db = Db::new();
+        db.set_snapshot_path(&path);
+        db.set(Bytes::from("k"), Bytes::from("v"), None);
+        // 已有快照在进行时，bgsave 在后台线程中等待它结束，而不是阻塞当前的运行时
+        let snapshot = db.snapshot();
+        bgsave(&db).unwrap();
+        assert!(bgsave(&db).is_err());
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(db.is_saving());
+        drop(snapshot);
+        while db.is_saving() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        let restored = Db::new();
+        restored.set_snapshot_path(&path);
+        assert_eq!(load(&restored).unwrap(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
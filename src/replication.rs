@@ -0,0 +1,111 @@
+//! 复制的偏移量与副本的确认，对应 redis `replication.c` 中 WAIT 用到的部分。
+//!
+//! 主节点的复制偏移量随写命令增长，每条写命令按其请求的编码长度计入，与 redis 复制流中的字节数一致。
+//! 副本通过 `REPLCONF ACK <offset>` 报告已经处理到的偏移量，发送过 ACK 的连接即被当作副本，断开时移除。
+//! `WAIT` 等待确认的偏移量不小于调用时主节点偏移量的副本达到指定个数，见 [`Replication::wait_for`]。
+//!
+//! toyredis 还不会向副本发送复制流，偏移量与确认只用于 WAIT 的同步
+
+use std::{collections::HashMap, pin::pin, sync::{Mutex, atomic::{AtomicU64, Ordering}}};
+
+use tokio::sync::Notify;
+
+/// 主节点的复制偏移量与各个副本确认的偏移量
+#[derive(Default)]
+pub struct Replication {
+    /// 主节点的复制偏移量
+    offset: AtomicU64,
+    /// 副本所在连接的 id 到它确认的偏移量
+    acks: Mutex<HashMap<u64, u64>>,
+    /// 有副本确认了新的偏移量，唤醒等待中的 WAIT
+    acked: Notify,
+}
+
+impl Replication {
+    /// 主节点当前的复制偏移量
+    pub fn offset(&self) -> u64 {
+        self.offset.load(Ordering::Acquire)
+    }
+
+    /// 执行了一条写命令，偏移量增加 len，返回增加后的偏移量
+    pub fn advance(&self, len: u64) -> u64 {
+        self.offset.fetch_add(len, Ordering::AcqRel) + len
+    }
+
+    /// 连接 replica 上的副本确认已经处理到 offset。偏移量只增不减，乱序到达的旧 ACK 被忽略
+    pub fn ack(&self, replica: u64, offset: u64) {
+        let mut acks = self.acks.lock().unwrap();
+        let acked = acks.entry(replica).or_default();
+        if offset > *acked {
+            *acked = offset;
+            self.acked.notify_waiters();
+        }
+    }
+
+    /// 连接断开，不再是副本
+    pub fn remove_replica(&self, replica: u64) {
+        self.acks.lock().unwrap().remove(&replica);
+    }
+
+    /// 已连接的副本数
+    pub fn replicas(&self) -> usize {
+        self.acks.lock().unwrap().len()
+    }
+
+    /// 确认的偏移量不小于 offset 的副本数
+    pub fn acked(&self, offset: u64) -> usize {
+        self.acks.lock().unwrap().values().filter(|&&acked| acked >= offset).count()
+    }
+
+    /// 等到至少 numreplicas 个副本确认了 offset，返回确认了的副本数。不会超时，需要由调用方限制等待的时间
+    pub async fn wait_for(&self, offset: u64, numreplicas: usize) -> usize {
+        loop {
+            // 先登记再检查，检查之后到达的 ACK 同样能唤醒
+            let mut notified = pin!(self.acked.notified());
+            notified.as_mut().enable();
+            let acked = self.acked(offset);
+            if acked >= numreplicas {
+                return acked;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::Replication;
+
+    #[tokio::test]
+    async fn ack_and_wait() {
+        let replication = Replication::default();
+        assert_eq!(replication.advance(10), 10);
+        assert_eq!(replication.advance(5), 15);
+        assert_eq!(replication.offset(), 15);
+        assert_eq!(replication.wait_for(15, 0).await, 0);
+
+        replication.ack(1, 10);
+        replication.ack(2, 15);
+        // 旧的 ACK 不会让偏移量倒退
+        replication.ack(2, 3);
+        assert_eq!((replication.replicas(), replication.acked(10), replication.acked(15)), (2, 2, 1));
+        assert_eq!(replication.wait_for(15, 1).await, 1);
+        assert!(timeout(Duration::from_millis(20), replication.wait_for(15, 2)).await.is_err());
+
+        // 等待期间到达的 ACK 唤醒等待者
+        let wait = replication.wait_for(15, 2);
+        let ack = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            replication.ack(1, 20);
+        };
+        let (acked, _) = tokio::join!(wait, ack);
+        assert_eq!(acked, 2);
+
+        replication.remove_replica(1);
+        assert_eq!((replication.replicas(), replication.acked(0)), (1, 1));
+    }
+}
@@ -0,0 +1,929 @@
+//! 内置的脚本引擎，支持 Lua 的一个小子集，足以编写常见的“读-判断-写”脚本：
+//!
+//! ```lua
+//! local current = redis.call('GET', KEYS[1])
+//! if current == ARGV[1] then
+//!     return redis.call('DEL', KEYS[1])
+//! end
+//! return 0
+//! ```
+//!
+//! - 语句：`local x = e`、`x = e`（只能给已声明的局部变量赋值）、`if ... elseif ... else ... end`、
+//!   数值 `for i = a, b[, step] do ... end`、`return [e]`，以及函数调用；
+//! - 值：nil、布尔、整数（没有浮点数）、字符串、数组形式的 table（`{a, b}`，下标从 1 开始）；
+//! - 运算：`or and == ~= < > <= >= .. + - * %`，一元 `not - #`，优先级与 Lua 相同；
+//! - 函数：`redis.call`、`redis.pcall`、`redis.error_reply`、`redis.status_reply`、`tonumber`、`tostring`、`type`；
+//! - 全局变量只有 `KEYS` 与 `ARGV`。
+//!
+//! 回复与值的转换规则与 redis 相同：integer ↔ 整数，bulk ↔ 字符串，array ↔ table，
+//! Null → false，status、error 分别对应 `redis.status_reply`、`redis.error_reply` 的返回值；
+//! 返回值中 true 转换为 1，false、nil 转换为 Null，table 在第一个 nil 处截断。
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::frame::Frame;
+
+use super::{Engine, Host, Program};
+
+/// 单次执行最多执行的语句数，避免死循环一直占用数据库
+const MAX_STEPS: u64 = 10_000_000;
+
+/// 表达式与语句块最多嵌套的层数，与 Lua 的 `LUAI_MAXCCALLS` 相同。
+/// 解析与执行都是递归的，嵌套过深的脚本会耗尽线程的栈
+const MAX_NESTING: usize = 200;
+
+/// 内置的脚本引擎
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MiniEngine;
+
+impl Engine for MiniEngine {
+    fn compile(&self, source: &[u8]) -> Result<Arc<dyn Program>, String> {
+        let tokens = lex(source)?;
+        let block = Parser { tokens, pos: 0, depth: 0 }.parse_chunk()?;
+        Ok(Arc::new(MiniProgram { block }))
+    }
+}
+
+struct MiniProgram {
+    block: Vec<Stat>,
+}
+
+impl Program for MiniProgram {
+    fn run(&self, keys: &[Bytes], argv: &[Bytes], host: &mut dyn Host) -> Frame {
+        let mut interp = Interp {
+            keys: Value::Table(keys.iter().cloned().map(Value::Str).collect()),
+            argv: Value::Table(argv.iter().cloned().map(Value::Str).collect()),
+            scopes: vec![],
+            host,
+            steps: 0,
+        };
+        match interp.exec_block(&self.block) {
+            Ok(Flow::Return(value)) => value.into_frame(),
+            Ok(Flow::Normal) => Frame::Null,
+            Err(err) => Frame::Error(err),
+        }
+    }
+}
+
+// ---------------------------------------------------------------- 词法分析
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// 标识符与关键字，`redis.call` 这样带点的名字也作为一个标识符
+    Name(String),
+    Int(i64),
+    Str(Bytes),
+    Sym(&'static str),
+    Eof,
+}
+
+/// 多个字符的符号需要排在其前缀的前面
+const SYMBOLS: [&str; 22] = [
+    "==", "~=", "<=", ">=", "..", "<", ">", "=", "+", "-", "*", "%", "#", "(", ")", "[", "]", "{", "}", ",", ";", ".",
+];
+
+fn lex(source: &[u8]) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut i = 0;
+    while i < source.len() {
+        let c = source[i];
+        if c == b'\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_ascii_whitespace() {
+            i += 1;
+        } else if source[i..].starts_with(b"--") {
+            while i < source.len() && source[i] != b'\n' {
+                i += 1;
+            }
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let start = i;
+            while i < source.len() {
+                let c = source[i];
+                let dotted = c == b'.' && source.get(i + 1).is_some_and(|c| c.is_ascii_alphabetic() || *c == b'_');
+                if !(c.is_ascii_alphanumeric() || c == b'_' || dotted) {
+                    break;
+                }
+                i += 1;
+            }
+            let name = String::from_utf8_lossy(&source[start..i]).into_owned();
+            tokens.push((Token::Name(name), line));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < source.len() && source[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let n = std::str::from_utf8(&source[start..i]).ok().and_then(|s| s.parse().ok());
+            match n {
+                Some(n) => tokens.push((Token::Int(n), line)),
+                None => return Err(format!("line {}: malformed number", line)),
+            }
+        } else if c == b'"' || c == b'\'' {
+            let mut s = vec![];
+            i += 1;
+            loop {
+                match source.get(i) {
+                    None | Some(b'\n') => return Err(format!("line {}: unfinished string", line)),
+                    Some(&b) if b == c => break,
+                    Some(b'\\') => {
+                        let escaped = match source.get(i + 1) {
+                            Some(b'n') => b'\n',
+                            Some(b'r') => b'\r',
+                            Some(b't') => b'\t',
+                            Some(b'0') => 0,
+                            Some(&b @ (b'\\' | b'"' | b'\'')) => b,
+                            _ => return Err(format!("line {}: invalid escape sequence", line)),
+                        };
+                        s.push(escaped);
+                        i += 2;
+                    },
+                    Some(&b) => {
+                        s.push(b);
+                        i += 1;
+                    },
+                }
+            }
+            i += 1;
+            tokens.push((Token::Str(Bytes::from(s)), line));
+        } else {
+            match SYMBOLS.iter().find(|sym| source[i..].starts_with(sym.as_bytes())) {
+                Some(sym) => {
+                    tokens.push((Token::Sym(sym), line));
+                    i += sym.len();
+                },
+                None => return Err(format!("line {}: unexpected symbol near '{}'", line, c as char)),
+            }
+        }
+    }
+    tokens.push((Token::Eof, line));
+    Ok(tokens)
+}
+
+// ---------------------------------------------------------------- 语法分析
+
+#[derive(Debug)]
+enum Stat {
+    Local(String, Expr),
+    Assign(String, Expr),
+    /// 各个分支的条件与语句，以及 else 分支
+    If(Vec<(Expr, Vec<Stat>)>, Vec<Stat>),
+    For { var: String, start: Expr, end: Expr, step: Option<Expr>, body: Vec<Stat> },
+    Return(Option<Expr>),
+    Call(Expr),
+}
+
+#[derive(Debug)]
+enum Expr {
+    Const(Value),
+    Var(String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Table(Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Len(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// 二元运算符的优先级（左、右），右结合的运算符右边的优先级较低。`and`、`or` 单独处理
+fn binary_priority(op: &str) -> Option<(u8, u8)> {
+    Some(match op {
+        "or" => (1, 1),
+        "and" => (2, 2),
+        "==" | "~=" | "<" | ">" | "<=" | ">=" => (3, 3),
+        ".." => (5, 4),
+        "+" | "-" => (6, 6),
+        "*" | "%" => (7, 7),
+        _ => return None,
+    })
+}
+
+/// 一元运算符的优先级
+const UNARY_PRIORITY: u8 = 8;
+
+const KEYWORDS: [&str; 15] = [
+    "and", "do", "else", "elseif", "end", "false", "for", "if", "local", "nil", "not", "or", "return", "then", "true",
+];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// 当前的嵌套层数，见 [`MAX_NESTING`]
+    depth: usize,
+}
+
+impl Parser {
+    fn parse_chunk(mut self) -> Result<Vec<Stat>, String> {
+        let block = self.parse_block()?;
+        match self.peek() {
+            Token::Eof => Ok(block),
+            _ => Err(self.error("'<eof>' expected")),
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn error(&self, msg: &str) -> String {
+        format!("line {}: {}", self.tokens[self.pos].1, msg)
+    }
+
+    /// 进入一层嵌套。出错时整个解析都会失败，因此只在成功解析完这一层之后恢复 depth
+    fn check_nesting(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(self.error("chunk has too many syntax levels"));
+        }
+        Ok(())
+    }
+
+    /// 下一个 token 是否为关键字或符号 word，是的话跳过它
+    fn accept(&mut self, word: &str) -> bool {
+        let matched = match self.peek() {
+            Token::Name(name) => name == word && KEYWORDS.contains(&word),
+            Token::Sym(sym) => *sym == word,
+            _ => false,
+        };
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        if self.accept(word) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{}' expected", word)))
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Token::Name(name) if !KEYWORDS.contains(&&name[..]) && !name.contains('.') => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            },
+            _ => Err(self.error("<name> expected")),
+        }
+    }
+
+    /// 语句块在 `end`、`else`、`elseif` 或者结尾处结束
+    fn parse_block(&mut self) -> Result<Vec<Stat>, String> {
+        self.check_nesting()?;
+        let mut block = vec![];
+        loop {
+            while self.accept(";") {}
+            match self.peek() {
+                Token::Eof => break,
+                Token::Name(name) if matches!(&name[..], "end" | "else" | "elseif") => break,
+                _ => {},
+            }
+            let stat = self.parse_stat()?;
+            let is_return = matches!(stat, Stat::Return(_));
+            block.push(stat);
+            if is_return {
+                // 与 Lua 一样，return 必须是语句块的最后一条语句
+                while self.accept(";") {}
+                break;
+            }
+        }
+        self.depth -= 1;
+        Ok(block)
+    }
+
+    fn parse_stat(&mut self) -> Result<Stat, String> {
+        if self.accept("local") {
+            let name = self.expect_name()?;
+            let value = if self.accept("=") { self.parse_expr(0)? } else { Expr::Const(Value::Nil) };
+            return Ok(Stat::Local(name, value));
+        }
+        if self.accept("if") {
+            let mut branches = vec![];
+            loop {
+                let cond = self.parse_expr(0)?;
+                self.expect("then")?;
+                branches.push((cond, self.parse_block()?));
+                if !self.accept("elseif") {
+                    break;
+                }
+            }
+            let otherwise = if self.accept("else") { self.parse_block()? } else { vec![] };
+            self.expect("end")?;
+            return Ok(Stat::If(branches, otherwise));
+        }
+        if self.accept("for") {
+            let var = self.expect_name()?;
+            self.expect("=")?;
+            let start = self.parse_expr(0)?;
+            self.expect(",")?;
+            let end = self.parse_expr(0)?;
+            let step = if self.accept(",") { Some(self.parse_expr(0)?) } else { None };
+            self.expect("do")?;
+            let body = self.parse_block()?;
+            self.expect("end")?;
+            return Ok(Stat::For { var, start, end, step, body });
+        }
+        if self.accept("return") {
+            let value = match self.peek() {
+                Token::Eof | Token::Sym(";") => None,
+                Token::Name(name) if matches!(&name[..], "end" | "else" | "elseif") => None,
+                _ => Some(self.parse_expr(0)?),
+            };
+            return Ok(Stat::Return(value));
+        }
+        let expr = self.parse_primary()?;
+        match expr {
+            Expr::Var(name) if self.accept("=") => Ok(Stat::Assign(name, self.parse_expr(0)?)),
+            Expr::Call(..) => Ok(Stat::Call(expr)),
+            _ => Err(self.error("syntax error")),
+        }
+    }
+
+    /// 优先级爬升，只解析优先级高于 limit 的二元运算。
+    /// 连续的左结合运算在循环中解析，但得到的表达式树同样一层套一层，每次运算都计入嵌套层数
+    fn parse_expr(&mut self, limit: u8) -> Result<Expr, String> {
+        let depth = self.depth;
+        self.check_nesting()?;
+        let mut left = if self.accept("not") {
+            Expr::Not(Box::new(self.parse_expr(UNARY_PRIORITY)?))
+        } else if self.accept("-") {
+            Expr::Neg(Box::new(self.parse_expr(UNARY_PRIORITY)?))
+        } else if self.accept("#") {
+            Expr::Len(Box::new(self.parse_expr(UNARY_PRIORITY)?))
+        } else {
+            self.parse_simple()?
+        };
+        loop {
+            let op = match self.peek() {
+                Token::Sym(sym) => *sym,
+                Token::Name(name) if name == "and" => "and",
+                Token::Name(name) if name == "or" => "or",
+                _ => break,
+            };
+            let right_priority = match binary_priority(op) {
+                Some((left_priority, right_priority)) if left_priority > limit => right_priority,
+                _ => break,
+            };
+            self.pos += 1;
+            self.check_nesting()?;
+            let right = Box::new(self.parse_expr(right_priority)?);
+            let left_box = Box::new(left);
+            left = match op {
+                "and" => Expr::And(left_box, right),
+                "or" => Expr::Or(left_box, right),
+                op => Expr::Binary(op, left_box, right),
+            };
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn parse_simple(&mut self) -> Result<Expr, String> {
+        let constant = match self.peek() {
+            Token::Int(n) => Value::Int(*n),
+            Token::Str(s) => Value::Str(s.clone()),
+            Token::Name(name) if name == "nil" => Value::Nil,
+            Token::Name(name) if name == "true" => Value::Bool(true),
+            Token::Name(name) if name == "false" => Value::Bool(false),
+            Token::Sym("{") => {
+                self.pos += 1;
+                let mut items = vec![];
+                while !self.accept("}") {
+                    items.push(self.parse_expr(0)?);
+                    if !self.accept(",") && !self.accept(";") {
+                        self.expect("}")?;
+                        break;
+                    }
+                }
+                return Ok(Expr::Table(items));
+            },
+            _ => return self.parse_primary(),
+        };
+        self.pos += 1;
+        Ok(Expr::Const(constant))
+    }
+
+    /// 变量、函数调用、下标以及括号中的表达式
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        let depth = self.depth;
+        let mut expr = match self.peek().clone() {
+            Token::Name(name) if !KEYWORDS.contains(&&name[..]) => {
+                self.pos += 1;
+                if self.accept("(") {
+                    let mut args = vec![];
+                    if !self.accept(")") {
+                        loop {
+                            args.push(self.parse_expr(0)?);
+                            if !self.accept(",") {
+                                break;
+                            }
+                        }
+                        self.expect(")")?;
+                    }
+                    Expr::Call(name, args)
+                } else {
+                    Expr::Var(name)
+                }
+            },
+            Token::Sym("(") => {
+                self.pos += 1;
+                let expr = self.parse_expr(0)?;
+                self.expect(")")?;
+                expr
+            },
+            _ => return Err(self.error("unexpected symbol")),
+        };
+        while self.accept("[") {
+            self.check_nesting()?;
+            let index = self.parse_expr(0)?;
+            self.expect("]")?;
+            expr = Expr::Index(Box::new(expr), Box::new(index));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+}
+
+// ---------------------------------------------------------------- 执行
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Str(Bytes),
+    Table(Vec<Value>),
+    /// 对应 Lua 中的 `{err = ...}`
+    Error(String),
+    /// 对应 Lua 中的 `{ok = ...}`
+    Status(String),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "boolean",
+            Value::Int(_) => "number",
+            Value::Str(_) => "string",
+            Value::Table(_) | Value::Error(_) | Value::Status(_) => "table",
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    /// 与 Lua 一样，算术运算时字符串会尝试转换为数字
+    fn to_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Str(s) => std::str::from_utf8(s).ok()?.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn to_bytes(&self) -> Option<Bytes> {
+        match self {
+            Value::Int(n) => Some(Bytes::from(n.to_string())),
+            Value::Str(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    fn from_frame(frame: Frame) -> Value {
+        match frame.to_resp2() {
+            Frame::Simple(s) => Value::Status(s),
+            Frame::Error(e) => Value::Error(e),
+            Frame::Integer(n) => Value::Int(n),
+            Frame::Bulk(b) => Value::Str(b),
+            Frame::Array(items) => Value::Table(items.into_iter().map(Value::from_frame).collect()),
+            // to_resp2 之后只剩 Null
+            _ => Value::Bool(false),
+        }
+    }
+
+    fn into_frame(self) -> Frame {
+        match self {
+            Value::Nil | Value::Bool(false) => Frame::Null,
+            Value::Bool(true) => Frame::Integer(1),
+            Value::Int(n) => Frame::Integer(n),
+            Value::Str(s) => Frame::Bulk(s),
+            Value::Table(items) => {
+                Frame::Array(items.into_iter().take_while(|item| *item != Value::Nil).map(Value::into_frame).collect())
+            },
+            Value::Error(e) => Frame::Error(e),
+            Value::Status(s) => Frame::Simple(s),
+        }
+    }
+}
+
+enum Flow {
+    Normal,
+    Return(Value),
+}
+
+struct Interp<'a> {
+    keys: Value,
+    argv: Value,
+    /// 局部变量，每个语句块一层
+    scopes: Vec<Vec<(String, Value)>>,
+    host: &'a mut dyn Host,
+    steps: u64,
+}
+
+/// 脚本本身的错误（而不是 redis.call 的命令返回的错误）
+fn script_error(msg: impl std::fmt::Display) -> String {
+    format!("ERR Error running script: {}", msg)
+}
+
+impl Interp<'_> {
+    fn exec_block(&mut self, block: &[Stat]) -> Result<Flow, String> {
+        self.scopes.push(vec![]);
+        let result = self.exec_stats(block);
+        self.scopes.pop();
+        result
+    }
+
+    fn exec_stats(&mut self, block: &[Stat]) -> Result<Flow, String> {
+        for stat in block {
+            self.step()?;
+            match stat {
+                Stat::Local(name, expr) => {
+                    let value = self.eval(expr)?;
+                    self.scopes.last_mut().unwrap().push((name.clone(), value));
+                },
+                Stat::Assign(name, expr) => {
+                    let value = self.eval(expr)?;
+                    match self.lookup(name) {
+                        Some(slot) => *slot = value,
+                        None => return Err(script_error(format!("attempt to create global variable '{}'", name))),
+                    }
+                },
+                Stat::If(branches, otherwise) => {
+                    let mut taken = None;
+                    for (cond, body) in branches {
+                        if self.eval(cond)?.truthy() {
+                            taken = Some(body);
+                            break;
+                        }
+                    }
+                    if let Flow::Return(value) = self.exec_block(taken.unwrap_or(otherwise))? {
+                        return Ok(Flow::Return(value));
+                    }
+                },
+                Stat::For { var, start, end, step, body } => {
+                    let int = |value: Value, what: &str| value.to_int().ok_or_else(|| script_error(format!("'for' {} must be a number", what)));
+                    let start = int(self.eval(start)?, "initial value")?;
+                    let end = int(self.eval(end)?, "limit")?;
+                    let step = match step {
+                        Some(step) => int(self.eval(step)?, "step")?,
+                        None => 1,
+                    };
+                    if step == 0 {
+                        return Err(script_error("'for' step is zero"));
+                    }
+                    let mut i = start;
+                    while (step > 0 && i <= end) || (step < 0 && i >= end) {
+                        self.step()?;
+                        self.scopes.push(vec![(var.clone(), Value::Int(i))]);
+                        let flow = self.exec_stats(body);
+                        self.scopes.pop();
+                        if let Flow::Return(value) = flow? {
+                            return Ok(Flow::Return(value));
+                        }
+                        i = match i.checked_add(step) {
+                            Some(i) => i,
+                            None => break,
+                        };
+                    }
+                },
+                Stat::Return(expr) => {
+                    let value = match expr {
+                        Some(expr) => self.eval(expr)?,
+                        None => Value::Nil,
+                    };
+                    return Ok(Flow::Return(value));
+                },
+                Stat::Call(expr) => {
+                    self.eval(expr)?;
+                },
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// 每条语句、每次循环计一步，超过 [`MAX_STEPS`] 时中止脚本
+    fn step(&mut self) -> Result<(), String> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(script_error("script exceeded the maximum number of steps"));
+        }
+        Ok(())
+    }
+
+    /// 由内向外查找局部变量
+    fn lookup(&mut self, name: &str) -> Option<&mut Value> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.iter_mut().rev().find(|(var, _)| var == name))
+            .map(|(_, value)| value)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, String> {
+        Ok(match expr {
+            Expr::Const(value) => value.clone(),
+            Expr::Var(name) => match self.lookup(name) {
+                Some(value) => value.clone(),
+                None => match &name[..] {
+                    "KEYS" => self.keys.clone(),
+                    "ARGV" => self.argv.clone(),
+                    _ => return Err(script_error(format!("attempt to access nonexistent global variable '{}'", name))),
+                },
+            },
+            Expr::Index(table, index) => {
+                let table = self.eval(table)?;
+                let index = self.eval(index)?;
+                match (&table, &index) {
+                    (Value::Table(items), Value::Int(i)) => {
+                        usize::try_from(*i - 1).ok().and_then(|i| items.get(i)).cloned().unwrap_or(Value::Nil)
+                    },
+                    (Value::Table(_), _) => Value::Nil,
+                    _ => return Err(script_error(format!("attempt to index a {} value", table.type_name()))),
+                }
+            },
+            Expr::Call(name, args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                self.call(name, values)?
+            },
+            Expr::Table(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.eval(item)?);
+                }
+                Value::Table(values)
+            },
+            Expr::Not(expr) => Value::Bool(!self.eval(expr)?.truthy()),
+            Expr::Neg(expr) => {
+                let value = self.eval(expr)?;
+                let n = value.to_int().ok_or_else(|| script_error(format!("attempt to perform arithmetic on a {} value", value.type_name())))?;
+                Value::Int(n.checked_neg().ok_or_else(|| script_error("integer overflow"))?)
+            },
+            Expr::Len(expr) => match self.eval(expr)? {
+                Value::Str(s) => Value::Int(s.len() as i64),
+                Value::Table(items) => Value::Int(items.iter().take_while(|item| **item != Value::Nil).count() as i64),
+                value => return Err(script_error(format!("attempt to get length of a {} value", value.type_name()))),
+            },
+            Expr::And(left, right) => {
+                let left = self.eval(left)?;
+                if left.truthy() { self.eval(right)? } else { left }
+            },
+            Expr::Or(left, right) => {
+                let left = self.eval(left)?;
+                if left.truthy() { left } else { self.eval(right)? }
+            },
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(op, left, right)?
+            },
+        })
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let first = || args.first().cloned().unwrap_or(Value::Nil);
+        Ok(match name {
+            "redis.call" | "redis.pcall" => {
+                if args.is_empty() {
+                    return Err(script_error("Please specify at least one argument for this redis lib call"));
+                }
+                let mut command = Vec::with_capacity(args.len());
+                for arg in &args {
+                    match arg.to_bytes() {
+                        Some(arg) => command.push(arg),
+                        None => return Err(script_error("Lua redis lib command arguments must be strings or integers")),
+                    }
+                }
+                match Value::from_frame(self.host.call(command)) {
+                    // redis.call 遇到错误时中止脚本，错误原样返回给客户端
+                    Value::Error(err) if name == "redis.call" => return Err(err),
+                    value => value,
+                }
+            },
+            "redis.error_reply" | "redis.status_reply" => match first() {
+                Value::Str(s) => {
+                    let s = String::from_utf8_lossy(&s).into_owned();
+                    if name == "redis.error_reply" { Value::Error(s) } else { Value::Status(s) }
+                },
+                _ => return Err(script_error(format!("wrong argument given to '{}'", name))),
+            },
+            "tonumber" => first().to_int().map_or(Value::Nil, Value::Int),
+            "tostring" => match first() {
+                Value::Nil => Value::Str(Bytes::from("nil")),
+                Value::Bool(b) => Value::Str(Bytes::from(b.to_string())),
+                value => value.to_bytes().map_or(Value::Str(Bytes::from("table")), Value::Str),
+            },
+            "type" => Value::Str(Bytes::from(first().type_name())),
+            _ => return Err(script_error(format!("attempt to call a nil value (global '{}')", name))),
+        })
+    }
+}
+
+fn binary(op: &str, left: Value, right: Value) -> Result<Value, String> {
+    Ok(match op {
+        "==" => Value::Bool(left == right),
+        "~=" => Value::Bool(left != right),
+        "<" | ">" | "<=" | ">=" => {
+            let ordering = match (&left, &right) {
+                (Value::Int(a), Value::Int(b)) => a.cmp(b),
+                (Value::Str(a), Value::Str(b)) => a.cmp(b),
+                _ => return Err(script_error(format!("attempt to compare {} with {}", left.type_name(), right.type_name()))),
+            };
+            Value::Bool(match op {
+                "<" => ordering.is_lt(),
+                ">" => ordering.is_gt(),
+                "<=" => ordering.is_le(),
+                _ => ordering.is_ge(),
+            })
+        },
+        ".." => match (left.to_bytes(), right.to_bytes()) {
+            (Some(a), Some(b)) => Value::Str(Bytes::from([a, b].concat())),
+            _ => {
+                let bad = if left.to_bytes().is_none() { &left } else { &right };
+                return Err(script_error(format!("attempt to concatenate a {} value", bad.type_name())));
+            },
+        },
+        _ => {
+            let (a, b) = match (left.to_int(), right.to_int()) {
+                (Some(a), Some(b)) => (a, b),
+                _ => {
+                    let bad = if left.to_int().is_none() { &left } else { &right };
+                    return Err(script_error(format!("attempt to perform arithmetic on a {} value", bad.type_name())));
+                },
+            };
+            let result = match op {
+                "+" => a.checked_add(b),
+                "-" => a.checked_sub(b),
+                "*" => a.checked_mul(b),
+                _ if b == 0 => return Err(script_error("attempt to perform 'n%0'")),
+                // 与 Lua 一样，结果的符号与除数相同
+                _ => a.checked_rem(b).map(|r| if r != 0 && (r < 0) != (b < 0) { r + b } else { r }),
+            };
+            Value::Int(result.ok_or_else(|| script_error("integer overflow"))?)
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{frame::Frame, script::{Engine, Host}};
+
+    use super::MiniEngine;
+
+    /// 记录收到的命令，GET 返回 Null，其他命令返回 OK，ERR 命令返回错误
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<Vec<Bytes>>,
+    }
+
+    impl Host for Recorder {
+        fn call(&mut self, args: Vec<Bytes>) -> Frame {
+            let reply = match &args[0][..] {
+                b"GET" => Frame::Null,
+                b"ERR" => Frame::Error("ERR boom".into()),
+                b"LIST" => Frame::Array(vec![Frame::Integer(1), Frame::Bulk(Bytes::from("a"))]),
+                _ => Frame::Simple("OK".into()),
+            };
+            self.calls.push(args);
+            reply
+        }
+    }
+
+    fn run(source: &str, keys: &[&str], argv: &[&str]) -> (Frame, Vec<Vec<Bytes>>) {
+        let program = MiniEngine.compile(source.as_bytes()).unwrap();
+        let mut host = Recorder::default();
+        let keys: Vec<Bytes> = keys.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())).collect();
+        let argv: Vec<Bytes> = argv.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect();
+        let frame = program.run(&keys, &argv, &mut host);
+        (frame, host.calls)
+    }
+
+    fn bulk(s: &str) -> Frame {
+        Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(run("return 1 + 2 * 3 - 4 % 3", &[], &[]).0, Frame::Integer(6));
+        assert_eq!(run("return -7 % 3", &[], &[]).0, Frame::Integer(2));
+        assert_eq!(run("return 'a' .. 1 .. \"b\\n\"", &[], &[]).0, bulk("a1b\n"));
+        assert_eq!(run("return ARGV[1] + 1", &[], &["41"]).0, Frame::Integer(42));
+        assert_eq!(run("return #KEYS + #ARGV[1]", &["k1", "k2"], &["abc"]).0, Frame::Integer(5));
+        assert_eq!(run("return not nil and 1 < 2 and 'a' < 'b'", &[], &[]).0, Frame::Integer(1));
+        assert_eq!(run("return nil or false", &[], &[]).0, Frame::Null);
+        assert_eq!(run("return {1, 'x', nil, 3}", &[], &[]).0, Frame::Array(vec![Frame::Integer(1), bulk("x")]));
+        assert_eq!(run("return tonumber('12') + tonumber(3)", &[], &[]).0, Frame::Integer(15));
+        assert_eq!(run("return type(KEYS) .. tostring(true)", &[], &[]).0, bulk("tabletrue"));
+        assert_eq!(run("return KEYS[3]", &["a"], &[]).0, Frame::Null);
+        // 没有 return 时返回 Null
+        assert_eq!(run("local x = 1", &[], &[]).0, Frame::Null);
+    }
+
+    #[test]
+    fn statements() {
+        let source = "
+            -- 累加 1..n 中的偶数
+            local sum = 0
+            for i = 1, tonumber(ARGV[1]) do
+                if i % 2 == 0 then
+                    sum = sum + i
+                elseif i == 5 then
+                    sum = sum + 100
+                else
+                    local ignored = i
+                end
+            end
+            return sum
+        ";
+        assert_eq!(run(source, &[], &["6"]).0, Frame::Integer(112));
+        assert_eq!(run("for i = 3, 1, -1 do if i == 2 then return i end end", &[], &[]).0, Frame::Integer(2));
+        // 内层的局部变量不影响外层
+        assert_eq!(run("local x = 1; if true then local x = 2 end; return x", &[], &[]).0, Frame::Integer(1));
+    }
+
+    #[test]
+    fn redis_calls() {
+        let (frame, calls) = run("redis.call('SET', KEYS[1], ARGV[1]); return redis.call('GET', KEYS[1])", &["k"], &["v"]);
+        assert_eq!(frame, Frame::Null);
+        assert_eq!(calls, [vec![Bytes::from("SET"), Bytes::from("k"), Bytes::from("v")], vec![Bytes::from("GET"), Bytes::from("k")]]);
+
+        // Null 转换为 false
+        assert_eq!(run("return redis.call('GET', 'k') == false", &[], &[]).0, Frame::Integer(1));
+        assert_eq!(run("return redis.call('SET', 'k', 1)", &[], &[]).0, Frame::Simple("OK".into()));
+        assert_eq!(run("return redis.call('LIST')[2]", &[], &[]).0, bulk("a"));
+
+        // redis.call 出错时中止脚本，pcall 返回错误
+        let (frame, calls) = run("redis.call('ERR'); redis.call('SET', 'k', 1)", &[], &[]);
+        assert_eq!(frame, Frame::Error("ERR boom".into()));
+        assert_eq!(calls.len(), 1);
+        assert_eq!(run("local e = redis.pcall('ERR'); return 1", &[], &[]).0, Frame::Integer(1));
+        assert_eq!(run("return redis.pcall('ERR')", &[], &[]).0, Frame::Error("ERR boom".into()));
+        assert_eq!(run("return redis.error_reply('MY err')", &[], &[]).0, Frame::Error("MY err".into()));
+        assert_eq!(run("return redis.status_reply('FINE')", &[], &[]).0, Frame::Simple("FINE".into()));
+    }
+
+    #[test]
+    fn errors() {
+        for source in ["return 1 +", "if true then return 1", "local = 1", "return 'abc", "x y", "return 1.5", "f(", "return 1 return 2"] {
+            assert!(MiniEngine.compile(source.as_bytes()).is_err(), "{}", source);
+        }
+        let error = |source: &str| match run(source, &[], &[]).0 {
+            Frame::Error(err) => err,
+            frame => panic!("{:?}", frame),
+        };
+        assert!(error("x = 1").contains("global variable 'x'"));
+        assert!(error("return y").contains("nonexistent global variable 'y'"));
+        assert!(error("return 1 + {}").contains("arithmetic on a table value"));
+        assert!(error("return 1 % 0").starts_with("ERR Error running script"));
+        assert!(error("return 1 < 'a'").contains("compare"));
+        assert!(error("return nosuch(1)").contains("attempt to call"));
+        assert!(error("redis.call()").contains("at least one argument"));
+        assert!(error("for i = 1, 100000000 do end").contains("maximum number of steps"));
+    }
+
+    #[test]
+    fn nesting() {
+        let nested = |open: &str, inner: &str, close: &str, depth: usize| {
+            format!("{}{}{}", open.repeat(depth), inner, close.repeat(depth))
+        };
+        assert_eq!(run(&format!("return {}", nested("(", "1", ")", 50)), &[], &[]).0, Frame::Integer(1));
+        for source in [
+            format!("return {}", nested("(", "1", ")", 1000)),
+            format!("return {}", nested("- ", "1", "", 1000)),
+            format!("return {}", nested("{", "1", "}", 1000)),
+            format!("return 1{}", "+1".repeat(1000)),
+            format!("return KEYS{}", "[1]".repeat(1000)),
+            nested("if true then ", "return 1", " end", 1000),
+        ] {
+            let err = MiniEngine.compile(source.as_bytes()).err().unwrap();
+            assert!(err.contains("too many syntax levels"), "{}", err);
+        }
+        // 恢复嵌套层数之后，同样长度的并列表达式不受影响
+        let source = format!("return {{{}}}", vec!["(((1)))"; 1000].join(", "));
+        assert!(MiniEngine.compile(source.as_bytes()).is_ok());
+    }
+}
@@ -0,0 +1,82 @@
+//! 服务端脚本：`EVAL`、`EVALSHA` 与 `SCRIPT`。
+//!
+//! redis 内嵌了 Lua 解释器，这里不引入完整的 Lua，而是把脚本的编译与执行抽象成 [`Engine`] 与 [`Program`]，
+//! 默认使用内置的 [`mini::MiniEngine`]（Lua 的一个小子集），也可以通过 [`crate::db::Db::set_script_engine`] 换成其他实现。
+//!
+//! 脚本通过 [`Host`] 调用 redis 命令。与 redis 一样，脚本执行期间独占数据库，不会穿插其他连接的命令。
+//! 编译后的脚本按源码的 SHA1 缓存，`EVALSHA` 可以直接执行缓存中的脚本。
+
+use std::{collections::HashMap, sync::Arc};
+
+use bytes::Bytes;
+
+use crate::frame::Frame;
+
+pub mod mini;
+
+/// 脚本引擎，把源码编译成可以反复执行的 [`Program`]
+pub trait Engine: Send + Sync {
+    /// 编译失败时返回错误信息，不需要 `ERR` 等前缀
+    fn compile(&self, source: &[u8]) -> Result<Arc<dyn Program>, String>;
+}
+
+/// 编译后的脚本
+pub trait Program: Send + Sync {
+    /// 以给定的 KEYS 与 ARGV 执行脚本，返回回复给客户端的 frame，出错时返回 `Frame::Error`
+    fn run(&self, keys: &[Bytes], argv: &[Bytes], host: &mut dyn Host) -> Frame;
+}
+
+/// 脚本执行 redis 命令的入口，对应 Lua 中的 `redis.call`
+pub trait Host {
+    /// 执行 args 表示的命令，返回 RESP2 形式的回复
+    fn call(&mut self, args: Vec<Bytes>) -> Frame;
+}
+
+/// 脚本缓存，key 为源码 SHA1 的十六进制小写形式
+pub struct Scripts {
+    engine: Box<dyn Engine>,
+    cache: HashMap<String, Arc<dyn Program>>,
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Self::new(Box::new(mini::MiniEngine))
+    }
+}
+
+impl Scripts {
+    pub fn new(engine: Box<dyn Engine>) -> Self {
+        Scripts { engine, cache: HashMap::new() }
+    }
+
+    /// 更换脚本引擎，已缓存的脚本由原来的引擎编译，会被一并清除
+    pub fn set_engine(&mut self, engine: Box<dyn Engine>) {
+        self.engine = engine;
+        self.cache.clear();
+    }
+
+    /// 编译并缓存脚本，返回其 SHA1。已经缓存过的脚本不会重新编译
+    pub fn load(&mut self, source: &[u8]) -> Result<(String, Arc<dyn Program>), String> {
+        let sha = sha1_hex(source);
+        if let Some(program) = self.cache.get(&sha) {
+            return Ok((sha, program.clone()));
+        }
+        let program = self.engine.compile(source)?;
+        self.cache.insert(sha.clone(), program.clone());
+        Ok((sha, program))
+    }
+
+    /// 缓存中的脚本，sha 不区分大小写
+    pub fn get(&self, sha: &str) -> Option<Arc<dyn Program>> {
+        self.cache.get(&sha.to_ascii_lowercase()).cloned()
+    }
+
+    pub fn flush(&mut self) {
+        self.cache.clear();
+    }
+}
+
+/// 源码的 SHA1，即 `SCRIPT LOAD` 的返回值
+pub fn sha1_hex(source: &[u8]) -> String {
+    sha1_smol::Sha1::from(source).digest().to_string()
+}
@@ -0,0 +1,679 @@
+//! 服务端：接受连接，为每个连接读取请求、执行命令，直到退出。
+//!
+//! [`run`] 在给定的 listener 上以默认配置运行；[`Server`] 可以指定配置、使用已有的 [`Db`]、在启动时加载快照。
+//! `server` 可执行文件、测试以及其他 crate 都通过它们启动服务端。
+//!
+//! 启用 `uring` feature 时使用 io_uring 接受连接、读写数据，[`Listener`] 是 tokio-uring 的 TcpListener，
+//! 服务端需要运行在 [`block_on`] 启动的运行时中；否则使用 tokio 的 TcpListener 与多线程运行时。
+//! `io-threads` 大于 1 时连接由多个 io 线程接受、处理，见 [`start_io_threads`]
+
+use std::{future::Future, io, net::{SocketAddr, ToSocketAddrs}, thread, time::Instant};
+
+use tokio::{io::AsyncRead, sync::{broadcast, mpsc, oneshot}};
+
+use crate::{acl::{Categories, DEFAULT_USER}, clients::ClientHandle, cmd::{Command, Debug}, config::Config, connection::{Connection, Output, OutputLimit, OutputLimitExceeded}, db::{Db, now_ms}, rdb, frame::{Frame, Protocol}, pubsub::Subscriber, shutdown::Shutdown, transaction::Transaction};
+
+pub use backend::{Listener, block_on};
+
+/// 在 listener 上以默认配置运行服务端，不加载快照。
+///
+/// shutdown 完成或者有连接执行了 `SHUTDOWN` 时停止接受新连接，等已有的连接处理完正在执行的命令后返回
+pub async fn run(listener: Listener, shutdown: impl Future) -> crate::Result<()> {
+    Server::new(Config::default()).serve(listener, shutdown).await
+}
+
+/// 可配置的服务端
+///
+/// ```no_run
+/// # use toyredis::{config::Config, server::Server};
+/// # async fn example() -> toyredis::Result<()> {
+/// Server::new(Config::default())
+///     .persistence(true)
+///     .run(tokio::signal::ctrl_c())
+///     .await
+/// # }
+/// ```
+pub struct Server {
+    config: Config,
+    db: Option<Db>,
+    persistence: bool,
+}
+
+impl Server {
+    /// 按配置创建服务端，数据库在开始运行时才创建，以便在运行时中启动后台定期任务
+    pub fn new(config: Config) -> Server {
+        Server { config, db: None, persistence: false }
+    }
+
+    /// 使用已有的数据库，服务端运行期间调用方仍然可以直接读写它。
+    /// 数据库相关的配置以 db 创建时的为准，config 中只有监听地址与 io 线程的配置生效
+    pub fn db(mut self, db: Db) -> Server {
+        self.db = Some(db);
+        self
+    }
+
+    /// 开始运行时是否从快照文件（`dbfilename`）加载数据，默认不加载
+    pub fn persistence(mut self, enabled: bool) -> Server {
+        self.persistence = enabled;
+        self
+    }
+
+    /// 监听配置中的地址并运行，何时返回见 [`run`]。监听失败或者快照加载失败时返回错误
+    pub async fn run(self, shutdown: impl Future) -> crate::Result<()> {
+        let addr = self.config.addr();
+        let io_threads = self.config.io_threads;
+        // 只有一个 io 线程时在主运行时中接受连接
+        let listener = match io_threads {
+            1 => Some(backend::bind(&addr, false).await?),
+            _ => None,
+        };
+        println!("start server on {} with {} io threads...", addr, io_threads);
+        self.start(listener, shutdown).await
+    }
+
+    /// 在已经监听的 listener 上运行，何时返回见 [`run`]。所有连接都由 listener 接受，不启动 io 线程
+    pub async fn serve(self, listener: Listener, shutdown: impl Future) -> crate::Result<()> {
+        self.start(Some(listener), shutdown).await
+    }
+
+    /// 没有 listener 时按配置启动 io 线程
+    async fn start(self, listener: Option<Listener>, shutdown: impl Future) -> crate::Result<()> {
+        let Server { config, db, persistence } = self;
+        let addr = config.addr();
+        let (io_threads, pinning) = (config.io_threads, config.io_threads_pinning);
+        let db = db.unwrap_or_else(|| Db::with_config(config));
+        if persistence {
+            let path = db.snapshot_path();
+            let keys = rdb::load(&db).map_err(|err| format!("failed to load {}: {}", path.display(), err))?;
+            println!("loaded {} keys from {}", keys, path.display());
+        }
+
+        // drop 时通知所有连接退出
+        let (notify_shutdown, notify_rx) = broadcast::channel(1);
+        // 每个连接任务持有一个 sender，全部 drop 后 receiver 返回 None，说明所有连接都已退出
+        let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel::<()>(1);
+        // 收到 SHUTDOWN 命令的连接通过它通知主循环
+        let (shutdown_cmd_tx, mut shutdown_cmd_rx) = mpsc::channel::<()>(1);
+
+        let tasks = Tasks { db, notify_shutdown: notify_rx, shutdown_complete_tx, shutdown_cmd_tx };
+        if listener.is_none() {
+            start_io_threads(&addr, io_threads, pinning, &tasks).await?;
+        }
+        tokio::select! {
+            _ = accept(listener.as_ref(), &tasks) => {},
+            _ = shutdown => {},
+            _ = shutdown_cmd_rx.recv() => println!("received SHUTDOWN, shutting down..."),
+        }
+
+        // 停止接受新连接，通知已有连接退出，并等待它们处理完正在执行的命令
+        drop(listener);
+        drop(tasks);
+        drop(notify_shutdown);
+        let _ = shutdown_complete_rx.recv().await;
+        println!("bye");
+        Ok(())
+    }
+}
+
+/// 在主运行时中接受连接。连接由 io 线程接受时没有 listener，一直等待到退出
+async fn accept(listener: Option<&backend::Listener>, tasks: &Tasks) {
+    match listener {
+        Some(listener) => backend::accept_loop(listener, tasks).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 启动 threads 个 io 线程，每个线程运行自己的单线程运行时，并各自监听 addr（SO_REUSEPORT），
+/// 由内核把新连接分给它们。连接在接受它的线程上处理，所有线程共用同一个 [`Db`]。
+///
+/// pinning 为 true 时依次把线程绑定到各个 CPU 核心。所有线程都开始监听之后返回，任何一个线程监听失败时返回错误
+async fn start_io_threads(addr: &str, threads: usize, pinning: bool, tasks: &Tasks) -> io::Result<()> {
+    let cores = match pinning {
+        true => core_affinity::get_core_ids().unwrap_or_default(),
+        false => vec![],
+    };
+    let mut listening = Vec::with_capacity(threads);
+    for i in 0..threads {
+        let (ready, ready_rx) = oneshot::channel();
+        let core = (!cores.is_empty()).then(|| cores[i % cores.len()]);
+        let (addr, tasks) = (addr.to_string(), tasks.clone());
+        thread::Builder::new().name(format!("io-thread-{}", i)).spawn(move || {
+            if let Some(core) = core {
+                core_affinity::set_for_current(core);
+            }
+            backend::block_on_local(io_thread(addr, tasks, ready));
+        })?;
+        listening.push(ready_rx);
+    }
+    for ready in listening {
+        ready.await.map_err(|_| io::Error::other("io thread exited before listening"))??;
+    }
+    Ok(())
+}
+
+/// 一个 io 线程：接受分到这个线程的连接，直到服务端退出
+async fn io_thread(addr: String, tasks: Tasks, ready: oneshot::Sender<io::Result<()>>) {
+    let listener = match backend::bind(&addr, true).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            let _ = ready.send(Err(err));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(()));
+    // 运行时退出时会取消其中的任务，这个线程上的连接都退出之后才能返回，为此单独计数。
+    // tasks 中主循环的 sender 留到最后再 drop
+    let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel::<()>(1);
+    let local = Tasks { shutdown_complete_tx, ..tasks.clone() };
+    let mut shutdown = Shutdown::new(local.notify_shutdown.resubscribe());
+    tokio::select! {
+        _ = backend::accept_loop(&listener, &local) => {},
+        _ = shutdown.recv() => {},
+    }
+    drop(listener);
+    drop(local);
+    let _ = shutdown_complete_rx.recv().await;
+    drop(tasks);
+}
+
+/// 解析监听的地址，有多个时使用第一个
+fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?.next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to bind"))
+}
+
+/// 创建连接任务所需的共享状态
+struct Tasks {
+    db: Db,
+    /// 主循环 drop sender 时收到通知，每个连接各自订阅一份
+    notify_shutdown: broadcast::Receiver<()>,
+    shutdown_complete_tx: mpsc::Sender<()>,
+    shutdown_cmd_tx: mpsc::Sender<()>,
+}
+
+impl Clone for Tasks {
+    fn clone(&self) -> Self {
+        Tasks {
+            db: self.db.clone(),
+            notify_shutdown: self.notify_shutdown.resubscribe(),
+            shutdown_complete_tx: self.shutdown_complete_tx.clone(),
+            shutdown_cmd_tx: self.shutdown_cmd_tx.clone(),
+        }
+    }
+}
+
+impl Tasks {
+    /// 为新连接准备任务所需的状态
+    fn task(&self) -> Task {
+        Task {
+            // 增加一次引用计数
+            db: self.db.clone(),
+            shutdown: Shutdown::new(self.notify_shutdown.resubscribe()),
+            shutdown_cmd_tx: self.shutdown_cmd_tx.clone(),
+            shutdown_complete: self.shutdown_complete_tx.clone(),
+        }
+    }
+}
+
+/// 一个连接任务持有的状态
+struct Task {
+    db: Db,
+    shutdown: Shutdown,
+    shutdown_cmd_tx: mpsc::Sender<()>,
+    /// 任务结束时随之 drop，主循环据此判断所有连接是否都已退出
+    shutdown_complete: mpsc::Sender<()>,
+}
+
+impl Task {
+    async fn run<S: AsyncRead + Unpin>(self, connection: Connection<S>, output: Output, peer: SocketAddr) {
+        let Task { db, shutdown, shutdown_cmd_tx, shutdown_complete } = self;
+        if let Err(err) = process(connection, output, peer, db, shutdown, shutdown_cmd_tx).await {
+            println!("connection error: {}", err);
+        }
+        drop(shutdown_complete);
+    }
+}
+
+/// 默认的后端：tokio 的多线程运行时与 TcpListener
+#[cfg(not(feature = "uring"))]
+mod backend {
+    use std::{future::Future, io};
+
+    use tokio::{net::{TcpListener, TcpSocket}, runtime};
+    use crate::connection::{Connection, Output};
+
+    use super::Tasks;
+
+    pub type Listener = TcpListener;
+
+    pub fn block_on<F: Future>(future: F) -> F::Output {
+        runtime::Runtime::new().expect("failed to build the tokio runtime").block_on(future)
+    }
+
+    /// io 线程使用的单线程运行时
+    pub fn block_on_local<F: Future>(future: F) -> F::Output {
+        runtime::Builder::new_current_thread().enable_all().build()
+            .expect("failed to build the tokio runtime")
+            .block_on(future)
+    }
+
+    /// reuseport 为 true 时设置 SO_REUSEPORT，多个线程可以监听同一个地址
+    pub async fn bind(addr: &str, reuseport: bool) -> io::Result<TcpListener> {
+        if !reuseport {
+            return TcpListener::bind(addr).await;
+        }
+        let addr = super::resolve(addr)?;
+        let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+        socket.set_reuseaddr(true)?;
+        socket.set_reuseport(true)?;
+        socket.bind(addr)?;
+        socket.listen(1024)
+    }
+
+    pub async fn accept_loop(listener: &TcpListener, tasks: &Tasks) {
+        loop {
+            let (socket, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    println!("accept error: {}", err);
+                    continue;
+                }
+            };
+            let (reader, writer) = socket.into_split();
+            let task = tasks.task();
+            // 一个 tokio 任务是一个异步绿色线程，通过 tokio::spawn 创建，返回 JoinHandle 句柄
+            // 创建的任务被调度到执行器中。
+            //  Tokio 创建一个任务时，该任务类型的生命周期必须是 'static。所以这里用 move 转移所有权
+            // 使用 move 后，数据只能被 一个任务使用
+            tokio::spawn(async move {
+                task.run(Connection::new(reader), Output::new(writer), peer).await
+            });
+        }
+    }
+}
+
+/// io_uring 后端：所有连接都在 tokio-uring 的单线程运行时中处理，读写经由 [`UringStream`] 适配
+///
+/// [`UringStream`]: crate::connection::UringStream
+#[cfg(feature = "uring")]
+mod backend {
+    use std::{future::Future, io};
+
+    use tokio_uring::net::TcpListener;
+    use crate::connection::{Connection, Output, UringStream};
+
+    use super::Tasks;
+
+    pub type Listener = TcpListener;
+
+    pub fn block_on<F: Future>(future: F) -> F::Output {
+        tokio_uring::start(future)
+    }
+
+    /// tokio-uring 的运行时本来就是单线程的
+    pub fn block_on_local<F: Future>(future: F) -> F::Output {
+        tokio_uring::start(future)
+    }
+
+    /// tokio-uring 总是设置 SO_REUSEPORT，reuseport 不起作用
+    pub async fn bind(addr: &str, _reuseport: bool) -> io::Result<TcpListener> {
+        TcpListener::bind(super::resolve(addr)?)
+    }
+
+    pub async fn accept_loop(listener: &TcpListener, tasks: &Tasks) {
+        loop {
+            let (socket, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    println!("accept error: {}", err);
+                    continue;
+                }
+            };
+            let (reader, writer) = UringStream::new(socket).into_split();
+            let task = tasks.task();
+            // io_uring 的 socket 不能跨线程，任务只能留在当前线程
+            tokio_uring::spawn(async move {
+                task.run(Connection::new(reader), Output::new_local(writer), peer).await
+            });
+        }
+    }
+}
+
+/// 处理一个客户端连接上的所有请求，直到客户端断开或者服务端退出。
+///
+/// 只在等待请求时响应退出信号，已经读到的命令会执行完并回复。
+/// 订阅了频道的连接在等待请求的同时，还会把订阅到的消息推送给客户端。
+/// 回复都经过输出队列写出，输出缓冲区超出限制的连接会被直接断开，见 [`Output`]
+async fn process<S: AsyncRead + Unpin>(mut connection: Connection<S>, mut output: Output, peer: SocketAddr, db: Db, mut shutdown: Shutdown, shutdown_cmd_tx: mpsc::Sender<()>) -> crate::Result<()> {
+    let _connected = db.stats().client_connected();
+    let client = db.clients().register(peer);
+    // default 用户不需要密码时自动登录
+    if db.acl().default_nopass() {
+        client.set_user(DEFAULT_USER.to_string());
+    }
+    let mut subscriber = Subscriber::new(db.clone());
+    let mut transaction = Transaction::new(db.clone());
+    // 这个连接最后一次写入之后的复制偏移量，WAIT 等待副本确认它
+    let mut write_offset = 0;
+    let result: crate::Result<()> = async {
+        // 使用 `read_frame` 方法从连接获取一个数据帧：一条redis命令 + 相应的数据
+        // 通过 while 连续处理一个 tcp 内的请求
+        while !shutdown.is_shutdown() {
+            // CONFIG SET 修改的限制从下一条请求开始生效
+            connection.set_limits(db.proto_limits());
+            let subscribed = subscriber.is_active();
+            let frame = tokio::select! {
+                res = connection.read_frame() => match res? {
+                    Some(frame) => frame,
+                    None => return Ok(()),
+                },
+                message = subscriber.recv() => {
+                    output.write_frames(&[message], connection.protocol(), output_limit(&db, &subscriber))?;
+                    continue;
+                },
+                _ = shutdown.recv() => return Ok(()),
+                // 被 CLIENT KILL 断开
+                _ = client.killed() => return Ok(()),
+                // 空闲超时，订阅了频道的连接只接收消息，不受影响
+                _ = client.idle_timeout(|| db.idle_timeout()), if !subscribed => return Ok(()),
+            };
+            // 客户端使用 pipeline 时，缓冲区中可能已经有多条完整的命令。
+            // 回复依次编码到输出缓冲区，把它们都执行完再一起放进输出队列，不必每条命令都等待一次 socket
+            let mut next = Some(frame);
+            while let Some(frame) = next {
+                let mut protocol = connection.protocol();
+                // 解析命令会消耗 frame，留一份用于记录慢查询日志。frame 中的数据是 Bytes，clone 只增加引用计数
+                let command = frame.clone();
+                let shutdown_requested = match frame {
+                    // 与 redis 一样，空的多元素请求 `*0\r\n` 直接忽略，不回复
+                    Frame::Array(items) if items.is_empty() => false,
+                    frame => match Command::from_frame(frame) {
+                        Ok(cmd) => {
+                            // 事务中的 SHUTDOWN 不会执行
+                            let shutdown_requested = matches!(cmd, Command::Shutdown(_)) && !transaction.is_active();
+                            let state = State { db: &db, client: &client, subscriber: &mut subscriber, transaction: &mut transaction, shutdown: &mut shutdown, write_offset: &mut write_offset };
+                            let started_at = now_ms() / 1000;
+                            let started = Instant::now();
+                            execute(cmd, &command, state, &mut protocol, &mut output).await;
+                            db.record_duration(&command, started_at, started.elapsed());
+                            shutdown_requested
+                        },
+                        // 命令格式有误，回复错误信息，连接继续可用。事务中的命令出错会导致 EXEC 失败
+                        Err(err) => {
+                            transaction.fail();
+                            output.reply(protocol).error(&err.to_string());
+                            false
+                        },
+                    },
+                };
+                // HELLO 切换了协议，之前的回复已经按原来的协议编码
+                connection.set_protocol(protocol);
+                if shutdown_requested {
+                    output.flush(output_limit(&db, &subscriber))?;
+                    // 主循环已经在退出时 receiver 可能已被 drop，忽略错误
+                    let _ = shutdown_cmd_tx.try_send(());
+                    return Ok(());
+                }
+                next = match connection.read_buffered_frame() {
+                    Ok(next) => next,
+                    Err(err) => {
+                        // 后续数据有误，先把已经执行的命令的回复发出去
+                        output.flush(output_limit(&db, &subscriber))?;
+                        return Err(err);
+                    },
+                };
+            }
+            output.flush(output_limit(&db, &subscriber))?;
+            client.interacted();
+        }
+        Ok(())
+    }.await;
+    // 超出输出缓冲区限制时直接断开，丢弃未写出的回复，其他情况下等待回复写完
+    if !result.as_ref().is_err_and(|err| err.is::<OutputLimitExceeded>()) {
+        output.close().await;
+    }
+    db.replication().remove_replica(client.id());
+    result
+}
+
+/// 连接当前适用的输出缓冲区限制，订阅了频道或模式的连接使用 pubsub 的限制
+fn output_limit(db: &Db, subscriber: &Subscriber) -> OutputLimit {
+    let limits = db.output_limits();
+    if subscriber.is_active() { limits.pubsub } else { limits.normal }
+}
+
+/// 连接上的状态
+struct State<'a> {
+    db: &'a Db,
+    client: &'a ClientHandle<'a>,
+    subscriber: &'a mut Subscriber,
+    transaction: &'a mut Transaction,
+    shutdown: &'a mut Shutdown,
+    write_offset: &'a mut u64,
+}
+
+/// 执行一条命令，把回复按 protocol 编码到 output 的缓冲区。订阅相关的命令会回复多个 frame。
+///
+/// args 为客户端发来的原始 frame，执行前按其中的 key 检查权限
+async fn execute(cmd: Command, args: &Frame, state: State<'_>, protocol: &mut Protocol, output: &mut Output) {
+    let State { db, client, subscriber, transaction, shutdown, write_offset } = state;
+    client.touch(cmd.get_name());
+    // 登录之前只能执行 AUTH 与 HELLO
+    let user = match client.user() {
+        Some(user) => user,
+        None if matches!(cmd, Command::Auth(_) | Command::Hello(_)) => String::new(),
+        None => return output.reply(*protocol).error("NOAUTH Authentication required."),
+    };
+    // 未知命令直接回复错误，AUTH 与 HELLO 总是可以执行
+    if !matches!(cmd, Command::Unknown(_) | Command::Auth(_) | Command::Hello(_)) {
+        if let Err(err) = db.acl().check(&user, &cmd, args) {
+            // 与格式有误的命令一样，事务中的命令没有权限时 EXEC 失败
+            transaction.fail();
+            return output.reply(*protocol).error(&err.to_string());
+        }
+    }
+    // 集群模式下访问的 key 不由本节点负责或者不在同一个槽时拒绝执行，与没有权限一样会导致 EXEC 失败
+    if let Some(cluster) = db.cluster() {
+        let checked = cluster.check(&cmd, args).and_then(|slot| match slot {
+            Some(slot) if transaction.is_active() => transaction.pin_slot(slot),
+            _ => Ok(()),
+        });
+        if let Err(redirect) = checked {
+            transaction.fail();
+            return output.reply(*protocol).error(&redirect.to_string());
+        }
+    }
+    // 未知命令的名字由客户端决定，不计入统计
+    if !matches!(cmd, Command::Unknown(_)) {
+        db.stats().record_command(cmd.get_name());
+    }
+    // 写命令按请求的长度计入复制偏移量。EXEC、脚本可能写入，同样计入；事务中排队的命令在 EXEC 时才算
+    let write = cmd.categories().contains(Categories::WRITE) || matches!(cmd, Command::Eval(_));
+    if matches!(cmd, Command::Exec(_)) || (write && !transaction.is_active()) {
+        *write_offset = db.replication().advance(args.encoded_len() as u64);
+    }
+    let response = match cmd {
+        Command::Subscribe(cmd) if !transaction.is_active() => return write_all(output, cmd.apply(subscriber), *protocol),
+        Command::Unsubscribe(cmd) if !transaction.is_active() => return write_all(output, cmd.apply(subscriber), *protocol),
+        // RESP3 的订阅消息是 push 类型，可以与普通回复区分开，只有 RESP2 需要限制可执行的命令
+        cmd if subscriber.is_active() && *protocol == Protocol::Resp2 => cmd.apply_subscribed(),
+        Command::Multi(cmd) => cmd.apply(transaction),
+        Command::Exec(cmd) => cmd.apply(transaction, protocol),
+        Command::Discard(cmd) => cmd.apply(transaction),
+        Command::Watch(cmd) => cmd.apply(transaction),
+        // MULTI 之后的命令只排队
+        cmd if transaction.is_active() => transaction.queue(cmd),
+        Command::Unwatch(cmd) => cmd.apply(transaction),
+        Command::Client(cmd) => cmd.apply(db, client),
+        Command::Auth(cmd) => cmd.apply(db, client),
+        Command::Acl(cmd) => cmd.apply(db, client),
+        // 副本的 ACK 不回复
+        Command::ReplConf(cmd) => match cmd.apply(db, client.id()) {
+            Some(reply) => reply,
+            None => return,
+        },
+        // 等待期间不持有任何锁，其他连接的命令照常执行
+        Command::Debug(Debug::Sleep(duration)) => {
+            tokio::time::sleep(duration).await;
+            Frame::Simple("OK".into())
+        },
+        // 阻塞期间同样不持有锁；服务端退出或者连接被 CLIENT KILL 时放弃等待
+        Command::XRead(cmd) if cmd.is_blocking() => tokio::select! {
+            reply = cmd.block_on(db, protocol) => reply,
+            _ = shutdown.recv() => Frame::Null,
+            _ = client.killed() => Frame::Null,
+        },
+        Command::LMove(cmd) if cmd.is_blocking() => tokio::select! {
+            reply = cmd.block_on(db, protocol) => reply,
+            _ = shutdown.recv() => Frame::Null,
+            _ = client.killed() => Frame::Null,
+        },
+        Command::Wait(cmd) if cmd.is_blocking() => {
+            let offset = *write_offset;
+            tokio::select! {
+                reply = cmd.block_on(db, offset) => reply,
+                _ = shutdown.recv() => Frame::Integer(db.replication().acked(offset) as i64),
+                _ = client.killed() => Frame::Integer(db.replication().acked(offset) as i64),
+            }
+        },
+        // 回复简单的命令直接编码，不构造 Frame
+        cmd @ (Command::Get(_) | Command::Set(_)) => return cmd.apply_reply(db, output.reply(*protocol)),
+        cmd => cmd.apply(db, protocol),
+    };
+    output.reply(*protocol).frame(&response);
+}
+
+/// 依次编码多个回复
+fn write_all(output: &mut Output, frames: Vec<Frame>, protocol: Protocol) {
+    for frame in &frames {
+        output.reply(protocol).frame(frame);
+    }
+}
+
+#[cfg(all(test, not(feature = "uring")))]
+mod tests {
+    use bytes::Bytes;
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}, sync::oneshot};
+
+    use crate::{client, config::Config, db::Db};
+
+    use super::Server;
+
+    #[tokio::test]
+    async fn embedded() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(super::run(listener, stopped));
+
+        let mut client = client::connect(addr).await.unwrap();
+        client.set("foo", Bytes::from("bar")).await.unwrap();
+        assert_eq!(client.get("foo").await.unwrap(), Some(Bytes::from("bar")));
+        // 通知退出后，服务端等已有连接断开才会返回
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        assert!(client.get("foo").await.is_err());
+
+        // 与服务端共享数据库
+        let db = Db::new();
+        db.set(Bytes::from("shared"), Bytes::from("v"), None);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(Server::new(Config::default()).db(db.clone()).serve(listener, std::future::pending::<()>()));
+        let mut client = client::connect(addr).await.unwrap();
+        assert_eq!(client.get("shared").await.unwrap(), Some(Bytes::from("v")));
+        client.set("foo", Bytes::from("bar")).await.unwrap();
+        assert_eq!(db.get(b"foo").unwrap(), Some(Bytes::from("bar")));
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn io_threads() {
+        // 各个 io 线程要监听同一个端口，先找一个空闲的端口
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = Config { bind: "127.0.0.1".into(), port, io_threads: 2, io_threads_pinning: true, ..Config::default() };
+        let db = Db::new();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(Server::new(config).db(db.clone()).run(stopped));
+        let mut clients = vec![];
+        for i in 0..8 {
+            let mut client = loop {
+                match client::connect(("127.0.0.1", port)).await {
+                    Ok(client) => break client,
+                    Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+                }
+            };
+            client.set(&format!("k{}", i), Bytes::from(i.to_string())).await.unwrap();
+            clients.push(client);
+        }
+        // 不论连接由哪个线程接受，看到的都是同一个 Db
+        for (i, client) in clients.iter_mut().enumerate() {
+            assert_eq!(client.get(&format!("k{}", 7 - i)).await.unwrap(), Some(Bytes::from((7 - i).to_string())));
+        }
+        assert_eq!(db.dbsize(), 8);
+        // 退出时各个线程上的连接都被断开
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        for client in &mut clients {
+            assert!(client.get("k0").await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn wait_for_replica_ack() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let db = Db::new();
+        let server = tokio::spawn(Server::new(Config::default()).db(db.clone()).serve(listener, std::future::pending::<()>()));
+        let mut buf = [0; 64];
+        let mut writer = TcpStream::connect(addr).await.unwrap();
+        writer.write_all(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n").await.unwrap();
+        let n = writer.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"+OK\r\n");
+        let offset = db.replication().offset();
+        assert!(offset > 0);
+
+        // 还没有副本确认写入，WAIT 一直等待
+        writer.write_all(b"*3\r\n$4\r\nWAIT\r\n$1\r\n1\r\n$1\r\n0\r\n").await.unwrap();
+        let timeout = std::time::Duration::from_millis(50);
+        assert!(tokio::time::timeout(timeout, writer.read(&mut buf)).await.is_err());
+
+        // 副本的 ACK 不回复，确认了写入之后 WAIT 返回
+        let mut replica = TcpStream::connect(addr).await.unwrap();
+        let ack = offset.to_string();
+        replica.write_all(format!("*3\r\n$8\r\nREPLCONF\r\n$3\r\nACK\r\n${}\r\n{}\r\n", ack.len(), ack).as_bytes()).await.unwrap();
+        let n = writer.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b":1\r\n");
+        replica.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        let n = replica.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"+PONG\r\n");
+        assert_eq!(db.replication().replicas(), 1);
+
+        // 副本断开后不再计数
+        drop(replica);
+        while db.replication().replicas() > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        writer.write_all(b"*3\r\n$4\r\nWAIT\r\n$1\r\n1\r\n$2\r\n20\r\n").await.unwrap();
+        let n = writer.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b":0\r\n");
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn empty_multibulk() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(Server::new(Config::default()).serve(listener, std::future::pending::<()>()));
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        // `*0` 被忽略，只有 PING 有回复
+        socket.write_all(b"*0\r\n*1\r\n$4\r\nPING\r\n").await.unwrap();
+        let mut buf = [0; 64];
+        let n = socket.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"+PONG\r\n");
+        // 长度带 `+` 号是协议错误，连接被断开
+        socket.write_all(b"*+1\r\n$4\r\nPING\r\n").await.unwrap();
+        assert_eq!(socket.read(&mut buf).await.unwrap(), 0);
+        server.abort();
+    }
+}
@@ -0,0 +1,55 @@
+//! 服务端的优雅退出。
+//!
+//! 主循环持有 `broadcast::Sender`，需要退出时将其 drop，所有连接上的 [`Shutdown`] 都会收到通知。
+//! 连接任务只在等待新请求时响应通知，正在执行的命令会先完成并回复，然后再退出。
+
+use tokio::sync::broadcast;
+
+/// 监听退出信号，每个连接持有一个
+#[derive(Debug)]
+pub struct Shutdown {
+    /// 是否已经收到过退出信号
+    is_shutdown: bool,
+    notify: broadcast::Receiver<()>,
+}
+
+impl Shutdown {
+    pub fn new(notify: broadcast::Receiver<()>) -> Shutdown {
+        Shutdown { is_shutdown: false, notify }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.is_shutdown
+    }
+
+    /// 等待退出信号，已经收到过时立即返回
+    pub async fn recv(&mut self) {
+        if self.is_shutdown {
+            return;
+        }
+        // 发送端被 drop 时会返回 Closed，同样视为退出信号
+        let _ = self.notify.recv().await;
+        self.is_shutdown = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast;
+
+    use super::Shutdown;
+
+    #[tokio::test]
+    async fn notify_on_drop() {
+        let (notify, _) = broadcast::channel(1);
+        let mut shutdowns = [Shutdown::new(notify.subscribe()), Shutdown::new(notify.subscribe())];
+        assert!(!shutdowns[0].is_shutdown());
+        drop(notify);
+        for shutdown in shutdowns.iter_mut() {
+            shutdown.recv().await;
+            assert!(shutdown.is_shutdown());
+            // 重复等待立即返回
+            shutdown.recv().await;
+        }
+    }
+}
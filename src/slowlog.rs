@@ -0,0 +1,182 @@
+//! 慢查询日志，供 `SLOWLOG` 命令查看。
+//!
+//! 连接的处理循环记录每条命令的执行时长（不包括读请求、写回复的时间），
+//! 超过 `slowlog-log-slower-than` 微秒的命令会被记录下来，最多保留 `slowlog-max-len` 条，超出时丢弃最早的记录。
+//! 与 redis 一样，参数过多、过长的命令只保留部分参数，避免日志占用过多内存。
+
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use bytes::{Bytes, BytesMut};
+
+use crate::frame::Frame;
+
+/// 默认的慢查询阈值（微秒）
+pub const DEFAULT_LOG_SLOWER_THAN: i64 = 10000;
+
+/// 默认最多保留的记录数
+pub const DEFAULT_MAX_LEN: usize = 128;
+
+/// 每条记录最多保留的参数个数
+const MAX_ARGC: usize = 32;
+
+/// 每个参数最多保留的字节数
+const MAX_ARG_LEN: usize = 128;
+
+/// 一条慢查询记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowLogEntry {
+    /// 递增的编号，重置日志后也不会重复
+    pub id: u64,
+    /// 命令开始执行的时间，unix 时间戳（秒）
+    pub timestamp: u64,
+    /// 执行时长（微秒）
+    pub duration: u64,
+    /// 命令及其参数，可能被截断
+    pub args: Vec<Bytes>,
+}
+
+impl SlowLogEntry {
+    /// `SLOWLOG GET` 中的一项：编号、时间戳、时长、参数组成的数组
+    pub fn to_frame(&self) -> Frame {
+        Frame::Array(vec![
+            Frame::Integer(self.id as i64),
+            Frame::Integer(self.timestamp as i64),
+            Frame::Integer(self.duration as i64),
+            Frame::Array(self.args.iter().cloned().map(Frame::Bulk).collect()),
+        ])
+    }
+}
+
+/// 慢查询日志，最新的记录在前
+#[derive(Default)]
+pub struct SlowLog {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: VecDeque<SlowLogEntry>,
+    next_id: u64,
+}
+
+impl SlowLog {
+    /// 记录一条执行了 duration 的命令，command 为客户端发来的 `Frame::Array`。
+    ///
+    /// threshold 为慢查询阈值（微秒），为负数时不记录，为 0 时记录所有命令
+    pub fn record(&self, command: &Frame, timestamp: u64, duration: Duration, threshold: i64, max_len: usize) {
+        let duration = duration.as_micros() as u64;
+        if threshold < 0 || duration < threshold as u64 {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.entries.push_front(SlowLogEntry { id, timestamp, duration, args: truncate_args(command) });
+        inner.entries.truncate(max_len);
+    }
+
+    /// 最新的 count 条记录，count 为 `None` 时返回所有记录
+    pub fn get(&self, count: Option<usize>) -> Vec<SlowLogEntry> {
+        let inner = self.inner.lock().unwrap();
+        let count = count.unwrap_or(inner.entries.len());
+        inner.entries.iter().take(count).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 清空日志，编号继续递增
+    pub fn reset(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+}
+
+/// 取出命令的参数，超过 [`MAX_ARGC`] 个时最后一个参数换成剩余参数的个数，
+/// 超过 [`MAX_ARG_LEN`] 字节的参数只保留开头的部分
+fn truncate_args(command: &Frame) -> Vec<Bytes> {
+    let frames = match command {
+        Frame::Array(frames) => &frames[..],
+        frame => std::slice::from_ref(frame),
+    };
+    let kept = if frames.len() > MAX_ARGC { MAX_ARGC - 1 } else { frames.len() };
+    let mut args: Vec<_> = frames[..kept]
+        .iter()
+        .map(|frame| {
+            let arg = match frame {
+                Frame::Bulk(data) => data.clone(),
+                Frame::Simple(s) => Bytes::from(s.clone()),
+                Frame::Integer(n) => Bytes::from(n.to_string()),
+                frame => Bytes::from(format!("{:?}", frame)),
+            };
+            if arg.len() <= MAX_ARG_LEN {
+                return arg;
+            }
+            let mut truncated = BytesMut::from(&arg[..MAX_ARG_LEN]);
+            truncated.extend_from_slice(format!("... ({} more bytes)", arg.len() - MAX_ARG_LEN).as_bytes());
+            truncated.freeze()
+        })
+        .collect();
+    if kept < frames.len() {
+        args.push(Bytes::from(format!("... ({} more arguments)", frames.len() - kept)));
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use crate::frame::Frame;
+
+    use super::SlowLog;
+
+    fn command(args: &[&str]) -> Frame {
+        Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::from(arg.to_string()))).collect())
+    }
+
+    #[test]
+    fn threshold_and_max_len() {
+        let log = SlowLog::default();
+        let ms = Duration::from_millis;
+        log.record(&command(&["get", "a"]), 1, ms(5), 10000, 2);
+        assert!(log.is_empty());
+        log.record(&command(&["get", "b"]), 2, ms(10), 10000, 2);
+        log.record(&command(&["get", "c"]), 3, ms(20), -1, 2);
+        log.record(&command(&["get", "d"]), 4, ms(0), 0, 2);
+        log.record(&command(&["get", "e"]), 5, ms(30), 10000, 2);
+        assert_eq!(log.len(), 2);
+
+        let entries = log.get(None);
+        assert_eq!(entries.iter().map(|entry| entry.id).collect::<Vec<_>>(), [2, 1]);
+        assert_eq!((entries[0].timestamp, entries[0].duration), (5, 30000));
+        assert_eq!(entries[0].args, [Bytes::from("get"), Bytes::from("e")]);
+        assert_eq!(log.get(Some(1)).len(), 1);
+
+        log.reset();
+        assert!(log.is_empty());
+        log.record(&command(&["ping"]), 6, ms(0), 0, 2);
+        assert_eq!(log.get(None)[0].id, 3);
+    }
+
+    #[test]
+    fn truncate() {
+        let log = SlowLog::default();
+        let long = "x".repeat(200);
+        let mut args = vec!["rpush", long.as_str()];
+        args.extend(["v"; 40]);
+        log.record(&command(&args), 0, Duration::ZERO, 0, 10);
+
+        let args = &log.get(None)[0].args;
+        assert_eq!(args.len(), 32);
+        assert_eq!(args[1], format!("{}... (72 more bytes)", "x".repeat(128)));
+        assert_eq!(args[30], "v");
+        assert_eq!(args[31], "... (11 more arguments)");
+    }
+}
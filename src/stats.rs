@@ -0,0 +1,140 @@
+//! 服务端的运行统计，供 `INFO` 命令展示。
+//!
+//! 统计保存在 `Db` 中，连接的处理循环记录连接数与执行的命令，键空间记录读命中与未命中。
+//! 计数都是原子变量，只有按命令统计的调用次数需要加锁。
+
+use std::{collections::HashMap, sync::{Mutex, atomic::{AtomicU64, AtomicUsize, Ordering}}, time::{Duration, Instant}};
+
+/// 运行统计
+pub struct Stats {
+    started_at: Instant,
+    /// 累计接受的连接数
+    connections_received: AtomicU64,
+    /// 当前的连接数
+    connected_clients: AtomicUsize,
+    /// 累计执行的命令数
+    commands_processed: AtomicU64,
+    /// 每个命令的调用次数
+    command_calls: Mutex<HashMap<String, u64>>,
+    /// 读 key 时 key 存在的次数
+    keyspace_hits: AtomicU64,
+    /// 读 key 时 key 不存在的次数
+    keyspace_misses: AtomicU64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            started_at: Instant::now(),
+            connections_received: AtomicU64::new(0),
+            connected_clients: AtomicUsize::new(0),
+            commands_processed: AtomicU64::new(0),
+            command_calls: Mutex::default(),
+            keyspace_hits: AtomicU64::new(0),
+            keyspace_misses: AtomicU64::new(0),
+        }
+    }
+}
+
+/// 连接存活期间持有，drop 时减少当前连接数
+pub struct ClientGuard<'a>(&'a Stats);
+
+impl Drop for ClientGuard<'_> {
+    fn drop(&mut self) {
+        self.0.connected_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Stats {
+    /// 服务端启动至今的时长
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// 记录一个新连接，返回的 guard 被 drop 时视为连接断开
+    pub fn client_connected(&self) -> ClientGuard<'_> {
+        self.connections_received.fetch_add(1, Ordering::Relaxed);
+        self.connected_clients.fetch_add(1, Ordering::Relaxed);
+        ClientGuard(self)
+    }
+
+    pub fn connections_received(&self) -> u64 {
+        self.connections_received.load(Ordering::Relaxed)
+    }
+
+    pub fn connected_clients(&self) -> usize {
+        self.connected_clients.load(Ordering::Relaxed)
+    }
+
+    /// 记录一次命令调用
+    pub fn record_command(&self, name: &str) {
+        self.commands_processed.fetch_add(1, Ordering::Relaxed);
+        let mut calls = self.command_calls.lock().unwrap();
+        match calls.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                calls.insert(name.to_owned(), 1);
+            },
+        }
+    }
+
+    pub fn commands_processed(&self) -> u64 {
+        self.commands_processed.load(Ordering::Relaxed)
+    }
+
+    /// 每个命令的调用次数，按命令名排序
+    pub fn command_calls(&self) -> Vec<(String, u64)> {
+        let mut calls: Vec<_> = self.command_calls
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, count)| (name.clone(), *count))
+            .collect();
+        calls.sort();
+        calls
+    }
+
+    /// 记录一次读 key，`hit` 表示 key 存在
+    pub(crate) fn record_lookup(&self, hit: bool) {
+        let counter = if hit { &self.keyspace_hits } else { &self.keyspace_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn keyspace_hits(&self) -> u64 {
+        self.keyspace_hits.load(Ordering::Relaxed)
+    }
+
+    pub fn keyspace_misses(&self) -> u64 {
+        self.keyspace_misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stats;
+
+    #[test]
+    fn counters() {
+        let stats = Stats::default();
+        let first = stats.client_connected();
+        {
+            let _second = stats.client_connected();
+            assert_eq!(stats.connected_clients(), 2);
+        }
+        assert_eq!(stats.connected_clients(), 1);
+        drop(first);
+        assert_eq!(stats.connected_clients(), 0);
+        assert_eq!(stats.connections_received(), 2);
+
+        for name in ["set", "get", "get"] {
+            stats.record_command(name);
+        }
+        assert_eq!(stats.commands_processed(), 3);
+        assert_eq!(stats.command_calls(), vec![("get".to_string(), 2), ("set".to_string(), 1)]);
+
+        stats.record_lookup(true);
+        stats.record_lookup(false);
+        stats.record_lookup(false);
+        assert_eq!((stats.keyspace_hits(), stats.keyspace_misses()), (1, 2));
+    }
+}
@@ -0,0 +1,251 @@
+//! 事务：MULTI、EXEC、DISCARD、WATCH。
+//!
+//! [`Transaction`] 是每个连接的事务状态。MULTI 之后的命令只是排队，EXEC 时持有 [`Db::exec_guard`]
+//! 依次执行，期间不会穿插其他连接的命令。WATCH 记录 key 当时的版本号，EXEC 时任一 key 的版本号变化则放弃执行。
+
+use bytes::Bytes;
+
+use crate::{cluster::Redirect, cmd::Command, db::Db, frame::{Frame, Protocol}};
+
+/// 一个连接的事务状态
+pub struct Transaction {
+    db: Db,
+    /// MULTI 之后排队的命令，`None` 表示不在事务中
+    queued: Option<Vec<Command>>,
+    /// 排队时有命令出错，EXEC 时放弃整个事务
+    failed: bool,
+    /// 集群模式下排队的命令访问的 key 所在的槽，见 [`Transaction::pin_slot`]
+    slot: Option<u16>,
+    /// WATCH 的 key 以及当时的版本号
+    watched: Vec<(Bytes, u64)>,
+}
+
+impl Transaction {
+    pub fn new(db: Db) -> Transaction {
+        Transaction { db, queued: None, failed: false, slot: None, watched: vec![] }
+    }
+
+    /// 是否处于 MULTI 之后、EXEC 之前
+    pub fn is_active(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    /// `MULTI`，开始一个事务
+    pub fn multi(&mut self) -> Frame {
+        if self.is_active() {
+            return Frame::Error("ERR MULTI calls can not be nested".into());
+        }
+        self.queued = Some(vec![]);
+        self.slot = None;
+        Frame::Simple("OK".into())
+    }
+
+    /// 集群模式下，事务中的命令在 EXEC 时一起执行，它们访问的 key 也必须位于同一个槽。
+    /// slot 为即将入队的命令访问的槽，与之前入队的命令不同时返回 [`Redirect::CrossSlot`]
+    pub fn pin_slot(&mut self, slot: u16) -> Result<(), Redirect> {
+        match *self.slot.get_or_insert(slot) == slot {
+            true => Ok(()),
+            false => Err(Redirect::CrossSlot),
+        }
+    }
+
+    /// 命令入队。未知命令以及不能在事务中执行的命令会让事务失败
+    pub fn queue(&mut self, cmd: Command) -> Frame {
+        let queued = match &mut self.queued {
+            Some(queued) => queued,
+            None => return Frame::Error("ERR not in a transaction".into()),
+        };
+        match cmd {
+            Command::Unknown(cmd) => {
+                self.failed = true;
+                cmd.apply()
+            },
+            Command::Subscribe(_) | Command::Unsubscribe(_) | Command::Shutdown(_) => {
+                self.failed = true;
+                Frame::Error("ERR Command not allowed inside a transaction".into())
+            },
+            cmd => {
+                queued.push(cmd);
+                Frame::Simple("QUEUED".into())
+            },
+        }
+    }
+
+    /// 事务中的命令格式有误，EXEC 时放弃整个事务
+    pub fn fail(&mut self) {
+        if self.is_active() {
+            self.failed = true;
+        }
+    }
+
+    /// `EXEC`，依次执行排队的命令，返回各命令回复组成的数组。
+    /// WATCH 的 key 被修改过时不执行，返回 Null。无论是否执行，都会取消所有 WATCH
+    pub fn exec(&mut self, protocol: &mut Protocol) -> Frame {
+        let queued = match self.queued.take() {
+            Some(queued) => queued,
+            None => return Frame::Error("ERR EXEC without MULTI".into()),
+        };
+        let failed = std::mem::take(&mut self.failed);
+        let watched = std::mem::take(&mut self.watched);
+        let response = if failed {
+            Frame::Error("EXECABORT Transaction discarded because of previous errors.".into())
+        } else {
+            let _guard = self.db.exec_guard();
+            if watched.iter().any(|(key, version)| self.db.version(key) != *version) {
+                Frame::Null
+            } else {
+                Frame::Array(queued.into_iter().map(|cmd| cmd.execute(&self.db, protocol)).collect())
+            }
+        };
+        for (key, _) in &watched {
+            self.db.unwatch(key);
+        }
+        response
+    }
+
+    /// `DISCARD`，放弃排队的命令，并取消所有 WATCH
+    pub fn discard(&mut self) -> Frame {
+        if self.queued.take().is_none() {
+            return Frame::Error("ERR DISCARD without MULTI".into());
+        }
+        self.failed = false;
+        self.unwatch()
+    }
+
+    /// `WATCH key [key ...]`
+    pub fn watch(&mut self, keys: Vec<Bytes>) -> Frame {
+        if self.is_active() {
+            return Frame::Error("ERR WATCH inside MULTI is not allowed".into());
+        }
+        for key in keys {
+            if self.watched.iter().all(|(watched, _)| *watched != key) {
+                let version = self.db.watch(&key);
+                self.watched.push((key, version));
+            }
+        }
+        Frame::Simple("OK".into())
+    }
+
+    /// `UNWATCH`，取消所有 WATCH
+    pub fn unwatch(&mut self) -> Frame {
+        for (key, _) in self.watched.drain(..) {
+            self.db.unwatch(&key);
+        }
+        Frame::Simple("OK".into())
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        self.unwatch();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{cmd::Command, db::Db, frame::{Frame, Protocol}};
+
+    use super::Transaction;
+
+    fn command(args: &[&str]) -> Command {
+        let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect());
+        Command::from_frame(frame).unwrap()
+    }
+
+    fn ok() -> Frame {
+        Frame::Simple("OK".into())
+    }
+
+    #[test]
+    fn multi_exec() {
+        let db = Db::new();
+        let mut protocol = Protocol::default();
+        let mut tx = Transaction::new(db.clone());
+        assert!(matches!(tx.exec(&mut protocol), Frame::Error(_)));
+        assert_eq!(tx.multi(), ok());
+        assert!(matches!(tx.multi(), Frame::Error(_)));
+        assert_eq!(tx.queue(command(&["set", "a", "1"])), Frame::Simple("QUEUED".into()));
+        assert_eq!(tx.queue(command(&["incr", "a"])), Frame::Simple("QUEUED".into()));
+        assert_eq!(tx.queue(command(&["lpush", "a", "x"])), Frame::Simple("QUEUED".into()));
+        // 入队时不执行
+        assert!(!db.exists(b"a"));
+        let reply = tx.exec(&mut protocol);
+        assert!(matches!(&reply, Frame::Array(replies) if replies[..2] == [ok(), Frame::Integer(2)] && matches!(replies[2], Frame::Error(_))));
+        assert!(!tx.is_active());
+
+        // 入队出错时整个事务被放弃
+        tx.multi();
+        tx.queue(command(&["set", "a", "100"]));
+        assert!(matches!(tx.queue(command(&["nosuchcommand"])), Frame::Error(_)));
+        assert!(matches!(tx.exec(&mut protocol), Frame::Error(err) if err.starts_with("EXECABORT")));
+        assert_eq!(db.get(b"a").unwrap(), Some(Bytes::from("2")));
+
+        tx.multi();
+        tx.queue(command(&["set", "a", "100"]));
+        assert_eq!(tx.discard(), ok());
+        assert!(matches!(tx.discard(), Frame::Error(_)));
+        assert_eq!(db.get(b"a").unwrap(), Some(Bytes::from("2")));
+    }
+
+    #[test]
+    fn pin_slot() {
+        let mut tx = Transaction::new(Db::new());
+        tx.multi();
+        tx.pin_slot(1).unwrap();
+        tx.pin_slot(1).unwrap();
+        assert!(tx.pin_slot(2).is_err());
+        tx.discard();
+        // 新的事务重新开始计算
+        tx.multi();
+        tx.pin_slot(2).unwrap();
+    }
+
+    #[test]
+    fn watch() {
+        let db = Db::new();
+        let mut protocol = Protocol::default();
+        let mut tx = Transaction::new(db.clone());
+
+        // 没有被修改
+        tx.watch(vec![Bytes::from("a"), Bytes::from("b")]);
+        db.set(Bytes::from("other"), Bytes::from("1"), None);
+        tx.multi();
+        tx.queue(command(&["set", "a", "1"]));
+        assert_eq!(tx.exec(&mut protocol), Frame::Array(vec![ok()]));
+
+        // 被其他连接修改
+        tx.watch(vec![Bytes::from("a")]);
+        db.set(Bytes::from("a"), Bytes::from("2"), None);
+        tx.multi();
+        assert!(matches!(tx.watch(vec![Bytes::from("a")]), Frame::Error(_)));
+        tx.queue(command(&["set", "a", "3"]));
+        assert_eq!(tx.exec(&mut protocol), Frame::Null);
+        assert_eq!(db.get(b"a").unwrap(), Some(Bytes::from("2")));
+
+        // EXEC 之后 WATCH 被取消，删除、不存在的 key 被创建都算作修改
+        for modify in [&["del", "a"][..], &["set", "b", "1"], &["expire", "a", "100"]] {
+            db.set(Bytes::from("a"), Bytes::from("2"), None);
+            tx.watch(vec![Bytes::from("a"), Bytes::from("b")]);
+            command(modify).apply(&db, &mut protocol);
+            db.del(b"b");
+            tx.multi();
+            assert_eq!(tx.exec(&mut protocol), Frame::Null, "{:?}", modify);
+        }
+
+        // 已经过期的 key 也算作被修改
+        db.set(Bytes::from("a"), Bytes::from("2"), Some(crate::db::now_ms() + 20));
+        tx.watch(vec![Bytes::from("a")]);
+        std::thread::sleep(std::time::Duration::from_millis(30));
+        tx.multi();
+        assert_eq!(tx.exec(&mut protocol), Frame::Null);
+
+        // UNWATCH 之后修改不影响 EXEC
+        tx.watch(vec![Bytes::from("a")]);
+        assert_eq!(tx.unwatch(), ok());
+        db.set(Bytes::from("a"), Bytes::from("3"), None);
+        tx.multi();
+        assert_eq!(tx.exec(&mut protocol), Frame::Array(vec![]));
+    }
+}
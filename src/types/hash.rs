@@ -0,0 +1,333 @@
+//! 哈希表。与 redis 一样有两种编码：
+//! - field 少且都较短时用 ziplist，field、value 依次交替存放；
+//! - 任一阈值被超过后转换为 Dict，此后不再转换回去。
+
+use std::collections::HashSet;
+
+use bytes::Bytes;
+use rand::{Rng, seq::SliceRandom};
+
+use crate::{ds::{MemSize, dict::Dict, perfstr::{SmartString, sds::SDS}, ziplist::ZipList}, object::{ObjectEncoding, ZipLimits}};
+
+use super::{dict_mem_usage, entry_bytes};
+
+#[derive(Clone)]
+pub enum Hash {
+    ZipList(ZipList),
+    HashTable(Dict<SDS, Bytes>),
+}
+
+impl Default for Hash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hash {
+    /// 新建的哈希表总是 ziplist 编码
+    pub fn new() -> Self {
+        Hash::ZipList(ZipList::new())
+    }
+
+    /// field 数量
+    pub fn len(&self) -> usize {
+        match self {
+            Hash::ZipList(zl) => zl.get_entry_cnt() / 2,
+            Hash::HashTable(dict) => dict.value_cnt() as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn encoding(&self) -> ObjectEncoding {
+        match self {
+            Hash::ZipList(_) => ObjectEncoding::ZipList,
+            Hash::HashTable(_) => ObjectEncoding::HashTable,
+        }
+    }
+
+    /// 占用内存的估计值（字节）
+    pub fn mem_usage(&self) -> usize {
+        match self {
+            Hash::ZipList(zl) => zl.blob_len(),
+            Hash::HashTable(dict) => dict_mem_usage(dict, |field, value| field.alloc_size() + value.len()),
+        }
+    }
+
+    /// 设置 field 的值，返回是否为新增的 field
+    pub fn insert(&mut self, field: Bytes, value: Bytes, limits: &ZipLimits) -> bool {
+        if let Hash::ZipList(zl) = self {
+            let len = zl.get_entry_cnt() / 2;
+            if limits.exceeded_by(len + 1, &field) || limits.exceeded_by(len, &value) {
+                self.convert();
+            }
+        }
+        match self {
+            Hash::ZipList(zl) => {
+                match pairs(zl).iter().position(|(f, _)| *f == field) {
+                    Some(idx) => {
+                        // 替换 field 之后的 value
+                        zl.delete_at(2 * idx + 1).unwrap();
+                        zl.insert_at(2 * idx + 1, &value).unwrap();
+                        false
+                    },
+                    None => {
+                        zl.push_tail_string(&field).unwrap();
+                        zl.push_tail_string(&value).unwrap();
+                        true
+                    },
+                }
+            },
+            Hash::HashTable(dict) => dict.insert(SDS::new(&field), value).is_none(),
+        }
+    }
+
+    /// 查询 field 的值。Dict 查找时会顺带做一步 rehash，所以需要可变引用
+    pub fn get(&mut self, field: &[u8]) -> Option<Bytes> {
+        match self {
+            Hash::ZipList(zl) => pairs(zl).into_iter().find(|(f, _)| f == field).map(|(_, v)| v),
+            Hash::HashTable(dict) => dict.get(&SDS::new(field)).cloned(),
+        }
+    }
+
+    /// 根据 field 原来的值（不存在时为 `None`）用 f 计算新的值并写入，返回新的值。f 返回错误时不做修改。
+    /// Dict 编码且 field 已存在时原地修改，不需要重新插入
+    pub fn update<E>(&mut self, field: Bytes, f: impl FnOnce(Option<&[u8]>) -> Result<Bytes, E>, limits: &ZipLimits) -> Result<Bytes, E> {
+        if let Hash::HashTable(dict) = self {
+            if let Some(value) = dict.get_mut(&field[..]) {
+                let new = f(Some(value))?;
+                *value = new.clone();
+                return Ok(new);
+            }
+        }
+        let new = f(self.get(&field).as_deref())?;
+        self.insert(field, new.clone(), limits);
+        Ok(new)
+    }
+
+    /// 随机返回若干 (field, value)，对应 HRANDFIELD：
+    /// count 为正数时 field 互不相同，最多返回全部；为负数时可能重复，正好返回 |count| 个
+    pub fn random_entries(&mut self, count: i64) -> Vec<(Bytes, Bytes)> {
+        let len = self.len();
+        if len == 0 || count == 0 {
+            return vec![];
+        }
+        let mut rng = rand::thread_rng();
+        if count > 0 && count as usize >= len {
+            return self.entries();
+        }
+        let dict = match self {
+            Hash::ZipList(zl) => {
+                let pairs = pairs(zl);
+                return if count < 0 {
+                    (0..count.unsigned_abs()).map(|_| pairs[rng.gen_range(0..len)].clone()).collect()
+                } else {
+                    pairs.choose_multiple(&mut rng, count as usize).cloned().collect()
+                };
+            },
+            Hash::HashTable(dict) => dict,
+        };
+        let entry = |(f, v): (&SDS, &Bytes)| (Bytes::copy_from_slice(f.val()), v.clone());
+        if count < 0 {
+            return (0..count.unsigned_abs()).map(|_| entry(dict.random_entry().unwrap())).collect();
+        }
+        let count = count as usize;
+        // 要取的数量接近总数时，随机抽取会频繁抽到重复的 field，与 redis 一样改为从全部中挑选
+        if count * 3 > len {
+            let mut entries: Vec<_> = dict.iter().map(entry).collect();
+            entries.shuffle(&mut rng);
+            entries.truncate(count);
+            return entries;
+        }
+        let mut picked = HashSet::with_capacity(count);
+        let mut entries = Vec::with_capacity(count);
+        while entries.len() < count {
+            let (field, value) = entry(dict.random_entry().unwrap());
+            if picked.insert(field.clone()) {
+                entries.push((field, value));
+            }
+        }
+        entries
+    }
+
+    /// 删除 field，返回其是否存在
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        match self {
+            Hash::ZipList(zl) => match pairs(zl).iter().position(|(f, _)| f == field) {
+                Some(idx) => {
+                    zl.delete_at(2 * idx + 1).unwrap();
+                    zl.delete_at(2 * idx).unwrap();
+                    true
+                },
+                None => false,
+            },
+            Hash::HashTable(dict) => dict.remove(&SDS::new(field)).is_some(),
+        }
+    }
+
+    /// 所有的 (field, value)，顺序不确定
+    pub fn entries(&self) -> Vec<(Bytes, Bytes)> {
+        match self {
+            Hash::ZipList(zl) => pairs(zl),
+            Hash::HashTable(dict) => dict
+                .iter()
+                .map(|(f, v)| (Bytes::copy_from_slice(f.val()), v.clone()))
+                .collect(),
+        }
+    }
+
+    /// 从 cursor 开始遍历一部分 (field, value)，返回下一次遍历的 cursor，0 表示遍历完成，语义同 [`Dict::scan`]。
+    /// ziplist 编码时元素很少，与 redis 一样一次返回全部
+    pub fn scan(&self, cursor: u64, count: usize, mut filter: impl FnMut(&[u8]) -> bool) -> (u64, Vec<(Bytes, Bytes)>) {
+        let dict = match self {
+            Hash::ZipList(zl) => {
+                let mut pairs = pairs(zl);
+                pairs.retain(|(f, _)| filter(f));
+                return (0, pairs);
+            },
+            Hash::HashTable(dict) => dict,
+        };
+        let mut cursor = cursor;
+        let mut entries = vec![];
+        for _ in 0..count.saturating_mul(10).max(1) {
+            cursor = dict.scan(cursor, |f, v| {
+                if filter(f.val()) {
+                    entries.push((Bytes::copy_from_slice(f.val()), v.clone()));
+                }
+            });
+            if cursor == 0 || entries.len() >= count {
+                break;
+            }
+        }
+        (cursor, entries)
+    }
+
+    /// 转换为 Dict 编码
+    fn convert(&mut self) {
+        let mut dict = Dict::new();
+        for (f, v) in self.entries() {
+            dict.insert(SDS::new(&f), v);
+        }
+        *self = Hash::HashTable(dict);
+    }
+}
+
+/// ziplist 中依次交替存放的 field、value
+fn pairs(zl: &ZipList) -> Vec<(Bytes, Bytes)> {
+    let mut values = zl.values().map(entry_bytes);
+    let mut pairs = Vec::with_capacity(zl.get_entry_cnt() / 2);
+    while let (Some(f), Some(v)) = (values.next(), values.next()) {
+        pairs.push((f, v));
+    }
+    pairs
+}
+
+impl MemSize for Hash {
+    fn mem_size(&self) -> usize {
+        match self {
+            Hash::ZipList(zl) => zl.mem_size(),
+            Hash::HashTable(dict) => dict.mem_size(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bytes::Bytes;
+
+    use crate::object::{ObjectEncoding, ZipLimits};
+
+    use super::Hash;
+
+    #[test]
+    fn both_encodings() {
+        let limits = ZipLimits { max_entries: 8, max_value: 16 };
+        let mut hash = Hash::new();
+        for i in 0..16 {
+            assert!(hash.insert(Bytes::from(format!("f{}", i)), Bytes::from(i.to_string()), &limits));
+            if i < 8 {
+                assert_eq!(hash.encoding(), ObjectEncoding::ZipList);
+            }
+            assert!(!hash.insert(Bytes::from(format!("f{}", i)), Bytes::from(format!("v{}", i)), &limits));
+            assert_eq!(hash.len(), i + 1);
+        }
+        assert_eq!(hash.encoding(), ObjectEncoding::HashTable);
+
+        let mut entries = hash.entries();
+        entries.sort();
+        assert_eq!(entries[0], (Bytes::from("f0"), Bytes::from("v0")));
+        assert_eq!(hash.get(b"f3"), Some(Bytes::from("v3")));
+        assert!(hash.remove(b"f3"));
+        assert!(!hash.remove(b"f3"));
+        assert_eq!(hash.get(b"f3"), None);
+
+        let mut small = Hash::new();
+        small.insert(Bytes::from("a"), Bytes::from("1"), &limits);
+        small.insert(Bytes::from("b"), Bytes::from("2"), &limits);
+        assert_eq!(small.get(b"b"), Some(Bytes::from("2")));
+        assert!(small.remove(b"a"));
+        assert_eq!(small.entries(), vec![(Bytes::from("b"), Bytes::from("2"))]);
+        assert!(small.remove(b"b"));
+        assert!(small.is_empty());
+    }
+
+    #[test]
+    fn update_and_random() {
+        let limits = ZipLimits { max_entries: 4, max_value: 16 };
+        for n in [3, 40] {
+            let mut hash = Hash::new();
+            for i in 0..n {
+                hash.insert(Bytes::from(format!("f{}", i)), Bytes::from(i.to_string()), &limits);
+            }
+            let append = |old: Option<&[u8]>| Ok::<_, ()>(Bytes::from([old.unwrap_or(b"new"), b"!"].concat()));
+            assert_eq!(hash.update(Bytes::from("f1"), append, &limits), Ok(Bytes::from("1!")));
+            assert_eq!(hash.update(Bytes::from("x"), append, &limits), Ok(Bytes::from("new!")));
+            assert_eq!(hash.update(Bytes::from("f2"), |_| Err("bad"), &limits), Err("bad"));
+            assert_eq!(hash.get(b"f2"), Some(Bytes::from("2")));
+            assert_eq!(hash.len(), n + 1);
+
+            for count in [1, 2, n as i64, n as i64 + 5] {
+                let entries = hash.random_entries(count);
+                let fields: HashSet<_> = entries.iter().map(|(f, _)| f.clone()).collect();
+                assert_eq!(entries.len(), (count as usize).min(n + 1));
+                assert_eq!(fields.len(), entries.len());
+            }
+            let entries = hash.random_entries(-100);
+            assert_eq!(entries.len(), 100);
+            assert!(entries.into_iter().all(|(f, v)| hash.get(&f) == Some(v)));
+        }
+        assert!(Hash::new().random_entries(-3).is_empty());
+    }
+
+    #[test]
+    fn scan() {
+        let limits = ZipLimits { max_entries: 4, max_value: 16 };
+        let mut hash = Hash::new();
+        for i in 0..3 {
+            hash.insert(Bytes::from(format!("f{}", i)), Bytes::from(i.to_string()), &limits);
+        }
+        // ziplist 编码一次返回全部
+        assert_eq!(hash.scan(0, 1, |f| f != b"f1"), (0, vec![(Bytes::from("f0"), Bytes::from("0")), (Bytes::from("f2"), Bytes::from("2"))]));
+
+        for i in 3..50 {
+            hash.insert(Bytes::from(format!("f{}", i)), Bytes::from(i.to_string()), &limits);
+        }
+        let (mut cursor, mut fields) = (0, vec![]);
+        loop {
+            let (next, entries) = hash.scan(cursor, 5, |_| true);
+            fields.extend(entries.into_iter().map(|(f, _)| f));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        fields.sort();
+        let mut expected: Vec<Bytes> = (0..50).map(|i| Bytes::from(format!("f{}", i))).collect();
+        expected.sort();
+        assert_eq!(fields, expected);
+    }
+}
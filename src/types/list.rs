@@ -0,0 +1,317 @@
+//! 列表。与 redis 一样有两种编码：
+//! - 元素少且都较短时用 ziplist，内存紧凑；
+//! - 任一阈值被超过后转换为双端链表，此后不再转换回去。
+
+use std::{collections::LinkedList, mem::size_of};
+
+use bytes::Bytes;
+
+use crate::{ds::{MemSize, adlist::Adlist, ziplist::ZipList}, object::{ObjectEncoding, ZipLimits}};
+
+use super::{entry_bytes, sampled_size};
+
+#[derive(Clone)]
+pub enum List {
+    ZipList(ZipList),
+    /// 链表编码，adlist 复用标准库的双端链表，中间位置的操作见 [`Adlist`]
+    LinkedList(LinkedList<Bytes>),
+}
+
+impl Default for List {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl List {
+    /// 新建的列表总是 ziplist 编码
+    pub fn new() -> Self {
+        List::ZipList(ZipList::new())
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            List::ZipList(zl) => zl.get_entry_cnt(),
+            List::LinkedList(list) => list.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn encoding(&self) -> ObjectEncoding {
+        match self {
+            List::ZipList(_) => ObjectEncoding::ZipList,
+            List::LinkedList(_) => ObjectEncoding::LinkedList,
+        }
+    }
+
+    /// 占用内存的估计值（字节）
+    pub fn mem_usage(&self) -> usize {
+        match self {
+            List::ZipList(zl) => zl.blob_len(),
+            List::LinkedList(list) => {
+                // 每个节点还有前后两个指针
+                let node = size_of::<Bytes>() + 2 * size_of::<usize>();
+                list.len() * node + sampled_size(list.len(), list.iter().map(Bytes::len))
+            },
+        }
+    }
+
+    /// 在表头插入
+    pub fn push_front(&mut self, value: Bytes, limits: &ZipLimits) {
+        self.convert_if_needed(self.len() + 1, &value, limits);
+        match self {
+            List::ZipList(zl) => zl.push_front_string(&value).unwrap(),
+            List::LinkedList(list) => list.push_front(value),
+        }
+    }
+
+    /// 在表尾插入
+    pub fn push_back(&mut self, value: Bytes, limits: &ZipLimits) {
+        self.convert_if_needed(self.len() + 1, &value, limits);
+        match self {
+            List::ZipList(zl) => zl.push_tail_string(&value).unwrap(),
+            List::LinkedList(list) => list.push_back(value),
+        }
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        match self {
+            List::ZipList(zl) => zl.pop_front().map(entry_bytes),
+            List::LinkedList(list) => list.pop_front(),
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<Bytes> {
+        match self {
+            List::ZipList(zl) => zl.pop_tail().map(entry_bytes),
+            List::LinkedList(list) => list.pop_back(),
+        }
+    }
+
+    /// 第 index 个元素，负数表示从表尾倒数
+    pub fn get(&self, index: i64) -> Option<Bytes> {
+        match self {
+            List::ZipList(zl) => zl.get(index as isize).map(entry_bytes),
+            List::LinkedList(list) => {
+                let index = if index < 0 { list.len() as i64 + index } else { index };
+                if index < 0 {
+                    return None;
+                }
+                Adlist::get(list, index as usize).cloned()
+            },
+        }
+    }
+
+    /// 返回 [start, stop] 内的元素，负数表示从表尾倒数，越界部分会被截掉
+    pub fn range(&self, start: i64, stop: i64) -> Vec<Bytes> {
+        let len = self.len() as i64;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+        if start > stop || start >= len {
+            return vec![];
+        }
+        let (skip, take) = (start as usize, (stop - start + 1) as usize);
+        match self {
+            List::ZipList(zl) => zl.values().skip(skip).take(take).map(entry_bytes).collect(),
+            List::LinkedList(list) => list.iter().skip(skip).take(take).cloned().collect(),
+        }
+    }
+
+    /// 值等于 value 的元素的位置，对应 LPOS。
+    ///
+    /// rank 为正数时从表头开始找，跳过前 rank - 1 个匹配的元素；为负数时从表尾开始找，不能为 0。
+    /// 最多返回 count 个位置，最多比较 maxlen 个元素，两者为 0 时都表示不限
+    pub fn positions(&self, value: &[u8], rank: i64, count: usize, maxlen: usize) -> Vec<usize> {
+        fn select(matches: impl Iterator<Item = (usize, bool)>, skip: usize, count: usize, maxlen: usize) -> Vec<usize> {
+            matches.take(maxlen).filter(|(_, matched)| *matched).map(|(index, _)| index).skip(skip).take(count).collect()
+        }
+        let skip = (rank.unsigned_abs() - 1) as usize;
+        let count = if count == 0 { usize::MAX } else { count };
+        let maxlen = if maxlen == 0 { usize::MAX } else { maxlen };
+        match self {
+            List::ZipList(zl) if rank > 0 => select(zl.matches(value).enumerate(), skip, count, maxlen),
+            List::ZipList(zl) => select(zl.matches(value).enumerate().rev(), skip, count, maxlen),
+            List::LinkedList(list) => {
+                let matches = list.iter().map(|v| v == value).enumerate();
+                if rank > 0 {
+                    select(matches, skip, count, maxlen)
+                } else {
+                    select(matches.rev(), skip, count, maxlen)
+                }
+            },
+        }
+    }
+
+    /// 把第 index 个元素替换为 value，负数表示从表尾倒数，越界时返回 false
+    pub fn set(&mut self, index: i64, value: Bytes, limits: &ZipLimits) -> bool {
+        let len = self.len() as i64;
+        let index = if index < 0 { len + index } else { index };
+        if index < 0 || index >= len {
+            return false;
+        }
+        self.convert_if_needed(self.len(), &value, limits);
+        match self {
+            List::ZipList(zl) => {
+                zl.replace_at(index as usize, &value).unwrap();
+            },
+            List::LinkedList(list) => *list.iter_mut().nth(index as usize).unwrap() = value,
+        }
+        true
+    }
+
+    /// 在第一个等于 pivot 的元素之前（after 为真时之后）插入 value，对应 LINSERT。
+    /// 返回插入后的长度，找不到 pivot 时返回 `None`
+    pub fn insert(&mut self, pivot: &[u8], value: Bytes, after: bool, limits: &ZipLimits) -> Option<usize> {
+        let index = match self {
+            List::ZipList(zl) => zl.find(pivot),
+            List::LinkedList(list) => Adlist::search(list, |v| v == pivot),
+        }?;
+        self.convert_if_needed(self.len() + 1, &value, limits);
+        match self {
+            List::ZipList(zl) => zl.insert_at(index + after as usize, &value).unwrap(),
+            List::LinkedList(list) if after => {
+                Adlist::insert_after(list, index, value);
+            },
+            List::LinkedList(list) => {
+                Adlist::insert_before(list, index, value);
+            },
+        }
+        Some(self.len())
+    }
+
+    /// 删除等于 value 的元素，对应 LREM：count 为正数时从表头开始最多删除 count 个，
+    /// 为负数时从表尾开始，为 0 时全部删除。返回删除的个数
+    pub fn remove(&mut self, value: &[u8], count: i64) -> usize {
+        let rank = if count < 0 { -1 } else { 1 };
+        let mut positions = self.positions(value, rank, count.unsigned_abs() as usize, 0);
+        // 从后往前删，前面的位置不受影响
+        positions.sort_unstable_by(|a, b| b.cmp(a));
+        for &index in &positions {
+            match self {
+                List::ZipList(zl) => {
+                    zl.delete_at(index).unwrap();
+                },
+                List::LinkedList(list) => {
+                    Adlist::remove(list, index);
+                },
+            }
+        }
+        positions.len()
+    }
+
+    /// 操作后共有 len 个元素、其中包括 value 时会超过 ziplist 的阈值的话，转换为链表
+    fn convert_if_needed(&mut self, len: usize, value: &[u8], limits: &ZipLimits) {
+        if let List::ZipList(zl) = self {
+            if limits.exceeded_by(len, value) {
+                *self = List::LinkedList(zl.values().map(entry_bytes).collect());
+            }
+        }
+    }
+}
+
+impl MemSize for List {
+    fn mem_size(&self) -> usize {
+        match self {
+            List::ZipList(zl) => zl.mem_size(),
+            List::LinkedList(list) => list.mem_size(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::object::ZipLimits;
+
+    use super::List;
+
+    #[test]
+    fn push_pop_and_range() {
+        let limits = ZipLimits::default();
+        let mut list = List::new();
+        for i in 0..3 {
+            list.push_back(Bytes::from(format!("r{}", i)), &limits);
+            list.push_front(Bytes::from(format!("l{}", i)), &limits);
+        }
+        assert!(matches!(list, List::ZipList(_)));
+        assert_eq!(list.len(), 6);
+        assert_eq!(list.range(0, -1), ["l2", "l1", "l0", "r0", "r1", "r2"].map(Bytes::from));
+        assert_eq!(list.range(-2, 100), ["r1", "r2"].map(Bytes::from));
+        assert_eq!(list.range(-100, 0), ["l2"].map(Bytes::from));
+        assert!(list.range(3, 2).is_empty());
+        assert!(list.range(6, 10).is_empty());
+        assert_eq!(list.get(1), Some(Bytes::from("l1")));
+        assert_eq!(list.get(-1), Some(Bytes::from("r2")));
+        assert_eq!(list.get(6), None);
+
+        assert_eq!(list.pop_front(), Some(Bytes::from("l2")));
+        assert_eq!(list.pop_back(), Some(Bytes::from("r2")));
+        assert_eq!(list.len(), 4);
+    }
+
+    #[test]
+    fn convert_to_linkedlist() {
+        let limits = ZipLimits { max_entries: 4, max_value: 8 };
+        let mut list = List::new();
+        for i in 0..4 {
+            list.push_back(Bytes::from(i.to_string()), &limits);
+        }
+        assert!(matches!(list, List::ZipList(_)));
+        list.push_back(Bytes::from("4"), &limits);
+        assert!(matches!(list, List::LinkedList(_)));
+        assert_eq!(list.range(0, -1), ["0", "1", "2", "3", "4"].map(Bytes::from));
+        assert_eq!(list.get(-1), Some(Bytes::from("4")));
+        assert_eq!(list.get(-6), None);
+
+        // 元素过长同样会触发转换
+        let mut list = List::new();
+        list.push_front(Bytes::from("short"), &limits);
+        list.push_front(Bytes::from("a long value"), &limits);
+        assert!(matches!(list, List::LinkedList(_)));
+        assert_eq!(list.range(0, -1), ["a long value", "short"].map(Bytes::from));
+    }
+
+    #[test]
+    fn search_and_modify() {
+        let limits = ZipLimits { max_entries: 8, max_value: 8 };
+        for convert in [false, true] {
+            let mut list = List::new();
+            for v in ["a", "b", "a", "1", "a", "c"] {
+                list.push_back(Bytes::from(v), &limits);
+            }
+            if convert {
+                list.set(0, Bytes::from("a long value"), &limits);
+                assert!(matches!(list, List::LinkedList(_)));
+                list.set(-6, Bytes::from("a"), &limits);
+            }
+            assert_eq!(list.positions(b"a", 1, 0, 0), [0, 2, 4]);
+            assert_eq!(list.positions(b"a", 2, 1, 0), [2]);
+            assert_eq!(list.positions(b"a", -1, 2, 0), [4, 2]);
+            assert_eq!(list.positions(b"a", 1, 0, 3), [0, 2]);
+            assert_eq!(list.positions(b"a", -3, 0, 0), [0]);
+            assert_eq!(list.positions(b"1", 1, 0, 0), [3]);
+            assert!(list.positions(b"x", 1, 0, 0).is_empty());
+
+            assert!(list.set(-1, Bytes::from("d"), &limits));
+            assert!(!list.set(6, Bytes::from("x"), &limits));
+            assert!(!list.set(-7, Bytes::from("x"), &limits));
+            assert_eq!(list.insert(b"b", Bytes::from("x"), false, &limits), Some(7));
+            assert_eq!(list.insert(b"d", Bytes::from("y"), true, &limits), Some(8));
+            assert_eq!(list.insert(b"z", Bytes::from("y"), true, &limits), None);
+            assert_eq!(list.range(0, -1), ["a", "x", "b", "a", "1", "a", "d", "y"].map(Bytes::from));
+
+            assert_eq!(list.remove(b"a", -1), 1);
+            assert_eq!(list.range(0, -1), ["a", "x", "b", "a", "1", "d", "y"].map(Bytes::from));
+            assert_eq!(list.remove(b"a", 0), 2);
+            assert_eq!(list.remove(b"1", 5), 1);
+            assert_eq!(list.range(0, -1), ["x", "b", "d", "y"].map(Bytes::from));
+            // 插入后超过元素个数的阈值
+            assert_eq!(list.insert(b"x", Bytes::from("w"), true, &limits), Some(5));
+            assert_eq!(matches!(list, List::LinkedList(_)), convert);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! 键空间中各种值类型的实现，对应 redis 的 `t_*.c`。
+//! 底层数据结构见 [`crate::ds`]，这里负责把它们组合成命令需要的语义，编码转换规则见 [`crate::object`]。
+
+use std::mem::size_of;
+
+use bytes::Bytes;
+
+use crate::{ds::{dict::Dict, perfstr::sds::SDS, ziplist::{ZipEntryValue, ZipList}}, object::int_to_bytes};
+
+mod list;
+pub use list::List;
+
+mod hash;
+pub use hash::Hash;
+
+mod set;
+pub use set::Set;
+
+mod zset;
+pub use zset::{AddFlags, AddOutcome, ZSet};
+
+mod stream;
+pub use stream::{ConsumerGroup, NewId, PendingEntry, Stream, StreamEntry, StreamId};
+
+/// ziplist entry 的值统一转换成字节串
+fn entry_bytes(value: ZipEntryValue) -> Bytes {
+    match value {
+        ZipEntryValue::Bytes(bytes) => Bytes::from(bytes),
+        ZipEntryValue::Int(i) => int_to_bytes(i),
+    }
+}
+
+/// 用给定的元素构建 ziplist，用于一次性修改大量元素的场景
+fn ziplist_from<T: AsRef<[u8]>>(values: impl IntoIterator<Item = T>) -> ZipList {
+    let mut zl = ZipList::new();
+    for v in values {
+        zl.push_tail_string(v.as_ref()).unwrap();
+    }
+    zl
+}
+
+/// 估计内存占用时抽样的元素个数
+const MEM_SAMPLES: usize = 5;
+
+/// 抽样前几个元素的大小，按平均值估计 len 个元素的总大小。与 redis 的 `MEMORY USAGE` 一样，不遍历全部元素
+fn sampled_size(len: usize, sizes: impl Iterator<Item = usize>) -> usize {
+    let (count, total) = sizes.take(MEM_SAMPLES).fold((0, 0), |(count, total), size| (count + 1, total + size));
+    (total * len).checked_div(count).unwrap_or(0)
+}
+
+/// Dict 占用内存的估计值：slot 数组、每个节点的固定开销，加上抽样估计的 key、value 内容大小
+fn dict_mem_usage<V>(dict: &Dict<SDS, V>, content: impl Fn(&SDS, &V) -> usize) -> usize {
+    let len = dict.value_cnt() as usize;
+    // 节点中除了 key、value 还有指向下一个节点的指针
+    let node = size_of::<SDS>() + size_of::<V>() + size_of::<usize>();
+    dict.slots_cnt() as usize * size_of::<usize>()
+        + len * node
+        + sampled_size(len, dict.iter().map(|(key, value)| content(key, value)))
+}
@@ -0,0 +1,315 @@
+//! 集合。与 redis 一样有两种编码：
+//! - 元素都是整数且个数不多时用 intset；
+//! - 加入非整数的元素，或者元素个数超过阈值后转换为 Dict，此后不再转换回去。
+
+use bytes::Bytes;
+use rand::Rng;
+
+use crate::{ds::{MemSize, dict::Dict, intset::IntSet, perfstr::{SmartString, sds::SDS}}, object::{IntSetLimits, ObjectEncoding, int_to_bytes, parse_int}};
+
+use super::dict_mem_usage;
+
+#[derive(Clone)]
+pub enum Set {
+    IntSet(IntSet),
+    /// 只使用 Dict 的 key，value 为空
+    HashTable(Dict<SDS, ()>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Set {
+    /// 新建的集合总是 intset 编码，加入第一个元素时再决定是否转换
+    pub fn new() -> Self {
+        Set::IntSet(IntSet::new())
+    }
+
+    /// 元素个数
+    pub fn len(&self) -> usize {
+        match self {
+            Set::IntSet(set) => set.len(),
+            Set::HashTable(dict) => dict.value_cnt() as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn encoding(&self) -> ObjectEncoding {
+        match self {
+            Set::IntSet(_) => ObjectEncoding::IntSet,
+            Set::HashTable(_) => ObjectEncoding::HashTable,
+        }
+    }
+
+    /// 占用内存的估计值（字节）
+    pub fn mem_usage(&self) -> usize {
+        match self {
+            Set::IntSet(set) => set.blob_len(),
+            Set::HashTable(dict) => dict_mem_usage(dict, |member, _| member.alloc_size()),
+        }
+    }
+
+    /// 加入 member，返回是否为新增的元素
+    pub fn insert(&mut self, member: &[u8], limits: &IntSetLimits) -> bool {
+        if let Set::IntSet(set) = self {
+            match parse_int(member) {
+                Some(value) => {
+                    let added = set.insert(value);
+                    if set.len() > limits.max_entries {
+                        self.convert();
+                    }
+                    return added;
+                },
+                None => self.convert(),
+            }
+        }
+        match self {
+            // 不是整数时上面已经转换
+            Set::IntSet(_) => unreachable!(),
+            Set::HashTable(dict) => dict.insert(SDS::new(member), ()).is_none(),
+        }
+    }
+
+    /// member 是否在集合中。Dict 查找时会顺带做一步 rehash，所以需要可变引用
+    pub fn contains(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(set) => parse_int(member).is_some_and(|value| set.contains(value)),
+            Set::HashTable(dict) => dict.get(&SDS::new(member)).is_some(),
+        }
+    }
+
+    /// 删除 member，返回其是否存在
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(set) => parse_int(member).is_some_and(|value| set.remove(value)),
+            Set::HashTable(dict) => dict.remove(&SDS::new(member)).is_some(),
+        }
+    }
+
+    /// 所有的元素。intset 编码时按整数升序，否则顺序不确定
+    pub fn members(&self) -> Vec<Bytes> {
+        match self {
+            Set::IntSet(set) => set.iter().map(int_to_bytes).collect(),
+            Set::HashTable(dict) => dict.keys().map(|member| Bytes::copy_from_slice(member.val())).collect(),
+        }
+    }
+
+    /// 从 cursor 开始遍历一部分元素，返回下一次遍历的 cursor，0 表示遍历完成，语义同 [`Dict::scan`]。
+    /// intset 编码时与 redis 一样一次返回全部
+    pub fn scan(&self, cursor: u64, count: usize, mut filter: impl FnMut(&[u8]) -> bool) -> (u64, Vec<Bytes>) {
+        let dict = match self {
+            Set::IntSet(set) => {
+                let mut members: Vec<Bytes> = set.iter().map(int_to_bytes).collect();
+                members.retain(|member| filter(member));
+                return (0, members);
+            },
+            Set::HashTable(dict) => dict,
+        };
+        let mut cursor = cursor;
+        let mut members = vec![];
+        for _ in 0..count.saturating_mul(10).max(1) {
+            cursor = dict.scan(cursor, |member, _| {
+                if filter(member.val()) {
+                    members.push(Bytes::copy_from_slice(member.val()));
+                }
+            });
+            if cursor == 0 || members.len() >= count {
+                break;
+            }
+        }
+        (cursor, members)
+    }
+
+    /// 随机返回若干元素，对应 SRANDMEMBER：count 为正数时元素互不相同，最多返回全部；
+    /// 为负数时可能重复，正好返回 |count| 个
+    pub fn random_members(&mut self, count: i64) -> Vec<Bytes> {
+        if self.is_empty() || count == 0 {
+            return vec![];
+        }
+        if count > 0 && count as usize >= self.len() {
+            return self.members();
+        }
+        let mut rng = rand::thread_rng();
+        match self {
+            Set::IntSet(set) if count < 0 => {
+                (0..count.unsigned_abs()).map(|_| int_to_bytes(set.get(rng.gen_range(0..set.len())).unwrap())).collect()
+            },
+            Set::HashTable(dict) if count < 0 => {
+                (0..count.unsigned_abs()).map(|_| Bytes::copy_from_slice(dict.random_entry().unwrap().0.val())).collect()
+            },
+            // 不重复的情况遍历一遍做蓄水池抽样，两种编码都不需要先取出全部元素
+            Set::IntSet(set) => reservoir(set.iter(), count as usize, &mut rng).into_iter().map(int_to_bytes).collect(),
+            Set::HashTable(dict) => reservoir(dict.keys(), count as usize, &mut rng)
+                .into_iter()
+                .map(|member| Bytes::copy_from_slice(member.val()))
+                .collect(),
+        }
+    }
+
+    /// 随机删除最多 count 个元素并返回，对应 SPOP
+    pub fn pop_random(&mut self, count: usize) -> Vec<Bytes> {
+        let members = self.random_members(count.min(i64::MAX as usize) as i64);
+        for member in &members {
+            self.remove(member);
+        }
+        members
+    }
+
+    /// 转换为 Dict 编码
+    fn convert(&mut self) {
+        let mut dict = Dict::new();
+        for member in self.members() {
+            dict.insert(SDS::new(&member), ());
+        }
+        *self = Set::HashTable(dict);
+    }
+}
+
+/// 蓄水池抽样：遍历一遍 iter，等概率地选出其中 k 个元素，元素个数不足 k 时全部返回
+fn reservoir<T>(iter: impl Iterator<Item = T>, k: usize, rng: &mut impl Rng) -> Vec<T> {
+    let mut picked = Vec::with_capacity(k);
+    for (i, item) in iter.enumerate() {
+        if i < k {
+            picked.push(item);
+        } else {
+            let j = rng.gen_range(0..=i);
+            if j < k {
+                picked[j] = item;
+            }
+        }
+    }
+    picked
+}
+
+impl MemSize for Set {
+    fn mem_size(&self) -> usize {
+        match self {
+            Set::IntSet(set) => set.mem_size(),
+            Set::HashTable(dict) => dict.mem_size(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::object::{IntSetLimits, ObjectEncoding};
+
+    use super::Set;
+
+    #[test]
+    fn both_encodings() {
+        let limits = IntSetLimits { max_entries: 4 };
+        let mut set = Set::new();
+        for member in ["3", "-1", "2", "3"] {
+            set.insert(member.as_bytes(), &limits);
+        }
+        assert_eq!(set.encoding(), ObjectEncoding::IntSet);
+        assert_eq!(set.members(), ["-1", "2", "3"].map(Bytes::from));
+        assert!(set.contains(b"2"));
+        // 不是规范表示的整数按字符串处理
+        assert!(!set.contains(b"02"));
+        assert!(!set.contains(b"a"));
+        assert!(set.remove(b"-1"));
+        assert!(!set.remove(b"-1"));
+
+        assert!(set.insert(b"a", &limits));
+        assert_eq!(set.encoding(), ObjectEncoding::HashTable);
+        assert!(!set.insert(b"2", &limits));
+        assert!(set.contains(b"a") && set.contains(b"3"));
+        let mut members = set.members();
+        members.sort();
+        assert_eq!(members, ["2", "3", "a"].map(Bytes::from));
+
+        let mut ints = Set::new();
+        for i in 0..5 {
+            assert!(ints.insert(i.to_string().as_bytes(), &limits));
+        }
+        assert_eq!(ints.encoding(), ObjectEncoding::HashTable);
+        assert_eq!(ints.len(), 5);
+        for i in 0..5 {
+            assert!(ints.remove(i.to_string().as_bytes()));
+        }
+        assert!(ints.is_empty());
+    }
+
+    #[test]
+    fn random() {
+        let limits = IntSetLimits { max_entries: 512 };
+        for strings in [false, true] {
+            let mut set = Set::new();
+            for i in 0..20 {
+                let member = if strings { format!("m{}", i) } else { i.to_string() };
+                set.insert(member.as_bytes(), &limits);
+            }
+            assert_eq!(set.encoding() == ObjectEncoding::HashTable, strings);
+
+            let mut members = set.random_members(5);
+            assert_eq!(members.len(), 5);
+            members.sort();
+            members.dedup();
+            assert_eq!(members.len(), 5);
+            assert!(members.iter().all(|member| set.contains(member)));
+            assert_eq!(set.random_members(30).len(), 20);
+            let repeated = set.random_members(-30);
+            assert_eq!(repeated.len(), 30);
+            assert!(repeated.iter().all(|member| set.contains(member)));
+
+            let popped = set.pop_random(15);
+            assert_eq!((popped.len(), set.len()), (15, 5));
+            assert!(popped.iter().all(|member| !set.contains(member)));
+            assert_eq!(set.pop_random(10).len(), 5);
+            assert!(set.is_empty());
+            assert!(set.random_members(-1).is_empty());
+        }
+    }
+
+    #[test]
+    fn reservoir_is_uniform() {
+        let mut rng = rand::thread_rng();
+        let mut hits = [0; 10];
+        for _ in 0..10_000 {
+            for i in super::reservoir(0..10, 3, &mut rng) {
+                hits[i] += 1;
+            }
+        }
+        // 每个元素被选中的期望为 3000 次
+        assert!(hits.iter().all(|&n| (2500..3500).contains(&n)), "{:?}", hits);
+    }
+
+    #[test]
+    fn scan() {
+        let limits = IntSetLimits { max_entries: 4 };
+        let mut set = Set::new();
+        for member in ["1", "2", "3"] {
+            set.insert(member.as_bytes(), &limits);
+        }
+        // intset 编码一次返回全部
+        assert_eq!(set.scan(0, 1, |member| member != b"2"), (0, vec![Bytes::from("1"), Bytes::from("3")]));
+
+        for i in 3..50 {
+            set.insert(format!("m{}", i).as_bytes(), &limits);
+        }
+        assert_eq!(set.encoding(), ObjectEncoding::HashTable);
+        let (mut cursor, mut members) = (0, vec![]);
+        loop {
+            let (next, batch) = set.scan(cursor, 5, |_| true);
+            members.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        members.sort();
+        let mut expected = set.members();
+        expected.sort();
+        assert_eq!(members, expected);
+    }
+}
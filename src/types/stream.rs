@@ -0,0 +1,471 @@
+//! 流。与 redis 的 rax + listpack 类似，条目按 ID 有序保存在 `BTreeMap` 中，
+//! 每个条目的 field value 依次保存在一个 listpack 里。
+//!
+//! ID 由毫秒时间戳与同一毫秒内的序号组成，新条目的 ID 必须大于流中已有的最大 ID，
+//! 删除条目后也不会复用，所以流另外记录了曾经分配过的最大 ID。
+//!
+//! 流上可以创建多个消费者组，见 [`ConsumerGroup`]。组内的消费者分摊新条目，
+//! 投递出去的条目在确认之前记录在组的待确认列表（PEL）中，可以被其他消费者认领。
+
+use std::{collections::{BTreeMap, btree_map::Entry}, fmt, mem::size_of, ops::Bound};
+
+use bytes::Bytes;
+
+use crate::{ds::{MemSize, listpack::{Listpack, ListpackEntry}}, object::{ObjectEncoding, int_to_bytes}};
+
+use super::sampled_size;
+
+/// 条目 ID，按 (ms, seq) 比较大小
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    /// 紧接着的下一个 ID，序号用尽时进位到下一毫秒，已经是最大值时返回 `None`
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    /// 紧挨着的前一个 ID，已经是最小值时返回 `None`
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+/// `ms-seq` 形式
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// XADD 指定的新条目 ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewId {
+    /// `*`，由当前时间生成
+    Auto,
+    /// `ms-*`，只指定毫秒数，序号自动生成
+    Ms(u64),
+    /// 完整的 ID
+    Explicit(StreamId),
+}
+
+/// 条目：ID 与按顺序排列的 field value
+pub type StreamEntry = (StreamId, Vec<(Bytes, Bytes)>);
+
+/// 已经投递给消费者、还没有确认的条目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    /// 当前持有这个条目的消费者
+    pub consumer: Bytes,
+    /// 最近一次投递的时间（毫秒）
+    pub delivery_time: u64,
+    /// 投递的次数
+    pub delivery_count: u64,
+}
+
+/// 消费者组。消费者在第一次读取或者认领时自动创建
+#[derive(Debug, Clone, Default)]
+pub struct ConsumerGroup {
+    /// 最近投递给组内消费者的 ID，之后的条目才是新条目
+    last_id: StreamId,
+    /// 待确认列表，按 ID 排序
+    pending: BTreeMap<StreamId, PendingEntry>,
+    /// 消费者及其最近一次活动的时间（毫秒）
+    consumers: BTreeMap<Bytes, u64>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_id: StreamId) -> ConsumerGroup {
+        ConsumerGroup { last_id, ..ConsumerGroup::default() }
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn set_last_id(&mut self, id: StreamId) {
+        self.last_id = id;
+    }
+
+    /// 待确认列表
+    pub fn pending(&self) -> &BTreeMap<StreamId, PendingEntry> {
+        &self.pending
+    }
+
+    /// 区间内的待确认条目，按 ID 从小到大
+    pub fn pending_range(&self, start: Bound<StreamId>, end: Bound<StreamId>) -> impl Iterator<Item = (StreamId, &PendingEntry)> {
+        let range = match is_empty_range(start, end) {
+            true => None,
+            false => Some(self.pending.range((start, end))),
+        };
+        range.into_iter().flatten().map(|(id, entry)| (*id, entry))
+    }
+
+    pub fn pending_mut(&mut self, id: StreamId) -> Option<&mut PendingEntry> {
+        self.pending.get_mut(&id)
+    }
+
+    /// 消费者及其最近一次活动的时间
+    pub fn consumers(&self) -> impl Iterator<Item = (&Bytes, u64)> {
+        self.consumers.iter().map(|(name, seen)| (name, *seen))
+    }
+
+    /// 记录消费者的一次活动，消费者不存在时创建，返回是否为新建的消费者
+    pub fn touch(&mut self, consumer: &Bytes, now: u64) -> bool {
+        self.consumers.insert(consumer.clone(), now).is_none()
+    }
+
+    /// 删除消费者，返回它在待确认列表中的条目个数，这些条目一并删除。消费者不存在时返回 `None`
+    pub fn remove_consumer(&mut self, consumer: &[u8]) -> Option<usize> {
+        self.consumers.remove(consumer)?;
+        let before = self.pending.len();
+        self.pending.retain(|_, entry| entry.consumer != consumer);
+        Some(before - self.pending.len())
+    }
+
+    /// 把条目投递给 consumer：加入待确认列表，已经在列表中的条目转给 consumer 并增加投递次数
+    pub fn deliver(&mut self, id: StreamId, consumer: &Bytes, now: u64) {
+        let entry = self.pending.entry(id).or_insert_with(|| PendingEntry { consumer: consumer.clone(), delivery_time: now, delivery_count: 0 });
+        entry.consumer = consumer.clone();
+        entry.delivery_time = now;
+        entry.delivery_count += 1;
+    }
+
+    /// 确认条目，返回它是否在待确认列表中
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        self.pending.remove(&id).is_some()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Listpack>,
+    /// 曾经分配过的最大 ID，流为空时也保留
+    last_id: StreamId,
+    /// 曾经添加过的条目总数，包括已经删除的
+    entries_added: u64,
+    /// 消费者组，按名称排序
+    groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 条目个数
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn encoding(&self) -> ObjectEncoding {
+        ObjectEncoding::Stream
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    /// 占用内存的估计值（字节）
+    pub fn mem_usage(&self) -> usize {
+        // 树节点中除了 key、value 还有指向子节点的指针
+        let node = size_of::<StreamId>() + size_of::<Listpack>() + size_of::<usize>();
+        self.len() * node + sampled_size(self.len(), self.entries.values().map(Listpack::blob_len))
+    }
+
+    /// 按 XADD 的规则计算新条目的 ID，得到的 ID 不大于流中最大的 ID 时返回 `None`。
+    /// 自动生成时使用 now 与最大 ID 中较大的毫秒数，所以时钟回拨后 ID 仍然递增
+    pub fn next_id(&self, id: NewId, now: u64) -> Option<StreamId> {
+        let id = match id {
+            NewId::Auto if now > self.last_id.ms => StreamId::new(now, 0),
+            NewId::Auto => self.last_id.next()?,
+            NewId::Ms(ms) if ms == self.last_id.ms => StreamId::new(ms, self.last_id.seq.checked_add(1)?),
+            // 空的流中 0-* 得到 0-1，0-0 不是合法的 ID
+            NewId::Ms(ms) => StreamId::new(ms, (ms == 0) as u64),
+            NewId::Explicit(id) => id,
+        };
+        (id > self.last_id).then_some(id)
+    }
+
+    /// 添加条目，id 必须大于流中最大的 ID，由 [`Stream::next_id`] 保证
+    pub fn insert(&mut self, id: StreamId, fields: &[(Bytes, Bytes)]) {
+        debug_assert!(id > self.last_id);
+        let mut listpack = Listpack::new();
+        for (field, value) in fields {
+            listpack.append(field);
+            listpack.append(value);
+        }
+        self.entries.insert(id, listpack);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    /// [start, end] 范围内的条目，rev 时按 ID 从大到小，最多返回 count 个
+    pub fn range(&self, start: Bound<StreamId>, end: Bound<StreamId>, count: Option<usize>, rev: bool) -> Vec<StreamEntry> {
+        let count = count.unwrap_or(usize::MAX);
+        if is_empty_range(start, end) {
+            return vec![];
+        }
+        let range = self.entries.range((start, end));
+        let entry = |(id, listpack): (&StreamId, &Listpack)| (*id, fields(listpack));
+        match rev {
+            true => range.rev().take(count).map(entry).collect(),
+            false => range.take(count).map(entry).collect(),
+        }
+    }
+
+    /// 删除最早的条目，直到只剩 maxlen 个，返回删除的个数
+    pub fn trim_len(&mut self, maxlen: usize) -> usize {
+        let mut removed = 0;
+        while self.entries.len() > maxlen {
+            self.entries.pop_first();
+            removed += 1;
+        }
+        removed
+    }
+
+    /// 删除 ID 小于 min 的条目，返回删除的个数
+    pub fn trim_min_id(&mut self, min: StreamId) -> usize {
+        let retained = self.entries.split_off(&min);
+        let removed = self.entries.len();
+        self.entries = retained;
+        removed
+    }
+
+    /// 条目的 field value，条目不存在时返回 `None`
+    pub fn get(&self, id: StreamId) -> Option<Vec<(Bytes, Bytes)>> {
+        self.entries.get(&id).map(fields)
+    }
+
+    /// 创建消费者组，last_id 之后的条目是组内的新条目。组已经存在时返回 `false`
+    pub fn create_group(&mut self, name: Bytes, last_id: StreamId) -> bool {
+        match self.groups.entry(name) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(ConsumerGroup::new(last_id));
+                true
+            },
+        }
+    }
+
+    /// 删除消费者组，返回组是否存在
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// 所有消费者组，按名称排序
+    pub fn groups(&self) -> impl Iterator<Item = (&Bytes, &ConsumerGroup)> {
+        self.groups.iter()
+    }
+
+    /// 把组内的新条目投递给 consumer，最多 count 个，并推进组的 last_id。
+    /// noack 时不加入待确认列表，视为投递后立即确认。组不存在时返回 `None`
+    pub fn read_group(&mut self, group: &[u8], consumer: &Bytes, count: Option<usize>, noack: bool, now: u64) -> Option<Vec<StreamEntry>> {
+        let group = self.groups.get_mut(group)?;
+        group.touch(consumer, now);
+        let entries: Vec<StreamEntry> = self.entries
+            .range((Bound::Excluded(group.last_id), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, listpack)| (*id, fields(listpack)))
+            .collect();
+        for (id, _) in &entries {
+            if !noack {
+                group.deliver(*id, consumer, now);
+            }
+            group.last_id = *id;
+        }
+        Some(entries)
+    }
+
+    /// consumer 的待确认列表中 ID 大于 after 的条目，最多 count 个。
+    /// 条目已经从流中删除时内容为 `None`。组不存在时返回 `None`
+    #[allow(clippy::type_complexity)]
+    pub fn read_pending(&mut self, group: &[u8], consumer: &Bytes, after: StreamId, count: Option<usize>, now: u64) -> Option<Vec<(StreamId, Option<Vec<(Bytes, Bytes)>>)>> {
+        let group = self.groups.get_mut(group)?;
+        group.touch(consumer, now);
+        let ids = group.pending
+            .range((Bound::Excluded(after), Bound::Unbounded))
+            .filter(|(_, entry)| entry.consumer == consumer)
+            .map(|(id, _)| *id)
+            .take(count.unwrap_or(usize::MAX));
+        Some(ids.map(|id| (id, self.entries.get(&id).map(fields))).collect())
+    }
+
+    /// 所有条目，按 ID 从小到大
+    pub fn entries(&self) -> impl Iterator<Item = StreamEntry> + '_ {
+        self.entries.iter().map(|(id, listpack)| (*id, fields(listpack)))
+    }
+
+    /// 恢复快照时设置流的元数据，last_id 不能小于已有条目的最大 ID
+    pub(crate) fn set_meta(&mut self, last_id: StreamId, entries_added: u64) {
+        self.last_id = last_id.max(self.last_id);
+        self.entries_added = entries_added;
+    }
+}
+
+/// 区间是否一定为空。这样的区间在 `BTreeMap::range` 中会 panic，需要提前排除
+fn is_empty_range(start: Bound<StreamId>, end: Bound<StreamId>) -> bool {
+    match (start, end) {
+        (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+        (Bound::Included(start) | Bound::Excluded(start), Bound::Included(end) | Bound::Excluded(end)) => start > end,
+        _ => false,
+    }
+}
+
+/// listpack 中依次保存的 field value
+fn fields(listpack: &Listpack) -> Vec<(Bytes, Bytes)> {
+    let mut items = listpack.iter().map(|entry| match entry {
+        ListpackEntry::String(bytes) => Bytes::from(bytes),
+        ListpackEntry::Integer(i) => int_to_bytes(i),
+    });
+    let mut fields = Vec::with_capacity(listpack.len() / 2);
+    while let (Some(field), Some(value)) = (items.next(), items.next()) {
+        fields.push((field, value));
+    }
+    fields
+}
+
+/// 消费者组只按待确认条目与消费者的个数估计
+impl MemSize for Stream {
+    fn mem_size(&self) -> usize {
+        let node = size_of::<StreamId>() + size_of::<Listpack>() + size_of::<usize>();
+        let groups = self.groups.iter().map(|(name, group)| {
+            name.len()
+                + group.pending.len() * (size_of::<StreamId>() + size_of::<PendingEntry>())
+                + group.consumers.keys().map(|consumer| consumer.len() + size_of::<u64>()).sum::<usize>()
+        });
+        self.entries.values().map(|listpack| node + listpack.mem_size()).sum::<usize>() + groups.sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use bytes::Bytes;
+
+    use super::{NewId, PendingEntry, Stream, StreamId};
+
+    fn add(stream: &mut Stream, id: NewId, now: u64) -> Option<StreamId> {
+        let id = stream.next_id(id, now)?;
+        stream.insert(id, &[(Bytes::from("f"), Bytes::from(id.to_string()))]);
+        Some(id)
+    }
+
+    #[test]
+    fn ids() {
+        let mut stream = Stream::new();
+        assert_eq!(add(&mut stream, NewId::Auto, 5), Some(StreamId::new(5, 0)));
+        assert_eq!(add(&mut stream, NewId::Auto, 5), Some(StreamId::new(5, 1)));
+        // 时钟回拨时沿用最大的毫秒数
+        assert_eq!(add(&mut stream, NewId::Auto, 3), Some(StreamId::new(5, 2)));
+        assert_eq!(add(&mut stream, NewId::Ms(5), 0), Some(StreamId::new(5, 3)));
+        assert_eq!(add(&mut stream, NewId::Ms(4), 0), None);
+        assert_eq!(add(&mut stream, NewId::Explicit(StreamId::new(5, 3)), 0), None);
+        assert_eq!(add(&mut stream, NewId::Ms(7), 0), Some(StreamId::new(7, 0)));
+        assert_eq!(stream.len(), 5);
+        assert_eq!(stream.last_id(), StreamId::new(7, 0));
+
+        assert_eq!(Stream::new().next_id(NewId::Ms(0), 0), Some(StreamId::new(0, 1)));
+        assert_eq!(Stream::new().next_id(NewId::Explicit(StreamId::MIN), 0), None);
+        let mut full = Stream::new();
+        full.insert(StreamId::MAX, &[]);
+        assert_eq!(full.next_id(NewId::Auto, u64::MAX), None);
+        assert_eq!(StreamId::new(1, u64::MAX).next(), Some(StreamId::new(2, 0)));
+        assert_eq!(StreamId::new(2, 0).prev(), Some(StreamId::new(1, u64::MAX)));
+        assert_eq!(StreamId::MIN.prev(), None);
+    }
+
+    #[test]
+    fn range_and_trim() {
+        let mut stream = Stream::new();
+        for ms in 1..=5 {
+            add(&mut stream, NewId::Ms(ms), 0);
+        }
+        let ids = |entries: Vec<(StreamId, Vec<(Bytes, Bytes)>)>| entries.into_iter().map(|(id, _)| id.ms).collect::<Vec<_>>();
+        assert_eq!(ids(stream.range(Bound::Unbounded, Bound::Unbounded, None, false)), [1, 2, 3, 4, 5]);
+        assert_eq!(ids(stream.range(Bound::Excluded(StreamId::new(2, 0)), Bound::Unbounded, Some(2), false)), [3, 4]);
+        assert_eq!(ids(stream.range(Bound::Unbounded, Bound::Included(StreamId::new(3, 0)), Some(2), true)), [3, 2]);
+        assert!(stream.range(Bound::Included(StreamId::new(4, 0)), Bound::Included(StreamId::new(3, 0)), None, false).is_empty());
+        assert!(stream.range(Bound::Excluded(StreamId::new(4, 0)), Bound::Excluded(StreamId::new(4, 0)), None, false).is_empty());
+        let (_, fields) = &stream.range(Bound::Unbounded, Bound::Unbounded, Some(1), false)[0];
+        assert_eq!(fields, &[(Bytes::from("f"), Bytes::from("1-0"))]);
+
+        assert_eq!(stream.trim_len(3), 2);
+        assert_eq!(stream.trim_min_id(StreamId::new(4, 0)), 1);
+        assert_eq!(ids(stream.entries().collect()), [4, 5]);
+        // 删除条目不影响最大 ID 与添加过的总数
+        assert_eq!(stream.trim_len(0), 2);
+        assert_eq!((stream.last_id(), stream.entries_added()), (StreamId::new(5, 0), 5));
+        assert_eq!(stream.next_id(NewId::Ms(5), 0), Some(StreamId::new(5, 1)));
+    }
+
+    #[test]
+    fn consumer_groups() {
+        let mut stream = Stream::new();
+        for ms in 1..=3 {
+            let id = stream.next_id(NewId::Ms(ms), 0).unwrap();
+            stream.insert(id, &[(Bytes::from("f"), Bytes::from("v"))]);
+        }
+        assert!(stream.create_group(Bytes::from("g"), StreamId::new(1, 0)));
+        assert!(!stream.create_group(Bytes::from("g"), StreamId::MIN));
+        let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+        assert!(stream.read_group(b"missing", &alice, None, false, 0).is_none());
+
+        let read = stream.read_group(b"g", &alice, Some(1), false, 10).unwrap();
+        assert_eq!(read.iter().map(|(id, _)| id.ms).collect::<Vec<_>>(), [2]);
+        let read = stream.read_group(b"g", &bob, None, false, 20).unwrap();
+        assert_eq!(read.iter().map(|(id, _)| id.ms).collect::<Vec<_>>(), [3]);
+        assert!(stream.read_group(b"g", &bob, None, false, 30).unwrap().is_empty());
+
+        let group = stream.group_mut(b"g").unwrap();
+        assert_eq!(group.last_id(), StreamId::new(3, 0));
+        assert_eq!(group.pending()[&StreamId::new(2, 0)], PendingEntry { consumer: alice.clone(), delivery_time: 10, delivery_count: 1 });
+        // 转给其他消费者时增加投递次数
+        group.deliver(StreamId::new(2, 0), &bob, 40);
+        assert_eq!(group.pending()[&StreamId::new(2, 0)].delivery_count, 2);
+        assert_eq!(group.consumers().map(|(name, seen)| (name.clone(), seen)).collect::<Vec<_>>(), [(alice.clone(), 10), (bob.clone(), 30)]);
+
+        // 已经删除的条目仍然在待确认列表中，内容为 None
+        stream.trim_len(1);
+        let pending = stream.read_pending(b"g", &bob, StreamId::MIN, None, 50).unwrap();
+        assert_eq!(pending.iter().map(|(id, fields)| (id.ms, fields.is_some())).collect::<Vec<_>>(), [(2, false), (3, true)]);
+
+        let group = stream.group_mut(b"g").unwrap();
+        assert!(group.ack(StreamId::new(2, 0)));
+        assert!(!group.ack(StreamId::new(2, 0)));
+        assert_eq!(group.remove_consumer(b"bob"), Some(1));
+        assert_eq!(group.remove_consumer(b"bob"), None);
+        assert!(group.pending().is_empty());
+        assert!(stream.destroy_group(b"g"));
+        assert_eq!(stream.groups().count(), 0);
+    }
+}
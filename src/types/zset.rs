@@ -0,0 +1,590 @@
+//! 有序集合。与 redis 一样有两种编码：
+//! - member 少且都较短时用 ziplist，member、score 依次交替存放，并按 (score, member) 排序；
+//! - 任一阈值被超过后转换为跳表加字典：跳表按 (score, member) 排序，负责范围查询；
+//!   字典保存 member → score，负责 O(1) 地查询分数以及判断 member 是否存在。此后不再转换回去。
+
+use std::mem::size_of;
+
+use bytes::Bytes;
+
+use crate::{ds::{MemSize, dict::Dict, perfstr::{SmartString, sds::SDS}, skiplist::{Bound, LexBound, Skiplist}, ziplist::ZipList}, object::{ObjectEncoding, ZipLimits}};
+
+use super::{dict_mem_usage, entry_bytes, ziplist_from};
+
+#[derive(Clone)]
+pub enum ZSet {
+    ZipList(ZipList),
+    SkipList {
+        /// Dict 较大，放在堆上以免 ziplist 编码的对象也占用同样的空间
+        dict: Box<Dict<SDS, f64>>,
+        list: Skiplist<Bytes>,
+    },
+}
+
+/// `ZADD` 的选项，对应 redis 的 `ZADD_IN_*`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddFlags {
+    /// 只新增，不更新已有的 member
+    pub nx: bool,
+    /// 只更新已有的 member，不新增
+    pub xx: bool,
+    /// 只在新分数更大时更新
+    pub gt: bool,
+    /// 只在新分数更小时更新
+    pub lt: bool,
+    /// 分数作为增量加到原分数上，即 ZINCRBY
+    pub incr: bool,
+}
+
+/// [`ZSet::add`] 的结果，携带 member 最终的分数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddOutcome {
+    /// 新增了 member
+    Added(f64),
+    /// 更新了已有 member 的分数
+    Updated(f64),
+    /// 分数与原来相同，没有修改
+    Unchanged(f64),
+    /// 被 NX/XX/GT/LT 跳过
+    Skipped,
+    /// 累加后的分数不是数字，如 `+inf` 加上 `-inf`
+    NotANumber,
+}
+
+impl Default for ZSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ZSet {
+    /// 新建的有序集合总是 ziplist 编码
+    pub fn new() -> Self {
+        ZSet::ZipList(ZipList::new())
+    }
+
+    /// member 数量
+    pub fn len(&self) -> usize {
+        match self {
+            ZSet::ZipList(zl) => zl.get_entry_cnt() / 2,
+            ZSet::SkipList { list, .. } => list.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn encoding(&self) -> ObjectEncoding {
+        match self {
+            ZSet::ZipList(_) => ObjectEncoding::ZipList,
+            ZSet::SkipList { .. } => ObjectEncoding::SkipList,
+        }
+    }
+
+    /// 占用内存的估计值（字节）
+    pub fn mem_usage(&self) -> usize {
+        match self {
+            ZSet::ZipList(zl) => zl.blob_len(),
+            ZSet::SkipList { dict, list } => {
+                // 跳表节点平均约有两层，每层一个指针和一个跨度；member 在跳表中另有一份
+                let node = size_of::<Bytes>() + size_of::<f64>() + 4 * size_of::<usize>();
+                dict_mem_usage(dict, |member, _| 2 * member.alloc_size()) + list.len() * node
+            },
+        }
+    }
+
+    /// 由互不重复的 (member, score) 构建有序集合，用于 ZUNIONSTORE 等一次性生成结果的命令。
+    /// 与 redis 一样先构建跳表加字典，元素个数与长度都不超过阈值时再转换为 ziplist
+    pub fn from_members(members: impl IntoIterator<Item = (Bytes, f64)>, limits: &ZipLimits) -> Self {
+        let mut dict = Dict::new();
+        let mut list = Skiplist::new();
+        let mut max_len = 0;
+        for (member, score) in members {
+            max_len = max_len.max(member.len());
+            dict.insert(SDS::new(&member), score);
+            list.insert(member, score);
+        }
+        let zset = ZSet::SkipList { dict: Box::new(dict), list };
+        if zset.len() > limits.max_entries || max_len > limits.max_value {
+            return zset;
+        }
+        let entries = zset
+            .range_by_rank(0, -1, false)
+            .into_iter()
+            .flat_map(|(member, score)| [member, Bytes::from(score.to_string())]);
+        ZSet::ZipList(ziplist_from(entries))
+    }
+
+    /// 新增 member 或更新其分数，返回是否为新增
+    pub fn insert(&mut self, member: Bytes, score: f64, limits: &ZipLimits) -> bool {
+        matches!(self.add(member, score, AddFlags::default(), limits), AddOutcome::Added(_))
+    }
+
+    /// 按 `ZADD` 的选项新增 member 或更新其分数
+    pub fn add(&mut self, member: Bytes, score: f64, flags: AddFlags, limits: &ZipLimits) -> AddOutcome {
+        match self.score(&member) {
+            Some(current) => {
+                if flags.nx {
+                    return AddOutcome::Skipped;
+                }
+                let score = if flags.incr { current + score } else { score };
+                if score.is_nan() {
+                    return AddOutcome::NotANumber;
+                }
+                if (flags.gt && score <= current) || (flags.lt && score >= current) {
+                    return AddOutcome::Skipped;
+                }
+                if score == current {
+                    return AddOutcome::Unchanged(score);
+                }
+                self.put(member, score, limits);
+                AddOutcome::Updated(score)
+            },
+            None => {
+                // GT/LT 只限制更新，不影响新增
+                if flags.xx {
+                    return AddOutcome::Skipped;
+                }
+                self.put(member, score, limits);
+                AddOutcome::Added(score)
+            },
+        }
+    }
+
+    /// 写入 member 及其分数，member 已存在时调整其位置
+    fn put(&mut self, member: Bytes, score: f64, limits: &ZipLimits) {
+        if let ZSet::ZipList(zl) = self {
+            if limits.exceeded_by(zl.get_entry_cnt() / 2 + 1, &member) {
+                self.convert();
+            }
+        }
+        match self {
+            ZSet::ZipList(zl) => {
+                let mut pairs = pairs(zl);
+                if let Some(idx) = pairs.iter().position(|(m, _)| *m == member) {
+                    pairs.remove(idx);
+                    remove_pair(zl, idx);
+                }
+                // 按 (score, member) 找到插入位置
+                let idx = pairs.partition_point(|(m, s)| (*s, m) < (score, &member));
+                zl.insert_at(2 * idx, &member).unwrap();
+                zl.insert_at(2 * idx + 1, score.to_string().as_bytes()).unwrap();
+            },
+            ZSet::SkipList { dict, list } => match dict.insert(SDS::new(&member), score) {
+                // 分数变化时，需要在跳表中重新排序
+                Some(old) => {
+                    list.update_score(&member, old, score);
+                },
+                None => list.insert(member, score),
+            },
+        }
+    }
+
+    /// 删除 member，返回其是否存在
+    pub fn remove(&mut self, member: &Bytes) -> bool {
+        match self {
+            ZSet::ZipList(zl) => match pairs(zl).iter().position(|(m, _)| m == member) {
+                Some(idx) => {
+                    remove_pair(zl, idx);
+                    true
+                },
+                None => false,
+            },
+            ZSet::SkipList { dict, list } => match dict.remove(&SDS::new(member)) {
+                Some(score) => list.remove(score, member),
+                None => false,
+            },
+        }
+    }
+
+    /// 查询 member 的分数
+    pub fn score(&mut self, member: &[u8]) -> Option<f64> {
+        match self {
+            ZSet::ZipList(zl) => pairs(zl).into_iter().find(|(m, _)| m == member).map(|(_, s)| s),
+            ZSet::SkipList { dict, .. } => dict.get(&SDS::new(member)).copied(),
+        }
+    }
+
+    /// 分数在 [min, max] 范围内的 member 数量，`None` 表示无穷
+    pub fn count(&self, min: Option<Bound>, max: Option<Bound>) -> usize {
+        match self {
+            ZSet::ZipList(zl) => pairs(zl)
+                .into_iter()
+                .filter(|(_, s)| in_range(*s, &min, &max))
+                .count(),
+            ZSet::SkipList { list, .. } => list.range_count(min, max),
+        }
+    }
+
+    /// 按分数从小到大返回范围内的 (member, score)。`limit` 为 0 表示不限制数量
+    pub fn range_by_score(&self, min: Option<Bound>, max: Option<Bound>, offset: usize, limit: usize) -> Vec<(Bytes, f64)> {
+        match self {
+            ZSet::ZipList(zl) => {
+                let limit = if limit == 0 { usize::MAX } else { limit };
+                pairs(zl)
+                    .into_iter()
+                    .filter(|(_, s)| in_range(*s, &min, &max))
+                    .skip(offset)
+                    .take(limit)
+                    .collect()
+            },
+            ZSet::SkipList { list, .. } => list
+                .range(min, max, offset, limit)
+                .into_iter()
+                .map(|item| (item.data.clone(), item.score))
+                .collect(),
+        }
+    }
+
+    /// member 按分数从小到大的排名（从 0 开始），`reverse` 时为从大到小的排名
+    pub fn rank(&mut self, member: &[u8], reverse: bool) -> Option<usize> {
+        let len = self.len();
+        let rank = match self {
+            ZSet::ZipList(zl) => pairs(zl).iter().position(|(m, _)| m == member)?,
+            ZSet::SkipList { dict, list } => {
+                let score = *dict.get(&SDS::new(member))?;
+                list.rank(score, &Bytes::copy_from_slice(member))?
+            },
+        };
+        Some(if reverse { len - 1 - rank } else { rank })
+    }
+
+    /// 按排名返回 [start, stop] 之间的 (member, score)，负数表示从最后一名倒数，越界部分会被截掉。
+    /// `reverse` 时排名按分数从大到小计算，结果也按从大到小排列
+    pub fn range_by_rank(&self, start: i64, stop: i64, reverse: bool) -> Vec<(Bytes, f64)> {
+        let len = self.len() as i64;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+        if start > stop || start >= len {
+            return vec![];
+        }
+        // 逆序时先换算成正序的排名
+        let (first, last) = if reverse { (len - 1 - stop, len - 1 - start) } else { (start, stop) };
+        let (first, last) = (first as usize, last as usize);
+        let mut range: Vec<(Bytes, f64)> = match self {
+            ZSet::ZipList(zl) => pairs(zl).into_iter().skip(first).take(last - first + 1).collect(),
+            ZSet::SkipList { list, .. } => list
+                .range_by_rank(first, last)
+                .into_iter()
+                .map(|item| (item.data.clone(), item.score))
+                .collect(),
+        };
+        if reverse {
+            range.reverse();
+        }
+        range
+    }
+
+    /// 删除并返回分数最小的 count 个 (member, score)，`max` 时为分数最大的，按弹出的顺序排列
+    pub fn pop(&mut self, count: usize, max: bool) -> Vec<(Bytes, f64)> {
+        match self {
+            ZSet::ZipList(zl) => {
+                let mut pairs = pairs(zl);
+                let count = count.min(pairs.len());
+                let popped: Vec<_> = if max {
+                    pairs.drain(pairs.len() - count..).rev().collect()
+                } else {
+                    pairs.drain(..count).collect()
+                };
+                *zl = ziplist_from(pairs.into_iter().flat_map(|(m, s)| [m, Bytes::from(s.to_string())]));
+                popped
+            },
+            ZSet::SkipList { dict, list } => {
+                let mut popped = Vec::with_capacity(count.min(list.len()));
+                for _ in 0..count {
+                    let next = if max { list.pop_back() } else { list.pop_front() };
+                    let Some((score, member)) = next else {
+                        break;
+                    };
+                    dict.remove(&SDS::new(&member));
+                    popped.push((member, score));
+                }
+                popped
+            },
+        }
+    }
+
+    /// member 在字典序范围内的数量，`None` 表示 `-`/`+`。只在所有分数都相同时有意义
+    pub fn lex_count(&self, min: Option<LexBound<Bytes>>, max: Option<LexBound<Bytes>>) -> usize {
+        match self {
+            ZSet::ZipList(zl) => pairs(zl)
+                .into_iter()
+                .filter(|(m, _)| in_lex_range(m, &min, &max))
+                .count(),
+            ZSet::SkipList { list, .. } => list.lex_count(min, max),
+        }
+    }
+
+    /// 按顺序返回 member 在字典序范围内的 (member, score)。`limit` 为 0 表示不限制数量
+    pub fn range_by_lex(&self, min: Option<LexBound<Bytes>>, max: Option<LexBound<Bytes>>, offset: usize, limit: usize) -> Vec<(Bytes, f64)> {
+        match self {
+            ZSet::ZipList(zl) => {
+                let limit = if limit == 0 { usize::MAX } else { limit };
+                pairs(zl)
+                    .into_iter()
+                    .filter(|(m, _)| in_lex_range(m, &min, &max))
+                    .skip(offset)
+                    .take(limit)
+                    .collect()
+            },
+            ZSet::SkipList { list, .. } => list
+                .range_by_lex(min, max, offset, limit)
+                .into_iter()
+                .map(|item| (item.data.clone(), item.score))
+                .collect(),
+        }
+    }
+
+    /// 转换为跳表编码
+    fn convert(&mut self) {
+        if let ZSet::ZipList(zl) = self {
+            let mut dict = Dict::new();
+            let mut list = Skiplist::new();
+            for (member, score) in pairs(zl) {
+                dict.insert(SDS::new(&member), score);
+                list.insert(member, score);
+            }
+            *self = ZSet::SkipList { dict: Box::new(dict), list };
+        }
+    }
+
+    /// 从 cursor 开始遍历一部分 (member, score)，返回下一次遍历的 cursor，0 表示遍历完成，语义同 [`Dict::scan`]。
+    /// ziplist 编码时元素很少，与 redis 一样一次返回全部
+    pub fn scan(&self, cursor: u64, count: usize, mut filter: impl FnMut(&[u8]) -> bool) -> (u64, Vec<(Bytes, f64)>) {
+        let dict = match self {
+            ZSet::ZipList(zl) => {
+                let mut pairs = pairs(zl);
+                pairs.retain(|(member, _)| filter(member));
+                return (0, pairs);
+            },
+            ZSet::SkipList { dict, .. } => dict,
+        };
+        let mut cursor = cursor;
+        let mut entries = vec![];
+        for _ in 0..count.saturating_mul(10).max(1) {
+            cursor = dict.scan(cursor, |member, score| {
+                if filter(member.val()) {
+                    entries.push((Bytes::copy_from_slice(member.val()), *score));
+                }
+            });
+            if cursor == 0 || entries.len() >= count {
+                break;
+            }
+        }
+        (cursor, entries)
+    }
+}
+
+/// ziplist 中依次交替存放的 member、score
+fn pairs(zl: &ZipList) -> Vec<(Bytes, f64)> {
+    let mut values = zl.values().map(entry_bytes);
+    let mut pairs = Vec::with_capacity(zl.get_entry_cnt() / 2);
+    while let (Some(m), Some(s)) = (values.next(), values.next()) {
+        // 分数由本模块写入，一定是合法的浮点数
+        let score = std::str::from_utf8(&s).unwrap().parse().unwrap();
+        pairs.push((m, score));
+    }
+    pairs
+}
+
+/// 删除 ziplist 中第 idx 对 member、score
+fn remove_pair(zl: &mut ZipList, idx: usize) {
+    zl.delete_at(2 * idx + 1).unwrap();
+    zl.delete_at(2 * idx).unwrap();
+}
+
+fn in_range(score: f64, min: &Option<Bound>, max: &Option<Bound>) -> bool {
+    min.is_none_or(|min| min.check_min(score)) && max.is_none_or(|max| max.check_max(score))
+}
+
+fn in_lex_range(member: &Bytes, min: &Option<LexBound<Bytes>>, max: &Option<LexBound<Bytes>>) -> bool {
+    min.as_ref().is_none_or(|min| min.check_min(member)) && max.as_ref().is_none_or(|max| max.check_max(member))
+}
+
+/// 跳表与字典各保存了一份 member
+impl MemSize for ZSet {
+    fn mem_size(&self) -> usize {
+        match self {
+            ZSet::ZipList(zl) => zl.mem_size(),
+            ZSet::SkipList { dict, list } => size_of::<Dict<SDS, f64>>() + dict.mem_size() + list.mem_size(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{ds::skiplist::{Bound, LexBound}, object::{ObjectEncoding, ZipLimits}};
+
+    use super::{AddFlags, AddOutcome, ZSet};
+
+    #[test]
+    fn basis() {
+        // 两种编码的行为应当一致
+        for limits in [ZipLimits::default(), ZipLimits { max_entries: 0, max_value: 0 }] {
+            let mut zset = ZSet::new();
+            for i in (0..100).rev() {
+                assert!(zset.insert(Bytes::from(format!("m{}", i)), i as f64, &limits));
+            }
+            assert_eq!(zset.len(), 100);
+            assert_eq!(zset.score(b"m42"), Some(42f64));
+            assert_eq!(zset.score(b"none"), None);
+
+            // 更新分数会调整顺序
+            assert!(!zset.insert(Bytes::from("m0"), 1000f64, &limits));
+            assert_eq!(zset.len(), 100);
+            assert_eq!(zset.range_by_score(None, None, 0, 1), vec![(Bytes::from("m1"), 1f64)]);
+            assert_eq!(zset.range_by_score(Some(Bound::new_inclusive(1000f64)), None, 0, 0), vec![(Bytes::from("m0"), 1000f64)]);
+
+            assert_eq!(zset.count(Some(Bound::new_inclusive(10f64)), Some(Bound::new_exclusive(20f64))), 10);
+            assert_eq!(zset.count(Some(Bound::new_inclusive(20f64)), Some(Bound::new_inclusive(10f64))), 0);
+            let range = zset.range_by_score(Some(Bound::new_exclusive(10f64)), Some(Bound::new_inclusive(20f64)), 2, 3);
+            assert_eq!(range.iter().map(|(_, score)| *score).collect::<Vec<_>>(), vec![13f64, 14f64, 15f64]);
+            assert!(zset.range_by_score(Some(Bound::new_exclusive(2000f64)), None, 0, 0).is_empty());
+
+            for i in 0..100 {
+                assert!(zset.remove(&Bytes::from(format!("m{}", i))));
+            }
+            assert!(zset.is_empty());
+            assert!(!zset.remove(&Bytes::from("m0")));
+            assert!(zset.insert(Bytes::from("again"), 1f64, &limits));
+            assert_eq!(zset.range_by_score(None, None, 0, 0), vec![(Bytes::from("again"), 1f64)]);
+        }
+    }
+
+    #[test]
+    fn from_members() {
+        let members = || (0..10).rev().map(|i| (Bytes::from(format!("m{}", i)), i as f64));
+        let zset = ZSet::from_members(members(), &ZipLimits::default());
+        assert_eq!(zset.encoding(), ObjectEncoding::ZipList);
+        assert_eq!(zset.range_by_rank(0, 1, false), vec![(Bytes::from("m0"), 0f64), (Bytes::from("m1"), 1f64)]);
+
+        let mut zset = ZSet::from_members(members(), &ZipLimits { max_entries: 9, max_value: 64 });
+        assert_eq!(zset.encoding(), ObjectEncoding::SkipList);
+        assert_eq!(zset.len(), 10);
+        assert_eq!(zset.score(b"m7"), Some(7f64));
+        assert!(ZSet::from_members(vec![], &ZipLimits::default()).is_empty());
+    }
+
+    #[test]
+    fn rank() {
+        for limits in [ZipLimits::default(), ZipLimits { max_entries: 0, max_value: 0 }] {
+            let mut zset = ZSet::new();
+            for i in 0..10 {
+                zset.insert(Bytes::from(format!("m{}", i)), (i * 10) as f64, &limits);
+            }
+            assert_eq!(zset.rank(b"m0", false), Some(0));
+            assert_eq!(zset.rank(b"m7", false), Some(7));
+            assert_eq!(zset.rank(b"m7", true), Some(2));
+            assert_eq!(zset.rank(b"none", false), None);
+
+            let members = |range: Vec<(Bytes, f64)>| range.into_iter().map(|(m, _)| m).collect::<Vec<_>>();
+            assert_eq!(members(zset.range_by_rank(2, 4, false)), ["m2", "m3", "m4"].map(Bytes::from));
+            assert_eq!(members(zset.range_by_rank(0, 1, true)), ["m9", "m8"].map(Bytes::from));
+            assert_eq!(zset.range_by_rank(8, 100, false), vec![(Bytes::from("m8"), 80f64), (Bytes::from("m9"), 90f64)]);
+            assert!(zset.range_by_rank(10, 20, false).is_empty());
+            assert!(zset.range_by_rank(3, 2, false).is_empty());
+            assert_eq!(members(zset.range_by_rank(-2, -1, false)), ["m8", "m9"].map(Bytes::from));
+            assert_eq!(members(zset.range_by_rank(-100, 0, true)), ["m9"].map(Bytes::from));
+        }
+    }
+
+    #[test]
+    fn add_flags() {
+        for limits in [ZipLimits::default(), ZipLimits { max_entries: 0, max_value: 0 }] {
+            let mut zset = ZSet::new();
+            let member = || Bytes::from("m");
+            let flags = |f: fn(&mut AddFlags)| {
+                let mut flags = AddFlags::default();
+                f(&mut flags);
+                flags
+            };
+            assert_eq!(zset.add(member(), 1f64, flags(|f| f.xx = true), &limits), AddOutcome::Skipped);
+            assert_eq!(zset.add(member(), 1f64, flags(|f| f.gt = true), &limits), AddOutcome::Added(1f64));
+            assert_eq!(zset.add(member(), 5f64, flags(|f| f.nx = true), &limits), AddOutcome::Skipped);
+            assert_eq!(zset.add(member(), 1f64, AddFlags::default(), &limits), AddOutcome::Unchanged(1f64));
+            assert_eq!(zset.add(member(), 0f64, flags(|f| f.gt = true), &limits), AddOutcome::Skipped);
+            assert_eq!(zset.add(member(), 3f64, flags(|f| f.gt = true), &limits), AddOutcome::Updated(3f64));
+            assert_eq!(zset.add(member(), 4f64, flags(|f| f.lt = true), &limits), AddOutcome::Skipped);
+            assert_eq!(zset.add(member(), 2.5, flags(|f| f.incr = true), &limits), AddOutcome::Updated(5.5));
+            assert_eq!(zset.add(member(), -1f64, flags(|f| { f.incr = true; f.lt = true }), &limits), AddOutcome::Updated(4.5));
+            assert_eq!(zset.score(b"m"), Some(4.5));
+            assert_eq!(zset.add(Bytes::from("n"), 2f64, flags(|f| f.incr = true), &limits), AddOutcome::Added(2f64));
+            assert_eq!(zset.range_by_rank(0, -1, false), vec![(Bytes::from("n"), 2f64), (member(), 4.5)]);
+
+            zset.add(member(), f64::INFINITY, AddFlags::default(), &limits);
+            assert_eq!(zset.add(member(), f64::NEG_INFINITY, flags(|f| f.incr = true), &limits), AddOutcome::NotANumber);
+            assert_eq!(zset.score(b"m"), Some(f64::INFINITY));
+        }
+    }
+
+    #[test]
+    fn pop() {
+        for limits in [ZipLimits::default(), ZipLimits { max_entries: 0, max_value: 0 }] {
+            let mut zset = ZSet::new();
+            for i in 0..5 {
+                zset.insert(Bytes::from(format!("m{}", i)), i as f64, &limits);
+            }
+            assert_eq!(zset.pop(1, false), vec![(Bytes::from("m0"), 0f64)]);
+            assert_eq!(zset.pop(2, true), vec![(Bytes::from("m4"), 4f64), (Bytes::from("m3"), 3f64)]);
+            assert_eq!(zset.score(b"m4"), None);
+            assert_eq!(zset.pop(0, true), vec![]);
+            assert_eq!(zset.pop(10, false), vec![(Bytes::from("m1"), 1f64), (Bytes::from("m2"), 2f64)]);
+            assert!(zset.is_empty());
+            assert_eq!(zset.pop(1, false), vec![]);
+        }
+    }
+
+    #[test]
+    fn lex_range() {
+        for limits in [ZipLimits::default(), ZipLimits { max_entries: 0, max_value: 0 }] {
+            let mut zset = ZSet::new();
+            for member in ["e", "a", "c", "b", "d"] {
+                zset.insert(Bytes::from(member), 0f64, &limits);
+            }
+            let bound = |member: &'static str, exclusive| Some(LexBound::new(Bytes::from(member), exclusive));
+            let members: Vec<Bytes> = zset.range_by_lex(bound("b", false), bound("d", true), 0, 0).into_iter().map(|(m, _)| m).collect();
+            assert_eq!(members, ["b", "c"].map(Bytes::from));
+            let members: Vec<Bytes> = zset.range_by_lex(None, None, 1, 2).into_iter().map(|(m, _)| m).collect();
+            assert_eq!(members, ["b", "c"].map(Bytes::from));
+            assert_eq!(zset.lex_count(bound("a", true), None), 4);
+            assert_eq!(zset.lex_count(bound("c", false), bound("a", false)), 0);
+        }
+    }
+
+    #[test]
+    fn same_score_ordered_by_member() {
+        let limits = ZipLimits::default();
+        let mut zset = ZSet::new();
+        for member in ["c", "a", "b"] {
+            zset.insert(Bytes::from(member), 1f64, &limits);
+        }
+        assert_eq!(zset.encoding(), ObjectEncoding::ZipList);
+        let members: Vec<Bytes> = zset.range_by_score(None, None, 0, 0).into_iter().map(|(m, _)| m).collect();
+        assert_eq!(members, ["a", "b", "c"].map(Bytes::from));
+    }
+
+    #[test]
+    fn scan() {
+        for limits in [ZipLimits::default(), ZipLimits { max_entries: 0, max_value: 0 }] {
+            let mut zset = ZSet::new();
+            for i in 0..50 {
+                zset.insert(Bytes::from(format!("m{}", i)), i as f64, &limits);
+            }
+            let (mut cursor, mut entries) = (0, vec![]);
+            loop {
+                let (next, batch) = zset.scan(cursor, 5, |member| member.ends_with(b"7"));
+                entries.extend(batch);
+                if next == 0 {
+                    break;
+                }
+                cursor = next;
+            }
+            entries.sort_by(|a, b| a.1.total_cmp(&b.1));
+            let expected: Vec<(Bytes, f64)> = [7, 17, 27, 37, 47].map(|i| (Bytes::from(format!("m{}", i)), i as f64)).into();
+            assert_eq!(entries, expected);
+        }
+    }
+}